@@ -3,6 +3,7 @@ extern crate krill;
 use std::process;
 
 use krill::commons::util::file;
+use krill::daemon::backup::KrillBackup;
 use krill::daemon::config::Config;
 use krill::daemon::http::server;
 
@@ -10,6 +11,11 @@ use krill::daemon::http::server;
 async fn main() {
     match Config::create() {
         Ok(config) => {
+            if let Some((backup_file, passphrase)) = Config::get_restore_args() {
+                restore(&config, &backup_file, passphrase.as_deref());
+                return;
+            }
+
             let pid_file = config.pid_file();
             if let Err(e) = file::save(process::id().to_string().as_bytes(), &pid_file) {
                 eprintln!("Could not write PID file: {}", e);
@@ -27,3 +33,28 @@ async fn main() {
         }
     }
 }
+
+/// Restores a backup into the data directory of the config, and exits on
+/// any issue.
+fn restore(config: &Config, backup_file: &std::path::PathBuf, passphrase: Option<&str>) {
+    let res = file::read(backup_file)
+        .map_err(|e| e.into())
+        .and_then(|bytes| KrillBackup::decode(&bytes))
+        .and_then(|backup| backup.restore(&config.data_dir, config.storage_type, passphrase));
+
+    match res {
+        Ok(restored_config) => {
+            println!("Restored backup into: {}", config.data_dir.display());
+            if let Some(path) = restored_config {
+                println!(
+                    "The config from the backup was saved as: {}, review and use it as needed",
+                    path.display()
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("Could not restore backup: {}", e);
+            ::std::process::exit(1);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::{env, fmt, io};
 
 use serde::de::DeserializeOwned;
@@ -10,12 +11,12 @@ use crate::cli::options::{
 };
 use crate::cli::report::{ApiResponse, ReportError};
 use crate::commons::api::{
-    AllCertAuthIssues, CaRepoDetails, CertAuthIssues, ChildCaInfo, CurrentRepoState,
-    ParentCaContact, PublisherDetails, PublisherList, Token,
+    AllCertAuthIssues, BackupRequest, CaRepoDetails, CaRepoState, CertAuthIssues, ChildCaInfo,
+    ParentCaContact, PublisherDetails, PublisherList, RtaValidationRequest, Token,
 };
 use crate::commons::bgp::BgpAnalysisReport;
 use crate::commons::remote::rfc8183;
-use crate::commons::util::httpclient;
+use crate::commons::util::{file, httpclient};
 use crate::constants::KRILL_CLI_API_ENV;
 use crate::daemon::config::Config;
 
@@ -58,9 +59,13 @@ impl KrillClient {
         match options.command {
             Command::Health => client.health().await,
             Command::Info => client.info().await,
+            Command::Tasks => client.tasks().await,
+            Command::Integrity(repair) => client.integrity(repair).await,
+            Command::Backup(output, request) => client.backup(&output, request).await,
             Command::Bulk(cmd) => client.bulk(cmd).await,
             Command::CertAuth(cmd) => client.certauth(cmd).await,
             Command::Publishers(cmd) => client.publishers(cmd).await,
+            Command::RtaValidate(request) => client.rta_validate(request).await,
             Command::Init(details) => client.init(details),
             Command::NotSet => Err(Error::MissingCommand),
         }
@@ -76,6 +81,29 @@ impl KrillClient {
         Ok(ApiResponse::Info(info))
     }
 
+    async fn tasks(&self) -> Result<ApiResponse, Error> {
+        let tasks = self.get_json("api/v1/tasks").await?;
+        Ok(ApiResponse::Tasks(tasks))
+    }
+
+    async fn integrity(&self, repair: bool) -> Result<ApiResponse, Error> {
+        let report = if repair {
+            self.post_json_with_response("api/v1/integrity/repair", ())
+                .await?
+        } else {
+            self.get_json("api/v1/integrity").await?
+        };
+        Ok(ApiResponse::Integrity(report))
+    }
+
+    async fn backup(&self, output: &str, request: BackupRequest) -> Result<ApiResponse, Error> {
+        let uri = self.resolve_uri("api/v1/backup");
+        let bytes =
+            httpclient::post_json_with_binary_response(&uri, request, Some(&self.token)).await?;
+        file::save(&bytes, &PathBuf::from(output))?;
+        Ok(ApiResponse::Empty)
+    }
+
     async fn bulk(&self, command: BulkCaCommand) -> Result<ApiResponse, Error> {
         match command {
             BulkCaCommand::Refresh => {
@@ -133,10 +161,22 @@ impl KrillClient {
 
             CaCommand::RepoState(handle) => {
                 let uri = format!("api/v1/cas/{}/repo/state", handle);
-                let state: CurrentRepoState = self.get_json(&uri).await?;
+                let state: CaRepoState = self.get_json(&uri).await?;
                 Ok(ApiResponse::RepoState(state))
             }
 
+            CaCommand::RepoMigrationResume(handle) => {
+                let uri = format!("api/v1/cas/{}/repo/migration/resume", handle);
+                self.post_empty(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::RepoMigrationAbort(handle) => {
+                let uri = format!("api/v1/cas/{}/repo/migration/abort", handle);
+                self.post_empty(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
+
             CaCommand::RepoUpdate(handle, update) => {
                 let uri = format!("api/v1/cas/{}/repo", handle);
                 self.post_json(&uri, update).await?;
@@ -199,6 +239,11 @@ impl KrillClient {
                 self.post_empty(&uri).await?;
                 Ok(ApiResponse::Empty)
             }
+            CaCommand::KeyRollSigner(handle, update) => {
+                let uri = format!("api/v1/cas/{}/signer", handle);
+                self.post_json(&uri, update).await?;
+                Ok(ApiResponse::Empty)
+            }
 
             CaCommand::RouteAuthorizationsList(handle) => {
                 let uri = format!("api/v1/cas/{}/routes", handle);
@@ -212,6 +257,72 @@ impl KrillClient {
                 Ok(ApiResponse::Empty)
             }
 
+            CaCommand::RouteAuthorizationsDryRunUpdate(handle, updates) => {
+                let uri = format!("api/v1/cas/{}/routes/analysis/dryrun", handle);
+                let report = self.post_json_with_response(&uri, updates).await?;
+                Ok(ApiResponse::BgpAnalysisDryRun(report))
+            }
+
+            CaCommand::AspasList(handle) => {
+                let uri = format!("api/v1/cas/{}/aspas", handle);
+                let aspas = self.get_json(&uri).await?;
+                Ok(ApiResponse::AspaDefinitions(aspas))
+            }
+
+            CaCommand::AspasUpdate(handle, updates) => {
+                let uri = format!("api/v1/cas/{}/aspas", handle);
+                self.post_json(&uri, updates).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::AspasUpdateExisting(handle, customer, update) => {
+                let uri = format!("api/v1/cas/{}/aspas/as/{}", handle, customer);
+                self.post_json(&uri, update).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::BgpSecList(handle) => {
+                let uri = format!("api/v1/cas/{}/bgpsec", handle);
+                let keys = self.get_json(&uri).await?;
+                Ok(ApiResponse::BgpSecKeys(keys))
+            }
+
+            CaCommand::BgpSecUpdate(handle, updates) => {
+                let uri = format!("api/v1/cas/{}/bgpsec", handle);
+                self.post_json(&uri, updates).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::RtaList(handle) => {
+                let uri = format!("api/v1/cas/{}/rta", handle);
+                let list = self.get_json(&uri).await?;
+                Ok(ApiResponse::RtaList(list))
+            }
+
+            CaCommand::RtaShow(handle, name) => {
+                let uri = format!("api/v1/cas/{}/rta/{}", handle, name);
+                let rta = self.get_json(&uri).await?;
+                Ok(ApiResponse::Rta(rta))
+            }
+
+            CaCommand::RtaSign(handle, name, request) => {
+                let uri = format!("api/v1/cas/{}/rta/{}/sign", handle, name);
+                self.post_json(&uri, request).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::RtaMultiPrep(handle, name, request) => {
+                let uri = format!("api/v1/cas/{}/rta/{}/multi/prep", handle, name);
+                let response = self.post_json_with_response(&uri, request).await?;
+                Ok(ApiResponse::RtaMultiPrep(response))
+            }
+
+            CaCommand::RtaMultiCoSign(handle, name, rta) => {
+                let uri = format!("api/v1/cas/{}/rta/{}/multi/cosign", handle, name);
+                self.post_json(&uri, rta).await?;
+                Ok(ApiResponse::Empty)
+            }
+
             CaCommand::BgpAnalysisFull(handle) => {
                 let uri = format!("api/v1/cas/{}/routes/analysis/full", handle);
                 let report = self.get_json(&uri).await?;
@@ -224,6 +335,18 @@ impl KrillClient {
                 Ok(ApiResponse::BgpAnalysisAnnouncements(report.into()))
             }
 
+            CaCommand::BgpAnalysisSuggest(handle) => {
+                let uri = format!("api/v1/cas/{}/routes/analysis/suggest", handle);
+                let suggestions = self.get_json(&uri).await?;
+                Ok(ApiResponse::BgpAnalysisSuggestions(suggestions))
+            }
+
+            CaCommand::BgpAnalysisTimeline(handle) => {
+                let uri = format!("api/v1/cas/{}/routes/analysis/timeline", handle);
+                let timeline = self.get_json(&uri).await?;
+                Ok(ApiResponse::BgpAnalysisTimeline(timeline))
+            }
+
             CaCommand::BgpAnalysisRoas(handle) => {
                 let uri = format!("api/v1/cas/{}/routes/analysis/full", handle);
                 let report: BgpAnalysisReport = self.get_json(&uri).await?;
@@ -237,6 +360,13 @@ impl KrillClient {
                 Ok(ApiResponse::CertAuthInfo(ca_info))
             }
 
+            CaCommand::ShowAt(handle, point) => {
+                let uri = format!("api/v1/cas/{}/at/{}", handle, point);
+                let ca_info = self.get_json(&uri).await?;
+
+                Ok(ApiResponse::CertAuthInfoAt(ca_info))
+            }
+
             CaCommand::ShowHistory(handle, options) => {
                 let uri = format!("api/v1/cas/{}/history/{}", handle, options);
                 let history = self.get_json(&uri).await?;
@@ -309,6 +439,13 @@ impl KrillClient {
         }
     }
 
+    async fn rta_validate(&self, request: RtaValidationRequest) -> Result<ApiResponse, Error> {
+        let report = self
+            .post_json_with_response("api/v1/rta/validate", request)
+            .await?;
+        Ok(ApiResponse::RtaValidation(report))
+    }
+
     fn resolve_uri(&self, path: &str) -> String {
         format!("{}{}", &self.server, path)
     }
@@ -1,21 +1,26 @@
 use std::convert::TryFrom;
 use std::io;
 use std::path::PathBuf;
-use std::str::{from_utf8_unchecked, FromStr};
+use std::str::{from_utf8, from_utf8_unchecked, FromStr};
 use std::{env, fmt};
 
 use bytes::Bytes;
 use clap::{App, Arg, ArgMatches, SubCommand};
+use openssl::x509::X509Req;
 
+use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 use rpki::x509::Time;
 
 use crate::cli::report::{ReportError, ReportFormat};
 use crate::commons::api::RepositoryUpdate;
 use crate::commons::api::{
-    AddChildRequest, AuthorizationFmtError, CertAuthInit, ChildAuthRequest, ChildHandle, Handle,
-    ParentCaContact, ParentCaReq, ParentHandle, PublisherHandle, ResourceSet, ResourceSetError,
-    RoaDefinitionUpdates, Token, UpdateChildRequest,
+    AddChildRequest, AsNumber, AspaDefinition, AspaDefinitionUpdates, AspaProvidersUpdate,
+    AuthorizationFmtError, BackupRequest, Base64, BgpSecAsnKey, BgpSecDefinition,
+    BgpSecDefinitionUpdates, CertAuthInit, CertAuthSignerUpdate, ChildAuthRequest, ChildHandle,
+    Handle, HistoryPoint, ParentCaContact, ParentCaReq, ParentHandle, PublisherHandle, ResourceSet,
+    ResourceSetError, ResourceTaggedAttestation, RoaDefinitionUpdates, RtaContentRequest, RtaName,
+    RtaPrepareRequest, RtaValidationRequest, Token, UpdateChildRequest,
 };
 use crate::commons::remote::id::IdCert;
 use crate::commons::remote::rfc8183;
@@ -296,6 +301,14 @@ impl Options {
         sub = Self::add_general_args(sub);
         sub = Self::add_my_ca_arg(sub);
 
+        sub = sub.arg(
+            Arg::with_name("at")
+                .long("at")
+                .help("Show the CA as it was at a version, or date/time in RFC 3339 format, e.g. 2020-04-09T19:37:02Z")
+                .value_name("<version|RFC 3339 DateTime>")
+                .required(false),
+        );
+
         app.subcommand(sub)
     }
 
@@ -555,11 +568,29 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_cas_keyroll_signer_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("signer")
+            .about("Initialise roll for all keys held by this CA to a (configured) signer.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        sub = sub.arg(
+            Arg::with_name("signer")
+                .long("signer")
+                .value_name("name")
+                .help("The name of the signer. Defaults to the signers configured per key purpose.")
+                .required(false),
+        );
+
+        app.subcommand(sub)
+    }
+
     fn make_cas_keyroll_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("keyroll").about("Perform a manual key-roll in Krill.");
 
         sub = Self::make_cas_keyroll_init_sc(sub);
         sub = Self::make_cas_keyroll_activate_sc(sub);
+        sub = Self::make_cas_keyroll_signer_sc(sub);
 
         app.subcommand(sub)
     }
@@ -589,12 +620,22 @@ impl Options {
                     "\n", // empty line
                     "A: 192.168.0.0/16 => 64496 # inline comment\n",
                     "A: 192.168.1.0/24 => 64496\n",
+                    "A: 192.168.2.0/24 => 64496 ; comment = why ; tags = a, b ; valid_until = 2021-01-01\n",
                     "R: 192.168.3.0/24 => 64496\n",
                 ))
                 .value_name("<file>")
                 .required(true),
         );
 
+        sub = sub.arg(
+            Arg::with_name("dryrun")
+                .long("dry-run")
+                .help(
+                    "Show the resulting ROAs and their impact on BGP, without applying the delta.",
+                )
+                .required(false),
+        );
+
         app.subcommand(sub)
     }
 
@@ -623,6 +664,24 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_cas_routes_bgp_suggest_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("suggest")
+            .about("Suggest ROA updates based on the known announcements. The text output can be used with 'roas update --delta'.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        app.subcommand(sub)
+    }
+
+    fn make_cas_routes_bgp_timeline_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("timeline")
+            .about("Show how the numbers of valid and invalid announcements changed over time.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        app.subcommand(sub)
+    }
+
     fn make_cas_routes_bgp_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("bgp")
             .about("Show current authorizations in relation to known announcements.");
@@ -630,6 +689,8 @@ impl Options {
         sub = Self::make_cas_routes_bgp_full_sc(sub);
         sub = Self::make_cas_routes_bgp_announcements_sc(sub);
         sub = Self::make_cas_routes_bgp_roas_sc(sub);
+        sub = Self::make_cas_routes_bgp_suggest_sc(sub);
+        sub = Self::make_cas_routes_bgp_timeline_sc(sub);
 
         app.subcommand(sub)
     }
@@ -644,6 +705,347 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_cas_aspas_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("list").about("Show current ASPA definitions.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_add_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("add")
+            .about("Add or replace the ASPA definition for a customer ASN.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("aspa")
+                .long("aspa")
+                .value_name("definition")
+                .help("The ASPA definition, e.g.: \"64496 => 64497, 64498\"")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("remove").about("Remove the ASPA definition for a customer ASN.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("customer")
+                .long("customer")
+                .value_name("ASN")
+                .help("The customer ASN, e.g.: 64496")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_update_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("update")
+            .about("Add or remove providers for an existing ASPA definition.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("customer")
+                    .long("customer")
+                    .value_name("ASN")
+                    .help("The customer ASN, e.g.: 64496")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("add")
+                    .long("add")
+                    .value_name("ASN")
+                    .help("Provider ASN to add. May be used multiple times.")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("remove")
+                    .long("remove")
+                    .value_name("ASN")
+                    .help("Provider ASN to remove. May be used multiple times.")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .required(false),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("aspas").about("Manage ASPAs for your CA.");
+
+        sub = Self::make_cas_aspas_list_sc(sub);
+        sub = Self::make_cas_aspas_add_sc(sub);
+        sub = Self::make_cas_aspas_remove_sc(sub);
+        sub = Self::make_cas_aspas_update_sc(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("list").about("Show current BGPsec router keys.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_add_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("add")
+            .about("Certify a BGPsec router key for an ASN held by your CA.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("asn")
+                    .long("asn")
+                    .value_name("ASN")
+                    .help("The ASN for the router key, e.g.: 64496")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("csr")
+                    .long("csr")
+                    .value_name("path")
+                    .help("Path to the PKCS#10 CSR of the router, in DER or PEM format")
+                    .required(true),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("remove")
+            .about("Remove a BGPsec router key and revoke its certificates.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("asn")
+                    .long("asn")
+                    .value_name("ASN")
+                    .help("The ASN for the router key, e.g.: 64496")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("key")
+                    .long("key")
+                    .value_name("key identifier")
+                    .help("The hex encoded key identifier of the router key")
+                    .required(true),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("bgpsec").about("Manage BGPsec router certificates for your CA.");
+
+        sub = Self::make_cas_bgpsec_list_sc(sub);
+        sub = Self::make_cas_bgpsec_add_sc(sub);
+        sub = Self::make_cas_bgpsec_remove_sc(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn add_rta_name_arg<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name("name")
+                .long("name")
+                .short("n")
+                .value_name("name")
+                .help("The local name of the RTA.")
+                .required(true),
+        )
+    }
+
+    fn add_rta_content_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name("asn")
+                .short("a")
+                .long("asn")
+                .value_name("AS resources")
+                .help("The AS resources to attest to: e.g. AS1, AS3-4")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ipv4")
+                .short("4")
+                .long("ipv4")
+                .value_name("IPv4 resources")
+                .help("The IPv4 resources to attest to: e.g. 192.168.0.0/16")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ipv6")
+                .short("6")
+                .long("ipv6")
+                .value_name("IPv6 resources")
+                .help("The IPv6 resources to attest to: e.g. 2001:db8::/32")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("days")
+                .long("days")
+                .value_name("number")
+                .help("The number of days that the RTA is valid.")
+                .required(true),
+        )
+    }
+
+    fn make_rta_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("list").about("List the RTAs of your CA.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_show_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("show").about("Show a signed RTA of your CA (base64 encoded).");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        sub = Self::add_rta_name_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_sign_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("sign")
+            .about("Sign an RTA over the content of a file, using the keys of your CA.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        sub = Self::add_rta_name_arg(sub);
+        sub = Self::add_rta_content_args(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("in")
+                    .long("in")
+                    .short("i")
+                    .value_name("file")
+                    .help("The file containing the content to sign.")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("keys")
+                    .long("keys")
+                    .value_name("key identifier")
+                    .help("A key prepared by a co-signer. May be used multiple times.")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .required(false),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_multi_prep_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("prep")
+            .about("Prepare keys in your CA for co-signing a multi-signer RTA.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        sub = Self::add_rta_name_arg(sub);
+        sub = Self::add_rta_content_args(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_multi_cosign_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("cosign")
+            .about("Co-sign a multi-signer RTA, using the keys prepared in your CA.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+        sub = Self::add_rta_name_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("rta")
+                .long("rta")
+                .value_name("file")
+                .help("The file containing the RTA to co-sign, in DER or base64.")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_multi_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("multi").about("Manage multi-signer RTAs.");
+
+        sub = Self::make_rta_multi_prep_sc(sub);
+        sub = Self::make_rta_multi_cosign_sc(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_validate_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("validate").about(
+            "Validate an RTA for the content of a file, using the TALs configured in Krill.",
+        );
+
+        sub = Self::add_general_args(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("rta")
+                    .long("rta")
+                    .value_name("file")
+                    .help("The file containing the RTA, in DER or base64.")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("in")
+                    .long("in")
+                    .short("i")
+                    .value_name("file")
+                    .help("The file containing the content attested to.")
+                    .required(true),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_rta_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("rta").about("Manage Resource Tagged Attestations (RTA).");
+
+        sub = Self::make_rta_list_sc(sub);
+        sub = Self::make_rta_show_sc(sub);
+        sub = Self::make_rta_sign_sc(sub);
+        sub = Self::make_rta_multi_sc(sub);
+        sub = Self::make_rta_validate_sc(sub);
+
+        app.subcommand(sub)
+    }
+
     fn make_cas_repo_request_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("request").about("Show RFC8183 Publisher Request XML.");
 
@@ -689,6 +1091,26 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_cas_repo_resume_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("resume")
+            .about("Resume a repository migration which did not finish.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_repo_abort_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("abort")
+            .about("Abort a repository migration, and move back to the previous repository.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
     fn make_cas_repo_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("repo").about("Manage the repository for your CA.");
 
@@ -696,6 +1118,8 @@ impl Options {
         sub = Self::make_cas_repo_show_sc(sub);
         sub = Self::make_cas_repo_state_sc(sub);
         sub = Self::make_cas_repo_update_sc(sub);
+        sub = Self::make_cas_repo_resume_sc(sub);
+        sub = Self::make_cas_repo_abort_sc(sub);
 
         app.subcommand(sub)
     }
@@ -845,6 +1269,45 @@ impl Options {
         app.subcommand(info)
     }
 
+    fn make_tasks_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let tasks = SubCommand::with_name("tasks").about("Show the status of background tasks");
+        let tasks = Self::add_general_args(tasks);
+        app.subcommand(tasks)
+    }
+
+    fn make_integrity_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("integrity")
+            .about("Verify the stored history of all CAs and the repository");
+        sub = Self::add_general_args(sub);
+        sub = sub.arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .help("Rebuild snapshots and info from the events, where this is safe")
+                .required(false),
+        );
+        app.subcommand(sub)
+    }
+
+    fn make_backup_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("backup")
+            .about("Save a backup of all CAs, the repository, signer keys and config");
+        sub = Self::add_general_args(sub);
+        sub = sub
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("file")
+                    .help("The file to save the backup to")
+                    .required(true),
+            )
+            .after_help(
+                "The signer keys and config in the backup are encrypted with the \
+                 passphrase in env: KRILL_BACKUP_PASSPHRASE, if set.",
+            );
+        app.subcommand(sub)
+    }
+
     fn make_matches<'a>() -> ArgMatches<'a> {
         let mut app = App::new(KRILL_CLIENT_APP).version(KRILL_VERSION);
 
@@ -858,6 +1321,9 @@ impl Options {
         app = Self::make_cas_parents_sc(app);
         app = Self::make_cas_keyroll_sc(app);
         app = Self::make_cas_routes_sc(app);
+        app = Self::make_cas_aspas_sc(app);
+        app = Self::make_cas_bgpsec_sc(app);
+        app = Self::make_rta_sc(app);
         app = Self::make_cas_repo_sc(app);
         app = Self::make_cas_issues_sc(app);
 
@@ -866,6 +1332,9 @@ impl Options {
         app = Self::make_health_sc(app);
 
         app = Self::make_info_sc(app);
+        app = Self::make_tasks_sc(app);
+        app = Self::make_integrity_sc(app);
+        app = Self::make_backup_sc(app);
 
         app = Self::make_bulk_sc(app);
 
@@ -1002,7 +1471,12 @@ impl Options {
         let general_args = GeneralArgs::from_matches(matches)?;
         let my_ca = Self::parse_my_ca(matches)?;
 
-        let command = Command::CertAuth(CaCommand::Show(my_ca));
+        let command = if let Some(at) = matches.value_of("at") {
+            let point = HistoryPoint::from_str(at).map_err(|e| Error::general(&e.to_string()))?;
+            Command::CertAuth(CaCommand::ShowAt(my_ca, point))
+        } else {
+            Command::CertAuth(CaCommand::Show(my_ca))
+        };
         Ok(Options::make(general_args, command))
     }
 
@@ -1245,11 +1719,24 @@ impl Options {
         Ok(Options::make(general_args, command))
     }
 
+    fn parse_matches_cas_keyroll_signer(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        let signer = matches.value_of("signer").map(|s| s.to_string());
+
+        let update = CertAuthSignerUpdate::new(signer);
+        let command = Command::CertAuth(CaCommand::KeyRollSigner(my_ca, update));
+
+        Ok(Options::make(general_args, command))
+    }
+
     fn parse_matches_cas_keyroll(matches: &ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("init") {
             Self::parse_matches_cas_keyroll_init(m)
         } else if let Some(m) = matches.subcommand_matches("activate") {
             Self::parse_matches_cas_keyroll_activate(m)
+        } else if let Some(m) = matches.subcommand_matches("signer") {
+            Self::parse_matches_cas_keyroll_signer(m)
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1275,7 +1762,11 @@ impl Options {
             RoaDefinitionUpdates::from_str(updates_str)?
         };
 
-        let command = Command::CertAuth(CaCommand::RouteAuthorizationsUpdate(my_ca, updates));
+        let command = if matches.is_present("dryrun") {
+            Command::CertAuth(CaCommand::RouteAuthorizationsDryRunUpdate(my_ca, updates))
+        } else {
+            Command::CertAuth(CaCommand::RouteAuthorizationsUpdate(my_ca, updates))
+        };
 
         Ok(Options::make(general_args, command))
     }
@@ -1307,6 +1798,24 @@ impl Options {
         ))
     }
 
+    fn parse_matches_cas_routes_bgp_suggest(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        Ok(Options::make(
+            general_args,
+            Command::CertAuth(CaCommand::BgpAnalysisSuggest(my_ca)),
+        ))
+    }
+
+    fn parse_matches_cas_routes_bgp_timeline(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        Ok(Options::make(
+            general_args,
+            Command::CertAuth(CaCommand::BgpAnalysisTimeline(my_ca)),
+        ))
+    }
+
     fn parse_matches_cas_routes_bgp(matches: &ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("full") {
             Self::parse_matches_cas_routes_bgp_full(m)
@@ -1314,6 +1823,10 @@ impl Options {
             Self::parse_matches_cas_routes_bgp_announcements(m)
         } else if let Some(m) = matches.subcommand_matches("roas") {
             Self::parse_matches_cas_routes_bgp_roas(m)
+        } else if let Some(m) = matches.subcommand_matches("suggest") {
+            Self::parse_matches_cas_routes_bgp_suggest(m)
+        } else if let Some(m) = matches.subcommand_matches("timeline") {
+            Self::parse_matches_cas_routes_bgp_timeline(m)
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1331,6 +1844,271 @@ impl Options {
         }
     }
 
+    fn parse_matches_cas_aspas_list(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::AspasList(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas_add(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let definition = AspaDefinition::from_str(matches.value_of("aspa").unwrap())?;
+        let updates = AspaDefinitionUpdates::new(vec![definition], vec![]);
+
+        let command = Command::CertAuth(CaCommand::AspasUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas_remove(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let customer = AsNumber::from_str(matches.value_of("customer").unwrap())?;
+        let updates = AspaDefinitionUpdates::new(vec![], vec![customer]);
+
+        let command = Command::CertAuth(CaCommand::AspasUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas_update(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let customer = AsNumber::from_str(matches.value_of("customer").unwrap())?;
+
+        fn parse_asns(matches: &ArgMatches, name: &str) -> Result<Vec<AsNumber>, Error> {
+            let mut res = vec![];
+            if let Some(values) = matches.values_of(name) {
+                for value in values {
+                    res.push(AsNumber::from_str(value)?);
+                }
+            }
+            Ok(res)
+        }
+
+        let added = parse_asns(matches, "add")?;
+        let removed = parse_asns(matches, "remove")?;
+
+        if added.is_empty() && removed.is_empty() {
+            return Err(Error::general(
+                "Use --add and/or --remove to update providers",
+            ));
+        }
+
+        let update = AspaProvidersUpdate::new(added, removed);
+
+        let command = Command::CertAuth(CaCommand::AspasUpdateExisting(my_ca, customer, update));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            Self::parse_matches_cas_aspas_list(m)
+        } else if let Some(m) = matches.subcommand_matches("add") {
+            Self::parse_matches_cas_aspas_add(m)
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            Self::parse_matches_cas_aspas_remove(m)
+        } else if let Some(m) = matches.subcommand_matches("update") {
+            Self::parse_matches_cas_aspas_update(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_matches_cas_bgpsec_list(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::BgpSecList(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_bgpsec_add(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let asn = AsNumber::from_str(matches.value_of("asn").unwrap())?;
+
+        let bytes = Self::read_file_arg(matches.value_of("csr").unwrap())?;
+        let der = if bytes.starts_with(b"-----BEGIN") {
+            X509Req::from_pem(bytes.as_ref())
+                .and_then(|req| req.to_der())
+                .map_err(|e| Error::general(&format!("Cannot parse CSR: {}", e)))?
+        } else {
+            bytes.to_vec()
+        };
+        let definition = BgpSecDefinition::new(asn, Base64::from_content(&der));
+
+        let updates = BgpSecDefinitionUpdates::new(vec![definition], vec![]);
+        let command = Command::CertAuth(CaCommand::BgpSecUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_bgpsec_remove(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let asn = AsNumber::from_str(matches.value_of("asn").unwrap())?;
+        let key = KeyIdentifier::from_str(matches.value_of("key").unwrap())
+            .map_err(|_| Error::general("Invalid key identifier"))?;
+
+        let updates = BgpSecDefinitionUpdates::new(vec![], vec![BgpSecAsnKey::new(asn, key)]);
+        let command = Command::CertAuth(CaCommand::BgpSecUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_bgpsec(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            Self::parse_matches_cas_bgpsec_list(m)
+        } else if let Some(m) = matches.subcommand_matches("add") {
+            Self::parse_matches_cas_bgpsec_add(m)
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            Self::parse_matches_cas_bgpsec_remove(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_rta_name(matches: &ArgMatches) -> RtaName {
+        matches.value_of("name").unwrap().to_string()
+    }
+
+    fn parse_rta_resources_and_days(matches: &ArgMatches) -> Result<(ResourceSet, u16), Error> {
+        let resources = Self::parse_resource_args(matches)?.ok_or_else(|| {
+            Error::general("Use --asn, --ipv4 and/or --ipv6 to specify resources")
+        })?;
+        let days = u16::from_str(matches.value_of("days").unwrap())
+            .map_err(|_| Error::general("Invalid number of days"))?;
+        Ok((resources, days))
+    }
+
+    /// Reads an RTA from a file. The RTA may be DER encoded, or base64
+    /// encoded as shown by 'rta show'.
+    fn read_rta_arg(path: &str) -> Result<ResourceTaggedAttestation, Error> {
+        let bytes = Self::read_file_arg(path)?;
+        let decoded = from_utf8(bytes.as_ref())
+            .ok()
+            .and_then(|s| base64::decode(s.trim()).ok());
+        match decoded {
+            Some(der) => Ok(ResourceTaggedAttestation::new(Bytes::from(der))),
+            None => Ok(ResourceTaggedAttestation::new(bytes)),
+        }
+    }
+
+    fn parse_matches_rta_list(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::RtaList(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta_show(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        let name = Self::parse_rta_name(matches);
+
+        let command = Command::CertAuth(CaCommand::RtaShow(my_ca, name));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta_sign(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        let name = Self::parse_rta_name(matches);
+        let (resources, days) = Self::parse_rta_resources_and_days(matches)?;
+
+        let content = Self::read_file_arg(matches.value_of("in").unwrap())?;
+
+        let mut keys = vec![];
+        if let Some(values) = matches.values_of("keys") {
+            for value in values {
+                let key = KeyIdentifier::from_str(value)
+                    .map_err(|_| Error::general("Invalid key identifier"))?;
+                keys.push(key);
+            }
+        }
+
+        let request = RtaContentRequest::new(resources, days, keys, content.as_ref());
+        let command = Command::CertAuth(CaCommand::RtaSign(my_ca, name, request));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta_multi_prep(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        let name = Self::parse_rta_name(matches);
+        let (resources, days) = Self::parse_rta_resources_and_days(matches)?;
+
+        let request = RtaPrepareRequest::new(resources, days);
+        let command = Command::CertAuth(CaCommand::RtaMultiPrep(my_ca, name, request));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta_multi_cosign(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+        let name = Self::parse_rta_name(matches);
+
+        let rta = Self::read_rta_arg(matches.value_of("rta").unwrap())?;
+        let command = Command::CertAuth(CaCommand::RtaMultiCoSign(my_ca, name, rta));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta_multi(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("prep") {
+            Self::parse_matches_rta_multi_prep(m)
+        } else if let Some(m) = matches.subcommand_matches("cosign") {
+            Self::parse_matches_rta_multi_cosign(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_matches_rta_validate(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+
+        let rta = Self::read_rta_arg(matches.value_of("rta").unwrap())?;
+        let content = Self::read_file_arg(matches.value_of("in").unwrap())?;
+
+        let request = RtaValidationRequest::new(rta, content.as_ref());
+        let command = Command::RtaValidate(request);
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_rta(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            Self::parse_matches_rta_list(m)
+        } else if let Some(m) = matches.subcommand_matches("show") {
+            Self::parse_matches_rta_show(m)
+        } else if let Some(m) = matches.subcommand_matches("sign") {
+            Self::parse_matches_rta_sign(m)
+        } else if let Some(m) = matches.subcommand_matches("multi") {
+            Self::parse_matches_rta_multi(m)
+        } else if let Some(m) = matches.subcommand_matches("validate") {
+            Self::parse_matches_rta_validate(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
     fn parse_matches_cas_repo_request(matches: &ArgMatches) -> Result<Options, Error> {
         let general_args = GeneralArgs::from_matches(matches)?;
         let my_ca = Self::parse_my_ca(matches)?;
@@ -1371,6 +2149,22 @@ impl Options {
         Ok(Options::make(general_args, command))
     }
 
+    fn parse_matches_cas_repo_resume(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::RepoMigrationResume(my_ca));
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_repo_abort(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::RepoMigrationAbort(my_ca));
+        Ok(Options::make(general_args, command))
+    }
+
     fn parse_matches_cas_repo(matches: &ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("request") {
             Self::parse_matches_cas_repo_request(m)
@@ -1380,6 +2174,10 @@ impl Options {
             Self::parse_matches_cas_repo_state(m)
         } else if let Some(m) = matches.subcommand_matches("update") {
             Self::parse_matches_cas_repo_update(m)
+        } else if let Some(m) = matches.subcommand_matches("resume") {
+            Self::parse_matches_cas_repo_resume(m)
+        } else if let Some(m) = matches.subcommand_matches("abort") {
+            Self::parse_matches_cas_repo_abort(m)
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1510,6 +2308,28 @@ impl Options {
         Ok(Options::make(general_args, command))
     }
 
+    fn parse_matches_tasks(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let command = Command::Tasks;
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_integrity(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let command = Command::Integrity(matches.is_present("repair"));
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_backup(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let output = matches.value_of("output").unwrap().to_string();
+        let passphrase = env::var(KRILL_ENV_BACKUP_PASSPHRASE)
+            .ok()
+            .filter(|p| !p.is_empty());
+        let command = Command::Backup(output, BackupRequest::new(passphrase));
+        Ok(Options::make(general_args, command))
+    }
+
     fn parse_matches(matches: ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("config") {
             Self::parse_matches_config(m)
@@ -1531,6 +2351,12 @@ impl Options {
             Self::parse_matches_cas_keyroll(m)
         } else if let Some(m) = matches.subcommand_matches("roas") {
             Self::parse_matches_cas_routes(m)
+        } else if let Some(m) = matches.subcommand_matches("aspas") {
+            Self::parse_matches_cas_aspas(m)
+        } else if let Some(m) = matches.subcommand_matches("bgpsec") {
+            Self::parse_matches_cas_bgpsec(m)
+        } else if let Some(m) = matches.subcommand_matches("rta") {
+            Self::parse_matches_rta(m)
         } else if let Some(m) = matches.subcommand_matches("repo") {
             Self::parse_matches_cas_repo(m)
         } else if let Some(m) = matches.subcommand_matches("issues") {
@@ -1543,6 +2369,12 @@ impl Options {
             Self::parse_matches_health(m)
         } else if let Some(m) = matches.subcommand_matches("info") {
             Self::parse_matches_info(m)
+        } else if let Some(m) = matches.subcommand_matches("tasks") {
+            Self::parse_matches_tasks(m)
+        } else if let Some(m) = matches.subcommand_matches("integrity") {
+            Self::parse_matches_integrity(m)
+        } else if let Some(m) = matches.subcommand_matches("backup") {
+            Self::parse_matches_backup(m)
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1566,6 +2398,15 @@ pub enum Command {
     #[display(fmt = "info")]
     Info,
 
+    #[display(fmt = "tasks")]
+    Tasks,
+
+    #[display(fmt = "integrity, repair: {}", _0)]
+    Integrity(bool),
+
+    #[display(fmt = "backup to: '{}'", _0)]
+    Backup(String, BackupRequest),
+
     #[display(fmt = "bulk: {}", _0)]
     Bulk(BulkCaCommand),
 
@@ -1575,6 +2416,9 @@ pub enum Command {
     #[display(fmt = "publishers: {}", _0)]
     Publishers(PublishersCommand),
 
+    #[display(fmt = "validate RTA")]
+    RtaValidate(RtaValidationRequest),
+
     #[display(fmt = "init")]
     Init(KrillInitDetails),
 }
@@ -1611,6 +2455,12 @@ pub enum CaCommand {
     #[display(fmt = "get repo state for ca: '{}'", _0)]
     RepoState(Handle),
 
+    #[display(fmt = "resume repo migration for ca: '{}'", _0)]
+    RepoMigrationResume(Handle),
+
+    #[display(fmt = "abort repo migration for ca: '{}'", _0)]
+    RepoMigrationAbort(Handle),
+
     #[display(fmt = "add parent '{}' to ca: '{}'", _0, _1)]
     AddParent(Handle, ParentCaReq),
 
@@ -1642,6 +2492,9 @@ pub enum CaCommand {
     #[display(fmt = "activate key roll for ca: '{}'", _0)]
     KeyRollActivate(Handle),
 
+    #[display(fmt = "initialise key roll to signer '{}' for ca: '{}'", _1, _0)]
+    KeyRollSigner(Handle, CertAuthSignerUpdate),
+
     // Authorizations
     #[display(fmt = "list ROAS for ca: '{}'", _0)]
     RouteAuthorizationsList(Handle),
@@ -1649,6 +2502,9 @@ pub enum CaCommand {
     #[display(fmt = "Update ROAS for ca: '{}' -> {}", _0, _1)]
     RouteAuthorizationsUpdate(Handle, RoaDefinitionUpdates),
 
+    #[display(fmt = "Dry run update ROAS for ca: '{}' -> {}", _0, _1)]
+    RouteAuthorizationsDryRunUpdate(Handle, RoaDefinitionUpdates),
+
     #[display(fmt = "Show detailed ROA vs BGP analysis for ca: '{}'", _0)]
     BgpAnalysisFull(Handle),
 
@@ -1664,10 +2520,52 @@ pub enum CaCommand {
     )]
     BgpAnalysisRoas(Handle),
 
+    #[display(fmt = "Suggest ROA updates based on BGP analysis for ca: '{}'", _0)]
+    BgpAnalysisSuggest(Handle),
+
+    #[display(fmt = "Show BGP analysis timeline for ca: '{}'", _0)]
+    BgpAnalysisTimeline(Handle),
+
+    // ASPAs
+    #[display(fmt = "list ASPAs for ca: '{}'", _0)]
+    AspasList(Handle),
+
+    #[display(fmt = "Update ASPAs for ca: '{}' -> {}", _0, _1)]
+    AspasUpdate(Handle, AspaDefinitionUpdates),
+
+    #[display(fmt = "Update ASPA for customer AS{} of ca: '{}' -> {}", _1, _0, _2)]
+    AspasUpdateExisting(Handle, AsNumber, AspaProvidersUpdate),
+
+    // BGPsec
+    #[display(fmt = "list BGPsec router keys for ca: '{}'", _0)]
+    BgpSecList(Handle),
+
+    #[display(fmt = "Update BGPsec router keys for ca: '{}' -> {}", _0, _1)]
+    BgpSecUpdate(Handle, BgpSecDefinitionUpdates),
+
+    // RTA
+    #[display(fmt = "list RTAs for ca: '{}'", _0)]
+    RtaList(Handle),
+
+    #[display(fmt = "show RTA '{}' for ca: '{}'", _1, _0)]
+    RtaShow(Handle, RtaName),
+
+    #[display(fmt = "sign RTA '{}' for ca: '{}'", _1, _0)]
+    RtaSign(Handle, RtaName, RtaContentRequest),
+
+    #[display(fmt = "prepare multi-signer RTA '{}' for ca: '{}'", _1, _0)]
+    RtaMultiPrep(Handle, RtaName, RtaPrepareRequest),
+
+    #[display(fmt = "co-sign multi-signer RTA '{}' for ca: '{}'", _1, _0)]
+    RtaMultiCoSign(Handle, RtaName, ResourceTaggedAttestation),
+
     // Show details for this CA
     #[display(fmt = "Show details for ca: '{}'", _0)]
     Show(Handle),
 
+    #[display(fmt = "Show details for ca: '{}' at: {}", _0, _1)]
+    ShowAt(Handle, HistoryPoint),
+
     #[display(fmt = "Show history for ca: '{}', mode: {}", _0, _1)]
     ShowHistory(Handle, HistoryOptions),
 
@@ -6,12 +6,16 @@ use serde::Serialize;
 use rpki::x509::Time;
 
 use crate::commons::api::{
-    AllCertAuthIssues, CaCommandDetails, CaCommandResult, CaRepoDetails, CertAuthInfo,
-    CertAuthIssues, CertAuthList, ChildCaInfo, CommandHistory, CurrentObjects, CurrentRepoState,
-    ParentCaContact, PublisherDetails, PublisherList, RepositoryContact, RoaDefinition, ServerInfo,
-    StoredEffect,
+    AllCertAuthIssues, AspaDefinitionList, BgpSecCsrInfoList, CaCommandDetails, CaCommandResult,
+    CaRepoDetails, CaRepoState, CertAuthInfo, CertAuthInfoAt, CertAuthIssues, CertAuthList,
+    ChildCaInfo, CommandHistory, CurrentObjects, CurrentRepoState, IntegrityReport,
+    ParentCaContact, PublisherDetails, PublisherList, RepositoryContact, ResourceTaggedAttestation,
+    RoaConfiguration, RoaDefinitionUpdates, RtaList, RtaPrepResponse, RtaValidationReport,
+    ServerInfo, StoredEffect, TaskList,
+};
+use crate::commons::bgp::{
+    AnnouncementReport, BgpAnalysisReport, BgpStatsTimeline, RoaDryRunReport, RoaReport,
 };
-use crate::commons::bgp::{AnnouncementReport, BgpAnalysisReport, RoaReport};
 use crate::commons::eventsourcing::WithStorableDetails;
 use crate::commons::remote::api::ClientInfo;
 use crate::commons::remote::rfc8183;
@@ -25,15 +29,27 @@ use crate::pubd::RepoStats;
 pub enum ApiResponse {
     Health,
     Info(ServerInfo),
+    Tasks(TaskList),
+    Integrity(IntegrityReport),
 
     CertAuthInfo(CertAuthInfo),
+    CertAuthInfoAt(CertAuthInfoAt),
     CertAuthHistory(CommandHistory),
     CertAuthAction(CaCommandDetails),
     CertAuths(CertAuthList),
-    RouteAuthorizations(Vec<RoaDefinition>),
+    RouteAuthorizations(Vec<RoaConfiguration>),
+    AspaDefinitions(AspaDefinitionList),
+    BgpSecKeys(BgpSecCsrInfoList),
+    RtaList(RtaList),
+    Rta(ResourceTaggedAttestation),
+    RtaMultiPrep(RtaPrepResponse),
+    RtaValidation(RtaValidationReport),
     BgpAnalysisFull(BgpAnalysisReport),
+    BgpAnalysisDryRun(RoaDryRunReport),
     BgpAnalysisAnnouncements(AnnouncementReport),
     BgpAnalysisRoas(RoaReport),
+    BgpAnalysisSuggestions(RoaDefinitionUpdates),
+    BgpAnalysisTimeline(BgpStatsTimeline),
 
     ParentCaContact(ParentCaContact),
 
@@ -49,7 +65,7 @@ pub enum ApiResponse {
     Rfc8183PublisherRequest(rfc8183::PublisherRequest),
 
     RepoDetails(CaRepoDetails),
-    RepoState(CurrentRepoState),
+    RepoState(CaRepoState),
 
     CertAuthIssues(CertAuthIssues),
     AllCertAuthIssues(AllCertAuthIssues),
@@ -66,16 +82,28 @@ impl ApiResponse {
             match self {
                 ApiResponse::Health => Ok(None),
                 ApiResponse::Info(info) => Ok(Some(info.report(fmt)?)),
+                ApiResponse::Tasks(tasks) => Ok(Some(tasks.report(fmt)?)),
+                ApiResponse::Integrity(report) => Ok(Some(report.report(fmt)?)),
                 ApiResponse::CertAuths(list) => Ok(Some(list.report(fmt)?)),
                 ApiResponse::CertAuthInfo(info) => Ok(Some(info.report(fmt)?)),
+                ApiResponse::CertAuthInfoAt(info) => Ok(Some(info.report(fmt)?)),
                 ApiResponse::CertAuthHistory(history) => Ok(Some(history.report(fmt)?)),
                 ApiResponse::CertAuthAction(details) => Ok(Some(details.report(fmt)?)),
                 ApiResponse::CertAuthIssues(issues) => Ok(Some(issues.report(fmt)?)),
                 ApiResponse::AllCertAuthIssues(issues) => Ok(Some(issues.report(fmt)?)),
                 ApiResponse::RouteAuthorizations(auths) => Ok(Some(auths.report(fmt)?)),
+                ApiResponse::AspaDefinitions(aspas) => Ok(Some(aspas.report(fmt)?)),
+                ApiResponse::BgpSecKeys(keys) => Ok(Some(keys.report(fmt)?)),
+                ApiResponse::RtaList(list) => Ok(Some(list.report(fmt)?)),
+                ApiResponse::Rta(rta) => Ok(Some(rta.report(fmt)?)),
+                ApiResponse::RtaMultiPrep(response) => Ok(Some(response.report(fmt)?)),
+                ApiResponse::RtaValidation(report) => Ok(Some(report.report(fmt)?)),
                 ApiResponse::BgpAnalysisFull(table) => Ok(Some(table.report(fmt)?)),
+                ApiResponse::BgpAnalysisDryRun(report) => Ok(Some(report.report(fmt)?)),
                 ApiResponse::BgpAnalysisAnnouncements(summary) => Ok(Some(summary.report(fmt)?)),
                 ApiResponse::BgpAnalysisRoas(summary) => Ok(Some(summary.report(fmt)?)),
+                ApiResponse::BgpAnalysisSuggestions(updates) => Ok(Some(updates.report(fmt)?)),
+                ApiResponse::BgpAnalysisTimeline(timeline) => Ok(Some(timeline.report(fmt)?)),
                 ApiResponse::ParentCaContact(contact) => Ok(Some(contact.report(fmt)?)),
                 ApiResponse::ChildInfo(info) => Ok(Some(info.report(fmt)?)),
                 ApiResponse::PublisherList(list) => Ok(Some(list.report(fmt)?)),
@@ -231,11 +259,40 @@ impl Report for CertAuthInfo {
     }
 }
 
+impl Report for CertAuthInfoAt {
+    fn text(&self) -> Result<String, ReportError> {
+        let mut res = String::new();
+
+        res.push_str(&format!("Version:  {}\n", self.version()));
+        res.push_str(&self.info().text()?);
+        res.push_str("\n");
+
+        res.push_str("ROAs:\n");
+        if self.roas().is_empty() {
+            res.push_str("<none>\n");
+        } else {
+            res.push_str(&self.roas().text()?);
+        }
+        res.push_str("\n");
+
+        res.push_str("Issued child certificates:\n");
+        if self.issued().is_empty() {
+            res.push_str("<none>\n");
+        } else {
+            for issued in self.issued() {
+                res.push_str(&format!("{}\n", issued));
+            }
+        }
+
+        Ok(res)
+    }
+}
+
 impl Report for CommandHistory {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
 
-        res.push_str("time::command::key::success\n");
+        res.push_str("time::actor::command::key::success\n");
 
         for command in self.commands() {
             let success_string = match &command.effect {
@@ -243,8 +300,9 @@ impl Report for CommandHistory {
                 StoredEffect::Events(_) => "OK".to_string(),
             };
             res.push_str(&format!(
-                "{}::{} ::{}::{}\n",
+                "{}::{}::{} ::{}::{}\n",
                 command.time().to_rfc3339_opts(SecondsFormat::Secs, true),
+                command.actor,
                 command.summary.msg,
                 command.key,
                 success_string
@@ -264,6 +322,7 @@ impl Report for CaCommandDetails {
             "Time:   {}\n",
             command.time().to_rfc3339_opts(SecondsFormat::Secs, true)
         ));
+        res.push_str(&format!("Actor:  {}\n", command.actor()));
         res.push_str(&format!("Action: {}\n", command.details().summary().msg));
 
         match self.effect() {
@@ -404,22 +463,67 @@ impl Report for rfc8183::PublisherRequest {
     }
 }
 
-impl Report for Vec<RoaDefinition> {
+impl Report for Vec<RoaConfiguration> {
     fn text(&self) -> Result<String, ReportError> {
+        let mut sorted: Vec<&RoaConfiguration> = self.iter().collect();
+        sorted.sort_by_key(|config| config.definition());
+
         let mut res = String::new();
-        for a in self.iter() {
+        for a in sorted {
             res.push_str(&format!("{}\n", a));
         }
         Ok(res)
     }
 }
 
+impl Report for AspaDefinitionList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for BgpSecCsrInfoList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for RtaList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for ResourceTaggedAttestation {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for RtaPrepResponse {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for RtaValidationReport {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
 impl Report for BgpAnalysisReport {
     fn text(&self) -> Result<String, ReportError> {
         Ok(self.to_string())
     }
 }
 
+impl Report for RoaDryRunReport {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
 impl Report for AnnouncementReport {
     fn text(&self) -> Result<String, ReportError> {
         Ok(self.to_string())
@@ -432,6 +536,32 @@ impl Report for RoaReport {
     }
 }
 
+impl Report for BgpStatsTimeline {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for RoaDefinitionUpdates {
+    /// Uses the same format as the '--delta' file for 'krillc roas update',
+    /// sorted so that suggestions are easy to review.
+    fn text(&self) -> Result<String, ReportError> {
+        let mut added: Vec<&RoaConfiguration> = self.added().iter().collect();
+        added.sort_by_key(|a| a.definition());
+        let mut removed: Vec<_> = self.removed().iter().collect();
+        removed.sort();
+
+        let mut res = String::new();
+        for roa in added {
+            res.push_str(&format!("A: {}\n", roa));
+        }
+        for roa in removed {
+            res.push_str(&format!("R: {}\n", roa));
+        }
+        Ok(res)
+    }
+}
+
 impl Report for CaRepoDetails {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
@@ -479,6 +609,20 @@ impl Report for CurrentRepoState {
     }
 }
 
+impl Report for CaRepoState {
+    fn text(&self) -> Result<String, ReportError> {
+        let mut res = String::new();
+        if let Some(migration) = self.migration() {
+            res.push_str(&format!(
+                "Repository migration in progress, {}\n",
+                migration
+            ));
+        }
+        res.push_str(&self.current().text()?);
+        Ok(res)
+    }
+}
+
 impl Report for CertAuthIssues {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
@@ -524,6 +668,18 @@ impl Report for AllCertAuthIssues {
     }
 }
 
+impl Report for IntegrityReport {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for TaskList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
 impl Report for ServerInfo {
     fn text(&self) -> Result<String, ReportError> {
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(self.started(), 0), Utc);
@@ -523,6 +523,35 @@ impl CertAuthInit {
     }
 }
 
+//------------ CertAuthSignerUpdate ------------------------------------------
+
+/// Sets the signer that a CA prefers for its new keys. If no signer is given,
+/// the CA will use the signers configured for each key purpose again.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CertAuthSignerUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
+}
+
+impl fmt::Display for CertAuthSignerUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.signer {
+            Some(signer) => write!(f, "{}", signer),
+            None => write!(f, "<configured>"),
+        }
+    }
+}
+
+impl CertAuthSignerUpdate {
+    pub fn new(signer: Option<String>) -> Self {
+        CertAuthSignerUpdate { signer }
+    }
+
+    pub fn unpack(self) -> Option<String> {
+        self.signer
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[allow(clippy::large_enum_variant)]
 #[serde(rename_all = "snake_case")]
@@ -635,6 +664,25 @@ impl ServerInfo {
     }
 }
 
+//------------ BackupRequest -------------------------------------------------
+
+/// Requests a backup of the complete server state. If a passphrase is given,
+/// the signer keys and config in the backup are encrypted with it.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BackupRequest {
+    passphrase: Option<String>,
+}
+
+impl BackupRequest {
+    pub fn new(passphrase: Option<String>) -> Self {
+        BackupRequest { passphrase }
+    }
+
+    pub fn passphrase(&self) -> Option<&str> {
+        self.passphrase.as_deref()
+    }
+}
+
 //------------ Tests ---------------------------------------------------------
 
 #[cfg(test)]
@@ -0,0 +1,271 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::commons::api::{AsNumber, AuthorizationFmtError};
+
+//------------ AspaDefinition ----------------------------------------------
+
+/// This type defines an AS Provider Authorization (ASPA), i.e. the customer
+/// ASN and the set of provider ASNs which are authorized to propagate the
+/// announcements of the customer to their upstreams and peers.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct AspaDefinition {
+    customer: AsNumber,
+    providers: Vec<AsNumber>,
+}
+
+impl AspaDefinition {
+    /// Creates a new definition. Providers are kept in ascending order
+    /// without duplicates, as required for the ASPA object.
+    pub fn new(customer: AsNumber, mut providers: Vec<AsNumber>) -> Self {
+        providers.sort();
+        providers.dedup();
+        AspaDefinition {
+            customer,
+            providers,
+        }
+    }
+
+    pub fn customer(&self) -> AsNumber {
+        self.customer
+    }
+
+    pub fn providers(&self) -> &[AsNumber] {
+        &self.providers
+    }
+
+    /// Returns `true` if the customer ASN also appears as a provider. This
+    /// is not allowed.
+    pub fn customer_used_as_provider(&self) -> bool {
+        self.providers.contains(&self.customer)
+    }
+
+    /// Applies an update to the providers of this definition.
+    pub fn apply_update(&mut self, update: &AspaProvidersUpdate) {
+        self.providers.retain(|p| !update.removed.contains(p));
+        self.providers.extend(update.added.iter().cloned());
+        self.providers.sort();
+        self.providers.dedup();
+    }
+}
+
+impl FromStr for AspaDefinition {
+    type Err = AuthorizationFmtError;
+
+    // "64496 => 64497, 64498"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split("=>");
+
+        let customer_str = parts.next().ok_or_else(|| AuthorizationFmtError::aspa(s))?;
+        let customer = AsNumber::from_str(customer_str)?;
+
+        let providers_str = parts.next().ok_or_else(|| AuthorizationFmtError::aspa(s))?;
+        if parts.next().is_some() {
+            return Err(AuthorizationFmtError::aspa(s));
+        }
+
+        let mut providers = vec![];
+        for provider_str in providers_str.split(',') {
+            if !provider_str.trim().is_empty() {
+                providers.push(AsNumber::from_str(provider_str)?);
+            }
+        }
+
+        Ok(AspaDefinition::new(customer, providers))
+    }
+}
+
+impl fmt::Display for AspaDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} =>", self.customer)?;
+        for (i, provider) in self.providers.iter().enumerate() {
+            if i == 0 {
+                write!(f, " {}", provider)?;
+            } else {
+                write!(f, ", {}", provider)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ AspaDefinitionList ------------------------------------------
+
+/// The ASPA definitions configured for a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitionList(Vec<AspaDefinition>);
+
+impl AspaDefinitionList {
+    pub fn new(mut definitions: Vec<AspaDefinition>) -> Self {
+        definitions.sort_by_key(|def| def.customer());
+        AspaDefinitionList(definitions)
+    }
+
+    pub fn definitions(&self) -> &[AspaDefinition] {
+        &self.0
+    }
+}
+
+impl fmt::Display for AspaDefinitionList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for def in self.0.iter() {
+            writeln!(f, "{}", def)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ AspaDefinitionUpdates ---------------------------------------
+
+/// This type defines a delta of ASPA definitions. Definitions for a customer
+/// ASN that is already configured replace the existing definition.
+///
+/// Updates are sent as a single delta, so that ASPA objects are re-issued
+/// and published together.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitionUpdates {
+    add_or_replace: Vec<AspaDefinition>,
+    remove: Vec<AsNumber>,
+}
+
+impl AspaDefinitionUpdates {
+    pub fn new(add_or_replace: Vec<AspaDefinition>, remove: Vec<AsNumber>) -> Self {
+        AspaDefinitionUpdates {
+            add_or_replace,
+            remove,
+        }
+    }
+
+    pub fn add_or_replace(&self) -> &Vec<AspaDefinition> {
+        &self.add_or_replace
+    }
+
+    pub fn remove(&self) -> &Vec<AsNumber> {
+        &self.remove
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add_or_replace.is_empty() && self.remove.is_empty()
+    }
+
+    pub fn unpack(self) -> (Vec<AspaDefinition>, Vec<AsNumber>) {
+        (self.add_or_replace, self.remove)
+    }
+}
+
+impl fmt::Display for AspaDefinitionUpdates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.add_or_replace.is_empty() {
+            write!(f, "add or replace:")?;
+            for def in &self.add_or_replace {
+                write!(f, " '{}'", def)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.remove.is_empty() {
+            write!(f, "remove customers:")?;
+            for customer in &self.remove {
+                write!(f, " {}", customer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ AspaProvidersUpdate -----------------------------------------
+
+/// Adds and/or removes providers for an existing ASPA definition.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaProvidersUpdate {
+    added: Vec<AsNumber>,
+    removed: Vec<AsNumber>,
+}
+
+impl AspaProvidersUpdate {
+    pub fn new(added: Vec<AsNumber>, removed: Vec<AsNumber>) -> Self {
+        AspaProvidersUpdate { added, removed }
+    }
+
+    pub fn added(&self) -> &Vec<AsNumber> {
+        &self.added
+    }
+
+    pub fn removed(&self) -> &Vec<AsNumber> {
+        &self.removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for AspaProvidersUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.added.is_empty() {
+            write!(f, "add providers:")?;
+            for provider in &self.added {
+                write!(f, " {}", provider)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.removed.is_empty() {
+            write!(f, "remove providers:")?;
+            for provider in &self.removed {
+                write!(f, " {}", provider)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asn(nr: u32) -> AsNumber {
+        AsNumber::new(nr)
+    }
+
+    #[test]
+    fn parse_and_display_aspa_definition() {
+        let def = AspaDefinition::from_str("64496 => 64499, 64497,64498, 64497").unwrap();
+        assert_eq!(def.customer(), asn(64496));
+        assert_eq!(def.providers(), &[asn(64497), asn(64498), asn(64499)]);
+        assert_eq!(def.to_string(), "64496 => 64497, 64498, 64499");
+
+        let parsed = AspaDefinition::from_str(&def.to_string()).unwrap();
+        assert_eq!(parsed, def);
+
+        assert!(AspaDefinition::from_str("64496").is_err());
+        assert!(AspaDefinition::from_str("64496 => AS1").is_err());
+        assert!(AspaDefinition::from_str("64496 => 1 => 2").is_err());
+    }
+
+    #[test]
+    fn update_aspa_providers() {
+        let mut def = AspaDefinition::from_str("64496 => 64497, 64498").unwrap();
+        let update = AspaProvidersUpdate::new(vec![asn(64499), asn(64497)], vec![asn(64498)]);
+        def.apply_update(&update);
+        assert_eq!(def.providers(), &[asn(64497), asn(64499)]);
+        assert!(!def.customer_used_as_provider());
+
+        def.apply_update(&AspaProvidersUpdate::new(vec![asn(64496)], vec![]));
+        assert!(def.customer_used_as_provider());
+    }
+
+    #[test]
+    fn serde_aspa_updates() {
+        let json = r#"{
+            "add_or_replace": [ { "customer": 64496, "providers": [ 64497 ] } ],
+            "remove": [ 64500 ]
+        }"#;
+        let updates: AspaDefinitionUpdates = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            updates.add_or_replace(),
+            &vec![AspaDefinition::new(asn(64496), vec![asn(64497)])]
+        );
+        assert_eq!(updates.remove(), &vec![asn(64500)]);
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use rpki::crypto::KeyIdentifier;
+
+use crate::commons::api::{AsNumber, Base64};
+
+//------------ BgpSecAsnKey ------------------------------------------------
+
+/// Identifies a BGPsec router key for an ASN. A router certificate is issued
+/// for each combination of ASN and router key.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BgpSecAsnKey {
+    asn: AsNumber,
+    key: KeyIdentifier,
+}
+
+impl BgpSecAsnKey {
+    pub fn new(asn: AsNumber, key: KeyIdentifier) -> Self {
+        BgpSecAsnKey { asn, key }
+    }
+
+    pub fn asn(&self) -> AsNumber {
+        self.asn
+    }
+
+    pub fn key(&self) -> &KeyIdentifier {
+        &self.key
+    }
+}
+
+impl FromStr for BgpSecAsnKey {
+    type Err = BgpSecAsnKeyFmtError;
+
+    // "AS64496-<hex encoded key identifier>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if !s.starts_with("AS") {
+            return Err(BgpSecAsnKeyFmtError(s.to_string()));
+        }
+
+        let mut parts = s[2..].split('-');
+        let asn_str = parts
+            .next()
+            .ok_or_else(|| BgpSecAsnKeyFmtError(s.to_string()))?;
+        let key_str = parts
+            .next()
+            .ok_or_else(|| BgpSecAsnKeyFmtError(s.to_string()))?;
+        if parts.next().is_some() {
+            return Err(BgpSecAsnKeyFmtError(s.to_string()));
+        }
+
+        let asn = AsNumber::from_str(asn_str).map_err(|_| BgpSecAsnKeyFmtError(s.to_string()))?;
+        let key =
+            KeyIdentifier::from_str(key_str).map_err(|_| BgpSecAsnKeyFmtError(s.to_string()))?;
+
+        Ok(BgpSecAsnKey { asn, key })
+    }
+}
+
+impl fmt::Display for BgpSecAsnKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AS{}-{}", self.asn, self.key)
+    }
+}
+
+impl Serialize for BgpSecAsnKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BgpSecAsnKey {
+    fn deserialize<D>(deserializer: D) -> Result<BgpSecAsnKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+        BgpSecAsnKey::from_str(&string).map_err(de::Error::custom)
+    }
+}
+
+//------------ BgpSecAsnKeyFmtError ----------------------------------------
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+#[display(
+    fmt = "Invalid BGPsec router key, expected 'AS<asn>-<key id>', got: {}",
+    _0
+)]
+pub struct BgpSecAsnKeyFmtError(String);
+
+//------------ BgpSecDefinition --------------------------------------------
+
+/// This type defines a BGPsec router key to be certified for an ASN. The
+/// key is provided as a PKCS#10 certificate sign request (CSR) generated by
+/// the router, in base64 encoded DER.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecDefinition {
+    asn: AsNumber,
+    csr: Base64,
+}
+
+impl BgpSecDefinition {
+    pub fn new(asn: AsNumber, csr: Base64) -> Self {
+        BgpSecDefinition { asn, csr }
+    }
+
+    pub fn asn(&self) -> AsNumber {
+        self.asn
+    }
+
+    pub fn csr(&self) -> &Base64 {
+        &self.csr
+    }
+}
+
+impl fmt::Display for BgpSecDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AS{} csr: {}", self.asn, self.csr.to_hex_hash())
+    }
+}
+
+//------------ BgpSecDefinitionUpdates -------------------------------------
+
+/// This type defines a delta of BGPsec router keys to be certified and/or
+/// removed. Removing a router key will revoke its router certificates.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecDefinitionUpdates {
+    add: Vec<BgpSecDefinition>,
+    remove: Vec<BgpSecAsnKey>,
+}
+
+impl BgpSecDefinitionUpdates {
+    pub fn new(add: Vec<BgpSecDefinition>, remove: Vec<BgpSecAsnKey>) -> Self {
+        BgpSecDefinitionUpdates { add, remove }
+    }
+
+    pub fn add(&self) -> &Vec<BgpSecDefinition> {
+        &self.add
+    }
+
+    pub fn remove(&self) -> &Vec<BgpSecAsnKey> {
+        &self.remove
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    pub fn unpack(self) -> (Vec<BgpSecDefinition>, Vec<BgpSecAsnKey>) {
+        (self.add, self.remove)
+    }
+}
+
+impl fmt::Display for BgpSecDefinitionUpdates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.add.is_empty() {
+            write!(f, "add:")?;
+            for definition in &self.add {
+                write!(f, " '{}'", definition)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.remove.is_empty() {
+            write!(f, "remove:")?;
+            for key in &self.remove {
+                write!(f, " {}", key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ BgpSecCsrInfo -----------------------------------------------
+
+/// Shows a BGPsec router key that is certified by a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecCsrInfo {
+    asn: AsNumber,
+    key_identifier: KeyIdentifier,
+    csr: Base64,
+}
+
+impl BgpSecCsrInfo {
+    pub fn new(asn: AsNumber, key_identifier: KeyIdentifier, csr: Base64) -> Self {
+        BgpSecCsrInfo {
+            asn,
+            key_identifier,
+            csr,
+        }
+    }
+
+    pub fn asn(&self) -> AsNumber {
+        self.asn
+    }
+
+    pub fn key_identifier(&self) -> &KeyIdentifier {
+        &self.key_identifier
+    }
+
+    pub fn csr(&self) -> &Base64 {
+        &self.csr
+    }
+}
+
+//------------ BgpSecCsrInfoList -------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecCsrInfoList(Vec<BgpSecCsrInfo>);
+
+impl BgpSecCsrInfoList {
+    pub fn new(mut keys: Vec<BgpSecCsrInfo>) -> Self {
+        keys.sort_by(|a, b| {
+            a.asn
+                .cmp(&b.asn)
+                .then_with(|| a.key_identifier.as_slice().cmp(b.key_identifier.as_slice()))
+        });
+        BgpSecCsrInfoList(keys)
+    }
+
+    pub fn keys(&self) -> &[BgpSecCsrInfo] {
+        &self.0
+    }
+}
+
+impl fmt::Display for BgpSecCsrInfoList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ASN, key identifier")?;
+        for info in self.0.iter() {
+            writeln!(f, "AS{}, {}", info.asn, info.key_identifier)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display_asn_key() {
+        let s = "AS64496-17316903F0671229E8808BA8E8AB0105FA915A07";
+        let key = BgpSecAsnKey::from_str(s).unwrap();
+        assert_eq!(key.asn(), AsNumber::new(64496));
+        assert_eq!(key.to_string(), s);
+
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, format!("\"{}\"", s));
+        let deserialized: BgpSecAsnKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, key);
+
+        assert!(BgpSecAsnKey::from_str("64496-17316903F0671229E8808BA8E8AB0105FA915A07").is_err());
+        assert!(BgpSecAsnKey::from_str("AS64496").is_err());
+        assert!(BgpSecAsnKey::from_str("AS64496-XYZ").is_err());
+    }
+}
@@ -8,13 +8,14 @@ use std::str::{from_utf8_unchecked, FromStr};
 use std::{fmt, ops, str};
 
 use bytes::Bytes;
+use chrono::SecondsFormat;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use rpki::cert::Cert;
 use rpki::crl::{Crl, CrlEntry};
 use rpki::crypto::KeyIdentifier;
 use rpki::manifest::{FileAndHash, Manifest};
-use rpki::resources::{AsBlocks, AsResources, IpBlocks, IpBlocksForFamily, IpResources};
+use rpki::resources::{AsBlocks, AsId, AsResources, IpBlocks, IpBlocksForFamily, IpResources};
 use rpki::roa::Roa;
 use rpki::uri;
 use rpki::x509::{Serial, Time};
@@ -22,12 +23,13 @@ use rpki::x509::{Serial, Time};
 use crate::commons::api::publication;
 use crate::commons::api::publication::Publish;
 use crate::commons::api::{
-    Base64, ChildHandle, ErrorResponse, Handle, HexEncodedHash, IssuanceRequest, ListReply,
-    ParentCaContact, ParentHandle, RepositoryContact, RequestResourceLimit, RoaDefinition,
+    AsNumber, Base64, BgpSecAsnKey, ChildHandle, ErrorResponse, Handle, HexEncodedHash,
+    IssuanceRequest, ListReply, ParentCaContact, ParentHandle, RepositoryContact,
+    RequestResourceLimit, RoaConfiguration, RoaDefinition,
 };
 use crate::commons::remote::id::IdCert;
 use crate::commons::util::ext_serde;
-use crate::daemon::ca::RouteAuthorization;
+use crate::daemon::ca::{Aspa, BgpSecRouterCert, RouteAuthorization};
 
 //------------ ResourceClassName -------------------------------------------
 
@@ -639,6 +641,34 @@ impl From<&Roa> for CurrentObject {
     }
 }
 
+impl From<&Aspa> for CurrentObject {
+    fn from(aspa: &Aspa) -> Self {
+        let content = Base64::from(aspa);
+        let serial = aspa.cert().serial_number();
+        let expires = aspa.cert().validity().not_after();
+
+        CurrentObject {
+            content,
+            serial,
+            expires,
+        }
+    }
+}
+
+impl From<&BgpSecRouterCert> for CurrentObject {
+    fn from(cert: &BgpSecRouterCert) -> Self {
+        let content = Base64::from(cert);
+        let serial = cert.serial_number();
+        let expires = cert.validity().not_after();
+
+        CurrentObject {
+            content,
+            serial,
+            expires,
+        }
+    }
+}
+
 //------------ ObjectName ----------------------------------------------------
 
 /// This type is used to represent the (deterministic) file names for
@@ -650,6 +680,25 @@ impl ObjectName {
     pub fn new(ki: &KeyIdentifier, extension: &str) -> Self {
         ObjectName(format!("{}.{}", ki, extension))
     }
+
+    /// There is at most one ASPA object per customer ASN in a resource
+    /// class, so its name is derived from the customer ASN.
+    pub fn aspa(customer: AsNumber) -> Self {
+        ObjectName(format!("AS{}.asa", customer))
+    }
+
+    /// Aggregated ROAs include all authorizations for an ASN in a resource
+    /// class, so their name is derived from the ASN.
+    pub fn aggregate_roa(asn: AsNumber) -> Self {
+        ObjectName(format!("AS{}.roa", asn))
+    }
+
+    /// The same router key may be certified for more than one ASN, so the
+    /// name of a router certificate includes both.
+    pub fn bgpsec(key: &BgpSecAsnKey) -> Self {
+        let asn = u32::from(AsId::from(key.asn()));
+        ObjectName(format!("ROUTER-{:08X}-{}.cer", asn, key.key()))
+    }
 }
 
 impl From<&Cert> for ObjectName {
@@ -1627,6 +1676,97 @@ impl CertAuthInfo {
     }
 }
 
+//------------ CertAuthInfoAt ------------------------------------------------
+
+/// This type represents a CertAuth as it was at a given version in its
+/// history, including its ROA configurations and the certificates that
+/// it had issued to its children at the time.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CertAuthInfoAt {
+    version: u64,
+    info: CertAuthInfo,
+    roas: Vec<RoaConfiguration>,
+    issued: Vec<ChildIssuedCert>,
+}
+
+impl CertAuthInfoAt {
+    pub fn new(
+        version: u64,
+        info: CertAuthInfo,
+        roas: Vec<RoaConfiguration>,
+        issued: Vec<ChildIssuedCert>,
+    ) -> Self {
+        CertAuthInfoAt {
+            version,
+            info,
+            roas,
+            issued,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn info(&self) -> &CertAuthInfo {
+        &self.info
+    }
+
+    pub fn roas(&self) -> &Vec<RoaConfiguration> {
+        &self.roas
+    }
+
+    pub fn issued(&self) -> &Vec<ChildIssuedCert> {
+        &self.issued
+    }
+}
+
+//------------ ChildIssuedCert -----------------------------------------------
+
+/// A certificate issued to a child CA under one of the resource classes
+/// of its parent.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChildIssuedCert {
+    child: ChildHandle,
+    class_name: ResourceClassName,
+    cert: IssuedCert,
+}
+
+impl ChildIssuedCert {
+    pub fn new(child: ChildHandle, class_name: ResourceClassName, cert: IssuedCert) -> Self {
+        ChildIssuedCert {
+            child,
+            class_name,
+            cert,
+        }
+    }
+
+    pub fn child(&self) -> &ChildHandle {
+        &self.child
+    }
+
+    pub fn class_name(&self) -> &ResourceClassName {
+        &self.class_name
+    }
+
+    pub fn cert(&self) -> &IssuedCert {
+        &self.cert
+    }
+}
+
+impl fmt::Display for ChildIssuedCert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} class: {} uri: {} resources: {}",
+            self.child,
+            self.class_name,
+            self.cert.uri(),
+            self.cert.resource_set()
+        )
+    }
+}
+
 //------------ KeyStateInfo -------------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -1804,6 +1944,146 @@ impl CurrentRepoState {
     }
 }
 
+//------------ CaRepoState ---------------------------------------------------
+
+/// The state of the current repository for a CA, and of the migration to
+/// this repository if that is still in progress.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CaRepoState {
+    #[serde(flatten)]
+    current: CurrentRepoState,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migration: Option<RepoMigrationStatus>,
+}
+
+impl CaRepoState {
+    pub fn new(current: CurrentRepoState, migration: Option<RepoMigrationStatus>) -> Self {
+        CaRepoState { current, migration }
+    }
+
+    pub fn current(&self) -> &CurrentRepoState {
+        &self.current
+    }
+
+    pub fn migration(&self) -> Option<&RepoMigrationStatus> {
+        self.migration.as_ref()
+    }
+
+    pub fn as_list(&self) -> &ListReply {
+        self.current.as_list()
+    }
+}
+
+//------------ RepoMigrationPhase --------------------------------------------
+
+/// The phases of migrating a CA to a new repository, in order.
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoMigrationPhase {
+    /// All objects were re-issued for the new repository, and are being
+    /// published there.
+    #[display(fmt = "publishing at new repository")]
+    PublishingNew,
+
+    /// Certificates with the updated SIA were requested from all parents,
+    /// waiting for them to be issued.
+    #[display(fmt = "awaiting certificates from parents")]
+    AwaitingCertificates,
+
+    /// All certificates were issued, objects are withdrawn from the old
+    /// repository.
+    #[display(fmt = "withdrawing from old repository")]
+    WithdrawingOld,
+}
+
+//------------ RepoMigration -------------------------------------------------
+
+/// The phase of a repository migration, and when it was entered.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepoMigration {
+    phase: RepoMigrationPhase,
+    since: Time,
+}
+
+impl RepoMigration {
+    pub fn new(phase: RepoMigrationPhase) -> Self {
+        RepoMigration {
+            phase,
+            since: Time::now(),
+        }
+    }
+
+    pub fn phase(&self) -> RepoMigrationPhase {
+        self.phase
+    }
+
+    pub fn since(&self) -> Time {
+        self.since
+    }
+}
+
+impl fmt::Display for RepoMigration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} since {}",
+            self.phase,
+            self.since.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    }
+}
+
+//------------ RepoMigrationStatus -------------------------------------------
+
+/// The status of a repository migration which is in progress for a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepoMigrationStatus {
+    old_repository: RepositoryContact,
+    phase: RepoMigrationPhase,
+    since: Option<Time>,
+}
+
+impl RepoMigrationStatus {
+    pub fn new(
+        old_repository: RepositoryContact,
+        phase: RepoMigrationPhase,
+        since: Option<Time>,
+    ) -> Self {
+        RepoMigrationStatus {
+            old_repository,
+            phase,
+            since,
+        }
+    }
+
+    pub fn old_repository(&self) -> &RepositoryContact {
+        &self.old_repository
+    }
+
+    pub fn phase(&self) -> RepoMigrationPhase {
+        self.phase
+    }
+
+    pub fn since(&self) -> Option<Time> {
+        self.since
+    }
+}
+
+impl fmt::Display for RepoMigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "migrating from {}: {}", self.old_repository, self.phase)?;
+        if let Some(since) = self.since {
+            write!(
+                f,
+                " since {}",
+                since.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// This struct contains the API details for the configure Repository server,
 /// and objects published there, for a CA.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -1924,6 +2204,8 @@ pub struct BgpStats {
     pub announcements_invalid_asn: usize,
     pub announcements_invalid_length: usize,
     pub announcements_not_found: usize,
+    #[serde(default)]
+    pub announcements_low_visibility: usize,
     pub roas_stale: usize,
 }
 
@@ -1934,6 +2216,7 @@ impl Default for BgpStats {
             announcements_invalid_asn: 0,
             announcements_invalid_length: 0,
             announcements_not_found: 0,
+            announcements_low_visibility: 0,
             roas_stale: 0,
         }
     }
@@ -1956,6 +2239,10 @@ impl BgpStats {
         self.announcements_not_found += 1;
     }
 
+    pub fn increment_low_visibility(&mut self) {
+        self.announcements_low_visibility += 1;
+    }
+
     pub fn increment_unseen(&mut self) {
         self.roas_stale += 1;
     }
@@ -2192,4 +2479,26 @@ mod test {
         let empty_set_from_string = ResourceSet::from_str(&empty_set_string).unwrap();
         assert_eq!(empty_set, empty_set_from_string);
     }
+
+    #[test]
+    fn repo_state_without_migration_is_compatible() {
+        let json = r#"{"list":{"elements":[]}}"#;
+        let state: CaRepoState = serde_json::from_str(json).unwrap();
+        assert!(state.migration().is_none());
+        assert!(state.as_list().elements().is_empty());
+        assert_eq!(json, serde_json::to_string(&state).unwrap());
+
+        let migration = RepoMigrationStatus::new(
+            RepositoryContact::embedded(info()),
+            RepoMigrationPhase::AwaitingCertificates,
+            None,
+        );
+        let state = CaRepoState::new(
+            CurrentRepoState::list(ListReply::new(vec![])),
+            Some(migration),
+        );
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains(r#""phase":"awaiting_certificates""#));
+        assert_eq!(state, serde_json::from_str(&json).unwrap());
+    }
 }
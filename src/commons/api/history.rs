@@ -5,12 +5,13 @@ use std::str::FromStr;
 use rpki::crypto::KeyIdentifier;
 use rpki::x509::Time;
 
-use chrono::Utc;
 use chrono::{DateTime, NaiveDateTime};
+use chrono::{SecondsFormat, Utc};
 
 use crate::commons::api::{
-    ArgKey, ArgVal, ChildHandle, Handle, Label, Message, ParentHandle, PublisherHandle,
-    RequestResourceLimit, ResourceClassName, ResourceSet, RevocationRequest, RoaDefinitionUpdates,
+    ArgKey, ArgVal, AsNumber, AspaDefinitionUpdates, AspaProvidersUpdate, BgpSecDefinitionUpdates,
+    ChildHandle, Handle, Label, Message, ParentHandle, PublisherHandle, RequestResourceLimit,
+    ResourceClassName, ResourceSet, RevocationRequest, RoaDefinitionUpdates, RtaName,
     StorableParentContact,
 };
 use crate::commons::eventsourcing::{
@@ -199,6 +200,10 @@ impl CommandSummary {
         self.with_arg("seconds", seconds)
     }
 
+    pub fn with_rta_name(self, name: &str) -> Self {
+        self.with_arg("rta_name", name)
+    }
+
     pub fn with_added(self, nr: usize) -> Self {
         self.with_arg("added", nr)
     }
@@ -207,6 +212,10 @@ impl CommandSummary {
         self.with_arg("removed", nr)
     }
 
+    pub fn with_customer(self, customer: AsNumber) -> Self {
+        self.with_arg("customer", customer)
+    }
+
     pub fn with_service_uri_opt(self, service_uri_opt: Option<&ServiceUri>) -> Self {
         match service_uri_opt {
             None => self,
@@ -255,6 +264,14 @@ impl CommandHistoryCriteria {
         self.offset = Some(offset);
     }
 
+    pub fn before(&self) -> Option<i64> {
+        self.before
+    }
+
+    pub fn after(&self) -> Option<i64> {
+        self.after
+    }
+
     pub fn matches_timestamp_secs(&self, stamp: i64) -> bool {
         if let Some(before) = self.before {
             if stamp > before {
@@ -307,6 +324,48 @@ impl Default for CommandHistoryCriteria {
     }
 }
 
+//------------ HistoryPoint --------------------------------------------------
+
+/// Identifies a point in the history of an aggregate, either by its version
+/// or by a date/time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HistoryPoint {
+    Version(u64),
+    Time(Time),
+}
+
+impl FromStr for HistoryPoint {
+    type Err = HistoryPointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(version) = u64::from_str(s) {
+            Ok(HistoryPoint::Version(version))
+        } else {
+            Time::from_str(s)
+                .map(HistoryPoint::Time)
+                .map_err(|_| HistoryPointError(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for HistoryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryPoint::Version(version) => write!(f, "{}", version),
+            HistoryPoint::Time(time) => {
+                write!(f, "{}", time.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+#[display(
+    fmt = "Invalid point in history '{}', expected a version number or RFC 3339 date/time",
+    _0
+)]
+pub struct HistoryPointError(String);
+
 //------------ StorableCaCommand -------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -326,6 +385,7 @@ pub enum StorableCaCommand {
     ChildRevokeKey(ChildHandle, RevocationRequest),
     ChildRemove(ChildHandle),
     GenerateNewIdKey,
+    UpdateSigner(Option<String>),
     AddParent(ParentHandle, StorableParentContact),
     UpdateParentContact(ParentHandle, StorableParentContact),
     RemoveParent(ParentHandle),
@@ -335,9 +395,17 @@ pub enum StorableCaCommand {
     KeyRollActivate(i64),
     KeyRollFinish(ResourceClassName),
     RoaDefinitionUpdates(RoaDefinitionUpdates),
+    AspasUpdate(AspaDefinitionUpdates),
+    AspasUpdateExisting(AsNumber, AspaProvidersUpdate),
+    BgpSecDefinitionsUpdate(BgpSecDefinitionUpdates),
+    RtaSign(RtaName),
+    RtaMultiPrepare(RtaName),
+    RtaCoSign(RtaName),
     Republish,
     RepoUpdate(Option<ServiceUri>),
     RepoRemoveOld,
+    RepoMigrationAdvance,
+    RepoMigrationAbort,
 }
 
 impl WithStorableDetails for StorableCaCommand {
@@ -378,6 +446,10 @@ impl WithStorableDetails for StorableCaCommand {
             StorableCaCommand::GenerateNewIdKey => {
                 CommandSummary::new("cmd-ca-generate-new-id", &self)
             }
+            StorableCaCommand::UpdateSigner(signer) => {
+                CommandSummary::new("cmd-ca-signer-update", &self)
+                    .with_arg("signer", signer.as_deref().unwrap_or("<none>"))
+            }
             StorableCaCommand::AddParent(parent, contact) => {
                 CommandSummary::new("cmd-ca-parent-add", &self)
                     .with_parent(parent)
@@ -413,12 +485,43 @@ impl WithStorableDetails for StorableCaCommand {
                     .with_added(updates.added().len())
                     .with_removed(updates.removed().len())
             }
+            StorableCaCommand::AspasUpdate(updates) => {
+                CommandSummary::new("cmd-ca-aspas-update", &self)
+                    .with_added(updates.add_or_replace().len())
+                    .with_removed(updates.remove().len())
+            }
+            StorableCaCommand::AspasUpdateExisting(customer, update) => {
+                CommandSummary::new("cmd-ca-aspa-update", &self)
+                    .with_customer(*customer)
+                    .with_added(update.added().len())
+                    .with_removed(update.removed().len())
+            }
+            StorableCaCommand::BgpSecDefinitionsUpdate(updates) => {
+                CommandSummary::new("cmd-ca-bgpsec-update", &self)
+                    .with_added(updates.add().len())
+                    .with_removed(updates.remove().len())
+            }
+            StorableCaCommand::RtaSign(name) => {
+                CommandSummary::new("cmd-ca-rta-sign", &self).with_rta_name(name)
+            }
+            StorableCaCommand::RtaMultiPrepare(name) => {
+                CommandSummary::new("cmd-ca-rta-multi-prep", &self).with_rta_name(name)
+            }
+            StorableCaCommand::RtaCoSign(name) => {
+                CommandSummary::new("cmd-ca-rta-multi-cosign", &self).with_rta_name(name)
+            }
             StorableCaCommand::Republish => CommandSummary::new("cmd-ca-publish", &self),
             StorableCaCommand::RepoUpdate(service_uri_opt) => {
                 CommandSummary::new("cmd-ca-repo-update", &self)
                     .with_service_uri_opt(service_uri_opt.as_ref())
             }
             StorableCaCommand::RepoRemoveOld => CommandSummary::new("cmd-ca-repo-clean", &self),
+            StorableCaCommand::RepoMigrationAdvance => {
+                CommandSummary::new("cmd-ca-repo-migration-advance", &self)
+            }
+            StorableCaCommand::RepoMigrationAbort => {
+                CommandSummary::new("cmd-ca-repo-migration-abort", &self)
+            }
         }
     }
 }
@@ -471,6 +574,10 @@ impl fmt::Display for StorableCaCommand {
             // Being a child (only allowed if this CA is not self-signed)
             // ------------------------------------------------------------
             StorableCaCommand::GenerateNewIdKey => write!(f, "Generate a new RFC8183 ID."),
+            StorableCaCommand::UpdateSigner(signer) => match signer {
+                Some(signer) => write!(f, "Prefer signer '{}' for new keys", signer),
+                None => write!(f, "Use configured signers for new keys"),
+            },
             StorableCaCommand::AddParent(parent, contact) => {
                 write!(f, "Add parent '{}' as '{}'", parent, contact)
             }
@@ -524,6 +631,30 @@ impl fmt::Display for StorableCaCommand {
                 updates.removed().len()
             ),
 
+            // ------------------------------------------------------------
+            // ASPA Support
+            // ------------------------------------------------------------
+            StorableCaCommand::AspasUpdate(updates) => write!(f, "Update ASPAs {}", updates),
+            StorableCaCommand::AspasUpdateExisting(customer, update) => {
+                write!(f, "Update ASPA for customer AS{}: {}", customer, update)
+            }
+
+            // ------------------------------------------------------------
+            // BGPsec Support
+            // ------------------------------------------------------------
+            StorableCaCommand::BgpSecDefinitionsUpdate(updates) => {
+                write!(f, "Update BGPsec router keys {}", updates)
+            }
+
+            // ------------------------------------------------------------
+            // RTA Support
+            // ------------------------------------------------------------
+            StorableCaCommand::RtaSign(name) => write!(f, "Sign RTA '{}'", name),
+            StorableCaCommand::RtaMultiPrepare(name) => {
+                write!(f, "Prepare keys for multi-signer RTA '{}'", name)
+            }
+            StorableCaCommand::RtaCoSign(name) => write!(f, "Co-sign multi-signer RTA '{}'", name),
+
             // ------------------------------------------------------------
             // Publishing
             // ------------------------------------------------------------
@@ -533,6 +664,8 @@ impl fmt::Display for StorableCaCommand {
                 Some(uri) => write!(f, "Update repo to server at: {}", uri),
             },
             StorableCaCommand::RepoRemoveOld => write!(f, "Clean up old repository"),
+            StorableCaCommand::RepoMigrationAdvance => write!(f, "Advance repository migration"),
+            StorableCaCommand::RepoMigrationAbort => write!(f, "Abort repository migration"),
         }
     }
 }
@@ -587,3 +720,29 @@ impl fmt::Display for StorableRepositoryCommand {
         }
     }
 }
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_history_point() {
+        assert_eq!(
+            HistoryPoint::Version(12),
+            HistoryPoint::from_str("12").unwrap()
+        );
+
+        let point = HistoryPoint::from_str("2020-04-09T19:37:02Z").unwrap();
+        match point {
+            HistoryPoint::Time(time) => assert_eq!(1_586_461_022, time.timestamp()),
+            _ => panic!("Expected a time"),
+        }
+        assert_eq!("2020-04-09T19:37:02Z", point.to_string());
+
+        assert!(HistoryPoint::from_str("-1").is_err());
+        assert!(HistoryPoint::from_str("2020-04-09").is_err());
+        assert!(HistoryPoint::from_str("yesterday").is_err());
+    }
+}
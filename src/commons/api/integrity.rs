@@ -0,0 +1,222 @@
+//! Integrity of the stored history of CAs and the repository.
+
+use std::fmt;
+
+use crate::commons::api::Handle;
+
+//------------ IntegrityReport -----------------------------------------------
+
+/// The result of verifying, and optionally repairing, all stores.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct IntegrityReport {
+    stores: Vec<StoreIntegrityReport>,
+}
+
+impl IntegrityReport {
+    pub fn new(stores: Vec<StoreIntegrityReport>) -> Self {
+        IntegrityReport { stores }
+    }
+
+    pub fn stores(&self) -> &Vec<StoreIntegrityReport> {
+        &self.stores
+    }
+
+    /// Returns true if no issues were found, or all were repaired.
+    pub fn is_ok(&self) -> bool {
+        self.stores.iter().all(|s| s.is_ok())
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for store in self.stores.iter() {
+            write!(f, "{}", store)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ StoreIntegrityReport ------------------------------------------
+
+/// The issues found for the aggregates in a single store, e.g. "cas".
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StoreIntegrityReport {
+    name: String,
+    checked: usize,
+    aggregates: Vec<AggregateIntegrityReport>,
+}
+
+impl StoreIntegrityReport {
+    /// Creates a report for a store, keeping only the aggregates which had
+    /// issues.
+    pub fn new(name: &str, aggregates: Vec<AggregateIntegrityReport>) -> Self {
+        let checked = aggregates.len();
+        let aggregates = aggregates.into_iter().filter(|a| !a.is_clean()).collect();
+        StoreIntegrityReport {
+            name: name.to_string(),
+            checked,
+            aggregates,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    pub fn aggregates(&self) -> &Vec<AggregateIntegrityReport> {
+        &self.aggregates
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.aggregates.iter().all(|a| a.is_ok())
+    }
+}
+
+impl fmt::Display for StoreIntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Store '{}': checked {}, with issues {}",
+            self.name,
+            self.checked,
+            self.aggregates.len()
+        )?;
+        for aggregate in self.aggregates.iter() {
+            write!(f, "{}", aggregate)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ AggregateIntegrityReport --------------------------------------
+
+/// The issues found for a single aggregate, and whether they were repaired.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AggregateIntegrityReport {
+    handle: Handle,
+    issues: Vec<IntegrityIssue>,
+    repaired: bool,
+}
+
+impl AggregateIntegrityReport {
+    pub fn new(handle: Handle, issues: Vec<IntegrityIssue>) -> Self {
+        AggregateIntegrityReport {
+            handle,
+            issues,
+            repaired: false,
+        }
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn issues(&self) -> &Vec<IntegrityIssue> {
+        &self.issues
+    }
+
+    pub fn repaired(&self) -> bool {
+        self.repaired
+    }
+
+    pub fn set_repaired(&mut self) {
+        self.repaired = true;
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns true if all issues can be repaired safely, i.e. without
+    /// losing any events.
+    pub fn is_repairable(&self) -> bool {
+        !self.is_clean() && self.issues.iter().all(|i| i.is_repairable())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.is_clean() || self.repaired
+    }
+}
+
+impl fmt::Display for AggregateIntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.repaired {
+            "repaired"
+        } else if self.is_repairable() {
+            "repairable"
+        } else {
+            "needs manual repair"
+        };
+        writeln!(f, "  {} ({}):", self.handle, status)?;
+        for issue in self.issues.iter() {
+            writeln!(f, "    {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ IntegrityIssue ------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum IntegrityIssue {
+    #[display(fmt = "init event is missing")]
+    InitEventMissing,
+
+    #[display(fmt = "event {} cannot be read: {}", version, cause)]
+    EventUnreadable { version: u64, cause: String },
+
+    #[display(fmt = "event {} is missing, but event {} exists", missing, found)]
+    EventMissing { missing: u64, found: u64 },
+
+    #[display(fmt = "archive cannot be read: {}", cause)]
+    ArchiveUnreadable { cause: String },
+
+    #[display(fmt = "snapshot cannot be read: {}", cause)]
+    SnapshotUnreadable { cause: String },
+
+    #[display(
+        fmt = "snapshot version {} is ahead of events up to version {}",
+        snapshot,
+        events
+    )]
+    SnapshotAhead { snapshot: u64, events: u64 },
+
+    #[display(fmt = "snapshot at version {} does not match replayed events", version)]
+    SnapshotMismatch { version: u64 },
+
+    #[display(fmt = "info cannot be read: {}", cause)]
+    InfoUnreadable { cause: String },
+
+    #[display(fmt = "info has '{}' {}, but found {}", field, stored, found)]
+    InfoInconsistent {
+        field: String,
+        stored: u64,
+        found: u64,
+    },
+
+    #[display(fmt = "command '{}' cannot be read: {}", key, cause)]
+    CommandUnreadable { key: String, cause: String },
+
+    #[display(fmt = "command '{}' refers to missing event {}", key, version)]
+    CommandEventMissing { key: String, version: u64 },
+}
+
+impl IntegrityIssue {
+    /// Snapshots and info can be rebuilt from the events, but events,
+    /// commands and archives cannot be recovered.
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            IntegrityIssue::SnapshotUnreadable { .. }
+            | IntegrityIssue::SnapshotAhead { .. }
+            | IntegrityIssue::SnapshotMismatch { .. }
+            | IntegrityIssue::InfoUnreadable { .. }
+            | IntegrityIssue::InfoInconsistent { .. } => true,
+            _ => false,
+        }
+    }
+}
@@ -3,12 +3,21 @@
 mod admin;
 pub use self::admin::*;
 
+mod aspa;
+pub use self::aspa::*;
+
+mod bgpsec;
+pub use self::bgpsec::*;
+
 mod ca;
 pub use self::ca::*;
 
 mod history;
 pub use self::history::*;
 
+mod integrity;
+pub use self::integrity::*;
+
 mod provisioning;
 pub use self::provisioning::*;
 
@@ -18,6 +27,12 @@ pub use self::publication::*;
 mod roas;
 pub use self::roas::*;
 
+mod rta;
+pub use self::rta::*;
+
+mod tasks;
+pub use self::tasks::*;
+
 pub mod rrdp;
 
 use std::collections::HashMap;
@@ -33,7 +48,7 @@ use rpki::manifest::Manifest;
 use rpki::roa::Roa;
 
 use crate::commons::util::sha256;
-use crate::daemon::ca::RouteAuthorization;
+use crate::daemon::ca::{Aspa, BgpSecRouterCert, RouteAuthorization};
 
 // Some syntactic sugar to help this old coder's brain deal with the mess of Strings
 pub type Message = String;
@@ -98,6 +113,18 @@ impl From<&Roa> for Base64 {
     }
 }
 
+impl From<&Aspa> for Base64 {
+    fn from(aspa: &Aspa) -> Self {
+        Base64::from_content(&aspa.to_captured().into_bytes())
+    }
+}
+
+impl From<&BgpSecRouterCert> for Base64 {
+    fn from(cert: &BgpSecRouterCert) -> Self {
+        Base64::from_content(cert.to_bytes().as_ref())
+    }
+}
+
 impl From<&Manifest> for Base64 {
     fn from(mft: &Manifest) -> Self {
         Base64::from_content(&mft.to_captured().into_bytes())
@@ -273,6 +300,10 @@ impl ErrorResponse {
         self.with_arg("base_uri", base_uri)
     }
 
+    pub fn with_history_point(self, point: &HistoryPoint) -> Self {
+        self.with_arg("point", point)
+    }
+
     pub fn with_ca(self, ca: &Handle) -> Self {
         self.with_arg("ca", ca)
     }
@@ -285,6 +316,10 @@ impl ErrorResponse {
         self.with_arg("child", child)
     }
 
+    pub fn with_signer(self, signer: &str) -> Self {
+        self.with_arg("signer", signer)
+    }
+
     pub fn with_auth(self, auth: &RouteAuthorization) -> Self {
         let mut res = self
             .with_arg("prefix", auth.prefix())
@@ -297,6 +332,26 @@ impl ErrorResponse {
         res
     }
 
+    pub fn with_customer(self, customer: AsNumber) -> Self {
+        self.with_arg("customer", customer)
+    }
+
+    pub fn with_asn(self, asn: AsNumber) -> Self {
+        self.with_arg("asn", asn)
+    }
+
+    pub fn with_router_key(self, key: &BgpSecAsnKey) -> Self {
+        self.with_arg("router_key", key)
+    }
+
+    pub fn with_rta(self, name: &str) -> Self {
+        self.with_arg("rta", name)
+    }
+
+    pub fn with_resources(self, resources: &ResourceSet) -> Self {
+        self.with_arg("resources", resources)
+    }
+
     pub fn with_key_identifier(self, ki: &KeyIdentifier) -> Self {
         self.with_arg("key_id", ki)
     }
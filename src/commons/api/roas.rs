@@ -5,9 +5,11 @@ use std::net::IpAddr;
 use std::ops::Deref;
 use std::str::FromStr;
 
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-use rpki::resources::{AsBlocks, AsId, IpBlocks, IpBlocksBuilder, Prefix};
+use rpki::resources::{AsBlocks, AsBlocksBuilder, AsId, IpBlocks, IpBlocksBuilder, Prefix};
+use rpki::x509::Time;
 
 use crate::commons::api::ResourceSet;
 use crate::daemon::ca::RouteAuthorizationUpdates;
@@ -160,6 +162,176 @@ impl AsRef<TypedPrefix> for RoaDefinition {
     }
 }
 
+//------------ RoaMeta -----------------------------------------------------
+
+/// Optional meta-information which users can attach to a ROA definition to
+/// record why it exists, and for how long it should exist.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RoaMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    valid_until: Option<Time>,
+}
+
+impl RoaMeta {
+    pub fn new(comment: Option<String>, tags: Vec<String>, valid_until: Option<Time>) -> Self {
+        RoaMeta {
+            comment,
+            tags,
+            valid_until,
+        }
+    }
+
+    pub fn comment(&self) -> Option<&String> {
+        self.comment.as_ref()
+    }
+
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    pub fn valid_until(&self) -> Option<Time> {
+        self.valid_until
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comment.is_none() && self.tags.is_empty() && self.valid_until.is_none()
+    }
+
+    /// Returns `true` if this has a 'valid until' time which is not after
+    /// the given time.
+    pub fn expired(&self, now: Time) -> bool {
+        match self.valid_until {
+            Some(valid_until) => valid_until <= now,
+            None => false,
+        }
+    }
+
+    /// Parses a single meta-information element, e.g. "comment = some text",
+    /// "tags = a, b" or "valid_until = 2021-01-01", into this.
+    fn parse_element(&mut self, s: &str) -> Result<(), AuthorizationFmtError> {
+        let mut parts = s.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| AuthorizationFmtError::meta(s))?
+            .trim();
+
+        match key {
+            "comment" => {
+                if !value.is_empty() {
+                    self.comment = Some(value.to_string());
+                }
+            }
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .map(|t| t.trim())
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.to_string())
+                    .collect();
+            }
+            "valid_until" => {
+                let time = match Time::from_str(value) {
+                    Ok(time) => time,
+                    Err(_) => {
+                        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                            .map_err(|_| AuthorizationFmtError::meta(s))?;
+                        Time::new(DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+                    }
+                };
+                self.valid_until = Some(time);
+            }
+            _ => return Err(AuthorizationFmtError::meta(s)),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RoaMeta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(comment) = &self.comment {
+            write!(f, " ; comment = {}", comment)?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " ; tags = {}", self.tags.join(", "))?;
+        }
+        if let Some(valid_until) = &self.valid_until {
+            write!(
+                f,
+                " ; valid_until = {}",
+                valid_until.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//------------ RoaConfiguration --------------------------------------------
+
+/// A ROA definition together with its (optional) meta-information, as it is
+/// configured by a user.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RoaConfiguration {
+    #[serde(flatten)]
+    definition: RoaDefinition,
+    #[serde(flatten)]
+    meta: RoaMeta,
+}
+
+impl RoaConfiguration {
+    pub fn new(definition: RoaDefinition, meta: RoaMeta) -> Self {
+        RoaConfiguration { definition, meta }
+    }
+
+    pub fn definition(&self) -> RoaDefinition {
+        self.definition
+    }
+
+    pub fn meta(&self) -> &RoaMeta {
+        &self.meta
+    }
+
+    pub fn unpack(self) -> (RoaDefinition, RoaMeta) {
+        (self.definition, self.meta)
+    }
+}
+
+impl From<RoaDefinition> for RoaConfiguration {
+    fn from(definition: RoaDefinition) -> Self {
+        RoaConfiguration {
+            definition,
+            meta: RoaMeta::default(),
+        }
+    }
+}
+
+impl FromStr for RoaConfiguration {
+    type Err = AuthorizationFmtError;
+
+    // "192.168.0.0/16 => 64496 ; comment = some text ; tags = a, b ; valid_until = 2021-01-01"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let definition = RoaDefinition::from_str(parts.next().unwrap_or("").trim())?;
+
+        let mut meta = RoaMeta::default();
+        for element in parts {
+            meta.parse_element(element)?;
+        }
+
+        Ok(RoaConfiguration { definition, meta })
+    }
+}
+
+impl fmt::Display for RoaConfiguration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.definition, self.meta)
+    }
+}
+
 //------------ RouteAuthorizationUpdates -----------------------------------
 
 /// This type defines a delta of Route Authorizations, i.e. additions or removals
@@ -172,18 +344,26 @@ impl AsRef<TypedPrefix> for RoaDefinition {
 /// avoid invalidating announcements.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct RoaDefinitionUpdates {
-    added: HashSet<RoaDefinition>,
+    added: HashSet<RoaConfiguration>,
     removed: HashSet<RoaDefinition>,
 }
 
 impl RoaDefinitionUpdates {
     pub fn new(added: HashSet<RoaDefinition>, removed: HashSet<RoaDefinition>) -> Self {
+        let added = added.into_iter().map(RoaConfiguration::from).collect();
+        RoaDefinitionUpdates { added, removed }
+    }
+
+    pub fn with_configurations(
+        added: HashSet<RoaConfiguration>,
+        removed: HashSet<RoaDefinition>,
+    ) -> Self {
         RoaDefinitionUpdates { added, removed }
     }
 
     /// Unpack this and return all added (left), and all removed (right) route
     /// authorizations.
-    pub fn unpack(self) -> (HashSet<RoaDefinition>, HashSet<RoaDefinition>) {
+    pub fn unpack(self) -> (HashSet<RoaConfiguration>, HashSet<RoaDefinition>) {
         (self.added, self.removed)
     }
 
@@ -192,10 +372,14 @@ impl RoaDefinitionUpdates {
     }
 
     pub fn add(&mut self, add: RoaDefinition) {
+        self.added.insert(add.into());
+    }
+
+    pub fn add_configuration(&mut self, add: RoaConfiguration) {
         self.added.insert(add);
     }
 
-    pub fn added(&self) -> &HashSet<RoaDefinition> {
+    pub fn added(&self) -> &HashSet<RoaConfiguration> {
         &self.added
     }
 
@@ -248,7 +432,7 @@ impl FromStr for RoaDefinitionUpdates {
             } else if line.starts_with("A:") {
                 let line = &line[2..];
                 let line = line.trim();
-                let auth = RoaDefinition::from_str(line)?;
+                let auth = RoaConfiguration::from_str(line)?;
                 added.insert(auth);
             } else if line.starts_with("R:") {
                 let line = &line[2..];
@@ -267,7 +451,10 @@ impl FromStr for RoaDefinitionUpdates {
 impl From<RouteAuthorizationUpdates> for RoaDefinitionUpdates {
     fn from(auth_updates: RouteAuthorizationUpdates) -> Self {
         let (auth_added, auth_removed) = auth_updates.unpack();
-        let added = auth_added.into_iter().map(|a| a.into()).collect();
+        let added = auth_added
+            .into_iter()
+            .map(|(auth, meta)| RoaConfiguration::new(auth.into(), meta))
+            .collect();
         let removed = auth_removed.into_iter().map(|a| a.into()).collect();
         RoaDefinitionUpdates { added, removed }
     }
@@ -281,6 +468,16 @@ pub enum TypedPrefix {
 }
 
 impl TypedPrefix {
+    /// Creates a prefix for the given address and length. Any bits in the
+    /// address beyond the length are ignored.
+    pub fn from_addr(addr: IpAddr, len: u8) -> Result<Self, AuthorizationFmtError> {
+        match addr {
+            IpAddr::V4(v4) if len <= 32 => Ok(TypedPrefix::V4(Ipv4Prefix(Prefix::new(v4, len)))),
+            IpAddr::V6(v6) if len <= 128 => Ok(TypedPrefix::V6(Ipv6Prefix(Prefix::new(v6, len)))),
+            _ => Err(AuthorizationFmtError::pfx(&format!("{}/{}", addr, len))),
+        }
+    }
+
     pub fn prefix(&self) -> &Prefix {
         self.as_ref()
     }
@@ -479,6 +676,16 @@ impl From<AsNumber> for AsId {
     }
 }
 
+impl From<AsNumber> for ResourceSet {
+    fn from(asn: AsNumber) -> ResourceSet {
+        let mut builder = AsBlocksBuilder::new();
+        builder.push(AsId::from(asn));
+        let blocks = builder.finalize();
+
+        ResourceSet::new(blocks, IpBlocks::empty(), IpBlocks::empty())
+    }
+}
+
 impl FromStr for AsNumber {
     type Err = AuthorizationFmtError;
 
@@ -528,6 +735,12 @@ pub enum AuthorizationFmtError {
 
     #[display(fmt = "Invalid authorization delta string: {}", _0)]
     Delta(String),
+
+    #[display(fmt = "Invalid ASPA definition string: {}", _0)]
+    Aspa(String),
+
+    #[display(fmt = "Invalid ROA meta-information: {}", _0)]
+    Meta(String),
 }
 
 impl AuthorizationFmtError {
@@ -546,6 +759,14 @@ impl AuthorizationFmtError {
     pub fn delta(s: &str) -> Self {
         AuthorizationFmtError::Delta(s.to_string())
     }
+
+    pub fn aspa(s: &str) -> Self {
+        AuthorizationFmtError::Aspa(s.to_string())
+    }
+
+    pub fn meta(s: &str) -> Self {
+        AuthorizationFmtError::Meta(s.to_string())
+    }
 }
 
 //------------ Tests -------------------------------------------------------
@@ -584,6 +805,72 @@ mod tests {
         assert_eq!(parsed, reparsed);
     }
 
+    #[test]
+    fn parse_delta_with_meta() {
+        let delta = concat!(
+            "A: 192.168.0.0/16 => 64496 ; comment = customer x ; tags = a, b\n",
+            "A: 192.168.1.0/24 => 64496 ; valid_until = 2021-01-01 # inline comment\n",
+            "R: 192.168.3.0/24 => 64496\n",
+        );
+
+        let parsed = RoaDefinitionUpdates::from_str(delta).unwrap();
+
+        let with_comment = parsed
+            .added()
+            .iter()
+            .find(|c| c.definition() == definition("192.168.0.0/16 => 64496"))
+            .unwrap();
+        assert_eq!(
+            Some(&"customer x".to_string()),
+            with_comment.meta().comment()
+        );
+        assert_eq!(
+            &vec!["a".to_string(), "b".to_string()],
+            with_comment.meta().tags()
+        );
+
+        let with_expiry = parsed
+            .added()
+            .iter()
+            .find(|c| c.definition() == definition("192.168.1.0/24 => 64496"))
+            .unwrap();
+        assert_eq!(
+            Some(Time::from_str("2021-01-01T00:00:00Z").unwrap()),
+            with_expiry.meta().valid_until()
+        );
+
+        let reparsed = RoaDefinitionUpdates::from_str(&parsed.to_string()).unwrap();
+        assert_eq!(parsed, reparsed);
+
+        assert!(RoaDefinitionUpdates::from_str("A: 192.168.0.0/16 => 64496 ; foo = bar").is_err());
+        assert!(
+            RoaDefinitionUpdates::from_str("A: 192.168.0.0/16 => 64496 ; valid_until = soon")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn deserialize_updates_without_meta() {
+        let json = concat!(
+            "{\"added\":[{\"asn\":64496,\"prefix\":\"192.168.0.0/16\"}],",
+            "\"removed\":[{\"asn\":64496,\"prefix\":\"192.168.3.0/24\",\"max_length\":24}]}"
+        );
+        let updates: RoaDefinitionUpdates = serde_json::from_str(json).unwrap();
+
+        let mut expected = RoaDefinitionUpdates::empty();
+        expected.add(definition("192.168.0.0/16 => 64496"));
+        expected.remove(definition("192.168.3.0/24-24 => 64496"));
+        assert_eq!(expected, updates);
+
+        let config = RoaConfiguration::from_str(
+            "192.168.0.0/16 => 64496 ; comment = test ; valid_until = 2021-01-01T00:00:00Z",
+        )
+        .unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        let de: RoaConfiguration = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, de);
+    }
+
     #[test]
     fn parse_type_prefix() {
         assert!(TypedPrefix::from_str("192.168.0.0/16").is_ok());
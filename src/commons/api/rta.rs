@@ -0,0 +1,259 @@
+use std::fmt;
+
+use bytes::Bytes;
+use chrono::Duration;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use rpki::crypto::KeyIdentifier;
+use rpki::x509::{Time, Validity};
+
+use crate::commons::api::{Base64, ResourceSet};
+
+//------------ RtaName -----------------------------------------------------
+
+pub type RtaName = String;
+
+//------------ RtaList -----------------------------------------------------
+
+/// The names of the RTAs known to a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaList(Vec<RtaName>);
+
+impl RtaList {
+    pub fn new(mut names: Vec<RtaName>) -> Self {
+        names.sort();
+        RtaList(names)
+    }
+
+    pub fn names(&self) -> &[RtaName] {
+        &self.0
+    }
+}
+
+impl fmt::Display for RtaList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for name in self.0.iter() {
+            writeln!(f, "{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ RtaContentRequest -------------------------------------------
+
+/// Requests that a CA signs an RTA over some content (the document).
+///
+/// The subject keys are the keys of other signers, which were prepared
+/// for signing this RTA. They are included in the RTA so that the other
+/// signers can co-sign it. The CA adds its own keys.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaContentRequest {
+    resources: ResourceSet,
+    validity_days: u16,
+    #[serde(default)]
+    subject_keys: Vec<KeyIdentifier>,
+    content: Base64,
+}
+
+impl RtaContentRequest {
+    pub fn new(
+        resources: ResourceSet,
+        validity_days: u16,
+        subject_keys: Vec<KeyIdentifier>,
+        content: &[u8],
+    ) -> Self {
+        RtaContentRequest {
+            resources,
+            validity_days,
+            subject_keys,
+            content: Base64::from_content(content),
+        }
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    pub fn validity(&self) -> Validity {
+        rta_validity(self.validity_days)
+    }
+
+    pub fn subject_keys(&self) -> &Vec<KeyIdentifier> {
+        &self.subject_keys
+    }
+
+    pub fn content(&self) -> Bytes {
+        self.content.to_bytes()
+    }
+}
+
+//------------ RtaPrepareRequest -------------------------------------------
+
+/// Requests that a CA prepares keys for co-signing a multi-signer RTA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaPrepareRequest {
+    resources: ResourceSet,
+    validity_days: u16,
+}
+
+impl RtaPrepareRequest {
+    pub fn new(resources: ResourceSet, validity_days: u16) -> Self {
+        RtaPrepareRequest {
+            resources,
+            validity_days,
+        }
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    pub fn validity(&self) -> Validity {
+        rta_validity(self.validity_days)
+    }
+}
+
+fn rta_validity(days: u16) -> Validity {
+    let not_after = Time::now() + Duration::days(i64::from(days));
+    Validity::new(Time::five_minutes_ago(), not_after)
+}
+
+//------------ RtaPrepResponse ---------------------------------------------
+
+/// The keys which a CA prepared for co-signing a multi-signer RTA. These
+/// keys need to be included as subject keys when the RTA is signed.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaPrepResponse {
+    keys: Vec<KeyIdentifier>,
+}
+
+impl RtaPrepResponse {
+    pub fn new(keys: Vec<KeyIdentifier>) -> Self {
+        RtaPrepResponse { keys }
+    }
+
+    pub fn keys(&self) -> &Vec<KeyIdentifier> {
+        &self.keys
+    }
+}
+
+impl fmt::Display for RtaPrepResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for key in self.keys.iter() {
+            writeln!(f, "{}", key)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ ResourceTaggedAttestation -----------------------------------
+
+/// A DER encoded RTA. Serialized as a base64 encoded string.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResourceTaggedAttestation(Bytes);
+
+impl ResourceTaggedAttestation {
+    pub fn new(bytes: Bytes) -> Self {
+        ResourceTaggedAttestation(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+}
+
+impl fmt::Display for ResourceTaggedAttestation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", base64::encode(&self.0))
+    }
+}
+
+impl Serialize for ResourceTaggedAttestation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        base64::encode(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceTaggedAttestation {
+    fn deserialize<D>(deserializer: D) -> Result<ResourceTaggedAttestation, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+        let bytes = base64::decode(&string).map_err(de::Error::custom)?;
+        Ok(ResourceTaggedAttestation(Bytes::from(bytes)))
+    }
+}
+
+//------------ RtaValidationRequest ----------------------------------------
+
+/// Requests that an RTA is validated for some content (the document). The
+/// RTA has to include the certificates needed to validate it, except for
+/// trust anchor certificates, which are retrieved using the configured TALs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaValidationRequest {
+    rta: ResourceTaggedAttestation,
+    content: Base64,
+}
+
+impl RtaValidationRequest {
+    pub fn new(rta: ResourceTaggedAttestation, content: &[u8]) -> Self {
+        RtaValidationRequest {
+            rta,
+            content: Base64::from_content(content),
+        }
+    }
+
+    pub fn unpack(self) -> (ResourceTaggedAttestation, Bytes) {
+        (self.rta, self.content.to_bytes())
+    }
+}
+
+//------------ RtaValidationReport -----------------------------------------
+
+/// Describes a valid RTA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RtaValidationReport {
+    tals: Vec<String>,
+    resources: ResourceSet,
+    signers: Vec<KeyIdentifier>,
+}
+
+impl RtaValidationReport {
+    pub fn new(tals: Vec<String>, resources: ResourceSet, signers: Vec<KeyIdentifier>) -> Self {
+        RtaValidationReport {
+            tals,
+            resources,
+            signers,
+        }
+    }
+
+    /// The names of the TALs under which the signers are valid.
+    pub fn tals(&self) -> &Vec<String> {
+        &self.tals
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    pub fn signers(&self) -> &Vec<KeyIdentifier> {
+        &self.signers
+    }
+}
+
+impl fmt::Display for RtaValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RTA is valid under TAL(s): {}", self.tals.join(", "))?;
+        writeln!(f, "Resources: {}", self.resources)?;
+        writeln!(f, "Signed by keys:")?;
+        for key in self.signers.iter() {
+            writeln!(f, "  {}", key)?;
+        }
+        Ok(())
+    }
+}
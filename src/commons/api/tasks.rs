@@ -0,0 +1,152 @@
+//! Status of the background tasks of the Krill server.
+
+use std::fmt;
+
+use chrono::SecondsFormat;
+
+use rpki::x509::Time;
+
+//------------ TaskList ------------------------------------------------------
+
+/// All periodic tasks, and all triggered tasks which are waiting to be
+/// processed or are running.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TaskList {
+    tasks: Vec<TaskStatus>,
+}
+
+impl TaskList {
+    pub fn new(tasks: Vec<TaskStatus>) -> Self {
+        TaskList { tasks }
+    }
+
+    pub fn tasks(&self) -> &Vec<TaskStatus> {
+        &self.tasks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+impl fmt::Display for TaskList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn time(time: Option<Time>) -> String {
+            time.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_else(|| "-".to_string())
+        }
+
+        writeln!(f, "Task, Type, State, Last Run, Next Run, Last Error")?;
+        for task in self.tasks.iter() {
+            writeln!(
+                f,
+                "{}, {}, {}, {}, {}, {}",
+                task.name,
+                task.kind,
+                task.state,
+                time(task.last_run),
+                time(task.next_run),
+                task.last_error.as_deref().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//------------ TaskStatus ----------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TaskStatus {
+    name: String,
+    kind: TaskKind,
+    state: TaskState,
+    last_run: Option<Time>,
+    next_run: Option<Time>,
+    last_error: Option<String>,
+}
+
+impl TaskStatus {
+    /// Creates the status for a task which did not run yet.
+    pub fn new(name: &str, kind: TaskKind, next_run: Option<Time>) -> Self {
+        TaskStatus {
+            name: name.to_string(),
+            kind,
+            state: TaskState::Scheduled,
+            last_run: None,
+            next_run,
+            last_error: None,
+        }
+    }
+
+    pub fn with_last_run(mut self, last_run: Option<Time>, last_error: Option<String>) -> Self {
+        self.last_run = last_run;
+        self.last_error = last_error;
+        self
+    }
+
+    pub fn with_state(mut self, state: TaskState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Marks the task as running, starting now.
+    pub fn start(&mut self) {
+        self.state = TaskState::Running;
+        self.last_run = Some(Time::now());
+        self.next_run = None;
+    }
+
+    /// Marks the task as done, with the result of this run and the time of
+    /// the next run.
+    pub fn finish(&mut self, error: Option<String>, next_run: Time) {
+        self.state = TaskState::Scheduled;
+        self.last_error = error;
+        self.next_run = Some(next_run);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> TaskKind {
+        self.kind
+    }
+
+    pub fn state(&self) -> TaskState {
+        self.state
+    }
+
+    pub fn last_run(&self) -> Option<Time> {
+        self.last_run
+    }
+
+    pub fn next_run(&self) -> Option<Time> {
+        self.next_run
+    }
+
+    pub fn last_error(&self) -> Option<&String> {
+        self.last_error.as_ref()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    /// Runs at a fixed interval, e.g. republishing.
+    #[display(fmt = "periodic")]
+    Periodic,
+
+    /// Runs once for an event, e.g. publishing after ROAs were updated.
+    #[display(fmt = "triggered")]
+    Triggered,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    #[display(fmt = "scheduled")]
+    Scheduled,
+
+    #[display(fmt = "running")]
+    Running,
+}
@@ -1,67 +1,163 @@
+use std::collections::HashMap;
 use std::env;
+use std::io;
+use std::path::PathBuf;
 use std::sync::RwLock;
 
-use chrono::Duration;
-
 use rpki::x509::Time;
 
-use crate::commons::api::{ResourceSet, RoaDefinition};
+use crate::commons::api::{
+    AsNumber, BgpStats, Handle, ResourceSet, RoaConfiguration, RoaDefinition, RoaDefinitionUpdates,
+    TypedPrefix,
+};
 use crate::commons::bgp::{
-    make_roa_tree, make_validated_announcement_tree, Announcement, AnnouncementValidity,
-    Announcements, BgpAnalysisEntry, BgpAnalysisReport, IpRange, RisDumpError, RisDumpLoader,
-    ValidatedAnnouncement,
+    make_roa_tree, make_validated_announcement_tree, Announcement, AnnouncementSourceConfig,
+    AnnouncementSourceError, AnnouncementSourceState, AnnouncementValidity, Announcements,
+    BgpAnalysisEntry, BgpAnalysisReport, BgpAnalysisState, BgpHistoryStore, BgpStatsTimeline,
+    IpRange, RoaDryRunReport, ValidatedAnnouncement,
 };
-use crate::constants::{BGP_RIS_REFRESH_MINUTES, KRILL_ENV_TEST_ANN};
+use crate::constants::{BGP_RIS_VISIBILITY_THRESHOLD, KRILL_ENV_TEST_ANN};
 
 //------------ BgpAnalyser -------------------------------------------------
 
 /// This type helps analyse ROAs vs BGP and vice versa.
 pub struct BgpAnalyser {
-    dumploader: Option<RisDumpLoader>,
+    sources: Vec<AnnouncementSourceState>,
     seen: RwLock<Announcements>,
+    visibility_threshold: u32,
+    history: Option<BgpHistoryStore>,
 }
 
 impl BgpAnalyser {
-    pub fn new(ris_enabled: bool, ris_v4_uri: &str, ris_v6_uri: &str) -> Self {
+    /// Creates an analyser which uses the announcements from all configured
+    /// sources. Announcements seen by `visibility_threshold` or fewer peers
+    /// are reported as low visibility. Fails if a source cannot be set up,
+    /// e.g. because a BMP listener cannot bind to its address.
+    ///
+    /// Announcements and their history are kept under the work directory,
+    /// and the announcements saved there are used until the sources are
+    /// loaded again.
+    pub fn new(
+        work_dir: &PathBuf,
+        sources: &[AnnouncementSourceConfig],
+        visibility_threshold: u32,
+    ) -> Result<Self, BgpAnalyserError> {
         if env::var(KRILL_ENV_TEST_ANN).is_ok() {
-            Self::with_test_announcements()
+            Ok(Self::with_test_announcements())
         } else {
-            let dumploader = if ris_enabled {
-                Some(RisDumpLoader::new(ris_v4_uri, ris_v6_uri))
-            } else {
-                None
+            let sources = sources
+                .iter()
+                .map(AnnouncementSourceState::build)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let history = BgpHistoryStore::new(work_dir);
+            let seen = match history.load_announcements() {
+                Ok(Some(snapshot)) => Announcements::from(snapshot),
+                Ok(None) => Announcements::default(),
+                Err(e) => {
+                    warn!(
+                        "Could not load saved BGP announcements, will start without: {}",
+                        e
+                    );
+                    Announcements::default()
+                }
             };
-            BgpAnalyser {
-                dumploader,
-                seen: RwLock::new(Announcements::default()),
-            }
+
+            Ok(BgpAnalyser {
+                sources,
+                seen: RwLock::new(seen),
+                visibility_threshold,
+                history: Some(history),
+            })
         }
     }
 
+    /// Loads announcements from all sources for which the refresh interval
+    /// has passed, and merges the announcements of all sources if any of
+    /// them changed. Returns an error for the first source that could not be
+    /// loaded, after trying all other sources.
     pub async fn update(&self) -> Result<bool, BgpAnalyserError> {
-        if let Some(loader) = &self.dumploader {
-            let mut seen = self.seen.write().unwrap();
-            if let Some(last_time) = seen.last_checked() {
-                if (last_time + Duration::minutes(BGP_RIS_REFRESH_MINUTES)) > Time::now() {
-                    trace!("Will not check BGP Ris Dumps until the refresh interval has passed");
-                    return Ok(false); // no need to update yet
+        let mut checked = false;
+        let mut changed = false;
+        let mut first_error = None;
+
+        for source in self.sources.iter().filter(|s| s.due()) {
+            match source.source().load().await {
+                Ok(announcements) => {
+                    checked = true;
+                    if source.update(announcements) {
+                        info!("Updated announcements based on {}", source.name());
+                        changed = true;
+                    } else {
+                        debug!("Announcements unchanged for {}", source.name());
+                    }
+                }
+                Err(e) => {
+                    source.update_checked();
+                    if first_error.is_none() {
+                        first_error = Some(BgpAnalyserError::Source(source.name().to_string(), e));
+                    }
                 }
             }
-            let announcements = loader.download_updates().await?;
-            if seen.equivalent(&announcements) {
-                debug!("BGP Ris Dumps unchanged");
-                seen.update_checked();
-                Ok(false)
-            } else {
-                info!(
-                    "Updated announcements ({}) based on BGP Ris Dumps",
-                    announcements.len()
-                );
-                seen.update(announcements);
-                Ok(true)
+        }
+
+        if changed {
+            // If multiple sources see the same route, keep the one with the
+            // best visibility. Routes from sources which do not report peers,
+            // e.g. our own routers, are always considered visible.
+            let visibility = |a: &Announcement| a.peers().unwrap_or(u32::MAX);
+            let mut best: HashMap<(TypedPrefix, AsNumber), Announcement> = HashMap::new();
+            for announcement in self.sources.iter().flat_map(|s| s.announcements()) {
+                let route = (*announcement.prefix(), *announcement.asn());
+                let current = best.entry(route).or_insert(announcement);
+                if visibility(&announcement) > visibility(current) {
+                    *current = announcement;
+                }
             }
-        } else {
-            Ok(false)
+            let merged: Vec<Announcement> = best.values().copied().collect();
+
+            info!("Updated announcements ({}) for all sources", merged.len());
+            let mut seen = self.seen.write().unwrap();
+            seen.update(merged);
+            if let Some(history) = self.history.as_ref() {
+                history
+                    .save_announcements(&seen.snapshot())
+                    .map_err(BgpAnalyserError::History)?;
+            }
+        } else if checked {
+            self.seen.write().unwrap().update_checked();
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(changed),
+        }
+    }
+
+    /// Adds the current stats for the ROAs of the CA to its timeline, if
+    /// they changed. Nothing is recorded if there is no announcement info.
+    pub fn record_stats(
+        &self,
+        ca: &Handle,
+        roas: &[RoaDefinition],
+        scope: &ResourceSet,
+    ) -> Result<(), BgpAnalyserError> {
+        if let Some(history) = self.history.as_ref() {
+            if self.seen.read().unwrap().last_checked().is_some() {
+                let stats = BgpStats::from(self.analyse(roas, scope));
+                history
+                    .record_stats(ca, stats, Time::now())
+                    .map_err(BgpAnalyserError::History)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how the stats for the CA changed over time.
+    pub fn timeline(&self, ca: &Handle) -> Result<BgpStatsTimeline, BgpAnalyserError> {
+        match self.history.as_ref() {
+            Some(history) => history.timeline(ca).map_err(BgpAnalyserError::History),
+            None => Ok(BgpStatsTimeline::default()),
         }
     }
 
@@ -99,7 +195,15 @@ impl BgpAnalyser {
             for roa in roas {
                 let covered = validated_tree.matching_or_more_specific(&roa.prefix());
                 if covered.is_empty() {
-                    entries.push(BgpAnalysisEntry::roa_unseen(*roa))
+                    // Unseen since the last covered announcement was
+                    // withdrawn, or at least since our history starts.
+                    let since = seen
+                        .withdrawn_contained_by(&roa.prefix())
+                        .iter()
+                        .map(|w| w.withdrawn())
+                        .max()
+                        .or_else(|| seen.history_start());
+                    entries.push(BgpAnalysisEntry::roa_unseen(*roa).with_since(since))
                 } else {
                     let allows: Vec<Announcement> = covered
                         .iter()
@@ -126,41 +230,178 @@ impl BgpAnalyser {
                         .map(|va| va.announcement())
                         .collect();
 
-                    entries.push(BgpAnalysisEntry::roa_seen(*roa, allows, disallows))
+                    let since = covered
+                        .iter()
+                        .filter_map(|va| seen.first_seen(&va.announcement()))
+                        .min();
+
+                    entries
+                        .push(BgpAnalysisEntry::roa_seen(*roa, allows, disallows).with_since(since))
                 }
             }
 
             // Loop over all validated announcements and report
             for v in validated.into_iter() {
                 let (announcement, validity, allowed_by, invalidating_roas) = v.unpack();
-                match validity {
-                    AnnouncementValidity::Valid => {
-                        entries.push(BgpAnalysisEntry::announcement_valid(
-                            announcement,
-                            allowed_by.unwrap(), // always set for valid announcements
-                        ))
-                    }
+                let since = seen.first_seen(&announcement);
+
+                let low_visibility = announcement.is_low_visibility(self.visibility_threshold);
+
+                let entry = match validity {
+                    AnnouncementValidity::Valid => BgpAnalysisEntry::announcement_valid(
+                        announcement,
+                        allowed_by.unwrap(), // always set for valid announcements
+                    ),
                     AnnouncementValidity::InvalidLength => {
-                        entries.push(BgpAnalysisEntry::announcement_invalid_length(
+                        BgpAnalysisEntry::announcement_invalid_length(
                             announcement,
                             invalidating_roas,
-                        ));
+                        )
                     }
                     AnnouncementValidity::InvalidAsn => {
-                        entries.push(BgpAnalysisEntry::announcement_invalid_asn(
-                            announcement,
-                            invalidating_roas,
-                        ));
+                        BgpAnalysisEntry::announcement_invalid_asn(announcement, invalidating_roas)
                     }
                     AnnouncementValidity::NotFound => {
-                        entries.push(BgpAnalysisEntry::announcement_not_found(announcement));
+                        BgpAnalysisEntry::announcement_not_found(announcement)
                     }
-                }
+                };
+
+                entries.push(entry.with_since(since).with_low_visibility(low_visibility));
             }
         }
         BgpAnalysisReport::new(entries)
     }
 
+    /// Analyses both the current and the proposed ROA definitions, and
+    /// reports which announcements would change state.
+    pub fn dry_run(
+        &self,
+        current: &[RoaDefinition],
+        proposed: &[RoaDefinition],
+        scope: &ResourceSet,
+    ) -> RoaDryRunReport {
+        let before = self.analyse(current, scope);
+        let after = self.analyse(proposed, scope);
+        RoaDryRunReport::new(proposed.to_vec(), &before, &after)
+    }
+
+    /// Suggests updates to the given ROAs, based on the announcements seen
+    /// for the resources in scope. The suggestions:
+    ///  - add ROAs for announcements which are 'not found', or invalid;
+    ///  - remove ROAs for which no announcements are seen;
+    ///  - narrow the max length of ROAs to what is actually announced;
+    ///  - remove ROAs which are included in other (remaining) ROAs.
+    ///
+    /// Added ROAs match the announcement exactly, i.e. they do not use a max
+    /// length. Note that invalid announcements may be hijacks, so these
+    /// suggestions should be reviewed before they are applied. Announcements
+    /// with low visibility are not used to suggest new ROAs, but they do keep
+    /// the ROAs which authorize them. Nothing is suggested if there is no
+    /// announcement information.
+    pub fn suggest(&self, roas: &[RoaConfiguration], scope: &ResourceSet) -> RoaDefinitionUpdates {
+        let definitions: Vec<RoaDefinition> = roas.iter().map(|r| r.definition()).collect();
+        let report = self.analyse(&definitions, scope);
+
+        let mut updates = RoaDefinitionUpdates::empty();
+        if !report
+            .matching_entries(BgpAnalysisState::RoaNoAnnouncementInfo)
+            .is_empty()
+        {
+            return updates;
+        }
+
+        let roa_entries: HashMap<RoaDefinition, &BgpAnalysisEntry> = report
+            .entries()
+            .iter()
+            .filter(|e| !e.state().is_announcement())
+            .map(|e| (*e.definition(), e))
+            .collect();
+
+        // The resulting ROAs, and whether they already exist.
+        let mut existing: Vec<RoaConfiguration> = vec![];
+        let mut proposed: Vec<RoaConfiguration> = vec![];
+
+        for roa in roas {
+            let definition = roa.definition();
+            let entry = match roa_entries.get(&definition) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            if entry.state() == BgpAnalysisState::RoaUnseen {
+                updates.remove(definition);
+                continue;
+            }
+
+            let announced_max = entry
+                .authorizes()
+                .iter()
+                .map(|a| a.prefix().addr_len())
+                .max();
+
+            match announced_max {
+                Some(max_length) if max_length < definition.effective_max_length() => {
+                    let prefix = definition.prefix();
+                    let max_length = if max_length == prefix.addr_len() {
+                        None
+                    } else {
+                        Some(max_length)
+                    };
+                    let narrowed = RoaDefinition::new(definition.asn(), prefix, max_length);
+                    updates.remove(definition);
+                    proposed.push(RoaConfiguration::new(narrowed, roa.meta().clone()));
+                }
+                _ => existing.push(roa.clone()),
+            }
+        }
+
+        for entry in report.entries() {
+            match entry.state() {
+                BgpAnalysisState::AnnouncementNotFound
+                | BgpAnalysisState::AnnouncementInvalidAsn
+                | BgpAnalysisState::AnnouncementInvalidLength => {
+                    proposed.push(RoaConfiguration::from(*entry.definition()))
+                }
+                _ => {}
+            }
+        }
+
+        // Existing ROAs take precedence over equal proposed ones.
+        let mut resulting: Vec<(RoaConfiguration, bool)> = vec![];
+        for (roa, is_existing) in existing
+            .into_iter()
+            .map(|r| (r, true))
+            .chain(proposed.into_iter().map(|r| (r, false)))
+        {
+            if !resulting
+                .iter()
+                .any(|(r, _)| r.definition() == roa.definition())
+            {
+                resulting.push((roa, is_existing));
+            }
+        }
+
+        for (roa, is_existing) in resulting.iter() {
+            let definition = roa.definition();
+            let redundant = resulting.iter().any(|(other, _)| {
+                let other = other.definition();
+                other != definition
+                    && other.includes(&definition)
+                    && (!definition.includes(&other) || other < definition)
+            });
+
+            if redundant {
+                if *is_existing {
+                    updates.remove(definition);
+                }
+            } else if !is_existing {
+                updates.add_configuration(roa.clone());
+            }
+        }
+
+        updates
+    }
+
     fn test_announcements() -> Vec<Announcement> {
         use crate::test::announcement;
 
@@ -184,8 +425,10 @@ impl BgpAnalyser {
         let mut announcements = Announcements::default();
         announcements.update(Self::test_announcements());
         BgpAnalyser {
-            dumploader: None,
+            sources: vec![],
             seen: RwLock::new(announcements),
+            visibility_threshold: BGP_RIS_VISIBILITY_THRESHOLD,
+            history: None,
         }
     }
 }
@@ -194,13 +437,19 @@ impl BgpAnalyser {
 
 #[derive(Debug, Display)]
 pub enum BgpAnalyserError {
-    #[display(fmt = "BGP RIS update error: {}", _0)]
-    RisDump(RisDumpError),
+    #[display(fmt = "Error loading announcements from {}: {}", _0, _1)]
+    Source(String, AnnouncementSourceError),
+
+    #[display(fmt = "Cannot set up announcement source: {}", _0)]
+    Setup(AnnouncementSourceError),
+
+    #[display(fmt = "Cannot access BGP history: {}", _0)]
+    History(io::Error),
 }
 
-impl From<RisDumpError> for BgpAnalyserError {
-    fn from(e: RisDumpError) -> Self {
-        BgpAnalyserError::RisDump(e)
+impl From<AnnouncementSourceError> for BgpAnalyserError {
+    fn from(e: AnnouncementSourceError) -> Self {
+        BgpAnalyserError::Setup(e)
     }
 }
 
@@ -209,7 +458,10 @@ impl From<RisDumpError> for BgpAnalyserError {
 #[cfg(test)]
 mod tests {
 
-    use crate::commons::bgp::BgpAnalysisState;
+    use std::str::FromStr;
+
+    use crate::commons::api::BgpStats;
+    use crate::commons::bgp::AnnouncementSourceType;
     use crate::test::*;
 
     use super::*;
@@ -220,7 +472,16 @@ mod tests {
         let bgp_risdump_v4_uri = "http://www.ris.ripe.net/dumps/riswhoisdump.IPv4.gz";
         let bgp_risdump_v6_uri = "http://www.ris.ripe.net/dumps/riswhoisdump.IPv6.gz";
 
-        let analyser = BgpAnalyser::new(true, bgp_risdump_v4_uri, bgp_risdump_v6_uri);
+        let analyser = BgpAnalyser::new(
+            &PathBuf::from("work"),
+            &[AnnouncementSourceConfig::ris(
+                bgp_risdump_v4_uri,
+                bgp_risdump_v6_uri,
+                60,
+            )],
+            BGP_RIS_VISIBILITY_THRESHOLD,
+        )
+        .unwrap();
 
         assert!(analyser.seen.read().unwrap().is_empty());
         assert!(analyser.seen.read().unwrap().last_checked().is_none());
@@ -241,6 +502,24 @@ mod tests {
 
         let report = analyser.analyse(&[roa_authorizing, roa_stale, roa_disallowing], &resources);
 
+        // all test announcements were first seen at the same time
+        let since = analyser
+            .seen
+            .read()
+            .unwrap()
+            .first_seen(&announcement("10.0.0.0/22 => 64496"));
+        assert!(since.is_some());
+        assert!(report.entries().iter().all(|e| e.since() == since));
+
+        let report = BgpAnalysisReport::new(
+            report
+                .entries()
+                .iter()
+                .cloned()
+                .map(|e| e.with_since(None))
+                .collect(),
+        );
+
         let expected: BgpAnalysisReport = serde_json::from_str(include_str!(
             "../../../test-resources/bgp/expected_full_report.json"
         ))
@@ -250,24 +529,273 @@ mod tests {
     }
 
     #[test]
-    fn analyse_bgp_no_announcements() {
-        let roa1 = definition("10.0.0.0/23-24 => 64496");
-        let roa2 = definition("10.0.3.0/24 => 64497");
-        let roa3 = definition("10.0.4.0/24 => 0");
+    fn dry_run_reports_changed_announcements() {
+        let current = definition("10.0.0.0/22-23 => 64496");
+        let mistyped = definition("10.0.0.0/22 => 64496");
 
         let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
 
-        let analyser = BgpAnalyser::new(false, "", "");
-        let table = analyser.analyse(&[roa1, roa2, roa3], &resources);
-        let table_entries = table.entries();
-        assert_eq!(3, table_entries.len());
+        let analyser = BgpAnalyser::with_test_announcements();
+
+        let report = analyser.dry_run(&[current], &[mistyped], &resources);
+        assert_eq!(report.roas(), &vec![mistyped]);
 
-        let roas_no_info: Vec<&RoaDefinition> = table_entries
+        let changes: Vec<(RoaDefinition, BgpAnalysisState, BgpAnalysisState)> = report
+            .changes()
             .iter()
-            .filter(|e| e.state() == BgpAnalysisState::RoaNoAnnouncementInfo)
-            .map(|e| e.definition())
+            .map(|c| (*c.announcement(), c.before(), c.after()))
             .collect();
 
-        assert_eq!(roas_no_info.as_slice(), &[&roa1, &roa2, &roa3]);
+        assert_eq!(
+            changes,
+            vec![(
+                definition("10.0.2.0/23 => 64496"),
+                BgpAnalysisState::AnnouncementValid,
+                BgpAnalysisState::AnnouncementInvalidLength
+            )]
+        );
+
+        let unchanged = analyser.dry_run(&[current], &[current], &resources);
+        assert!(unchanged.changes().is_empty());
+    }
+
+    #[test]
+    fn analyse_bgp_low_visibility() {
+        let roa = definition("10.0.0.0/22-24 => 64496");
+        let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+        let visible = announcement("10.0.0.0/24 => 64496")
+            .with_source(AnnouncementSourceType::Ris, Some(200));
+        let beacon =
+            announcement("10.0.1.0/24 => 64496").with_source(AnnouncementSourceType::Ris, Some(3));
+        let hijack =
+            announcement("10.0.2.0/24 => 64497").with_source(AnnouncementSourceType::Ris, Some(1));
+
+        let analyser = BgpAnalyser::with_test_announcements();
+        analyser
+            .seen
+            .write()
+            .unwrap()
+            .update(vec![visible, beacon, hijack]);
+
+        let report = analyser.analyse(&[roa], &resources);
+
+        // low visibility announcements keep their validity state
+        let valid = report.matching_entries(BgpAnalysisState::AnnouncementValid);
+        assert_eq!(valid.len(), 2);
+        assert_eq!(valid[0].definition(), &RoaDefinition::from(visible));
+        assert!(!valid[0].is_low_visibility());
+        assert_eq!(valid[1].definition(), &RoaDefinition::from(beacon));
+        assert!(valid[1].is_low_visibility());
+
+        let invalid_asn = report.matching_entries(BgpAnalysisState::AnnouncementInvalidAsn);
+        assert_eq!(invalid_asn.len(), 1);
+        assert_eq!(invalid_asn[0].definition(), &RoaDefinition::from(hijack));
+        assert_eq!(invalid_asn[0].disallowed_by(), &vec![roa]);
+        assert!(invalid_asn[0].is_low_visibility());
+
+        let stats = BgpStats::from(report.clone());
+        assert_eq!(stats.announcements_valid, 2);
+        assert_eq!(stats.announcements_invalid_asn, 1);
+        assert_eq!(stats.announcements_low_visibility, 2);
+
+        // the ROA is seen, even if it only authorizes the beacon
+        let roa_seen = report.matching_entries(BgpAnalysisState::RoaSeen);
+        assert_eq!(roa_seen[0].authorizes(), &vec![visible, beacon]);
+    }
+
+    #[test]
+    fn suggest_roa_updates() {
+        let too_permissive = configuration("10.0.0.0/22-28 => 64496 ; comment = our /22");
+        let redundant = configuration("10.0.0.0/23-24 => 64496");
+        let stale = configuration("10.0.3.0/24 => 64497");
+        let too_strict = configuration("10.0.0.0/21 => 64497");
+
+        let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+        let analyser = BgpAnalyser::with_test_announcements();
+        let updates = analyser.suggest(
+            &[
+                too_permissive.clone(),
+                redundant.clone(),
+                stale.clone(),
+                too_strict,
+            ],
+            &resources,
+        );
+
+        let mut expected = RoaDefinitionUpdates::empty();
+        expected.add_configuration(configuration("10.0.0.0/22-24 => 64496 ; comment = our /22"));
+        expected.add(definition("10.0.0.0/22 => 64497"));
+        expected.remove(too_permissive.definition());
+        expected.remove(redundant.definition());
+        expected.remove(stale.definition());
+
+        assert_eq!(updates, expected);
+
+        // Applying the suggestions leaves nothing more to suggest
+        let suggested = vec![
+            configuration("10.0.0.0/22-24 => 64496 ; comment = our /22"),
+            configuration("10.0.0.0/21 => 64497"),
+            configuration("10.0.0.0/22 => 64497"),
+        ];
+        assert_eq!(
+            analyser.suggest(&suggested, &resources),
+            RoaDefinitionUpdates::empty()
+        );
+    }
+
+    #[test]
+    fn suggest_nothing_without_announcements() {
+        test_under_tmp(|d| {
+            let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+            let analyser = BgpAnalyser::new(&d, &[], BGP_RIS_VISIBILITY_THRESHOLD).unwrap();
+            let updates = analyser.suggest(&[configuration("10.0.3.0/24 => 64497")], &resources);
+            assert_eq!(updates, RoaDefinitionUpdates::empty());
+        })
+    }
+
+    #[test]
+    fn analyse_bgp_since() {
+        let roa_seen = definition("10.0.0.0/22-24 => 64496");
+        let roa_withdrawn = definition("10.0.4.0/24 => 64496");
+        let roa_never_seen = definition("10.0.5.0/24 => 64496");
+        let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+        let old = announcement("10.0.0.0/22 => 64496");
+        let withdrawn = announcement("10.0.4.0/24 => 64496");
+        let new = announcement("10.0.1.0/24 => 64496");
+
+        let analyser = BgpAnalyser::with_test_announcements();
+        let seen = |a: &Announcement| analyser.seen.read().unwrap().first_seen(a).unwrap();
+
+        analyser.seen.write().unwrap().update(vec![old, withdrawn]);
+        let start = analyser.seen.read().unwrap().history_start().unwrap();
+        let old_since = seen(&old);
+
+        analyser.seen.write().unwrap().update(vec![old, new]);
+        let new_since = seen(&new);
+        assert_eq!(seen(&old), old_since);
+
+        let report = analyser.analyse(&[roa_seen, roa_withdrawn, roa_never_seen], &resources);
+        let since = |state, def: RoaDefinition| {
+            report
+                .matching_entries(state)
+                .into_iter()
+                .find(|e| e.definition() == &def)
+                .and_then(|e| e.since())
+        };
+
+        assert_eq!(since(BgpAnalysisState::RoaSeen, roa_seen), Some(old_since));
+        assert_eq!(
+            since(BgpAnalysisState::AnnouncementValid, new.into()),
+            Some(new_since)
+        );
+
+        let withdrawal = analyser
+            .seen
+            .read()
+            .unwrap()
+            .withdrawn_contained_by(&roa_withdrawn.prefix())[0]
+            .withdrawn();
+        assert_eq!(
+            since(BgpAnalysisState::RoaUnseen, roa_withdrawn),
+            Some(withdrawal)
+        );
+        assert_eq!(
+            since(BgpAnalysisState::RoaUnseen, roa_never_seen),
+            Some(start)
+        );
+
+        // announcing it again clears the withdrawal
+        analyser
+            .seen
+            .write()
+            .unwrap()
+            .update(vec![old, new, withdrawn]);
+        assert!(analyser
+            .seen
+            .read()
+            .unwrap()
+            .withdrawn_contained_by(&roa_withdrawn.prefix())
+            .is_empty());
+    }
+
+    #[test]
+    fn record_stats_timeline() {
+        test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let roa = definition("10.0.0.0/22-24 => 64496");
+            let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+            let mut analyser = BgpAnalyser::new(&d, &[], BGP_RIS_VISIBILITY_THRESHOLD).unwrap();
+
+            // nothing is recorded without announcement info
+            analyser.record_stats(&ca, &[roa], &resources).unwrap();
+            assert!(analyser.timeline(&ca).unwrap().is_empty());
+
+            analyser
+                .seen
+                .write()
+                .unwrap()
+                .update(vec![announcement("10.0.0.0/24 => 64496")]);
+            analyser.record_stats(&ca, &[roa], &resources).unwrap();
+            analyser.record_stats(&ca, &[roa], &resources).unwrap();
+
+            analyser
+                .seen
+                .write()
+                .unwrap()
+                .update(vec![announcement("10.0.0.0/24 => 64497")]);
+            analyser.record_stats(&ca, &[roa], &resources).unwrap();
+
+            let timeline = analyser.timeline(&ca).unwrap();
+            let stats: Vec<(usize, usize)> = timeline
+                .points()
+                .iter()
+                .map(|p| {
+                    (
+                        p.stats().announcements_valid,
+                        p.stats().announcements_invalid_asn,
+                    )
+                })
+                .collect();
+            assert_eq!(stats, vec![(1, 0), (0, 1)]);
+
+            // saved announcements are used after a restart
+            let history = analyser.history.take().unwrap();
+            history
+                .save_announcements(&analyser.seen.read().unwrap().snapshot())
+                .unwrap();
+            let restarted = BgpAnalyser::new(&d, &[], BGP_RIS_VISIBILITY_THRESHOLD).unwrap();
+            assert_eq!(
+                restarted.seen.read().unwrap().snapshot(),
+                analyser.seen.read().unwrap().snapshot()
+            );
+        })
+    }
+
+    #[test]
+    fn analyse_bgp_no_announcements() {
+        test_under_tmp(|d| {
+            let roa1 = definition("10.0.0.0/23-24 => 64496");
+            let roa2 = definition("10.0.3.0/24 => 64497");
+            let roa3 = definition("10.0.4.0/24 => 0");
+
+            let resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+            let analyser = BgpAnalyser::new(&d, &[], BGP_RIS_VISIBILITY_THRESHOLD).unwrap();
+            let table = analyser.analyse(&[roa1, roa2, roa3], &resources);
+            let table_entries = table.entries();
+            assert_eq!(3, table_entries.len());
+
+            let roas_no_info: Vec<&RoaDefinition> = table_entries
+                .iter()
+                .filter(|e| e.state() == BgpAnalysisState::RoaNoAnnouncementInfo)
+                .map(|e| e.definition())
+                .collect();
+
+            assert_eq!(roas_no_info.as_slice(), &[&roa1, &roa2, &roa3]);
+        })
     }
 }
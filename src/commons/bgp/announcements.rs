@@ -1,13 +1,16 @@
-use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::iter::FromIterator;
 use std::str::FromStr;
 
+use chrono::Duration;
+
 use rpki::x509::Time;
 
 use crate::commons::api::{AsNumber, RoaDefinition, TypedPrefix};
 use crate::commons::bgp::{IpRange, TypedPrefixTree, TypedPrefixTreeBuilder};
+use crate::constants::{BGP_WITHDRAWN_MAX, BGP_WITHDRAWN_RETENTION_DAYS};
 
 //------------ AnnouncementTree ----------------------------------------------
 
@@ -46,11 +49,28 @@ where
 pub struct Announcement {
     asn: AsNumber,
     prefix: TypedPrefix,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peers: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<AnnouncementSourceType>,
 }
 
 impl Announcement {
     pub fn new(asn: AsNumber, prefix: TypedPrefix) -> Self {
-        Announcement { asn, prefix }
+        Announcement {
+            asn,
+            prefix,
+            peers: None,
+            source: None,
+        }
+    }
+
+    /// Sets the type of source this announcement was learned from, and the
+    /// number of peers that see it, if the source knows this.
+    pub fn with_source(mut self, source: AnnouncementSourceType, peers: Option<u32>) -> Self {
+        self.source = Some(source);
+        self.peers = peers;
+        self
     }
 
     pub fn asn(&self) -> &AsNumber {
@@ -61,6 +81,24 @@ impl Announcement {
         &self.prefix
     }
 
+    pub fn peers(&self) -> Option<u32> {
+        self.peers
+    }
+
+    pub fn source(&self) -> Option<AnnouncementSourceType> {
+        self.source
+    }
+
+    /// Returns `true` if the number of peers that see this announcement is
+    /// known, and does not exceed the threshold. Announcements from sources
+    /// that do not report peers, e.g. our own routers, are always visible.
+    pub fn is_low_visibility(&self, threshold: u32) -> bool {
+        match self.peers {
+            Some(peers) => peers <= threshold,
+            None => false,
+        }
+    }
+
     pub fn validate(&self, roas: &RoaTree) -> ValidatedAnnouncement {
         let covering = roas.matching_or_less_specific(&self.prefix);
         if covering.is_empty() {
@@ -132,11 +170,11 @@ impl fmt::Display for Announcement {
 
 impl Ord for Announcement {
     fn cmp(&self, other: &Self) -> Ordering {
-        let mut ordering = self.prefix.cmp(&other.prefix());
-        if ordering == Ordering::Equal {
-            ordering = self.asn.cmp(&other.asn);
-        }
-        ordering
+        self.prefix
+            .cmp(&other.prefix)
+            .then_with(|| self.asn.cmp(&other.asn))
+            .then_with(|| self.peers.cmp(&other.peers))
+            .then_with(|| self.source.cmp(&other.source))
     }
 }
 
@@ -154,10 +192,7 @@ impl From<Announcement> for RoaDefinition {
 
 impl From<RoaDefinition> for Announcement {
     fn from(d: RoaDefinition) -> Self {
-        Announcement {
-            asn: d.asn(),
-            prefix: d.prefix(),
-        }
+        Announcement::new(d.asn(), d.prefix())
     }
 }
 
@@ -167,27 +202,117 @@ impl AsRef<TypedPrefix> for Announcement {
     }
 }
 
+//------------ AnnouncementSourceType ----------------------------------------
+
+/// The type of source an announcement was learned from.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementSourceType {
+    Ris,
+    Mrt,
+    Bmp,
+    File,
+}
+
+impl fmt::Display for AnnouncementSourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            AnnouncementSourceType::Ris => "ris",
+            AnnouncementSourceType::Mrt => "mrt",
+            AnnouncementSourceType::Bmp => "bmp",
+            AnnouncementSourceType::File => "file",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 //------------ Announcements -------------------------------------------------
 
+/// The announcements currently seen, when they first appeared, and which
+/// announcements disappeared recently.
 pub struct Announcements {
     seen: TypedPrefixTree<Announcement>,
+    first_seen: HashMap<Route, Time>,
+    withdrawn: TypedPrefixTree<WithdrawnAnnouncement>,
+    history_start: Option<Time>,
     last_updated: Option<Time>,
     last_checked: Option<Time>,
 }
 
+/// Identifies an announcement regardless of its visibility or source.
+type Route = (TypedPrefix, AsNumber);
+
 impl Announcements {
     pub fn update(&mut self, announcements: Vec<Announcement>) {
+        let now = Time::now();
+
+        let mut first_seen = HashMap::with_capacity(announcements.len());
+        for a in announcements.iter() {
+            let route = (a.prefix, a.asn);
+            let since = self.first_seen.get(&route).cloned().unwrap_or(now);
+            first_seen.insert(route, since);
+        }
+
+        let mut withdrawn: Vec<WithdrawnAnnouncement> = self
+            .withdrawn
+            .all()
+            .into_iter()
+            .filter(|w| !first_seen.contains_key(&(w.prefix, w.asn)))
+            .cloned()
+            .collect();
+
+        for ((prefix, asn), since) in self.first_seen.iter() {
+            if !first_seen.contains_key(&(*prefix, *asn)) {
+                withdrawn.push(WithdrawnAnnouncement {
+                    asn: *asn,
+                    prefix: *prefix,
+                    first_seen: *since,
+                    withdrawn: now,
+                });
+            }
+        }
+
+        self.history_start.get_or_insert(now);
+        self.set_withdrawn(withdrawn, now, BGP_WITHDRAWN_MAX);
+
         let mut builder = TypedPrefixTreeBuilder::default();
         for a in announcements {
             builder.add(a);
         }
         let tree = builder.build();
         self.seen = tree;
-        let now = Time::now();
+        self.first_seen = first_seen;
         self.last_updated = Some(now);
         self.last_checked = Some(now);
     }
 
+    /// Keeps the withdrawals from the retention period, but no more than
+    /// `max` of the most recent ones. Withdrawals seen at the same time are
+    /// kept or dropped together. We can only be sure that nothing else was
+    /// withdrawn since the last withdrawal that was dropped, so the history
+    /// start is moved accordingly.
+    fn set_withdrawn(&mut self, mut withdrawn: Vec<WithdrawnAnnouncement>, now: Time, max: usize) {
+        let mut retain_from = now - Duration::days(BGP_WITHDRAWN_RETENTION_DAYS);
+
+        withdrawn.sort_by_key(|w| Reverse(w.withdrawn));
+        withdrawn.retain(|w| w.withdrawn > retain_from);
+        if withdrawn.len() > max {
+            retain_from = withdrawn[max].withdrawn;
+            withdrawn.retain(|w| w.withdrawn > retain_from);
+        }
+
+        self.history_start = match self.history_start {
+            Some(start) if start > retain_from => Some(start),
+            _ => Some(retain_from),
+        };
+
+        let mut builder = TypedPrefixTreeBuilder::default();
+        for w in withdrawn {
+            builder.add(w);
+        }
+        self.withdrawn = builder.build();
+    }
+
     pub fn update_checked(&mut self) {
         self.last_checked = Some(Time::now())
     }
@@ -221,18 +346,146 @@ impl Announcements {
     pub fn last_updated(&self) -> Option<Time> {
         self.last_updated
     }
+
+    /// Returns the time that the announcement was first seen, since it was
+    /// last (re-)announced.
+    pub fn first_seen(&self, announcement: &Announcement) -> Option<Time> {
+        self.first_seen
+            .get(&(announcement.prefix, announcement.asn))
+            .cloned()
+    }
+
+    /// Returns the recently withdrawn announcements for (more specifics of)
+    /// the prefix.
+    pub fn withdrawn_contained_by(&self, prefix: &TypedPrefix) -> Vec<&WithdrawnAnnouncement> {
+        self.withdrawn.matching_or_more_specific(prefix)
+    }
+
+    /// Returns the time since which the withdrawal of announcements is
+    /// known. I.e. if nothing was withdrawn for a prefix, then it was not
+    /// announced since this time.
+    pub fn history_start(&self) -> Option<Time> {
+        self.history_start
+    }
+
+    pub fn snapshot(&self) -> AnnouncementsSnapshot {
+        let seen = self
+            .seen
+            .all()
+            .into_iter()
+            .map(|a| SeenAnnouncement {
+                announcement: *a,
+                first_seen: self.first_seen(a),
+            })
+            .collect();
+
+        AnnouncementsSnapshot {
+            seen,
+            withdrawn: self.withdrawn.all().into_iter().cloned().collect(),
+            history_start: self.history_start,
+            last_updated: self.last_updated,
+            last_checked: self.last_checked,
+        }
+    }
 }
 
 impl Default for Announcements {
     fn default() -> Self {
         Announcements {
             seen: TypedPrefixTreeBuilder::default().build(),
+            first_seen: HashMap::new(),
+            withdrawn: TypedPrefixTreeBuilder::default().build(),
+            history_start: None,
             last_updated: None,
             last_checked: None,
         }
     }
 }
 
+impl From<AnnouncementsSnapshot> for Announcements {
+    fn from(snapshot: AnnouncementsSnapshot) -> Self {
+        let mut builder = TypedPrefixTreeBuilder::default();
+        let mut first_seen = HashMap::new();
+        for seen in snapshot.seen {
+            let announcement = seen.announcement;
+            if let Some(since) = seen.first_seen {
+                first_seen.insert((announcement.prefix, announcement.asn), since);
+            }
+            builder.add(announcement);
+        }
+
+        let mut withdrawn = TypedPrefixTreeBuilder::default();
+        for w in snapshot.withdrawn {
+            withdrawn.add(w);
+        }
+
+        Announcements {
+            seen: builder.build(),
+            first_seen,
+            withdrawn: withdrawn.build(),
+            history_start: snapshot.history_start,
+            last_updated: snapshot.last_updated,
+            last_checked: snapshot.last_checked,
+        }
+    }
+}
+
+//------------ AnnouncementsSnapshot -----------------------------------------
+
+/// The persisted form of `Announcements`, so that they and their history
+/// survive a restart.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AnnouncementsSnapshot {
+    seen: Vec<SeenAnnouncement>,
+    withdrawn: Vec<WithdrawnAnnouncement>,
+    history_start: Option<Time>,
+    last_updated: Option<Time>,
+    last_checked: Option<Time>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct SeenAnnouncement {
+    #[serde(flatten)]
+    announcement: Announcement,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_seen: Option<Time>,
+}
+
+//------------ WithdrawnAnnouncement -----------------------------------------
+
+/// An announcement which is no longer seen.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct WithdrawnAnnouncement {
+    asn: AsNumber,
+    prefix: TypedPrefix,
+    first_seen: Time,
+    withdrawn: Time,
+}
+
+impl WithdrawnAnnouncement {
+    pub fn asn(&self) -> AsNumber {
+        self.asn
+    }
+
+    pub fn prefix(&self) -> TypedPrefix {
+        self.prefix
+    }
+
+    pub fn first_seen(&self) -> Time {
+        self.first_seen
+    }
+
+    pub fn withdrawn(&self) -> Time {
+        self.withdrawn
+    }
+}
+
+impl AsRef<TypedPrefix> for WithdrawnAnnouncement {
+    fn as_ref(&self) -> &TypedPrefix {
+        &self.prefix
+    }
+}
+
 //------------ ValidatedAnnouncement -----------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -292,6 +545,62 @@ mod tests {
     use super::*;
     use crate::test::*;
 
+    #[test]
+    fn index_and_limit_withdrawn() {
+        let prefix = |s: &str| TypedPrefix::from_str(s).unwrap();
+
+        let mut announcements = Announcements::default();
+        announcements.update(vec![
+            announcement("10.0.0.0/24 => 64496"),
+            announcement("10.0.1.0/24 => 64496"),
+            announcement("10.1.0.0/24 => 64497"),
+        ]);
+        announcements.update(vec![announcement("10.0.0.0/24 => 64496")]);
+
+        let withdrawn = announcements.withdrawn_contained_by(&prefix("10.0.0.0/16"));
+        assert_eq!(withdrawn.len(), 1);
+        assert_eq!(withdrawn[0].prefix(), prefix("10.0.1.0/24"));
+        assert_eq!(
+            announcements
+                .withdrawn_contained_by(&prefix("10.0.0.0/8"))
+                .len(),
+            2
+        );
+
+        // re-announced routes are no longer withdrawn
+        announcements.update(vec![
+            announcement("10.0.0.0/24 => 64496"),
+            announcement("10.0.1.0/24 => 64496"),
+        ]);
+        assert!(announcements
+            .withdrawn_contained_by(&prefix("10.0.0.0/16"))
+            .is_empty());
+
+        // keep only the most recent withdrawals, and move the history start
+        let mut announcements = Announcements::default();
+        let now = Time::now();
+        let withdrawal = |s: &str, days: i64| WithdrawnAnnouncement {
+            asn: AsNumber::new(64496),
+            prefix: prefix(s),
+            first_seen: now - Duration::days(40),
+            withdrawn: now - Duration::days(days),
+        };
+        let withdrawn = vec![
+            withdrawal("10.2.0.0/24", 40),
+            withdrawal("10.3.0.0/24", 3),
+            withdrawal("10.4.0.0/24", 2),
+            withdrawal("10.5.0.0/24", 1),
+        ];
+        announcements.set_withdrawn(withdrawn, now, 2);
+
+        let withdrawn = announcements.withdrawn_contained_by(&prefix("10.0.0.0/8"));
+        assert_eq!(withdrawn.len(), 2);
+        assert!(withdrawn
+            .iter()
+            .all(|w| w.prefix() != prefix("10.3.0.0/24")));
+        assert_eq!(announcements.history_start(), Some(now - Duration::days(3)));
+    }
+
     #[test]
     fn find_contained() {
         let ann_v4 = Announcement::from_str("1.0.0.0/24 => 13335").unwrap();
@@ -0,0 +1,400 @@
+//! Support learning announcements from routers through the BGP Monitoring
+//! Protocol (BMP), as defined in RFC 7854.
+//!
+//! Routers are configured to connect to the listener, after which they will
+//! send the routes received from their peers (Adj-RIB-In). All routes are
+//! kept in memory for as long as the router stays connected.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use crate::commons::api::{AsNumber, TypedPrefix};
+use crate::commons::bgp::{
+    Announcement, AnnouncementSourceType, PathAttributes, PathOrigin, WireError, WireReader,
+    AFI_IPV4,
+};
+
+const BMP_VERSION: u8 = 3;
+const BMP_COMMON_HEADER_LEN: usize = 6;
+const BMP_PER_PEER_HEADER_LEN: usize = 42;
+
+/// The largest BMP message we accept: a route monitoring message carrying a
+/// BGP message of the maximum size allowed for extended messages (RFC 8654).
+/// Other messages are smaller.
+const BMP_MAX_MSG_LEN: usize = BMP_COMMON_HEADER_LEN + BMP_PER_PEER_HEADER_LEN + BGP_MAX_MSG_LEN;
+
+/// The maximum number of routers that can be connected at the same time.
+const BMP_MAX_CONNECTIONS: usize = 64;
+
+const BMP_MSG_ROUTE_MONITORING: u8 = 0;
+const BMP_MSG_PEER_DOWN: u8 = 2;
+
+const PEER_FLAG_IPV6: u8 = 0x80;
+const PEER_FLAG_LEGACY_AS_PATH: u8 = 0x20;
+
+const BGP_MARKER_LEN: usize = 16;
+const BGP_MAX_MSG_LEN: usize = 65535;
+const BGP_MSG_UPDATE: u8 = 2;
+
+//------------ BmpListener ---------------------------------------------------
+
+/// Listens for BMP connections from routers, and keeps their Adj-RIB-In.
+pub struct BmpListener {
+    rib: Arc<RwLock<AdjRibIn>>,
+}
+
+impl BmpListener {
+    /// Binds to the address and starts accepting connections in the
+    /// background.
+    pub fn start(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let rib = Arc::new(RwLock::new(AdjRibIn::default()));
+
+        let accept_rib = rib.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for (router, stream) in listener.incoming().enumerate() {
+                match stream {
+                    Ok(stream) => {
+                        if connections.load(Ordering::SeqCst) >= BMP_MAX_CONNECTIONS {
+                            warn!(
+                                "Refusing BMP connection, already serving {} routers",
+                                BMP_MAX_CONNECTIONS
+                            );
+                            continue; // dropping the stream closes it
+                        }
+
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        let connections = connections.clone();
+                        let rib = accept_rib.clone();
+                        thread::spawn(move || {
+                            Self::serve(router, stream, rib);
+                            connections.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(e) => warn!("Could not accept BMP connection: {}", e),
+                }
+            }
+        });
+
+        info!("Listening for BMP connections on {}", addr);
+        Ok(BmpListener { rib })
+    }
+
+    /// Returns all routes currently known from all connected routers.
+    pub fn announcements(&self) -> Vec<Announcement> {
+        self.rib.read().unwrap().announcements()
+    }
+
+    fn serve(router: usize, mut stream: TcpStream, rib: Arc<RwLock<AdjRibIn>>) {
+        let remote = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        info!("BMP connection from router at {}", remote);
+
+        if let Err(e) = Self::read_messages(router, &mut stream, &rib) {
+            warn!("BMP connection from {} closed: {}", remote, e);
+        } else {
+            info!("BMP connection from {} closed", remote);
+        }
+
+        rib.write().unwrap().remove_router(router);
+    }
+
+    /// Reads and processes messages until the stream ends. Returns an error,
+    /// which closes the connection, for messages exceeding the maximum size.
+    fn read_messages<R: Read>(
+        router: usize,
+        stream: &mut R,
+        rib: &RwLock<AdjRibIn>,
+    ) -> Result<(), BmpError> {
+        loop {
+            let mut header = [0u8; BMP_COMMON_HEADER_LEN];
+            match stream.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(BmpError::Io(e)),
+            }
+
+            let mut reader = WireReader::new(&header);
+            let version = reader.u8()?;
+            let len = reader.u32()? as usize;
+            let msg_type = reader.u8()?;
+
+            if version != BMP_VERSION {
+                return Err(BmpError::Wire(WireError::Invalid("BMP version")));
+            }
+            if !(BMP_COMMON_HEADER_LEN..=BMP_MAX_MSG_LEN).contains(&len) {
+                return Err(BmpError::Wire(WireError::Invalid("BMP message length")));
+            }
+
+            let mut body = vec![0u8; len - BMP_COMMON_HEADER_LEN];
+            stream.read_exact(&mut body)?;
+
+            rib.write().unwrap().process(router, msg_type, &body)?;
+        }
+    }
+}
+
+//------------ AdjRibIn ------------------------------------------------------
+
+/// Identifies a monitored peer of a router: the peer address and the route
+/// distinguisher (zero for global instance peers).
+type PeerKey = (IpAddr, u64);
+
+/// The routes received from monitored peers, per connected router.
+#[derive(Debug, Default)]
+pub struct AdjRibIn {
+    routers: HashMap<usize, HashMap<PeerKey, HashMap<TypedPrefix, AsNumber>>>,
+}
+
+impl AdjRibIn {
+    /// Processes the body of a BMP message (i.e. after the common header)
+    /// received from the given router.
+    pub fn process(&mut self, router: usize, msg_type: u8, body: &[u8]) -> Result<(), WireError> {
+        match msg_type {
+            BMP_MSG_ROUTE_MONITORING => self.route_monitoring(router, body),
+            BMP_MSG_PEER_DOWN => {
+                let (peer, _, _) = Self::per_peer_header(&mut WireReader::new(body))?;
+                if let Some(peers) = self.routers.get_mut(&router) {
+                    peers.remove(&peer);
+                }
+                Ok(())
+            }
+            _ => Ok(()), // statistics, peer up, initiation, etc. carry no routes
+        }
+    }
+
+    pub fn remove_router(&mut self, router: usize) {
+        self.routers.remove(&router);
+    }
+
+    pub fn announcements(&self) -> Vec<Announcement> {
+        let mut res: Vec<Announcement> = self
+            .routers
+            .values()
+            .flat_map(|peers| peers.values())
+            .flat_map(|routes| routes.iter())
+            .map(|(prefix, asn)| {
+                Announcement::new(*asn, *prefix).with_source(AnnouncementSourceType::Bmp, None)
+            })
+            .collect();
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    /// Returns the peer, its AS, and whether it uses two byte AS numbers in
+    /// its AS paths.
+    fn per_peer_header(reader: &mut WireReader) -> Result<(PeerKey, AsNumber, bool), WireError> {
+        reader.skip(1)?; // peer type
+        let flags = reader.u8()?;
+        let distinguisher = reader.u64()?;
+        let address = if flags & PEER_FLAG_IPV6 != 0 {
+            reader.ip_addr(true)?
+        } else {
+            reader.skip(12)?; // IPv4 addresses are in the last 4 bytes
+            reader.ip_addr(false)?
+        };
+        let asn = AsNumber::new(reader.u32()?);
+        reader.skip(4)?; // peer BGP ID
+        reader.skip(8)?; // timestamp
+
+        let legacy_as_path = flags & PEER_FLAG_LEGACY_AS_PATH != 0;
+        Ok(((address, distinguisher), asn, legacy_as_path))
+    }
+
+    fn route_monitoring(&mut self, router: usize, body: &[u8]) -> Result<(), WireError> {
+        let mut reader = WireReader::new(body);
+        let (peer, peer_asn, legacy_as_path) = Self::per_peer_header(&mut reader)?;
+
+        reader.skip(BGP_MARKER_LEN)?;
+        reader.skip(2)?; // BGP message length
+        if reader.u8()? != BGP_MSG_UPDATE {
+            return Ok(());
+        }
+
+        let withdrawn_len = reader.u16()?;
+        let withdrawn = WireReader::new(reader.take(usize::from(withdrawn_len))?);
+        let withdrawn = withdrawn.prefixes(AFI_IPV4)?;
+
+        let attributes_len = reader.u16()?;
+        let attributes = reader.take(usize::from(attributes_len))?;
+        let attributes = PathAttributes::parse(attributes, !legacy_as_path, true)?;
+
+        let announced = reader.prefixes(AFI_IPV4)?;
+
+        let routes = self
+            .routers
+            .entry(router)
+            .or_default()
+            .entry(peer)
+            .or_default();
+
+        for prefix in withdrawn.iter().chain(attributes.mp_unreach().iter()) {
+            routes.remove(prefix);
+        }
+
+        let announced = announced.iter().chain(attributes.mp_reach().iter());
+        match attributes.origin() {
+            PathOrigin::Asn(origin) => {
+                for prefix in announced {
+                    routes.insert(*prefix, origin);
+                }
+            }
+            PathOrigin::Empty => {
+                for prefix in announced {
+                    routes.insert(*prefix, peer_asn);
+                }
+            }
+            PathOrigin::Set => {
+                // not supported, but this still replaces any earlier route
+                for prefix in announced {
+                    routes.remove(prefix);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//------------ BmpError ------------------------------------------------------
+
+#[derive(Debug, Display)]
+enum BmpError {
+    #[display(fmt = "{}", _0)]
+    Io(io::Error),
+
+    #[display(fmt = "malformed BMP message: {}", _0)]
+    Wire(WireError),
+}
+
+impl From<io::Error> for BmpError {
+    fn from(e: io::Error) -> Self {
+        BmpError::Io(e)
+    }
+}
+
+impl From<WireError> for BmpError {
+    fn from(e: WireError) -> Self {
+        BmpError::Wire(e)
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcement(s: &str) -> Announcement {
+        crate::test::announcement(s).with_source(AnnouncementSourceType::Bmp, None)
+    }
+
+    fn per_peer_header() -> Vec<u8> {
+        let mut header = vec![0, 0]; // global instance peer, IPv4, 4 byte ASNs
+        header.extend_from_slice(&[0; 8]); // distinguisher
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&[192, 0, 2, 2]); // peer address
+        header.extend_from_slice(&[0, 0, 0xfb, 0xf2]); // peer AS 64498
+        header.extend_from_slice(&[192, 0, 2, 2]); // peer BGP ID
+        header.extend_from_slice(&[0; 8]); // timestamp
+        header
+    }
+
+    fn route_monitoring(withdrawn: &[u8], attributes: &[u8], nlri: &[u8]) -> Vec<u8> {
+        let mut update = vec![BGP_MSG_UPDATE];
+        update.extend_from_slice(&(withdrawn.len() as u16).to_be_bytes());
+        update.extend_from_slice(withdrawn);
+        update.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        update.extend_from_slice(attributes);
+        update.extend_from_slice(nlri);
+
+        let mut body = per_peer_header();
+        body.extend_from_slice(&[0xff; BGP_MARKER_LEN]);
+        body.extend_from_slice(&((BGP_MARKER_LEN + 2 + update.len()) as u16).to_be_bytes());
+        body.append(&mut update);
+        body
+    }
+
+    #[test]
+    fn track_adj_rib_in() {
+        let mut rib = AdjRibIn::default();
+
+        // AS_PATH 64498 64496, announcing 10.0.0.0/24 and 10.1.0.0/16
+        let as_path = [0x40, 2, 10, 2, 2, 0, 0, 0xfb, 0xf2, 0, 0, 0xfb, 0xf0];
+        let announce = route_monitoring(&[], &as_path, &[24, 10, 0, 0, 16, 10, 1]);
+        rib.process(0, BMP_MSG_ROUTE_MONITORING, &announce).unwrap();
+
+        // another router sees the same route
+        rib.process(1, BMP_MSG_ROUTE_MONITORING, &announce).unwrap();
+
+        assert_eq!(
+            rib.announcements(),
+            vec![
+                announcement("10.0.0.0/24 => 64496"),
+                announcement("10.1.0.0/16 => 64496")
+            ]
+        );
+
+        // withdraw 10.1.0.0/16 at both routers
+        let withdraw = route_monitoring(&[16, 10, 1], &[], &[]);
+        rib.process(0, BMP_MSG_ROUTE_MONITORING, &withdraw).unwrap();
+        rib.process(1, BMP_MSG_ROUTE_MONITORING, &withdraw).unwrap();
+        assert_eq!(
+            rib.announcements(),
+            vec![announcement("10.0.0.0/24 => 64496")]
+        );
+
+        // peer down at one router, router disconnect for the other
+        rib.process(0, BMP_MSG_PEER_DOWN, &per_peer_header())
+            .unwrap();
+        rib.remove_router(1);
+        assert!(rib.announcements().is_empty());
+    }
+
+    #[test]
+    fn ignore_as_set_origin() {
+        let mut rib = AdjRibIn::default();
+
+        // AS_PATH 64498 64496
+        let as_path = [0x40, 2, 10, 2, 2, 0, 0, 0xfb, 0xf2, 0, 0, 0xfb, 0xf0];
+        let announce = route_monitoring(&[], &as_path, &[24, 10, 0, 0]);
+        rib.process(0, BMP_MSG_ROUTE_MONITORING, &announce).unwrap();
+
+        // AS_PATH 64498 {64496 64497} replaces the route, but is not used
+        let as_path = [
+            0x40, 2, 16, 2, 1, 0, 0, 0xfb, 0xf2, 1, 2, 0, 0, 0xfb, 0xf0, 0, 0, 0xfb, 0xf1,
+        ];
+        let announce = route_monitoring(&[], &as_path, &[24, 10, 0, 0]);
+        rib.process(0, BMP_MSG_ROUTE_MONITORING, &announce).unwrap();
+        assert!(rib.announcements().is_empty());
+
+        // an empty AS_PATH means the route was originated by the peer
+        let announce = route_monitoring(&[], &[0x40, 2, 0], &[24, 10, 0, 0]);
+        rib.process(0, BMP_MSG_ROUTE_MONITORING, &announce).unwrap();
+        assert_eq!(
+            rib.announcements(),
+            vec![announcement("10.0.0.0/24 => 64498")]
+        );
+    }
+
+    #[test]
+    fn reject_oversized_message() {
+        let rib = RwLock::new(AdjRibIn::default());
+
+        let mut message = vec![BMP_VERSION];
+        message.extend_from_slice(&u32::MAX.to_be_bytes());
+        message.push(BMP_MSG_ROUTE_MONITORING);
+
+        let mut stream = io::Cursor::new(message);
+        assert!(BmpListener::read_messages(0, &mut stream, &rib).is_err());
+        assert!(rib.read().unwrap().announcements().is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+//! Persistence of announcements and their history, and of the BGP analysis
+//! statistics of CAs over time.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::SecondsFormat;
+
+use rpki::x509::Time;
+
+use crate::commons::api::{BgpStats, Handle};
+use crate::commons::bgp::AnnouncementsSnapshot;
+use crate::commons::util::file;
+
+const BGP_DIR: &str = "bgp";
+const ANNOUNCEMENTS_FILE: &str = "announcements.json";
+const TIMELINE_DIR: &str = "timeline";
+
+//------------ BgpHistoryStore -----------------------------------------------
+
+/// Stores announcements, so that their history survives a restart, and a
+/// timeline of the BGP analysis statistics for each CA.
+pub struct BgpHistoryStore {
+    dir: PathBuf,
+}
+
+impl BgpHistoryStore {
+    /// Uses the 'bgp' directory under the given work directory.
+    pub fn new(work_dir: &PathBuf) -> Self {
+        BgpHistoryStore {
+            dir: file::file_path(work_dir, BGP_DIR),
+        }
+    }
+
+    /// Loads the saved announcements, if any.
+    pub fn load_announcements(&self) -> io::Result<Option<AnnouncementsSnapshot>> {
+        let path = file::file_path(&self.dir, ANNOUNCEMENTS_FILE);
+        if path.exists() {
+            file::load_json(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn save_announcements(&self, snapshot: &AnnouncementsSnapshot) -> io::Result<()> {
+        file::save_json(snapshot, &file::file_path(&self.dir, ANNOUNCEMENTS_FILE))
+    }
+
+    /// Returns the timeline for the CA, which is empty if nothing was
+    /// recorded for it.
+    pub fn timeline(&self, ca: &Handle) -> io::Result<BgpStatsTimeline> {
+        let path = self.timeline_path(ca);
+        if path.exists() {
+            file::load_json(&path)
+        } else {
+            Ok(BgpStatsTimeline::default())
+        }
+    }
+
+    /// Adds the stats to the timeline of the CA, unless they are the same
+    /// as the last recorded stats. Returns whether they were added.
+    pub fn record_stats(&self, ca: &Handle, stats: BgpStats, time: Time) -> io::Result<bool> {
+        let mut timeline = self.timeline(ca)?;
+        if timeline.last().map(|point| &point.stats) == Some(&stats) {
+            Ok(false)
+        } else {
+            timeline.0.push(BgpStatsTimelinePoint { time, stats });
+            file::save_json(&timeline, &self.timeline_path(ca))?;
+            Ok(true)
+        }
+    }
+
+    fn timeline_path(&self, ca: &Handle) -> PathBuf {
+        let dir = file::file_path(&self.dir, TIMELINE_DIR);
+        file::file_path(&dir, &format!("{}.json", ca))
+    }
+}
+
+//------------ BgpStatsTimeline ----------------------------------------------
+
+/// The BGP analysis statistics of a CA, each time they changed.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpStatsTimeline(Vec<BgpStatsTimelinePoint>);
+
+impl BgpStatsTimeline {
+    pub fn points(&self) -> &Vec<BgpStatsTimelinePoint> {
+        &self.0
+    }
+
+    pub fn last(&self) -> Option<&BgpStatsTimelinePoint> {
+        self.0.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for BgpStatsTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "no BGP analysis history known");
+        }
+
+        writeln!(
+            f,
+            "Time, Valid, Invalid ASN, Invalid Length, Not Found, Low Visibility, Stale ROAs"
+        )?;
+        for point in self.0.iter() {
+            let stats = &point.stats;
+            writeln!(
+                f,
+                "{}, {}, {}, {}, {}, {}, {}",
+                point.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                stats.announcements_valid,
+                stats.announcements_invalid_asn,
+                stats.announcements_invalid_length,
+                stats.announcements_not_found,
+                stats.announcements_low_visibility,
+                stats.roas_stale
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpStatsTimelinePoint {
+    time: Time,
+    #[serde(flatten)]
+    stats: BgpStats,
+}
+
+impl BgpStatsTimelinePoint {
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    pub fn stats(&self) -> &BgpStats {
+        &self.stats
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::commons::bgp::Announcements;
+    use crate::test::*;
+
+    use super::*;
+
+    #[test]
+    fn record_timeline_and_announcements() {
+        test_under_tmp(|d| {
+            let store = BgpHistoryStore::new(&d);
+            let ca = Handle::from_str("ca").unwrap();
+            assert!(store.timeline(&ca).unwrap().is_empty());
+            assert!(store.load_announcements().unwrap().is_none());
+
+            let mut stats = BgpStats::default();
+            stats.increment_valid();
+            assert!(store.record_stats(&ca, stats.clone(), Time::now()).unwrap());
+            assert!(!store.record_stats(&ca, stats.clone(), Time::now()).unwrap());
+
+            stats.increment_invalid_asn();
+            assert!(store.record_stats(&ca, stats.clone(), Time::now()).unwrap());
+
+            let timeline = store.timeline(&ca).unwrap();
+            assert_eq!(timeline.points().len(), 2);
+            assert_eq!(timeline.last().unwrap().stats(), &stats);
+
+            let mut announcements = Announcements::default();
+            announcements.update(vec![announcement("10.0.0.0/24 => 64496")]);
+            announcements.update(vec![announcement("10.0.1.0/24 => 64496")]);
+            store.save_announcements(&announcements.snapshot()).unwrap();
+
+            let loaded = store.load_announcements().unwrap().unwrap();
+            assert_eq!(loaded, announcements.snapshot());
+            assert_eq!(
+                Announcements::from(loaded).snapshot(),
+                announcements.snapshot()
+            );
+        })
+    }
+}
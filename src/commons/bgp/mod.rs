@@ -12,3 +12,18 @@ pub use self::risdumps::*;
 
 mod report;
 pub use self::report::*;
+
+mod sources;
+pub use self::sources::*;
+
+mod wire;
+pub use self::wire::*;
+
+mod mrt;
+pub use self::mrt::*;
+
+mod bmp;
+pub use self::bmp::*;
+
+mod history;
+pub use self::history::*;
@@ -0,0 +1,202 @@
+//! Support loading announcements from MRT TABLE_DUMP_V2 RIB dumps, as
+//! defined in RFC 6396. Such dumps are produced by e.g. BIRD, GoBGP and
+//! the RIPE RIS route collectors ("bview" files).
+
+use std::collections::HashSet;
+
+use crate::commons::api::AsNumber;
+use crate::commons::bgp::{
+    load_location, Announcement, AnnouncementSourceError, AnnouncementSourceType, PathAttributes,
+    PathOrigin, WireError, WireReader, AFI_IPV4, AFI_IPV6,
+};
+
+const MRT_TYPE_TABLE_DUMP_V2: u16 = 13;
+
+const SUBTYPE_PEER_INDEX_TABLE: u16 = 1;
+const SUBTYPE_RIB_IPV4_UNICAST: u16 = 2;
+const SUBTYPE_RIB_IPV6_UNICAST: u16 = 4;
+
+const PEER_TYPE_IPV6: u8 = 0x01;
+const PEER_TYPE_AS4: u8 = 0x02;
+
+//------------ MrtLoader -----------------------------------------------------
+
+/// Loads announcements from an MRT RIB dump in a local file, or at an
+/// http(s) URI. Files ending in '.gz' are gunzipped.
+pub struct MrtLoader {
+    location: String,
+}
+
+impl MrtLoader {
+    pub fn new(location: &str) -> Self {
+        MrtLoader {
+            location: location.to_string(),
+        }
+    }
+
+    pub async fn load(&self) -> Result<Vec<Announcement>, AnnouncementSourceError> {
+        let bytes = load_location(&self.location).await?;
+        Ok(Self::parse_rib_dump(&bytes)?)
+    }
+
+    /// Parses all IPv4 and IPv6 unicast RIB entries in the dump. The origin
+    /// of each route is taken from its AS path, or is the AS of the peer if
+    /// the path is empty. Other MRT records are ignored.
+    pub fn parse_rib_dump(bytes: &[u8]) -> Result<Vec<Announcement>, WireError> {
+        let mut peer_asns: Vec<AsNumber> = vec![];
+        let mut announcements = HashSet::new();
+
+        let mut reader = WireReader::new(bytes);
+        while !reader.is_empty() {
+            reader.skip(4)?; // timestamp
+            let typ = reader.u16()?;
+            let subtype = reader.u16()?;
+            let len = reader.u32()? as usize;
+            let body = reader.take(len)?;
+
+            if typ != MRT_TYPE_TABLE_DUMP_V2 {
+                continue;
+            }
+
+            match subtype {
+                SUBTYPE_PEER_INDEX_TABLE => peer_asns = Self::parse_peer_index_table(body)?,
+                SUBTYPE_RIB_IPV4_UNICAST => {
+                    Self::parse_rib(body, AFI_IPV4, &peer_asns, &mut announcements)?
+                }
+                SUBTYPE_RIB_IPV6_UNICAST => {
+                    Self::parse_rib(body, AFI_IPV6, &peer_asns, &mut announcements)?
+                }
+                _ => {} // multicast, generic and add-path RIBs are not supported
+            }
+        }
+
+        Ok(announcements.into_iter().collect())
+    }
+
+    fn parse_peer_index_table(body: &[u8]) -> Result<Vec<AsNumber>, WireError> {
+        let mut reader = WireReader::new(body);
+        reader.skip(4)?; // collector BGP ID
+        let view_name_len = reader.u16()?;
+        reader.skip(usize::from(view_name_len))?;
+
+        let count = reader.u16()?;
+        let mut res = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let peer_type = reader.u8()?;
+            reader.skip(4)?; // peer BGP ID
+            reader.ip_addr(peer_type & PEER_TYPE_IPV6 != 0)?;
+            let asn = if peer_type & PEER_TYPE_AS4 != 0 {
+                reader.u32()?
+            } else {
+                u32::from(reader.u16()?)
+            };
+            res.push(AsNumber::new(asn));
+        }
+        Ok(res)
+    }
+
+    fn parse_rib(
+        body: &[u8],
+        afi: u16,
+        peer_asns: &[AsNumber],
+        announcements: &mut HashSet<Announcement>,
+    ) -> Result<(), WireError> {
+        let mut reader = WireReader::new(body);
+        reader.skip(4)?; // sequence number
+        let prefix = reader.prefix(afi)?;
+
+        let count = reader.u16()?;
+        for _ in 0..count {
+            let peer_index = usize::from(reader.u16()?);
+            reader.skip(4)?; // originated time
+            let attributes_len = reader.u16()?;
+            let attributes = reader.take(usize::from(attributes_len))?;
+
+            // AS numbers are always encoded as four bytes in TABLE_DUMP_V2
+            let attributes = PathAttributes::parse(attributes, true, false)?;
+            let origin = match attributes.origin() {
+                PathOrigin::Asn(origin) => origin,
+                PathOrigin::Set => continue,
+                PathOrigin::Empty => match peer_asns.get(peer_index) {
+                    Some(asn) => *asn,
+                    None => return Err(WireError::Invalid("peer index")),
+                },
+            };
+
+            let announcement =
+                Announcement::new(origin, prefix).with_source(AnnouncementSourceType::Mrt, None);
+            announcements.insert(announcement);
+        }
+
+        Ok(())
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::announcement;
+
+    fn mrt_record(subtype: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = vec![0, 0, 0, 0, 0, 13];
+        record.extend_from_slice(&subtype.to_be_bytes());
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        record.extend_from_slice(body);
+        record
+    }
+
+    #[test]
+    fn parse_table_dump_v2() {
+        let peer_index_table = [
+            192,
+            0,
+            2,
+            1, // collector BGP ID
+            0,
+            0, // no view name
+            0,
+            1,             // one peer
+            PEER_TYPE_AS4, // IPv4 address, four byte AS
+            192,
+            0,
+            2,
+            2, // peer BGP ID
+            192,
+            0,
+            2,
+            2, // peer address
+            0,
+            0,
+            0xfb,
+            0xf2, // peer AS 64498
+        ];
+
+        let rib_ipv4 = [
+            0, 0, 0, 0, // sequence
+            24, 10, 0, 0, // 10.0.0.0/24
+            0, 2, // two entries
+            // entry with AS_PATH 64498 64496
+            0, 0, 0, 0, 0, 0, 0, 13, 0x40, 2, 10, 2, 2, 0, 0, 0xfb, 0xf2, 0, 0, 0xfb, 0xf0,
+            // entry with empty AS_PATH: originated by the peer
+            0, 0, 0, 0, 0, 0, 0, 3, 0x40, 2, 0,
+        ];
+
+        let mut dump = mrt_record(SUBTYPE_PEER_INDEX_TABLE, &peer_index_table);
+        dump.append(&mut mrt_record(SUBTYPE_RIB_IPV4_UNICAST, &rib_ipv4));
+
+        let mut announcements = MrtLoader::parse_rib_dump(&dump).unwrap();
+        announcements.sort();
+
+        assert_eq!(
+            announcements,
+            vec![
+                announcement("10.0.0.0/24 => 64496").with_source(AnnouncementSourceType::Mrt, None),
+                announcement("10.0.0.0/24 => 64498").with_source(AnnouncementSourceType::Mrt, None)
+            ]
+        );
+
+        assert!(MrtLoader::parse_rib_dump(&dump[..dump.len() - 1]).is_err());
+    }
+}
@@ -2,6 +2,10 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 
+use chrono::SecondsFormat;
+
+use rpki::x509::Time;
+
 use crate::commons::api::{BgpStats, RoaDefinition};
 use crate::commons::bgp::Announcement;
 
@@ -44,6 +48,9 @@ impl From<BgpAnalysisReport> for BgpStats {
                 BgpAnalysisState::RoaUnseen => stats.increment_unseen(),
                 _ => {} // nothing to see, move along
             }
+            if e.low_visibility {
+                stats.increment_low_visibility();
+            }
         }
         stats
     }
@@ -67,7 +74,7 @@ impl fmt::Display for BgpAnalysisReport {
                 writeln!(f, "Authorizations covering announcements seen:")?;
                 for roa in authorizing {
                     writeln!(f)?;
-                    writeln!(f, "\tDefinition: {}", roa.definition)?;
+                    writeln!(f, "\tDefinition: {}{}", roa.definition, Since(roa.since))?;
                     writeln!(f)?;
                     writeln!(f, "\t\tAuthorizes:")?;
                     for ann in roa.authorizes.iter() {
@@ -92,7 +99,7 @@ impl fmt::Display for BgpAnalysisReport {
                 )?;
                 writeln!(f)?;
                 for roa in unseens {
-                    writeln!(f, "\tDefinition: {}", roa.definition)?;
+                    writeln!(f, "\tDefinition: {}{}", roa.definition, Since(roa.since))?;
                 }
                 writeln!(f)?;
             }
@@ -101,7 +108,13 @@ impl fmt::Display for BgpAnalysisReport {
                 writeln!(f, "Announcements which are valid:")?;
                 writeln!(f)?;
                 for ann in valids {
-                    writeln!(f, "\tAnnouncement: {}", ann.definition)?;
+                    writeln!(
+                        f,
+                        "\tAnnouncement: {}{}{}",
+                        ann.definition,
+                        Since(ann.since),
+                        LowVisibility(ann.low_visibility)
+                    )?;
                 }
                 writeln!(f)?;
             }
@@ -110,7 +123,13 @@ impl fmt::Display for BgpAnalysisReport {
                 writeln!(f, "Announcements from an unauthorized ASN:")?;
                 for ann in invalid_asn {
                     writeln!(f)?;
-                    writeln!(f, "\tAnnouncement: {}", ann.definition)?;
+                    writeln!(
+                        f,
+                        "\tAnnouncement: {}{}{}",
+                        ann.definition,
+                        Since(ann.since),
+                        LowVisibility(ann.low_visibility)
+                    )?;
                     writeln!(f)?;
                     writeln!(f, "\t\tDisallowed by authorization(s):")?;
                     for roa in ann.disallowed_by.iter() {
@@ -126,7 +145,13 @@ impl fmt::Display for BgpAnalysisReport {
                 writeln!(f, "Announcements from an authorized ASN, which are too specific (not allowed by max length):")?;
                 for ann in invalid_length {
                     writeln!(f)?;
-                    writeln!(f, "\tAnnouncement: {}", ann.definition)?;
+                    writeln!(
+                        f,
+                        "\tAnnouncement: {}{}{}",
+                        ann.definition,
+                        Since(ann.since),
+                        LowVisibility(ann.low_visibility)
+                    )?;
                     writeln!(f)?;
                     writeln!(f, "\t\tDisallowed by authorization(s):")?;
                     for roa in ann.disallowed_by.iter() {
@@ -140,7 +165,13 @@ impl fmt::Display for BgpAnalysisReport {
                 writeln!(f, "Announcements which are 'not found' (not covered by any of your authorizations):")?;
                 writeln!(f)?;
                 for ann in not_found {
-                    writeln!(f, "\tAnnouncement: {}", ann.definition)?;
+                    writeln!(
+                        f,
+                        "\tAnnouncement: {}{}{}",
+                        ann.definition,
+                        Since(ann.since),
+                        LowVisibility(ann.low_visibility)
+                    )?;
                 }
                 writeln!(f)?;
             }
@@ -150,6 +181,35 @@ impl fmt::Display for BgpAnalysisReport {
     }
 }
 
+/// Shows the time since when a state applies, if it is known.
+struct Since(Option<Time>);
+
+impl fmt::Display for Since {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(time) => write!(
+                f,
+                " (since {})",
+                time.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Marks announcements seen by too few peers to be considered reliable.
+struct LowVisibility(bool);
+
+impl fmt::Display for LowVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 {
+            write!(f, " (low visibility)")
+        } else {
+            Ok(())
+        }
+    }
+}
+
 //------------ BgpAnalysisEntry --------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -165,6 +225,10 @@ pub struct BgpAnalysisEntry {
     authorizes: Vec<Announcement>,
     #[serde(skip_serializing_if = "Vec::is_empty", default = "Vec::new")]
     disallows: Vec<Announcement>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    since: Option<Time>,
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    low_visibility: bool,
 }
 
 impl BgpAnalysisEntry {
@@ -192,6 +256,30 @@ impl BgpAnalysisEntry {
         &self.disallows
     }
 
+    /// The time since when this state applies, if known. For announcements
+    /// this is when they were first seen. For ROAs this is when the first
+    /// announcement they cover was seen, or when the last one was withdrawn.
+    pub fn since(&self) -> Option<Time> {
+        self.since
+    }
+
+    pub fn with_since(mut self, since: Option<Time>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Whether this announcement is seen by too few peers to be considered
+    /// reliable, e.g. a beacon or a newly announced prefix. Its state is
+    /// reported as usual.
+    pub fn is_low_visibility(&self) -> bool {
+        self.low_visibility
+    }
+
+    pub fn with_low_visibility(mut self, low_visibility: bool) -> Self {
+        self.low_visibility = low_visibility;
+        self
+    }
+
     pub fn roa_seen(
         definition: RoaDefinition,
         mut authorizes: Vec<Announcement>,
@@ -206,6 +294,8 @@ impl BgpAnalysisEntry {
             disallowed_by: vec![],
             authorizes,
             disallows,
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -217,6 +307,8 @@ impl BgpAnalysisEntry {
             disallowed_by: vec![],
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -228,6 +320,8 @@ impl BgpAnalysisEntry {
             disallowed_by: vec![],
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -239,6 +333,8 @@ impl BgpAnalysisEntry {
             disallowed_by: vec![],
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -254,6 +350,8 @@ impl BgpAnalysisEntry {
             disallowed_by,
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -269,6 +367,8 @@ impl BgpAnalysisEntry {
             disallowed_by,
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 
@@ -280,6 +380,8 @@ impl BgpAnalysisEntry {
             disallowed_by: vec![],
             authorizes: vec![],
             disallows: vec![],
+            since: None,
+            low_visibility: false,
         }
     }
 }
@@ -314,6 +416,146 @@ pub enum BgpAnalysisState {
     RoaNoAnnouncementInfo,
 }
 
+impl BgpAnalysisState {
+    /// Returns `true` if this is the state of an announcement, rather than
+    /// the state of a ROA.
+    pub fn is_announcement(self) -> bool {
+        match self {
+            BgpAnalysisState::AnnouncementValid
+            | BgpAnalysisState::AnnouncementInvalidLength
+            | BgpAnalysisState::AnnouncementInvalidAsn
+            | BgpAnalysisState::AnnouncementNotFound => true,
+            BgpAnalysisState::RoaSeen
+            | BgpAnalysisState::RoaUnseen
+            | BgpAnalysisState::RoaNoAnnouncementInfo => false,
+        }
+    }
+}
+
+impl fmt::Display for BgpAnalysisState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            BgpAnalysisState::RoaSeen => "roa seen",
+            BgpAnalysisState::RoaUnseen => "roa unseen",
+            BgpAnalysisState::AnnouncementValid => "valid",
+            BgpAnalysisState::AnnouncementInvalidLength => "invalid length",
+            BgpAnalysisState::AnnouncementInvalidAsn => "invalid asn",
+            BgpAnalysisState::AnnouncementNotFound => "not found",
+            BgpAnalysisState::RoaNoAnnouncementInfo => "no announcement info",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+//------------ RoaDryRunReport ---------------------------------------------
+
+/// Shows the ROA definitions which would result from an update, and which
+/// announcements would change their validation state because of it.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RoaDryRunReport {
+    roas: Vec<RoaDefinition>,
+    changes: Vec<AnnouncementStateChange>,
+}
+
+impl RoaDryRunReport {
+    pub fn new(
+        roas: Vec<RoaDefinition>,
+        before: &BgpAnalysisReport,
+        after: &BgpAnalysisReport,
+    ) -> Self {
+        let announcement_states =
+            |report: &BgpAnalysisReport| -> HashMap<RoaDefinition, BgpAnalysisState> {
+                report
+                    .entries()
+                    .iter()
+                    .filter(|e| e.state.is_announcement())
+                    .map(|e| (e.definition, e.state))
+                    .collect()
+            };
+
+        let before = announcement_states(before);
+        let after = announcement_states(after);
+
+        let mut changes: Vec<AnnouncementStateChange> = after
+            .iter()
+            .filter_map(|(announcement, after)| match before.get(announcement) {
+                Some(before) if before == after => None,
+                Some(before) => Some(AnnouncementStateChange {
+                    announcement: *announcement,
+                    before: *before,
+                    after: *after,
+                }),
+                None => None,
+            })
+            .collect();
+        changes.sort_by_key(|c| c.announcement);
+
+        RoaDryRunReport { roas, changes }
+    }
+
+    pub fn roas(&self) -> &Vec<RoaDefinition> {
+        &self.roas
+    }
+
+    pub fn changes(&self) -> &Vec<AnnouncementStateChange> {
+        &self.changes
+    }
+}
+
+impl fmt::Display for RoaDryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ROAs after update:")?;
+        writeln!(f)?;
+        for roa in self.roas.iter() {
+            writeln!(f, "\t{}", roa)?;
+        }
+        writeln!(f)?;
+
+        if self.changes.is_empty() {
+            writeln!(f, "No known announcements would change state.")
+        } else {
+            writeln!(f, "Announcements which would change state:")?;
+            writeln!(f)?;
+            for change in self.changes.iter() {
+                writeln!(f, "\t{}", change)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The change in validation state for an announcement.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct AnnouncementStateChange {
+    announcement: RoaDefinition,
+    before: BgpAnalysisState,
+    after: BgpAnalysisState,
+}
+
+impl AnnouncementStateChange {
+    pub fn announcement(&self) -> &RoaDefinition {
+        &self.announcement
+    }
+
+    pub fn before(&self) -> BgpAnalysisState {
+        self.before
+    }
+
+    pub fn after(&self) -> BgpAnalysisState {
+        self.after
+    }
+}
+
+impl fmt::Display for AnnouncementStateChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{} -> {}",
+            self.announcement, self.before, self.after
+        )
+    }
+}
+
 //------------ AnnouncementReport ------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
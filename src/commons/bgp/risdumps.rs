@@ -12,7 +12,7 @@ use bytes::Bytes;
 use libflate::gzip::Decoder;
 
 use crate::commons::api::{AsNumber, AuthorizationFmtError, TypedPrefix};
-use crate::commons::bgp::Announcement;
+use crate::commons::bgp::{Announcement, AnnouncementSourceType};
 
 pub struct RisDumpLoader {
     bgp_risdumps_v4_uri: String,
@@ -57,8 +57,12 @@ impl RisDumpLoader {
         Ok(gunzipped)
     }
 
+    /// Parses all announcements in the dump, including the number of RIS
+    /// peers that see them. Announcements with an AS_SET origin are skipped,
+    /// as they cannot be valid under any ROA, but their number is logged.
     fn parse_dump(bytes: &[u8]) -> Result<Vec<Announcement>, RisDumpError> {
         let mut res = vec![];
+        let mut as_sets = 0;
         for lres in bytes.lines() {
             let line = lres.map_err(RisDumpError::parse_error)?;
             if line.is_empty() || line.starts_with('%') {
@@ -71,20 +75,27 @@ impl RisDumpLoader {
             let prefix_str = values.next().ok_or(RisDumpError::MissingColumn)?;
             let peers = values.next().ok_or(RisDumpError::MissingColumn)?;
 
-            if u32::from_str(peers)? <= 5 {
-                continue;
-            }
-
             if asn_str.contains('{') {
-                continue; // assets not supported (not important here either)
+                as_sets += 1;
+                continue;
             }
 
             let asn = AsNumber::from_str(asn_str)?;
             let prefix = TypedPrefix::from_str(prefix_str)?;
+            let peers = u32::from_str(peers)?;
 
-            let ann = Announcement::new(asn, prefix);
+            let ann = Announcement::new(asn, prefix)
+                .with_source(AnnouncementSourceType::Ris, Some(peers));
             res.push(ann);
         }
+
+        if as_sets > 0 {
+            info!(
+                "Skipped {} announcements with an AS_SET origin in RIS dump",
+                as_sets
+            );
+        }
+
         Ok(res)
     }
 }
@@ -153,4 +164,27 @@ mod tests {
 
         assert!(!announcements.is_empty())
     }
+
+    #[test]
+    fn parse_dump_with_peers() {
+        let dump = concat!(
+            "% This is the RIS whois dump\n",
+            "\n",
+            "64496\t10.0.0.0/24\t250\n",
+            "64497\t10.1.0.0/24\t2\n",
+            "{64496,64497}\t10.2.0.0/24\t100\n",
+        );
+
+        let announcements = RisDumpLoader::parse_dump(dump.as_bytes()).unwrap();
+        assert_eq!(announcements.len(), 2);
+
+        let beacon = announcements[1];
+        assert_eq!(beacon.to_string(), "10.1.0.0/24 => 64497");
+        assert_eq!(beacon.peers(), Some(2));
+        assert_eq!(beacon.source(), Some(AnnouncementSourceType::Ris));
+        assert!(beacon.is_low_visibility(5));
+        assert!(!announcements[0].is_low_visibility(5));
+
+        assert!(RisDumpLoader::parse_dump(b"64496\t10.0.0.0/24\n").is_err());
+    }
 }
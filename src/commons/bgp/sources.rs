@@ -0,0 +1,387 @@
+//! Sources of BGP announcements for the ROA vs BGP analysis.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use chrono::Duration;
+use libflate::gzip::Decoder;
+
+use rpki::x509::Time;
+
+use crate::commons::api::{AsNumber, TypedPrefix};
+use crate::commons::bgp::{
+    Announcement, AnnouncementSourceType, BmpListener, MrtLoader, RisDumpError, RisDumpLoader,
+    WireError,
+};
+use crate::constants::BGP_RIS_REFRESH_MINUTES;
+
+//------------ AnnouncementSourceConfig --------------------------------------
+
+/// The configuration of a source of announcements, and how often it should
+/// be checked for updates.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct AnnouncementSourceConfig {
+    #[serde(flatten)]
+    kind: AnnouncementSourceKind,
+    #[serde(default = "AnnouncementSourceConfig::default_refresh_minutes")]
+    refresh_minutes: u32,
+}
+
+impl AnnouncementSourceConfig {
+    pub fn new(kind: AnnouncementSourceKind, refresh_minutes: u32) -> Self {
+        AnnouncementSourceConfig {
+            kind,
+            refresh_minutes,
+        }
+    }
+
+    pub fn ris(v4_uri: &str, v6_uri: &str, refresh_minutes: u32) -> Self {
+        AnnouncementSourceConfig {
+            kind: AnnouncementSourceKind::Ris {
+                v4_uri: v4_uri.to_string(),
+                v6_uri: v6_uri.to_string(),
+            },
+            refresh_minutes,
+        }
+    }
+
+    pub fn kind(&self) -> &AnnouncementSourceKind {
+        &self.kind
+    }
+
+    pub fn refresh_minutes(&self) -> u32 {
+        self.refresh_minutes
+    }
+
+    fn default_refresh_minutes() -> u32 {
+        BGP_RIS_REFRESH_MINUTES as u32
+    }
+}
+
+impl fmt::Display for AnnouncementSourceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            AnnouncementSourceKind::Ris { .. } => write!(f, "BGP RIS dumps"),
+            AnnouncementSourceKind::Mrt { location } => write!(f, "MRT RIB dump '{}'", location),
+            AnnouncementSourceKind::Bmp { listen } => write!(f, "BMP listener on {}", listen),
+            AnnouncementSourceKind::File { location, .. } => {
+                write!(f, "announcements file '{}'", location)
+            }
+        }
+    }
+}
+
+//------------ AnnouncementSourceKind ----------------------------------------
+
+/// The supported sources of announcements. Locations can be a local file,
+/// or an http(s) URI. Files ending in '.gz' are gunzipped.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnouncementSourceKind {
+    /// The RIPE RIS whois dumps for IPv4 and IPv6.
+    Ris { v4_uri: String, v6_uri: String },
+
+    /// An MRT TABLE_DUMP_V2 RIB dump.
+    Mrt { location: String },
+
+    /// A BMP listener, receiving the Adj-RIB-In of connected routers.
+    Bmp { listen: SocketAddr },
+
+    /// A plain CSV or JSON file.
+    File {
+        location: String,
+        format: AnnouncementFileFormat,
+    },
+}
+
+//------------ AnnouncementFileFormat ----------------------------------------
+
+/// The format of a plain announcements file:
+///  - csv: one "asn,prefix" per line, e.g. "64496,192.0.2.0/24", where the
+///    asn may be prefixed with "AS", and lines starting with '#' and a
+///    header line starting with "asn" are ignored;
+///  - json: an array of objects, e.g. [{"asn":64496,"prefix":"192.0.2.0/24"}].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementFileFormat {
+    Csv,
+    Json,
+}
+
+//------------ AnnouncementSource --------------------------------------------
+
+/// A configured source of announcements.
+pub enum AnnouncementSource {
+    Ris(RisDumpLoader),
+    Mrt(MrtLoader),
+    Bmp(BmpListener),
+    File(String, AnnouncementFileFormat),
+}
+
+impl AnnouncementSource {
+    /// Creates the source. This will start listening in case of BMP.
+    pub fn build(config: &AnnouncementSourceConfig) -> Result<Self, AnnouncementSourceError> {
+        match &config.kind {
+            AnnouncementSourceKind::Ris { v4_uri, v6_uri } => {
+                Ok(AnnouncementSource::Ris(RisDumpLoader::new(v4_uri, v6_uri)))
+            }
+            AnnouncementSourceKind::Mrt { location } => {
+                Ok(AnnouncementSource::Mrt(MrtLoader::new(location)))
+            }
+            AnnouncementSourceKind::Bmp { listen } => {
+                Ok(AnnouncementSource::Bmp(BmpListener::start(*listen)?))
+            }
+            AnnouncementSourceKind::File { location, format } => {
+                Ok(AnnouncementSource::File(location.clone(), *format))
+            }
+        }
+    }
+
+    /// Gets the current announcements from this source.
+    pub async fn load(&self) -> Result<Vec<Announcement>, AnnouncementSourceError> {
+        match self {
+            AnnouncementSource::Ris(loader) => Ok(loader.download_updates().await?),
+            AnnouncementSource::Mrt(loader) => loader.load().await,
+            AnnouncementSource::Bmp(listener) => Ok(listener.announcements()),
+            AnnouncementSource::File(location, format) => {
+                let bytes = load_location(location).await?;
+                let announcements: Vec<Announcement> = match format {
+                    AnnouncementFileFormat::Csv => parse_csv(&bytes)?,
+                    AnnouncementFileFormat::Json => serde_json::from_slice(&bytes)
+                        .map_err(AnnouncementSourceError::parse_error)?,
+                };
+                Ok(announcements
+                    .into_iter()
+                    .map(|a| a.with_source(AnnouncementSourceType::File, a.peers()))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Reads the bytes from a local file, or an http(s) URI, and gunzips them
+/// if the location ends with '.gz'.
+pub async fn load_location(location: &str) -> Result<Vec<u8>, AnnouncementSourceError> {
+    let bytes = if location.starts_with("http://") || location.starts_with("https://") {
+        reqwest::get(location).await?.bytes().await?.to_vec()
+    } else {
+        fs::read(location)?
+    };
+
+    if location.ends_with(".gz") {
+        let mut gunzipped: Vec<u8> = vec![];
+        let mut decoder = Decoder::new(bytes.as_slice())?;
+        decoder.read_to_end(&mut gunzipped)?;
+        Ok(gunzipped)
+    } else {
+        Ok(bytes)
+    }
+}
+
+fn parse_csv(bytes: &[u8]) -> Result<Vec<Announcement>, AnnouncementSourceError> {
+    let mut res = vec![];
+    for line in bytes.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.to_lowercase().starts_with("asn") {
+            continue;
+        }
+
+        let mut columns = line.split(',');
+        let asn_str = columns.next().unwrap_or("").trim();
+        let prefix_str = columns
+            .next()
+            .ok_or_else(|| AnnouncementSourceError::parse_error(line))?;
+
+        let asn_str = if asn_str
+            .get(..2)
+            .map_or(false, |p| p.eq_ignore_ascii_case("as"))
+        {
+            &asn_str[2..]
+        } else {
+            asn_str
+        };
+        let asn = AsNumber::from_str(asn_str).map_err(AnnouncementSourceError::parse_error)?;
+        let prefix =
+            TypedPrefix::from_str(prefix_str).map_err(AnnouncementSourceError::parse_error)?;
+
+        res.push(Announcement::new(asn, prefix));
+    }
+    Ok(res)
+}
+
+//------------ AnnouncementSourceState ---------------------------------------
+
+/// Keeps the announcements last loaded from a source, and when.
+pub struct AnnouncementSourceState {
+    name: String,
+    source: AnnouncementSource,
+    refresh: Duration,
+    last_checked: RwLock<Option<Time>>,
+    announcements: RwLock<HashSet<Announcement>>,
+}
+
+impl AnnouncementSourceState {
+    pub fn build(config: &AnnouncementSourceConfig) -> Result<Self, AnnouncementSourceError> {
+        Ok(AnnouncementSourceState {
+            name: config.to_string(),
+            source: AnnouncementSource::build(config)?,
+            refresh: Duration::minutes(i64::from(config.refresh_minutes)),
+            last_checked: RwLock::new(None),
+            announcements: RwLock::new(HashSet::new()),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &AnnouncementSource {
+        &self.source
+    }
+
+    /// Returns `true` if this source was never checked, or if the refresh
+    /// interval has passed since it was last checked.
+    pub fn due(&self) -> bool {
+        match *self.last_checked.read().unwrap() {
+            None => true,
+            Some(last_checked) => last_checked + self.refresh <= Time::now(),
+        }
+    }
+
+    /// Updates the announcements for this source, returns `true` if they
+    /// changed.
+    pub fn update(&self, announcements: Vec<Announcement>) -> bool {
+        *self.last_checked.write().unwrap() = Some(Time::now());
+
+        let announcements: HashSet<Announcement> = announcements.into_iter().collect();
+        let mut current = self.announcements.write().unwrap();
+        if *current == announcements {
+            false
+        } else {
+            *current = announcements;
+            true
+        }
+    }
+
+    /// Marks that this source was checked, even though it could not be
+    /// loaded, so that it is not retried until the refresh interval passed.
+    pub fn update_checked(&self) {
+        *self.last_checked.write().unwrap() = Some(Time::now());
+    }
+
+    pub fn announcements(&self) -> Vec<Announcement> {
+        self.announcements.read().unwrap().iter().cloned().collect()
+    }
+}
+
+//------------ AnnouncementSourceError ---------------------------------------
+
+#[derive(Debug, Display)]
+pub enum AnnouncementSourceError {
+    #[display(fmt = "{}", _0)]
+    RisDump(RisDumpError),
+
+    #[display(fmt = "Cannot get uri: {}", _0)]
+    Reqwest(reqwest::Error),
+
+    #[display(fmt = "Error parsing announcements: {}", _0)]
+    Parse(String),
+
+    #[display(fmt = "IO error: {}", _0)]
+    Io(io::Error),
+}
+
+impl AnnouncementSourceError {
+    fn parse_error(e: impl fmt::Display) -> Self {
+        AnnouncementSourceError::Parse(e.to_string())
+    }
+}
+
+impl From<RisDumpError> for AnnouncementSourceError {
+    fn from(e: RisDumpError) -> Self {
+        AnnouncementSourceError::RisDump(e)
+    }
+}
+
+impl From<reqwest::Error> for AnnouncementSourceError {
+    fn from(e: reqwest::Error) -> Self {
+        AnnouncementSourceError::Reqwest(e)
+    }
+}
+
+impl From<io::Error> for AnnouncementSourceError {
+    fn from(e: io::Error) -> Self {
+        AnnouncementSourceError::Io(e)
+    }
+}
+
+impl From<WireError> for AnnouncementSourceError {
+    fn from(e: WireError) -> Self {
+        Self::parse_error(e)
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::announcement;
+
+    #[test]
+    fn parse_announcement_files() {
+        let csv = concat!(
+            "asn,prefix\n",
+            "# some comment\n",
+            "64496,10.0.0.0/24\n",
+            "AS64497, 2001:db8::/32\n",
+        );
+        assert_eq!(
+            parse_csv(csv.as_bytes()).unwrap(),
+            vec![
+                announcement("10.0.0.0/24 => 64496"),
+                announcement("2001:db8::/32 => 64497")
+            ]
+        );
+        assert!(parse_csv(b"64496\n").is_err());
+        assert!(parse_csv("é64496,10.0.0.0/24\n".as_bytes()).is_err());
+
+        let json = r#"[{"asn":64496,"prefix":"10.0.0.0/24"}]"#;
+        let parsed: Vec<Announcement> = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed, vec![announcement("10.0.0.0/24 => 64496")]);
+    }
+
+    #[test]
+    fn deserialize_source_config() {
+        let toml = r#"
+            type = "file"
+            location = "/var/lib/krill/bgp/announcements.csv"
+            format = "csv"
+            refresh_minutes = 5
+        "#;
+        let config: AnnouncementSourceConfig = toml::from_str(toml).unwrap();
+        assert_eq!(
+            config,
+            AnnouncementSourceConfig::new(
+                AnnouncementSourceKind::File {
+                    location: "/var/lib/krill/bgp/announcements.csv".to_string(),
+                    format: AnnouncementFileFormat::Csv
+                },
+                5
+            )
+        );
+
+        let toml = r#"
+            type = "bmp"
+            listen = "127.0.0.1:11019"
+        "#;
+        let config: AnnouncementSourceConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.refresh_minutes(), 60);
+    }
+}
@@ -0,0 +1,317 @@
+//! Parsing of the parts of BGP messages that are needed to learn about
+//! announcements. This is shared by the MRT and BMP announcement sources.
+//!
+//! See RFC 4271 (BGP-4), RFC 4760 (multi-protocol extensions) and RFC 6793
+//! (four-octet AS numbers).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::commons::api::{AsNumber, TypedPrefix};
+
+pub const AFI_IPV4: u16 = 1;
+pub const AFI_IPV6: u16 = 2;
+pub const SAFI_UNICAST: u8 = 1;
+
+const ATTR_FLAG_EXTENDED_LENGTH: u8 = 0x10;
+
+const ATTR_TYPE_AS_PATH: u8 = 2;
+const ATTR_TYPE_MP_REACH_NLRI: u8 = 14;
+const ATTR_TYPE_MP_UNREACH_NLRI: u8 = 15;
+const ATTR_TYPE_AS4_PATH: u8 = 17;
+
+const AS_PATH_SEGMENT_SET: u8 = 1;
+const AS_PATH_SEGMENT_SEQUENCE: u8 = 2;
+
+//------------ WireReader ----------------------------------------------------
+
+/// Reads network byte order values from a slice, failing on truncated data.
+pub struct WireReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        WireReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.data.len() < len {
+            return Err(WireError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), WireError> {
+        self.take(len).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, WireError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, WireError> {
+        let bytes = self.take(4)?;
+        Ok(bytes
+            .iter()
+            .fold(0u32, |acc, byte| acc << 8 | u32::from(*byte)))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, WireError> {
+        let high = u64::from(self.u32()?);
+        let low = u64::from(self.u32()?);
+        Ok(high << 32 | low)
+    }
+
+    /// Reads an IPv4 address (4 bytes), or IPv6 address (16 bytes).
+    pub fn ip_addr(&mut self, ipv6: bool) -> Result<IpAddr, WireError> {
+        if ipv6 {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(self.take(16)?);
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        } else {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(self.take(4)?);
+            Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+    }
+
+    /// Reads a prefix encoded as a length in bits, followed by the minimum
+    /// number of bytes needed for the address.
+    pub fn prefix(&mut self, afi: u16) -> Result<TypedPrefix, WireError> {
+        let len = self.u8()?;
+        let bytes = self.take((usize::from(len) + 7) / 8)?;
+
+        let addr = match afi {
+            AFI_IPV4 => {
+                let mut octets = [0u8; 4];
+                if bytes.len() > octets.len() {
+                    return Err(WireError::Invalid("IPv4 prefix length"));
+                }
+                octets[..bytes.len()].copy_from_slice(bytes);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            AFI_IPV6 => {
+                let mut octets = [0u8; 16];
+                if bytes.len() > octets.len() {
+                    return Err(WireError::Invalid("IPv6 prefix length"));
+                }
+                octets[..bytes.len()].copy_from_slice(bytes);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(WireError::Invalid("address family")),
+        };
+
+        TypedPrefix::from_addr(addr, len).map_err(|_| WireError::Invalid("prefix"))
+    }
+
+    /// Reads prefixes until the end of the data.
+    pub fn prefixes(mut self, afi: u16) -> Result<Vec<TypedPrefix>, WireError> {
+        let mut res = vec![];
+        while !self.is_empty() {
+            res.push(self.prefix(afi)?);
+        }
+        Ok(res)
+    }
+}
+
+//------------ PathOrigin ----------------------------------------------------
+
+/// The origin of a route, as found at the end of its AS path.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PathOrigin {
+    /// The path ends in an AS_SEQUENCE, the last AS of which is the origin.
+    Asn(AsNumber),
+
+    /// The path ends in an AS_SET, so there is no single origin AS. Such
+    /// routes are not supported, similar to the RIS dumps.
+    Set,
+
+    /// The path is empty, i.e. the route was originated by the peer itself.
+    #[default]
+    Empty,
+}
+
+//------------ PathAttributes ------------------------------------------------
+
+/// The parts of the BGP path attributes of a route that matter for learning
+/// announcements.
+#[derive(Debug, Default)]
+pub struct PathAttributes {
+    origin: PathOrigin,
+    as4_origin: Option<PathOrigin>,
+    mp_reach: Vec<TypedPrefix>,
+    mp_unreach: Vec<TypedPrefix>,
+}
+
+impl PathAttributes {
+    /// Parses path attributes. If `four_byte_asn` is false, the AS_PATH is
+    /// expected to use two byte AS numbers, in which case an AS4_PATH takes
+    /// precedence. If `mp_nlri` is false, the MP_REACH_NLRI attribute is not
+    /// parsed, as is the case for the abbreviated form used in MRT dumps.
+    pub fn parse(data: &[u8], four_byte_asn: bool, mp_nlri: bool) -> Result<Self, WireError> {
+        let mut res = PathAttributes::default();
+        let mut reader = WireReader::new(data);
+
+        while !reader.is_empty() {
+            let flags = reader.u8()?;
+            let typ = reader.u8()?;
+            let len = if flags & ATTR_FLAG_EXTENDED_LENGTH != 0 {
+                usize::from(reader.u16()?)
+            } else {
+                usize::from(reader.u8()?)
+            };
+            let value = reader.take(len)?;
+
+            match typ {
+                ATTR_TYPE_AS_PATH => res.origin = Self::path_origin(value, four_byte_asn)?,
+                ATTR_TYPE_AS4_PATH if !four_byte_asn => {
+                    res.as4_origin = Some(Self::path_origin(value, true)?)
+                }
+                ATTR_TYPE_MP_REACH_NLRI if mp_nlri => res.mp_reach = Self::parse_mp_reach(value)?,
+                ATTR_TYPE_MP_UNREACH_NLRI if mp_nlri => {
+                    res.mp_unreach = Self::parse_mp_unreach(value)?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// The origin of the route. The AS4_PATH, if present and not empty,
+    /// takes precedence over the AS_PATH.
+    pub fn origin(&self) -> PathOrigin {
+        match self.as4_origin {
+            Some(PathOrigin::Empty) | None => self.origin,
+            Some(origin) => origin,
+        }
+    }
+
+    pub fn mp_reach(&self) -> &Vec<TypedPrefix> {
+        &self.mp_reach
+    }
+
+    pub fn mp_unreach(&self) -> &Vec<TypedPrefix> {
+        &self.mp_unreach
+    }
+
+    fn path_origin(data: &[u8], four_byte_asn: bool) -> Result<PathOrigin, WireError> {
+        let mut reader = WireReader::new(data);
+        let mut origin = PathOrigin::Empty;
+
+        while !reader.is_empty() {
+            let segment_type = reader.u8()?;
+            let count = reader.u8()?;
+            let mut last = None;
+            for _ in 0..count {
+                let asn = if four_byte_asn {
+                    reader.u32()?
+                } else {
+                    u32::from(reader.u16()?)
+                };
+                last = Some(AsNumber::new(asn));
+            }
+
+            match segment_type {
+                AS_PATH_SEGMENT_SEQUENCE => {
+                    if let Some(asn) = last {
+                        origin = PathOrigin::Asn(asn)
+                    }
+                }
+                AS_PATH_SEGMENT_SET if count > 0 => origin = PathOrigin::Set,
+                _ => {} // empty and confederation segments do not change the origin
+            }
+        }
+
+        Ok(origin)
+    }
+
+    fn parse_mp_reach(data: &[u8]) -> Result<Vec<TypedPrefix>, WireError> {
+        let mut reader = WireReader::new(data);
+        let afi = reader.u16()?;
+        let safi = reader.u8()?;
+        let next_hop_len = reader.u8()?;
+        reader.skip(usize::from(next_hop_len))?;
+        reader.skip(1)?; // reserved
+
+        if safi == SAFI_UNICAST && (afi == AFI_IPV4 || afi == AFI_IPV6) {
+            reader.prefixes(afi)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn parse_mp_unreach(data: &[u8]) -> Result<Vec<TypedPrefix>, WireError> {
+        let mut reader = WireReader::new(data);
+        let afi = reader.u16()?;
+        let safi = reader.u8()?;
+
+        if safi == SAFI_UNICAST && (afi == AFI_IPV4 || afi == AFI_IPV6) {
+            reader.prefixes(afi)
+        } else {
+            Ok(vec![])
+        }
+    }
+}
+
+//------------ WireError -----------------------------------------------------
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum WireError {
+    #[display(fmt = "unexpected end of data")]
+    Truncated,
+
+    #[display(fmt = "invalid {}", _0)]
+    Invalid(&'static str),
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn parse_prefixes_and_origin() {
+        let prefixes = WireReader::new(&[24, 192, 0, 2, 16, 10, 1]).prefixes(AFI_IPV4);
+        assert_eq!(
+            prefixes.unwrap(),
+            vec![
+                TypedPrefix::from_str("192.0.2.0/24").unwrap(),
+                TypedPrefix::from_str("10.1.0.0/16").unwrap()
+            ]
+        );
+
+        // AS_PATH: sequence 64496 64497
+        let as_path = [0x40, 2, 10, 2, 2, 0, 0, 0xfb, 0xf0, 0, 0, 0xfb, 0xf1];
+        let attributes = PathAttributes::parse(&as_path, true, true).unwrap();
+        assert_eq!(attributes.origin(), PathOrigin::Asn(AsNumber::new(64497)));
+
+        // AS_PATH: sequence 64496, set {64497}
+        let as_path = [0x40, 2, 12, 2, 1, 0, 0, 0xfb, 0xf0, 1, 1, 0, 0, 0xfb, 0xf1];
+        let attributes = PathAttributes::parse(&as_path, true, true).unwrap();
+        assert_eq!(attributes.origin(), PathOrigin::Set);
+
+        // AS_PATH: empty
+        let attributes = PathAttributes::parse(&[0x40, 2, 0], true, true).unwrap();
+        assert_eq!(attributes.origin(), PathOrigin::Empty);
+
+        assert_eq!(
+            PathAttributes::parse(&[0x40, 2, 10, 2], true, true).unwrap_err(),
+            WireError::Truncated
+        );
+    }
+}
@@ -12,8 +12,8 @@ use rpki::x509::ValidationError;
 
 use crate::commons::api::rrdp::PublicationDeltaError;
 use crate::commons::api::{
-    ChildHandle, ErrorResponse, Handle, ParentHandle, PublisherHandle, ResourceClassName,
-    ResourceSetError,
+    AsNumber, BgpSecAsnKey, ChildHandle, ErrorResponse, Handle, HistoryPoint, ParentHandle,
+    PublisherHandle, ResourceClassName, ResourceSet, ResourceSetError, RtaName,
 };
 use crate::commons::eventsourcing::AggregateStoreError;
 use crate::commons::remote::rfc6492;
@@ -44,6 +44,9 @@ pub enum Error {
     #[display(fmt = "HTTP client error: {}", _0)]
     HttpClientError(httpclient::Error),
 
+    #[display(fmt = "Backup issue: {}", _0)]
+    BackupError(String),
+
     //-----------------------------------------------------------------
     // General API Client Issues
     //-----------------------------------------------------------------
@@ -62,12 +65,21 @@ pub enum Error {
     #[display(fmt = "Invalid path argument for seconds")]
     ApiInvalidSeconds,
 
+    #[display(fmt = "Invalid query parameter for event id")]
+    ApiInvalidEventId,
+
     #[display(fmt = "POST body exceeds configured limit")]
     PostTooBig,
 
     #[display(fmt = "POST body cannot be read")]
     PostCannotRead,
 
+    #[display(fmt = "Login through OpenID Connect is not configured")]
+    ApiLoginNotConfigured,
+
+    #[display(fmt = "Login failed: {}", _0)]
+    ApiLoginFailed(String),
+
     //-----------------------------------------------------------------
     // Repository Issues
     //-----------------------------------------------------------------
@@ -114,11 +126,22 @@ pub enum Error {
     CaDuplicate(Handle),
     #[display(fmt = "CA '{}' is unknown", _0)]
     CaUnknown(Handle),
+    #[display(fmt = "CA '{}' has no history at '{}'", _0, _1)]
+    CaHistoryPointUnknown(Handle, HistoryPoint),
 
     // CA Repo Issues
     #[display(fmt = "CA '{}' already uses this repository", _0)]
     CaRepoInUse(Handle),
 
+    #[display(
+        fmt = "CA '{}' is still migrating to its current repository, resume or abort the migration first",
+        _0
+    )]
+    CaRepoMigrationInProgress(Handle),
+
+    #[display(fmt = "CA '{}' has no repository migration in progress", _0)]
+    CaRepoNoMigration(Handle),
+
     #[display(
         fmt = "CA '{}' cannot get response from repository '{}'. Is the 'service_uri' in the XML reachable? Note that when upgrading Krill you should re-use existing configuration and data. For a fresh re-install of Krill you will need to send XML to all other parties again: parent(s), children, and repository",
         _0,
@@ -153,6 +176,10 @@ pub enum Error {
     )]
     CaParentAddNotResponsive(Handle, ParentHandle),
 
+    // CA Signer Issues
+    #[display(fmt = "CA '{}' cannot use unknown signer '{}'", _0, _1)]
+    CaSignerUnknown(Handle, String),
+
     //-----------------------------------------------------------------
     // RFC6492 (requesting resources)
     //-----------------------------------------------------------------
@@ -210,6 +237,51 @@ pub enum Error {
     #[display(fmt = "Prefix in ROA '{}' not held by you", _1)]
     CaAuthorizationNotEntitled(Handle, RouteAuthorization),
 
+    #[display(fmt = "ROA '{}' was not added because it is no longer valid", _1)]
+    CaAuthorizationExpired(Handle, RouteAuthorization),
+
+    // ASPAs
+    #[display(fmt = "No ASPA exists for customer AS '{}'", _1)]
+    CaAspaUnknown(Handle, AsNumber),
+
+    #[display(fmt = "Customer AS '{}' in ASPA not held by you", _1)]
+    CaAspaNotEntitled(Handle, AsNumber),
+
+    #[display(fmt = "ASPA for customer AS '{}' cannot have itself as provider", _1)]
+    CaAspaCustomerAsProvider(Handle, AsNumber),
+
+    #[display(fmt = "ASPA for customer AS '{}' must have at least one provider", _1)]
+    CaAspaProvidersEmpty(Handle, AsNumber),
+
+    // BGPsec
+    #[display(fmt = "No BGPsec router key exists for '{}'", _1)]
+    CaBgpSecUnknown(Handle, BgpSecAsnKey),
+
+    #[display(fmt = "AS '{}' in BGPsec router key definition not held by you", _1)]
+    CaBgpSecNotEntitled(Handle, AsNumber),
+
+    #[display(fmt = "Invalid BGPsec router CSR: {}", _1)]
+    CaBgpSecCsrInvalid(Handle, String),
+
+    // RTA
+    #[display(fmt = "Unknown RTA '{}'", _1)]
+    CaRtaUnknown(Handle, RtaName),
+
+    #[display(fmt = "RTA '{}' already exists", _1)]
+    CaRtaDuplicate(Handle, RtaName),
+
+    #[display(fmt = "Resources '{}' in RTA not held by you", _1)]
+    CaRtaNotEntitled(Handle, ResourceSet),
+
+    #[display(fmt = "RTA '{}' was not prepared for co-signing", _1)]
+    CaRtaNotPrepared(Handle, RtaName),
+
+    #[display(fmt = "Cannot co-sign RTA: {}", _1)]
+    CaRtaCoSignInvalid(Handle, String),
+
+    #[display(fmt = "Invalid RTA: {}", _0)]
+    RtaInvalid(String),
+
     //-----------------------------------------------------------------
     // Key Usage Issues
     //-----------------------------------------------------------------
@@ -329,14 +401,18 @@ impl Error {
     pub fn status(&self) -> StatusCode {
         match self {
             // Most is bad requests by users, so just mapping the things that are not
-            Error::IoError(_) | Error::SignerError(_) | Error::AggregateStoreError(_) => {
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
+            Error::IoError(_)
+            | Error::SignerError(_)
+            | Error::AggregateStoreError(_)
+            | Error::BackupError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::PublisherUnknown(_)
             | Error::CaUnknown(_)
             | Error::CaChildUnknown(_, _)
             | Error::CaParentUnknown(_, _)
-            | Error::ApiUnknownResource => StatusCode::NOT_FOUND,
+            | Error::ApiUnknownResource
+            | Error::ApiLoginNotConfigured => StatusCode::NOT_FOUND,
+
+            Error::ApiLoginFailed(_) => StatusCode::FORBIDDEN,
 
             _ => StatusCode::BAD_REQUEST,
         }
@@ -363,6 +439,9 @@ impl Error {
             // internal server error
             Error::HttpClientError(e) => ErrorResponse::new("sys-http-client", &self).with_cause(e),
 
+            // internal server error
+            Error::BackupError(e) => ErrorResponse::new("sys-backup", &self).with_cause(e),
+
             //-----------------------------------------------------------------
             // General API Client Issues (label: api-*)
             //-----------------------------------------------------------------
@@ -377,10 +456,16 @@ impl Error {
 
             Error::ApiInvalidSeconds => ErrorResponse::new("api-invalid-path-seconds", &self),
 
+            Error::ApiInvalidEventId => ErrorResponse::new("api-invalid-event-id", &self),
+
             Error::PostTooBig => ErrorResponse::new("api-post-body-exceeds-limit", &self),
 
             Error::PostCannotRead => ErrorResponse::new("api-post-body-cannot-read", &self),
 
+            Error::ApiLoginNotConfigured => ErrorResponse::new("api-login-not-configured", &self),
+
+            Error::ApiLoginFailed(e) => ErrorResponse::new("api-login-failed", &self).with_cause(e),
+
             //-----------------------------------------------------------------
             // Repository Issues (label: repo-*)
             //-----------------------------------------------------------------
@@ -430,8 +515,22 @@ impl Error {
 
             Error::CaUnknown(ca) => ErrorResponse::new("ca-unknown", &self).with_ca(ca),
 
+            Error::CaHistoryPointUnknown(ca, point) => {
+                ErrorResponse::new("ca-history-point-unknown", &self)
+                    .with_ca(ca)
+                    .with_history_point(point)
+            }
+
             Error::CaRepoInUse(ca) => ErrorResponse::new("ca-repo-same", &self).with_ca(ca),
 
+            Error::CaRepoMigrationInProgress(ca) => {
+                ErrorResponse::new("ca-repo-migration-in-progress", &self).with_ca(ca)
+            }
+
+            Error::CaRepoNoMigration(ca) => {
+                ErrorResponse::new("ca-repo-no-migration", &self).with_ca(ca)
+            }
+
             Error::CaRepoIssue(ca, err) => ErrorResponse::new("ca-repo-issue", &self)
                 .with_ca(ca)
                 .with_cause(err),
@@ -477,6 +576,10 @@ impl Error {
                     .with_parent(parent)
             }
 
+            Error::CaSignerUnknown(ca, signer) => ErrorResponse::new("ca-signer-unknown", &self)
+                .with_ca(ca)
+                .with_signer(signer),
+
             //-----------------------------------------------------------------
             // RFC6492 (requesting resources, not on JSON api)
             //-----------------------------------------------------------------
@@ -552,6 +655,70 @@ impl Error {
                     .with_auth(auth)
             }
 
+            Error::CaAuthorizationExpired(ca, auth) => ErrorResponse::new("ca-roa-expired", &self)
+                .with_ca(ca)
+                .with_auth(auth),
+
+            Error::CaAspaUnknown(ca, customer) => ErrorResponse::new("ca-aspa-unknown", &self)
+                .with_ca(ca)
+                .with_customer(*customer),
+
+            Error::CaAspaNotEntitled(ca, customer) => {
+                ErrorResponse::new("ca-aspa-not-entitled", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            Error::CaAspaCustomerAsProvider(ca, customer) => {
+                ErrorResponse::new("ca-aspa-customer-as-provider", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            Error::CaAspaProvidersEmpty(ca, customer) => {
+                ErrorResponse::new("ca-aspa-providers-empty", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            Error::CaBgpSecUnknown(ca, key) => ErrorResponse::new("ca-bgpsec-unknown", &self)
+                .with_ca(ca)
+                .with_router_key(key),
+
+            Error::CaBgpSecNotEntitled(ca, asn) => {
+                ErrorResponse::new("ca-bgpsec-not-entitled", &self)
+                    .with_ca(ca)
+                    .with_asn(*asn)
+            }
+
+            Error::CaBgpSecCsrInvalid(ca, _) => {
+                ErrorResponse::new("ca-bgpsec-csr-invalid", &self).with_ca(ca)
+            }
+
+            Error::CaRtaUnknown(ca, name) => ErrorResponse::new("ca-rta-unknown", &self)
+                .with_ca(ca)
+                .with_rta(name),
+
+            Error::CaRtaDuplicate(ca, name) => ErrorResponse::new("ca-rta-duplicate", &self)
+                .with_ca(ca)
+                .with_rta(name),
+
+            Error::CaRtaNotEntitled(ca, resources) => {
+                ErrorResponse::new("ca-rta-not-entitled", &self)
+                    .with_ca(ca)
+                    .with_resources(resources)
+            }
+
+            Error::CaRtaNotPrepared(ca, name) => ErrorResponse::new("ca-rta-not-prepared", &self)
+                .with_ca(ca)
+                .with_rta(name),
+
+            Error::CaRtaCoSignInvalid(ca, _) => {
+                ErrorResponse::new("ca-rta-cosign-invalid", &self).with_ca(ca)
+            }
+
+            Error::RtaInvalid(_) => ErrorResponse::new("rta-invalid", &self),
+
             //-----------------------------------------------------------------
             // Key Usage Issues (key-*)
             //-----------------------------------------------------------------
@@ -666,6 +833,10 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/sys-http-client.json"),
             Error::HttpClientError(httpclient::Error::Forbidden),
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/sys-backup.json"),
+            Error::BackupError("wrong passphrase".to_string()),
+        );
 
         //-----------------------------------------------------------------
         // General API Client Issues
@@ -686,6 +857,16 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/api-unknown-resource.json"),
             Error::ApiUnknownResource,
         );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/api-login-not-configured.json"
+            ),
+            Error::ApiLoginNotConfigured,
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/api-login-failed.json"),
+            Error::ApiLoginFailed("wrong nonce".to_string()),
+        );
 
         //-----------------------------------------------------------------
         // Repository Issues
@@ -757,11 +938,27 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/ca-unknown.json"),
             Error::CaUnknown(ca.clone()),
         );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-history-point-unknown.json"
+            ),
+            Error::CaHistoryPointUnknown(ca.clone(), HistoryPoint::Version(42)),
+        );
 
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-repo-same.json"),
             Error::CaRepoInUse(ca.clone()),
         );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-repo-migration-in-progress.json"
+            ),
+            Error::CaRepoMigrationInProgress(ca.clone()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-repo-no-migration.json"),
+            Error::CaRepoNoMigration(ca.clone()),
+        );
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-repo-issue.json"),
             Error::CaRepoIssue(ca.clone(), "cannot connect".to_string()),
@@ -803,6 +1000,10 @@ mod tests {
             ),
             Error::CaParentResponseWrongXml(ca.clone()),
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-signer-unknown.json"),
+            Error::CaSignerUnknown(ca.clone(), "hsm".to_string()),
+        );
 
         verify(
             include_str!("../../test-resources/api/regressions/errors/rfc6492-protocol.json"),
@@ -869,7 +1070,77 @@ mod tests {
         );
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-roa-not-entitled.json"),
-            Error::CaAuthorizationNotEntitled(ca, auth),
+            Error::CaAuthorizationNotEntitled(ca.clone(), auth),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-roa-expired.json"),
+            Error::CaAuthorizationExpired(ca.clone(), auth),
+        );
+
+        let customer = AsNumber::new(64496);
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-aspa-unknown.json"),
+            Error::CaAspaUnknown(ca.clone(), customer),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-aspa-not-entitled.json"),
+            Error::CaAspaNotEntitled(ca.clone(), customer),
+        );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-aspa-customer-as-provider.json"
+            ),
+            Error::CaAspaCustomerAsProvider(ca.clone(), customer),
+        );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-aspa-providers-empty.json"
+            ),
+            Error::CaAspaProvidersEmpty(ca.clone(), customer),
+        );
+
+        let router_key =
+            BgpSecAsnKey::from_str("AS64496-17316903F0671229E8808BA8E8AB0105FA915A07").unwrap();
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-unknown.json"),
+            Error::CaBgpSecUnknown(ca.clone(), router_key),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-not-entitled.json"),
+            Error::CaBgpSecNotEntitled(ca.clone(), customer),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-csr-invalid.json"),
+            Error::CaBgpSecCsrInvalid(ca.clone(), "CSR signature is invalid".to_string()),
+        );
+
+        let rta_name = "attestation".to_string();
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-rta-unknown.json"),
+            Error::CaRtaUnknown(ca.clone(), rta_name.clone()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-rta-duplicate.json"),
+            Error::CaRtaDuplicate(ca.clone(), rta_name.clone()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-rta-not-entitled.json"),
+            Error::CaRtaNotEntitled(
+                ca.clone(),
+                ResourceSet::from_strs("AS65000", "10.0.0.0/8", "").unwrap(),
+            ),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-rta-not-prepared.json"),
+            Error::CaRtaNotPrepared(ca.clone(), rta_name),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-rta-cosign-invalid.json"),
+            Error::CaRtaCoSignInvalid(ca, "RTA does not include prepared keys".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/rta-invalid.json"),
+            Error::RtaInvalid("RTA does not match content".to_string()),
         );
 
         verify(
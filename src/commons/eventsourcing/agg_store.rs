@@ -1,16 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::RwLock;
 
 use rpki::x509::Time;
 
-use crate::commons::api::{CommandHistory, CommandHistoryCriteria, Handle};
+use crate::commons::api::{
+    AggregateIntegrityReport, CommandHistory, CommandHistoryCriteria, Handle, IntegrityIssue,
+    StoredEffect,
+};
 use crate::commons::eventsourcing::cmd::{Command, StoredCommandBuilder};
 use crate::commons::eventsourcing::{
-    Aggregate, CommandKey, DiskKeyStore, Event, EventListener, KeyStore, KeyStoreError,
-    KeyStoreVersion, StoredCommand,
+    Aggregate, AnyKeyStore, CommandKey, Event, EventListener, HistoryArchive, KeyStore,
+    KeyStoreError, KeyStoreVersion, StorageType, StoreBackup, StoredCommand,
 };
 
 const SNAPSHOT_FREQ: u64 = 5;
@@ -26,6 +30,16 @@ where
     /// does not exist.
     fn get_latest(&self, id: &Handle) -> StoreResult<Arc<A>>;
 
+    /// Rebuilds the aggregate as it was at the given version by replaying
+    /// its events. Returns an AggregateStoreError::UnknownVersion in case
+    /// the aggregate never had this version.
+    fn get_at_version(&self, id: &Handle, version: u64) -> StoreResult<A>;
+
+    /// Returns the version that the aggregate had at the given time, based
+    /// on the commands processed up to and including that time. Returns
+    /// None if no command had changed the aggregate yet.
+    fn version_at(&self, id: &Handle, time: Time) -> StoreResult<Option<u64>>;
+
     /// Adds a new aggregate instance based on the init event.
     fn add(&self, init: A::InitEvent) -> StoreResult<Arc<A>>;
 
@@ -61,6 +75,29 @@ where
 
     /// Returns a stored event if it can be found.
     fn stored_event(&self, id: &Handle, version: u64) -> StoreResult<Option<A::Event>>;
+
+    /// Verifies the stored history of all aggregates: events must be
+    /// contiguous, the snapshot must match a replay of the events, the
+    /// info must be consistent and commands must refer to existing events.
+    ///
+    /// If 'repair' is true, then snapshots and info are rebuilt from the
+    /// events for aggregates where this can be done without losing events.
+    fn verify_integrity(&self, repair: bool) -> StoreResult<Vec<AggregateIntegrityReport>>;
+
+    /// Moves the commands processed before the given time, and the events
+    /// they resulted in, from the live history of all aggregates into their
+    /// archives. Returns the number of commands archived.
+    ///
+    /// A snapshot of the latest version is saved first, so that aggregates
+    /// are loaded without replaying archived events. The archived history
+    /// is still included in the command history.
+    fn archive_history(&self, before: Time) -> StoreResult<usize>;
+
+    /// Returns a copy of all stored values, made while no commands are
+    /// processed. The function 'with' is called before commands are allowed
+    /// again, so that state kept outside of the store, e.g. keys, can be
+    /// copied consistently with it.
+    fn backup<T, F: FnOnce() -> T>(&self, name: &str, with: F) -> StoreResult<(StoreBackup, T)>;
 }
 
 /// This type defines possible Errors for the AggregateStore
@@ -91,8 +128,17 @@ pub enum AggregateStoreError {
     )]
     UnknownCommand(Handle, u64),
 
+    #[display(fmt = "Aggregate '{}' does not have version '{}'", _0, _1)]
+    UnknownVersion(Handle, u64),
+
     #[display(fmt = "Offset '{}' exceeds total '{}'", _0, _1)]
     CommandOffsetTooLarge(u64, u64),
+
+    #[display(fmt = "Store '{}' has unsupported version '{:?}'", _0, _1)]
+    UnsupportedVersion(String, KeyStoreVersion),
+
+    #[display(fmt = "Store '{}' is not empty", _0)]
+    NotEmpty(String),
 }
 
 impl From<KeyStoreError> for AggregateStoreError {
@@ -102,7 +148,7 @@ impl From<KeyStoreError> for AggregateStoreError {
 }
 
 pub struct DiskAggregateStore<A: Aggregate> {
-    store: DiskKeyStore,
+    store: AnyKeyStore,
     cache: RwLock<HashMap<Handle, Arc<A>>>,
     use_cache: bool,
     listeners: Vec<Arc<dyn EventListener<A>>>,
@@ -110,13 +156,16 @@ pub struct DiskAggregateStore<A: Aggregate> {
 }
 
 impl<A: Aggregate> DiskAggregateStore<A> {
-    pub fn new(work_dir: &PathBuf, name_space: &str) -> StoreResult<Self> {
-        let store = DiskKeyStore::under_work_dir(work_dir, name_space)
-            .map_err(AggregateStoreError::IoError)?;
+    pub fn new(
+        work_dir: &PathBuf,
+        name_space: &str,
+        storage_type: StorageType,
+    ) -> StoreResult<Self> {
+        let store = AnyKeyStore::under_work_dir(work_dir, name_space, storage_type)?;
 
         if store.aggregates().is_empty() {
             store
-                .set_version(&KeyStoreVersion::V0_7)
+                .set_version(&KeyStoreVersion::current())
                 .map_err(AggregateStoreError::KeyStoreError)?;
         }
 
@@ -183,6 +232,384 @@ impl<A: Aggregate> DiskAggregateStore<A> {
     }
 }
 
+/// # Integrity
+///
+impl<A: Aggregate> DiskAggregateStore<A> {
+    /// Checks the stored history of an aggregate. Returns the issues found
+    /// and, if possible, the aggregate replayed from all contiguous events.
+    fn check_integrity(&self, id: &Handle) -> (Vec<IntegrityIssue>, Option<A>, u64) {
+        let mut issues = vec![];
+
+        let archive = match self.store.get_archive(id) {
+            Ok(archive) => archive,
+            Err(e) => {
+                issues.push(IntegrityIssue::ArchiveUnreadable {
+                    cause: e.to_string(),
+                });
+                HistoryArchive::default()
+            }
+        };
+
+        let mut event_versions: HashSet<u64> = self.live_event_versions(id).into_iter().collect();
+        event_versions.extend(1..archive.next_version());
+
+        // Replay all contiguous events, starting with the init event.
+        let replayed = match self.store.get_event::<A::InitEvent>(id, 0) {
+            Err(e) => {
+                issues.push(IntegrityIssue::EventUnreadable {
+                    version: 0,
+                    cause: e.to_string(),
+                });
+                None
+            }
+            Ok(None) => {
+                issues.push(IntegrityIssue::InitEventMissing);
+                None
+            }
+            Ok(Some(init)) => match A::init(init) {
+                Err(e) => {
+                    issues.push(IntegrityIssue::EventUnreadable {
+                        version: 0,
+                        cause: e.to_string(),
+                    });
+                    None
+                }
+                Ok(mut aggregate) => {
+                    let mut archive_readable = true;
+                    for chunk in archive.chunks() {
+                        match self.store.get_archived_events::<A>(id, chunk) {
+                            Ok(events) => events.into_iter().for_each(|e| aggregate.apply(e)),
+                            Err(e) => {
+                                issues.push(IntegrityIssue::ArchiveUnreadable {
+                                    cause: e.to_string(),
+                                });
+                                archive_readable = false;
+                                break;
+                            }
+                        }
+                    }
+
+                    if archive_readable {
+                        loop {
+                            match self.store.get_event::<A::Event>(id, aggregate.version()) {
+                                Ok(Some(e)) => aggregate.apply(e),
+                                Ok(None) => break Some(aggregate),
+                                Err(e) => {
+                                    issues.push(IntegrityIssue::EventUnreadable {
+                                        version: aggregate.version(),
+                                        cause: e.to_string(),
+                                    });
+                                    break None;
+                                }
+                            }
+                        }
+                    } else {
+                        None
+                    }
+                }
+            },
+        };
+
+        // Next expected event version, i.e. the version of the replayed aggregate.
+        let events_version = replayed.as_ref().map(|a| a.version()).unwrap_or(0);
+        if let Some(found) = event_versions
+            .iter()
+            .filter(|v| **v >= events_version)
+            .max()
+        {
+            if replayed.is_some() {
+                issues.push(IntegrityIssue::EventMissing {
+                    missing: events_version,
+                    found: *found,
+                });
+            }
+        }
+
+        let snapshot_version = match self.store.get::<A>(id, &AnyKeyStore::key_for_snapshot()) {
+            Err(e) => {
+                issues.push(IntegrityIssue::SnapshotUnreadable {
+                    cause: e.to_string(),
+                });
+                None
+            }
+            Ok(None) => None,
+            Ok(Some(snapshot)) => {
+                if replayed.is_some() {
+                    if snapshot.version() > events_version {
+                        issues.push(IntegrityIssue::SnapshotAhead {
+                            snapshot: snapshot.version(),
+                            events: events_version,
+                        });
+                    } else if let Ok(Some(at_snapshot)) =
+                        self.store.get_aggregate_at::<A>(id, snapshot.version())
+                    {
+                        if !Self::same_state(&snapshot, &at_snapshot) {
+                            issues.push(IntegrityIssue::SnapshotMismatch {
+                                version: snapshot.version(),
+                            });
+                        }
+                    }
+                }
+                Some(snapshot.version())
+            }
+        };
+
+        let mut last_command = 0;
+        for chunk in archive.chunks() {
+            let commands = match self.store.get_archived_commands::<A>(id, chunk) {
+                Ok(commands) => commands,
+                Err(e) => {
+                    issues.push(IntegrityIssue::ArchiveUnreadable {
+                        cause: e.to_string(),
+                    });
+                    break;
+                }
+            };
+            for command in commands {
+                last_command = command.sequence();
+                if let StoredEffect::Events(versions) = command.effect() {
+                    for version in versions {
+                        if !event_versions.contains(version) {
+                            issues.push(IntegrityIssue::CommandEventMissing {
+                                key: AnyKeyStore::key_for_command(&command).to_string(),
+                                version: *version,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let crit = CommandHistoryCriteria::default();
+        for key in self.store.command_keys_ascending(id, &crit) {
+            last_command = key.sequence();
+            match self
+                .store
+                .get::<StoredCommand<A::StorableCommandDetails>>(id, &(&key).into())
+            {
+                Err(e) => issues.push(IntegrityIssue::CommandUnreadable {
+                    key: key.to_string(),
+                    cause: e.to_string(),
+                }),
+                Ok(None) => {}
+                Ok(Some(command)) => {
+                    if let StoredEffect::Events(versions) = command.effect() {
+                        for version in versions {
+                            if !event_versions.contains(version) {
+                                issues.push(IntegrityIssue::CommandEventMissing {
+                                    key: key.to_string(),
+                                    version: *version,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        match self.store.get_info(id) {
+            Err(e) => issues.push(IntegrityIssue::InfoUnreadable {
+                cause: e.to_string(),
+            }),
+            Ok(info) => {
+                let mut check = |field: &str, stored: u64, found: u64| {
+                    if stored != found {
+                        issues.push(IntegrityIssue::InfoInconsistent {
+                            field: field.to_string(),
+                            stored,
+                            found,
+                        });
+                    }
+                };
+                if replayed.is_some() {
+                    check("last_event", info.last_event, events_version - 1);
+                }
+                if info.snapshot_version != 0 {
+                    check(
+                        "snapshot_version",
+                        info.snapshot_version,
+                        snapshot_version.unwrap_or(0),
+                    );
+                }
+                check("last_command", info.last_command, last_command);
+            }
+        }
+
+        (issues, replayed, last_command)
+    }
+
+    /// Returns the versions of all events in the live history.
+    fn live_event_versions(&self, id: &Handle) -> Vec<u64> {
+        self.store
+            .keys(id, "delta-")
+            .iter()
+            .filter_map(|key| {
+                let name = key.to_string_lossy();
+                let version = name.strip_prefix("delta-")?.strip_suffix(".json")?;
+                u64::from_str(version).ok()
+            })
+            .collect()
+    }
+
+    /// Compares two instances of an aggregate by their json. Lists are
+    /// compared regardless of their order, because sets and maps may be
+    /// serialized in any order.
+    fn same_state(left: &A, right: &A) -> bool {
+        fn normalize(value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Array(values) => {
+                    values.iter_mut().for_each(normalize);
+                    values.sort_by_key(|v| v.to_string());
+                }
+                serde_json::Value::Object(map) => map.values_mut().for_each(normalize),
+                _ => {}
+            }
+        }
+
+        match (serde_json::to_value(left), serde_json::to_value(right)) {
+            (Ok(mut left), Ok(mut right)) => {
+                normalize(&mut left);
+                normalize(&mut right);
+                left == right
+            }
+            _ => false,
+        }
+    }
+
+    /// Rebuilds the snapshot and info of an aggregate from its replayed
+    /// events, and drops it from the cache so that it is reloaded.
+    fn repair_integrity(
+        &self,
+        id: &Handle,
+        issues: &[IntegrityIssue],
+        replayed: &A,
+        last_command: u64,
+    ) -> StoreResult<()> {
+        let mut info = self.store.get_info(id).unwrap_or_default();
+
+        let snapshot_issue = issues.iter().any(|issue| {
+            matches!(
+                issue,
+                IntegrityIssue::SnapshotUnreadable { .. }
+                    | IntegrityIssue::SnapshotAhead { .. }
+                    | IntegrityIssue::SnapshotMismatch { .. }
+            )
+        });
+        if snapshot_issue {
+            self.store.store_snapshot(id, replayed)?;
+            info.snapshot_version = replayed.version();
+        } else if issues.iter().any(|issue| match issue {
+            IntegrityIssue::InfoInconsistent { field, .. } => field == "snapshot_version",
+            _ => false,
+        }) {
+            info.snapshot_version = self
+                .store
+                .get::<A>(id, &AnyKeyStore::key_for_snapshot())?
+                .map(|snapshot| snapshot.version())
+                .unwrap_or(0);
+        }
+
+        info.last_event = replayed.version() - 1;
+        info.last_command = last_command;
+        self.store.save_info(id, &info)?;
+
+        self.cache.write().unwrap().remove(id);
+
+        Ok(())
+    }
+}
+
+/// # Archive
+///
+impl<A: Aggregate> DiskAggregateStore<A> {
+    /// Moves the commands processed before the given time, and the events
+    /// they resulted in, into the archive of the aggregate. Returns the
+    /// number of commands archived.
+    ///
+    /// Commands are archived in order of their sequence, up to the first
+    /// command at or after the given time. Each run adds a new chunk to the
+    /// archive, earlier chunks are left alone. The archive is saved before
+    /// anything is removed from the live history, so that an interrupted
+    /// run can be completed by the next.
+    fn archive_aggregate(&self, id: &Handle, before: Time) -> StoreResult<usize> {
+        let mut archive = self.store.get_archive(id)?;
+
+        let crit = CommandHistoryCriteria::default();
+        let keys: Vec<CommandKey> = self
+            .store
+            .command_keys_ascending(id, &crit)
+            .into_iter()
+            .take_while(|key| key.timestamp_secs() < before.timestamp())
+            .collect();
+
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        // Events can be archived up to the last event of the last command.
+        // Commands which were archived by an interrupted run are only removed.
+        let mut commands = vec![];
+        let mut until = archive.next_version();
+        for key in keys
+            .iter()
+            .filter(|k| k.sequence() > archive.last_sequence())
+        {
+            let command: StoredCommand<A::StorableCommandDetails> = self
+                .store
+                .get(id, &key.into())?
+                .ok_or(KeyStoreError::CommandNotFound)?;
+
+            if let StoredEffect::Events(versions) = command.effect() {
+                if let Some(last) = versions.iter().max() {
+                    until = until.max(last + 1);
+                }
+            }
+            commands.push(command);
+        }
+
+        let mut events = vec![];
+        for version in archive.next_version()..until {
+            match self.store.get_event::<A::Event>(id, version)? {
+                Some(event) => events.push(event),
+                None => {
+                    warn!(
+                        "Cannot archive history of '{}', event {} is missing",
+                        id, version
+                    );
+                    return Ok(0);
+                }
+            }
+        }
+
+        // Make sure that loading the aggregate does not need archived events.
+        let latest = self.get_latest_no_lock(id)?;
+        let mut info = self.store.get_info(id)?;
+        self.store.store_snapshot(id, latest.as_ref())?;
+        info.snapshot_version = latest.version();
+        self.store.save_info(id, &info)?;
+
+        if let (Some(first), Some(last)) = (commands.first(), commands.last()) {
+            let first = (first.sequence(), first.time());
+            let last = (last.sequence(), last.time());
+            if archive.add_chunk(until, first, last).is_some() {
+                self.store
+                    .store_archive_chunk::<A>(id, &archive, &commands, &events)?;
+            }
+        }
+
+        for key in keys.iter() {
+            self.store.drop(id, &key.into())?;
+        }
+        for version in self.live_event_versions(id) {
+            if version > 0 && version < archive.next_version() {
+                self.store.drop(id, &AnyKeyStore::key_for_event(version))?;
+            }
+        }
+
+        Ok(commands.len())
+    }
+}
+
 impl<A: Aggregate> AggregateStore<A> for DiskAggregateStore<A>
 where
     A::Error: From<AggregateStoreError>,
@@ -192,6 +619,63 @@ where
         self.get_latest_no_lock(handle)
     }
 
+    fn get_at_version(&self, id: &Handle, version: u64) -> StoreResult<A> {
+        let _lock = self.outer_lock.read().unwrap();
+        if !self.store.has_aggregate(id) {
+            return Err(AggregateStoreError::UnknownAggregate(id.clone()));
+        }
+
+        self.store
+            .get_aggregate_at(id, version)?
+            .ok_or_else(|| AggregateStoreError::UnknownVersion(id.clone(), version))
+    }
+
+    fn version_at(&self, id: &Handle, time: Time) -> StoreResult<Option<u64>> {
+        let _lock = self.outer_lock.read().unwrap();
+        if !self.store.has_aggregate(id) {
+            return Err(AggregateStoreError::UnknownAggregate(id.clone()));
+        }
+
+        let mut crit = CommandHistoryCriteria::default();
+        crit.set_before(time.timestamp());
+
+        // Find the last command up to this time which resulted in events.
+        for key in self.store.command_keys_ascending(id, &crit).iter().rev() {
+            let command: StoredCommand<A::StorableCommandDetails> = self
+                .store
+                .get(id, &key.into())?
+                .ok_or(KeyStoreError::CommandNotFound)?;
+
+            if let StoredEffect::Events(versions) = command.effect() {
+                if let Some(last) = versions.iter().max() {
+                    return Ok(Some(last + 1));
+                }
+            }
+        }
+
+        // Or else the last archived command up to this time, only decoding
+        // the chunks which can hold such commands.
+        let archive = self.store.get_archive(id)?;
+        for chunk in archive.chunks().iter().rev() {
+            if chunk.first_time().timestamp() > time.timestamp() {
+                continue;
+            }
+            let commands = self.store.get_archived_commands::<A>(id, chunk)?;
+            for command in commands.iter().rev() {
+                if command.time().timestamp() > time.timestamp() {
+                    continue;
+                }
+                if let StoredEffect::Events(versions) = command.effect() {
+                    if let Some(last) = versions.iter().max() {
+                        return Ok(Some(last + 1));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     fn add(&self, init: A::InitEvent) -> StoreResult<Arc<A>> {
         let _lock = self.outer_lock.write().unwrap();
 
@@ -241,11 +725,11 @@ where
         let stored_command_builder =
             StoredCommandBuilder::new(&cmd, latest.version(), info.last_command);
 
-        let res = match latest.process_command(cmd) {
+        match latest.process_command(cmd) {
             Err(e) => {
                 let stored_command = stored_command_builder.finish_with_error(&e);
                 self.store
-                    .store_command(stored_command)
+                    .store_update::<_, A::Event, A>(&handle, stored_command, &[], None, &info)
                     .map_err(AggregateStoreError::KeyStoreError)?;
                 Err(e)
             }
@@ -287,27 +771,25 @@ where
                         }
                     }
 
-                    // Time to start saving things.
-                    let stored_command =
-                        stored_command_builder.finish_with_events(events.as_slice());
-                    self.store
-                        .store_command(stored_command)
-                        .map_err(AggregateStoreError::KeyStoreError)?;
-
+                    let mut snapshot_due = false;
                     for event in &events {
-                        self.store
-                            .store_event(event)
-                            .map_err(AggregateStoreError::KeyStoreError)?;
-
                         agg.apply(event.clone());
                         if agg.version() % SNAPSHOT_FREQ == 0 {
-                            info.snapshot_version = agg.version();
-
-                            self.store
-                                .store_snapshot(&handle, agg)
-                                .map_err(AggregateStoreError::KeyStoreError)?;
+                            snapshot_due = true;
                         }
                     }
+                    if snapshot_due {
+                        info.snapshot_version = agg.version();
+                    }
+
+                    // Time to start saving things. The command, events, snapshot and info
+                    // are saved together, so that a failure cannot leave a partial update.
+                    let stored_command =
+                        stored_command_builder.finish_with_events(events.as_slice());
+                    let snapshot = if snapshot_due { Some(&*agg) } else { None };
+                    self.store
+                        .store_update(&handle, stored_command, &events, snapshot, &info)
+                        .map_err(AggregateStoreError::KeyStoreError)?;
 
                     cache.insert(handle.clone(), Arc::new(agg.clone()));
 
@@ -321,13 +803,7 @@ where
                     Ok(latest)
                 }
             }
-        };
-
-        self.store
-            .save_info(&handle, &info)
-            .map_err(AggregateStoreError::KeyStoreError)?;
-
-        res
+        }
     }
 
     fn has(&self, id: &Handle) -> bool {
@@ -360,9 +836,20 @@ where
         id: &Handle,
         key: &CommandKey,
     ) -> StoreResult<Option<StoredCommand<<A as Aggregate>::StorableCommandDetails>>> {
-        self.store
-            .get(id, &key.into())
-            .map_err(AggregateStoreError::KeyStoreError)
+        match self.store.get(id, &key.into())? {
+            Some(command) => Ok(Some(command)),
+            None => {
+                let archive = self.store.get_archive(id)?;
+                match archive.chunk_for_sequence(key.sequence()) {
+                    None => Ok(None),
+                    Some(chunk) => Ok(self
+                        .store
+                        .get_archived_commands::<A>(id, chunk)?
+                        .into_iter()
+                        .find(|command| command.sequence() == key.sequence())),
+                }
+            }
+        }
     }
 
     fn stored_event(
@@ -370,9 +857,64 @@ where
         id: &Handle,
         version: u64,
     ) -> StoreResult<Option<<A as Aggregate>::Event>> {
-        let key = DiskKeyStore::key_for_event(version);
-        self.store
-            .get(id, &key)
-            .map_err(AggregateStoreError::KeyStoreError)
+        let key = AnyKeyStore::key_for_event(version);
+        match self.store.get(id, &key)? {
+            Some(event) => Ok(Some(event)),
+            None => {
+                let archive = self.store.get_archive(id)?;
+                match archive.chunk_for_version(version) {
+                    None => Ok(None),
+                    Some(chunk) => Ok(self
+                        .store
+                        .get_archived_events::<A>(id, chunk)?
+                        .into_iter()
+                        .find(|event| event.version() == version)),
+                }
+            }
+        }
+    }
+
+    fn verify_integrity(&self, repair: bool) -> StoreResult<Vec<AggregateIntegrityReport>> {
+        let _lock = self.outer_lock.write().unwrap();
+
+        let mut reports = vec![];
+        for id in self.store.aggregates() {
+            let (issues, replayed, last_command) = self.check_integrity(&id);
+            let mut report = AggregateIntegrityReport::new(id.clone(), issues);
+
+            if repair && report.is_repairable() {
+                if let Some(replayed) = replayed {
+                    self.repair_integrity(&id, report.issues(), &replayed, last_command)?;
+                    info!("Repaired stored snapshot and info for '{}'", id);
+                    report.set_repaired();
+                }
+            }
+
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+
+    fn archive_history(&self, before: Time) -> StoreResult<usize> {
+        let _lock = self.outer_lock.write().unwrap();
+
+        let mut total = 0;
+        for id in self.store.aggregates() {
+            let archived = self.archive_aggregate(&id, before)?;
+            if archived > 0 {
+                info!("Archived {} commands in the history of '{}'", archived, id);
+            }
+            total += archived;
+        }
+
+        Ok(total)
+    }
+
+    fn backup<T, F: FnOnce() -> T>(&self, name: &str, with: F) -> StoreResult<(StoreBackup, T)> {
+        let _lock = self.outer_lock.read().unwrap();
+
+        let backup = StoreBackup::copy(name, &self.store)?;
+        Ok((backup, with()))
     }
 }
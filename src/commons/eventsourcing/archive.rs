@@ -0,0 +1,151 @@
+//! Archive for the old history of aggregates.
+
+use std::io;
+use std::io::{Read, Write};
+
+use libflate::gzip::{Decoder, Encoder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use rpki::x509::Time;
+
+//------------ HistoryArchive ------------------------------------------------
+
+/// The index of the commands and events of an aggregate which were moved out
+/// of its live history, so that they no longer slow down listing and loading
+/// the live history.
+///
+/// Each archive run adds a chunk, which keeps its commands and its events as
+/// separate compressed values. This way listing the archived commands does
+/// not require decoding any events, and earlier chunks never need to be
+/// rewritten.
+///
+/// The init event is never archived. Archived events are contiguous starting
+/// with version 1, and archived commands are ordered by their sequence.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryArchive {
+    chunks: Vec<ArchiveChunk>,
+}
+
+impl HistoryArchive {
+    /// The name under which the index is kept in the archive of an aggregate.
+    pub const INDEX: &'static str = "index";
+
+    pub fn chunks(&self) -> &Vec<ArchiveChunk> {
+        &self.chunks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The version of the first event which is not archived.
+    pub fn next_version(&self) -> u64 {
+        self.chunks.last().map(|c| c.next_version).unwrap_or(1)
+    }
+
+    /// The sequence of the last archived command, or 0 if there is none.
+    pub fn last_sequence(&self) -> u64 {
+        self.chunks.last().map(|c| c.last_sequence).unwrap_or(0)
+    }
+
+    /// The chunk holding the event with the given version, if it is archived.
+    pub fn chunk_for_version(&self, version: u64) -> Option<&ArchiveChunk> {
+        self.chunks
+            .iter()
+            .find(|c| c.first_version <= version && version < c.next_version)
+    }
+
+    /// The chunk holding the command with the given sequence, if it is
+    /// archived.
+    pub fn chunk_for_sequence(&self, sequence: u64) -> Option<&ArchiveChunk> {
+        self.chunks
+            .iter()
+            .find(|c| c.first_sequence <= sequence && sequence <= c.last_sequence)
+    }
+
+    /// Adds a chunk for the next events, starting at `next_version`, and
+    /// the commands with the given sequences and times. Returns None if
+    /// there are no commands, or if they are not all after the last
+    /// archived command.
+    pub fn add_chunk(
+        &mut self,
+        next_version: u64,
+        first: (u64, Time),
+        last: (u64, Time),
+    ) -> Option<&ArchiveChunk> {
+        if first.0 <= self.last_sequence() || last.0 < first.0 || next_version < self.next_version()
+        {
+            return None;
+        }
+
+        let chunk = ArchiveChunk {
+            number: self.chunks.len() as u64 + 1,
+            first_version: self.next_version(),
+            next_version,
+            first_sequence: first.0,
+            last_sequence: last.0,
+            first_time: first.1,
+            last_time: last.1,
+        };
+        self.chunks.push(chunk);
+        self.chunks.last()
+    }
+}
+
+//------------ ArchiveChunk --------------------------------------------------
+
+/// Describes the commands and events added to the archive in a single run.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchiveChunk {
+    number: u64,
+    first_version: u64,
+    next_version: u64,
+    first_sequence: u64,
+    last_sequence: u64,
+    first_time: Time,
+    last_time: Time,
+}
+
+impl ArchiveChunk {
+    /// The name under which the commands of this chunk are kept.
+    pub fn commands_name(&self) -> String {
+        format!("commands-{}", self.number)
+    }
+
+    /// The name under which the events of this chunk are kept.
+    pub fn events_name(&self) -> String {
+        format!("events-{}", self.number)
+    }
+
+    /// The versions of the events in this chunk, which may be none.
+    pub fn versions(&self) -> std::ops::Range<u64> {
+        self.first_version..self.next_version
+    }
+
+    pub fn first_time(&self) -> Time {
+        self.first_time
+    }
+
+    pub fn last_time(&self) -> Time {
+        self.last_time
+    }
+}
+
+//------------ Encoding ------------------------------------------------------
+
+/// Returns the value as gzipped json.
+pub fn encode<V: Serialize>(value: &V) -> io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(value)?;
+    let mut encoder = Encoder::new(vec![])?;
+    encoder.write_all(&json)?;
+    encoder.finish().into_result()
+}
+
+/// Parses a value from gzipped json.
+pub fn decode<V: DeserializeOwned>(bytes: &[u8]) -> io::Result<V> {
+    let mut json = vec![];
+    let mut decoder = Decoder::new(bytes)?;
+    decoder.read_to_end(&mut json)?;
+    serde_json::from_slice(&json).map_err(io::Error::from)
+}
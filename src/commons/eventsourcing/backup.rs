@@ -0,0 +1,130 @@
+//! Backup and restore of all aggregates in a key store.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::commons::api::{Base64, Handle};
+use crate::commons::eventsourcing::{
+    AggregateStoreError, AnyKeyStore, KeyStore, KeyStoreError, KeyStoreVersion, StorageType,
+};
+
+//------------ StoreBackup ---------------------------------------------------
+
+/// A copy of all values kept for the aggregates in a key store, e.g. "cas".
+///
+/// Values are kept by their key names, which are the same for all storage
+/// types, so that a backup can be restored into a store of another type.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StoreBackup {
+    name: String,
+    version: KeyStoreVersion,
+    aggregates: Vec<AggregateBackup>,
+}
+
+impl StoreBackup {
+    /// Copies all values from the store. The caller must make sure that no
+    /// commands are processed while this is done.
+    pub fn copy(name: &str, store: &AnyKeyStore) -> Result<Self, KeyStoreError> {
+        let version = store.get_version()?;
+
+        let mut aggregates = vec![];
+        for handle in store.aggregates() {
+            let mut values = BTreeMap::new();
+            for key in store.keys(&handle, ".json") {
+                let name = key.to_string_lossy().to_string();
+                if !name.ends_with(".json") {
+                    continue;
+                }
+                if let Some(value) = store.get::<serde_json::Value>(&handle, &key)? {
+                    values.insert(name, value);
+                }
+            }
+
+            let mut archive = BTreeMap::new();
+            for name in store.archive_names(&handle)? {
+                if let Some(data) = store.get_archive_data(&handle, &name)? {
+                    archive.insert(name, Base64::from_content(&data));
+                }
+            }
+
+            aggregates.push(AggregateBackup {
+                handle,
+                values,
+                archive,
+            });
+        }
+
+        Ok(StoreBackup {
+            name: name.to_string(),
+            version,
+            aggregates,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &KeyStoreVersion {
+        &self.version
+    }
+
+    pub fn aggregates(&self) -> &Vec<AggregateBackup> {
+        &self.aggregates
+    }
+
+    /// Writes all values into the store with the same name under the work
+    /// dir. The backup must have been made by this version of Krill, and
+    /// the store must not contain any aggregates yet.
+    pub fn restore(
+        &self,
+        work_dir: &PathBuf,
+        storage_type: StorageType,
+    ) -> Result<(), AggregateStoreError> {
+        if self.version != KeyStoreVersion::current() {
+            return Err(AggregateStoreError::UnsupportedVersion(
+                self.name.clone(),
+                self.version.clone(),
+            ));
+        }
+
+        let store = AnyKeyStore::under_work_dir(work_dir, &self.name, storage_type)?;
+        if !store.aggregates().is_empty() {
+            return Err(AggregateStoreError::NotEmpty(self.name.clone()));
+        }
+
+        store.set_version(&self.version)?;
+        for aggregate in self.aggregates.iter() {
+            for (key, value) in aggregate.values.iter() {
+                store.store(&aggregate.handle, &PathBuf::from(key), value)?;
+            }
+            for (name, data) in aggregate.archive.iter() {
+                store.store_archive_data(&aggregate.handle, name, &data.to_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//------------ AggregateBackup -----------------------------------------------
+
+/// All values kept for a single aggregate, and the compressed values in its
+/// archive by name.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AggregateBackup {
+    handle: Handle,
+    values: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    archive: BTreeMap<String, Base64>,
+}
+
+impl AggregateBackup {
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn values(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.values
+    }
+}
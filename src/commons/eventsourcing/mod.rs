@@ -9,10 +9,16 @@ pub use self::evt::{Event, StoredEvent};
 mod cmd;
 pub use self::cmd::{Command, CommandDetails, SentCommand, StoredCommand, WithStorableDetails};
 
+mod archive;
+pub use self::archive::{ArchiveChunk, HistoryArchive};
+
+mod backup;
+pub use self::backup::{AggregateBackup, StoreBackup};
+
 mod store;
 pub use self::store::{
-    CommandKey, CommandKeyError, DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion, Storable,
-    StoredValueInfo,
+    AnyKeyStore, CommandKey, CommandKeyError, DiskKeyStore, KeyStore, KeyStoreError,
+    KeyStoreVersion, SqlKeyStore, Storable, StorageType, StoredValueInfo,
 };
 
 mod agg_store;
@@ -36,7 +42,12 @@ mod tests {
 
     use serde::Serialize;
 
-    use crate::commons::api::{CommandHistoryCriteria, CommandSummary, Handle};
+    use rpki::x509::Time;
+
+    use crate::commons::api::{
+        CommandHistory, CommandHistoryCriteria, CommandSummary, Handle, IntegrityIssue,
+    };
+    use crate::commons::util::file;
     use crate::test;
 
     use super::*;
@@ -278,11 +289,11 @@ mod tests {
         }
     }
 
-    #[test]
-    fn event_sourcing_framework() {
+    fn event_sourcing_framework_with(storage_type: StorageType) {
         test::test_under_tmp(|d| {
             let counter = Arc::new(EventCounter::default());
-            let mut manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let mut manager =
+                DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
             manager.add_listener(counter.clone());
 
             let id_alice = unsafe { Handle::from_str_unsafe("alice") };
@@ -314,7 +325,7 @@ mod tests {
             assert_eq!(21, alice.age());
 
             // Should read state from disk
-            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
 
             let alice = manager.get_latest(&id_alice).unwrap();
             assert_eq!("alice smith-doe", alice.name());
@@ -322,6 +333,16 @@ mod tests {
 
             assert_eq!(22, counter.total());
 
+            // Rebuild earlier versions from history
+            let young_alice = manager.get_at_version(&id_alice, 11).unwrap();
+            assert_eq!("alice smith", young_alice.name());
+            assert_eq!(10, young_alice.age());
+            assert!(manager.get_at_version(&id_alice, 0).is_err());
+            assert!(manager.get_at_version(&id_alice, 24).is_err());
+
+            let version_now = manager.version_at(&id_alice, Time::now()).unwrap();
+            assert_eq!(Some(alice.version()), version_now);
+
             // Get paginated history
             let mut crit = CommandHistoryCriteria::default();
             crit.set_offset(3);
@@ -338,6 +359,271 @@ mod tests {
             crit.set_exclude(&["person-around-sun"]);
             let history = manager.command_history(&id_alice, crit).unwrap();
             assert_eq!(history.total(), 1);
+
+            // Archive all history, it should still be used for history and replays
+            let tomorrow = Time::now() + chrono::Duration::days(1);
+            assert_eq!(22, manager.archive_history(tomorrow).unwrap());
+            assert_eq!(0, manager.archive_history(tomorrow).unwrap());
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!("alice smith-doe", alice.name());
+            assert_eq!(21, alice.age());
+
+            let young_alice = manager.get_at_version(&id_alice, 11).unwrap();
+            assert_eq!(10, young_alice.age());
+            let version_now = manager.version_at(&id_alice, Time::now()).unwrap();
+            assert_eq!(Some(alice.version()), version_now);
+            assert!(manager.stored_event(&id_alice, 5).unwrap().is_some());
+
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            let alice = manager.command(get_older).unwrap();
+            assert_eq!(22, alice.age());
+
+            let crit = CommandHistoryCriteria::default();
+            let history = manager.command_history(&id_alice, crit).unwrap();
+            assert_eq!(history.total(), 23);
+            assert_eq!(history.commands().last().unwrap().sequence, 23);
+
+            let key = history.commands().first().unwrap().command_key().unwrap();
+            assert!(manager.stored_command(&id_alice, &key).unwrap().is_some());
+
+            assert!(manager.verify_integrity(false).unwrap()[0].is_clean());
+
+            // Archive again, this adds the new command in its own chunk
+            assert_eq!(1, manager.archive_history(tomorrow).unwrap());
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
+            let alice = manager.get_at_version(&id_alice, alice.version()).unwrap();
+            assert_eq!(22, alice.age());
+            assert!(manager
+                .stored_event(&id_alice, alice.version() - 1)
+                .unwrap()
+                .is_some());
+
+            let crit = CommandHistoryCriteria::default();
+            let history = manager.command_history(&id_alice, crit).unwrap();
+            assert_eq!(history.total(), 23);
+            let key = history.commands().last().unwrap().command_key().unwrap();
+            assert!(manager.stored_command(&id_alice, &key).unwrap().is_some());
+
+            assert!(manager.verify_integrity(false).unwrap()[0].is_clean());
+        })
+    }
+
+    #[test]
+    fn event_sourcing_framework() {
+        event_sourcing_framework_with(StorageType::Disk)
+    }
+
+    #[test]
+    fn event_sourcing_framework_sqlite() {
+        event_sourcing_framework_with(StorageType::Sqlite)
+    }
+
+    fn archive_history_with(storage_type: StorageType) {
+        test::test_under_tmp(|d| {
+            let tomorrow = Time::now() + chrono::Duration::days(1);
+            let crit = CommandHistoryCriteria::default;
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+            for _ in 0..5 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let latest = manager.get_latest(&id_alice).unwrap();
+            let at_3 = manager.get_at_version(&id_alice, 3).unwrap();
+            let history = manager.command_history(&id_alice, crit()).unwrap();
+            let first_key = history.commands().first().unwrap().command_key().unwrap();
+
+            // The first run moves all commands and events into chunk 1
+            assert_eq!(5, manager.archive_history(tomorrow).unwrap());
+
+            let store = AnyKeyStore::under_work_dir(&d, "person", storage_type).unwrap();
+            let archive = store.get_archive(&id_alice).unwrap();
+            assert_eq!(1, archive.chunks().len());
+            assert_eq!(1..6, archive.chunks()[0].versions());
+            assert!(store.command_keys_ascending(&id_alice, &crit()).is_empty());
+            assert!(store
+                .get_event::<PersonEvent>(&id_alice, 1)
+                .unwrap()
+                .is_none());
+
+            // Without cached aggregates, state and history come from the archive
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
+            let archived_latest = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(latest.version(), archived_latest.version());
+            assert_eq!(latest.age(), archived_latest.age());
+            let archived_at_3 = manager.get_at_version(&id_alice, 3).unwrap();
+            assert_eq!(at_3.version(), archived_at_3.version());
+            assert_eq!(at_3.age(), archived_at_3.age());
+
+            let archived_history = manager.command_history(&id_alice, crit()).unwrap();
+            assert_eq!(history.total(), archived_history.total());
+            let sequences = |h: &CommandHistory| -> Vec<u64> {
+                h.commands().iter().map(|c| c.sequence).collect()
+            };
+            assert_eq!(sequences(&history), sequences(&archived_history));
+
+            assert!(manager
+                .stored_command(&id_alice, &first_key)
+                .unwrap()
+                .is_some());
+            let event = manager.stored_event(&id_alice, 1).unwrap().unwrap();
+            assert_eq!(1, event.version());
+
+            // A second run adds chunk 2 with only the new command and event
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            manager.command(get_older).unwrap();
+            assert_eq!(1, manager.archive_history(tomorrow).unwrap());
+
+            let archive = store.get_archive(&id_alice).unwrap();
+            assert_eq!(2, archive.chunks().len());
+            assert_eq!("commands-2", archive.chunks()[1].commands_name());
+            assert_eq!(6..7, archive.chunks()[1].versions());
+            let event = manager.stored_event(&id_alice, 6).unwrap().unwrap();
+            assert_eq!(6, event.version());
+            let history = manager.command_history(&id_alice, crit()).unwrap();
+            assert_eq!(6, history.total());
+            let last_key = history.commands().last().unwrap().command_key().unwrap();
+            assert!(manager
+                .stored_command(&id_alice, &last_key)
+                .unwrap()
+                .is_some());
+
+            // A run interrupted after saving the archive index, but before
+            // removing the live command and event, is finished by the next.
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            manager.command(get_older).unwrap();
+            let command_key = store.command_keys_ascending(&id_alice, &crit())[0].clone();
+            let command_key = (&command_key).into();
+            let event_key = AnyKeyStore::key_for_event(7);
+            let command: serde_json::Value = store.get(&id_alice, &command_key).unwrap().unwrap();
+            let event: serde_json::Value = store.get(&id_alice, &event_key).unwrap().unwrap();
+
+            assert_eq!(1, manager.archive_history(tomorrow).unwrap());
+            store.store(&id_alice, &command_key, &command).unwrap();
+            store.store(&id_alice, &event_key, &event).unwrap();
+
+            assert_eq!(0, manager.archive_history(tomorrow).unwrap());
+            assert_eq!(3, store.get_archive(&id_alice).unwrap().chunks().len());
+            assert!(store.command_keys_ascending(&id_alice, &crit()).is_empty());
+            assert!(store
+                .get_event::<PersonEvent>(&id_alice, 7)
+                .unwrap()
+                .is_none());
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person", storage_type).unwrap();
+            assert_eq!(7, manager.get_latest(&id_alice).unwrap().age());
+            let history = manager.command_history(&id_alice, crit()).unwrap();
+            assert_eq!(7, history.total());
+            assert!(manager.verify_integrity(false).unwrap()[0].is_clean());
+        })
+    }
+
+    #[test]
+    fn archive_history() {
+        archive_history_with(StorageType::Disk)
+    }
+
+    #[test]
+    fn archive_history_sqlite() {
+        archive_history_with(StorageType::Sqlite)
+    }
+
+    #[test]
+    fn refuse_to_switch_storage_type() {
+        test::test_under_tmp(|d| {
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            {
+                let manager =
+                    DiskAggregateStore::<Person>::new(&d, "person", StorageType::Disk).unwrap();
+                manager
+                    .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                    .unwrap();
+            }
+
+            match DiskAggregateStore::<Person>::new(&d, "person", StorageType::Sqlite) {
+                Err(AggregateStoreError::KeyStoreError(KeyStoreError::StorageTypeMismatch(
+                    name,
+                    StorageType::Disk,
+                ))) => assert_eq!(name, "person"),
+                _ => panic!("Expected storage type mismatch"),
+            }
+
+            // an empty store of the other type is no problem
+            {
+                let manager =
+                    DiskAggregateStore::<Person>::new(&d, "other", StorageType::Sqlite).unwrap();
+                manager
+                    .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                    .unwrap();
+            }
+            assert!(DiskAggregateStore::<Person>::new(&d, "other", StorageType::Disk).is_err());
+        })
+    }
+
+    #[test]
+    fn verify_and_repair_integrity() {
+        test::test_under_tmp(|d| {
+            let manager =
+                DiskAggregateStore::<Person>::new(&d, "person", StorageType::Disk).unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+            for _ in 0..12 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let reports = manager.verify_integrity(false).unwrap();
+            assert_eq!(1, reports.len());
+            assert!(reports[0].is_clean());
+
+            let mut alice_dir = d.clone();
+            alice_dir.push("person");
+            alice_dir.push("alice");
+
+            let path = |name: &str| {
+                let mut path = alice_dir.clone();
+                path.push(name);
+                path
+            };
+
+            // A broken snapshot and info can be rebuilt from the events.
+            file::save(b"{ broken", &path("snapshot.json")).unwrap();
+            let mut info: StoredValueInfo =
+                serde_json::from_slice(&file::read(&path("info.json")).unwrap()).unwrap();
+            info.last_command = 3;
+            file::save(&serde_json::to_vec(&info).unwrap(), &path("info.json")).unwrap();
+
+            let reports = manager.verify_integrity(false).unwrap();
+            assert_eq!(2, reports[0].issues().len());
+            assert!(reports[0].is_repairable());
+
+            let reports = manager.verify_integrity(true).unwrap();
+            assert!(reports[0].repaired());
+            assert!(manager.verify_integrity(false).unwrap()[0].is_clean());
+
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(12, alice.age());
+
+            // A missing event cannot be repaired.
+            std::fs::remove_file(path("delta-5.json")).unwrap();
+            let reports = manager.verify_integrity(true).unwrap();
+            assert!(!reports[0].is_repairable());
+            assert!(!reports[0].repaired());
+            assert!(reports[0].issues().contains(&IntegrityIssue::EventMissing {
+                missing: 5,
+                found: 12
+            }));
         })
     }
 }
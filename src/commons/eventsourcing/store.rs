@@ -5,18 +5,24 @@ use std::io;
 use std::io::Write;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::{fmt, fs};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+
 use rpki::x509::Time;
 
 use crate::commons::api::{
     CommandHistory, CommandHistoryCriteria, CommandHistoryRecord, Handle, Label,
 };
-use crate::commons::eventsourcing::{Aggregate, Event, StoredCommand, WithStorableDetails};
+use crate::commons::eventsourcing::archive::{self, ArchiveChunk};
+use crate::commons::eventsourcing::{
+    Aggregate, Event, HistoryArchive, StoredCommand, WithStorableDetails,
+};
 use crate::commons::util::file;
 
 //------------ Storable ------------------------------------------------------
@@ -48,6 +54,14 @@ pub enum KeyStoreVersion {
     Pre0_6,
     V0_6,
     V0_7,
+    V0_8,
+}
+
+impl KeyStoreVersion {
+    /// The version of the stores used by this version of Krill.
+    pub fn current() -> Self {
+        KeyStoreVersion::V0_8
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -66,6 +80,14 @@ impl CommandKey {
         }
     }
 
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn timestamp_secs(&self) -> i64 {
+        self.timestamp_secs
+    }
+
     pub fn matches_crit(&self, crit: &CommandHistoryCriteria) -> bool {
         crit.matches_timestamp_secs(self.timestamp_secs) && crit.matches_label(&self.label)
     }
@@ -132,6 +154,27 @@ impl TryFrom<PathBuf> for CommandKey {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CommandKeyError;
 
+//------------ StorageType ---------------------------------------------------
+
+/// The type of storage used for the event sourced aggregates.
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageType {
+    /// One json file for each event, command, snapshot and info.
+    #[display(fmt = "disk")]
+    Disk,
+
+    /// An SQLite database for each type of aggregate.
+    #[display(fmt = "sqlite")]
+    Sqlite,
+}
+
+impl Default for StorageType {
+    fn default() -> Self {
+        StorageType::Disk
+    }
+}
+
 //------------ KeyStore ------------------------------------------------------
 
 /// Generic KeyStore for AggregateManager
@@ -210,6 +253,162 @@ pub trait KeyStore {
     fn store_snapshot<V: Aggregate>(&self, id: &Handle, aggregate: &V)
         -> Result<(), KeyStoreError>;
 
+    /// Returns the names of all values in the archive of an aggregate.
+    fn archive_names(&self, id: &Handle) -> Result<Vec<String>, KeyStoreError>;
+
+    /// Returns a compressed value from the archive of an aggregate, if any.
+    fn get_archive_data(&self, id: &Handle, name: &str) -> Result<Option<Vec<u8>>, KeyStoreError>;
+
+    /// Saves a compressed value in the archive of an aggregate - overwrites
+    /// any previous value with the same name.
+    fn store_archive_data(&self, id: &Handle, name: &str, data: &[u8])
+        -> Result<(), KeyStoreError>;
+
+    /// Returns the index of the archive. The index is empty if nothing was
+    /// archived yet.
+    fn get_archive(&self, id: &Handle) -> Result<HistoryArchive, KeyStoreError> {
+        match self.get_archive_data(id, HistoryArchive::INDEX)? {
+            None => Ok(HistoryArchive::default()),
+            Some(data) => Ok(archive::decode(&data)?),
+        }
+    }
+
+    /// Returns the commands kept in an archived chunk.
+    fn get_archived_commands<V: Aggregate>(
+        &self,
+        id: &Handle,
+        chunk: &ArchiveChunk,
+    ) -> Result<Vec<StoredCommand<V::StorableCommandDetails>>, KeyStoreError> {
+        self.get_archived(id, &chunk.commands_name())
+    }
+
+    /// Returns the events kept in an archived chunk.
+    fn get_archived_events<V: Aggregate>(
+        &self,
+        id: &Handle,
+        chunk: &ArchiveChunk,
+    ) -> Result<Vec<V::Event>, KeyStoreError> {
+        self.get_archived(id, &chunk.events_name())
+    }
+
+    fn get_archived<T: DeserializeOwned>(
+        &self,
+        id: &Handle,
+        name: &str,
+    ) -> Result<T, KeyStoreError> {
+        match self.get_archive_data(id, name)? {
+            None => Err(KeyStoreError::KeyUnknown(name.to_string())),
+            Some(data) => Ok(archive::decode(&data)?),
+        }
+    }
+
+    /// Saves the commands and events of the last chunk in the archive, and
+    /// then the index. If this is interrupted the index still refers to the
+    /// earlier chunks only, and the next run overwrites any saved values.
+    fn store_archive_chunk<V: Aggregate>(
+        &self,
+        id: &Handle,
+        archive: &HistoryArchive,
+        commands: &[StoredCommand<V::StorableCommandDetails>],
+        events: &[V::Event],
+    ) -> Result<(), KeyStoreError> {
+        if let Some(chunk) = archive.chunks().last() {
+            self.store_archive_data(id, &chunk.commands_name(), &archive::encode(&commands)?)?;
+            self.store_archive_data(id, &chunk.events_name(), &archive::encode(&events)?)?;
+        }
+        self.store_archive_data(id, HistoryArchive::INDEX, &archive::encode(archive)?)
+    }
+
+    /// Saves everything resulting from processing a command: the command,
+    /// its events, the snapshot if one is due, and the updated info.
+    ///
+    /// Stores which support transactions should override this, so that a
+    /// failure cannot leave a command partially saved.
+    fn store_update<S: WithStorableDetails, E: Event, V: Aggregate>(
+        &self,
+        id: &Handle,
+        command: StoredCommand<S>,
+        events: &[E],
+        snapshot: Option<&V>,
+        info: &StoredValueInfo,
+    ) -> Result<(), KeyStoreError> {
+        self.store_command(command)?;
+        for event in events {
+            self.store_event(event)?;
+        }
+        if let Some(aggregate) = snapshot {
+            self.store_snapshot(id, aggregate)?;
+        }
+        self.save_info(id, info)
+    }
+
+    /// Applies all events after the current version of the aggregate.
+    fn update_aggregate<V: Aggregate>(
+        &self,
+        id: &Handle,
+        aggregate: &mut V,
+    ) -> Result<(), KeyStoreError> {
+        while let Some(e) = self.get_event(id, aggregate.version())? {
+            aggregate.apply(e);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the aggregate by replaying its events from the init event,
+    /// using the archived events first. Stops at the given version, if any,
+    /// or else after the last event.
+    fn replay_aggregate<V: Aggregate>(
+        &self,
+        id: &Handle,
+        version: Option<u64>,
+    ) -> Result<Option<V>, KeyStoreError> {
+        let mut aggregate = match self.get_event::<V::InitEvent>(id, 0)? {
+            Some(e) => V::init(e).map_err(|_| KeyStoreError::InitError)?,
+            None => return Ok(None),
+        };
+
+        let until = version.unwrap_or(u64::MAX);
+        let archive = self.get_archive(id)?;
+
+        for chunk in archive.chunks() {
+            if aggregate.version() >= until {
+                break;
+            }
+            for e in self.get_archived_events::<V>(id, chunk)? {
+                if aggregate.version() >= until {
+                    break;
+                }
+                aggregate.apply(e);
+            }
+        }
+
+        while aggregate.version() < until {
+            match self.get_event(id, aggregate.version())? {
+                Some(e) => aggregate.apply(e),
+                None => break,
+            }
+        }
+
+        Ok(Some(aggregate))
+    }
+
+    /// Rebuilds the aggregate as it was at the given version by replaying
+    /// its events from the init event. Returns None if the aggregate never
+    /// had this version.
+    fn get_aggregate_at<V: Aggregate>(
+        &self,
+        id: &Handle,
+        version: u64,
+    ) -> Result<Option<V>, KeyStoreError> {
+        if version == 0 {
+            return Ok(None);
+        }
+
+        Ok(self
+            .replay_aggregate::<V>(id, Some(version))?
+            .filter(|aggregate| aggregate.version() == version))
+    }
+
     /// Find all commands that fit the criteria and return history
     fn command_history<A: Aggregate>(
         &self,
@@ -223,7 +422,26 @@ pub trait KeyStore {
         let mut skipped = 0;
         let mut total = 0;
 
+        // Archived commands come first, they are older than all live commands.
+        // Only the commands are decoded, the archived events are not needed.
+        let archive = self.get_archive(id)?;
+        for chunk in archive.chunks() {
+            for stored in self.get_archived_commands::<A>(id, chunk)? {
+                if Self::key_for_command(&stored).matches_crit(&crit) {
+                    total += 1;
+                    if skipped < offset {
+                        skipped += 1;
+                    } else if commands.len() < rows {
+                        commands.push(stored.into());
+                    }
+                }
+            }
+        }
+
         for key in self.command_keys_ascending(id, &crit) {
+            if key.sequence() <= archive.last_sequence() {
+                continue; // already archived, but not yet removed
+            }
             total += 1;
             if skipped < offset {
                 skipped += 1;
@@ -251,6 +469,9 @@ pub enum KeyStoreError {
     #[display(fmt = "{}", _0)]
     JsonError(serde_json::Error),
 
+    #[display(fmt = "{}", _0)]
+    SqlError(rusqlite::Error),
+
     #[display(fmt = "Key '{}' already exists", _0)]
     KeyExists(String),
 
@@ -271,6 +492,13 @@ pub enum KeyStoreError {
 
     #[display(fmt = "StoredCommand offset out of bounds")]
     CommandOffSetError,
+
+    #[display(
+        fmt = "Store '{}' already holds data using storage type '{}', refusing to use another storage type",
+        _0,
+        _1
+    )]
+    StorageTypeMismatch(String, StorageType),
 }
 
 impl From<io::Error> for KeyStoreError {
@@ -285,10 +513,22 @@ impl From<serde_json::Error> for KeyStoreError {
     }
 }
 
+impl From<rusqlite::Error> for KeyStoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        KeyStoreError::SqlError(e)
+    }
+}
+
 impl std::error::Error for KeyStoreError {}
 
 //------------ DiskKeyStore --------------------------------------------------
 
+/// The directory of an aggregate that holds its archive.
+const ARCHIVE_DIR: &str = "archive";
+
+/// The extension of the compressed values in the archive.
+const ARCHIVE_EXTENSION: &str = ".json.gz";
+
 /// This type can store and retrieve values to/from disk, using json
 /// serialization.
 pub struct DiskKeyStore {
@@ -518,15 +758,12 @@ impl KeyStore for DiskKeyStore {
 
     fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError> {
         // try to get a snapshot.
-        // If that fails, try to get the init event.
+        // If that fails, replay the archived events from the init event.
         // Then replay all newer events that can be found.
         let key = Self::key_for_snapshot();
         let aggregate_opt = match self.get::<V>(id, &key)? {
             Some(aggregate) => Some(aggregate),
-            None => match self.get_event::<V::InitEvent>(id, 0)? {
-                Some(e) => Some(V::init(e).map_err(|_| KeyStoreError::InitError)?),
-                None => None,
-            },
+            None => self.replay_aggregate(id, None)?,
         };
 
         match aggregate_opt {
@@ -546,6 +783,39 @@ impl KeyStore for DiskKeyStore {
         let key = Self::key_for_snapshot();
         self.store(id, &key, aggregate)
     }
+
+    fn archive_names(&self, id: &Handle) -> Result<Vec<String>, KeyStoreError> {
+        let mut res = vec![];
+        let dir = self.archive_dir(id);
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let file_name = entry?.file_name().to_string_lossy().to_string();
+                if let Some(name) = file_name.strip_suffix(ARCHIVE_EXTENSION) {
+                    res.push(name.to_string());
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn get_archive_data(&self, id: &Handle, name: &str) -> Result<Option<Vec<u8>>, KeyStoreError> {
+        let path = self.archive_path(id, name);
+        if path.exists() {
+            Ok(Some(file::read(&path)?.to_vec()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store_archive_data(
+        &self,
+        id: &Handle,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), KeyStoreError> {
+        file::save(data, &self.archive_path(id, name))?;
+        Ok(())
+    }
 }
 
 impl DiskKeyStore {
@@ -583,20 +853,816 @@ impl DiskKeyStore {
         dir_path
     }
 
+    fn archive_dir(&self, id: &Handle) -> PathBuf {
+        let mut dir_path = self.dir_for_aggregate(id);
+        dir_path.push(ARCHIVE_DIR);
+        dir_path
+    }
+
+    fn archive_path(&self, id: &Handle, name: &str) -> PathBuf {
+        let mut file_path = self.archive_dir(id);
+        file_path.push(format!("{}{}", name, ARCHIVE_EXTENSION));
+        file_path
+    }
+
     fn path_for_event(&self, id: &Handle, version: u64) -> PathBuf {
         let mut file_path = self.dir_for_aggregate(id);
         file_path.push(format!("delta-{}.json", version));
         file_path
     }
+}
+
+//------------ SqlKeyStore ---------------------------------------------------
+
+const SQL_DB_FILE: &str = "keystore.sqlite";
+
+const SQL_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        name        TEXT PRIMARY KEY,
+        value       TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS events (
+        handle      TEXT NOT NULL,
+        version     INTEGER NOT NULL,
+        value       TEXT NOT NULL,
+        PRIMARY KEY (handle, version)
+    );
+    CREATE TABLE IF NOT EXISTS commands (
+        handle      TEXT NOT NULL,
+        sequence    INTEGER NOT NULL,
+        timestamp   INTEGER NOT NULL,
+        label       TEXT NOT NULL,
+        value       TEXT NOT NULL,
+        PRIMARY KEY (handle, sequence)
+    );
+    CREATE INDEX IF NOT EXISTS commands_timestamp ON commands (handle, timestamp);
+    CREATE TABLE IF NOT EXISTS key_values (
+        handle      TEXT NOT NULL,
+        name        TEXT NOT NULL,
+        value       TEXT NOT NULL,
+        PRIMARY KEY (handle, name)
+    );
+    CREATE TABLE IF NOT EXISTS archive_values (
+        handle      TEXT NOT NULL,
+        name        TEXT NOT NULL,
+        value       BLOB NOT NULL,
+        PRIMARY KEY (handle, name)
+    );
+";
+
+/// The tables used for the keys of a KeyStore. Keys use the same names as
+/// in the DiskKeyStore, so that both can be used by the same code.
+enum SqlKey {
+    Event(u64),
+    Command(CommandKey),
+    Value(String),
+}
+
+impl From<&PathBuf> for SqlKey {
+    fn from(key: &PathBuf) -> Self {
+        if let Ok(command_key) = CommandKey::try_from(key.clone()) {
+            return SqlKey::Command(command_key);
+        }
+
+        let name = key.to_string_lossy().to_string();
+        if name.starts_with("delta-") && name.ends_with(".json") {
+            if let Ok(version) = u64::from_str(&name[6..name.len() - 5]) {
+                return SqlKey::Event(version);
+            }
+        }
+
+        SqlKey::Value(name)
+    }
+}
+
+/// This type stores and retrieves values in an SQLite database, using json
+/// serialization. Events and commands are kept in their own tables, so
+/// that they can be found with indexed queries, and all values saved for a
+/// command are saved in a single transaction.
+pub struct SqlKeyStore {
+    conn: Mutex<Connection>,
+}
+
+impl KeyStore for SqlKeyStore {
+    type Key = PathBuf;
+
+    fn get_version(&self) -> Result<KeyStoreVersion, KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE name = 'version'",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match json {
+            None => Err(KeyStoreError::NotInitialised),
+            Some(json) => Ok(serde_json::from_str(&json)?),
+        }
+    }
+
+    fn set_version(&self, version: &KeyStoreVersion) -> Result<(), KeyStoreError> {
+        let json = serde_json::to_string(version)?;
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO meta (name, value) VALUES ('version', ?1)",
+            params![json],
+        )?;
+        Ok(())
+    }
+
+    fn key_for_info() -> Self::Key {
+        DiskKeyStore::key_for_info()
+    }
+
+    fn key_for_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_snapshot()
+    }
+
+    fn key_for_event(version: u64) -> Self::Key {
+        DiskKeyStore::key_for_event(version)
+    }
+
+    fn key_for_command<S: WithStorableDetails>(command: &StoredCommand<S>) -> CommandKey {
+        DiskKeyStore::key_for_command(command)
+    }
+
+    fn keys(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        let conn = self.conn.lock().unwrap();
+        let handle = id.as_str();
+        let mut res = vec![];
+
+        let mut names = vec![];
+        let queries = [
+            "SELECT 'delta-' || version || '.json' FROM events WHERE handle = ?1",
+            "SELECT 'command--' || timestamp || '--' || sequence || '--' || label || '.json' \
+             FROM commands WHERE handle = ?1",
+            "SELECT name FROM key_values WHERE handle = ?1",
+        ];
+        for query in queries.iter() {
+            if let Ok(mut stmt) = conn.prepare(query) {
+                if let Ok(rows) = stmt.query_map(params![handle], |row| row.get::<_, String>(0)) {
+                    names.extend(rows.filter_map(Result::ok));
+                }
+            }
+        }
+
+        for name in names {
+            if name.contains(matching) {
+                res.push(PathBuf::from(name));
+            }
+        }
+
+        res
+    }
+
+    fn keys_ascending(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        let mut res = self.keys(id, matching);
+        res.sort_by(|a, b| a.to_string_lossy().cmp(&b.to_string_lossy()));
+        res
+    }
+
+    fn command_keys_ascending(
+        &self,
+        id: &Handle,
+        crit: &CommandHistoryCriteria,
+    ) -> Vec<CommandKey> {
+        let conn = self.conn.lock().unwrap();
+
+        let after = crit.after().unwrap_or(i64::MIN);
+        let before = crit.before().unwrap_or(i64::MAX);
+
+        let mut stmt = match conn.prepare(
+            "SELECT sequence, timestamp, label FROM commands \
+             WHERE handle = ?1 AND timestamp >= ?2 AND timestamp <= ?3 \
+             ORDER BY sequence",
+        ) {
+            Ok(stmt) => stmt,
+            Err(e) => {
+                error!("Could not query commands for '{}': {}", id, e);
+                return vec![];
+            }
+        };
+
+        let rows = stmt.query_map(params![id.as_str(), after, before], |row| {
+            Ok(CommandKey {
+                sequence: row.get::<_, i64>(0)? as u64,
+                timestamp_secs: row.get(1)?,
+                label: row.get(2)?,
+            })
+        });
+
+        match rows {
+            Ok(rows) => rows
+                .filter_map(Result::ok)
+                .filter(|key| crit.matches_label(&key.label))
+                .collect(),
+            Err(e) => {
+                error!("Could not query commands for '{}': {}", id, e);
+                vec![]
+            }
+        }
+    }
+
+    fn has_key(&self, id: &Handle, key: &Self::Key) -> bool {
+        let conn = self.conn.lock().unwrap();
+        Self::has_key_in(&conn, id, key).unwrap_or(false)
+    }
+
+    fn has_aggregate(&self, id: &Handle) -> bool {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM events WHERE handle = ?1)",
+            params![id.as_str()],
+            |row| row.get(0),
+        )
+        .unwrap_or(false)
+    }
+
+    fn aggregates(&self) -> Vec<Handle> {
+        let conn = self.conn.lock().unwrap();
+        let mut res = vec![];
+
+        if let Ok(mut stmt) = conn.prepare("SELECT DISTINCT handle FROM events ORDER BY handle") {
+            if let Ok(rows) = stmt.query_map(NO_PARAMS, |row| row.get::<_, String>(0)) {
+                for handle in rows.filter_map(Result::ok) {
+                    match Handle::from_str(&handle) {
+                        Ok(handle) => res.push(handle),
+                        Err(_) => warn!("Ignoring invalid handle in key store: {}", handle),
+                    }
+                }
+            }
+        }
+
+        res
+    }
 
-    pub fn update_aggregate<A: Aggregate>(
+    fn store<V: Any + Serialize>(
         &self,
         id: &Handle,
-        aggregate: &mut A,
+        key: &Self::Key,
+        value: &V,
     ) -> Result<(), KeyStoreError> {
-        while let Some(e) = self.get_event(id, aggregate.version())? {
-            aggregate.apply(e);
+        let conn = self.conn.lock().unwrap();
+        Self::store_in(&conn, id, key, value)
+    }
+
+    fn get<V: Any + Storable>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+    ) -> Result<Option<V>, KeyStoreError> {
+        let json: Option<String> = {
+            let conn = self.conn.lock().unwrap();
+            match SqlKey::from(key) {
+                SqlKey::Event(version) => conn
+                    .query_row(
+                        "SELECT value FROM events WHERE handle = ?1 AND version = ?2",
+                        params![id.as_str(), version as i64],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+                SqlKey::Command(command_key) => conn
+                    .query_row(
+                        "SELECT value FROM commands WHERE handle = ?1 AND sequence = ?2",
+                        params![id.as_str(), command_key.sequence as i64],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+                SqlKey::Value(name) => conn
+                    .query_row(
+                        "SELECT value FROM key_values WHERE handle = ?1 AND name = ?2",
+                        params![id.as_str(), name],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            }
+        };
+
+        match json {
+            None => {
+                trace!(
+                    "Could not find key '{}' for '{}'",
+                    key.to_string_lossy(),
+                    id
+                );
+                Ok(None)
+            }
+            Some(json) => match serde_json::from_str(&json) {
+                Err(e) => {
+                    warn!(
+                        "Could not deserialize json for key '{}' of '{}', got error: '{}'. Will fall back to events.",
+                        key.to_string_lossy(),
+                        id,
+                        e
+                    );
+                    Ok(None)
+                }
+                Ok(v) => Ok(Some(v)),
+            },
+        }
+    }
+
+    fn drop(&self, id: &Handle, key: &Self::Key) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let handle = id.as_str();
+        let removed = match SqlKey::from(key) {
+            SqlKey::Event(version) => conn.execute(
+                "DELETE FROM events WHERE handle = ?1 AND version = ?2",
+                params![handle, version as i64],
+            )?,
+            SqlKey::Command(command_key) => conn.execute(
+                "DELETE FROM commands WHERE handle = ?1 AND sequence = ?2",
+                params![handle, command_key.sequence as i64],
+            )?,
+            SqlKey::Value(name) => conn.execute(
+                "DELETE FROM key_values WHERE handle = ?1 AND name = ?2",
+                params![handle, name],
+            )?,
+        };
+
+        if removed == 0 {
+            Err(KeyStoreError::KeyUnknown(key.to_string_lossy().to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
+        let json: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM events WHERE handle = ?1 AND version = ?2",
+                params![id.as_str(), version as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match json {
+            None => {
+                trace!("No more events for '{}' after version {}", id, version);
+                Ok(None)
+            }
+            Some(json) => match serde_json::from_str(&json) {
+                Err(e) => {
+                    error!(
+                        "Could not deserialize event {} for '{}', error: {}",
+                        version, id, e
+                    );
+                    Err(KeyStoreError::JsonError(e))
+                }
+                Ok(v) => Ok(Some(v)),
+            },
+        }
+    }
+
+    fn store_event<V: Event>(&self, event: &V) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        Self::store_event_in(&conn, event)
+    }
+
+    fn store_command<S: WithStorableDetails>(
+        &self,
+        command: StoredCommand<S>,
+    ) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        Self::store_command_in(&conn, &command)
+    }
+
+    fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError> {
+        // try to get a snapshot.
+        // If that fails, replay the archived events from the init event.
+        // Then replay all newer events that can be found.
+        let key = Self::key_for_snapshot();
+        let aggregate_opt = match self.get::<V>(id, &key)? {
+            Some(aggregate) => Some(aggregate),
+            None => self.replay_aggregate(id, None)?,
+        };
+
+        match aggregate_opt {
+            None => Ok(None),
+            Some(mut aggregate) => {
+                self.update_aggregate(id, &mut aggregate)?;
+                Ok(Some(aggregate))
+            }
+        }
+    }
+
+    fn store_snapshot<V: Aggregate>(
+        &self,
+        id: &Handle,
+        aggregate: &V,
+    ) -> Result<(), KeyStoreError> {
+        let key = Self::key_for_snapshot();
+        self.store(id, &key, aggregate)
+    }
+
+    fn store_update<S: WithStorableDetails, E: Event, V: Aggregate>(
+        &self,
+        id: &Handle,
+        command: StoredCommand<S>,
+        events: &[E],
+        snapshot: Option<&V>,
+        info: &StoredValueInfo,
+    ) -> Result<(), KeyStoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        Self::store_command_in(&tx, &command)?;
+        for event in events {
+            Self::store_event_in(&tx, event)?;
         }
+        if let Some(aggregate) = snapshot {
+            Self::store_in(&tx, id, &Self::key_for_snapshot(), aggregate)?;
+        }
+        Self::store_in(&tx, id, &Self::key_for_info(), info)?;
+
+        tx.commit()?;
         Ok(())
     }
+
+    fn archive_names(&self, id: &Handle) -> Result<Vec<String>, KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT name FROM archive_values WHERE handle = ?1")?;
+        let names = stmt
+            .query_map(params![id.as_str()], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    fn get_archive_data(&self, id: &Handle, name: &str) -> Result<Option<Vec<u8>>, KeyStoreError> {
+        let data = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM archive_values WHERE handle = ?1 AND name = ?2",
+                params![id.as_str(), name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data)
+    }
+
+    fn store_archive_data(
+        &self,
+        id: &Handle,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), KeyStoreError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO archive_values (handle, name, value) VALUES (?1, ?2, ?3)",
+            params![id.as_str(), name, data],
+        )?;
+        Ok(())
+    }
+}
+
+impl SqlKeyStore {
+    /// Opens, or creates, the database in a directory for the name_space
+    /// under the work_dir.
+    pub fn under_work_dir(work_dir: &PathBuf, name_space: &str) -> Result<Self, KeyStoreError> {
+        let mut path = work_dir.clone();
+        path.push(name_space);
+        if !path.is_dir() {
+            fs::create_dir_all(&path)?;
+        }
+        path.push(SQL_DB_FILE);
+
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SQL_SCHEMA)?;
+
+        Ok(SqlKeyStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Returns whether there is a database for the name_space under the
+    /// work_dir.
+    pub fn exists(work_dir: &PathBuf, name_space: &str) -> bool {
+        let mut path = work_dir.clone();
+        path.push(name_space);
+        path.push(SQL_DB_FILE);
+        path.exists()
+    }
+
+    fn has_key_in(conn: &Connection, id: &Handle, key: &PathBuf) -> Result<bool, KeyStoreError> {
+        let handle = id.as_str();
+        let exists = match SqlKey::from(key) {
+            SqlKey::Event(version) => conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM events WHERE handle = ?1 AND version = ?2)",
+                params![handle, version as i64],
+                |row| row.get(0),
+            )?,
+            SqlKey::Command(command_key) => conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM commands WHERE handle = ?1 AND sequence = ?2)",
+                params![handle, command_key.sequence as i64],
+                |row| row.get(0),
+            )?,
+            SqlKey::Value(name) => conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM key_values WHERE handle = ?1 AND name = ?2)",
+                params![handle, name],
+                |row| row.get(0),
+            )?,
+        };
+        Ok(exists)
+    }
+
+    fn store_in<V: Any + Serialize>(
+        conn: &Connection,
+        id: &Handle,
+        key: &PathBuf,
+        value: &V,
+    ) -> Result<(), KeyStoreError> {
+        let handle = id.as_str();
+        let json = serde_json::to_string(value)?;
+        match SqlKey::from(key) {
+            SqlKey::Event(version) => conn.execute(
+                "INSERT OR REPLACE INTO events (handle, version, value) VALUES (?1, ?2, ?3)",
+                params![handle, version as i64, json],
+            )?,
+            SqlKey::Command(command_key) => conn.execute(
+                "INSERT OR REPLACE INTO commands (handle, sequence, timestamp, label, value) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    handle,
+                    command_key.sequence as i64,
+                    command_key.timestamp_secs,
+                    command_key.label,
+                    json
+                ],
+            )?,
+            SqlKey::Value(name) => conn.execute(
+                "INSERT OR REPLACE INTO key_values (handle, name, value) VALUES (?1, ?2, ?3)",
+                params![handle, name, json],
+            )?,
+        };
+        Ok(())
+    }
+
+    fn store_event_in<V: Event>(conn: &Connection, event: &V) -> Result<(), KeyStoreError> {
+        trace!("Storing event: {}", event);
+
+        let id = event.handle();
+        let key = Self::key_for_event(event.version());
+        if Self::has_key_in(conn, id, &key)? {
+            Err(KeyStoreError::KeyExists(key.to_string_lossy().to_string()))
+        } else {
+            Self::store_in(conn, id, &key, event)
+        }
+    }
+
+    fn store_command_in<S: WithStorableDetails>(
+        conn: &Connection,
+        command: &StoredCommand<S>,
+    ) -> Result<(), KeyStoreError> {
+        let id = command.handle();
+        let key = Self::key_for_command(command).into();
+        if Self::has_key_in(conn, id, &key)? {
+            Err(KeyStoreError::KeyExists(key.to_string_lossy().to_string()))
+        } else {
+            Self::store_in(conn, id, &key, command)
+        }
+    }
+}
+
+//------------ AnyKeyStore ---------------------------------------------------
+
+/// A KeyStore of the type chosen in the configuration.
+pub enum AnyKeyStore {
+    Disk(DiskKeyStore),
+    Sql(SqlKeyStore),
+}
+
+impl AnyKeyStore {
+    /// Opens, or creates, the store for the name_space under the work_dir.
+    /// Fails if a store of the other storage type already holds aggregates
+    /// for the name_space, as using an empty store instead would make them
+    /// disappear.
+    pub fn under_work_dir(
+        work_dir: &PathBuf,
+        name_space: &str,
+        storage_type: StorageType,
+    ) -> Result<Self, KeyStoreError> {
+        let other = match storage_type {
+            StorageType::Disk if SqlKeyStore::exists(work_dir, name_space) => {
+                let aggregates = SqlKeyStore::under_work_dir(work_dir, name_space)?.aggregates();
+                Some(StorageType::Sqlite).filter(|_| !aggregates.is_empty())
+            }
+            StorageType::Sqlite => {
+                let aggregates = DiskKeyStore::new(work_dir, name_space).aggregates();
+                Some(StorageType::Disk).filter(|_| !aggregates.is_empty())
+            }
+            _ => None,
+        };
+        if let Some(other) = other {
+            return Err(KeyStoreError::StorageTypeMismatch(
+                name_space.to_string(),
+                other,
+            ));
+        }
+
+        match storage_type {
+            StorageType::Disk => Ok(AnyKeyStore::Disk(DiskKeyStore::under_work_dir(
+                work_dir, name_space,
+            )?)),
+            StorageType::Sqlite => Ok(AnyKeyStore::Sql(SqlKeyStore::under_work_dir(
+                work_dir, name_space,
+            )?)),
+        }
+    }
+}
+
+impl KeyStore for AnyKeyStore {
+    type Key = PathBuf;
+
+    fn get_version(&self) -> Result<KeyStoreVersion, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_version(),
+            AnyKeyStore::Sql(store) => store.get_version(),
+        }
+    }
+
+    fn set_version(&self, version: &KeyStoreVersion) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.set_version(version),
+            AnyKeyStore::Sql(store) => store.set_version(version),
+        }
+    }
+
+    fn key_for_info() -> Self::Key {
+        DiskKeyStore::key_for_info()
+    }
+
+    fn key_for_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_snapshot()
+    }
+
+    fn key_for_event(version: u64) -> Self::Key {
+        DiskKeyStore::key_for_event(version)
+    }
+
+    fn key_for_command<S: WithStorableDetails>(command: &StoredCommand<S>) -> CommandKey {
+        DiskKeyStore::key_for_command(command)
+    }
+
+    fn keys(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        match self {
+            AnyKeyStore::Disk(store) => store.keys(id, matching),
+            AnyKeyStore::Sql(store) => store.keys(id, matching),
+        }
+    }
+
+    fn keys_ascending(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        match self {
+            AnyKeyStore::Disk(store) => store.keys_ascending(id, matching),
+            AnyKeyStore::Sql(store) => store.keys_ascending(id, matching),
+        }
+    }
+
+    fn command_keys_ascending(
+        &self,
+        id: &Handle,
+        crit: &CommandHistoryCriteria,
+    ) -> Vec<CommandKey> {
+        match self {
+            AnyKeyStore::Disk(store) => store.command_keys_ascending(id, crit),
+            AnyKeyStore::Sql(store) => store.command_keys_ascending(id, crit),
+        }
+    }
+
+    fn has_key(&self, id: &Handle, key: &Self::Key) -> bool {
+        match self {
+            AnyKeyStore::Disk(store) => store.has_key(id, key),
+            AnyKeyStore::Sql(store) => store.has_key(id, key),
+        }
+    }
+
+    fn has_aggregate(&self, id: &Handle) -> bool {
+        match self {
+            AnyKeyStore::Disk(store) => store.has_aggregate(id),
+            AnyKeyStore::Sql(store) => store.has_aggregate(id),
+        }
+    }
+
+    fn aggregates(&self) -> Vec<Handle> {
+        match self {
+            AnyKeyStore::Disk(store) => store.aggregates(),
+            AnyKeyStore::Sql(store) => store.aggregates(),
+        }
+    }
+
+    fn store<V: Any + Serialize>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+        value: &V,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store(id, key, value),
+            AnyKeyStore::Sql(store) => store.store(id, key, value),
+        }
+    }
+
+    fn get<V: Any + Storable>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+    ) -> Result<Option<V>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get(id, key),
+            AnyKeyStore::Sql(store) => store.get(id, key),
+        }
+    }
+
+    fn drop(&self, id: &Handle, key: &Self::Key) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.drop(id, key),
+            AnyKeyStore::Sql(store) => store.drop(id, key),
+        }
+    }
+
+    fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_event(id, version),
+            AnyKeyStore::Sql(store) => store.get_event(id, version),
+        }
+    }
+
+    fn store_event<V: Event>(&self, event: &V) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_event(event),
+            AnyKeyStore::Sql(store) => store.store_event(event),
+        }
+    }
+
+    fn store_command<S: WithStorableDetails>(
+        &self,
+        command: StoredCommand<S>,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_command(command),
+            AnyKeyStore::Sql(store) => store.store_command(command),
+        }
+    }
+
+    fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_aggregate(id),
+            AnyKeyStore::Sql(store) => store.get_aggregate(id),
+        }
+    }
+
+    fn store_snapshot<V: Aggregate>(
+        &self,
+        id: &Handle,
+        aggregate: &V,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_snapshot(id, aggregate),
+            AnyKeyStore::Sql(store) => store.store_snapshot(id, aggregate),
+        }
+    }
+
+    fn store_update<S: WithStorableDetails, E: Event, V: Aggregate>(
+        &self,
+        id: &Handle,
+        command: StoredCommand<S>,
+        events: &[E],
+        snapshot: Option<&V>,
+        info: &StoredValueInfo,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_update(id, command, events, snapshot, info),
+            AnyKeyStore::Sql(store) => store.store_update(id, command, events, snapshot, info),
+        }
+    }
+
+    fn archive_names(&self, id: &Handle) -> Result<Vec<String>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.archive_names(id),
+            AnyKeyStore::Sql(store) => store.archive_names(id),
+        }
+    }
+
+    fn get_archive_data(&self, id: &Handle, name: &str) -> Result<Option<Vec<u8>>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_archive_data(id, name),
+            AnyKeyStore::Sql(store) => store.get_archive_data(id, name),
+        }
+    }
+
+    fn store_archive_data(
+        &self,
+        id: &Handle,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_archive_data(id, name, data),
+            AnyKeyStore::Sql(store) => store.store_archive_data(id, name, data),
+        }
+    }
 }
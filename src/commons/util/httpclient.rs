@@ -113,6 +113,20 @@ pub async fn get_text(uri: &str, token: Option<&Token>) -> Result<String, Error>
     }
 }
 
+/// Performs a GET request and expects a binary response, e.g. a certificate.
+pub async fn get_binary(uri: &str) -> Result<Bytes, Error> {
+    let headers = headers(None, None)?;
+    let res = client(uri).await?.get(uri).headers(headers).send().await?;
+
+    match res.status() {
+        StatusCode::OK => {
+            let bytes = res.bytes().await?;
+            Ok(bytes)
+        }
+        status => Err(Error::BadStatus(status)),
+    }
+}
+
 /// Checks that there is a 200 OK response at the given URI. Discards the
 /// response body.
 pub async fn get_ok(uri: &str, token: Option<&Token>) -> Result<(), Error> {
@@ -180,6 +194,56 @@ pub async fn post_json_with_response<T: DeserializeOwned>(
     process_json_response(res).await
 }
 
+/// Performs a POST of data that can be serialized into json, and expects
+/// a binary response, e.g. a backup file.
+pub async fn post_json_with_binary_response(
+    uri: &str,
+    data: impl Serialize,
+    token: Option<&Token>,
+) -> Result<Bytes, Error> {
+    if env::var(KRILL_CLI_API_ENV).is_ok() {
+        let body = serde_json::to_string_pretty(&data)?;
+        report_post_and_exit(uri, Some(JSON_CONTENT), token, PostBody::String(&body));
+    }
+
+    let body = serde_json::to_string(&data)?;
+    let headers = headers(Some(JSON_CONTENT), token)?;
+    let res = client(uri)
+        .await?
+        .post(uri)
+        .headers(headers)
+        .body(body)
+        .send()
+        .await?;
+
+    match res.status() {
+        StatusCode::OK => Ok(res.bytes().await?),
+        StatusCode::FORBIDDEN => Err(Error::Forbidden),
+        status => match res.text().await {
+            Ok(body) if !body.is_empty() => Err(Error::wrap_err_res(status, body)),
+            _ => Err(Error::BadStatus(status)),
+        },
+    }
+}
+
+/// Performs a POST of form data (application/x-www-form-urlencoded), and
+/// expects a json response that can be deserialized into the an owned value
+/// of the expected type.
+pub async fn post_form_with_response<T: DeserializeOwned>(
+    uri: &str,
+    form: &[(&str, &str)],
+) -> Result<T, Error> {
+    let headers = headers(None, None)?;
+    let res = client(uri)
+        .await?
+        .post(uri)
+        .headers(headers)
+        .form(form)
+        .send()
+        .await?;
+    process_json_response(res).await
+}
+
 /// Performs a POST with no data to the given URI and expects and empty 200 OK response.
 pub async fn post_empty(uri: &str, token: Option<&Token>) -> Result<(), Error> {
     if env::var(KRILL_CLI_API_ENV).is_ok() {
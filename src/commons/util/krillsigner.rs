@@ -0,0 +1,398 @@
+//! The signer used by the Krill server. Dispatches to the signer
+//! implementations that were selected in the configuration.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use rpki::crypto::signer::KeyError;
+use rpki::crypto::{
+    KeyIdentifier, PublicKey, PublicKeyFormat, Signature, SignatureAlgorithm, Signer, SigningError,
+};
+
+use crate::commons::util::pkcs11signer::Pkcs11Signer;
+use crate::commons::util::softsigner::{OpenSslSigner, SignerError};
+use crate::daemon::ca::{self, KeyPurpose};
+
+/// The name of the signer configured in the `[signer]` section.
+pub const DEFAULT_SIGNER: &str = "default";
+
+//------------ SignerConfig --------------------------------------------------
+
+/// Determines which signer implementation is used for keys.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum SignerConfig {
+    /// Keys are generated using openssl and stored unencrypted in the
+    /// "keys" directory under the data directory.
+    #[serde(rename = "openssl")]
+    OpenSsl,
+
+    /// Keys are generated and kept in a PKCS#11 token, e.g. an HSM.
+    #[serde(rename = "pkcs11")]
+    Pkcs11 {
+        lib_path: PathBuf,
+        #[serde(default)]
+        slot: u64,
+        user_pin: Option<String>,
+    },
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig::OpenSsl
+    }
+}
+
+//------------ NamedSignerConfig ---------------------------------------------
+
+/// An additional signer, configured in a `[[signers]]` section. CAs and key
+/// purposes refer to it by name.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct NamedSignerConfig {
+    name: String,
+    #[serde(flatten)]
+    config: SignerConfig,
+}
+
+impl NamedSignerConfig {
+    pub fn new(name: &str, config: SignerConfig) -> Self {
+        NamedSignerConfig {
+            name: name.to_string(),
+            config,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> &SignerConfig {
+        &self.config
+    }
+}
+
+//------------ SignerBackend -------------------------------------------------
+
+#[derive(Clone)]
+enum SignerBackend {
+    OpenSsl(OpenSslSigner),
+    Pkcs11(Pkcs11Signer),
+}
+
+impl SignerBackend {
+    /// Builds the signer. PKCS#11 signers re-use the library context of an
+    /// existing signer for the same library, because a library can only be
+    /// initialised once.
+    fn build(
+        config: &SignerConfig,
+        work_dir: &PathBuf,
+        existing: &[(String, SignerBackend)],
+    ) -> Result<Self, SignerError> {
+        match config {
+            SignerConfig::OpenSsl => OpenSslSigner::build(work_dir).map(SignerBackend::OpenSsl),
+            SignerConfig::Pkcs11 {
+                lib_path,
+                slot,
+                user_pin,
+            } => {
+                let shared = existing.iter().find_map(|(_, backend)| match backend {
+                    SignerBackend::Pkcs11(signer) if signer.lib_path() == lib_path => Some(signer),
+                    _ => None,
+                });
+                match shared {
+                    Some(signer) => signer.for_slot(*slot, user_pin.as_deref()),
+                    None => {
+                        info!("Using PKCS#11 signer with library: {}", lib_path.display());
+                        Pkcs11Signer::build(lib_path, *slot, user_pin.as_deref())
+                    }
+                }
+                .map(SignerBackend::Pkcs11)
+            }
+        }
+    }
+
+    fn create_key(&mut self, algorithm: PublicKeyFormat) -> Result<KeyIdentifier, SignerError> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.create_key(algorithm),
+            SignerBackend::Pkcs11(signer) => signer.create_key(algorithm),
+        }
+    }
+
+    fn get_key_info(&self, key_id: &KeyIdentifier) -> Result<PublicKey, KeyError<SignerError>> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.get_key_info(key_id),
+            SignerBackend::Pkcs11(signer) => signer.get_key_info(key_id),
+        }
+    }
+
+    fn destroy_key(&mut self, key_id: &KeyIdentifier) -> Result<(), KeyError<SignerError>> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.destroy_key(key_id),
+            SignerBackend::Pkcs11(signer) => signer.destroy_key(key_id),
+        }
+    }
+
+    fn sign<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        key_id: &KeyIdentifier,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<Signature, SigningError<SignerError>> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.sign(key_id, algorithm, data),
+            SignerBackend::Pkcs11(signer) => signer.sign(key_id, algorithm, data),
+        }
+    }
+
+    fn sign_one_off<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<(Signature, PublicKey), SignerError> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.sign_one_off(algorithm, data),
+            SignerBackend::Pkcs11(signer) => signer.sign_one_off(algorithm, data),
+        }
+    }
+
+    fn rand(&self, target: &mut [u8]) -> Result<(), SignerError> {
+        match self {
+            SignerBackend::OpenSsl(signer) => signer.rand(target),
+            SignerBackend::Pkcs11(signer) => signer.rand(target),
+        }
+    }
+}
+
+//------------ KrillSigner ---------------------------------------------------
+
+/// Keeps keys in one or more named signers.
+///
+/// New keys are created in the signer preferred by the CA, if it has one,
+/// or else in the signer configured for the purpose of the key. Existing
+/// keys are looked up in all signers, and where they were found is
+/// remembered, so that keys can be used from whichever signer they were
+/// created in. This allows CAs to move to another signer using a normal
+/// key roll.
+#[derive(Clone)]
+pub struct KrillSigner {
+    signers: Vec<(String, SignerBackend)>,
+    id_key_signer: usize,
+    ca_key_signer: usize,
+    key_locations: Arc<RwLock<HashMap<KeyIdentifier, usize>>>,
+}
+
+impl KrillSigner {
+    /// Builds a KrillSigner which uses the given signer, named "default",
+    /// for all keys.
+    pub fn build(config: &SignerConfig, work_dir: &PathBuf) -> Result<Self, SignerError> {
+        let default = SignerBackend::build(config, work_dir, &[])?;
+        Ok(KrillSigner {
+            signers: vec![(DEFAULT_SIGNER.to_string(), default)],
+            id_key_signer: 0,
+            ca_key_signer: 0,
+            key_locations: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// Adds an additional named signer. Additional openssl signers keep
+    /// their keys under "signers/<name>" in the data directory.
+    pub fn add_signer(
+        &mut self,
+        named: &NamedSignerConfig,
+        work_dir: &PathBuf,
+    ) -> Result<(), SignerError> {
+        if self.signer_idx(named.name()).is_ok() {
+            return Err(SignerError::DuplicateSigner(named.name().to_string()));
+        }
+
+        let mut signer_dir = work_dir.clone();
+        if named.config() == &SignerConfig::OpenSsl {
+            signer_dir.push("signers");
+            signer_dir.push(named.name());
+            fs::create_dir_all(&signer_dir)?;
+        }
+
+        let backend = SignerBackend::build(named.config(), &signer_dir, &self.signers)?;
+        self.signers.push((named.name().to_string(), backend));
+        Ok(())
+    }
+
+    /// Sets the signers used for new ID keys and resource class keys of CAs
+    /// which have no preference of their own. If no signer is given for a
+    /// purpose the default signer is used.
+    pub fn set_key_placement(
+        &mut self,
+        id_key_signer: Option<&str>,
+        ca_key_signer: Option<&str>,
+    ) -> Result<(), SignerError> {
+        self.id_key_signer = self.signer_idx(id_key_signer.unwrap_or(DEFAULT_SIGNER))?;
+        self.ca_key_signer = self.signer_idx(ca_key_signer.unwrap_or(DEFAULT_SIGNER))?;
+        Ok(())
+    }
+
+    fn signer_idx(&self, name: &str) -> Result<usize, SignerError> {
+        self.signers
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| SignerError::UnknownSigner(name.to_string()))
+    }
+
+    fn default_signer(&self) -> &SignerBackend {
+        &self.signers[0].1
+    }
+
+    /// Finds the signer which holds the key. Keys are looked for in all
+    /// signers the first time they are used.
+    fn locate_key(&self, key_id: &KeyIdentifier) -> Result<usize, KeyError<SignerError>> {
+        if self.signers.len() == 1 {
+            return Ok(0);
+        }
+
+        if let Some(idx) = self.key_locations.read().unwrap().get(key_id) {
+            return Ok(*idx);
+        }
+
+        for (idx, (_, signer)) in self.signers.iter().enumerate() {
+            if signer.get_key_info(key_id).is_ok() {
+                self.key_locations.write().unwrap().insert(*key_id, idx);
+                return Ok(idx);
+            }
+        }
+
+        Err(KeyError::KeyNotFound)
+    }
+
+    fn create_key_in(&mut self, idx: usize) -> Result<KeyIdentifier, SignerError> {
+        let key_id = self.signers[idx].1.create_key(PublicKeyFormat::default())?;
+        self.key_locations.write().unwrap().insert(key_id, idx);
+        Ok(key_id)
+    }
+}
+
+impl Signer for KrillSigner {
+    type KeyId = KeyIdentifier;
+    type Error = SignerError;
+
+    fn create_key(&mut self, _algorithm: PublicKeyFormat) -> Result<Self::KeyId, Self::Error> {
+        self.create_key_in(0)
+    }
+
+    fn get_key_info(&self, key_id: &Self::KeyId) -> Result<PublicKey, KeyError<Self::Error>> {
+        let idx = self.locate_key(key_id)?;
+        self.signers[idx].1.get_key_info(key_id)
+    }
+
+    fn destroy_key(&mut self, key_id: &Self::KeyId) -> Result<(), KeyError<Self::Error>> {
+        let idx = self.locate_key(key_id)?;
+        self.signers[idx].1.destroy_key(key_id)?;
+        self.key_locations.write().unwrap().remove(key_id);
+        Ok(())
+    }
+
+    fn sign<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        key_id: &Self::KeyId,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<Signature, SigningError<Self::Error>> {
+        let idx = self.locate_key(key_id).map_err(|e| match e {
+            KeyError::KeyNotFound => SigningError::KeyNotFound,
+            KeyError::Signer(e) => SigningError::Signer(e),
+        })?;
+        self.signers[idx].1.sign(key_id, algorithm, data)
+    }
+
+    fn sign_one_off<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<(Signature, PublicKey), Self::Error> {
+        self.default_signer().sign_one_off(algorithm, data)
+    }
+
+    fn rand(&self, target: &mut [u8]) -> Result<(), Self::Error> {
+        self.default_signer().rand(target)
+    }
+}
+
+impl ca::Signer for KrillSigner {
+    fn create_key_for(
+        &mut self,
+        purpose: KeyPurpose,
+        preferred: Option<&str>,
+    ) -> Result<KeyIdentifier, Self::Error> {
+        let idx = match preferred.map(|name| self.signer_idx(name)) {
+            Some(Ok(idx)) => idx,
+            other => {
+                if let Some(Err(e)) = other {
+                    warn!("{}, using configured signer for new key instead", e);
+                }
+                match purpose {
+                    KeyPurpose::Id => self.id_key_signer,
+                    KeyPurpose::ResourceClass => self.ca_key_signer,
+                }
+            }
+        };
+        self.create_key_in(idx)
+    }
+
+    fn has_signer(&self, name: &str) -> bool {
+        self.signer_idx(name).is_ok()
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::daemon::ca::Signer as _;
+    use crate::test;
+
+    #[test]
+    fn should_place_keys_by_purpose_and_preference() {
+        test::test_under_tmp(|d| {
+            let other = NamedSignerConfig::new("other", SignerConfig::OpenSsl);
+
+            let mut signer = KrillSigner::build(&SignerConfig::OpenSsl, &d).unwrap();
+            signer.add_signer(&other, &d).unwrap();
+            signer.set_key_placement(None, Some("other")).unwrap();
+
+            let id_key = signer.create_key_for(KeyPurpose::Id, None).unwrap();
+            let rc_key = signer
+                .create_key_for(KeyPurpose::ResourceClass, None)
+                .unwrap();
+            let preferred = signer
+                .create_key_for(KeyPurpose::ResourceClass, Some(DEFAULT_SIGNER))
+                .unwrap();
+
+            assert_eq!(0, signer.locate_key(&id_key).unwrap());
+            assert_eq!(1, signer.locate_key(&rc_key).unwrap());
+            assert_eq!(0, signer.locate_key(&preferred).unwrap());
+
+            // Keys are found again by a signer which has not seen them yet
+            let mut fresh = KrillSigner::build(&SignerConfig::OpenSsl, &d).unwrap();
+            fresh.add_signer(&other, &d).unwrap();
+            assert_eq!(1, fresh.locate_key(&rc_key).unwrap());
+            fresh.get_key_info(&rc_key).unwrap();
+
+            fresh.destroy_key(&rc_key).unwrap();
+            assert!(signer.get_key_info(&rc_key).is_err());
+        })
+    }
+
+    #[test]
+    fn should_reject_unknown_and_duplicate_signers() {
+        test::test_under_tmp(|d| {
+            let mut signer = KrillSigner::build(&SignerConfig::OpenSsl, &d).unwrap();
+            assert!(signer.has_signer(DEFAULT_SIGNER));
+            assert!(!signer.has_signer("hsm"));
+            assert!(signer.set_key_placement(Some("hsm"), None).is_err());
+
+            let default = NamedSignerConfig::new(DEFAULT_SIGNER, SignerConfig::OpenSsl);
+            assert!(signer.add_signer(&default, &d).is_err());
+        })
+    }
+}
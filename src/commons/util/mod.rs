@@ -5,6 +5,8 @@ use rpki::crypto::DigestAlgorithm;
 pub mod ext_serde;
 pub mod file;
 pub mod httpclient;
+pub mod krillsigner;
+pub mod pkcs11signer;
 pub mod softsigner;
 pub mod xml;
 
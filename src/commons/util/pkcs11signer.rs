@@ -0,0 +1,407 @@
+//! Support for signing things using keys held in an HSM, or any other
+//! token, that is accessed through a PKCS#11 library (e.g. SoftHSMv2).
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use openssl::bn::BigNum;
+use openssl::rsa::Rsa;
+use pkcs11::types::{
+    CKA_CLASS, CKA_ID, CKA_KEY_TYPE, CKA_LABEL, CKA_MODULUS, CKA_MODULUS_BITS, CKA_PRIVATE,
+    CKA_PUBLIC_EXPONENT, CKA_SENSITIVE, CKA_SIGN, CKA_TOKEN, CKA_VERIFY, CKF_RW_SESSION,
+    CKF_SERIAL_SESSION, CKK_RSA, CKM_RSA_PKCS_KEY_PAIR_GEN, CKM_SHA256_RSA_PKCS, CKO_PRIVATE_KEY,
+    CKO_PUBLIC_KEY, CKR_USER_ALREADY_LOGGED_IN, CKU_USER, CK_ATTRIBUTE, CK_BBOOL, CK_FALSE,
+    CK_KEY_TYPE, CK_MECHANISM, CK_OBJECT_CLASS, CK_OBJECT_HANDLE, CK_SESSION_HANDLE, CK_SLOT_ID,
+    CK_TRUE, CK_ULONG,
+};
+use pkcs11::Ctx;
+
+use rpki::crypto::signer::KeyError;
+use rpki::crypto::{
+    KeyIdentifier, PublicKey, PublicKeyFormat, Signature, SignatureAlgorithm, Signer, SigningError,
+};
+
+use crate::commons::util::softsigner::SignerError;
+
+const RSA_KEY_BITS: CK_ULONG = 2048;
+const RSA_PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+const KEY_LABEL: &str = "Krill";
+
+//------------ Pkcs11Signer --------------------------------------------------
+
+/// A signer which keeps its keys in a PKCS#11 token.
+///
+/// Keys are created as token objects which cannot be extracted. The
+/// CKA_ID attribute of both the public and private key object is set to
+/// the bytes of the RPKI key identifier, so that keys can be found again
+/// using only the key identifier that Krill keeps in its CA state.
+///
+/// A PKCS#11 library can only be initialised once per process, so signers
+/// which use other slots of the same library must share its context, see
+/// `for_slot`.
+#[derive(Clone)]
+pub struct Pkcs11Signer {
+    ctx: Arc<Ctx>,
+    lib_path: PathBuf,
+    slot: CK_SLOT_ID,
+
+    // This session is kept open for as long as the signer lives, because
+    // the login state in PKCS#11 is lost when the last session is closed.
+    #[allow(dead_code)] // just need to keep this open
+    login_session: CK_SESSION_HANDLE,
+}
+
+impl Pkcs11Signer {
+    pub fn build(
+        lib_path: &PathBuf,
+        slot: u64,
+        user_pin: Option<&str>,
+    ) -> Result<Self, SignerError> {
+        let ctx = Ctx::new_and_initialize(lib_path).map_err(SignerError::pkcs11)?;
+        Self::with_ctx(Arc::new(ctx), lib_path, slot, user_pin)
+    }
+
+    /// Builds a signer for a slot in the same library as this signer,
+    /// sharing the initialised library context.
+    pub fn for_slot(&self, slot: u64, user_pin: Option<&str>) -> Result<Self, SignerError> {
+        Self::with_ctx(self.ctx.clone(), &self.lib_path, slot, user_pin)
+    }
+
+    pub fn lib_path(&self) -> &PathBuf {
+        &self.lib_path
+    }
+
+    fn with_ctx(
+        ctx: Arc<Ctx>,
+        lib_path: &Path,
+        slot: u64,
+        user_pin: Option<&str>,
+    ) -> Result<Self, SignerError> {
+        let slot = slot as CK_SLOT_ID;
+
+        let login_session = ctx
+            .open_session(slot, CKF_SERIAL_SESSION | CKF_RW_SESSION, None, None)
+            .map_err(SignerError::pkcs11)?;
+
+        match ctx.login(login_session, CKU_USER, user_pin) {
+            Ok(()) | Err(pkcs11::errors::Error::Pkcs11(CKR_USER_ALREADY_LOGGED_IN)) => {}
+            Err(e) => return Err(SignerError::pkcs11(e)),
+        }
+
+        Ok(Pkcs11Signer {
+            ctx,
+            lib_path: lib_path.to_path_buf(),
+            slot,
+            login_session,
+        })
+    }
+}
+
+impl Pkcs11Signer {
+    /// Runs the operation in a fresh session, and closes the session
+    /// afterwards regardless of the outcome.
+    fn with_session<T, F>(&self, op: F) -> Result<T, SignerError>
+    where
+        F: FnOnce(CK_SESSION_HANDLE) -> Result<T, SignerError>,
+    {
+        let session = self
+            .ctx
+            .open_session(self.slot, CKF_SERIAL_SESSION | CKF_RW_SESSION, None, None)
+            .map_err(SignerError::pkcs11)?;
+
+        let res = op(session);
+        let _ = self.ctx.close_session(session);
+        res
+    }
+
+    /// Generates a new RSA key pair. If `token` is false, then the key pair
+    /// only lives for as long as the session.
+    fn generate_key_pair(
+        &self,
+        session: CK_SESSION_HANDLE,
+        token: bool,
+    ) -> Result<(CK_OBJECT_HANDLE, CK_OBJECT_HANDLE), SignerError> {
+        let mechanism = CK_MECHANISM {
+            mechanism: CKM_RSA_PKCS_KEY_PAIR_GEN,
+            pParameter: std::ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+
+        let token: CK_BBOOL = if token { CK_TRUE } else { CK_FALSE };
+        let yes: CK_BBOOL = CK_TRUE;
+        let key_type: CK_KEY_TYPE = CKK_RSA;
+
+        let pub_template = vec![
+            CK_ATTRIBUTE::new(CKA_TOKEN).with_bool(&token),
+            CK_ATTRIBUTE::new(CKA_KEY_TYPE).with_ck_ulong(&key_type),
+            CK_ATTRIBUTE::new(CKA_VERIFY).with_bool(&yes),
+            CK_ATTRIBUTE::new(CKA_MODULUS_BITS).with_ck_ulong(&RSA_KEY_BITS),
+            CK_ATTRIBUTE::new(CKA_PUBLIC_EXPONENT).with_bytes(&RSA_PUBLIC_EXPONENT),
+            CK_ATTRIBUTE::new(CKA_LABEL).with_string(KEY_LABEL),
+        ];
+
+        let priv_template = vec![
+            CK_ATTRIBUTE::new(CKA_TOKEN).with_bool(&token),
+            CK_ATTRIBUTE::new(CKA_KEY_TYPE).with_ck_ulong(&key_type),
+            CK_ATTRIBUTE::new(CKA_SIGN).with_bool(&yes),
+            CK_ATTRIBUTE::new(CKA_PRIVATE).with_bool(&yes),
+            CK_ATTRIBUTE::new(CKA_SENSITIVE).with_bool(&yes),
+            CK_ATTRIBUTE::new(CKA_LABEL).with_string(KEY_LABEL),
+        ];
+
+        self.ctx
+            .generate_key_pair(session, &mechanism, &pub_template, &priv_template)
+            .map_err(SignerError::pkcs11)
+    }
+
+    /// Reads the RSA modulus and exponent of a public key object and turns
+    /// them into an RPKI `PublicKey`.
+    fn public_key(
+        &self,
+        session: CK_SESSION_HANDLE,
+        pub_handle: CK_OBJECT_HANDLE,
+    ) -> Result<PublicKey, SignerError> {
+        let mut template = vec![
+            CK_ATTRIBUTE::new(CKA_MODULUS),
+            CK_ATTRIBUTE::new(CKA_PUBLIC_EXPONENT),
+        ];
+
+        // The first call only determines the length of the values.
+        self.ctx
+            .get_attribute_value(session, pub_handle, &mut template)
+            .map_err(SignerError::pkcs11)?;
+
+        let modulus_buf = vec![0; template[0].ulValueLen as usize];
+        let exponent_buf = vec![0; template[1].ulValueLen as usize];
+        template[0].set_bytes(&modulus_buf);
+        template[1].set_bytes(&exponent_buf);
+
+        self.ctx
+            .get_attribute_value(session, pub_handle, &mut template)
+            .map_err(SignerError::pkcs11)?;
+
+        let modulus = template[0].get_bytes().map_err(SignerError::pkcs11)?;
+        let exponent = template[1].get_bytes().map_err(SignerError::pkcs11)?;
+
+        let rsa = Rsa::from_public_components(
+            BigNum::from_slice(&modulus)?,
+            BigNum::from_slice(&exponent)?,
+        )?;
+
+        let mut der = Bytes::from(rsa.public_key_to_der()?);
+        PublicKey::decode(&mut der).map_err(|_| SignerError::DecodeError)
+    }
+
+    /// Finds the handle of the public or private key object for a key id.
+    fn find_key(
+        &self,
+        session: CK_SESSION_HANDLE,
+        key_id: &KeyIdentifier,
+        class: CK_OBJECT_CLASS,
+    ) -> Result<CK_OBJECT_HANDLE, SignerError> {
+        let template = vec![
+            CK_ATTRIBUTE::new(CKA_CLASS).with_ck_ulong(&class),
+            CK_ATTRIBUTE::new(CKA_ID).with_bytes(key_id.as_slice()),
+        ];
+
+        self.ctx
+            .find_objects_init(session, &template)
+            .map_err(SignerError::pkcs11)?;
+        let found = self.ctx.find_objects(session, 1);
+        let _ = self.ctx.find_objects_final(session);
+
+        found
+            .map_err(SignerError::pkcs11)?
+            .first()
+            .cloned()
+            .ok_or(SignerError::KeyNotFound)
+    }
+
+    fn sign_with_key<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        session: CK_SESSION_HANDLE,
+        priv_handle: CK_OBJECT_HANDLE,
+        data: &D,
+    ) -> Result<Signature, SignerError> {
+        let mechanism = CK_MECHANISM {
+            mechanism: CKM_SHA256_RSA_PKCS,
+            pParameter: std::ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+
+        self.ctx
+            .sign_init(session, &mechanism, priv_handle)
+            .map_err(SignerError::pkcs11)?;
+        let signature = self
+            .ctx
+            .sign(session, data.as_ref())
+            .map_err(SignerError::pkcs11)?;
+
+        Ok(Signature::new(
+            SignatureAlgorithm::default(),
+            Bytes::from(signature),
+        ))
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    type KeyId = KeyIdentifier;
+    type Error = SignerError;
+
+    fn create_key(&mut self, _algorithm: PublicKeyFormat) -> Result<Self::KeyId, Self::Error> {
+        self.with_session(|session| {
+            let (pub_handle, priv_handle) = self.generate_key_pair(session, true)?;
+
+            // The key identifier is derived from the public key, so the
+            // CKA_ID can only be set after the key pair was generated. If
+            // that fails, the objects could never be found again, so they
+            // are destroyed rather than left behind on the token.
+            let set_id = || {
+                let key_id = self.public_key(session, pub_handle)?.key_identifier();
+
+                let id_template = vec![CK_ATTRIBUTE::new(CKA_ID).with_bytes(key_id.as_slice())];
+                self.ctx
+                    .set_attribute_value(session, pub_handle, &id_template)
+                    .map_err(SignerError::pkcs11)?;
+                self.ctx
+                    .set_attribute_value(session, priv_handle, &id_template)
+                    .map_err(SignerError::pkcs11)?;
+
+                Ok(key_id)
+            };
+
+            let res = set_id();
+            if res.is_err() {
+                let _ = self.ctx.destroy_object(session, pub_handle);
+                let _ = self.ctx.destroy_object(session, priv_handle);
+            }
+            res
+        })
+    }
+
+    fn get_key_info(&self, key_id: &Self::KeyId) -> Result<PublicKey, KeyError<Self::Error>> {
+        self.with_session(|session| {
+            let pub_handle = self.find_key(session, key_id, CKO_PUBLIC_KEY)?;
+            self.public_key(session, pub_handle)
+        })
+        .map_err(|e| match e {
+            SignerError::KeyNotFound => KeyError::KeyNotFound,
+            _ => KeyError::Signer(e),
+        })
+    }
+
+    fn destroy_key(&mut self, key_id: &Self::KeyId) -> Result<(), KeyError<Self::Error>> {
+        self.with_session(|session| {
+            for class in &[CKO_PUBLIC_KEY, CKO_PRIVATE_KEY] {
+                match self.find_key(session, key_id, *class) {
+                    Ok(handle) => self
+                        .ctx
+                        .destroy_object(session, handle)
+                        .map_err(SignerError::pkcs11)?,
+                    Err(SignerError::KeyNotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        })
+        .map_err(KeyError::Signer)
+    }
+
+    fn sign<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        key_id: &Self::KeyId,
+        _algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<Signature, SigningError<Self::Error>> {
+        self.with_session(|session| {
+            let priv_handle = self.find_key(session, key_id, CKO_PRIVATE_KEY)?;
+            self.sign_with_key(session, priv_handle, data)
+        })
+        .map_err(|e| match e {
+            SignerError::KeyNotFound => SigningError::KeyNotFound,
+            _ => SigningError::Signer(e),
+        })
+    }
+
+    fn sign_one_off<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        _algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<(Signature, PublicKey), SignerError> {
+        // Session objects are destroyed by the token when the session is closed.
+        self.with_session(|session| {
+            let (pub_handle, priv_handle) = self.generate_key_pair(session, false)?;
+            let signature = self.sign_with_key(session, priv_handle, data)?;
+            let key = self.public_key(session, pub_handle)?;
+            Ok((signature, key))
+        })
+    }
+
+    fn rand(&self, target: &mut [u8]) -> Result<(), SignerError> {
+        self.with_session(|session| {
+            let random = self
+                .ctx
+                .generate_random(session, target.len() as CK_ULONG)
+                .map_err(SignerError::pkcs11)?;
+            target.copy_from_slice(&random);
+            Ok(())
+        })
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+pub mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// Requires a SoftHSMv2 token which has been initialised with, e.g.:
+    ///   softhsm2-util --init-token --slot 0 --label krill --so-pin 1234 --pin 1234
+    /// and the path to the library in KRILL_TEST_PKCS11_LIB.
+    #[test]
+    #[ignore]
+    fn should_create_use_and_destroy_key() {
+        let lib_path = PathBuf::from(env::var("KRILL_TEST_PKCS11_LIB").unwrap());
+        let slot: u64 = env::var("KRILL_TEST_PKCS11_SLOT")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(0);
+
+        let mut s = Pkcs11Signer::build(&lib_path, slot, Some("1234")).unwrap();
+        let ki = s.create_key(PublicKeyFormat::default()).unwrap();
+
+        let key = s.get_key_info(&ki).unwrap();
+        assert_eq!(ki, key.key_identifier());
+
+        let signature = s.sign(&ki, SignatureAlgorithm::default(), b"data").unwrap();
+        key.verify(b"data", &signature).unwrap();
+
+        let (signature, key) = s
+            .sign_one_off(SignatureAlgorithm::default(), b"data")
+            .unwrap();
+        key.verify(b"data", &signature).unwrap();
+
+        s.destroy_key(&ki).unwrap();
+        assert!(s.get_key_info(&ki).is_err());
+    }
+
+    /// Requires a SoftHSMv2 token as above. A second signer for the same
+    /// library must not initialise the library again.
+    #[test]
+    #[ignore]
+    fn should_share_library_between_signers() {
+        let lib_path = PathBuf::from(env::var("KRILL_TEST_PKCS11_LIB").unwrap());
+        let slot: u64 = env::var("KRILL_TEST_PKCS11_SLOT")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(0);
+
+        let first = Pkcs11Signer::build(&lib_path, slot, Some("1234")).unwrap();
+        assert!(Pkcs11Signer::build(&lib_path, slot, Some("1234")).is_err());
+
+        let mut second = first.for_slot(slot, Some("1234")).unwrap();
+        let ki = second.create_key(PublicKeyFormat::default()).unwrap();
+        assert!(first.get_key_info(&ki).is_ok());
+        second.destroy_key(&ki).unwrap();
+    }
+}
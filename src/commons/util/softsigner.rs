@@ -1,5 +1,6 @@
 //! Support for signing things using software keys (through openssl) and
 //! storing them unencrypted on disk.
+use std::fmt::Display;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
@@ -218,6 +219,21 @@ pub enum SignerError {
 
     #[display(fmt = "Could not decode key")]
     DecodeError,
+
+    #[display(fmt = "PKCS#11 Error: {}", _0)]
+    Pkcs11Error(String),
+
+    #[display(fmt = "Unknown signer: '{}'", _0)]
+    UnknownSigner(String),
+
+    #[display(fmt = "Signer '{}' is configured more than once", _0)]
+    DuplicateSigner(String),
+}
+
+impl SignerError {
+    pub fn pkcs11(e: impl Display) -> Self {
+        SignerError::Pkcs11Error(e.to_string())
+    }
 }
 
 impl From<ErrorStack> for SignerError {
@@ -1,8 +1,10 @@
 pub const KRILL_VERSION: &str = "0.7.3-plus";
 pub const KRILL_SERVER_APP: &str = "Krill";
 pub const KRILL_CLIENT_APP: &str = "Krill Client";
+pub const KRILL_PUBD_APP: &str = "Krill Publication Server";
 
 pub const KRILL_DEFAULT_CONFIG_FILE: &str = "./defaults/krill.conf";
+pub const KRILL_PUBD_DEFAULT_CONFIG_FILE: &str = "./defaults/krillpubd.conf";
 
 pub const KRILL_ENV_TEST: &str = "KRILL_TEST";
 pub const KRILL_ENV_TEST_ANN: &str = "KRILL_TEST_ANN";
@@ -11,6 +13,8 @@ pub const KRILL_ENV_REPO_ENABLED: &str = "KRILL_REPO_ENABLED";
 pub const KRILL_ENV_USE_TA: &str = "KRILL_USE_TA";
 pub const KRILL_ENV_LOG_LEVEL: &str = "KRILL_LOG_LEVEL";
 pub const KRILL_ENV_AUTH_TOKEN: &str = "KRILL_AUTH_TOKEN";
+pub const KRILL_ENV_PUBD_AUTH_TOKEN: &str = "KRILL_PUBD_AUTH_TOKEN";
+pub const KRILL_ENV_BACKUP_PASSPHRASE: &str = "KRILL_BACKUP_PASSPHRASE";
 
 pub const CASERVER_DIR: &str = "cas";
 
@@ -45,8 +49,32 @@ pub const CHILD_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
 pub const CHILD_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
 pub const ROA_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
 pub const ROA_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
+pub const ROA_AGGREGATE_THRESHOLD_DEFAULT: usize = 100;
+pub const ROA_DEAGGREGATE_THRESHOLD_DEFAULT: usize = 90;
+pub const ASPA_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
+pub const ASPA_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
+pub const BGPSEC_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
+pub const BGPSEC_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
 pub const ID_CERTIFICATE_VALIDITY_YEARS: i32 = 15;
 
 pub const BGP_RIS_REFRESH_MINUTES: i64 = 60;
+pub const BGP_RIS_VISIBILITY_THRESHOLD: u32 = 5;
+pub const BGP_WITHDRAWN_RETENTION_DAYS: i64 = 30;
+pub const BGP_WITHDRAWN_MAX: usize = 100_000;
 
 pub const HTTTP_CLIENT_TIMEOUT_SECS: u64 = 120;
+
+pub const SCHEDULER_MAX_PARENT_SYNCS: usize = 4;
+pub const SCHEDULER_MAX_REPO_SYNCS: usize = 4;
+
+pub const MQ_RETRY_MIN_SECS: i64 = 10;
+pub const MQ_RETRY_MAX_SECS: i64 = 600;
+pub const MQ_MAX_ATTEMPTS: u32 = 10;
+
+pub const EVENTS_RECENT_MAX: usize = 1000;
+pub const EVENTS_ID_BLOCK: u64 = 1000;
+pub const EVENTS_LONG_POLL_SECS: u64 = 30;
+pub const WEBHOOK_DELIVERY_SECONDS: u32 = 10;
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 20;
+pub const WEBHOOK_MAX_BACKOFF_SECS: i64 = 3600;
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Krill-Signature";
@@ -0,0 +1,462 @@
+//! Alerts for announcements which are made invalid by the ROAs of a CA.
+//!
+//! The BGP analysis of each CA is checked whenever announcements or ROAs
+//! are updated. An alert is raised when the number of invalid announcements
+//! for a CA rises, and for each announcement which turns invalid. The
+//! invalid announcements which were alerted are remembered, so that alerts
+//! are not repeated, also not after a restart. An announcement which becomes
+//! valid and then invalid again is alerted again.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+
+use rpki::x509::Time;
+
+use crate::commons::api::{Handle, RoaDefinition};
+use crate::commons::bgp::{BgpAnalysisReport, BgpAnalysisState};
+use crate::commons::util::{file, httpclient};
+
+const ALERTS_DIR: &str = "alerts";
+const STATE_FILE: &str = "state.json";
+
+//------------ AlertSinkConfig -----------------------------------------------
+
+/// Where alerts are sent to.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertSinkConfig {
+    /// POST each alert as JSON to the URI.
+    Webhook { uri: String },
+
+    /// Mail each alert using a local sendmail compatible command.
+    Sendmail {
+        to: String,
+        #[serde(default)]
+        from: Option<String>,
+        #[serde(default = "AlertSinkConfig::default_sendmail_command")]
+        command: String,
+    },
+
+    /// Append each alert as a line of JSON to the file.
+    Log { path: PathBuf },
+}
+
+impl AlertSinkConfig {
+    fn default_sendmail_command() -> String {
+        "/usr/sbin/sendmail".to_string()
+    }
+}
+
+impl fmt::Display for AlertSinkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertSinkConfig::Webhook { uri } => write!(f, "webhook '{}'", uri),
+            AlertSinkConfig::Sendmail { to, .. } => write!(f, "mail to '{}'", to),
+            AlertSinkConfig::Log { path } => write!(f, "alert log '{}'", path.to_string_lossy()),
+        }
+    }
+}
+
+//------------ Alert ---------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Alert {
+    ca: Handle,
+    time: Time,
+    #[serde(flatten)]
+    kind: AlertKind,
+}
+
+impl Alert {
+    pub fn ca(&self) -> &Handle {
+        &self.ca
+    }
+
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    pub fn kind(&self) -> &AlertKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            AlertKind::InvalidsIncreased { before, after } => write!(
+                f,
+                "Invalid announcements for CA '{}' increased from {} to {}",
+                self.ca, before, after
+            ),
+            AlertKind::AnnouncementInvalid {
+                announcement,
+                state,
+                disallowed_by,
+            } => {
+                write!(
+                    f,
+                    "Announcement '{}' is {} because of ROAs of CA '{}'",
+                    announcement, state, self.ca
+                )?;
+                if !disallowed_by.is_empty() {
+                    let roas: Vec<String> = disallowed_by.iter().map(|r| r.to_string()).collect();
+                    write!(f, ", disallowed by: {}", roas.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertKind {
+    /// The number of invalid announcements for the CA increased.
+    InvalidsIncreased { before: usize, after: usize },
+
+    /// An announcement turned invalid, because of the ROAs of the CA.
+    AnnouncementInvalid {
+        announcement: RoaDefinition,
+        state: BgpAnalysisState,
+        disallowed_by: Vec<RoaDefinition>,
+    },
+}
+
+//------------ AlertState ----------------------------------------------------
+
+/// The invalid announcements last seen for each CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct AlertState {
+    invalids: HashMap<Handle, HashSet<RoaDefinition>>,
+}
+
+impl AlertState {
+    /// Updates the known invalids for the CA, and returns the alerts for
+    /// any changes which need attention.
+    fn update(&mut self, ca: &Handle, report: &BgpAnalysisReport, time: Time) -> Vec<Alert> {
+        let entries: Vec<_> = report
+            .entries()
+            .iter()
+            .filter(|e| {
+                e.state() == BgpAnalysisState::AnnouncementInvalidAsn
+                    || e.state() == BgpAnalysisState::AnnouncementInvalidLength
+            })
+            .collect();
+
+        let current: HashSet<RoaDefinition> = entries.iter().map(|e| *e.definition()).collect();
+        let previous = self.invalids.insert(ca.clone(), current.clone());
+        let previous = previous.unwrap_or_default();
+
+        let mut alerts = vec![];
+        if current.len() > previous.len() {
+            alerts.push(Alert {
+                ca: ca.clone(),
+                time,
+                kind: AlertKind::InvalidsIncreased {
+                    before: previous.len(),
+                    after: current.len(),
+                },
+            });
+        }
+
+        for entry in entries {
+            if !previous.contains(entry.definition()) {
+                alerts.push(Alert {
+                    ca: ca.clone(),
+                    time,
+                    kind: AlertKind::AnnouncementInvalid {
+                        announcement: *entry.definition(),
+                        state: entry.state(),
+                        disallowed_by: entry.disallowed_by().clone(),
+                    },
+                });
+            }
+        }
+
+        alerts
+    }
+}
+
+//------------ Alerter -------------------------------------------------------
+
+/// Checks BGP analysis reports for new invalids, and sends alerts to all
+/// configured sinks.
+pub struct Alerter {
+    sinks: Vec<AlertSinkConfig>,
+    state_path: PathBuf,
+    state: Mutex<AlertState>,
+}
+
+impl Alerter {
+    /// Creates an alerter which keeps its state under the work directory.
+    pub fn new(work_dir: &PathBuf, sinks: Vec<AlertSinkConfig>) -> Self {
+        let dir = file::file_path(work_dir, ALERTS_DIR);
+        let state_path = file::file_path(&dir, STATE_FILE);
+
+        let state = if sinks.is_empty() || !state_path.exists() {
+            AlertState::default()
+        } else {
+            file::load_json(&state_path).unwrap_or_else(|e| {
+                warn!("Could not load alert state, will start without: {}", e);
+                AlertState::default()
+            })
+        };
+
+        Alerter {
+            sinks,
+            state_path,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Checks the report for the CA, and sends alerts for new invalids.
+    /// Nothing is checked if no sinks are configured, or if there is no
+    /// announcement information. Failures to send alerts are logged.
+    pub async fn check(&self, ca: &Handle, report: &BgpAnalysisReport) -> Vec<Alert> {
+        if self.sinks.is_empty()
+            || !report
+                .matching_entries(BgpAnalysisState::RoaNoAnnouncementInfo)
+                .is_empty()
+        {
+            return vec![];
+        }
+
+        let alerts = {
+            let mut state = self.state.lock().unwrap();
+            let previous = state.invalids.get(ca).cloned();
+            let alerts = state.update(ca, report, Time::now());
+            if state.invalids.get(ca) != previous.as_ref() {
+                if let Err(e) = file::save_json(&*state, &self.state_path) {
+                    error!("Could not save alert state: {}", e);
+                }
+            }
+            alerts
+        };
+
+        for alert in alerts.iter() {
+            warn!("{}", alert);
+            for sink in self.sinks.iter() {
+                if let Err(e) = Self::send(sink, alert).await {
+                    error!("Could not send alert to {}: {}", sink, e);
+                }
+            }
+        }
+
+        alerts
+    }
+
+    async fn send(sink: &AlertSinkConfig, alert: &Alert) -> Result<(), AlertError> {
+        match sink {
+            AlertSinkConfig::Webhook { uri } => {
+                let body = serde_json::to_string(alert)?;
+                httpclient::client(uri)
+                    .await?
+                    .post(uri)
+                    .header(USER_AGENT, "krill")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(httpclient::Error::RequestError)?;
+                Ok(())
+            }
+            AlertSinkConfig::Sendmail { to, from, command } => {
+                let mut mail = format!("To: {}\n", to);
+                if let Some(from) = from {
+                    mail.push_str(&format!("From: {}\n", from));
+                }
+                mail.push_str(&format!("Subject: Krill alert for CA '{}'\n\n", alert.ca));
+                mail.push_str(&format!("{}\n", alert));
+
+                let mut child = Command::new(command)
+                    .arg("-t")
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                if let Some(stdin) = child.stdin.as_mut() {
+                    stdin.write_all(mail.as_bytes())?;
+                }
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(AlertError::Sendmail(status.to_string()))
+                }
+            }
+            AlertSinkConfig::Log { path } => {
+                let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(log, "{}", serde_json::to_string(alert)?)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+//------------ AlertError ----------------------------------------------------
+
+#[derive(Debug, Display)]
+pub enum AlertError {
+    #[display(fmt = "{}", _0)]
+    Io(io::Error),
+
+    #[display(fmt = "{}", _0)]
+    Json(serde_json::Error),
+
+    #[display(fmt = "{}", _0)]
+    Http(httpclient::Error),
+
+    #[display(fmt = "sendmail exited with {}", _0)]
+    Sendmail(String),
+}
+
+impl From<io::Error> for AlertError {
+    fn from(e: io::Error) -> Self {
+        AlertError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AlertError {
+    fn from(e: serde_json::Error) -> Self {
+        AlertError::Json(e)
+    }
+}
+
+impl From<httpclient::Error> for AlertError {
+    fn from(e: httpclient::Error) -> Self {
+        AlertError::Http(e)
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::str::FromStr;
+
+    use crate::commons::bgp::BgpAnalysisEntry;
+    use crate::test::*;
+
+    use super::*;
+
+    fn report(invalids: &[&str]) -> BgpAnalysisReport {
+        let roa = definition("10.0.0.0/22 => 64496");
+        let mut entries = vec![BgpAnalysisEntry::roa_seen(roa, vec![], vec![])];
+        for invalid in invalids {
+            let announcement = announcement(invalid);
+            let entry = if announcement.asn() == &roa.asn() {
+                BgpAnalysisEntry::announcement_invalid_length(announcement, vec![roa])
+            } else {
+                BgpAnalysisEntry::announcement_invalid_asn(announcement, vec![roa])
+            };
+            entries.push(entry);
+        }
+        BgpAnalysisReport::new(entries)
+    }
+
+    fn kinds(alerts: &[Alert]) -> Vec<&AlertKind> {
+        alerts.iter().map(|a| a.kind()).collect()
+    }
+
+    #[test]
+    fn alert_on_new_invalids_only() {
+        let ca = Handle::from_str("ca").unwrap();
+        let hijack = "10.0.0.0/24 => 64497";
+        let too_specific = "10.0.1.0/24 => 64496";
+        let mut state = AlertState::default();
+
+        let alerts = state.update(&ca, &report(&[hijack]), Time::now());
+        assert_eq!(
+            kinds(&alerts),
+            vec![
+                &AlertKind::InvalidsIncreased {
+                    before: 0,
+                    after: 1
+                },
+                &AlertKind::AnnouncementInvalid {
+                    announcement: definition(hijack),
+                    state: BgpAnalysisState::AnnouncementInvalidAsn,
+                    disallowed_by: vec![definition("10.0.0.0/22 => 64496")]
+                }
+            ]
+        );
+
+        // already known
+        assert!(state
+            .update(&ca, &report(&[hijack]), Time::now())
+            .is_empty());
+
+        // replaced by another invalid: the count did not rise
+        let alerts = state.update(&ca, &report(&[too_specific]), Time::now());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].to_string(),
+            "Announcement '10.0.1.0/24 => 64496' is invalid length because of ROAs of CA 'ca', disallowed by: 10.0.0.0/22 => 64496"
+        );
+
+        // the hijack returns
+        let alerts = state.update(&ca, &report(&[hijack, too_specific]), Time::now());
+        assert_eq!(alerts.len(), 2);
+
+        // fixed
+        assert!(state.update(&ca, &report(&[]), Time::now()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn persist_state_and_log_alerts() {
+        let d = tmp_dir();
+        let log = file::file_path(&d, "alerts.log");
+        let ca = Handle::from_str("ca").unwrap();
+        let sinks = vec![AlertSinkConfig::Log { path: log.clone() }];
+
+        let alerter = Alerter::new(&d, sinks.clone());
+        let alerts = alerter.check(&ca, &report(&["10.0.0.0/24 => 64497"])).await;
+        assert_eq!(alerts.len(), 2);
+
+        let logged = fs::read_to_string(&log).unwrap();
+        let logged: Vec<Alert> = logged
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(logged, alerts);
+
+        // no repeats after a restart
+        let alerter = Alerter::new(&d, sinks);
+        assert!(alerter
+            .check(&ca, &report(&["10.0.0.0/24 => 64497"]))
+            .await
+            .is_empty());
+
+        let _ = fs::remove_dir_all(d);
+    }
+
+    #[tokio::test]
+    async fn alert_on_rebreak_after_restart() {
+        let d = tmp_dir();
+        let log = file::file_path(&d, "alerts.log");
+        let ca = Handle::from_str("ca").unwrap();
+        let sinks = vec![AlertSinkConfig::Log { path: log }];
+        let hijack = "10.0.0.0/24 => 64497";
+
+        let alerter = Alerter::new(&d, sinks.clone());
+        assert_eq!(alerter.check(&ca, &report(&[hijack])).await.len(), 2);
+
+        // fixed, and then Krill restarts
+        assert!(alerter.check(&ca, &report(&[])).await.is_empty());
+        let alerter = Alerter::new(&d, sinks);
+
+        // the hijack returns
+        let alerts = alerter.check(&ca, &report(&[hijack])).await;
+        assert_eq!(alerts.len(), 2);
+
+        let _ = fs::remove_dir_all(d);
+    }
+}
@@ -0,0 +1,413 @@
+//! Authorization for the API
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+
+use crate::commons::api::{Handle, Token};
+use crate::commons::error::Error;
+use crate::commons::KrillResult;
+
+pub mod openid_connect;
+
+use self::openid_connect::OpenIdConnect;
+
+//------------ Authorizer ----------------------------------------------------
+
+/// This type is responsible for checking authorizations when the API is
+/// accessed.
+#[derive(Clone)]
+pub struct Authorizer {
+    krill_auth_token: Token,
+    api_tokens: Vec<ApiToken>,
+    openid_connect: Option<Arc<OpenIdConnect>>,
+
+    // Sessions created by logging in: token -> (actor, expiry time)
+    sessions: Arc<RwLock<HashMap<Token, (Actor, i64)>>>,
+}
+
+impl Authorizer {
+    pub fn new(krill_auth_token: &Token, api_tokens: &[ApiToken]) -> Self {
+        Authorizer {
+            krill_auth_token: krill_auth_token.clone(),
+            api_tokens: api_tokens.to_vec(),
+            openid_connect: None,
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Enables login through an OpenID Connect provider.
+    pub fn with_openid_connect(mut self, openid_connect: OpenIdConnect) -> Self {
+        self.openid_connect = Some(Arc::new(openid_connect));
+        self
+    }
+
+    /// Returns the actor for the given authentication, or None if the
+    /// authentication is not recognised. Session tokens may also be used as
+    /// bearer tokens, e.g. by the UI.
+    pub fn actor(&self, auth: &Auth) -> Option<Actor> {
+        match auth {
+            Auth::Bearer(token) => {
+                if &self.krill_auth_token == token {
+                    Some(Actor::admin())
+                } else {
+                    self.api_tokens
+                        .iter()
+                        .find(|api_token| &api_token.token == token)
+                        .map(ApiToken::actor)
+                        .or_else(|| self.session_actor(token))
+                }
+            }
+            Auth::Session(token) => self.session_actor(token),
+        }
+    }
+
+    fn session_actor(&self, token: &Token) -> Option<Actor> {
+        let sessions = self.sessions.read().unwrap();
+        match sessions.get(token) {
+            Some((actor, expires)) if *expires > Utc::now().timestamp() => Some(actor.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the URI at the OpenID Connect provider where users log in.
+    pub async fn login_uri(&self) -> KrillResult<String> {
+        self.get_openid_connect()?.login_uri().await
+    }
+
+    /// Completes an OpenID Connect login, and returns a new session token
+    /// together with the number of seconds that it is valid.
+    pub async fn login_callback(&self, code: &str, state: &str) -> KrillResult<(Token, i64)> {
+        let openid_connect = self.get_openid_connect()?;
+        let actor = openid_connect.callback(code, state).await?;
+        let ttl = openid_connect.session_ttl();
+
+        info!("User '{}' logged in as '{:?}'", actor.name(), actor.role());
+
+        let token = Token::from(random_hex());
+        let now = Utc::now().timestamp();
+
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.retain(|_, (_, expires)| *expires > now);
+        sessions.insert(token.clone(), (actor, now + ttl));
+
+        Ok((token, ttl))
+    }
+
+    /// Ends a session, if it exists.
+    pub fn logout(&self, token: &Token) {
+        self.sessions.write().unwrap().remove(token);
+    }
+
+    fn get_openid_connect(&self) -> KrillResult<&OpenIdConnect> {
+        self.openid_connect
+            .as_ref()
+            .map(|oidc| oidc.as_ref())
+            .ok_or_else(|| Error::ApiLoginNotConfigured)
+    }
+}
+
+pub enum Auth {
+    Bearer(Token),
+    Session(Token),
+}
+
+impl Auth {
+    pub fn bearer(token: Token) -> Self {
+        Auth::Bearer(token)
+    }
+
+    pub fn session(token: Token) -> Self {
+        Auth::Session(token)
+    }
+}
+
+/// Returns a random hex string, used for session tokens and the state and
+/// nonce in logins.
+fn random_hex() -> String {
+    let mut bytes = [0u8; 20];
+    openssl::rand::rand_bytes(&mut bytes).unwrap();
+    hex::encode(bytes)
+}
+
+//------------ ApiToken ------------------------------------------------------
+
+/// A named API token with a role, as configured in 'api_tokens'. If 'cas' is
+/// set, then the CA permissions of the role only apply to the listed CAs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ApiToken {
+    name: String,
+    token: Token,
+    role: Role,
+    cas: Option<Vec<Handle>>,
+}
+
+impl ApiToken {
+    pub fn new(name: &str, token: Token, role: Role, cas: Option<Vec<Handle>>) -> Self {
+        ApiToken {
+            name: name.to_string(),
+            token,
+            role,
+            cas,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn actor(&self) -> Actor {
+        Actor {
+            name: self.name.clone(),
+            role: self.role,
+            cas: self.cas.clone(),
+        }
+    }
+}
+
+//------------ Role ----------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can do everything.
+    Admin,
+
+    /// Can see all CAs and the publication server, but change nothing.
+    ReadOnly,
+
+    /// Can see CAs and change their ROAs.
+    RoaEditor,
+
+    /// Can see and manage CAs, but not the publication server.
+    CaAdmin,
+
+    /// Can see and manage the publishers of the publication server.
+    PublicationAdmin,
+}
+
+impl Role {
+    fn has(self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::ReadOnly => matches!(permission, Permission::CaRead | Permission::PubRead),
+            Role::RoaEditor => matches!(permission, Permission::CaRead | Permission::CaRoutes),
+            Role::CaAdmin => matches!(
+                permission,
+                Permission::CaRead | Permission::CaRoutes | Permission::CaAdmin
+            ),
+            Role::PublicationAdmin => {
+                matches!(permission, Permission::PubRead | Permission::PubAdmin)
+            }
+        }
+    }
+
+    /// Returns whether this role has all permissions of the other role.
+    pub fn includes(self, other: Role) -> bool {
+        Permission::ALL
+            .iter()
+            .all(|permission| !other.has(*permission) || self.has(*permission))
+    }
+}
+
+//------------ Permission ----------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Permission {
+    /// Show CAs and their details
+    CaRead,
+
+    /// Update the ROAs and ASPAs of a CA
+    CaRoutes,
+
+    /// Manage CAs: add, parents, children, repository, keys
+    CaAdmin,
+
+    /// Show publishers and repository stats
+    PubRead,
+
+    /// Add and remove publishers
+    PubAdmin,
+}
+
+impl Permission {
+    const ALL: [Permission; 5] = [
+        Permission::CaRead,
+        Permission::CaRoutes,
+        Permission::CaAdmin,
+        Permission::PubRead,
+        Permission::PubAdmin,
+    ];
+
+    fn is_ca_permission(self) -> bool {
+        match self {
+            Permission::CaRead | Permission::CaRoutes | Permission::CaAdmin => true,
+            Permission::PubRead | Permission::PubAdmin => false,
+        }
+    }
+}
+
+//------------ Actor ---------------------------------------------------------
+
+/// The party on whose behalf a command is sent. Its name is recorded in the
+/// history of a CA.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Actor {
+    name: String,
+    role: Role,
+    cas: Option<Vec<Handle>>,
+}
+
+impl Actor {
+    pub fn new(name: &str, role: Role, cas: Option<Vec<Handle>>) -> Self {
+        Actor {
+            name: name.to_string(),
+            role,
+            cas,
+        }
+    }
+
+    /// Krill itself, used for commands triggered by background jobs and
+    /// remote parents or children.
+    pub fn system() -> Self {
+        Actor {
+            name: "krill".to_string(),
+            role: Role::Admin,
+            cas: None,
+        }
+    }
+
+    /// The actor using the master 'auth_token'.
+    pub fn admin() -> Self {
+        Actor {
+            name: "admin".to_string(),
+            role: Role::Admin,
+            cas: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Returns whether this actor may see the given CA at all.
+    pub fn can_see_ca(&self, ca: &Handle) -> bool {
+        self.is_allowed(Permission::CaRead, Some(ca))
+    }
+
+    /// Returns whether this actor has the permission, for the CA if given.
+    /// Actors restricted to specific CAs have no CA permissions for things
+    /// that are not specific to a CA, such as bulk operations.
+    pub fn is_allowed(&self, permission: Permission, ca: Option<&Handle>) -> bool {
+        if !self.role.has(permission) {
+            return false;
+        }
+
+        if !permission.is_ca_permission() {
+            return true;
+        }
+
+        match (&self.cas, ca) {
+            (None, _) => true,
+            (Some(cas), Some(ca)) => cas.contains(ca),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn handle(s: &str) -> Handle {
+        Handle::from_str(s).unwrap()
+    }
+
+    fn authorizer() -> Authorizer {
+        let tokens = vec![
+            ApiToken::new("viewer", Token::from("ro"), Role::ReadOnly, None),
+            ApiToken::new(
+                "alice",
+                Token::from("roas"),
+                Role::RoaEditor,
+                Some(vec![handle("ca1")]),
+            ),
+            ApiToken::new("bob", Token::from("cas"), Role::CaAdmin, None),
+            ApiToken::new("carol", Token::from("pub"), Role::PublicationAdmin, None),
+        ];
+        Authorizer::new(&Token::from("secret"), &tokens)
+    }
+
+    fn actor(token: &str) -> Actor {
+        authorizer()
+            .actor(&Auth::bearer(Token::from(token)))
+            .unwrap()
+    }
+
+    #[test]
+    fn should_resolve_actors() {
+        let authorizer = authorizer();
+        assert_eq!(
+            authorizer.actor(&Auth::bearer(Token::from("secret"))),
+            Some(Actor::admin())
+        );
+        assert_eq!(actor("roas").name(), "alice");
+        assert_eq!(actor("pub").role(), Role::PublicationAdmin);
+        assert!(authorizer
+            .actor(&Auth::bearer(Token::from("unknown")))
+            .is_none());
+    }
+
+    #[test]
+    fn should_enforce_roles() {
+        let ca1 = handle("ca1");
+        let ca2 = handle("ca2");
+
+        let admin = actor("secret");
+        assert!(admin.is_allowed(Permission::CaAdmin, None));
+        assert!(admin.is_allowed(Permission::PubAdmin, None));
+
+        let viewer = actor("ro");
+        assert!(viewer.is_allowed(Permission::CaRead, Some(&ca2)));
+        assert!(viewer.is_allowed(Permission::PubRead, None));
+        assert!(!viewer.is_allowed(Permission::CaRoutes, Some(&ca1)));
+        assert!(!viewer.is_allowed(Permission::PubAdmin, None));
+
+        let roa_editor = actor("roas");
+        assert!(roa_editor.is_allowed(Permission::CaRoutes, Some(&ca1)));
+        assert!(!roa_editor.is_allowed(Permission::CaRead, None));
+        assert!(!roa_editor.is_allowed(Permission::CaRoutes, Some(&ca2)));
+        assert!(!roa_editor.can_see_ca(&ca2));
+        assert!(!roa_editor.is_allowed(Permission::CaAdmin, Some(&ca1)));
+        assert!(!roa_editor.is_allowed(Permission::PubRead, None));
+
+        let ca_admin = actor("cas");
+        assert!(ca_admin.is_allowed(Permission::CaAdmin, None));
+        assert!(ca_admin.is_allowed(Permission::CaRoutes, Some(&ca2)));
+        assert!(!ca_admin.is_allowed(Permission::PubRead, None));
+
+        let pub_admin = actor("pub");
+        assert!(pub_admin.is_allowed(Permission::PubAdmin, None));
+        assert!(!pub_admin.is_allowed(Permission::CaRead, Some(&ca1)));
+    }
+}
@@ -0,0 +1,604 @@
+//! Login through an OpenID Connect provider, using the authorization code
+//! flow. The claims in the ID token issued by the provider are mapped to a
+//! Krill role, and optionally to the CAs that the user may access.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use chrono::Utc;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use reqwest::Url;
+use serde_json::Value;
+
+use crate::commons::api::Handle;
+use crate::commons::error::Error;
+use crate::commons::util::httpclient;
+use crate::commons::KrillResult;
+use crate::daemon::auth::{random_hex, Actor, Role};
+
+/// Logins which are not completed within this time are forgotten.
+const PENDING_LOGIN_SECONDS: i64 = 600;
+
+//------------ OpenIdConnectConfig -------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct OpenIdConnectConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+
+    #[serde(default = "OpenIdConnectConfig::default_id_claim")]
+    pub id_claim: String,
+
+    #[serde(default = "OpenIdConnectConfig::default_role_claim")]
+    pub role_claim: String,
+
+    #[serde(default = "OpenIdConnectConfig::default_cas_claim")]
+    pub cas_claim: String,
+
+    #[serde(default)]
+    pub role_map: HashMap<String, Role>,
+
+    #[serde(default = "OpenIdConnectConfig::default_session_ttl")]
+    pub session_ttl: i64,
+}
+
+impl OpenIdConnectConfig {
+    fn default_id_claim() -> String {
+        "email".to_string()
+    }
+
+    fn default_role_claim() -> String {
+        "krill_role".to_string()
+    }
+
+    fn default_cas_claim() -> String {
+        "krill_cas".to_string()
+    }
+
+    fn default_session_ttl() -> i64 {
+        8 * 3600
+    }
+
+    fn issuer(&self) -> &str {
+        self.issuer_url.trim_end_matches('/')
+    }
+}
+
+//------------ OpenIdConnect -------------------------------------------------
+
+/// Performs the relying party side of the authorization code flow.
+pub struct OpenIdConnect {
+    config: OpenIdConnectConfig,
+    redirect_uri: String,
+
+    // Logins in progress: state -> (nonce, time started)
+    pending: RwLock<HashMap<String, (String, i64)>>,
+}
+
+impl OpenIdConnect {
+    pub fn new(config: OpenIdConnectConfig, redirect_uri: String) -> Self {
+        OpenIdConnect {
+            config,
+            redirect_uri,
+            pending: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn session_ttl(&self) -> i64 {
+        self.config.session_ttl
+    }
+
+    /// Returns the URI at the provider to which the user should be sent to
+    /// log in. The provider will send the user back to the redirect URI.
+    pub async fn login_uri(&self) -> KrillResult<String> {
+        let metadata = self.discover().await?;
+
+        let state = random_hex();
+        let nonce = random_hex();
+
+        {
+            let now = Utc::now().timestamp();
+            let mut pending = self.pending.write().unwrap();
+            pending.retain(|_, (_, started)| *started + PENDING_LOGIN_SECONDS > now);
+            pending.insert(state.clone(), (nonce.clone(), now));
+        }
+
+        let uri = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", "openid email profile"),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+            ],
+        )
+        .map_err(|e| Error::ApiLoginFailed(format!("invalid authorization endpoint: {}", e)))?;
+
+        Ok(uri.to_string())
+    }
+
+    /// Completes a login when the provider sends the user back with an
+    /// authorization code. Exchanges the code for an ID token, verifies it,
+    /// and returns the actor described by its claims.
+    pub async fn callback(&self, code: &str, state: &str) -> KrillResult<Actor> {
+        let nonce = {
+            let mut pending = self.pending.write().unwrap();
+            match pending.remove(state) {
+                Some((nonce, started))
+                    if started + PENDING_LOGIN_SECONDS > Utc::now().timestamp() =>
+                {
+                    nonce
+                }
+                _ => {
+                    return Err(Error::ApiLoginFailed(
+                        "unknown or expired state".to_string(),
+                    ))
+                }
+            }
+        };
+
+        let metadata = self.discover().await?;
+
+        let response: TokenResponse = httpclient::post_form_with_response(
+            &metadata.token_endpoint,
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+            ],
+        )
+        .await
+        .map_err(|e| Error::ApiLoginFailed(format!("cannot get token: {}", e)))?;
+
+        let jwks: JsonWebKeySet = httpclient::get_json(&metadata.jwks_uri, None)
+            .await
+            .map_err(|e| Error::ApiLoginFailed(format!("cannot get keys: {}", e)))?;
+
+        let claims = self.verify_id_token(&response.id_token, &jwks, &nonce)?;
+        self.actor(&claims)
+    }
+
+    async fn discover(&self) -> KrillResult<ProviderMetadata> {
+        let uri = format!("{}/.well-known/openid-configuration", self.config.issuer());
+        let metadata: ProviderMetadata = httpclient::get_json(&uri, None)
+            .await
+            .map_err(|e| Error::ApiLoginFailed(format!("cannot discover provider: {}", e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer() {
+            Err(Error::ApiLoginFailed(format!(
+                "provider uses issuer '{}'",
+                metadata.issuer
+            )))
+        } else {
+            Ok(metadata)
+        }
+    }
+
+    /// Verifies the signature (RS256) and the standard claims of an ID token,
+    /// and returns all its claims.
+    fn verify_id_token(
+        &self,
+        id_token: &str,
+        jwks: &JsonWebKeySet,
+        nonce: &str,
+    ) -> KrillResult<Value> {
+        let invalid = |msg: &str| Error::ApiLoginFailed(format!("invalid ID token: {}", msg));
+
+        let parts: Vec<&str> = id_token.split('.').collect();
+        if parts.len() != 3 {
+            return Err(invalid("not a JWS in compact form"));
+        }
+
+        let header: JwtHeader = decode_json(parts[0]).ok_or_else(|| invalid("bad header"))?;
+        let claims: Value = decode_json(parts[1]).ok_or_else(|| invalid("bad claims"))?;
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)
+            .map_err(|_| invalid("bad signature encoding"))?;
+
+        if header.alg != "RS256" {
+            return Err(invalid("only RS256 is supported"));
+        }
+
+        let jwk = jwks
+            .keys
+            .iter()
+            .find(|jwk| jwk.kty == "RSA" && (header.kid.is_none() || jwk.kid == header.kid))
+            .ok_or_else(|| invalid("unknown key"))?;
+
+        let signed = format!("{}.{}", parts[0], parts[1]);
+        if !jwk.verify(signed.as_bytes(), &signature) {
+            return Err(invalid("signature does not verify"));
+        }
+
+        if claims["iss"].as_str().map(|iss| iss.trim_end_matches('/')) != Some(self.config.issuer())
+        {
+            return Err(invalid("wrong issuer"));
+        }
+
+        let audience_ok = match &claims["aud"] {
+            Value::String(aud) => aud == &self.config.client_id,
+            Value::Array(auds) => auds
+                .iter()
+                .any(|aud| aud.as_str() == Some(&self.config.client_id)),
+            _ => false,
+        };
+        if !audience_ok {
+            return Err(invalid("wrong audience"));
+        }
+
+        match claims["exp"].as_i64() {
+            Some(exp) if exp > Utc::now().timestamp() => {}
+            _ => return Err(invalid("expired")),
+        }
+
+        if claims["nonce"].as_str() != Some(nonce) {
+            return Err(invalid("wrong nonce"));
+        }
+
+        Ok(claims)
+    }
+
+    /// Maps the claims to an actor. The role claim may hold a single value,
+    /// or a list (e.g. of groups). Only values found in the 'role_map' give
+    /// a role. If several values do, the most restrictive role is used, and
+    /// the login is rejected if there is no such role.
+    fn actor(&self, claims: &Value) -> KrillResult<Actor> {
+        let name = claims[&self.config.id_claim]
+            .as_str()
+            .or_else(|| claims["sub"].as_str())
+            .ok_or_else(|| Error::ApiLoginFailed("no subject in ID token".to_string()))?;
+
+        let roles: Vec<Role> = string_values(&claims[&self.config.role_claim])
+            .into_iter()
+            .filter_map(|value| self.config.role_map.get(value).copied())
+            .collect();
+
+        if roles.is_empty() {
+            return Err(Error::ApiLoginFailed(format!(
+                "no Krill role in claim '{}'",
+                self.config.role_claim
+            )));
+        }
+
+        let role = roles
+            .iter()
+            .copied()
+            .find(|role| roles.iter().all(|other| other.includes(*role)))
+            .ok_or_else(|| {
+                Error::ApiLoginFailed(format!(
+                    "conflicting Krill roles in claim '{}'",
+                    self.config.role_claim
+                ))
+            })?;
+
+        let cas = match &claims[&self.config.cas_claim] {
+            Value::Null => None,
+            value => {
+                let mut cas = vec![];
+                for ca in string_values(value) {
+                    let handle = Handle::from_str(ca).map_err(|_| {
+                        Error::ApiLoginFailed(format!("invalid CA handle in claims: {}", ca))
+                    })?;
+                    cas.push(handle);
+                }
+                Some(cas)
+            }
+        };
+
+        Ok(Actor::new(name, role, cas))
+    }
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    let bytes = base64::decode_config(part, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn string_values(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(s) => vec![s.as_str()],
+        Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+//------------ Provider Messages ---------------------------------------------
+
+#[derive(Clone, Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct JsonWebKeySet {
+    keys: Vec<JsonWebKey>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct JsonWebKey {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl JsonWebKey {
+    fn verify(&self, signed: &[u8], signature: &[u8]) -> bool {
+        let component = |c: &Option<String>| {
+            c.as_ref()
+                .and_then(|c| base64::decode_config(c, base64::URL_SAFE_NO_PAD).ok())
+                .and_then(|bytes| BigNum::from_slice(&bytes).ok())
+        };
+
+        let key = match (component(&self.n), component(&self.e)) {
+            (Some(n), Some(e)) => Rsa::from_public_components(n, e)
+                .ok()
+                .and_then(|rsa| PKey::from_rsa(rsa).ok()),
+            _ => None,
+        };
+
+        match key {
+            None => false,
+            Some(key) => Verifier::new(MessageDigest::sha256(), &key)
+                .and_then(|mut verifier| {
+                    verifier.update(signed)?;
+                    verifier.verify(signature)
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use serde_json::json;
+
+    use crate::daemon::auth::Permission;
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// A minimal OpenID Connect provider, which issues an ID token for the
+    /// code "good-code", using the nonce set by the test.
+    struct MockProvider {
+        issuer: String,
+        key: Rsa<Private>,
+        nonce: Mutex<Option<String>>,
+        claims: Value,
+    }
+
+    impl MockProvider {
+        fn id_token(&self, nonce: &str) -> String {
+            let header = json!({"alg": "RS256", "kid": "test-key"});
+            let mut claims = self.claims.clone();
+            claims["iss"] = json!(self.issuer);
+            claims["aud"] = json!("krill");
+            claims["exp"] = json!(Utc::now().timestamp() + 300);
+            claims["nonce"] = json!(nonce);
+
+            let signed = format!(
+                "{}.{}",
+                b64(header.to_string().as_bytes()),
+                b64(claims.to_string().as_bytes())
+            );
+
+            let key = PKey::from_rsa(self.key.clone()).unwrap();
+            let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+            signer.update(signed.as_bytes()).unwrap();
+            let signature = signer.sign_to_vec().unwrap();
+
+            format!("{}.{}", signed, b64(&signature))
+        }
+
+        async fn handle(&self, req: Request<Body>) -> Response<Body> {
+            let json = match req.uri().path() {
+                "/.well-known/openid-configuration" => json!({
+                    "issuer": self.issuer,
+                    "authorization_endpoint": format!("{}/authorize", self.issuer),
+                    "token_endpoint": format!("{}/token", self.issuer),
+                    "jwks_uri": format!("{}/jwks", self.issuer),
+                }),
+                "/jwks" => json!({
+                    "keys": [{
+                        "kty": "RSA",
+                        "kid": "test-key",
+                        "n": b64(&self.key.n().to_vec()),
+                        "e": b64(&self.key.e().to_vec()),
+                    }]
+                }),
+                "/token" => {
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    let body = String::from_utf8(body.to_vec()).unwrap();
+                    if !body.contains("code=good-code") {
+                        return Response::builder().status(400).body(Body::empty()).unwrap();
+                    }
+                    let nonce = self.nonce.lock().unwrap().clone().unwrap();
+                    json!({ "id_token": self.id_token(&nonce), "token_type": "Bearer" })
+                }
+                _ => return Response::builder().status(404).body(Body::empty()).unwrap(),
+            };
+            Response::new(Body::from(json.to_string()))
+        }
+    }
+
+    async fn start_provider(claims: Value) -> Arc<MockProvider> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let provider = Arc::new(MockProvider {
+            issuer: format!("http://127.0.0.1:{}", port),
+            key: Rsa::generate(2048).unwrap(),
+            nonce: Mutex::new(None),
+            claims,
+        });
+
+        let service_provider = provider.clone();
+        let service = make_service_fn(move |_| {
+            let provider = service_provider.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let provider = provider.clone();
+                    async move { Ok::<_, Infallible>(provider.handle(req).await) }
+                }))
+            }
+        });
+
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(service));
+        provider
+    }
+
+    fn client(provider: &MockProvider) -> OpenIdConnect {
+        let config = OpenIdConnectConfig {
+            issuer_url: format!("{}/", provider.issuer),
+            client_id: "krill".to_string(),
+            client_secret: "client-secret".to_string(),
+            id_claim: OpenIdConnectConfig::default_id_claim(),
+            role_claim: "groups".to_string(),
+            cas_claim: OpenIdConnectConfig::default_cas_claim(),
+            role_map: vec![("rpki-operators".to_string(), Role::RoaEditor)]
+                .into_iter()
+                .collect(),
+            session_ttl: 60,
+        };
+        OpenIdConnect::new(config, "https://localhost:3000/auth/callback".to_string())
+    }
+
+    fn query_param(uri: &str, name: &str) -> String {
+        Url::parse(uri)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn login_with_mock_provider() {
+        let claims = json!({
+            "sub": "1234",
+            "email": "alice@example.com",
+            "groups": [ "staff", "rpki-operators" ],
+            "krill_cas": [ "ca1" ]
+        });
+        let provider = start_provider(claims).await;
+        let oidc = client(&provider);
+
+        let login_uri = oidc.login_uri().await.unwrap();
+        assert!(login_uri.starts_with(&format!("{}/authorize?", provider.issuer)));
+        assert_eq!(query_param(&login_uri, "client_id"), "krill");
+
+        let state = query_param(&login_uri, "state");
+        let nonce = query_param(&login_uri, "nonce");
+        *provider.nonce.lock().unwrap() = Some(nonce);
+
+        assert!(oidc.callback("bad-code", &state).await.is_err());
+
+        // the state was used up by the failed attempt
+        let login_uri = oidc.login_uri().await.unwrap();
+        let state = query_param(&login_uri, "state");
+        let nonce = query_param(&login_uri, "nonce");
+        *provider.nonce.lock().unwrap() = Some(nonce);
+
+        let actor = oidc.callback("good-code", &state).await.unwrap();
+        assert_eq!(actor.name(), "alice@example.com");
+        assert_eq!(actor.role(), Role::RoaEditor);
+
+        let ca1 = Handle::from_str("ca1").unwrap();
+        let ca2 = Handle::from_str("ca2").unwrap();
+        assert!(actor.is_allowed(Permission::CaRoutes, Some(&ca1)));
+        assert!(!actor.is_allowed(Permission::CaRoutes, Some(&ca2)));
+
+        // states cannot be replayed
+        assert!(oidc.callback("good-code", &state).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reject_token_with_wrong_nonce_or_without_role() {
+        let claims = json!({ "sub": "1234", "groups": "admin" });
+        let provider = start_provider(claims).await;
+        let oidc = client(&provider);
+
+        let login_uri = oidc.login_uri().await.unwrap();
+        let state = query_param(&login_uri, "state");
+        *provider.nonce.lock().unwrap() = Some("other-nonce".to_string());
+        assert!(oidc.callback("good-code", &state).await.is_err());
+
+        // Role names are not accepted unless they are in the role map
+        let login_uri = oidc.login_uri().await.unwrap();
+        let state = query_param(&login_uri, "state");
+        let nonce = query_param(&login_uri, "nonce");
+        *provider.nonce.lock().unwrap() = Some(nonce);
+        assert!(oidc.callback("good-code", &state).await.is_err());
+
+        let no_role = json!({ "sub": "1234", "groups": [ "staff" ] });
+        assert!(oidc.actor(&no_role).is_err());
+    }
+
+    #[test]
+    fn use_most_restrictive_mapped_role() {
+        let config = OpenIdConnectConfig {
+            issuer_url: "https://login.example.com/".to_string(),
+            client_id: "krill".to_string(),
+            client_secret: "client-secret".to_string(),
+            id_claim: OpenIdConnectConfig::default_id_claim(),
+            role_claim: "groups".to_string(),
+            cas_claim: OpenIdConnectConfig::default_cas_claim(),
+            role_map: vec![
+                ("rpki-admins".to_string(), Role::Admin),
+                ("rpki-operators".to_string(), Role::CaAdmin),
+                ("roa-editors".to_string(), Role::RoaEditor),
+                ("noc".to_string(), Role::ReadOnly),
+            ]
+            .into_iter()
+            .collect(),
+            session_ttl: 60,
+        };
+        let oidc = OpenIdConnect::new(config, "https://localhost:3000/auth/callback".to_string());
+
+        let claims =
+            json!({ "sub": "1234", "groups": [ "rpki-admins", "roa-editors", "rpki-operators" ] });
+        assert_eq!(oidc.actor(&claims).unwrap().role(), Role::RoaEditor);
+
+        let claims = json!({ "sub": "1234", "groups": [ "admin", "noc" ] });
+        assert_eq!(oidc.actor(&claims).unwrap().role(), Role::ReadOnly);
+
+        // neither role includes the other
+        let claims = json!({ "sub": "1234", "groups": [ "roa-editors", "noc" ] });
+        assert!(oidc.actor(&claims).is_err());
+    }
+}
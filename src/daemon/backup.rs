@@ -0,0 +1,578 @@
+//! Backup and restore of the complete state of a Krill server.
+//!
+//! A backup contains the stores of all CAs and the embedded repository, the
+//! keys of all openssl signers, the repository files and the configuration.
+//! It is taken while the server is running, and can only be restored into a
+//! data directory which does not contain any of this state yet. The signer
+//! keys and the configuration, which holds secrets such as the auth token,
+//! are encrypted if a passphrase is given.
+//!
+//! Keys kept in a PKCS#11 signer cannot be exported, and are therefore not
+//! included. They must be backed up using the tools of the HSM.
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use libflate::gzip::{Decoder, Encoder};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use rpki::x509::Time;
+
+use crate::commons::api::Base64;
+use crate::commons::error::Error;
+use crate::commons::eventsourcing::{
+    AggregateStoreError, KeyStoreVersion, StorageType, StoreBackup,
+};
+use crate::commons::util::file;
+use crate::constants::{KRILL_VERSION, PUBSERVER_DIR, REPOSITORY_DIR};
+
+/// The version of the backup format. Backups in any other format are not
+/// restored.
+const BACKUP_FORMAT: u64 = 1;
+
+/// The directories under the data directory where openssl signers keep
+/// their keys.
+pub const KEY_DIRS: &[&str] = &["keys", "signers"];
+
+/// The directories under the data directory with the repository files.
+pub const FILE_DIRS: &[&str] = &[REPOSITORY_DIR];
+
+/// The name of the file in the data directory where the configuration from
+/// the backup is saved on restore. It is not used by the restored server
+/// unless it is copied to where the server expects its configuration.
+pub const RESTORED_CONFIG_FILE: &str = "krill.conf.restored";
+
+const PBKDF2_ITERATIONS: usize = 100_000;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+//------------ KrillBackup ---------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct KrillBackup {
+    format: u64,
+    krill_version: String,
+    created: Time,
+    data_dir: PathBuf,
+    stores: Vec<StoreBackup>,
+    secrets: SecretsBackup,
+    files: Vec<BackupFile>,
+}
+
+impl KrillBackup {
+    pub fn new(
+        data_dir: &PathBuf,
+        stores: Vec<StoreBackup>,
+        keys: Vec<BackupFile>,
+        files: Vec<BackupFile>,
+        config: Option<String>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, BackupError> {
+        Ok(KrillBackup {
+            format: BACKUP_FORMAT,
+            krill_version: KRILL_VERSION.to_string(),
+            created: Time::now(),
+            data_dir: data_dir.clone(),
+            stores,
+            secrets: SecretsBackup::new(Secrets { keys, config }, passphrase)?,
+            files,
+        })
+    }
+
+    pub fn krill_version(&self) -> &str {
+        &self.krill_version
+    }
+
+    pub fn created(&self) -> Time {
+        self.created
+    }
+
+    pub fn stores(&self) -> &Vec<StoreBackup> {
+        &self.stores
+    }
+
+    pub fn secrets_encrypted(&self) -> bool {
+        matches!(self.secrets, SecretsBackup::Encrypted { .. })
+    }
+
+    /// Returns the backup as gzipped json.
+    pub fn encode(&self) -> Result<Vec<u8>, BackupError> {
+        let json = serde_json::to_vec(self)?;
+        let mut encoder = Encoder::new(vec![])?;
+        encoder.write_all(&json)?;
+        Ok(encoder.finish().into_result()?)
+    }
+
+    /// Parses a backup from gzipped json. The format is checked before the
+    /// backup is parsed, so that backups in a newer format are reported as
+    /// such.
+    pub fn decode(bytes: &[u8]) -> Result<Self, BackupError> {
+        let mut json = vec![];
+        let mut decoder = Decoder::new(bytes)?;
+        decoder.read_to_end(&mut json)?;
+
+        let value: serde_json::Value = serde_json::from_slice(&json)?;
+        match value.get("format").and_then(|f| f.as_u64()) {
+            Some(BACKUP_FORMAT) => Ok(serde_json::from_value(value)?),
+            format => Err(BackupError::UnsupportedFormat(format.unwrap_or(0))),
+        }
+    }
+
+    /// Restores the backup into the data directory. Everything is verified
+    /// before anything is written: the versions of all stores, the
+    /// passphrase if the secrets are encrypted, and that the data directory
+    /// does not contain any of the state in the backup.
+    ///
+    /// Returns the path where the configuration was saved, if the backup
+    /// included it.
+    pub fn restore(
+        &self,
+        data_dir: &PathBuf,
+        storage_type: StorageType,
+        passphrase: Option<&str>,
+    ) -> Result<Option<PathBuf>, BackupError> {
+        for store in self.stores.iter() {
+            if store.version() != &KeyStoreVersion::current() {
+                return Err(BackupError::Store(AggregateStoreError::UnsupportedVersion(
+                    store.name().to_string(),
+                    store.version().clone(),
+                )));
+            }
+        }
+
+        // The repository keeps the absolute path of its files in its state.
+        if self.stores.iter().any(|s| s.name() == PUBSERVER_DIR) && data_dir != &self.data_dir {
+            return Err(BackupError::DataDirMismatch(
+                self.data_dir.to_string_lossy().to_string(),
+            ));
+        }
+
+        let Secrets { keys, config } = self.secrets.decrypt(passphrase)?;
+
+        let store_names = self.stores.iter().map(|s| s.name());
+        let dir_names = KEY_DIRS.iter().chain(FILE_DIRS.iter()).cloned();
+        for name in store_names.chain(dir_names) {
+            if data_dir.join(name).exists() {
+                return Err(BackupError::NotEmpty(name.to_string()));
+            }
+        }
+        for file in keys.iter().chain(self.files.iter()) {
+            file.verify_path()?;
+        }
+
+        for store in self.stores.iter() {
+            store.restore(data_dir, storage_type)?;
+        }
+        for file in keys.iter().chain(self.files.iter()) {
+            file.write(data_dir)?;
+        }
+
+        match config {
+            None => Ok(None),
+            Some(config) => {
+                let path = data_dir.join(RESTORED_CONFIG_FILE);
+                file::save(config.as_bytes(), &path)?;
+                Ok(Some(path))
+            }
+        }
+    }
+}
+
+//------------ Secrets -------------------------------------------------------
+
+/// The key files of the openssl signers and the configuration.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Secrets {
+    keys: Vec<BackupFile>,
+    config: Option<String>,
+}
+
+//------------ SecretsBackup -------------------------------------------------
+
+/// The secrets in a backup, optionally encrypted using AES-256-GCM with a
+/// key derived from a passphrase.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SecretsBackup {
+    Plain(Secrets),
+    Encrypted {
+        salt: Base64,
+        iv: Base64,
+        tag: Base64,
+        data: Base64,
+    },
+}
+
+impl SecretsBackup {
+    fn new(secrets: Secrets, passphrase: Option<&str>) -> Result<Self, BackupError> {
+        match passphrase {
+            None => Ok(SecretsBackup::Plain(secrets)),
+            Some(passphrase) => {
+                let mut salt = [0u8; SALT_LEN];
+                let mut iv = [0u8; IV_LEN];
+                let mut tag = [0u8; TAG_LEN];
+                rand_bytes(&mut salt)?;
+                rand_bytes(&mut iv)?;
+
+                let key = Self::derive_key(passphrase, &salt)?;
+                let plain = serde_json::to_vec(&secrets)?;
+                let data = encrypt_aead(
+                    Cipher::aes_256_gcm(),
+                    &key,
+                    Some(&iv),
+                    &[],
+                    &plain,
+                    &mut tag,
+                )?;
+
+                Ok(SecretsBackup::Encrypted {
+                    salt: Base64::from_content(&salt),
+                    iv: Base64::from_content(&iv),
+                    tag: Base64::from_content(&tag),
+                    data: Base64::from_content(&data),
+                })
+            }
+        }
+    }
+
+    fn decrypt(&self, passphrase: Option<&str>) -> Result<Secrets, BackupError> {
+        match self {
+            SecretsBackup::Plain(secrets) => Ok(secrets.clone()),
+            SecretsBackup::Encrypted {
+                salt,
+                iv,
+                tag,
+                data,
+            } => {
+                let passphrase = passphrase.ok_or(BackupError::PassphraseRequired)?;
+                let key = Self::derive_key(passphrase, &salt.to_bytes())?;
+                let plain = decrypt_aead(
+                    Cipher::aes_256_gcm(),
+                    &key,
+                    Some(&iv.to_bytes()),
+                    &[],
+                    &data.to_bytes(),
+                    &tag.to_bytes(),
+                )
+                .map_err(|_| BackupError::PassphraseWrong)?;
+                Ok(serde_json::from_slice(&plain)?)
+            }
+        }
+    }
+
+    fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], BackupError> {
+        let mut key = [0u8; KEY_LEN];
+        pbkdf2_hmac(
+            passphrase.as_bytes(),
+            salt,
+            PBKDF2_ITERATIONS,
+            MessageDigest::sha256(),
+            &mut key,
+        )?;
+        Ok(key)
+    }
+}
+
+//------------ BackupFile ----------------------------------------------------
+
+/// A file under the data directory, with its path relative to it.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BackupFile {
+    path: PathBuf,
+    content: Base64,
+}
+
+impl BackupFile {
+    /// Reads all files found under the given directories of the data
+    /// directory. Directories which do not exist are skipped.
+    pub fn read_dirs(data_dir: &PathBuf, dirs: &[&str]) -> io::Result<Vec<BackupFile>> {
+        let mut res = vec![];
+        for dir in dirs {
+            Self::read_dir(data_dir, &data_dir.join(dir), &mut res)?;
+        }
+        Ok(res)
+    }
+
+    fn read_dir(data_dir: &PathBuf, dir: &Path, res: &mut Vec<BackupFile>) -> io::Result<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::read_dir(data_dir, &path, res)?;
+            } else if let Ok(rel) = path.strip_prefix(data_dir) {
+                res.push(BackupFile {
+                    path: rel.to_path_buf(),
+                    content: Base64::from_content(&file::read(&path)?),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Paths must stay inside the data directory.
+    fn verify_path(&self) -> Result<(), BackupError> {
+        if self
+            .path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            Ok(())
+        } else {
+            Err(BackupError::InvalidPath(
+                self.path.to_string_lossy().to_string(),
+            ))
+        }
+    }
+
+    fn write(&self, data_dir: &PathBuf) -> io::Result<()> {
+        file::save(&self.content.to_bytes(), &data_dir.join(&self.path))
+    }
+}
+
+//------------ BackupError ---------------------------------------------------
+
+#[derive(Debug, Display)]
+pub enum BackupError {
+    #[display(fmt = "{}", _0)]
+    Io(io::Error),
+
+    #[display(fmt = "Invalid backup: {}", _0)]
+    Json(serde_json::Error),
+
+    #[display(fmt = "{}", _0)]
+    Store(AggregateStoreError),
+
+    #[display(fmt = "Cannot encrypt or decrypt the signer keys and config: {}", _0)]
+    Crypto(ErrorStack),
+
+    #[display(fmt = "Unsupported backup format '{}'", _0)]
+    UnsupportedFormat(u64),
+
+    #[display(fmt = "Backup includes a repository, it must be restored to '{}'", _0)]
+    DataDirMismatch(String),
+
+    #[display(
+        fmt = "The signer keys and config in the backup are encrypted, a passphrase is required"
+    )]
+    PassphraseRequired,
+
+    #[display(fmt = "Cannot decrypt the signer keys and config in the backup, wrong passphrase")]
+    PassphraseWrong,
+
+    #[display(fmt = "Data directory already contains '{}'", _0)]
+    NotEmpty(String),
+
+    #[display(fmt = "Invalid file path in backup: {}", _0)]
+    InvalidPath(String),
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        BackupError::Json(e)
+    }
+}
+
+impl From<AggregateStoreError> for BackupError {
+    fn from(e: AggregateStoreError) -> Self {
+        BackupError::Store(e)
+    }
+}
+
+impl From<ErrorStack> for BackupError {
+    fn from(e: ErrorStack) -> Self {
+        BackupError::Crypto(e)
+    }
+}
+
+impl From<BackupError> for Error {
+    fn from(e: BackupError) -> Self {
+        Error::BackupError(e.to_string())
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use crate::commons::api::Handle;
+    use crate::commons::eventsourcing::{AnyKeyStore, KeyStore};
+    use crate::constants::CASERVER_DIR;
+    use crate::test;
+
+    fn store_backup(work_dir: &PathBuf) -> StoreBackup {
+        let store = AnyKeyStore::under_work_dir(work_dir, CASERVER_DIR, StorageType::Disk).unwrap();
+        store.set_version(&KeyStoreVersion::current()).unwrap();
+
+        let handle = Handle::from_str("ca").unwrap();
+        let key = PathBuf::from("delta-0.json");
+        store.store(&handle, &key, &"init").unwrap();
+        store
+            .store_archive_data(&handle, "index", b"archived")
+            .unwrap();
+
+        StoreBackup::copy(CASERVER_DIR, &store).unwrap()
+    }
+
+    fn key_files(data_dir: &PathBuf) -> Vec<BackupFile> {
+        file::save(b"key", &data_dir.join("keys").join("A1B2")).unwrap();
+        file::save(b"other", &data_dir.join("signers/hsm-less/keys/C3D4")).unwrap();
+        BackupFile::read_dirs(data_dir, KEY_DIRS).unwrap()
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        test::test_under_tmp(|d| {
+            let source = d.join("source");
+            let target = d.join("target");
+
+            let stores = vec![store_backup(&source)];
+            let keys = key_files(&source);
+            assert_eq!(2, keys.len());
+
+            let config = Some("data_dir = \"/tmp\"".to_string());
+            let backup = KrillBackup::new(&source, stores, keys, vec![], config, None).unwrap();
+            assert!(!backup.secrets_encrypted());
+
+            let decoded = KrillBackup::decode(&backup.encode().unwrap()).unwrap();
+            let restored_config = decoded
+                .restore(&target, StorageType::Sqlite, None)
+                .unwrap()
+                .unwrap();
+
+            let store =
+                AnyKeyStore::under_work_dir(&target, CASERVER_DIR, StorageType::Sqlite).unwrap();
+            let handle = Handle::from_str("ca").unwrap();
+            assert_eq!(KeyStoreVersion::current(), store.get_version().unwrap());
+            let init: Option<String> = store.get(&handle, &PathBuf::from("delta-0.json")).unwrap();
+            assert_eq!(Some("init".to_string()), init);
+            assert_eq!(
+                Some(b"archived".to_vec()),
+                store.get_archive_data(&handle, "index").unwrap()
+            );
+
+            assert_eq!(
+                b"key".to_vec(),
+                file::read(&target.join("keys/A1B2")).unwrap().to_vec()
+            );
+            assert_eq!(
+                b"other".to_vec(),
+                file::read(&target.join("signers/hsm-less/keys/C3D4"))
+                    .unwrap()
+                    .to_vec()
+            );
+            assert!(restored_config.is_file());
+
+            // Nothing is restored over existing state.
+            match decoded.restore(&target, StorageType::Disk, None) {
+                Err(BackupError::NotEmpty(name)) => assert_eq!(CASERVER_DIR, name),
+                other => panic!("Expected not empty error, got: {:?}", other.map(|_| ())),
+            }
+        })
+    }
+
+    #[test]
+    fn restore_encrypted_secrets() {
+        test::test_under_tmp(|d| {
+            let source = d.join("source");
+            let keys = key_files(&source);
+
+            let config = Some("auth_token = \"secret-token\"".to_string());
+            let backup =
+                KrillBackup::new(&source, vec![], keys, vec![], config, Some("secret")).unwrap();
+            assert!(backup.secrets_encrypted());
+            let json = serde_json::to_string(&backup).unwrap();
+            assert!(!json.contains(&Base64::from_content(b"key").as_ref().to_string()));
+            assert!(!json.contains("secret-token"));
+
+            let target = d.join("target");
+            assert!(matches!(
+                backup.restore(&target, StorageType::Disk, None),
+                Err(BackupError::PassphraseRequired)
+            ));
+            assert!(matches!(
+                backup.restore(&target, StorageType::Disk, Some("wrong")),
+                Err(BackupError::PassphraseWrong)
+            ));
+            assert!(!target.join("keys").exists());
+
+            let restored_config = backup
+                .restore(&target, StorageType::Disk, Some("secret"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                b"key".to_vec(),
+                file::read(&target.join("keys/A1B2")).unwrap().to_vec()
+            );
+            assert_eq!(
+                b"auth_token = \"secret-token\"".to_vec(),
+                file::read(&restored_config).unwrap().to_vec()
+            );
+        })
+    }
+
+    #[test]
+    fn reject_unsupported_backups() {
+        test::test_under_tmp(|d| {
+            let source = d.join("source");
+            let target = d.join("target");
+
+            let mut backup = KrillBackup::new(
+                &source,
+                vec![store_backup(&source)],
+                vec![],
+                vec![],
+                None,
+                None,
+            )
+            .unwrap();
+
+            let mut json = serde_json::to_value(&backup).unwrap();
+            json["stores"][0]["version"] = serde_json::json!("V0_7");
+            backup = serde_json::from_value(json.clone()).unwrap();
+            assert!(matches!(
+                backup.restore(&target, StorageType::Disk, None),
+                Err(BackupError::Store(AggregateStoreError::UnsupportedVersion(
+                    _,
+                    KeyStoreVersion::V0_7
+                )))
+            ));
+            assert!(!target.join(CASERVER_DIR).exists());
+
+            json["format"] = serde_json::json!(BACKUP_FORMAT + 1);
+            let mut encoder = Encoder::new(vec![]).unwrap();
+            encoder
+                .write_all(&serde_json::to_vec(&json).unwrap())
+                .unwrap();
+            let bytes = encoder.finish().into_result().unwrap();
+            assert!(matches!(
+                KrillBackup::decode(&bytes),
+                Err(BackupError::UnsupportedFormat(2))
+            ));
+        })
+    }
+}
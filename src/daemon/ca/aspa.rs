@@ -0,0 +1,388 @@
+//! AS Provider Authorization (ASPA) objects.
+//!
+//! An ASPA object is an RPKI signed object in which the holder of a customer
+//! AS attests the set of provider ASes that may propagate its routes. See
+//! draft-ietf-sidrops-aspa-profile for details.
+use std::collections::HashMap;
+
+use bcder::encode::{PrimitiveContent, Values};
+use bcder::{decode, encode};
+use bcder::{Captured, Mode, Oid, Tag};
+use bytes::Bytes;
+
+use rpki::cert::Cert;
+use rpki::crypto::{Signer as RpkiSigner, SigningError};
+use rpki::resources::{AsBlocksBuilder, AsId, AsResources};
+use rpki::sigobj::{SignedObject, SignedObjectBuilder};
+use rpki::uri;
+use rpki::x509::{Serial, Time};
+
+use crate::commons::api::{
+    AsNumber, AspaDefinition, AspaDefinitionList, AspaProvidersUpdate, CurrentObject, ObjectName,
+    ReplacedObject,
+};
+use crate::commons::KrillResult;
+use crate::constants::ASPA_CERTIFICATE_VALIDITY_YEARS;
+use crate::daemon::ca::events::AspaObjectsUpdates;
+use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
+
+/// The content type for ASPA objects: 1.2.840.113549.1.9.16.1.49
+const ASPA_CONTENT_TYPE: [u8; 11] = [42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 49];
+
+/// The version of the ASPA profile that is produced.
+const ASPA_VERSION: u8 = 1;
+
+//------------ AspaDefinitions ---------------------------------------------
+
+/// The ASPA definitions configured for a CA, keyed by customer ASN.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitions {
+    attestations: HashMap<AsNumber, AspaDefinition>,
+}
+
+impl AspaDefinitions {
+    pub fn get(&self, customer: AsNumber) -> Option<&AspaDefinition> {
+        self.attestations.get(&customer)
+    }
+
+    pub fn has(&self, customer: AsNumber) -> bool {
+        self.attestations.contains_key(&customer)
+    }
+
+    /// Adds a new definition, or replaces the existing definition for the
+    /// same customer ASN.
+    pub fn add_or_replace(&mut self, definition: AspaDefinition) {
+        self.attestations.insert(definition.customer(), definition);
+    }
+
+    pub fn apply_update(&mut self, customer: AsNumber, update: &AspaProvidersUpdate) {
+        if let Some(definition) = self.attestations.get_mut(&customer) {
+            definition.apply_update(update);
+        }
+    }
+
+    pub fn remove(&mut self, customer: AsNumber) {
+        self.attestations.remove(&customer);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &AspaDefinition> {
+        self.attestations.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attestations.is_empty()
+    }
+
+    pub fn as_list(&self) -> AspaDefinitionList {
+        AspaDefinitionList::new(self.attestations.values().cloned().collect())
+    }
+}
+
+//------------ AspaInfo ----------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaInfo {
+    definition: AspaDefinition,       // definition used for the object
+    object: CurrentObject,            // actual ASPA
+    name: ObjectName,                 // Name for object in repo
+    since: Time,                      // first ASPA in RC created
+    replaces: Option<ReplacedObject>, // for revoking when re-newing
+}
+
+impl AspaInfo {
+    pub fn new_aspa(definition: AspaDefinition, aspa: &Aspa) -> Self {
+        let object = CurrentObject::from(aspa);
+        let name = ObjectName::aspa(definition.customer());
+        AspaInfo {
+            definition,
+            object,
+            name,
+            since: Time::now(),
+            replaces: None,
+        }
+    }
+
+    pub fn updated_aspa(old: &AspaInfo, definition: AspaDefinition, aspa: &Aspa) -> Self {
+        let object = CurrentObject::from(aspa);
+        let name = ObjectName::aspa(definition.customer());
+        let replaces = Some(ReplacedObject::from(old.object()));
+        AspaInfo {
+            definition,
+            object,
+            name,
+            since: old.since,
+            replaces,
+        }
+    }
+
+    pub fn definition(&self) -> &AspaDefinition {
+        &self.definition
+    }
+
+    pub fn object(&self) -> &CurrentObject {
+        &self.object
+    }
+
+    pub fn name(&self) -> &ObjectName {
+        &self.name
+    }
+
+    pub fn since(&self) -> Time {
+        self.since
+    }
+
+    pub fn replaces(&self) -> Option<&ReplacedObject> {
+        self.replaces.as_ref()
+    }
+}
+
+//------------ AspaObjects -------------------------------------------------
+
+/// ASPA objects held by a resource class in a CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaObjects {
+    inner: HashMap<AsNumber, AspaInfo>,
+}
+
+impl AspaObjects {
+    pub fn get(&self, customer: AsNumber) -> Option<&AspaInfo> {
+        self.inner.get(&customer)
+    }
+
+    pub fn updated(&mut self, updates: AspaObjectsUpdates) {
+        let (updated, removed) = updates.unpack();
+
+        for (customer, info) in updated.into_iter() {
+            self.inner.insert(customer, info);
+        }
+
+        for customer in removed.keys() {
+            self.inner.remove(customer);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AsNumber, &AspaInfo)> {
+        self.inner.iter()
+    }
+
+    pub fn current(&self) -> impl Iterator<Item = &AspaInfo> {
+        self.inner.values()
+    }
+
+    pub fn customers(&self) -> impl Iterator<Item = &AsNumber> {
+        self.inner.keys()
+    }
+
+    pub fn make_aspa<S: Signer>(
+        definition: &AspaDefinition,
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<Aspa> {
+        let name = ObjectName::aspa(definition.customer());
+
+        let incoming_cert = certified_key.incoming_cert();
+        let crl_uri = match &new_repo {
+            None => incoming_cert.crl_uri(),
+            Some(base_uri) => base_uri.join(incoming_cert.crl_name().as_bytes()),
+        };
+
+        let aspa_uri = match &new_repo {
+            None => incoming_cert.uri_for_object(name),
+            Some(base_uri) => base_uri.join(name.as_bytes()),
+        };
+
+        let aia = incoming_cert.uri();
+
+        let signing_key = certified_key.key_id();
+
+        let mut object_builder = SignedObjectBuilder::new(
+            Serial::random(signer).map_err(ca::Error::signer)?,
+            SignSupport::sign_validity_years(ASPA_CERTIFICATE_VALIDITY_YEARS),
+            crl_uri,
+            aia.clone(),
+            aspa_uri,
+        );
+        object_builder.set_issuer(Some(incoming_cert.cert().subject().clone()));
+        object_builder.set_signing_time(Some(Time::now()));
+
+        AspaBuilder::new(definition)
+            .finalize(object_builder, signer, signing_key)
+            .map_err(ca::Error::signer)
+    }
+}
+
+//------------ Aspa --------------------------------------------------------
+
+/// A signed ASPA object.
+#[derive(Clone, Debug)]
+pub struct Aspa {
+    signed: SignedObject,
+    content: AsProviderAttestation,
+}
+
+impl Aspa {
+    pub fn decode<S: decode::Source>(source: S, strict: bool) -> Result<Self, S::Err> {
+        let signed = SignedObject::decode(source, strict)?;
+        if signed.content_type().as_ref() != &ASPA_CONTENT_TYPE[..] {
+            return Err(decode::Malformed.into());
+        }
+        let content = signed.decode_content(AsProviderAttestation::take_from)?;
+        Ok(Aspa { signed, content })
+    }
+
+    pub fn content(&self) -> &AsProviderAttestation {
+        &self.content
+    }
+
+    /// Returns a value encoder for a reference to an ASPA.
+    pub fn encode_ref<'a>(&'a self) -> impl encode::Values + 'a {
+        self.signed.encode_ref()
+    }
+
+    /// Returns a DER encoded Captured for this.
+    pub fn to_captured(&self) -> Captured {
+        self.encode_ref().to_captured(Mode::Der)
+    }
+
+    /// Returns a reference to the EE certificate of this ASPA.
+    pub fn cert(&self) -> &Cert {
+        self.signed.cert()
+    }
+}
+
+//------------ AsProviderAttestation ---------------------------------------
+
+/// The content of an ASPA object.
+///
+/// ```text
+/// ASProviderAttestation ::= SEQUENCE {
+///     version [0] INTEGER DEFAULT 0,
+///     customerASID ASID,
+///     providers ProviderASSet }
+///
+/// ProviderASSet ::= SEQUENCE (SIZE(1..MAX)) OF ASID
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsProviderAttestation {
+    customer: AsId,
+    providers: Vec<AsId>,
+}
+
+impl AsProviderAttestation {
+    pub fn customer(&self) -> AsId {
+        self.customer
+    }
+
+    pub fn providers(&self) -> &[AsId] {
+        &self.providers
+    }
+
+    fn take_from<S: decode::Source>(cons: &mut decode::Constructed<S>) -> Result<Self, S::Err> {
+        cons.take_sequence(|cons| {
+            cons.take_constructed_if(Tag::CTX_0, |cons| cons.skip_u8_if(ASPA_VERSION))?;
+            let customer = AsId::take_from(cons)?;
+            let providers = cons.take_sequence(|cons| {
+                let mut providers = vec![];
+                while let Some(provider) = cons.take_opt_u32()? {
+                    providers.push(AsId::from(provider));
+                }
+                Ok(providers)
+            })?;
+            if providers.is_empty() {
+                return Err(decode::Malformed.into());
+            }
+            Ok(AsProviderAttestation {
+                customer,
+                providers,
+            })
+        })
+    }
+
+    fn encode_ref<'a>(&'a self) -> impl encode::Values + 'a {
+        encode::sequence((
+            encode::sequence_as(Tag::CTX_0, ASPA_VERSION.encode()),
+            self.customer.encode(),
+            encode::sequence(encode::iter(self.providers.iter().map(|p| p.encode()))),
+        ))
+    }
+}
+
+//------------ AspaBuilder -------------------------------------------------
+
+struct AspaBuilder {
+    content: AsProviderAttestation,
+}
+
+impl AspaBuilder {
+    fn new(definition: &AspaDefinition) -> Self {
+        let customer = AsId::from(definition.customer());
+        let providers = definition
+            .providers()
+            .iter()
+            .map(|p| AsId::from(*p))
+            .collect();
+        AspaBuilder {
+            content: AsProviderAttestation {
+                customer,
+                providers,
+            },
+        }
+    }
+
+    /// Finalizes the builder into an ASPA. The EE certificate contains the
+    /// customer ASN as its only resource.
+    fn finalize<S: RpkiSigner>(
+        self,
+        mut sigobj: SignedObjectBuilder,
+        signer: &S,
+        issuer_key: &S::KeyId,
+    ) -> Result<Aspa, SigningError<S::Error>> {
+        let content = self.content;
+        let mut blocks = AsBlocksBuilder::new();
+        blocks.push(content.customer);
+        sigobj.set_as_resources(Some(AsResources::blocks(blocks.finalize())));
+        let signed = sigobj.finalize(
+            Oid(Bytes::from_static(&ASPA_CONTENT_TYPE)),
+            content.encode_ref().to_captured(Mode::Der).into_bytes(),
+            signer,
+            issuer_key,
+        )?;
+        Ok(Aspa { signed, content })
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn encode_decode_aspa_content() {
+        let definition = AspaDefinition::from_str("64496 => 64498, 64497").unwrap();
+        let content = AspaBuilder::new(&definition).content;
+
+        let encoded = content.encode_ref().to_captured(Mode::Der).into_bytes();
+        let decoded =
+            decode::Constructed::decode(encoded, Mode::Der, AsProviderAttestation::take_from)
+                .unwrap();
+
+        assert_eq!(decoded, content);
+        assert_eq!(decoded.customer(), AsId::from(64496));
+        assert_eq!(decoded.providers(), &[AsId::from(64497), AsId::from(64498)]);
+    }
+
+    #[test]
+    fn serde_aspa_definitions() {
+        let mut definitions = AspaDefinitions::default();
+        definitions.add_or_replace(AspaDefinition::from_str("64496 => 64497").unwrap());
+        definitions.add_or_replace(AspaDefinition::from_str("64500 => 64501").unwrap());
+
+        let json = serde_json::to_string(&definitions).unwrap();
+        let deserialized: AspaDefinitions = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, definitions);
+    }
+}
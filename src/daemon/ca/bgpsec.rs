@@ -0,0 +1,537 @@
+//! BGPsec router certificates.
+//!
+//! A router certificate is an EE certificate issued by a CA, which certifies
+//! the BGPsec key of a router for an ASN held by the CA. See RFC 8209 for the
+//! profile.
+//!
+//! Router keys are ECDSA P-256 keys, which the rpki crate cannot represent,
+//! so router CSRs and certificates are handled here rather than through its
+//! `Csr` and `TbsCert` types.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use bcder::encode::{PrimitiveContent, Values};
+use bcder::string::{BitString, OctetString};
+use bcder::{encode, Captured, Mode, Oid, Tag};
+use bytes::Bytes;
+use openssl::nid::Nid;
+use openssl::x509::X509Req;
+
+use rpki::cert::{KeyUsage, Overclaim};
+use rpki::crypto::{KeyIdentifier, SignatureAlgorithm, Signer as RpkiSigner, SigningError};
+use rpki::oid;
+use rpki::resources::{AsBlocksBuilder, AsId, AsResources};
+use rpki::uri;
+use rpki::x509::{encode_extension, Name, Serial, SignedData, Time, Validity};
+
+use crate::commons::api::{
+    AsNumber, Base64, BgpSecAsnKey, BgpSecCsrInfo, BgpSecCsrInfoList, BgpSecDefinition,
+    CurrentObject, ObjectName, ReplacedObject,
+};
+use crate::daemon::ca::events::BgpSecCertificateUpdates;
+
+/// The extended key usage for BGPsec router certificates: 1.3.6.1.5.5.7.3.30
+const KP_BGPSEC_ROUTER: [u8; 8] = [43, 6, 1, 5, 5, 7, 3, 30];
+
+//------------ BgpSecCsr ---------------------------------------------------
+
+/// A parsed and verified router certificate sign request.
+///
+/// Only the public key is used from the request. All other content of the
+/// router certificate is determined by the issuing CA.
+#[derive(Clone, Debug)]
+pub struct BgpSecCsr {
+    public_key_info: Bytes,
+    key_identifier: KeyIdentifier,
+}
+
+impl BgpSecCsr {
+    /// Decodes a DER encoded PKCS#10 CSR. The CSR must be signed with the
+    /// ECDSA P-256 key that it contains.
+    pub fn decode(der: &[u8]) -> Result<Self, BgpSecCsrError> {
+        let req = X509Req::from_der(der).map_err(|e| BgpSecCsrError::Parse(e.to_string()))?;
+        let key = req
+            .public_key()
+            .map_err(|e| BgpSecCsrError::Parse(e.to_string()))?;
+
+        match req.verify(&key) {
+            Ok(true) => {}
+            _ => return Err(BgpSecCsrError::Signature),
+        }
+
+        let curve = key.ec_key().ok().and_then(|ec| ec.group().curve_name());
+        if curve != Some(Nid::X9_62_PRIME256V1) {
+            return Err(BgpSecCsrError::KeyAlgorithm);
+        }
+
+        let public_key_info = key
+            .public_key_to_der()
+            .map_err(|e| BgpSecCsrError::Parse(e.to_string()))?;
+        let public_key_info = Bytes::from(public_key_info);
+
+        // The key identifier is the SHA-1 hash of the public key bits.
+        let bits = Mode::Der
+            .decode(public_key_info.clone(), |cons| {
+                cons.take_sequence(|cons| {
+                    cons.take_sequence(|cons| cons.skip_all())?;
+                    BitString::take_from(cons)
+                })
+            })
+            .map_err(|e| BgpSecCsrError::Parse(e.to_string()))?;
+        let bits = bits
+            .octet_slice()
+            .ok_or_else(|| BgpSecCsrError::Parse("unused bits in public key".to_string()))?;
+        let key_identifier = KeyIdentifier::try_from(&openssl::sha::sha1(bits)[..])
+            .map_err(|_| BgpSecCsrError::Parse("invalid key identifier".to_string()))?;
+
+        Ok(BgpSecCsr {
+            public_key_info,
+            key_identifier,
+        })
+    }
+
+    pub fn key_identifier(&self) -> KeyIdentifier {
+        self.key_identifier
+    }
+
+    fn public_key_info_captured(&self) -> Captured {
+        Mode::Der
+            .decode(self.public_key_info.clone(), |cons| cons.capture_one())
+            .unwrap() // was decoded before
+    }
+}
+
+impl TryFrom<&Base64> for BgpSecCsr {
+    type Error = BgpSecCsrError;
+
+    fn try_from(base64: &Base64) -> Result<Self, Self::Error> {
+        BgpSecCsr::decode(base64.to_bytes().as_ref())
+    }
+}
+
+//------------ BgpSecCsrError ----------------------------------------------
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum BgpSecCsrError {
+    #[display(fmt = "cannot parse CSR: {}", _0)]
+    Parse(String),
+
+    #[display(fmt = "CSR signature is invalid")]
+    Signature,
+
+    #[display(fmt = "CSR must contain an ECDSA P-256 key")]
+    KeyAlgorithm,
+}
+
+//------------ BgpSecDefinitions -------------------------------------------
+
+/// The BGPsec router keys configured for a CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecDefinitions {
+    definitions: HashMap<BgpSecAsnKey, BgpSecDefinition>,
+}
+
+impl BgpSecDefinitions {
+    pub fn get(&self, key: &BgpSecAsnKey) -> Option<&BgpSecDefinition> {
+        self.definitions.get(key)
+    }
+
+    pub fn has(&self, key: &BgpSecAsnKey) -> bool {
+        self.definitions.contains_key(key)
+    }
+
+    pub fn add(&mut self, key: BgpSecAsnKey, definition: BgpSecDefinition) {
+        self.definitions.insert(key, definition);
+    }
+
+    pub fn remove(&mut self, key: &BgpSecAsnKey) {
+        self.definitions.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&BgpSecAsnKey, &BgpSecDefinition)> {
+        self.definitions.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    pub fn as_list(&self) -> BgpSecCsrInfoList {
+        BgpSecCsrInfoList::new(
+            self.definitions
+                .iter()
+                .map(|(key, def)| BgpSecCsrInfo::new(key.asn(), *key.key(), def.csr().clone()))
+                .collect(),
+        )
+    }
+}
+
+//------------ BgpSecCertInfo ----------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecCertInfo {
+    definition: BgpSecDefinition,     // definition used for the cert
+    object: CurrentObject,            // actual router certificate
+    name: ObjectName,                 // Name for object in repo
+    since: Time,                      // first cert in RC created
+    replaces: Option<ReplacedObject>, // for revoking when re-newing
+}
+
+impl BgpSecCertInfo {
+    pub fn new_cert(
+        key: &BgpSecAsnKey,
+        definition: BgpSecDefinition,
+        cert: &BgpSecRouterCert,
+    ) -> Self {
+        BgpSecCertInfo {
+            definition,
+            object: CurrentObject::from(cert),
+            name: ObjectName::bgpsec(key),
+            since: Time::now(),
+            replaces: None,
+        }
+    }
+
+    pub fn updated_cert(
+        old: &BgpSecCertInfo,
+        key: &BgpSecAsnKey,
+        definition: BgpSecDefinition,
+        cert: &BgpSecRouterCert,
+    ) -> Self {
+        BgpSecCertInfo {
+            definition,
+            object: CurrentObject::from(cert),
+            name: ObjectName::bgpsec(key),
+            since: old.since,
+            replaces: Some(ReplacedObject::from(old.object())),
+        }
+    }
+
+    pub fn definition(&self) -> &BgpSecDefinition {
+        &self.definition
+    }
+
+    pub fn object(&self) -> &CurrentObject {
+        &self.object
+    }
+
+    pub fn name(&self) -> &ObjectName {
+        &self.name
+    }
+
+    pub fn since(&self) -> Time {
+        self.since
+    }
+
+    pub fn replaces(&self) -> Option<&ReplacedObject> {
+        self.replaces.as_ref()
+    }
+}
+
+//------------ BgpSecCertificates ------------------------------------------
+
+/// Router certificates issued under a resource class in a CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecCertificates {
+    inner: HashMap<BgpSecAsnKey, BgpSecCertInfo>,
+}
+
+impl BgpSecCertificates {
+    pub fn get(&self, key: &BgpSecAsnKey) -> Option<&BgpSecCertInfo> {
+        self.inner.get(key)
+    }
+
+    pub fn updated(&mut self, updates: BgpSecCertificateUpdates) {
+        let (updated, removed) = updates.unpack();
+
+        for (key, info) in updated.into_iter() {
+            self.inner.insert(key, info);
+        }
+
+        for key in removed.keys() {
+            self.inner.remove(key);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&BgpSecAsnKey, &BgpSecCertInfo)> {
+        self.inner.iter()
+    }
+
+    pub fn current(&self) -> impl Iterator<Item = &BgpSecCertInfo> {
+        self.inner.values()
+    }
+
+    /// Returns the definitions used for the current router certificates.
+    pub fn definitions(&self) -> BgpSecDefinitions {
+        let mut definitions = BgpSecDefinitions::default();
+        for (key, info) in self.inner.iter() {
+            definitions.add(key.clone(), info.definition().clone());
+        }
+        definitions
+    }
+}
+
+//------------ BgpSecRouterCert --------------------------------------------
+
+/// A signed BGPsec router certificate.
+#[derive(Clone, Debug)]
+pub struct BgpSecRouterCert {
+    serial_number: Serial,
+    validity: Validity,
+    bytes: Bytes,
+}
+
+impl BgpSecRouterCert {
+    pub fn serial_number(&self) -> Serial {
+        self.serial_number
+    }
+
+    pub fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    /// Returns the DER encoded certificate.
+    pub fn to_bytes(&self) -> Bytes {
+        self.bytes.clone()
+    }
+}
+
+//------------ TbsRouterCert -----------------------------------------------
+
+/// The data of a router certificate before it is signed.
+///
+/// Router certificates contain the router key, the ASN as their only
+/// resource and the BGPsec router extended key usage. Unlike other RPKI
+/// certificates they do not have a subject information access extension.
+pub struct TbsRouterCert {
+    serial_number: Serial,
+    issuer: Name,
+    validity: Validity,
+    asn: AsId,
+    subject_public_key_info: Captured,
+    subject_key_identifier: KeyIdentifier,
+    authority_key_identifier: KeyIdentifier,
+    crl_uri: uri::Rsync,
+    ca_issuer: uri::Rsync,
+}
+
+impl TbsRouterCert {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        serial_number: Serial,
+        issuer: Name,
+        validity: Validity,
+        asn: AsNumber,
+        csr: &BgpSecCsr,
+        authority_key_identifier: KeyIdentifier,
+        crl_uri: uri::Rsync,
+        ca_issuer: uri::Rsync,
+    ) -> Self {
+        TbsRouterCert {
+            serial_number,
+            issuer,
+            validity,
+            asn: AsId::from(asn),
+            subject_public_key_info: csr.public_key_info_captured(),
+            subject_key_identifier: csr.key_identifier(),
+            authority_key_identifier,
+            crl_uri,
+            ca_issuer,
+        }
+    }
+
+    /// Signs this with the issuing CA key.
+    pub fn into_cert<S: RpkiSigner>(
+        self,
+        signer: &S,
+        key: &S::KeyId,
+    ) -> Result<BgpSecRouterCert, SigningError<S::Error>> {
+        let data = Captured::from_values(Mode::Der, self.encode_ref());
+        let signature = signer.sign(key, SignatureAlgorithm::default(), &data)?;
+        let bytes = SignedData::new(data, signature)
+            .encode_ref()
+            .to_captured(Mode::Der)
+            .into_bytes();
+
+        Ok(BgpSecRouterCert {
+            serial_number: self.serial_number,
+            validity: self.validity,
+            bytes,
+        })
+    }
+
+    /// The subject uses the common name "ROUTER-" followed by the ASN as
+    /// eight hex digits, as described in RFC 8209. The key identifier is
+    /// included as the serial number, so that the name is unique for each
+    /// router key.
+    fn encode_subject(&self) -> impl encode::Values {
+        let common_name = format!("ROUTER-{:08X}", u32::from(self.asn));
+        let serial_number = self.subject_key_identifier.to_string();
+        encode::sequence((
+            encode::set(encode::sequence((
+                oid::AT_COMMON_NAME.encode(),
+                OctetString::encode_slice_as(Bytes::from(common_name), Tag::PRINTABLE_STRING),
+            ))),
+            encode::set(encode::sequence((
+                oid::AT_SERIAL_NUMBER.encode(),
+                OctetString::encode_slice_as(Bytes::from(serial_number), Tag::PRINTABLE_STRING),
+            ))),
+        ))
+    }
+
+    fn encode_ref<'a>(&'a self) -> impl encode::Values + 'a {
+        let mut blocks = AsBlocksBuilder::new();
+        blocks.push(self.asn);
+        let as_resources = AsResources::blocks(blocks.finalize());
+
+        encode::sequence((
+            encode::sequence_as(Tag::CTX_0, 2.encode()), // version
+            self.serial_number.encode(),
+            SignatureAlgorithm::default().x509_encode(),
+            self.issuer.encode_ref(),
+            self.validity.encode(),
+            self.encode_subject(),
+            &self.subject_public_key_info,
+            encode::sequence_as(
+                Tag::CTX_3,
+                encode::sequence((
+                    // Subject Key Identifier
+                    encode_extension(
+                        &oid::CE_SUBJECT_KEY_IDENTIFIER,
+                        false,
+                        self.subject_key_identifier.encode_ref(),
+                    ),
+                    // Authority Key Identifier
+                    encode_extension(
+                        &oid::CE_AUTHORITY_KEY_IDENTIFIER,
+                        false,
+                        encode::sequence(self.authority_key_identifier.encode_ref_as(Tag::CTX_0)),
+                    ),
+                    // Key Usage
+                    encode_extension(&oid::CE_KEY_USAGE, true, KeyUsage::Ee.encode()),
+                    // Extended Key Usage
+                    encode_extension(
+                        &oid::CE_EXTENDED_KEY_USAGE,
+                        false,
+                        encode::sequence(Oid(Bytes::from_static(&KP_BGPSEC_ROUTER)).encode()),
+                    ),
+                    // CRL Distribution Points
+                    encode_extension(
+                        &oid::CE_CRL_DISTRIBUTION_POINTS,
+                        false,
+                        encode::sequence(encode::sequence(encode::sequence_as(
+                            Tag::CTX_0,
+                            encode::sequence_as(Tag::CTX_0, self.crl_uri.encode_general_name()),
+                        ))),
+                    ),
+                    // Authority Information Access
+                    encode_extension(
+                        &oid::PE_AUTHORITY_INFO_ACCESS,
+                        false,
+                        encode::sequence(encode::sequence((
+                            oid::AD_CA_ISSUERS.encode(),
+                            self.ca_issuer.encode_general_name(),
+                        ))),
+                    ),
+                    // Certificate Policies
+                    encode_extension(
+                        &oid::CE_CERTIFICATE_POLICIES,
+                        true,
+                        encode::sequence(encode::sequence(Overclaim::Refuse.policy_id().encode())),
+                    ),
+                    // AS Resources
+                    encode_extension(Overclaim::Refuse.as_res_id(), true, as_resources.encode()),
+                )),
+            ),
+        ))
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509ReqBuilder, X509};
+
+    use crate::commons::util::softsigner::OpenSslSigner;
+    use crate::test;
+
+    fn router_csr() -> Vec<u8> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build().to_der().unwrap()
+    }
+
+    #[test]
+    fn decode_router_csr() {
+        let der = router_csr();
+        let csr = BgpSecCsr::decode(&der).unwrap();
+
+        let key = PKey::public_key_from_der(&csr.public_key_info).unwrap();
+        assert!(key.ec_key().is_ok());
+    }
+
+    #[test]
+    fn reject_rsa_csr() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let der = builder.build().to_der().unwrap();
+
+        assert_eq!(
+            BgpSecCsr::decode(&der).unwrap_err(),
+            BgpSecCsrError::KeyAlgorithm
+        );
+        assert!(BgpSecCsr::decode(b"not a csr").is_err());
+    }
+
+    #[test]
+    fn sign_router_cert() {
+        test::test_under_tmp(|d| {
+            let mut signer = OpenSslSigner::build(&d).unwrap();
+            let issuer_key_id = signer
+                .create_key(rpki::crypto::PublicKeyFormat::default())
+                .unwrap();
+            let issuer_key = signer.get_key_info(&issuer_key_id).unwrap();
+
+            let csr = BgpSecCsr::decode(&router_csr()).unwrap();
+            let crl_uri = uri::Rsync::from_str("rsync://localhost/repo/ca/ca.crl").unwrap();
+            let ca_issuer = uri::Rsync::from_str("rsync://localhost/repo/ta/ca.cer").unwrap();
+
+            let tbs = TbsRouterCert::new(
+                Serial::from(1_u64),
+                Name::from_pub_key(&issuer_key),
+                Validity::new(Time::five_minutes_ago(), Time::next_year()),
+                AsNumber::new(64496),
+                &csr,
+                issuer_key.key_identifier(),
+                crl_uri,
+                ca_issuer,
+            );
+            let cert = tbs.into_cert(&signer, &issuer_key_id).unwrap();
+
+            let x509 = X509::from_der(cert.to_bytes().as_ref()).unwrap();
+            let issuer_pkey = PKey::public_key_from_der(&issuer_key.to_info_bytes()).unwrap();
+            assert!(x509.verify(&issuer_pkey).unwrap());
+
+            let cn = x509
+                .subject_name()
+                .entries_by_nid(Nid::COMMONNAME)
+                .next()
+                .unwrap();
+            assert_eq!(cn.data().as_slice(), b"ROUTER-0000FBF0");
+        });
+    }
+}
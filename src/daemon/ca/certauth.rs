@@ -9,16 +9,20 @@ use bytes::Bytes;
 use chrono::Duration;
 
 use rpki::cert::{Cert, KeyUsage, Overclaim, TbsCert};
-use rpki::crypto::{KeyIdentifier, PublicKey, PublicKeyFormat};
+use rpki::crypto::{KeyIdentifier, PublicKey};
 use rpki::uri;
 use rpki::x509::{Serial, Time, Validity};
 
 use crate::commons::api::rrdp::PublishElement;
 use crate::commons::api::{
-    self, CertAuthInfo, ChildHandle, EntitlementClass, Entitlements, Handle, IdCertPem,
-    IssuanceRequest, IssuedCert, ObjectsDelta, ParentCaContact, ParentHandle, RcvdCert,
-    RepositoryContact, RequestResourceLimit, ResourceClassName, ResourceSet, RevocationRequest,
-    RevocationResponse, RoaDefinition, SigningCert, StorableCaCommand, TaCertDetails,
+    self, AsNumber, AspaDefinition, AspaDefinitionList, AspaDefinitionUpdates, AspaProvidersUpdate,
+    BgpSecAsnKey, BgpSecCsrInfoList, BgpSecDefinitionUpdates, CertAuthInfo, ChildHandle,
+    ChildIssuedCert, EntitlementClass, Entitlements, Handle, IdCertPem, IssuanceRequest,
+    IssuedCert, ObjectsDelta, ParentCaContact, ParentHandle, RcvdCert, RepoMigration,
+    RepoMigrationPhase, RepoMigrationStatus, RepositoryContact, RequestResourceLimit,
+    ResourceClassName, ResourceSet, ResourceTaggedAttestation, RevocationRequest,
+    RevocationResponse, RoaConfiguration, RoaDefinition, RoaMeta, RtaContentRequest, RtaList,
+    RtaName, RtaPrepResponse, RtaPrepareRequest, SigningCert, StorableCaCommand, TaCertDetails,
     TrustAnchorLocator,
 };
 use crate::commons::error::Error;
@@ -33,11 +37,13 @@ use crate::constants::{
     CHILD_CERTIFICATE_REISSUE_WEEKS, CHILD_CERTIFICATE_VALIDITY_YEARS, KRILL_ENV_TEST,
 };
 use crate::daemon::ca::events::ChildCertificateUpdates;
-use crate::daemon::ca::rc::PublishMode;
+use crate::daemon::ca::rc::{ObjectsToPublish, PublishMode};
 use crate::daemon::ca::signing::CsrInfo;
 use crate::daemon::ca::{
-    ta_handle, ChildDetails, Cmd, CmdDet, CurrentObjectSetDelta, Evt, EvtDet, Ini, ResourceClass,
-    RouteAuthorization, RouteAuthorizationUpdates, Routes, Signer,
+    ta_handle, AspaDefinitions, BgpSecCsr, BgpSecDefinitions, ChildDetails, Cmd, CmdDet,
+    CurrentObjectSetDelta, Evt, EvtDet, Ini, KeyPurpose, PreparedRta, ResourceClass,
+    RoaAggregation, RouteAuthorization, RouteAuthorizationUpdates, Routes, Rta, RtaContent, Rtas,
+    SignSupport, SignedRta, Signer,
 };
 
 //------------ Rfc8183Id ---------------------------------------------------
@@ -49,9 +55,12 @@ pub struct Rfc8183Id {
 }
 
 impl Rfc8183Id {
-    pub fn generate<S: Signer>(signer: &mut S) -> KrillResult<Self> {
+    pub fn generate<S: Signer>(
+        signer: &mut S,
+        preferred_signer: Option<&str>,
+    ) -> KrillResult<Self> {
         let key = signer
-            .create_key(PublicKeyFormat::default())
+            .create_key_for(KeyPurpose::Id, preferred_signer)
             .map_err(|e| Error::SignerError(e.to_string()))?;
         let cert = IdCertBuilder::new_ta_id_cert(&key, signer.deref())
             .map_err(|e| Error::SignerError(e.to_string()))?;
@@ -79,6 +88,11 @@ pub struct CertAuth<S: Signer> {
     repository: Option<RepositoryContact>,
     repository_pending_withdraw: Option<RepositoryContact>,
 
+    // The phase of the migration to the current repository, if a migration
+    // is in progress.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repository_migration: Option<RepoMigration>,
+
     parents: HashMap<ParentHandle, ParentCaContact>,
 
     next_class_name: u32,
@@ -88,6 +102,20 @@ pub struct CertAuth<S: Signer> {
 
     routes: Routes,
 
+    #[serde(default)]
+    aspas: AspaDefinitions,
+
+    #[serde(default)]
+    bgpsec_defs: BgpSecDefinitions,
+
+    #[serde(default, skip_serializing_if = "Rtas::is_empty")]
+    rtas: Rtas,
+
+    // The name of the signer where new keys should be created, if this
+    // CA has a preference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
+
     phantom_signer: PhantomData<S>,
 }
 
@@ -128,6 +156,7 @@ impl<S: Signer> Aggregate for CertAuth<S> {
 
             repository,
             repository_pending_withdraw: None,
+            repository_migration: None,
 
             parents,
 
@@ -137,6 +166,11 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             children,
 
             routes,
+            aspas: AspaDefinitions::default(),
+            bgpsec_defs: BgpSecDefinitions::default(),
+            rtas: Rtas::default(),
+
+            signer: None,
 
             phantom_signer: PhantomData,
         })
@@ -225,6 +259,9 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             EvtDet::IdUpdated(id) => {
                 self.id = id;
             }
+            EvtDet::SignerUpdated(signer) => {
+                self.signer = signer;
+            }
             EvtDet::ParentAdded(handle, info) => {
                 self.parents.insert(handle, info);
             }
@@ -296,12 +333,42 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             //-----------------------------------------------------------------------
             // Route Authorizations
             //-----------------------------------------------------------------------
-            EvtDet::RouteAuthorizationAdded(update) => self.routes.add(update),
+            EvtDet::RouteAuthorizationAdded(update) => self.routes.add(update, RoaMeta::default()),
+            EvtDet::RouteAuthorizationAddedWithMeta(update, meta) => self.routes.add(update, meta),
             EvtDet::RouteAuthorizationRemoved(removal) => self.routes.remove(&removal),
             EvtDet::RoasUpdated(rcn, updates) => {
                 self.resources.get_mut(&rcn).unwrap().roas_updated(updates)
             }
 
+            //-----------------------------------------------------------------------
+            // ASPA
+            //-----------------------------------------------------------------------
+            EvtDet::AspaConfigAdded(definition) => self.aspas.add_or_replace(definition),
+            EvtDet::AspaConfigUpdated(customer, update) => {
+                self.aspas.apply_update(customer, &update)
+            }
+            EvtDet::AspaConfigRemoved(customer) => self.aspas.remove(customer),
+            EvtDet::AspaObjectsUpdated(rcn, updates) => {
+                self.resources.get_mut(&rcn).unwrap().aspas_updated(updates)
+            }
+
+            //-----------------------------------------------------------------------
+            // BGPsec
+            //-----------------------------------------------------------------------
+            EvtDet::BgpSecDefinitionAdded(key, definition) => self.bgpsec_defs.add(key, definition),
+            EvtDet::BgpSecDefinitionRemoved(key) => self.bgpsec_defs.remove(&key),
+            EvtDet::BgpSecCertificatesUpdated(rcn, updates) => self
+                .resources
+                .get_mut(&rcn)
+                .unwrap()
+                .bgpsec_certs_updated(updates),
+
+            //-----------------------------------------------------------------------
+            // RTA
+            //-----------------------------------------------------------------------
+            EvtDet::RtaPrepared(name, prepared) => self.rtas.add_prepared(name, prepared),
+            EvtDet::RtaSigned(name, signed) => self.rtas.add_signed(name, signed),
+
             //-----------------------------------------------------------------------
             // Publication
             //-----------------------------------------------------------------------
@@ -317,8 +384,12 @@ impl<S: Signer> Aggregate for CertAuth<S> {
                 }
                 self.repository = Some(contact);
             }
+            EvtDet::RepoMigrationUpdated(migration) => {
+                self.repository_migration = Some(migration);
+            }
             EvtDet::RepoCleaned(_) => {
                 self.repository_pending_withdraw = None;
+                self.repository_migration = None;
             }
         }
     }
@@ -349,6 +420,7 @@ impl<S: Signer> Aggregate for CertAuth<S> {
 
             // being a child
             CmdDet::GenerateNewIdKey(signer) => self.generate_new_id_key(signer),
+            CmdDet::UpdateSigner(preferred, signer) => self.update_signer(preferred, signer),
             CmdDet::AddParent(parent, info) => self.add_parent(parent, info),
             CmdDet::UpdateParentContact(parent, info) => self.update_parent(parent, info),
             CmdDet::RemoveParent(parent) => self.remove_parent(parent),
@@ -356,24 +428,50 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             CmdDet::UpdateResourceClasses(parent, entitlements, signer) => {
                 self.update_resource_classes(parent, entitlements, signer)
             }
-            CmdDet::UpdateRcvdCert(class_name, rcvd_cert, signer) => {
-                self.update_received_cert(class_name, rcvd_cert, signer)
+            CmdDet::UpdateRcvdCert(class_name, rcvd_cert, roa_aggregation, signer) => {
+                self.update_received_cert(class_name, rcvd_cert, roa_aggregation, signer)
             }
 
             // Key rolls
             CmdDet::KeyRollInitiate(duration, signer) => self.keyroll_initiate(duration, signer),
-            CmdDet::KeyRollActivate(duration, signer) => self.keyroll_activate(duration, signer),
+            CmdDet::KeyRollActivate(duration, roa_aggregation, signer) => {
+                self.keyroll_activate(duration, roa_aggregation, signer)
+            }
             CmdDet::KeyRollFinish(rcn, response) => self.keyroll_finish(rcn, response),
 
             // Route Authorizations
-            CmdDet::RouteAuthorizationsUpdate(updates, signer) => {
-                self.route_authorizations_update(updates, signer)
+            CmdDet::RouteAuthorizationsUpdate(updates, roa_aggregation, signer) => {
+                self.route_authorizations_update(updates, roa_aggregation, signer)
+            }
+
+            // ASPA
+            CmdDet::AspasUpdate(updates, signer) => self.aspas_definitions_update(updates, signer),
+            CmdDet::AspasUpdateExisting(customer, update, signer) => {
+                self.aspas_update_existing(customer, update, signer)
+            }
+
+            // BGPsec
+            CmdDet::BgpSecUpdateDefinitions(updates, signer) => {
+                self.bgpsec_definitions_update(updates, signer)
+            }
+
+            // RTA
+            CmdDet::RtaSign(name, request, signer) => self.rta_sign(name, request, signer),
+            CmdDet::RtaMultiPrepare(name, request, signer) => {
+                self.rta_multi_prep(name, request, signer)
             }
+            CmdDet::RtaCoSign(name, rta, signer) => self.rta_cosign(name, rta, signer),
 
             // Republish
-            CmdDet::Republish(signer) => self.republish(signer),
-            CmdDet::RepoUpdate(new_contact, signer) => self.update_repo(new_contact, signer),
+            CmdDet::Republish(roa_aggregation, signer) => self.republish(roa_aggregation, signer),
+            CmdDet::RepoUpdate(new_contact, roa_aggregation, signer) => {
+                self.update_repo(new_contact, roa_aggregation, signer)
+            }
             CmdDet::RepoRemoveOld(signer) => self.clean_repo(signer),
+            CmdDet::RepoMigrationAdvance(signer) => self.repo_migration_advance(signer),
+            CmdDet::RepoMigrationAbort(roa_aggregation, signer) => {
+                self.repo_migration_abort(roa_aggregation, signer)
+            }
         }
     }
 }
@@ -410,6 +508,64 @@ impl<S: Signer> CertAuth<S> {
             .collect()
     }
 
+    pub fn roa_configurations(&self) -> Vec<RoaConfiguration> {
+        self.routes.configurations()
+    }
+
+    /// Returns the current certificates issued to all children, ordered by
+    /// child handle and resource class name.
+    pub fn child_issued_certs(&self) -> Vec<ChildIssuedCert> {
+        let mut res = vec![];
+        for (child, details) in &self.children {
+            for (rcn, rc) in &self.resources {
+                for ki in details.issued(rcn) {
+                    if let Some(issued) = rc.issued(&ki) {
+                        res.push(ChildIssuedCert::new(
+                            child.clone(),
+                            rcn.clone(),
+                            issued.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+        res.sort_by(|a, b| {
+            (a.child().as_str(), a.class_name()).cmp(&(b.child().as_str(), b.class_name()))
+        });
+        res
+    }
+
+    /// Returns all authorizations which are no longer valid at the given time.
+    pub fn expired_authorizations(&self, now: Time) -> HashSet<RouteAuthorization> {
+        self.routes.expired(now)
+    }
+
+    pub fn aspa_definitions(&self) -> AspaDefinitionList {
+        self.aspas.as_list()
+    }
+
+    pub fn bgpsec_definitions(&self) -> BgpSecCsrInfoList {
+        self.bgpsec_defs.as_list()
+    }
+
+    pub fn rta_list(&self) -> RtaList {
+        self.rtas.list()
+    }
+
+    pub fn rta_show(&self, name: &str) -> KrillResult<ResourceTaggedAttestation> {
+        self.rtas
+            .signed(name)
+            .map(|signed| signed.rta().clone())
+            .ok_or_else(|| Error::CaRtaUnknown(self.handle.clone(), name.to_string()))
+    }
+
+    pub fn rta_prep_response(&self, name: &str) -> KrillResult<RtaPrepResponse> {
+        self.rtas
+            .prepared(name)
+            .map(|prepared| prepared.as_response())
+            .ok_or_else(|| Error::CaRtaNotPrepared(self.handle.clone(), name.to_string()))
+    }
+
     pub fn child_request(&self) -> rfc8183::ChildRequest {
         rfc8183::ChildRequest::new(self.handle.clone(), self.id.cert.clone())
     }
@@ -427,6 +583,9 @@ impl<S: Signer> CertAuth<S> {
     pub fn handle(&self) -> &Handle {
         &self.handle
     }
+    pub fn preferred_signer(&self) -> Option<&String> {
+        self.signer.as_ref()
+    }
 
     pub fn all_resources(&self) -> ResourceSet {
         let mut resources = ResourceSet::default();
@@ -459,6 +618,27 @@ impl<S: Signer> CertAuth<S> {
     pub fn old_repository_contact(&self) -> Option<&RepositoryContact> {
         self.repository_pending_withdraw.as_ref()
     }
+
+    /// Returns the phase of the migration to the current repository, if a
+    /// migration is in progress.
+    pub fn repo_migration_phase(&self) -> Option<RepoMigrationPhase> {
+        self.repository_pending_withdraw.as_ref()?;
+        match &self.repository_migration {
+            Some(migration) => Some(migration.phase()),
+            // Migrations started before phases were tracked requested the
+            // new certificates right away.
+            None => Some(RepoMigrationPhase::AwaitingCertificates),
+        }
+    }
+
+    /// Returns the status of the migration to the current repository, if a
+    /// migration is in progress.
+    pub fn repo_migration_status(&self) -> Option<RepoMigrationStatus> {
+        let old = self.repository_pending_withdraw.as_ref()?;
+        let phase = self.repo_migration_phase()?;
+        let since = self.repository_migration.as_ref().map(|m| m.since());
+        Some(RepoMigrationStatus::new(old.clone(), phase, since))
+    }
 }
 
 /// # Being a trustanchor
@@ -478,7 +658,7 @@ impl<S: Signer> CertAuth<S> {
         let repo_info = self.get_repository_contact()?.repo_info();
 
         let key = signer
-            .create_key(PublicKeyFormat::default())
+            .create_key_for(KeyPurpose::ResourceClass, self.signer.as_deref())
             .map_err(Error::signer)?;
 
         let resources = ResourceSet::all_resources();
@@ -931,11 +1111,35 @@ impl<S: Signer> CertAuth<S> {
     /// Generates a new ID key for this CA.
     fn generate_new_id_key(&self, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
         let mut signer = signer.write().unwrap();
-        let id = Rfc8183Id::generate(signer.deref_mut())?;
+        let id = Rfc8183Id::generate(signer.deref_mut(), self.signer.as_deref())?;
 
         Ok(vec![EvtDet::id_updated(&self.handle, self.version, id)])
     }
 
+    /// Sets the signer where new keys for this CA should be created. Use a
+    /// key roll to move existing keys to this signer.
+    fn update_signer(
+        &self,
+        preferred: Option<String>,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        if let Some(name) = preferred.as_ref() {
+            if !signer.read().unwrap().has_signer(name) {
+                return Err(Error::CaSignerUnknown(self.handle.clone(), name.clone()));
+            }
+        }
+
+        if preferred == self.signer {
+            return Ok(vec![]);
+        }
+
+        Ok(vec![EvtDet::signer_updated(
+            &self.handle,
+            self.version,
+            preferred,
+        )])
+    }
+
     /// List all parents
     pub fn parents(&self) -> impl Iterator<Item = &ParentHandle> {
         self.parents.keys()
@@ -1192,7 +1396,7 @@ impl<S: Signer> CertAuth<S> {
                         signer
                             .write()
                             .unwrap()
-                            .create_key(PublicKeyFormat::default())
+                            .create_key_for(KeyPurpose::ResourceClass, self.signer.as_deref())
                             .map_err(Error::signer)?
                     };
 
@@ -1245,6 +1449,7 @@ impl<S: Signer> CertAuth<S> {
         &self,
         rcn: ResourceClassName,
         rcvd_cert: RcvdCert,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Vec<Evt>> {
         debug!(
@@ -1261,7 +1466,8 @@ impl<S: Signer> CertAuth<S> {
 
         let repo = self.get_repository_contact()?;
 
-        let evt_details = rc.update_received_cert(rcvd_cert, repo.repo_info(), signer.deref())?;
+        let evt_details =
+            rc.update_received_cert(rcvd_cert, repo.repo_info(), roa_aggregation, signer.deref())?;
 
         let mut res = vec![];
         let mut version = self.version;
@@ -1295,7 +1501,12 @@ impl<S: Signer> CertAuth<S> {
             let mut started = false;
             let repo = self.get_repository_contact()?;
             for details in rc
-                .keyroll_initiate(repo.repo_info(), duration, signer.deref_mut())?
+                .keyroll_initiate(
+                    repo.repo_info(),
+                    duration,
+                    self.signer.as_deref(),
+                    signer.deref_mut(),
+                )?
                 .into_iter()
             {
                 started = true;
@@ -1311,7 +1522,12 @@ impl<S: Signer> CertAuth<S> {
         Ok(res)
     }
 
-    fn keyroll_activate(&self, staging: Duration, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+    fn keyroll_activate(
+        &self,
+        staging: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
         if self.is_ta() {
             return Ok(vec![]);
         }
@@ -1326,7 +1542,7 @@ impl<S: Signer> CertAuth<S> {
             let repo = self.get_repository_contact()?;
 
             for details in rc
-                .keyroll_activate(repo.repo_info(), staging, signer.deref())?
+                .keyroll_activate(repo.repo_info(), staging, roa_aggregation, signer.deref())?
                 .into_iter()
             {
                 activated = true;
@@ -1373,14 +1589,20 @@ impl<S: Signer> CertAuth<S> {
 ///
 impl<S: Signer> CertAuth<S> {
     /// Republish objects for this CA
-    pub fn republish(&self, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+    pub fn republish(
+        &self,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
         let signer = signer.read().unwrap();
         let signer = signer.deref();
 
         let mut version = self.version;
         let mut res = vec![];
 
-        for evt_det in self.republish_resource_classes(&PublishMode::Normal, signer)? {
+        for evt_det in
+            self.republish_resource_classes(&PublishMode::Normal, roa_aggregation, signer)?
+        {
             res.push(StoredEvent::new(&self.handle, version, evt_det));
             version += 1;
         }
@@ -1391,22 +1613,27 @@ impl<S: Signer> CertAuth<S> {
     fn republish_resource_classes(
         &self,
         mode: &PublishMode,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         let mut res = vec![];
 
+        let objects = ObjectsToPublish::new(
+            self.routes.authorizations().cloned().collect(),
+            self.aspas.all().cloned().collect(),
+            self.bgpsec_defs.clone(),
+            roa_aggregation,
+        );
+
         for rc in self.resources.values() {
             if rc.current_key().is_some() {
-                let auths: Vec<RouteAuthorization> =
-                    self.routes.authorizations().cloned().collect();
-
                 let repo_info = if let PublishMode::NewRepo(info) = mode {
                     info
                 } else {
                     self.get_repository_contact()?.repo_info()
                 };
 
-                res.append(&mut rc.republish(auths.as_slice(), repo_info, mode, signer)?);
+                res.append(&mut rc.republish(&objects, repo_info, mode, signer)?);
             }
         }
 
@@ -1414,21 +1641,51 @@ impl<S: Signer> CertAuth<S> {
     }
 
     /// Update repository:
+    /// - check that no other migration is in progress
     /// - check that it is indeed different
     /// - regenerate all objects under the new URI (CRL URIs updated)
-    /// - request new certs for all keys
     ///
-    /// Note that this will then trigger (asynchronous):
-    /// - updated objects synchronised with repository
-    /// - CSRs submitted to parent(s)
+    /// Note that this will then trigger (asynchronous) the next phases of
+    /// the migration (see `repo_migration_advance`), unless this CA did not
+    /// have a repository before, in which case new certs are requested
+    /// right away.
     pub fn update_repo(
         &self,
         new_contact: RepositoryContact,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Vec<Evt>> {
+        if self.has_old_repo() {
+            return Err(Error::CaRepoMigrationInProgress(self.handle.clone()));
+        }
+
         let signer = signer.read().unwrap();
-        let signer = signer.deref();
+        self.migrate_repo(new_contact, roa_aggregation, signer.deref())
+    }
+
+    /// Abort a migration in progress, by migrating back to the old
+    /// repository. Objects published at the new repository are withdrawn
+    /// when this 'migration' finishes in turn.
+    fn repo_migration_abort(
+        &self,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let old_contact = self
+            .repository_pending_withdraw
+            .clone()
+            .ok_or_else(|| Error::CaRepoNoMigration(self.handle.clone()))?;
 
+        let signer = signer.read().unwrap();
+        self.migrate_repo(old_contact, roa_aggregation, signer.deref())
+    }
+
+    fn migrate_repo(
+        &self,
+        new_contact: RepositoryContact,
+        roa_aggregation: RoaAggregation,
+        signer: &S,
+    ) -> KrillResult<Vec<Evt>> {
         // check that it is indeed different
         if let Some(contact) = &self.repository {
             if contact == &new_contact {
@@ -1444,13 +1701,74 @@ impl<S: Signer> CertAuth<S> {
         evt_dts.push(EvtDet::RepoUpdated(new_contact));
 
         // issue new things => will trigger publication at the new location
-        evt_dts.append(
-            &mut self.republish_resource_classes(&PublishMode::NewRepo(info.clone()), signer)?,
-        );
+        evt_dts.append(&mut self.republish_resource_classes(
+            &PublishMode::NewRepo(info.clone()),
+            roa_aggregation,
+            signer,
+        )?);
+
+        if self.repository.is_some() {
+            // new certs are only requested once the objects were published
+            // at the new location
+            evt_dts.push(EvtDet::RepoMigrationUpdated(RepoMigration::new(
+                RepoMigrationPhase::PublishingNew,
+            )));
+        } else {
+            for rc in self.resources.values() {
+                evt_dts.append(&mut rc.make_request_events_new_repo(&info, signer)?);
+            }
+        }
 
-        // request new certs => when received will trigger unpublishing at old location
-        for rc in self.resources.values() {
-            evt_dts.append(&mut rc.make_request_events_new_repo(&info, signer)?);
+        let mut version = self.version;
+        let mut res = vec![];
+        for dt in evt_dts.into_iter() {
+            res.push(StoredEvent::new(&self.handle, version, dt));
+            version += 1;
+        }
+        Ok(res)
+    }
+
+    /// Moves a repository migration on to its next phase, if the current
+    /// phase is done:
+    /// - published at the new repository => request new certs for all keys
+    /// - all new certs received => withdraw from the old repository
+    ///
+    /// The caller is responsible for only calling this after the objects
+    /// were published at the new repository.
+    fn repo_migration_advance(&self, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+        let phase = match self.repo_migration_phase() {
+            Some(phase) => phase,
+            None => return Ok(vec![]),
+        };
+
+        let mut evt_dts = vec![];
+
+        match phase {
+            RepoMigrationPhase::PublishingNew => {
+                let signer = signer.read().unwrap();
+                let info = self.get_repository_contact()?.repo_info();
+                for rc in self.resources.values() {
+                    evt_dts.append(&mut rc.make_request_events_new_repo(info, signer.deref())?);
+                }
+                let next = if evt_dts.is_empty() {
+                    RepoMigrationPhase::WithdrawingOld
+                } else {
+                    RepoMigrationPhase::AwaitingCertificates
+                };
+                evt_dts.push(EvtDet::RepoMigrationUpdated(RepoMigration::new(next)));
+            }
+            RepoMigrationPhase::AwaitingCertificates => {
+                if self
+                    .resources
+                    .values()
+                    .all(|rc| rc.cert_requests().is_empty())
+                {
+                    evt_dts.push(EvtDet::RepoMigrationUpdated(RepoMigration::new(
+                        RepoMigrationPhase::WithdrawingOld,
+                    )));
+                }
+            }
+            RepoMigrationPhase::WithdrawingOld => {}
         }
 
         let mut version = self.version;
@@ -1481,22 +1799,18 @@ impl<S: Signer> CertAuth<S> {
 /// # Managing Route Authorizations
 ///
 impl<S: Signer> CertAuth<S> {
-    /// Updates the route authorizations for this CA, and update ROAs. Will return
-    /// an error in case authorizations are added for which this CA does not hold
-    /// the prefix.
-    fn route_authorizations_update(
+    /// Verifies the route authorization updates against the current
+    /// authorizations and resources of this CA. Returns the resulting set of
+    /// authorizations, and the events for the changed authorizations. Will
+    /// return an error in case authorizations are added for which this CA
+    /// does not hold the prefix.
+    fn verify_route_authorizations_update(
         &self,
         updates: RouteAuthorizationUpdates,
-        signer: Arc<RwLock<S>>,
-    ) -> KrillResult<Vec<Evt>> {
+    ) -> KrillResult<(HashSet<RouteAuthorization>, Vec<EvtDet>)> {
         let (added, removed) = updates.unpack();
-        let signer = signer.read().unwrap();
-        let mode = PublishMode::Normal;
 
-        let repo = self.get_repository_contact()?;
-
-        let mut res = vec![];
-        let mut version = self.version;
+        let mut evt_dets = vec![];
         let all_resources = self.all_resources();
 
         let mut current_auths: HashSet<RouteAuthorization> =
@@ -1505,18 +1819,15 @@ impl<S: Signer> CertAuth<S> {
         for auth in removed {
             if current_auths.contains(&auth) {
                 current_auths.remove(&auth);
-                res.push(StoredEvent::new(
-                    self.handle(),
-                    version,
-                    EvtDet::RouteAuthorizationRemoved(auth),
-                ));
-                version += 1;
+                evt_dets.push(EvtDet::RouteAuthorizationRemoved(auth));
             } else {
                 return Err(Error::CaAuthorizationUnknown(self.handle.clone(), auth));
             }
         }
 
-        for auth in added {
+        let now = Time::now();
+
+        for (auth, meta) in added {
             if !auth.max_length_valid() {
                 return Err(Error::CaAuthorizationInvalidMaxlength(
                     self.handle.clone(),
@@ -1531,24 +1842,72 @@ impl<S: Signer> CertAuth<S> {
                 return Err(Error::CaAuthorizationRedundant(self.handle.clone(), auth));
             } else if current_auths.iter().any(|a| auth.includes(a.as_ref())) {
                 return Err(Error::CaAuthorizationIncludes(self.handle.clone(), auth));
+            } else if meta.expired(now) {
+                return Err(Error::CaAuthorizationExpired(self.handle.clone(), auth));
             } else {
                 current_auths.insert(auth.explicit_length());
-                res.push(StoredEvent::new(
-                    self.handle(),
-                    version,
-                    EvtDet::RouteAuthorizationAdded(auth),
-                ));
-                version += 1;
+
+                // Only use the newer event type if there is meta-information,
+                // so that the plain event stays the norm.
+                if meta.is_empty() {
+                    evt_dets.push(EvtDet::RouteAuthorizationAdded(auth));
+                } else {
+                    evt_dets.push(EvtDet::RouteAuthorizationAddedWithMeta(auth, meta));
+                }
             }
         }
 
+        Ok((current_auths, evt_dets))
+    }
+
+    /// Returns the ROA definitions that would result from applying the
+    /// updates, without changing anything. Will return the same errors as
+    /// an actual update would.
+    pub fn route_authorizations_dry_run(
+        &self,
+        updates: RouteAuthorizationUpdates,
+    ) -> KrillResult<Vec<RoaDefinition>> {
+        let (auths, _) = self.verify_route_authorizations_update(updates)?;
+        let mut definitions: Vec<RoaDefinition> = auths.into_iter().map(|a| a.into()).collect();
+        definitions.sort();
+        Ok(definitions)
+    }
+
+    /// Updates the route authorizations for this CA, and update ROAs. Will return
+    /// an error in case authorizations are added for which this CA does not hold
+    /// the prefix.
+    fn route_authorizations_update(
+        &self,
+        updates: RouteAuthorizationUpdates,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+
+        let mut res = vec![];
+        let mut version = self.version;
+
+        let (current_auths, evt_dets) = self.verify_route_authorizations_update(updates)?;
+        for evt_det in evt_dets {
+            res.push(StoredEvent::new(self.handle(), version, evt_det));
+            version += 1;
+        }
+
         let current_auths: Vec<RouteAuthorization> = current_auths.into_iter().collect();
 
         let mut deltas = HashMap::new();
 
         // Update ROAs, and derive deltas and revocations for publishing.
         for (rcn, rc) in self.resources.iter() {
-            let updates = rc.update_roas(current_auths.as_slice(), &mode, signer.deref())?;
+            let updates = rc.update_roas(
+                current_auths.as_slice(),
+                &mode,
+                roa_aggregation,
+                signer.deref(),
+            )?;
             if updates.contains_changes() {
                 let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
 
@@ -1590,6 +1949,437 @@ impl<S: Signer> CertAuth<S> {
     }
 }
 
+/// # Managing ASPAs
+///
+impl<S: Signer> CertAuth<S> {
+    /// Adds, replaces and/or removes ASPA definitions, and updates the ASPA
+    /// objects in each resource class. Will return an error in case a
+    /// definition is added for a customer ASN that this CA does not hold.
+    fn aspas_definitions_update(
+        &self,
+        updates: AspaDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let (add_or_replace, remove) = updates.unpack();
+        let all_resources = self.all_resources();
+
+        let mut definitions = self.aspas.clone();
+        let mut evt_dets = vec![];
+
+        for customer in remove {
+            if !definitions.has(customer) {
+                return Err(Error::CaAspaUnknown(self.handle.clone(), customer));
+            }
+            definitions.remove(customer);
+            evt_dets.push(EvtDet::AspaConfigRemoved(customer));
+        }
+
+        for definition in add_or_replace {
+            self.verify_aspa(&definition, &all_resources)?;
+            definitions.add_or_replace(definition.clone());
+            evt_dets.push(EvtDet::AspaConfigAdded(definition));
+        }
+
+        self.aspas_publish(&definitions, evt_dets, signer)
+    }
+
+    /// Updates the providers of an existing ASPA definition, and updates
+    /// the ASPA objects in each resource class.
+    fn aspas_update_existing(
+        &self,
+        customer: AsNumber,
+        update: AspaProvidersUpdate,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let mut definitions = self.aspas.clone();
+
+        let mut definition = definitions
+            .get(customer)
+            .cloned()
+            .ok_or_else(|| Error::CaAspaUnknown(self.handle.clone(), customer))?;
+
+        definition.apply_update(&update);
+        self.verify_aspa(&definition, &self.all_resources())?;
+        definitions.add_or_replace(definition);
+
+        let evt_dets = vec![EvtDet::AspaConfigUpdated(customer, update)];
+
+        self.aspas_publish(&definitions, evt_dets, signer)
+    }
+
+    fn verify_aspa(&self, definition: &AspaDefinition, resources: &ResourceSet) -> KrillResult<()> {
+        let customer = definition.customer();
+        if !resources.contains(&customer.into()) {
+            Err(Error::CaAspaNotEntitled(self.handle.clone(), customer))
+        } else if definition.providers().is_empty() {
+            Err(Error::CaAspaProvidersEmpty(self.handle.clone(), customer))
+        } else if definition.customer_used_as_provider() {
+            Err(Error::CaAspaCustomerAsProvider(
+                self.handle.clone(),
+                customer,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Updates the ASPA objects in all resource classes in accordance with
+    /// the given definitions, and publishes the changes. The ASPA config
+    /// events are expected to be passed in, so that they are stored before
+    /// the ASPA object and publication events.
+    fn aspas_publish(
+        &self,
+        definitions: &AspaDefinitions,
+        mut evt_dets: Vec<EvtDet>,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+        let definitions: Vec<AspaDefinition> = definitions.all().cloned().collect();
+
+        for (rcn, rc) in self.resources.iter() {
+            if rc.current_key().is_none() {
+                continue;
+            }
+
+            let updates = rc.update_aspas(definitions.as_slice(), &mode, signer.deref())?;
+            if updates.contains_changes() {
+                let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
+
+                for added in updates.added().into_iter() {
+                    delta.add(added);
+                }
+                for update in updates.updated().into_iter() {
+                    delta.update(update);
+                }
+                for withdraw in updates.withdrawn().into_iter() {
+                    delta.withdraw(withdraw);
+                }
+
+                let revocations = updates.revocations();
+
+                evt_dets.push(EvtDet::AspaObjectsUpdated(rcn.clone(), updates));
+                evt_dets.push(rc.publish_objects(
+                    repo.repo_info(),
+                    delta,
+                    revocations,
+                    &mode,
+                    signer.deref(),
+                )?);
+            }
+        }
+
+        let mut version = self.version;
+        let mut res = vec![];
+        for evt_det in evt_dets.into_iter() {
+            res.push(StoredEvent::new(&self.handle, version, evt_det));
+            version += 1;
+        }
+        Ok(res)
+    }
+}
+
+/// # Managing BGPsec router certificates
+///
+impl<S: Signer> CertAuth<S> {
+    /// Adds and/or removes BGPsec router keys, and issues or revokes router
+    /// certificates in each resource class. Will return an error in case a
+    /// router key is added for an ASN that this CA does not hold, or if the
+    /// router CSR is invalid.
+    fn bgpsec_definitions_update(
+        &self,
+        updates: BgpSecDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let (add, remove) = updates.unpack();
+        let all_resources = self.all_resources();
+
+        let mut definitions = self.bgpsec_defs.clone();
+        let mut evt_dets = vec![];
+
+        for key in remove {
+            if !definitions.has(&key) {
+                return Err(Error::CaBgpSecUnknown(self.handle.clone(), key));
+            }
+            definitions.remove(&key);
+            evt_dets.push(EvtDet::BgpSecDefinitionRemoved(key));
+        }
+
+        for definition in add {
+            let asn = definition.asn();
+            if !all_resources.contains(&asn.into()) {
+                return Err(Error::CaBgpSecNotEntitled(self.handle.clone(), asn));
+            }
+
+            let csr = BgpSecCsr::try_from(definition.csr())
+                .map_err(|e| Error::CaBgpSecCsrInvalid(self.handle.clone(), e.to_string()))?;
+
+            let key = BgpSecAsnKey::new(asn, csr.key_identifier());
+            definitions.add(key.clone(), definition.clone());
+            evt_dets.push(EvtDet::BgpSecDefinitionAdded(key, definition));
+        }
+
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+
+        for (rcn, rc) in self.resources.iter() {
+            if rc.current_key().is_none() {
+                continue;
+            }
+
+            let updates = rc.update_bgpsec_certs(&definitions, &mode, signer.deref())?;
+            if updates.contains_changes() {
+                let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
+
+                for added in updates.added().into_iter() {
+                    delta.add(added);
+                }
+                for update in updates.updated().into_iter() {
+                    delta.update(update);
+                }
+                for withdraw in updates.withdrawn().into_iter() {
+                    delta.withdraw(withdraw);
+                }
+
+                let revocations = updates.revocations();
+
+                evt_dets.push(EvtDet::BgpSecCertificatesUpdated(rcn.clone(), updates));
+                evt_dets.push(rc.publish_objects(
+                    repo.repo_info(),
+                    delta,
+                    revocations,
+                    &mode,
+                    signer.deref(),
+                )?);
+            }
+        }
+
+        let mut version = self.version;
+        let mut res = vec![];
+        for evt_det in evt_dets.into_iter() {
+            res.push(StoredEvent::new(&self.handle, version, evt_det));
+            version += 1;
+        }
+        Ok(res)
+    }
+}
+
+/// # Resource Tagged Attestations
+///
+impl<S: Signer> CertAuth<S> {
+    /// Signs an RTA over the content in the request. A one-off key is used
+    /// for each resource class which holds some of the resources. If the
+    /// request includes keys prepared by other signers, then the resulting
+    /// RTA still needs to be co-signed by them.
+    fn rta_sign(
+        &self,
+        name: RtaName,
+        request: RtaContentRequest,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        if self.rtas.has(&name) {
+            return Err(Error::CaRtaDuplicate(self.handle.clone(), name));
+        }
+
+        let resources = request.resources().clone();
+        self.rta_verify_entitled(&resources)?;
+
+        let mut signer = signer.write().unwrap();
+        let keys = self.rta_create_keys(&resources, signer.deref_mut())?;
+
+        let mut subject_keys: Vec<KeyIdentifier> = keys.values().cloned().collect();
+        subject_keys.extend(request.subject_keys().iter().cloned());
+
+        let content = RtaContent::new(subject_keys, resources.clone(), request.content().as_ref());
+        let mut rta = Rta::new(content);
+
+        let signed = self.rta_sign_with_keys(
+            &mut rta,
+            &resources,
+            request.validity(),
+            &keys,
+            signer.deref(),
+        );
+        self.rta_destroy_keys(&keys, signer.deref_mut());
+        signed?;
+
+        let signed = SignedRta::new(resources, rta.to_attestation());
+        Ok(vec![StoredEvent::new(
+            &self.handle,
+            self.version,
+            EvtDet::RtaSigned(name, signed),
+        )])
+    }
+
+    /// Prepares a one-off key for each resource class which holds some of
+    /// the resources, so that this CA can co-sign a multi-signer RTA.
+    fn rta_multi_prep(
+        &self,
+        name: RtaName,
+        request: RtaPrepareRequest,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        if self.rtas.has(&name) {
+            return Err(Error::CaRtaDuplicate(self.handle.clone(), name));
+        }
+
+        let resources = request.resources().clone();
+        self.rta_verify_entitled(&resources)?;
+
+        let mut signer = signer.write().unwrap();
+        let keys = self.rta_create_keys(&resources, signer.deref_mut())?;
+
+        let prepared = PreparedRta::new(resources, request.validity(), keys);
+        Ok(vec![StoredEvent::new(
+            &self.handle,
+            self.version,
+            EvtDet::RtaPrepared(name, prepared),
+        )])
+    }
+
+    /// Co-signs a multi-signer RTA using the keys that were prepared for it.
+    /// The RTA must list all prepared keys, and must not be signed by them
+    /// yet. The prepared keys are destroyed after use.
+    fn rta_cosign(
+        &self,
+        name: RtaName,
+        rta: ResourceTaggedAttestation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let prepared = self
+            .rtas
+            .prepared(&name)
+            .ok_or_else(|| Error::CaRtaNotPrepared(self.handle.clone(), name.clone()))?;
+
+        let mut rta = Rta::decode(rta.as_bytes().clone())
+            .map_err(|e| Error::CaRtaCoSignInvalid(self.handle.clone(), e.to_string()))?;
+
+        for key in prepared.keys().values() {
+            if !rta.content().subject_keys().contains(key) {
+                return Err(Error::CaRtaCoSignInvalid(
+                    self.handle.clone(),
+                    "RTA does not include prepared keys".to_string(),
+                ));
+            }
+            if rta.is_signed_by(key) {
+                return Err(Error::CaRtaCoSignInvalid(
+                    self.handle.clone(),
+                    "RTA is already signed by prepared keys".to_string(),
+                ));
+            }
+        }
+
+        let resources = prepared.resources().intersection(rta.content().resources());
+
+        let mut signer = signer.write().unwrap();
+        let signed = self.rta_sign_with_keys(
+            &mut rta,
+            &resources,
+            prepared.validity(),
+            prepared.keys(),
+            signer.deref(),
+        );
+        if signed.is_ok() {
+            self.rta_destroy_keys(prepared.keys(), signer.deref_mut());
+        }
+        signed?;
+
+        let signed = SignedRta::new(rta.content().resources().clone(), rta.to_attestation());
+        Ok(vec![StoredEvent::new(
+            &self.handle,
+            self.version,
+            EvtDet::RtaSigned(name, signed),
+        )])
+    }
+
+    fn rta_verify_entitled(&self, resources: &ResourceSet) -> KrillResult<()> {
+        if resources.is_empty() || !self.all_resources().contains(resources) {
+            Err(Error::CaRtaNotEntitled(
+                self.handle.clone(),
+                resources.clone(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Creates a one-off key for each resource class which holds some of
+    /// the resources.
+    fn rta_create_keys(
+        &self,
+        resources: &ResourceSet,
+        signer: &mut S,
+    ) -> KrillResult<HashMap<ResourceClassName, KeyIdentifier>> {
+        let mut keys = HashMap::new();
+        for (rcn, rc) in self.resources.iter() {
+            if let Some(rc_resources) = rc.current_resources() {
+                if !rc_resources.intersection(resources).is_empty() {
+                    let key = signer
+                        .create_key_for(KeyPurpose::ResourceClass, self.signer.as_deref())
+                        .map_err(Error::signer)?;
+                    keys.insert(rcn.clone(), key);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Signs the RTA with each key, using an EE certificate issued by the
+    /// current key of its resource class.
+    fn rta_sign_with_keys(
+        &self,
+        rta: &mut Rta,
+        resources: &ResourceSet,
+        validity: Validity,
+        keys: &HashMap<ResourceClassName, KeyIdentifier>,
+        signer: &S,
+    ) -> KrillResult<()> {
+        for (rcn, key) in keys.iter() {
+            let rc = self
+                .resources
+                .get(rcn)
+                .ok_or_else(|| Error::ResourceClassUnknown(rcn.clone()))?;
+            let current_key = rc.current_key().ok_or(Error::KeyUseNoCurrentKey)?;
+
+            let ee_resources = current_key
+                .incoming_cert()
+                .resources()
+                .intersection(resources);
+            if ee_resources.is_empty() {
+                return Err(Error::CaRtaNotEntitled(
+                    self.handle.clone(),
+                    resources.clone(),
+                ));
+            }
+
+            let pub_key = signer.get_key_info(key).map_err(Error::signer)?;
+            let ee_cert = SignSupport::make_rta_ee_cert(
+                &ee_resources,
+                current_key,
+                validity,
+                pub_key,
+                signer,
+            )?;
+
+            rta.sign(ee_cert, current_key.incoming_cert().cert(), signer)
+                .map_err(Error::signer)?;
+        }
+        Ok(())
+    }
+
+    fn rta_destroy_keys(&self, keys: &HashMap<ResourceClassName, KeyIdentifier>, signer: &mut S) {
+        for key in keys.values() {
+            if let Err(e) = signer.destroy_key(key) {
+                warn!("Could not destroy one-off RTA key '{}': {}", key, e);
+            }
+        }
+    }
+}
+
 //------------ Tests ---------------------------------------------------------
 
 #[cfg(test)]
@@ -1602,7 +2392,7 @@ mod tests {
     fn generate_id_cert() {
         test::test_under_tmp(|d| {
             let mut signer = OpenSslSigner::build(&d).unwrap();
-            let id = Rfc8183Id::generate(&mut signer).unwrap();
+            let id = Rfc8183Id::generate(&mut signer, None).unwrap();
             id.cert.validate_ta().unwrap();
         });
     }
@@ -7,13 +7,15 @@ use chrono::Duration;
 use rpki::uri;
 
 use crate::commons::api::{
-    ChildHandle, Entitlements, Handle, IssuanceRequest, ParentCaContact, ParentHandle, RcvdCert,
-    RepositoryContact, ResourceClassName, ResourceSet, RevocationRequest, RevocationResponse,
+    AsNumber, AspaDefinitionUpdates, AspaProvidersUpdate, BgpSecDefinitionUpdates, ChildHandle,
+    Entitlements, Handle, IssuanceRequest, ParentCaContact, ParentHandle, RcvdCert,
+    RepositoryContact, ResourceClassName, ResourceSet, ResourceTaggedAttestation,
+    RevocationRequest, RevocationResponse, RtaContentRequest, RtaName, RtaPrepareRequest,
     StorableCaCommand,
 };
 use crate::commons::eventsourcing;
 use crate::commons::remote::id::IdCert;
-use crate::daemon::ca::{Evt, RouteAuthorizationUpdates, Signer};
+use crate::daemon::ca::{Evt, RoaAggregation, RouteAuthorizationUpdates, Signer};
 
 //------------ Command -----------------------------------------------------
 
@@ -58,6 +60,12 @@ pub enum CmdDet<S: Signer> {
     // on how to re-do the ID exchange.
     GenerateNewIdKey(Arc<RwLock<S>>),
 
+    // Set the name of the signer where new keys for this CA should be
+    // created, or remove the preference so that the configured signer for
+    // each key purpose is used. Existing keys are not moved, but a key
+    // roll will result in a new key in the preferred signer.
+    UpdateSigner(Option<String>, Arc<RwLock<S>>),
+
     // Add a parent to this CA. Can have multiple parents.
     AddParent(ParentHandle, ParentCaContact),
     // Update a parent's contact
@@ -70,7 +78,7 @@ pub enum CmdDet<S: Signer> {
     // as needed.
     UpdateResourceClasses(ParentHandle, Entitlements, Arc<RwLock<S>>),
     // Process a new certificate received from a parent.
-    UpdateRcvdCert(ResourceClassName, RcvdCert, Arc<RwLock<S>>),
+    UpdateRcvdCert(ResourceClassName, RcvdCert, RoaAggregation, Arc<RwLock<S>>),
 
     // ------------------------------------------------------------
     // Key rolls
@@ -90,7 +98,7 @@ pub enum CmdDet<S: Signer> {
     //
     // RFC6489 dictates that 24 hours MUST be observed. However, shorter time frames can
     // be used for testing, and in case of emergency rolls.
-    KeyRollActivate(Duration, Arc<RwLock<S>>),
+    KeyRollActivate(Duration, RoaAggregation, Arc<RwLock<S>>),
 
     // Finish the keyroll after the parent confirmed that a key for a parent and resource
     // class has been revoked. I.e. remove the old key, and withdraw the crl and mft for it.
@@ -99,20 +107,58 @@ pub enum CmdDet<S: Signer> {
     // ------------------------------------------------------------
     // ROA Support
     // ------------------------------------------------------------
-    RouteAuthorizationsUpdate(RouteAuthorizationUpdates, Arc<RwLock<S>>),
+    // Update the route authorizations, and update ROAs accordingly. ROAs are
+    // aggregated per ASN, or de-aggregated, as determined by the RoaAggregation.
+    RouteAuthorizationsUpdate(RouteAuthorizationUpdates, RoaAggregation, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // ASPA Support
+    // ------------------------------------------------------------
+    // Add or replace ASPA definitions, and/or remove definitions for
+    // customer ASNs.
+    AspasUpdate(AspaDefinitionUpdates, Arc<RwLock<S>>),
+
+    // Update the providers of an existing ASPA definition.
+    AspasUpdateExisting(AsNumber, AspaProvidersUpdate, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // BGPsec Support
+    // ------------------------------------------------------------
+    // Add and/or remove BGPsec router keys, and issue or revoke router
+    // certificates accordingly.
+    BgpSecUpdateDefinitions(BgpSecDefinitionUpdates, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // RTA Support
+    // ------------------------------------------------------------
+    // Sign an RTA over some content, with the keys of this CA and
+    // including any prepared keys of other signers.
+    RtaSign(RtaName, RtaContentRequest, Arc<RwLock<S>>),
+
+    // Prepare keys for co-signing a multi-signer RTA.
+    RtaMultiPrepare(RtaName, RtaPrepareRequest, Arc<RwLock<S>>),
+
+    // Co-sign a multi-signer RTA using the prepared keys.
+    RtaCoSign(RtaName, ResourceTaggedAttestation, Arc<RwLock<S>>),
 
     // ------------------------------------------------------------
     // Publishing
     // ------------------------------------------------------------
 
     // Republish, if needed, may be a no-op if everything is still fresh.
-    Republish(Arc<RwLock<S>>),
+    Republish(RoaAggregation, Arc<RwLock<S>>),
 
     // Update the repository where this CA publishes
-    RepoUpdate(RepositoryContact, Arc<RwLock<S>>),
+    RepoUpdate(RepositoryContact, RoaAggregation, Arc<RwLock<S>>),
 
     // Clean up the old pending to withdraw repo.
     RepoRemoveOld(Arc<RwLock<S>>),
+
+    // Move a repository migration on to its next phase, if possible.
+    RepoMigrationAdvance(Arc<RwLock<S>>),
+
+    // Abort a repository migration, by migrating back to the old repo.
+    RepoMigrationAbort(RoaAggregation, Arc<RwLock<S>>),
 }
 
 impl<S: Signer> eventsourcing::CommandDetails for CmdDet<S> {
@@ -151,6 +197,7 @@ impl<S: Signer> From<CmdDet<S>> for StorableCaCommand {
             CmdDet::ChildRevokeKey(child, req, _) => StorableCaCommand::ChildRevokeKey(child, req),
             CmdDet::ChildRemove(child, _) => StorableCaCommand::ChildRemove(child),
             CmdDet::GenerateNewIdKey(_) => StorableCaCommand::GenerateNewIdKey,
+            CmdDet::UpdateSigner(signer, _) => StorableCaCommand::UpdateSigner(signer),
             CmdDet::AddParent(parent, contact) => {
                 StorableCaCommand::AddParent(parent, contact.into())
             }
@@ -169,21 +216,31 @@ impl<S: Signer> From<CmdDet<S>> for StorableCaCommand {
 
                 StorableCaCommand::UpdateResourceClasses(parent, classes)
             }
-            CmdDet::UpdateRcvdCert(rcn, rcvd_cert, _) => {
+            CmdDet::UpdateRcvdCert(rcn, rcvd_cert, _, _) => {
                 StorableCaCommand::UpdateRcvdCert(rcn, rcvd_cert.resources().clone())
             }
             CmdDet::KeyRollInitiate(duration, _) => {
                 StorableCaCommand::KeyRollInitiate(duration.num_seconds())
             }
-            CmdDet::KeyRollActivate(duration, _) => {
+            CmdDet::KeyRollActivate(duration, _, _) => {
                 StorableCaCommand::KeyRollActivate(duration.num_seconds())
             }
             CmdDet::KeyRollFinish(rcn, _) => StorableCaCommand::KeyRollFinish(rcn),
-            CmdDet::RouteAuthorizationsUpdate(updates, _) => {
+            CmdDet::RouteAuthorizationsUpdate(updates, _, _) => {
                 StorableCaCommand::RoaDefinitionUpdates(updates.into())
             }
-            CmdDet::Republish(_) => StorableCaCommand::Republish,
-            CmdDet::RepoUpdate(update, _) => {
+            CmdDet::AspasUpdate(updates, _) => StorableCaCommand::AspasUpdate(updates),
+            CmdDet::AspasUpdateExisting(customer, update, _) => {
+                StorableCaCommand::AspasUpdateExisting(customer, update)
+            }
+            CmdDet::BgpSecUpdateDefinitions(updates, _) => {
+                StorableCaCommand::BgpSecDefinitionsUpdate(updates)
+            }
+            CmdDet::RtaSign(name, _, _) => StorableCaCommand::RtaSign(name),
+            CmdDet::RtaMultiPrepare(name, _, _) => StorableCaCommand::RtaMultiPrepare(name),
+            CmdDet::RtaCoSign(name, _, _) => StorableCaCommand::RtaCoSign(name),
+            CmdDet::Republish(_, _) => StorableCaCommand::Republish,
+            CmdDet::RepoUpdate(update, _, _) => {
                 let service_uri_opt = match update {
                     RepositoryContact::Embedded(_) => None,
                     RepositoryContact::Rfc8181(res) => Some(res.service_uri().clone()),
@@ -191,6 +248,8 @@ impl<S: Signer> From<CmdDet<S>> for StorableCaCommand {
                 StorableCaCommand::RepoUpdate(service_uri_opt)
             }
             CmdDet::RepoRemoveOld(_) => StorableCaCommand::RepoRemoveOld,
+            CmdDet::RepoMigrationAdvance(_) => StorableCaCommand::RepoMigrationAdvance,
+            CmdDet::RepoMigrationAbort(_, _) => StorableCaCommand::RepoMigrationAbort,
         }
     }
 }
@@ -277,6 +336,14 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::GenerateNewIdKey(signer))
     }
 
+    pub fn update_signer(
+        handle: &Handle,
+        preferred: Option<String>,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::UpdateSigner(preferred, signer))
+    }
+
     pub fn add_parent(handle: &Handle, parent: ParentHandle, info: ParentCaContact) -> Cmd<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::AddParent(parent, info))
     }
@@ -306,12 +373,13 @@ impl<S: Signer> CmdDet<S> {
         handle: &Handle,
         class_name: ResourceClassName,
         cert: RcvdCert,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
         eventsourcing::SentCommand::new(
             handle,
             None,
-            CmdDet::UpdateRcvdCert(class_name, cert, signer),
+            CmdDet::UpdateRcvdCert(class_name, cert, roa_aggregation, signer),
         )
     }
 
@@ -323,8 +391,17 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollInitiate(duration, signer))
     }
 
-    pub fn key_roll_activate(handle: &Handle, staging: Duration, signer: Arc<RwLock<S>>) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollActivate(staging, signer))
+    pub fn key_roll_activate(
+        handle: &Handle,
+        staging: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::KeyRollActivate(staging, roa_aggregation, signer),
+        )
     }
 
     pub fn key_roll_finish(
@@ -335,34 +412,130 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollFinish(rcn, res))
     }
 
-    pub fn publish(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::Republish(signer))
+    pub fn publish(
+        handle: &Handle,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::Republish(roa_aggregation, signer))
     }
 
     pub fn update_repo(
         handle: &Handle,
         contact: RepositoryContact,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::RepoUpdate(contact, signer))
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::RepoUpdate(contact, roa_aggregation, signer),
+        )
     }
 
     pub fn remove_old_repo(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::RepoRemoveOld(signer))
     }
 
+    pub fn repo_migration_advance(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::RepoMigrationAdvance(signer))
+    }
+
+    pub fn repo_migration_abort(
+        handle: &Handle,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::RepoMigrationAbort(roa_aggregation, signer),
+        )
+    }
+
     //-------------------------------------------------------------------------------
     // Route Authorizations
     //-------------------------------------------------------------------------------
     pub fn route_authorizations_update(
         handle: &Handle,
         updates: RouteAuthorizationUpdates,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::RouteAuthorizationsUpdate(updates, roa_aggregation, signer),
+        )
+    }
+
+    /// Adds, replaces and/or removes ASPA definitions.
+    pub fn aspas_definitions_update(
+        handle: &Handle,
+        updates: AspaDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::AspasUpdate(updates, signer))
+    }
+
+    /// Updates the providers of an existing ASPA definition.
+    pub fn aspas_update_aspa(
+        handle: &Handle,
+        customer: AsNumber,
+        update: AspaProvidersUpdate,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::AspasUpdateExisting(customer, update, signer),
+        )
+    }
+
+    /// Adds and/or removes BGPsec router keys.
+    pub fn bgpsec_update_definitions(
+        handle: &Handle,
+        updates: BgpSecDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::BgpSecUpdateDefinitions(updates, signer),
+        )
+    }
+
+    /// Signs an RTA over the content in the request.
+    pub fn rta_sign(
+        handle: &Handle,
+        name: RtaName,
+        request: RtaContentRequest,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::RtaSign(name, request, signer))
+    }
+
+    /// Prepares keys for co-signing a multi-signer RTA.
+    pub fn rta_multi_prep(
+        handle: &Handle,
+        name: RtaName,
+        request: RtaPrepareRequest,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
         eventsourcing::SentCommand::new(
             handle,
             None,
-            CmdDet::RouteAuthorizationsUpdate(updates, signer),
+            CmdDet::RtaMultiPrepare(name, request, signer),
         )
     }
+
+    /// Co-signs a multi-signer RTA with the prepared keys.
+    pub fn rta_multi_cosign(
+        handle: &Handle,
+        name: RtaName,
+        rta: ResourceTaggedAttestation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::RtaCoSign(name, rta, signer))
+    }
 }
@@ -6,18 +6,19 @@ use std::sync::{Arc, RwLock};
 use rpki::crypto::KeyIdentifier;
 
 use crate::commons::api::{
-    AddedObject, ChildHandle, Handle, IssuanceRequest, IssuedCert, ObjectName, ObjectsDelta,
-    ParentCaContact, ParentHandle, RcvdCert, RepoInfo, RepositoryContact, ResourceClassName,
-    ResourceSet, Revocation, RevocationRequest, RevokedObject, TaCertDetails, UpdatedObject,
-    WithdrawnObject,
+    AddedObject, AsNumber, AspaDefinition, AspaProvidersUpdate, BgpSecAsnKey, BgpSecDefinition,
+    ChildHandle, Handle, IssuanceRequest, IssuedCert, ObjectName, ObjectsDelta, ParentCaContact,
+    ParentHandle, RcvdCert, RepoInfo, RepoMigration, RepositoryContact, ResourceClassName,
+    ResourceSet, Revocation, RevocationRequest, RevokedObject, RoaMeta, RtaName, TaCertDetails,
+    UpdatedObject, WithdrawnObject,
 };
 use crate::commons::eventsourcing::StoredEvent;
 use crate::commons::remote::id::IdCert;
 use crate::commons::KrillResult;
 use crate::daemon::ca::signing::Signer;
 use crate::daemon::ca::{
-    CertifiedKey, ChildDetails, CurrentObjectSetDelta, ResourceClass, Rfc8183Id, RoaInfo,
-    RouteAuthorization,
+    AggregateRoaInfo, AspaInfo, BgpSecCertInfo, CertifiedKey, ChildDetails, CurrentObjectSetDelta,
+    PreparedRta, ResourceClass, Rfc8183Id, RoaInfo, RouteAuthorization, SignedRta,
 };
 
 //------------ Ini -----------------------------------------------------------
@@ -51,7 +52,7 @@ impl IniDet {
 impl IniDet {
     pub fn init<S: Signer>(handle: &Handle, signer: Arc<RwLock<S>>) -> KrillResult<Ini> {
         let mut signer = signer.write().unwrap();
-        let id = Rfc8183Id::generate(signer.deref_mut())?;
+        let id = Rfc8183Id::generate(signer.deref_mut(), None)?;
         Ok(Ini::new(
             handle,
             0,
@@ -74,10 +75,20 @@ impl fmt::Display for IniDet {
 //------------ RoaUpdates --------------------------------------------------
 
 /// Describes an update to the set of ROAs under a ResourceClass.
+///
+/// ROAs are updated either per authorization, or per ASN for aggregated
+/// ROAs. When a resource class switches between the two, then this will
+/// contain the ROAs to remove in the one form and to add in the other.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct RoaUpdates {
     updated: HashMap<RouteAuthorization, RoaInfo>,
     removed: HashMap<RouteAuthorization, RevokedObject>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aggregate_updated: HashMap<AsNumber, AggregateRoaInfo>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aggregate_removed: HashMap<AsNumber, RevokedObject>,
 }
 
 impl Default for RoaUpdates {
@@ -85,6 +96,8 @@ impl Default for RoaUpdates {
         RoaUpdates {
             updated: HashMap::new(),
             removed: HashMap::new(),
+            aggregate_updated: HashMap::new(),
+            aggregate_removed: HashMap::new(),
         }
     }
 }
@@ -94,11 +107,19 @@ impl RoaUpdates {
         updated: HashMap<RouteAuthorization, RoaInfo>,
         removed: HashMap<RouteAuthorization, RevokedObject>,
     ) -> Self {
-        RoaUpdates { updated, removed }
+        RoaUpdates {
+            updated,
+            removed,
+            aggregate_updated: HashMap::new(),
+            aggregate_removed: HashMap::new(),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.updated.is_empty() && self.removed.is_empty()
+        self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.aggregate_updated.is_empty()
+            && self.aggregate_removed.is_empty()
     }
 
     pub fn contains_changes(&self) -> bool {
@@ -113,9 +134,23 @@ impl RoaUpdates {
         self.removed.insert(auth, revoke);
     }
 
+    pub fn update_aggregate(&mut self, asn: AsNumber, info: AggregateRoaInfo) {
+        self.aggregate_updated.insert(asn, info);
+    }
+
+    pub fn remove_aggregate(&mut self, asn: AsNumber, revoke: RevokedObject) {
+        self.aggregate_removed.insert(asn, revoke);
+    }
+
+    fn all_updated(&self) -> impl Iterator<Item = &RoaInfo> {
+        self.updated
+            .values()
+            .chain(self.aggregate_updated.values().map(|info| info.roa()))
+    }
+
     pub fn added(&self) -> Vec<AddedObject> {
         let mut res = vec![];
-        for (_auth, info) in self.updated.iter() {
+        for info in self.all_updated() {
             if info.replaces().is_none() {
                 let object = info.object().clone();
                 let name = info.name().clone();
@@ -127,7 +162,7 @@ impl RoaUpdates {
 
     pub fn updated(&self) -> Vec<UpdatedObject> {
         let mut res = vec![];
-        for (_auth, info) in self.updated.iter() {
+        for info in self.all_updated() {
             if let Some(replaced) = info.replaces() {
                 let object = info.object().clone();
                 let name = info.name().clone();
@@ -144,29 +179,213 @@ impl RoaUpdates {
             let hash = revoked.hash().clone();
             res.push(WithdrawnObject::new(name, hash));
         }
+        for (asn, revoked) in self.aggregate_removed.iter() {
+            let name = ObjectName::aggregate_roa(*asn);
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(name, hash));
+        }
         res
     }
 
     pub fn revocations(&self) -> Vec<Revocation> {
         let mut res = vec![];
-        for info in self.updated.values() {
+        for info in self.all_updated() {
             if let Some(old) = info.replaces() {
                 res.push(old.revocation())
             }
         }
 
-        for revoked in self.removed.values() {
+        for revoked in self.removed.values().chain(self.aggregate_removed.values()) {
             res.push(revoked.revocation())
         }
 
         res
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn unpack(
         self,
     ) -> (
         HashMap<RouteAuthorization, RoaInfo>,
         HashMap<RouteAuthorization, RevokedObject>,
+        HashMap<AsNumber, AggregateRoaInfo>,
+        HashMap<AsNumber, RevokedObject>,
+    ) {
+        (
+            self.updated,
+            self.removed,
+            self.aggregate_updated,
+            self.aggregate_removed,
+        )
+    }
+}
+
+//------------ AspaObjectsUpdates ------------------------------------------
+
+/// Describes an update to the set of ASPA objects under a ResourceClass.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaObjectsUpdates {
+    updated: HashMap<AsNumber, AspaInfo>,
+    removed: HashMap<AsNumber, RevokedObject>,
+}
+
+impl AspaObjectsUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn contains_changes(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub fn update(&mut self, customer: AsNumber, aspa: AspaInfo) {
+        self.updated.insert(customer, aspa);
+    }
+
+    pub fn remove(&mut self, customer: AsNumber, revoke: RevokedObject) {
+        self.removed.insert(customer, revoke);
+    }
+
+    pub fn added(&self) -> Vec<AddedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if info.replaces().is_none() {
+                let object = info.object().clone();
+                let name = info.name().clone();
+                res.push(AddedObject::new(name, object));
+            }
+        }
+        res
+    }
+
+    pub fn updated(&self) -> Vec<UpdatedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(replaced) = info.replaces() {
+                let object = info.object().clone();
+                let name = info.name().clone();
+                res.push(UpdatedObject::new(name, object, replaced.hash().clone()));
+            }
+        }
+        res
+    }
+
+    pub fn withdrawn(&self) -> Vec<WithdrawnObject> {
+        let mut res = vec![];
+        for (customer, revoked) in self.removed.iter() {
+            let name = ObjectName::aspa(*customer);
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(name, hash));
+        }
+        res
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(old) = info.replaces() {
+                res.push(old.revocation())
+            }
+        }
+
+        for revoked in self.removed.values() {
+            res.push(revoked.revocation())
+        }
+
+        res
+    }
+
+    pub fn unpack(
+        self,
+    ) -> (
+        HashMap<AsNumber, AspaInfo>,
+        HashMap<AsNumber, RevokedObject>,
+    ) {
+        (self.updated, self.removed)
+    }
+}
+
+//------------ BgpSecCertificateUpdates ------------------------------------
+
+/// Describes an update to the set of BGPsec router certificates under a
+/// ResourceClass.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BgpSecCertificateUpdates {
+    updated: HashMap<BgpSecAsnKey, BgpSecCertInfo>,
+    removed: HashMap<BgpSecAsnKey, RevokedObject>,
+}
+
+impl BgpSecCertificateUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn contains_changes(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub fn update(&mut self, key: BgpSecAsnKey, cert: BgpSecCertInfo) {
+        self.updated.insert(key, cert);
+    }
+
+    pub fn remove(&mut self, key: BgpSecAsnKey, revoke: RevokedObject) {
+        self.removed.insert(key, revoke);
+    }
+
+    pub fn added(&self) -> Vec<AddedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if info.replaces().is_none() {
+                let object = info.object().clone();
+                let name = info.name().clone();
+                res.push(AddedObject::new(name, object));
+            }
+        }
+        res
+    }
+
+    pub fn updated(&self) -> Vec<UpdatedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(replaced) = info.replaces() {
+                let object = info.object().clone();
+                let name = info.name().clone();
+                res.push(UpdatedObject::new(name, object, replaced.hash().clone()));
+            }
+        }
+        res
+    }
+
+    pub fn withdrawn(&self) -> Vec<WithdrawnObject> {
+        let mut res = vec![];
+        for (key, revoked) in self.removed.iter() {
+            let name = ObjectName::bgpsec(key);
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(name, hash));
+        }
+        res
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(old) = info.replaces() {
+                res.push(old.revocation())
+            }
+        }
+
+        for revoked in self.removed.values() {
+            res.push(revoked.revocation())
+        }
+
+        res
+    }
+
+    pub fn unpack(
+        self,
+    ) -> (
+        HashMap<BgpSecAsnKey, BgpSecCertInfo>,
+        HashMap<BgpSecAsnKey, RevokedObject>,
     ) {
         (self.updated, self.removed)
     }
@@ -227,6 +446,7 @@ pub enum EvtDet {
 
     // Being a child Events
     IdUpdated(Rfc8183Id),
+    SignerUpdated(Option<String>),
     ParentAdded(ParentHandle, ParentCaContact),
     ParentUpdated(ParentHandle, ParentCaContact),
     ParentRemoved(ParentHandle, Vec<ObjectsDelta>),
@@ -251,15 +471,32 @@ pub enum EvtDet {
 
     // Route Authorizations
     RouteAuthorizationAdded(RouteAuthorization),
+    RouteAuthorizationAddedWithMeta(RouteAuthorization, RoaMeta),
     RouteAuthorizationRemoved(RouteAuthorization),
     RoasUpdated(ResourceClassName, RoaUpdates),
 
+    // ASPA
+    AspaConfigAdded(AspaDefinition),
+    AspaConfigUpdated(AsNumber, AspaProvidersUpdate),
+    AspaConfigRemoved(AsNumber),
+    AspaObjectsUpdated(ResourceClassName, AspaObjectsUpdates),
+
+    // BGPsec
+    BgpSecDefinitionAdded(BgpSecAsnKey, BgpSecDefinition),
+    BgpSecDefinitionRemoved(BgpSecAsnKey),
+    BgpSecCertificatesUpdated(ResourceClassName, BgpSecCertificateUpdates),
+
+    // RTA
+    RtaPrepared(RtaName, PreparedRta),
+    RtaSigned(RtaName, SignedRta),
+
     // Publishing
     ObjectSetUpdated(
         ResourceClassName,
         HashMap<KeyIdentifier, CurrentObjectSetDelta>,
     ),
     RepoUpdated(RepositoryContact),
+    RepoMigrationUpdated(RepoMigration),
     RepoCleaned(RepositoryContact),
 }
 
@@ -269,6 +506,11 @@ impl EvtDet {
         StoredEvent::new(handle, version, EvtDet::IdUpdated(id))
     }
 
+    /// This marks the preferred signer for new keys as updated
+    pub(super) fn signer_updated(handle: &Handle, version: u64, signer: Option<String>) -> Evt {
+        StoredEvent::new(handle, version, EvtDet::SignerUpdated(signer))
+    }
+
     /// This marks a parent as added to the CA.
     pub(super) fn parent_added(
         handle: &Handle,
@@ -489,6 +731,10 @@ impl fmt::Display for EvtDet {
             EvtDet::IdUpdated(id) => {
                 write!(f, "updated RFC8183 id to key '{}'", id.key_hash())
             }
+            EvtDet::SignerUpdated(signer) => match signer {
+                Some(signer) => write!(f, "updated preferred signer to '{}'", signer),
+                None => write!(f, "removed preferred signer"),
+            },
             EvtDet::ParentAdded(parent, contact) => {
                 let contact_str = match contact {
                     ParentCaContact::Embedded => "embedded",
@@ -568,6 +814,12 @@ impl fmt::Display for EvtDet {
                 "added ROA: '{}'",
                 route
             ),
+            EvtDet::RouteAuthorizationAddedWithMeta(route, meta) => write!(
+                f,
+                "added ROA: '{}{}'",
+                route,
+                meta
+            ),
             EvtDet::RouteAuthorizationRemoved(route) => write!(
                 f,
                 "removed ROA: '{}'",
@@ -587,9 +839,91 @@ impl fmt::Display for EvtDet {
                         write!(f, "{} ", auth)?;
                     }
                 }
+                if ! roa_updates.aggregate_updated.is_empty() {
+                    write!(f, " aggregated for: ")?;
+                    for asn in roa_updates.aggregate_updated.keys() {
+                        write!(f, "AS{} ", asn)?;
+                    }
+                }
+                if ! roa_updates.aggregate_removed.is_empty() {
+                    write!(f, " removed aggregated for: ")?;
+                    for asn in roa_updates.aggregate_removed.keys() {
+                        write!(f, "AS{} ", asn)?;
+                    }
+                }
                 Ok(())
             },
 
+            // ASPA
+            EvtDet::AspaConfigAdded(definition) => write!(
+                f,
+                "added ASPA: '{}'",
+                definition
+            ),
+            EvtDet::AspaConfigUpdated(customer, update) => write!(
+                f,
+                "updated ASPA for customer '{}': {}",
+                customer, update
+            ),
+            EvtDet::AspaConfigRemoved(customer) => write!(
+                f,
+                "removed ASPA for customer '{}'",
+                customer
+            ),
+            EvtDet::AspaObjectsUpdated(rcn, updates) => {
+                write!(f, "updated ASPA objects under resource class '{}'", rcn)?;
+                if !updates.updated.is_empty() {
+                    write!(f, " updated: ")?;
+                    for customer in updates.updated.keys() {
+                        write!(f, "AS{} ", customer)?;
+                    }
+                }
+                if !updates.removed.is_empty() {
+                    write!(f, " removed: ")?;
+                    for customer in updates.removed.keys() {
+                        write!(f, "AS{} ", customer)?;
+                    }
+                }
+                Ok(())
+            }
+
+            // BGPsec
+            EvtDet::BgpSecDefinitionAdded(key, _) => {
+                write!(f, "added BGPsec router key '{}'", key)
+            }
+            EvtDet::BgpSecDefinitionRemoved(key) => {
+                write!(f, "removed BGPsec router key '{}'", key)
+            }
+            EvtDet::BgpSecCertificatesUpdated(rcn, updates) => {
+                write!(
+                    f,
+                    "updated BGPsec router certificates under resource class '{}'",
+                    rcn
+                )?;
+                if !updates.updated.is_empty() {
+                    write!(f, " updated: ")?;
+                    for key in updates.updated.keys() {
+                        write!(f, "{} ", key)?;
+                    }
+                }
+                if !updates.removed.is_empty() {
+                    write!(f, " removed: ")?;
+                    for key in updates.removed.keys() {
+                        write!(f, "{} ", key)?;
+                    }
+                }
+                Ok(())
+            }
+
+            // RTA
+            EvtDet::RtaPrepared(name, prepared) => write!(
+                f,
+                "prepared {} key(s) for multi-signer RTA '{}'",
+                prepared.keys().len(),
+                name
+            ),
+            EvtDet::RtaSigned(name, _) => write!(f, "signed RTA '{}'", name),
+
             // Publishing
             EvtDet::ObjectSetUpdated(rcn, key_objects_map) => {
                 write!(f, "updated objects under resource class '{}'", rcn)?;
@@ -620,6 +954,9 @@ impl fmt::Display for EvtDet {
                     RepositoryContact::Rfc8181(res) => write!(f, "updated repository to remote server: {}", res.service_uri())
                 }
             }
+            EvtDet::RepoMigrationUpdated(migration) => {
+                write!(f, "repository migration: {}", migration)
+            }
             EvtDet::RepoCleaned(old) => {
                 match old {
                     RepositoryContact::Embedded(_) => write!(f, "cleaned old embedded repository"),
@@ -3,7 +3,7 @@ use std::ops::{Deref, DerefMut};
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
 
-use rpki::crypto::{KeyIdentifier, PublicKeyFormat};
+use rpki::crypto::KeyIdentifier;
 use rpki::csr::Csr;
 use rpki::x509::Time;
 
@@ -15,7 +15,7 @@ use crate::commons::api::{
 use crate::commons::error::Error;
 use crate::commons::KrillResult;
 use crate::constants::PUBLISH_THRESHOLD_HOURS;
-use crate::daemon::ca::{CurrentObjectSet, CurrentObjectSetDelta, EvtDet, Signer};
+use crate::daemon::ca::{CurrentObjectSet, CurrentObjectSetDelta, EvtDet, KeyPurpose, Signer};
 
 //------------ CertifiedKey --------------------------------------------------
 
@@ -533,13 +533,14 @@ impl KeyState {
         parent_class_name: ResourceClassName,
         base_repo: &RepoInfo,
         name_space: &str,
+        preferred_signer: Option<&str>,
         signer: &mut S,
     ) -> KrillResult<Vec<EvtDet>> {
         match self {
             KeyState::Active(_current) => {
                 let key_id = {
                     signer
-                        .create_key(PublicKeyFormat::default())
+                        .create_key_for(KeyPurpose::ResourceClass, preferred_signer)
                         .map_err(Error::signer)?
                 };
 
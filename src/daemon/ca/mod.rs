@@ -22,6 +22,15 @@ pub use self::publishing::*;
 mod routes;
 pub use self::routes::*;
 
+mod aspa;
+pub use self::aspa::*;
+
+mod bgpsec;
+pub use self::bgpsec::*;
+
+mod rta;
+pub use self::rta::*;
+
 mod commands;
 pub use self::commands::*;
 
@@ -17,7 +17,7 @@ use crate::commons::api::{
 };
 use crate::commons::KrillResult;
 use crate::constants::{PUBLISH_NEXT_HOURS, PUBLISH_VALID_DAYS};
-use crate::daemon::ca::{self, RoaInfo, RouteAuthorization, Signer};
+use crate::daemon::ca::{self, AspaInfo, BgpSecCertInfo, RoaInfo, Signer};
 
 //------------ AddedOrUpdated ----------------------------------------------
 
@@ -295,7 +295,9 @@ impl ManifestBuilder {
     pub fn new<'a>(
         crl_info: &CrlInfo,
         issued: impl Iterator<Item = &'a IssuedCert>,
-        roas: impl Iterator<Item = (&'a RouteAuthorization, &'a RoaInfo)>,
+        roas: impl Iterator<Item = &'a RoaInfo>,
+        aspas: impl Iterator<Item = &'a AspaInfo>,
+        router_certs: impl Iterator<Item = &'a BgpSecCertInfo>,
         delta: &ObjectsDelta,
     ) -> Self {
         let mut entries: HashMap<Bytes, Bytes> = HashMap::new();
@@ -316,13 +318,29 @@ impl ManifestBuilder {
         }
 
         // Add all *current* ROAs
-        for (_auth, roa_info) in roas {
+        for roa_info in roas {
             let name = roa_info.name().clone();
             let hash = Self::mft_hash(&roa_info.object().content().to_bytes());
 
             entries.insert(name.into(), hash);
         }
 
+        // Add all *current* ASPAs
+        for aspa_info in aspas {
+            let name = aspa_info.name().clone();
+            let hash = Self::mft_hash(&aspa_info.object().content().to_bytes());
+
+            entries.insert(name.into(), hash);
+        }
+
+        // Add all *current* BGPsec router certs
+        for router_cert_info in router_certs {
+            let name = router_cert_info.name().clone();
+            let hash = Self::mft_hash(&router_cert_info.object().content().to_bytes());
+
+            entries.insert(name.into(), hash);
+        }
+
         // Add all *new* objects
         for added in delta.added() {
             let name = added.name().clone();
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
@@ -10,20 +11,25 @@ use rpki::x509::Time;
 use crate::commons::api::rrdp::PublishElement;
 use crate::commons::api::Base64;
 use crate::commons::api::{
-    AddedObject, CurrentObject, CurrentObjects, EntitlementClass, HexEncodedHash, IssuanceRequest,
-    IssuedCert, ObjectName, ObjectsDelta, ParentHandle, RcvdCert, ReplacedObject, RepoInfo,
-    RequestResourceLimit, ResourceClassInfo, ResourceClassName, ResourceSet, Revocation,
-    RevocationRequest, RevokedObject, UpdatedObject, WithdrawnObject,
+    AddedObject, AsNumber, AspaDefinition, CurrentObject, CurrentObjects, EntitlementClass,
+    HexEncodedHash, IssuanceRequest, IssuedCert, ObjectName, ObjectsDelta, ParentHandle, RcvdCert,
+    ReplacedObject, RepoInfo, RequestResourceLimit, ResourceClassInfo, ResourceClassName,
+    ResourceSet, Revocation, RevocationRequest, RevokedObject, UpdatedObject, WithdrawnObject,
 };
 use crate::commons::error::Error;
 use crate::commons::KrillResult;
-use crate::constants::ROA_CERTIFICATE_REISSUE_WEEKS;
-use crate::daemon::ca::events::{ChildCertificateUpdates, RoaUpdates};
+use crate::constants::{
+    ASPA_CERTIFICATE_REISSUE_WEEKS, BGPSEC_CERTIFICATE_REISSUE_WEEKS, ROA_CERTIFICATE_REISSUE_WEEKS,
+};
+use crate::daemon::ca::events::{
+    AspaObjectsUpdates, BgpSecCertificateUpdates, ChildCertificateUpdates, RoaUpdates,
+};
 use crate::daemon::ca::signing::CsrInfo;
 use crate::daemon::ca::{
-    self, ta_handle, AddedOrUpdated, CertifiedKey, ChildCertificates, CrlBuilder, CurrentKey,
-    CurrentObjectSetDelta, EvtDet, KeyState, ManifestBuilder, NewKey, OldKey, PendingKey, RoaInfo,
-    Roas, RouteAuthorization, SignSupport, Signer,
+    self, ta_handle, AddedOrUpdated, AggregateRoaInfo, AspaInfo, AspaObjects, BgpSecCertInfo,
+    BgpSecCertificates, BgpSecCsr, BgpSecDefinitions, CertifiedKey, ChildCertificates, CrlBuilder,
+    CurrentKey, CurrentObjectSetDelta, EvtDet, KeyState, ManifestBuilder, NewKey, OldKey,
+    PendingKey, RoaAggregation, RoaInfo, Roas, RouteAuthorization, SignSupport, Signer,
 };
 
 //------------ ResourceClass -----------------------------------------------
@@ -48,6 +54,10 @@ pub struct ResourceClass {
     parent_rc_name: ResourceClassName,
 
     roas: Roas,
+    #[serde(default)]
+    aspas: AspaObjects,
+    #[serde(default)]
+    bgpsec_certs: BgpSecCertificates,
     certificates: ChildCertificates,
 
     last_key_change: Time,
@@ -71,6 +81,8 @@ impl ResourceClass {
             parent_handle,
             parent_rc_name,
             roas: Roas::default(),
+            aspas: AspaObjects::default(),
+            bgpsec_certs: BgpSecCertificates::default(),
             certificates: ChildCertificates::default(),
             last_key_change: Time::now(),
             key_state: KeyState::create(pending_key),
@@ -84,6 +96,8 @@ impl ResourceClass {
             parent_handle: ta_handle(),
             parent_rc_name,
             roas: Roas::default(),
+            aspas: AspaObjects::default(),
+            bgpsec_certs: BgpSecCertificates::default(),
             certificates: ChildCertificates::default(),
             last_key_change: Time::now(),
             key_state: KeyState::create(pending_key),
@@ -154,6 +168,14 @@ impl ResourceClass {
             current_objects.insert(roa_info.name().clone(), roa_info.object().clone());
         }
 
+        for aspa_info in self.aspas.current() {
+            current_objects.insert(aspa_info.name().clone(), aspa_info.object().clone());
+        }
+
+        for bgpsec_info in self.bgpsec_certs.current() {
+            current_objects.insert(bgpsec_info.name().clone(), bgpsec_info.object().clone());
+        }
+
         for issued in self.certificates.current() {
             let cert = issued.cert();
             current_objects.insert(ObjectName::from(cert), CurrentObject::from(cert));
@@ -208,6 +230,7 @@ impl ResourceClass {
         &self,
         rcvd_cert: RcvdCert,
         repo_info: &RepoInfo,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         // If this is for a pending key, then we need to promote this key
@@ -253,9 +276,13 @@ impl ResourceClass {
                     )])
                 }
             }
-            KeyState::Active(current) => {
-                self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
-            }
+            KeyState::Active(current) => self.update_rcvd_cert_current(
+                current,
+                rcvd_cert,
+                repo_info,
+                roa_aggregation,
+                signer,
+            ),
             KeyState::RollPending(pending, current) => {
                 if rcvd_cert_ki == pending.key_id() {
                     let (active_key, delta) = create_active_key_and_delta(
@@ -270,7 +297,13 @@ impl ResourceClass {
                         delta,
                     )])
                 } else {
-                    self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                    self.update_rcvd_cert_current(
+                        current,
+                        rcvd_cert,
+                        repo_info,
+                        roa_aggregation,
+                        signer,
+                    )
                 }
             }
             KeyState::RollNew(new, current) => {
@@ -281,12 +314,24 @@ impl ResourceClass {
                         rcvd_cert,
                     )])
                 } else {
-                    self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                    self.update_rcvd_cert_current(
+                        current,
+                        rcvd_cert,
+                        repo_info,
+                        roa_aggregation,
+                        signer,
+                    )
                 }
             }
             KeyState::RollOld(current, _old) => {
                 // We will never request a new certificate for an old key
-                self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                self.update_rcvd_cert_current(
+                    current,
+                    rcvd_cert,
+                    repo_info,
+                    roa_aggregation,
+                    signer,
+                )
             }
         }
     }
@@ -296,6 +341,7 @@ impl ResourceClass {
         current: &CurrentKey,
         rcvd_cert: RcvdCert,
         repo_info: &RepoInfo,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         let rcvd_cert_ki = rcvd_cert.cert().subject_key_identifier();
@@ -314,14 +360,8 @@ impl ResourceClass {
 
         if &rcvd_resources != current.incoming_cert().resources() {
             let publish_mode = PublishMode::UpdatedResources(rcvd_resources);
-            let authorizations: Vec<RouteAuthorization> =
-                self.roas.authorizations().cloned().collect();
-            res.append(&mut self.republish(
-                authorizations.as_slice(),
-                repo_info,
-                &publish_mode,
-                signer,
-            )?)
+            let objects = self.objects_to_publish(roa_aggregation);
+            res.append(&mut self.republish(&objects, repo_info, &publish_mode, signer)?)
         }
 
         Ok(res)
@@ -452,11 +492,22 @@ impl ResourceClass {
         }
     }
 
+    /// The objects currently published by this resource class, used when
+    /// republishing them under a new key or for new resources.
+    fn objects_to_publish(&self, roa_aggregation: RoaAggregation) -> ObjectsToPublish {
+        ObjectsToPublish::new(
+            self.roas.authorizations().cloned().collect(),
+            self.aspa_definitions(),
+            self.bgpsec_certs.definitions(),
+            roa_aggregation,
+        )
+    }
+
     /// Republish all keys in this class (that want it). Also update
-    /// ROAs as needed.
+    /// ROAs, ASPA objects and BGPsec router certificates as needed.
     pub fn republish<S: Signer>(
         &self,
-        authorizations: &[RouteAuthorization],
+        objects: &ObjectsToPublish,
         repo_info: &RepoInfo,
         mode: &PublishMode,
         signer: &S,
@@ -467,7 +518,12 @@ impl ResourceClass {
         let mut delta = ObjectsDelta::new(repo_info.ca_repository(ns));
         let mut revocations = vec![];
 
-        let roa_updates = self.update_roas(authorizations, mode, signer)?;
+        let roa_updates = self.update_roas(
+            &objects.authorizations,
+            mode,
+            objects.roa_aggregation,
+            signer,
+        )?;
         if roa_updates.contains_changes() {
             for added in roa_updates.added().into_iter() {
                 delta.add(added);
@@ -483,6 +539,41 @@ impl ResourceClass {
             res.push(EvtDet::RoasUpdated(self.name.clone(), roa_updates));
         }
 
+        let aspa_updates = self.update_aspas(&objects.aspas, mode, signer)?;
+        if aspa_updates.contains_changes() {
+            for added in aspa_updates.added().into_iter() {
+                delta.add(added);
+            }
+            for update in aspa_updates.updated().into_iter() {
+                delta.update(update);
+            }
+            for withdraw in aspa_updates.withdrawn().into_iter() {
+                delta.withdraw(withdraw);
+            }
+            revocations.append(&mut aspa_updates.revocations());
+
+            res.push(EvtDet::AspaObjectsUpdated(self.name.clone(), aspa_updates));
+        }
+
+        let bgpsec_updates = self.update_bgpsec_certs(&objects.bgpsec_defs, mode, signer)?;
+        if bgpsec_updates.contains_changes() {
+            for added in bgpsec_updates.added().into_iter() {
+                delta.add(added);
+            }
+            for update in bgpsec_updates.updated().into_iter() {
+                delta.update(update);
+            }
+            for withdraw in bgpsec_updates.withdrawn().into_iter() {
+                delta.withdraw(withdraw);
+            }
+            revocations.append(&mut bgpsec_updates.revocations());
+
+            res.push(EvtDet::BgpSecCertificatesUpdated(
+                self.name.clone(),
+                bgpsec_updates,
+            ));
+        }
+
         let child_cert_updates = self.update_child_certificates(mode, signer)?;
         if !child_cert_updates.is_empty() {
             for issued in child_cert_updates.issued() {
@@ -599,19 +690,25 @@ impl ResourceClass {
         // List all current files, i.e.
         //  - the new CRL
         //  - current ROAs
+        //  - current ASPAs
+        //  - current BGPsec router certs
         //  - current Certs
-        //  - applying the delta - which may update the current ROAs and Certs on the MFT
+        //  - applying the delta - which may update the current ROAs, ASPAs and Certs on the MFT
         let issued = self.certificates.current();
-        let roas = self.roas.iter();
-
-        let manifest_info = ManifestBuilder::new(&crl_info, issued, roas, &objects_delta).build(
-            signing_cert,
-            repo_info,
-            self.name_space(),
-            number,
-            Some(current_mft_hash),
-            signer,
-        )?;
+        let roas = self.roas.current();
+        let aspas = self.aspas.current();
+        let router_certs = self.bgpsec_certs.current();
+
+        let manifest_info =
+            ManifestBuilder::new(&crl_info, issued, roas, aspas, router_certs, &objects_delta)
+                .build(
+                    signing_cert,
+                    repo_info,
+                    self.name_space(),
+                    number,
+                    Some(current_mft_hash),
+                    signer,
+                )?;
 
         match manifest_info.added_or_updated() {
             AddedOrUpdated::Added(added) => objects_delta.add(added),
@@ -638,6 +735,20 @@ impl ResourceClass {
             let uri = base_repo.resolve(ns, object_name.as_str());
             res.push(PublishElement::new(base64, uri));
         }
+        // ASPAs
+        for info in self.aspas.current() {
+            let base64 = info.object().content().clone();
+            let object_name = info.name().clone();
+            let uri = base_repo.resolve(ns, object_name.as_str());
+            res.push(PublishElement::new(base64, uri));
+        }
+        // BGPsec router certs
+        for info in self.bgpsec_certs.current() {
+            let base64 = info.object().content().clone();
+            let object_name = info.name().clone();
+            let uri = base_repo.resolve(ns, object_name.as_str());
+            res.push(PublishElement::new(base64, uri));
+        }
         // Certs
         for cert in self.certificates.current() {
             let base64 = Base64::from_content(cert.to_captured().as_slice());
@@ -779,6 +890,7 @@ impl ResourceClass {
         &self,
         base_repo: &RepoInfo,
         duration: Duration,
+        preferred_signer: Option<&str>,
         signer: &mut S,
     ) -> KrillResult<Vec<EvtDet>> {
         if self.last_key_change + duration > Time::now() {
@@ -790,6 +902,7 @@ impl ResourceClass {
             self.parent_rc_name.clone(),
             base_repo,
             &self.name_space,
+            preferred_signer,
             signer,
         )
     }
@@ -799,6 +912,7 @@ impl ResourceClass {
         &self,
         repo_info: &RepoInfo,
         staging: Duration,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         if !self.key_state.has_new_key() || self.last_key_change + staging > Time::now() {
@@ -807,7 +921,7 @@ impl ResourceClass {
 
         let mut res = vec![];
 
-        let authorizations: Vec<RouteAuthorization> = self.roas.authorizations().cloned().collect();
+        let objects = self.objects_to_publish(roa_aggregation);
 
         res.push(self.key_state.keyroll_activate(
             self.name.clone(),
@@ -816,7 +930,7 @@ impl ResourceClass {
         )?);
 
         res.append(&mut self.republish(
-            authorizations.as_slice(),
+            &objects,
             repo_info,
             &PublishMode::KeyRollActivation,
             signer,
@@ -991,10 +1105,14 @@ impl ResourceClass {
 impl ResourceClass {
     /// Updates the ROAs in accordance with the current authorizations, and
     /// the target resources and key determined by the PublishMode.
+    ///
+    /// ROAs are aggregated per ASN, or de-aggregated again, depending on the
+    /// number of authorizations in this class and the RoaAggregation.
     pub fn update_roas<S: Signer>(
         &self,
         auths: &[RouteAuthorization],
         mode: &PublishMode,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<RoaUpdates> {
         let mut updates = RoaUpdates::default();
@@ -1015,37 +1133,98 @@ impl ResourceClass {
             _ => None,
         };
 
-        // Remove any ROAs no longer in auths, or no longer in resources.
-        for (current_auth, roa_info) in self.roas.iter() {
-            if !auths.contains(current_auth) || !resources.contains(&current_auth.prefix().into()) {
+        // Only the auths for which this resource class holds the prefix are relevant.
+        let auths: Vec<RouteAuthorization> = auths
+            .iter()
+            .filter(|auth| resources.contains(&auth.prefix().into()))
+            .cloned()
+            .collect();
+
+        // Re-issue if the ROA is getting close to its expiration time, or if we are
+        // activating the new key, or moving to a new repository.
+        let must_reissue = |roa: &RoaInfo| {
+            roa.object().expires() < Time::now() + Duration::weeks(ROA_CERTIFICATE_REISSUE_WEEKS)
+                || mode == &PublishMode::KeyRollActivation
+                || new_repo.is_some()
+        };
+
+        if roa_aggregation.aggregate(auths.len(), self.roas.is_aggregated()) {
+            // Remove all ROAs for single authorizations.
+            for (current_auth, roa_info) in self.roas.iter() {
                 updates.remove(*current_auth, RevokedObject::from(roa_info.object()));
             }
-        }
 
-        for auth in auths {
-            // if the auth is not in this resource class, just skip it.
-            if !resources.contains(&auth.prefix().into()) {
-                continue;
+            let mut grouped: HashMap<AsNumber, Vec<RouteAuthorization>> = HashMap::new();
+            for auth in auths {
+                grouped.entry(auth.asn()).or_default().push(auth);
             }
 
-            match self.roas.get(auth) {
-                None => {
-                    // NO ROA yet, so create one.
-                    let roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
-                    let name = ObjectName::from(auth);
-                    updates.update(*auth, RoaInfo::new_roa(&roa, name));
+            // Remove any aggregated ROAs for ASNs no longer authorized.
+            for (asn, info) in self.roas.iter_aggregate() {
+                if !grouped.contains_key(asn) {
+                    updates.remove_aggregate(*asn, RevokedObject::from(info.roa().object()));
                 }
-                Some(roa) => {
-                    // Re-issue if the ROA is getting close to its expiration time, or if we are
-                    //  activating the new key.
-                    let expiring = roa.object().expires()
-                        < Time::now() + Duration::weeks(ROA_CERTIFICATE_REISSUE_WEEKS);
-                    let activating = mode == &PublishMode::KeyRollActivation;
+            }
 
-                    if expiring || activating || new_repo.is_some() {
-                        let new_roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
+            for (asn, mut asn_auths) in grouped.into_iter() {
+                asn_auths.sort();
+
+                match self.roas.get_aggregate(asn) {
+                    None => {
+                        let roa = Roas::make_aggregate_roa(
+                            asn,
+                            &asn_auths,
+                            key,
+                            new_repo.as_ref(),
+                            signer,
+                        )?;
+                        let name = ObjectName::aggregate_roa(asn);
+                        let info = RoaInfo::new_roa(&roa, name);
+                        updates.update_aggregate(asn, AggregateRoaInfo::new(asn_auths, info));
+                    }
+                    Some(existing) => {
+                        if existing.authorizations() != &asn_auths || must_reissue(existing.roa()) {
+                            let roa = Roas::make_aggregate_roa(
+                                asn,
+                                &asn_auths,
+                                key,
+                                new_repo.as_ref(),
+                                signer,
+                            )?;
+                            let name = ObjectName::aggregate_roa(asn);
+                            let info = RoaInfo::updated_roa(existing.roa(), &roa, name);
+                            updates.update_aggregate(asn, AggregateRoaInfo::new(asn_auths, info));
+                        }
+                    }
+                }
+            }
+        } else {
+            // Remove all aggregated ROAs.
+            for (asn, info) in self.roas.iter_aggregate() {
+                updates.remove_aggregate(*asn, RevokedObject::from(info.roa().object()));
+            }
+
+            // Remove any ROAs no longer in auths, or no longer in resources.
+            for (current_auth, roa_info) in self.roas.iter() {
+                if !auths.contains(current_auth) {
+                    updates.remove(*current_auth, RevokedObject::from(roa_info.object()));
+                }
+            }
+
+            for auth in auths.iter() {
+                match self.roas.get(auth) {
+                    None => {
+                        // NO ROA yet, so create one.
+                        let roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
                         let name = ObjectName::from(auth);
-                        updates.update(*auth, RoaInfo::updated_roa(roa, &new_roa, name));
+                        updates.update(*auth, RoaInfo::new_roa(&roa, name));
+                    }
+                    Some(roa) => {
+                        if must_reissue(roa) {
+                            let new_roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
+                            let name = ObjectName::from(auth);
+                            updates.update(*auth, RoaInfo::updated_roa(roa, &new_roa, name));
+                        }
                     }
                 }
             }
@@ -1060,6 +1239,208 @@ impl ResourceClass {
     }
 }
 
+/// # ASPAs
+///
+impl ResourceClass {
+    /// Returns the definitions for the ASPA objects in this class.
+    fn aspa_definitions(&self) -> Vec<AspaDefinition> {
+        self.aspas
+            .current()
+            .map(|info| info.definition().clone())
+            .collect()
+    }
+
+    /// Updates the ASPA objects in accordance with the current definitions,
+    /// and the target resources and key determined by the PublishMode. An
+    /// ASPA object is only issued for a customer ASN held in this class.
+    pub fn update_aspas<S: Signer>(
+        &self,
+        definitions: &[AspaDefinition],
+        mode: &PublishMode,
+        signer: &S,
+    ) -> KrillResult<AspaObjectsUpdates> {
+        let mut updates = AspaObjectsUpdates::default();
+
+        let key = match mode {
+            PublishMode::KeyRollActivation => self.get_new_key()?,
+            _ => self.get_current_key()?,
+        };
+
+        let resources = match mode {
+            PublishMode::Normal | PublishMode::NewRepo(_) => key.incoming_cert().resources(),
+            PublishMode::UpdatedResources(resources) => resources,
+            PublishMode::KeyRollActivation => self.get_current_key()?.incoming_cert().resources(),
+        };
+
+        let new_repo = match &mode {
+            PublishMode::NewRepo(info) => Some(info.ca_repository(self.name_space())),
+            _ => None,
+        };
+
+        // Remove any ASPA objects no longer defined, or for customers no
+        // longer held.
+        for (customer, aspa_info) in self.aspas.iter() {
+            let defined = definitions.iter().any(|def| def.customer() == *customer);
+            if !defined || !resources.contains(&(*customer).into()) {
+                updates.remove(*customer, RevokedObject::from(aspa_info.object()));
+            }
+        }
+
+        for definition in definitions {
+            let customer = definition.customer();
+
+            // if the customer ASN is not in this resource class, just skip it.
+            if !resources.contains(&customer.into()) {
+                continue;
+            }
+
+            match self.aspas.get(customer) {
+                None => {
+                    let aspa = AspaObjects::make_aspa(definition, key, new_repo.as_ref(), signer)?;
+                    updates.update(customer, AspaInfo::new_aspa(definition.clone(), &aspa));
+                }
+                Some(aspa_info) => {
+                    // Re-issue if the definition changed, if the ASPA is getting close
+                    // to its expiration time, or if we are activating the new key.
+                    let changed = aspa_info.definition() != definition;
+                    let expiring = aspa_info.object().expires()
+                        < Time::now() + Duration::weeks(ASPA_CERTIFICATE_REISSUE_WEEKS);
+                    let activating = mode == &PublishMode::KeyRollActivation;
+
+                    if changed || expiring || activating || new_repo.is_some() {
+                        let aspa =
+                            AspaObjects::make_aspa(definition, key, new_repo.as_ref(), signer)?;
+                        updates.update(
+                            customer,
+                            AspaInfo::updated_aspa(aspa_info, definition.clone(), &aspa),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Marks the ASPA objects as updated from an AspaObjectsUpdated event.
+    pub fn aspas_updated(&mut self, updates: AspaObjectsUpdates) {
+        self.aspas.updated(updates);
+    }
+}
+
+/// # BGPsec router certificates
+///
+impl ResourceClass {
+    /// Updates the router certificates in accordance with the current
+    /// BGPsec router keys, and the target resources and key determined by
+    /// the PublishMode. A router certificate is only issued for an ASN held
+    /// in this class.
+    pub fn update_bgpsec_certs<S: Signer>(
+        &self,
+        definitions: &BgpSecDefinitions,
+        mode: &PublishMode,
+        signer: &S,
+    ) -> KrillResult<BgpSecCertificateUpdates> {
+        let mut updates = BgpSecCertificateUpdates::default();
+
+        let key = match mode {
+            PublishMode::KeyRollActivation => self.get_new_key()?,
+            _ => self.get_current_key()?,
+        };
+
+        let resources = match mode {
+            PublishMode::Normal | PublishMode::NewRepo(_) => key.incoming_cert().resources(),
+            PublishMode::UpdatedResources(resources) => resources,
+            PublishMode::KeyRollActivation => self.get_current_key()?.incoming_cert().resources(),
+        };
+
+        let new_repo = match &mode {
+            PublishMode::NewRepo(info) => Some(info.ca_repository(self.name_space())),
+            _ => None,
+        };
+
+        // Revoke any router certificates for keys that were removed, or for
+        // ASNs no longer held.
+        for (asn_key, info) in self.bgpsec_certs.iter() {
+            if !definitions.has(asn_key) || !resources.contains(&asn_key.asn().into()) {
+                updates.remove(asn_key.clone(), RevokedObject::from(info.object()));
+            }
+        }
+
+        for (asn_key, definition) in definitions.iter() {
+            // if the ASN is not in this resource class, just skip it.
+            if !resources.contains(&asn_key.asn().into()) {
+                continue;
+            }
+
+            let existing = self.bgpsec_certs.get(asn_key);
+
+            // Re-issue if the router cert is getting close to its expiration
+            // time, or if we are activating the new key.
+            if let Some(info) = existing {
+                let expiring = info.object().expires()
+                    < Time::now() + Duration::weeks(BGPSEC_CERTIFICATE_REISSUE_WEEKS);
+                let activating = mode == &PublishMode::KeyRollActivation;
+
+                if !expiring && !activating && new_repo.is_none() {
+                    continue;
+                }
+            }
+
+            let csr = BgpSecCsr::try_from(definition.csr()).map_err(Error::custom)?;
+            let cert = SignSupport::make_bgpsec_router_cert(
+                &csr,
+                asn_key.asn(),
+                key,
+                new_repo.as_ref(),
+                signer,
+            )?;
+
+            let info = match existing {
+                None => BgpSecCertInfo::new_cert(asn_key, definition.clone(), &cert),
+                Some(old) => BgpSecCertInfo::updated_cert(old, asn_key, definition.clone(), &cert),
+            };
+            updates.update(asn_key.clone(), info);
+        }
+
+        Ok(updates)
+    }
+
+    /// Marks the router certificates as updated from a
+    /// BgpSecCertificatesUpdated event.
+    pub fn bgpsec_certs_updated(&mut self, updates: BgpSecCertificateUpdates) {
+        self.bgpsec_certs.updated(updates);
+    }
+}
+
+//------------ ObjectsToPublish --------------------------------------------
+
+/// The definitions of the ROAs, ASPA objects and BGPsec router certificates
+/// which a resource class should publish, and how ROAs are aggregated.
+#[derive(Clone, Debug)]
+pub struct ObjectsToPublish {
+    authorizations: Vec<RouteAuthorization>,
+    aspas: Vec<AspaDefinition>,
+    bgpsec_defs: BgpSecDefinitions,
+    roa_aggregation: RoaAggregation,
+}
+
+impl ObjectsToPublish {
+    pub fn new(
+        authorizations: Vec<RouteAuthorization>,
+        aspas: Vec<AspaDefinition>,
+        bgpsec_defs: BgpSecDefinitions,
+        roa_aggregation: RoaAggregation,
+    ) -> Self {
+        ObjectsToPublish {
+            authorizations,
+            aspas,
+            bgpsec_defs,
+            roa_aggregation,
+        }
+    }
+}
+
 //------------ PublishMode -------------------------------------------------
 
 /// Describes which kind of publication we're after:
@@ -11,10 +11,14 @@ use rpki::uri;
 use rpki::x509::{Serial, Time};
 
 use crate::commons::api::{
-    CurrentObject, ObjectName, ReplacedObject, RoaDefinition, RoaDefinitionUpdates,
+    AsNumber, CurrentObject, ObjectName, ReplacedObject, RoaConfiguration, RoaDefinition,
+    RoaDefinitionUpdates, RoaMeta,
 };
 use crate::commons::KrillResult;
-use crate::constants::ROA_CERTIFICATE_VALIDITY_YEARS;
+use crate::constants::{
+    ROA_AGGREGATE_THRESHOLD_DEFAULT, ROA_CERTIFICATE_VALIDITY_YEARS,
+    ROA_DEAGGREGATE_THRESHOLD_DEFAULT,
+};
 use crate::daemon::ca::events::RoaUpdates;
 use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
 
@@ -22,7 +26,7 @@ use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
 
 /// This type defines a prefix and optional maximum length (other than the
 /// prefix length) which is to be authorized for the given origin ASN.
-#[derive(Clone, Copy, Debug, Display, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Display, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct RouteAuthorization(RoaDefinition);
 
 impl RouteAuthorization {
@@ -90,16 +94,32 @@ impl From<RouteAuthorization> for RoaDefinition {
 ///
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct RouteAuthorizationUpdates {
-    added: HashSet<RouteAuthorization>,
+    added: HashMap<RouteAuthorization, RoaMeta>,
     removed: HashSet<RouteAuthorization>,
 }
 
 impl RouteAuthorizationUpdates {
-    pub fn new(added: HashSet<RouteAuthorization>, removed: HashSet<RouteAuthorization>) -> Self {
+    pub fn new(
+        added: HashMap<RouteAuthorization, RoaMeta>,
+        removed: HashSet<RouteAuthorization>,
+    ) -> Self {
         RouteAuthorizationUpdates { added, removed }
     }
 
-    pub fn unpack(self) -> (HashSet<RouteAuthorization>, HashSet<RouteAuthorization>) {
+    /// Creates an update which only removes the given authorizations.
+    pub fn removals(removed: HashSet<RouteAuthorization>) -> Self {
+        RouteAuthorizationUpdates {
+            added: HashMap::new(),
+            removed,
+        }
+    }
+
+    pub fn unpack(
+        self,
+    ) -> (
+        HashMap<RouteAuthorization, RoaMeta>,
+        HashSet<RouteAuthorization>,
+    ) {
         (self.added, self.removed)
     }
 }
@@ -107,7 +127,13 @@ impl RouteAuthorizationUpdates {
 impl From<RoaDefinitionUpdates> for RouteAuthorizationUpdates {
     fn from(definitions: RoaDefinitionUpdates) -> Self {
         let (added, removed) = definitions.unpack();
-        let added = added.into_iter().map(RoaDefinition::into).collect();
+        let added = added
+            .into_iter()
+            .map(|config| {
+                let (definition, meta) = config.unpack();
+                (definition.into(), meta)
+            })
+            .collect();
         let removed = removed.into_iter().map(RoaDefinition::into).collect();
         RouteAuthorizationUpdates { added, removed }
     }
@@ -117,7 +143,7 @@ impl fmt::Display for RouteAuthorizationUpdates {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if !self.added.is_empty() {
             write!(f, "added:")?;
-            for a in &self.added {
+            for a in self.added.keys() {
                 write!(f, " {}", a)?;
             }
             write!(f, " ")?;
@@ -161,9 +187,27 @@ impl Routes {
         self.map.contains_key(auth)
     }
 
+    /// Returns all authorizations together with their meta-information.
+    pub fn configurations(&self) -> Vec<RoaConfiguration> {
+        self.map
+            .iter()
+            .map(|(auth, info)| RoaConfiguration::new(auth.0, info.meta.clone()))
+            .collect()
+    }
+
+    /// Returns all authorizations which have a 'valid until' time that is
+    /// not after the given time.
+    pub fn expired(&self, now: Time) -> HashSet<RouteAuthorization> {
+        self.map
+            .iter()
+            .filter(|(_, info)| info.meta.expired(now))
+            .map(|(auth, _)| *auth)
+            .collect()
+    }
+
     /// Adds a new authorization, or updates an existing one.
-    pub fn add(&mut self, auth: RouteAuthorization) {
-        self.map.insert(auth, RouteInfo::default());
+    pub fn add(&mut self, auth: RouteAuthorization, meta: RoaMeta) {
+        self.map.insert(auth, RouteInfo::new(meta));
     }
 
     /// Removes an authorization
@@ -178,11 +222,30 @@ impl Routes {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RouteInfo {
     since: Time, // authorization first added by user
+    #[serde(flatten)]
+    meta: RoaMeta, // optional comment, tags and 'valid until' time
+}
+
+impl RouteInfo {
+    pub fn new(meta: RoaMeta) -> Self {
+        RouteInfo {
+            since: Time::now(),
+            meta,
+        }
+    }
+
+    pub fn since(&self) -> Time {
+        self.since
+    }
+
+    pub fn meta(&self) -> &RoaMeta {
+        &self.meta
+    }
 }
 
 impl Default for RouteInfo {
     fn default() -> Self {
-        RouteInfo { since: Time::now() }
+        RouteInfo::new(RoaMeta::default())
     }
 }
 
@@ -235,18 +298,97 @@ impl RoaInfo {
     }
 }
 
+//------------ AggregateRoaInfo --------------------------------------------
+
+/// A ROA which includes all authorizations for an ASN in a resource class.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AggregateRoaInfo {
+    authorizations: Vec<RouteAuthorization>, // sorted
+    roa: RoaInfo,
+}
+
+impl AggregateRoaInfo {
+    pub fn new(mut authorizations: Vec<RouteAuthorization>, roa: RoaInfo) -> Self {
+        authorizations.sort();
+        AggregateRoaInfo {
+            authorizations,
+            roa,
+        }
+    }
+
+    pub fn authorizations(&self) -> &Vec<RouteAuthorization> {
+        &self.authorizations
+    }
+
+    pub fn roa(&self) -> &RoaInfo {
+        &self.roa
+    }
+}
+
+//------------ RoaAggregation ----------------------------------------------
+
+/// Determines whether the ROAs in a resource class are published as one
+/// ROA per authorization, or as one ROA per ASN including all authorizations
+/// for that ASN.
+///
+/// ROAs are aggregated when the number of authorizations in a resource class
+/// exceeds the aggregate threshold, and they are de-aggregated again when
+/// this number drops below the de-aggregate threshold. Using a lower
+/// threshold for de-aggregation ensures that ROAs are not flipped back and
+/// forth when authorizations around the threshold are added and removed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RoaAggregation {
+    aggregate_threshold: usize,
+    deaggregate_threshold: usize,
+}
+
+impl RoaAggregation {
+    pub fn new(aggregate_threshold: usize, deaggregate_threshold: usize) -> Self {
+        RoaAggregation {
+            aggregate_threshold,
+            deaggregate_threshold,
+        }
+    }
+
+    /// Returns whether ROAs should be aggregated for the given number of
+    /// authorizations, given whether they are aggregated now.
+    pub fn aggregate(&self, authorizations: usize, aggregated: bool) -> bool {
+        if aggregated {
+            authorizations >= self.deaggregate_threshold
+        } else {
+            authorizations > self.aggregate_threshold
+        }
+    }
+}
+
+impl Default for RoaAggregation {
+    fn default() -> Self {
+        RoaAggregation::new(
+            ROA_AGGREGATE_THRESHOLD_DEFAULT,
+            ROA_DEAGGREGATE_THRESHOLD_DEFAULT,
+        )
+    }
+}
+
 //------------ Roas --------------------------------------------------------
 
 /// ROAs held by a resource class in a CA.
+///
+/// ROAs are either held per authorization, or aggregated per ASN. See
+/// [`RoaAggregation`] for when they are aggregated.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Roas {
     inner: HashMap<RouteAuthorization, RoaInfo>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aggregate: HashMap<AsNumber, AggregateRoaInfo>,
 }
 
 impl Default for Roas {
     fn default() -> Self {
         Roas {
             inner: HashMap::new(),
+            aggregate: HashMap::new(),
         }
     }
 }
@@ -256,8 +398,17 @@ impl Roas {
         self.inner.get(auth)
     }
 
+    pub fn get_aggregate(&self, asn: AsNumber) -> Option<&AggregateRoaInfo> {
+        self.aggregate.get(&asn)
+    }
+
+    /// Returns true if the ROAs are currently aggregated per ASN.
+    pub fn is_aggregated(&self) -> bool {
+        !self.aggregate.is_empty()
+    }
+
     pub fn updated(&mut self, updates: RoaUpdates) {
-        let (updated, removed) = updates.unpack();
+        let (updated, removed, aggregate_updated, aggregate_removed) = updates.unpack();
 
         for (auth, info) in updated.into_iter() {
             self.inner.insert(auth, info);
@@ -266,18 +417,38 @@ impl Roas {
         for auth in removed.keys() {
             self.inner.remove(auth);
         }
+
+        for (asn, info) in aggregate_updated.into_iter() {
+            self.aggregate.insert(asn, info);
+        }
+
+        for asn in aggregate_removed.keys() {
+            self.aggregate.remove(asn);
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&RouteAuthorization, &RoaInfo)> {
         self.inner.iter()
     }
 
+    pub fn iter_aggregate(&self) -> impl Iterator<Item = (&AsNumber, &AggregateRoaInfo)> {
+        self.aggregate.iter()
+    }
+
     pub fn current(&self) -> impl Iterator<Item = &RoaInfo> {
-        self.inner.values()
+        self.inner
+            .values()
+            .chain(self.aggregate.values().map(|info| info.roa()))
     }
 
+    /// Returns all authorizations for which ROAs are held, whether they
+    /// are aggregated or not.
     pub fn authorizations(&self) -> impl Iterator<Item = &RouteAuthorization> {
-        self.inner.keys()
+        self.inner.keys().chain(
+            self.aggregate
+                .values()
+                .flat_map(|info| info.authorizations().iter()),
+        )
     }
 
     pub fn make_roa<S: Signer>(
@@ -286,8 +457,30 @@ impl Roas {
         new_repo: Option<&uri::Rsync>,
         signer: &S,
     ) -> KrillResult<Roa> {
-        let prefix = auth.prefix();
+        let name = ObjectName::from(auth);
+        Self::make_roa_for_asn(auth.asn(), &[*auth], name, certified_key, new_repo, signer)
+    }
+
+    /// Makes a single ROA for all authorizations for the given ASN.
+    pub fn make_aggregate_roa<S: Signer>(
+        asn: AsNumber,
+        auths: &[RouteAuthorization],
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<Roa> {
+        let name = ObjectName::aggregate_roa(asn);
+        Self::make_roa_for_asn(asn, auths, name, certified_key, new_repo, signer)
+    }
 
+    fn make_roa_for_asn<S: Signer>(
+        asn: AsNumber,
+        auths: &[RouteAuthorization],
+        name: ObjectName,
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<Roa> {
         let incoming_cert = certified_key.incoming_cert();
         let crl_uri = match &new_repo {
             None => incoming_cert.crl_uri(),
@@ -295,20 +488,23 @@ impl Roas {
         };
 
         let roa_uri = match &new_repo {
-            None => incoming_cert.uri_for_object(auth),
-            Some(base_uri) => base_uri.join(ObjectName::from(auth).as_bytes()),
+            None => incoming_cert.uri_for_object(name),
+            Some(base_uri) => base_uri.join(name.as_bytes()),
         };
 
         let aia = incoming_cert.uri();
 
         let signing_key = certified_key.key_id();
 
-        let mut roa_builder = RoaBuilder::new(auth.asn().into());
+        let mut roa_builder = RoaBuilder::new(asn.into());
 
-        if auth.effective_max_length() > auth.prefix().prefix().addr_len() {
-            roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), auth.max_length());
-        } else {
-            roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), None);
+        for auth in auths {
+            let prefix = auth.prefix();
+            if auth.effective_max_length() > prefix.prefix().addr_len() {
+                roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), auth.max_length());
+            } else {
+                roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), None);
+            }
         }
 
         let mut object_builder = SignedObjectBuilder::new(
@@ -352,4 +548,50 @@ mod tests {
         parse_encode_authorization("2001:db8::/32 => 64496");
         parse_encode_authorization("2001:db8::/32-48 => 64496");
     }
+
+    #[test]
+    fn roa_aggregation_thresholds() {
+        let aggregation = RoaAggregation::new(3, 2);
+
+        // not aggregated yet: aggregate when exceeding the aggregate threshold
+        assert!(!aggregation.aggregate(3, false));
+        assert!(aggregation.aggregate(4, false));
+
+        // aggregated: stay aggregated until dropping below the de-aggregate threshold
+        assert!(aggregation.aggregate(3, true));
+        assert!(aggregation.aggregate(2, true));
+        assert!(!aggregation.aggregate(1, true));
+    }
+
+    #[test]
+    fn deserialize_roas_without_aggregates() {
+        let roas: Roas = serde_json::from_str(r#"{"inner":{}}"#).unwrap();
+        assert!(!roas.is_aggregated());
+        assert_eq!(Roas::default(), roas);
+        assert_eq!(r#"{"inner":{}}"#, serde_json::to_string(&roas).unwrap());
+    }
+
+    #[test]
+    fn routes_expired() {
+        let routes_json = r#"{"map":{
+            "192.168.0.0/16 => 64496":{"since":"2020-01-01T00:00:00Z"},
+            "192.168.1.0/24 => 64496":{"since":"2020-01-01T00:00:00Z","valid_until":"2020-06-01T00:00:00Z"},
+            "192.168.2.0/24 => 64496":{"since":"2020-01-01T00:00:00Z","comment":"keep","valid_until":"2030-01-01T00:00:00Z"}
+        }}"#;
+        let routes: Routes = serde_json::from_str(routes_json).unwrap();
+
+        let now = Time::from_str("2021-01-01T00:00:00Z").unwrap();
+        let expired = routes.expired(now);
+
+        let expected_auth =
+            RouteAuthorization(RoaDefinition::from_str("192.168.1.0/24 => 64496").unwrap());
+        assert_eq!(1, expired.len());
+        assert!(expired.contains(&expected_auth));
+
+        let kept = RouteAuthorization(RoaDefinition::from_str("192.168.2.0/24 => 64496").unwrap());
+        assert_eq!(
+            Some(&"keep".to_string()),
+            routes.info(&kept).unwrap().meta().comment()
+        );
+    }
 }
@@ -0,0 +1,1053 @@
+//! Resource Tagged Attestations (RTA).
+//!
+//! An RTA is a signed object over the digest of an arbitrary document. It
+//! attests that the holders of a set of resources endorse the document. See
+//! draft-ietf-sidrops-rpki-rta for the format.
+//!
+//! Unlike other RPKI signed objects, an RTA can be signed by more than one
+//! EE certificate, and it is not published. So, the CMS structure is handled
+//! here rather than through the rpki crate's `SignedObject`. The RTA
+//! includes the EE certificates and the certificates of the signing CAs, so
+//! that it can be validated by others without access to the repositories.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bcder::encode::PrimitiveContent;
+use bcder::string::OctetString;
+use bcder::{decode, encode, Captured, ConstOid, Mode, Oid, Tag};
+use bytes::Bytes;
+
+use rpki::cert::{Cert, ResourceCert};
+use rpki::crypto::{
+    DigestAlgorithm, KeyIdentifier, PublicKey, Signature, SignatureAlgorithm, Signer as RpkiSigner,
+    SigningError,
+};
+use rpki::oid;
+use rpki::resources::{AsBlock, AsBlocks, AsBlocksBuilder, AsId, IpBlocks, IpResources};
+use rpki::tal::{Tal, TalInfo};
+use rpki::x509::{Time, Validity};
+
+use crate::commons::api::{
+    ResourceClassName, ResourceSet, ResourceTaggedAttestation, RtaList, RtaName, RtaPrepResponse,
+    RtaValidationReport,
+};
+
+/// The content type for RTAs: 1.2.840.113549.1.9.16.1.36
+const ID_CT_RTA: ConstOid = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 36]);
+
+/// The maximum length of a certificate chain that is followed when
+/// validating an RTA.
+const MAX_CHAIN_LENGTH: usize = 16;
+
+//------------ Rtas --------------------------------------------------------
+
+/// The RTAs of a CA, which are either signed, or prepared for co-signing.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Rtas {
+    map: HashMap<RtaName, RtaState>,
+}
+
+impl Rtas {
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+
+    pub fn list(&self) -> RtaList {
+        RtaList::new(self.map.keys().cloned().collect())
+    }
+
+    pub fn signed(&self, name: &str) -> Option<&SignedRta> {
+        match self.map.get(name) {
+            Some(RtaState::Signed(signed)) => Some(signed),
+            _ => None,
+        }
+    }
+
+    pub fn prepared(&self, name: &str) -> Option<&PreparedRta> {
+        match self.map.get(name) {
+            Some(RtaState::Prepared(prepared)) => Some(prepared),
+            _ => None,
+        }
+    }
+
+    pub fn add_prepared(&mut self, name: RtaName, prepared: PreparedRta) {
+        self.map.insert(name, RtaState::Prepared(prepared));
+    }
+
+    /// Adds a signed RTA. This replaces the RTA if it was prepared before.
+    pub fn add_signed(&mut self, name: RtaName, signed: SignedRta) {
+        self.map.insert(name, RtaState::Signed(signed));
+    }
+}
+
+//------------ RtaState ----------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RtaState {
+    Prepared(PreparedRta),
+    Signed(SignedRta),
+}
+
+//------------ PreparedRta -------------------------------------------------
+
+/// The keys that a CA created for co-signing a multi-signer RTA, one for
+/// each resource class holding some of the resources.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PreparedRta {
+    resources: ResourceSet,
+    validity: Validity,
+    keys: HashMap<ResourceClassName, KeyIdentifier>,
+}
+
+impl PreparedRta {
+    pub fn new(
+        resources: ResourceSet,
+        validity: Validity,
+        keys: HashMap<ResourceClassName, KeyIdentifier>,
+    ) -> Self {
+        PreparedRta {
+            resources,
+            validity,
+            keys,
+        }
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    pub fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    pub fn keys(&self) -> &HashMap<ResourceClassName, KeyIdentifier> {
+        &self.keys
+    }
+
+    pub fn as_response(&self) -> RtaPrepResponse {
+        RtaPrepResponse::new(self.keys.values().cloned().collect())
+    }
+}
+
+//------------ SignedRta ---------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SignedRta {
+    resources: ResourceSet,
+    rta: ResourceTaggedAttestation,
+}
+
+impl SignedRta {
+    pub fn new(resources: ResourceSet, rta: ResourceTaggedAttestation) -> Self {
+        SignedRta { resources, rta }
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    pub fn rta(&self) -> &ResourceTaggedAttestation {
+        &self.rta
+    }
+}
+
+//------------ RtaContent --------------------------------------------------
+
+/// The attestation contained in an RTA.
+///
+/// ```text
+/// ResourceTaggedAttestation ::= SEQUENCE {
+///     version [0] INTEGER DEFAULT 0,
+///     subjectKeyIdentifiers SET SIZE (1..MAX) OF SubjectKeyIdentifier,
+///     resources ResourceBlock,
+///     digestAlgorithm AlgorithmIdentifier,
+///     messageDigest OCTET STRING }
+///
+/// ResourceBlock ::= SEQUENCE {
+///     asID [0] AsList OPTIONAL,
+///     ipAddrBlocks [1] IPList OPTIONAL }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RtaContent {
+    subject_keys: Vec<KeyIdentifier>,
+    resources: ResourceSet,
+    digest: Bytes,
+}
+
+impl RtaContent {
+    pub fn new(
+        mut subject_keys: Vec<KeyIdentifier>,
+        resources: ResourceSet,
+        document: &[u8],
+    ) -> Self {
+        subject_keys.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        subject_keys.dedup();
+        let digest = DigestAlgorithm::default().digest(document);
+        RtaContent {
+            subject_keys,
+            resources,
+            digest: Bytes::copy_from_slice(digest.as_ref()),
+        }
+    }
+
+    pub fn subject_keys(&self) -> &Vec<KeyIdentifier> {
+        &self.subject_keys
+    }
+
+    pub fn resources(&self) -> &ResourceSet {
+        &self.resources
+    }
+
+    /// Returns whether this attests the given document.
+    pub fn matches(&self, document: &[u8]) -> bool {
+        DigestAlgorithm::default().digest(document).as_ref() == self.digest.as_ref()
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        let asns = self.resources.asn();
+        let v4 = self.resources.to_ip_resources_v4();
+        let v6 = self.resources.to_ip_resources_v6();
+        let v4 = if v4.as_blocks().map(|b| b.is_empty()).unwrap_or(false) {
+            None
+        } else {
+            Some(v4)
+        };
+        let v6 = if v6.as_blocks().map(|b| b.is_empty()).unwrap_or(false) {
+            None
+        } else {
+            Some(v6)
+        };
+
+        let resources = encode::sequence((
+            if asns.is_empty() {
+                None
+            } else {
+                Some(encode::sequence_as(
+                    Tag::CTX_0,
+                    encode::sequence(asns.encode_ref()),
+                ))
+            },
+            IpResources::encode_families(v4, v6).map(|ips| encode::sequence_as(Tag::CTX_1, ips)),
+        ));
+
+        let mut keys = Captured::builder(Mode::Der);
+        for key in &self.subject_keys {
+            keys.extend(key.encode_ref());
+        }
+
+        Captured::from_values(
+            Mode::Der,
+            encode::sequence((
+                encode::set(keys.freeze()),
+                resources,
+                DigestAlgorithm::default().encode(),
+                OctetString::encode_slice(self.digest.as_ref()),
+            )),
+        )
+        .into_bytes()
+    }
+
+    fn decode(bytes: Bytes) -> Result<Self, decode::Error> {
+        Mode::Der.decode(bytes, |cons| {
+            cons.take_sequence(|cons| {
+                cons.take_opt_constructed_if(Tag::CTX_0, |cons| cons.skip_u8_if(0))?;
+
+                let subject_keys = cons.take_set(|cons| {
+                    let mut keys = vec![];
+                    while let Some(key) =
+                        cons.take_opt_value_if(Tag::OCTET_STRING, KeyIdentifier::from_content)?
+                    {
+                        keys.push(key);
+                    }
+                    Ok(keys)
+                })?;
+                if subject_keys.is_empty() {
+                    return Err(decode::Malformed);
+                }
+
+                let resources = cons.take_sequence(|cons| {
+                    let asns = cons
+                        .take_opt_constructed_if(Tag::CTX_0, Self::take_as_list)?
+                        .unwrap_or_else(AsBlocks::empty);
+                    let (v4, v6) = cons
+                        .take_opt_constructed_if(Tag::CTX_1, IpResources::take_families_from)?
+                        .unwrap_or((None, None));
+                    let v4 = match v4 {
+                        Some(v4) => v4.to_blocks().map_err(|_| decode::Malformed)?,
+                        None => IpBlocks::empty(),
+                    };
+                    let v6 = match v6 {
+                        Some(v6) => v6.to_blocks().map_err(|_| decode::Malformed)?,
+                        None => IpBlocks::empty(),
+                    };
+                    Ok(ResourceSet::new(asns, v4, v6))
+                })?;
+
+                DigestAlgorithm::take_from(cons)?;
+                let digest = OctetString::take_from(cons)?.to_bytes();
+
+                Ok(RtaContent {
+                    subject_keys,
+                    resources,
+                    digest,
+                })
+            })
+        })
+    }
+
+    fn take_as_list<S: decode::Source>(
+        cons: &mut decode::Constructed<S>,
+    ) -> Result<AsBlocks, S::Err> {
+        cons.take_sequence(|cons| {
+            let mut builder = AsBlocksBuilder::new();
+            while let Some(block) = cons.take_opt_value(|tag, content| {
+                if tag == Tag::INTEGER {
+                    Ok(AsBlock::from(AsId::from(content.to_u32()?)))
+                } else if tag == Tag::SEQUENCE {
+                    let cons = content.as_constructed()?;
+                    let min = AsId::from(cons.take_u32()?);
+                    let max = AsId::from(cons.take_u32()?);
+                    Ok(AsBlock::from((min, max)))
+                } else {
+                    Err(decode::Malformed.into())
+                }
+            })? {
+                builder.push(block);
+            }
+            Ok(builder.finalize())
+        })
+    }
+}
+
+//------------ RtaSignerInfo -----------------------------------------------
+
+#[derive(Clone, Debug)]
+struct RtaSignerInfo {
+    sid: KeyIdentifier,
+    signed_attrs: Captured,
+    signature: Signature,
+}
+
+impl RtaSignerInfo {
+    /// Creates the signed attributes for the content, and signs these with
+    /// the key.
+    fn sign<S: RpkiSigner<KeyId = KeyIdentifier>>(
+        key: &KeyIdentifier,
+        content: &[u8],
+        signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        let digest = DigestAlgorithm::default().digest(content);
+
+        // In DER the attributes in the SET OF are ordered by their encoding.
+        let mut attrs = vec![
+            Captured::from_values(
+                Mode::Der,
+                encode::sequence((oid::CONTENT_TYPE.encode(), encode::set(ID_CT_RTA.encode()))),
+            ),
+            Captured::from_values(
+                Mode::Der,
+                encode::sequence((
+                    oid::SIGNING_TIME.encode(),
+                    encode::set(Time::now().encode_varied()),
+                )),
+            ),
+            Captured::from_values(
+                Mode::Der,
+                encode::sequence((
+                    oid::MESSAGE_DIGEST.encode(),
+                    encode::set(OctetString::encode_slice(digest.as_ref())),
+                )),
+            ),
+        ];
+        attrs.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        let mut builder = Captured::builder(Mode::Der);
+        for attr in attrs {
+            builder.extend(attr);
+        }
+        let signed_attrs = builder.freeze();
+
+        let msg = Self::verify_message(&signed_attrs);
+        let signature = signer.sign(key, SignatureAlgorithm::default(), &msg)?;
+
+        Ok(RtaSignerInfo {
+            sid: *key,
+            signed_attrs,
+            signature,
+        })
+    }
+
+    /// The signature is made over the signed attributes encoded as a SET OF,
+    /// rather than with the implicit tag used in the signer info.
+    fn verify_message(signed_attrs: &Captured) -> Bytes {
+        Captured::from_values(Mode::Der, encode::set(signed_attrs)).into_bytes()
+    }
+
+    /// Verifies that the signed attributes are for the content, and that
+    /// the signature was made with the key.
+    fn verify(&self, content: &[u8], key: &PublicKey) -> Result<(), RtaError> {
+        let (content_type, message_digest) = self
+            .signed_attrs
+            .clone()
+            .decode(|cons| {
+                let mut content_type = None;
+                let mut message_digest = None;
+                while let Some(()) = cons.take_opt_sequence(|cons| {
+                    let attr = Oid::take_from(cons)?;
+                    if attr == oid::CONTENT_TYPE {
+                        content_type = Some(cons.take_set(Oid::take_from)?);
+                    } else if attr == oid::MESSAGE_DIGEST {
+                        message_digest = Some(cons.take_set(OctetString::take_from)?.to_bytes());
+                    } else {
+                        cons.skip_all()?;
+                    }
+                    Ok(())
+                })? {}
+                Ok((content_type, message_digest))
+            })
+            .map_err(|_| RtaError::Signature(self.sid))?;
+
+        if content_type.map(|ct| ct != ID_CT_RTA).unwrap_or(true) {
+            return Err(RtaError::Signature(self.sid));
+        }
+
+        let digest = DigestAlgorithm::default().digest(content);
+        if message_digest.as_ref().map(|d| d.as_ref()) != Some(digest.as_ref()) {
+            return Err(RtaError::Signature(self.sid));
+        }
+
+        key.verify(&Self::verify_message(&self.signed_attrs), &self.signature)
+            .map_err(|_| RtaError::Signature(self.sid))
+    }
+
+    fn from_constructed<S: decode::Source>(
+        cons: &mut decode::Constructed<S>,
+    ) -> Result<Self, S::Err> {
+        cons.skip_u8_if(3)?;
+        let sid = cons.take_value_if(Tag::CTX_0, KeyIdentifier::from_content)?;
+        DigestAlgorithm::take_from(cons)?;
+        let signed_attrs = cons.take_constructed_if(Tag::CTX_0, |cons| cons.capture_all())?;
+        let algorithm = SignatureAlgorithm::cms_take_from(cons)?;
+        let value = OctetString::take_from(cons)?.to_bytes();
+        Ok(RtaSignerInfo {
+            sid,
+            signed_attrs,
+            signature: Signature::new(algorithm, value),
+        })
+    }
+
+    fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            3u8.encode(),
+            self.sid.encode_ref_as(Tag::CTX_0),
+            DigestAlgorithm::default().encode(),
+            encode::sequence_as(Tag::CTX_0, &self.signed_attrs),
+            self.signature.algorithm().cms_encode(),
+            OctetString::encode_slice(self.signature.value().as_ref()),
+        ))
+    }
+}
+
+//------------ Rta ---------------------------------------------------------
+
+/// A (possibly partially) signed RTA.
+///
+/// An RTA is complete when it is signed by all keys listed in its content.
+#[derive(Clone, Debug)]
+pub struct Rta {
+    content_bytes: Bytes,
+    content: RtaContent,
+    certs: Vec<Cert>,
+    signers: Vec<RtaSignerInfo>,
+}
+
+impl Rta {
+    /// Creates a new RTA for the content, without any signatures.
+    pub fn new(content: RtaContent) -> Self {
+        Rta {
+            content_bytes: content.to_bytes(),
+            content,
+            certs: vec![],
+            signers: vec![],
+        }
+    }
+
+    pub fn content(&self) -> &RtaContent {
+        &self.content
+    }
+
+    /// Returns whether the RTA is signed with the key.
+    pub fn is_signed_by(&self, key: &KeyIdentifier) -> bool {
+        self.signers.iter().any(|signer| signer.sid == *key)
+    }
+
+    /// Signs the RTA with the key of the EE certificate. The EE certificate
+    /// and the certificate of the CA that issued it are included in the RTA.
+    pub fn sign<S: RpkiSigner<KeyId = KeyIdentifier>>(
+        &mut self,
+        ee_cert: Cert,
+        ca_cert: &Cert,
+        signer: &S,
+    ) -> Result<(), SigningError<S::Error>> {
+        let key = ee_cert.subject_key_identifier();
+        let signer_info = RtaSignerInfo::sign(&key, self.content_bytes.as_ref(), signer)?;
+        self.signers.push(signer_info);
+        self.add_cert(ee_cert);
+        self.add_cert(ca_cert.clone());
+        Ok(())
+    }
+
+    fn add_cert(&mut self, cert: Cert) {
+        let key = cert.subject_key_identifier();
+        if !self.certs.iter().any(|c| c.subject_key_identifier() == key) {
+            self.certs.push(cert);
+        }
+    }
+
+    fn find_cert(&self, key: &KeyIdentifier) -> Option<&Cert> {
+        self.certs
+            .iter()
+            .find(|cert| cert.subject_key_identifier() == *key)
+    }
+
+    /// Returns the key identifiers of the possible trust anchors for this
+    /// RTA. These are the keys at the top of the chains of included
+    /// certificates, i.e. the keys of issuers whose certificates are not
+    /// included, or of included self-signed certificates.
+    pub fn anchor_keys(&self) -> Vec<KeyIdentifier> {
+        let mut res = vec![];
+        for signer in &self.signers {
+            let mut current = match self.find_cert(&signer.sid) {
+                Some(cert) => cert,
+                None => continue,
+            };
+            for _ in 0..MAX_CHAIN_LENGTH {
+                let top = match current.authority_key_identifier() {
+                    Some(aki) if aki != current.subject_key_identifier() => {
+                        match self.find_cert(&aki) {
+                            Some(cert) => {
+                                current = cert;
+                                continue;
+                            }
+                            None => aki,
+                        }
+                    }
+                    _ => current.subject_key_identifier(),
+                };
+                if !res.contains(&top) {
+                    res.push(top);
+                }
+                break;
+            }
+        }
+        res
+    }
+
+    /// Validates the RTA for the document, under one of the trust anchors.
+    ///
+    /// The RTA must be signed by all keys listed in it, the chain for each
+    /// signing EE certificate must lead up to a trust anchor, and the EE
+    /// certificates together must hold all resources in the RTA.
+    ///
+    /// Note that revocation is not checked, as RTAs do not include CRLs.
+    pub fn validate(
+        &self,
+        document: &[u8],
+        anchors: &[RtaTrustAnchor],
+    ) -> Result<RtaValidationReport, RtaError> {
+        self.validate_at(document, anchors, Time::now())
+    }
+
+    pub fn validate_at(
+        &self,
+        document: &[u8],
+        anchors: &[RtaTrustAnchor],
+        now: Time,
+    ) -> Result<RtaValidationReport, RtaError> {
+        if !self.content.matches(document) {
+            return Err(RtaError::ContentMismatch);
+        }
+
+        let mut signed_by: Vec<KeyIdentifier> = self.signers.iter().map(|s| s.sid).collect();
+        signed_by.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        signed_by.dedup();
+        if &signed_by != self.content.subject_keys() || signed_by.len() != self.signers.len() {
+            return Err(RtaError::SignerMismatch);
+        }
+
+        let mut tals = vec![];
+        let mut held = ResourceSet::default();
+
+        for signer in &self.signers {
+            let ee = self
+                .find_cert(&signer.sid)
+                .ok_or(RtaError::MissingCert(signer.sid))?;
+            signer.verify(&self.content_bytes, ee.subject_public_key_info())?;
+
+            let (anchor, chain) = self.find_chain(ee, anchors)?;
+
+            let mut issuer = anchor
+                .cert()
+                .clone()
+                .validate_ta_at(anchor.info.clone(), false, now)
+                .map_err(|_| RtaError::InvalidCert(anchor.cert().subject_key_identifier()))?;
+
+            for cert in chain.into_iter().rev() {
+                issuer = cert
+                    .clone()
+                    .validate_ca_at(&issuer, false, now)
+                    .map_err(|_| RtaError::InvalidCert(cert.subject_key_identifier()))?;
+            }
+
+            let ee = ee
+                .clone()
+                .validate_ee_at(&issuer, false, now)
+                .map_err(|_| RtaError::InvalidCert(signer.sid))?;
+
+            held = held.union(&Self::cert_resources(&ee));
+
+            let tal = anchor.info.name().to_string();
+            if !tals.contains(&tal) {
+                tals.push(tal);
+            }
+        }
+
+        if !held.contains(self.content.resources()) {
+            return Err(RtaError::Resources);
+        }
+
+        Ok(RtaValidationReport::new(
+            tals,
+            self.content.resources().clone(),
+            signed_by,
+        ))
+    }
+
+    /// Finds the trust anchor and the CA certificates from the anchor down
+    /// to the EE certificate. The CA certificates are returned bottom up.
+    fn find_chain<'a>(
+        &'a self,
+        ee: &Cert,
+        anchors: &'a [RtaTrustAnchor],
+    ) -> Result<(&'a RtaTrustAnchor, Vec<&'a Cert>), RtaError> {
+        let mut chain = vec![];
+        let mut issuer = ee
+            .authority_key_identifier()
+            .ok_or_else(|| RtaError::InvalidCert(ee.subject_key_identifier()))?;
+
+        for _ in 0..MAX_CHAIN_LENGTH {
+            if let Some(anchor) = anchors.iter().find(|a| a.key_identifier() == issuer) {
+                return Ok((anchor, chain));
+            }
+
+            let cert = self
+                .find_cert(&issuer)
+                .ok_or(RtaError::MissingCert(issuer))?;
+
+            match cert.authority_key_identifier() {
+                Some(aki) if aki != issuer => {
+                    chain.push(cert);
+                    issuer = aki;
+                }
+                _ => return Err(RtaError::NoTrustAnchor(issuer)),
+            }
+        }
+
+        Err(RtaError::NoTrustAnchor(issuer))
+    }
+
+    fn cert_resources(cert: &ResourceCert) -> ResourceSet {
+        ResourceSet::new(
+            cert.as_resources().clone(),
+            cert.v4_resources().clone(),
+            cert.v6_resources().clone(),
+        )
+    }
+}
+
+/// # Encoding and Decoding
+///
+/// ```text
+/// ContentInfo ::= SEQUENCE {
+///     contentType ContentType,   -- id-signedData
+///     content [0] EXPLICIT SignedData }
+///
+/// SignedData ::= SEQUENCE {
+///     version CMSVersion,        -- 3
+///     digestAlgorithms DigestAlgorithmIdentifiers,
+///     encapContentInfo EncapsulatedContentInfo,
+///     certificates [0] IMPLICIT CertificateSet,
+///     signerInfos SignerInfos }
+/// ```
+impl Rta {
+    pub fn decode(bytes: Bytes) -> Result<Self, RtaError> {
+        let (content_bytes, certs, signers) = Mode::Der
+            .decode(bytes, |cons| {
+                cons.take_sequence(|cons| {
+                    oid::SIGNED_DATA.skip_if(cons)?;
+                    cons.take_constructed_if(Tag::CTX_0, |cons| {
+                        cons.take_sequence(|cons| {
+                            cons.skip_u8_if(3)?;
+                            DigestAlgorithm::skip_set(cons)?;
+                            let content_bytes = cons.take_sequence(|cons| {
+                                ID_CT_RTA.skip_if(cons)?;
+                                cons.take_constructed_if(Tag::CTX_0, OctetString::take_from)
+                            })?;
+                            let certs = cons
+                                .take_opt_constructed_if(Tag::CTX_0, |cons| {
+                                    let mut certs = vec![];
+                                    while let Some(cert) =
+                                        cons.take_opt_sequence(Cert::from_constructed)?
+                                    {
+                                        certs.push(cert);
+                                    }
+                                    Ok(certs)
+                                })?
+                                .unwrap_or_default();
+                            cons.take_opt_constructed_if(Tag::CTX_1, |cons| cons.skip_all())?;
+                            let signers = cons.take_set(|cons| {
+                                let mut signers = vec![];
+                                while let Some(signer) =
+                                    cons.take_opt_sequence(RtaSignerInfo::from_constructed)?
+                                {
+                                    signers.push(signer);
+                                }
+                                Ok(signers)
+                            })?;
+                            Ok((content_bytes.to_bytes(), certs, signers))
+                        })
+                    })
+                })
+            })
+            .map_err(|_| RtaError::Decode)?;
+
+        let content = RtaContent::decode(content_bytes.clone()).map_err(|_| RtaError::Decode)?;
+
+        Ok(Rta {
+            content_bytes,
+            content,
+            certs,
+            signers,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut certs: Vec<Captured> = self.certs.iter().map(Cert::to_captured).collect();
+        certs.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        let mut certs_builder = Captured::builder(Mode::Der);
+        for cert in certs {
+            certs_builder.extend(cert);
+        }
+
+        let mut signers: Vec<Captured> = self
+            .signers
+            .iter()
+            .map(|signer| Captured::from_values(Mode::Der, signer.encode_ref()))
+            .collect();
+        signers.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        let mut signers_builder = Captured::builder(Mode::Der);
+        for signer in signers {
+            signers_builder.extend(signer);
+        }
+
+        Captured::from_values(
+            Mode::Der,
+            encode::sequence((
+                oid::SIGNED_DATA.encode(),
+                encode::sequence_as(
+                    Tag::CTX_0,
+                    encode::sequence((
+                        3u8.encode(),
+                        DigestAlgorithm::default().encode_set(),
+                        encode::sequence((
+                            ID_CT_RTA.encode(),
+                            encode::sequence_as(
+                                Tag::CTX_0,
+                                OctetString::encode_slice(self.content_bytes.as_ref()),
+                            ),
+                        )),
+                        encode::sequence_as(Tag::CTX_0, certs_builder.freeze()),
+                        encode::set(signers_builder.freeze()),
+                    )),
+                ),
+            )),
+        )
+        .into_bytes()
+    }
+
+    pub fn to_attestation(&self) -> ResourceTaggedAttestation {
+        ResourceTaggedAttestation::new(self.to_bytes())
+    }
+}
+
+//------------ RtaTrustAnchor ----------------------------------------------
+
+/// A trust anchor for validating RTAs.
+#[derive(Clone, Debug)]
+pub struct RtaTrustAnchor {
+    info: Arc<TalInfo>,
+    cert: Cert,
+}
+
+impl RtaTrustAnchor {
+    /// Creates a trust anchor for the TAL, using the certificate that was
+    /// retrieved for it. Returns `None` if the certificate does not have the
+    /// key from the TAL.
+    pub fn new(tal: &Tal, cert: Cert) -> Option<Self> {
+        if cert.subject_public_key_info() != tal.key_info() {
+            None
+        } else {
+            Some(RtaTrustAnchor {
+                info: tal.info().clone(),
+                cert,
+            })
+        }
+    }
+
+    fn cert(&self) -> &Cert {
+        &self.cert
+    }
+
+    fn key_identifier(&self) -> KeyIdentifier {
+        self.cert.subject_key_identifier()
+    }
+}
+
+//------------ RtaError ----------------------------------------------------
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum RtaError {
+    #[display(fmt = "cannot decode RTA")]
+    Decode,
+
+    #[display(fmt = "RTA does not match content")]
+    ContentMismatch,
+
+    #[display(fmt = "RTA is not signed by exactly the keys listed in it")]
+    SignerMismatch,
+
+    #[display(fmt = "invalid signature by key {}", _0)]
+    Signature(KeyIdentifier),
+
+    #[display(fmt = "RTA does not include certificate for key {}", _0)]
+    MissingCert(KeyIdentifier),
+
+    #[display(fmt = "invalid certificate for key {}", _0)]
+    InvalidCert(KeyIdentifier),
+
+    #[display(fmt = "no trust anchor found for key {}", _0)]
+    NoTrustAnchor(KeyIdentifier),
+
+    #[display(fmt = "resources in RTA are not held by its signers")]
+    Resources,
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rpki::cert::{KeyUsage, Overclaim, TbsCert};
+    use rpki::crypto::PublicKeyFormat;
+    use rpki::x509::{Name, Serial};
+
+    use crate::commons::api::{RcvdCert, RepoInfo};
+    use crate::commons::util::softsigner::OpenSslSigner;
+    use crate::daemon::ca::{CertifiedKey, SignSupport};
+    use crate::test;
+
+    fn validity() -> Validity {
+        Validity::new(Time::five_minutes_ago(), Time::next_year())
+    }
+
+    fn set_resources(tbs: &mut TbsCert, resources: &ResourceSet) {
+        tbs.set_as_resources(Some(resources.to_as_resources()));
+        tbs.set_v4_resources(Some(resources.to_ip_resources_v4()));
+        tbs.set_v6_resources(Some(resources.to_ip_resources_v6()));
+    }
+
+    fn make_ta(signer: &mut OpenSslSigner) -> (KeyIdentifier, Cert) {
+        let key = signer.create_key(PublicKeyFormat::default()).unwrap();
+        let pub_key = signer.get_key_info(&key).unwrap();
+        let name = Name::from_pub_key(&pub_key);
+
+        let mut tbs = TbsCert::new(
+            Serial::from(1_u64),
+            name.clone(),
+            validity(),
+            Some(name),
+            pub_key,
+            KeyUsage::Ca,
+            Overclaim::Refuse,
+        );
+        tbs.set_basic_ca(Some(true));
+        tbs.set_ca_repository(Some(test::rsync("rsync://localhost/repo/ta/")));
+        tbs.set_rpki_manifest(Some(test::rsync("rsync://localhost/repo/ta/ta.mft")));
+        set_resources(
+            &mut tbs,
+            &ResourceSet::from_strs("AS65000-AS65003", "10.0.0.0/8", "2001:db8::/32").unwrap(),
+        );
+
+        let cert = tbs.into_cert(signer, &key).unwrap();
+        (key, cert)
+    }
+
+    fn make_ca(
+        name: &str,
+        resources: ResourceSet,
+        ta_key: &KeyIdentifier,
+        ta_cert: &Cert,
+        signer: &mut OpenSslSigner,
+    ) -> CertifiedKey {
+        let key = signer.create_key(PublicKeyFormat::default()).unwrap();
+        let pub_key = signer.get_key_info(&key).unwrap();
+
+        let mut tbs = TbsCert::new(
+            Serial::from(2_u64),
+            ta_cert.subject().clone(),
+            validity(),
+            Some(Name::from_pub_key(&pub_key)),
+            pub_key,
+            KeyUsage::Ca,
+            Overclaim::Refuse,
+        );
+        tbs.set_basic_ca(Some(true));
+        tbs.set_authority_key_identifier(Some(ta_cert.subject_key_identifier()));
+        tbs.set_crl_uri(Some(test::rsync("rsync://localhost/repo/ta/ta.crl")));
+        tbs.set_ca_issuer(Some(test::rsync("rsync://localhost/repo/ta/ta.cer")));
+        tbs.set_ca_repository(Some(test::rsync(&format!(
+            "rsync://localhost/repo/{}/",
+            name
+        ))));
+        tbs.set_rpki_manifest(Some(test::rsync(&format!(
+            "rsync://localhost/repo/{}/{}.mft",
+            name, name
+        ))));
+        set_resources(&mut tbs, &resources);
+
+        let cert = tbs.into_cert(signer, ta_key).unwrap();
+        let uri = test::rsync(&format!("rsync://localhost/repo/ta/{}.cer", name));
+        let repo_info = RepoInfo::new(
+            test::rsync("rsync://localhost/repo/"),
+            test::https("https://localhost/rrdp/notification.xml"),
+        );
+
+        CertifiedKey::create(
+            RcvdCert::new(cert, uri, resources),
+            &repo_info,
+            name,
+            signer,
+        )
+        .unwrap()
+    }
+
+    fn sign(rta: &mut Rta, key: &KeyIdentifier, ca: &CertifiedKey, signer: &OpenSslSigner) {
+        let pub_key = signer.get_key_info(key).unwrap();
+        let resources = ca.incoming_cert().resources();
+        let ee = SignSupport::make_rta_ee_cert(resources, ca, validity(), pub_key, signer).unwrap();
+        rta.sign(ee, ca.incoming_cert().cert(), signer).unwrap();
+    }
+
+    #[test]
+    fn encode_decode_content() {
+        test::test_under_tmp(|d| {
+            let mut signer = OpenSslSigner::build(&d).unwrap();
+            let key = signer.create_key(PublicKeyFormat::default()).unwrap();
+
+            let resources =
+                ResourceSet::from_strs("AS65000, AS65002-AS65003", "10.0.0.0/16", "").unwrap();
+            let content = RtaContent::new(vec![key], resources.clone(), b"document");
+
+            let decoded = RtaContent::decode(content.to_bytes()).unwrap();
+            assert_eq!(decoded.subject_keys(), &vec![key]);
+            assert_eq!(decoded.resources(), &resources);
+            assert!(decoded.matches(b"document"));
+            assert!(!decoded.matches(b"other document"));
+        })
+    }
+
+    #[test]
+    fn sign_and_validate_multi_signer_rta() {
+        test::test_under_tmp(|d| {
+            let mut signer = OpenSslSigner::build(&d).unwrap();
+
+            let (ta_key, ta_cert) = make_ta(&mut signer);
+            let ca1_resources =
+                ResourceSet::from_strs("AS65000-AS65001", "10.0.0.0/16", "").unwrap();
+            let ca2_resources = ResourceSet::from_strs("AS65002", "10.1.0.0/16", "").unwrap();
+            let ca1 = make_ca("ca1", ca1_resources.clone(), &ta_key, &ta_cert, &mut signer);
+            let ca2 = make_ca("ca2", ca2_resources.clone(), &ta_key, &ta_cert, &mut signer);
+
+            let key1 = signer.create_key(PublicKeyFormat::default()).unwrap();
+            let key2 = signer.create_key(PublicKeyFormat::default()).unwrap();
+
+            let resources = ca1_resources.union(&ca2_resources);
+            let content = RtaContent::new(vec![key1, key2], resources, b"document");
+
+            let anchor = RtaTrustAnchor {
+                info: TalInfo::from_name("test".to_string()).into_arc(),
+                cert: ta_cert.clone(),
+            };
+            let anchors = vec![anchor];
+
+            // Sign with the first key, and check that the RTA is not yet valid
+            let mut rta = Rta::new(content);
+            sign(&mut rta, &key1, &ca1, &signer);
+            let mut rta = Rta::decode(rta.to_bytes()).unwrap();
+            assert!(rta.is_signed_by(&key1));
+            assert!(!rta.is_signed_by(&key2));
+            assert_eq!(
+                rta.validate(b"document", &anchors).unwrap_err(),
+                RtaError::SignerMismatch
+            );
+
+            // Co-sign with the second key
+            sign(&mut rta, &key2, &ca2, &signer);
+            let rta = Rta::decode(rta.to_bytes()).unwrap();
+            assert_eq!(rta.anchor_keys(), vec![ta_cert.subject_key_identifier()]);
+
+            let report = rta.validate(b"document", &anchors).unwrap();
+            assert_eq!(report.tals(), &vec!["test".to_string()]);
+            assert_eq!(report.signers().len(), 2);
+
+            assert_eq!(
+                rta.validate(b"other document", &anchors).unwrap_err(),
+                RtaError::ContentMismatch
+            );
+            assert!(rta.validate(b"document", &[]).is_err());
+        })
+    }
+
+    #[test]
+    fn reject_rta_for_resources_not_held() {
+        test::test_under_tmp(|d| {
+            let mut signer = OpenSslSigner::build(&d).unwrap();
+
+            let (ta_key, ta_cert) = make_ta(&mut signer);
+            let ca_resources = ResourceSet::from_strs("AS65000", "10.0.0.0/16", "").unwrap();
+            let ca = make_ca("ca", ca_resources, &ta_key, &ta_cert, &mut signer);
+
+            let key = signer.create_key(PublicKeyFormat::default()).unwrap();
+            let resources = ResourceSet::from_strs("AS65000", "10.0.0.0/8", "").unwrap();
+            let content = RtaContent::new(vec![key], resources, b"document");
+
+            let mut rta = Rta::new(content);
+            sign(&mut rta, &key, &ca, &signer);
+
+            let anchor = RtaTrustAnchor {
+                info: TalInfo::from_name("test".to_string()).into_arc(),
+                cert: ta_cert,
+            };
+            assert_eq!(
+                rta.validate(b"document", &[anchor]).unwrap_err(),
+                RtaError::Resources
+            );
+        })
+    }
+}
@@ -8,17 +8,23 @@ use chrono::Duration;
 
 use rpki::crypto::KeyIdentifier;
 use rpki::uri;
+use rpki::x509::Time;
 
 use crate::commons::api::{
-    self, AddChildRequest, Base64, CaCommandDetails, CaCommandResult, CertAuthList,
-    CertAuthSummary, ChildAuthRequest, ChildCaInfo, ChildHandle, CommandHistory,
-    CommandHistoryCriteria, Entitlements, Handle, IssuanceRequest, IssuanceResponse, IssuedCert,
-    ListReply, ParentCaContact, ParentCaReq, ParentHandle, PublishDelta, RcvdCert, RepoInfo,
-    RepositoryContact, ResourceClassName, ResourceSet, RevocationRequest, RevocationResponse,
-    StoredEffect, UpdateChildRequest,
+    self, AddChildRequest, AsNumber, AspaDefinitionUpdates, AspaProvidersUpdate, Base64,
+    BgpSecDefinitionUpdates, CaCommandDetails, CaCommandResult, CertAuthList, CertAuthSummary,
+    ChildAuthRequest, ChildCaInfo, ChildHandle, CommandHistory, CommandHistoryCriteria,
+    Entitlements, Handle, HistoryPoint, IssuanceRequest, IssuanceResponse, IssuedCert, ListReply,
+    ParentCaContact, ParentCaReq, ParentHandle, PublishDelta, RcvdCert, RepoInfo,
+    RepoMigrationPhase, RepositoryContact, ResourceClassName, ResourceSet,
+    ResourceTaggedAttestation, RevocationRequest, RevocationResponse, RtaContentRequest, RtaName,
+    RtaPrepareRequest, StoreIntegrityReport, StoredEffect, UpdateChildRequest,
 };
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::{Aggregate, AggregateStore, CommandKey, DiskAggregateStore};
+use crate::commons::eventsourcing::{
+    Aggregate, AggregateStore, AggregateStoreError, CommandKey, DiskAggregateStore, StorageType,
+    StoreBackup,
+};
 use crate::commons::remote::builder::SignedMessageBuilder;
 use crate::commons::remote::cmslogger::CmsLogger;
 use crate::commons::remote::id::IdCert;
@@ -27,10 +33,13 @@ use crate::commons::remote::{rfc6492, rfc8181, rfc8183};
 use crate::commons::util::httpclient;
 use crate::commons::KrillResult;
 use crate::constants::CASERVER_DIR;
+use crate::daemon::auth::Actor;
 use crate::daemon::ca::{
-    self, ta_handle, CertAuth, Cmd, CmdDet, IniDet, RouteAuthorizationUpdates, Signer,
+    self, ta_handle, CertAuth, Cmd, CmdDet, IniDet, RoaAggregation, RouteAuthorizationUpdates,
+    Signer,
 };
-use crate::daemon::mq::EventQueueListener;
+use crate::daemon::mq::{EventQueueListener, QueueEvent};
+use crate::daemon::webhooks::EventNotifier;
 
 //------------ CaServer ------------------------------------------------------
 
@@ -40,26 +49,34 @@ pub struct CaServer<S: Signer> {
     ca_store: Arc<DiskAggregateStore<CertAuth<S>>>,
     rfc8181_log_dir: Option<PathBuf>,
     rfc6492_log_dir: Option<PathBuf>,
+    roa_aggregation: RoaAggregation,
 }
 
 impl<S: Signer> CaServer<S> {
     /// Builds a new CaServer. Will return an error if the TA store cannot be
     /// initialised.
+    #[allow(clippy::too_many_arguments)]
     pub fn build(
         work_dir: &PathBuf,
+        storage_type: StorageType,
         rfc8181_log_dir: Option<&PathBuf>,
         rfc6492_log_dir: Option<&PathBuf>,
         events_queue: Arc<EventQueueListener>,
+        event_notifier: Arc<EventNotifier>,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Self> {
-        let mut ca_store = DiskAggregateStore::<CertAuth<S>>::new(work_dir, CASERVER_DIR)?;
+        let mut ca_store =
+            DiskAggregateStore::<CertAuth<S>>::new(work_dir, CASERVER_DIR, storage_type)?;
         ca_store.add_listener(events_queue);
+        ca_store.add_listener(event_notifier);
 
         Ok(CaServer {
             signer,
             ca_store: Arc::new(ca_store),
             rfc6492_log_dir: rfc6492_log_dir.cloned(),
             rfc8181_log_dir: rfc8181_log_dir.cloned(),
+            roa_aggregation,
         })
     }
 
@@ -87,7 +104,8 @@ impl<S: Signer> CaServer<S> {
 
             // add embedded repo
             let embedded = RepositoryContact::embedded(info);
-            let upd_repo_cmd = CmdDet::update_repo(&handle, embedded, self.signer.clone());
+            let upd_repo_cmd =
+                CmdDet::update_repo(&handle, embedded, self.roa_aggregation, self.signer.clone());
             self.ca_store.command(upd_repo_cmd)?;
 
             // make trust anchor
@@ -102,6 +120,7 @@ impl<S: Signer> CaServer<S> {
                 &handle,
                 ResourceClassName::default(),
                 rcvd_cert,
+                self.roa_aggregation,
                 self.signer.clone(),
             );
             self.ca_store.command(rcv_cert)?;
@@ -133,13 +152,55 @@ impl<S: Signer> CaServer<S> {
 
     /// Republish a CA, this is a no-op when there is nothing to publish.
     pub fn republish(&self, handle: &Handle) -> KrillResult<()> {
-        let cmd = CmdDet::publish(handle, self.signer.clone());
+        let cmd = CmdDet::publish(handle, self.roa_aggregation, self.signer.clone());
         self.send_command(cmd)
     }
 
     /// Update repository where a CA publishes.
-    pub fn update_repo(&self, handle: Handle, new_contact: RepositoryContact) -> KrillResult<()> {
-        let cmd = CmdDet::update_repo(&handle, new_contact, self.signer.clone());
+    pub fn update_repo(
+        &self,
+        handle: Handle,
+        new_contact: RepositoryContact,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::update_repo(
+            &handle,
+            new_contact,
+            self.roa_aggregation,
+            self.signer.clone(),
+        )
+        .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Move the repository migration of a CA on to its next phase, if the
+    /// current phase is done.
+    pub fn repo_migration_advance(&self, handle: &Handle) -> KrillResult<()> {
+        let cmd = CmdDet::repo_migration_advance(handle, self.signer.clone());
+        self.send_command(cmd)
+    }
+
+    /// Returns the events which resume the repository migration of a CA in
+    /// its current phase, when they are queued.
+    pub fn repo_migration_resume(&self, handle: &Handle) -> KrillResult<Vec<QueueEvent>> {
+        let ca = self.get_ca(handle)?;
+        let phase = ca
+            .repo_migration_phase()
+            .ok_or_else(|| Error::CaRepoNoMigration(handle.clone()))?;
+
+        let mut res = vec![];
+        if phase == RepoMigrationPhase::AwaitingCertificates {
+            res.push(QueueEvent::RequestsPending(handle.clone(), ca.version()));
+        }
+        res.push(QueueEvent::RepoMigration(handle.clone(), ca.version()));
+        Ok(res)
+    }
+
+    /// Abort the repository migration of a CA, by migrating back to the old
+    /// repository.
+    pub fn repo_migration_abort(&self, handle: &Handle, actor: &Actor) -> KrillResult<()> {
+        let cmd = CmdDet::repo_migration_abort(handle, self.roa_aggregation, self.signer.clone())
+            .with_actor(actor.name());
         self.send_command(cmd)
     }
 
@@ -170,6 +231,7 @@ impl<S: Signer> CaServer<S> {
         parent: &ParentHandle,
         req: AddChildRequest,
         service_uri: &uri::Https,
+        actor: &Actor,
     ) -> KrillResult<ParentCaContact> {
         info!("CA '{}' process add child request: {}", &parent, &req);
         let (child_handle, child_res, child_auth) = req.unwrap();
@@ -179,7 +241,8 @@ impl<S: Signer> CaServer<S> {
             ChildAuthRequest::Rfc8183(req) => Some(req.id_cert().clone()),
         };
 
-        let add_child = CmdDet::child_add(&parent, child_handle.clone(), id_cert, child_res);
+        let add_child = CmdDet::child_add(&parent, child_handle.clone(), id_cert, child_res)
+            .with_actor(actor.name());
         self.ca_store.command(add_child)?;
 
         let tag = match child_auth {
@@ -248,6 +311,7 @@ impl<S: Signer> CaServer<S> {
         handle: &Handle,
         child: ChildHandle,
         req: UpdateChildRequest,
+        actor: &Actor,
     ) -> KrillResult<()> {
         let (id_opt, resources_opt) = req.unpack();
 
@@ -256,17 +320,26 @@ impl<S: Signer> CaServer<S> {
         {
             Err(Error::CaChildUpdateOneThing(handle.clone(), child))
         } else if let Some(id) = id_opt {
-            self.send_command(CmdDet::child_update_id(handle, child, id))
+            let cmd = CmdDet::child_update_id(handle, child, id).with_actor(actor.name());
+            self.send_command(cmd)
         } else {
             let resources = resources_opt.unwrap();
-            self.send_command(CmdDet::child_update_resources(handle, child, resources))
+            let cmd =
+                CmdDet::child_update_resources(handle, child, resources).with_actor(actor.name());
+            self.send_command(cmd)
         }
     }
 
     /// Update a child under this CA.
-    pub fn ca_child_remove(&self, handle: &Handle, child: ChildHandle) -> KrillResult<()> {
+    pub fn ca_child_remove(
+        &self,
+        handle: &Handle,
+        child: ChildHandle,
+        actor: &Actor,
+    ) -> KrillResult<()> {
         let signer = self.signer.clone();
-        self.send_command(CmdDet::child_remove(handle, child, signer))
+        let cmd = CmdDet::child_remove(handle, child, signer).with_actor(actor.name());
+        self.send_command(cmd)
     }
 }
 
@@ -281,6 +354,53 @@ impl<S: Signer> CaServer<S> {
             .map_err(|_| Error::CaUnknown(handle.clone()))
     }
 
+    /// Rebuilds a CA as it was at the given point in its history, by
+    /// replaying its events up to that point. For a point in time this
+    /// uses the version after the last command processed at or before
+    /// that time, or the CA as it was created if there was no such command.
+    pub fn get_ca_at(&self, handle: &Handle, point: HistoryPoint) -> KrillResult<CertAuth<S>> {
+        let version = match point {
+            HistoryPoint::Version(version) => version,
+            HistoryPoint::Time(time) => match self.ca_store.version_at(handle, time) {
+                Ok(Some(version)) => version,
+                Ok(None) => 1, // the version after the init event
+                Err(AggregateStoreError::UnknownAggregate(_)) => {
+                    return Err(Error::CaUnknown(handle.clone()))
+                }
+                Err(e) => return Err(Error::AggregateStoreError(e)),
+            },
+        };
+
+        self.ca_store
+            .get_at_version(handle, version)
+            .map_err(|e| match e {
+                AggregateStoreError::UnknownAggregate(_) => Error::CaUnknown(handle.clone()),
+                AggregateStoreError::UnknownVersion(_, _) => {
+                    Error::CaHistoryPointUnknown(handle.clone(), point)
+                }
+                e => Error::AggregateStoreError(e),
+            })
+    }
+
+    /// Verifies the stored history of all CAs, and repairs it where asked
+    /// and possible.
+    pub fn verify_integrity(&self, repair: bool) -> KrillResult<StoreIntegrityReport> {
+        let aggregates = self.ca_store.verify_integrity(repair)?;
+        Ok(StoreIntegrityReport::new(CASERVER_DIR, aggregates))
+    }
+
+    /// Moves the history of all CAs before the given time to their archives.
+    pub fn archive_history(&self, before: Time) -> KrillResult<usize> {
+        Ok(self.ca_store.archive_history(before)?)
+    }
+
+    /// Returns a copy of the stored state of all CAs. The function 'with'
+    /// is called while no CA commands are processed, so that the signer
+    /// keys used by the CAs can be copied consistently.
+    pub fn backup<T, F: FnOnce() -> T>(&self, with: F) -> KrillResult<(StoreBackup, T)> {
+        Ok(self.ca_store.backup(CASERVER_DIR, with)?)
+    }
+
     /// Gets the history for a CA.
     pub fn get_ca_history(
         &self,
@@ -473,16 +593,22 @@ impl<S: Signer> CaServer<S> {
         }
     }
 
-    pub fn ca_update_id(&self, handle: Handle) -> KrillResult<()> {
-        let cmd = CmdDet::update_id(&handle, self.signer.clone());
+    pub fn ca_update_id(&self, handle: Handle, actor: &Actor) -> KrillResult<()> {
+        let cmd = CmdDet::update_id(&handle, self.signer.clone()).with_actor(actor.name());
         self.send_command(cmd)
     }
 
     /// Adds a parent to a CA
-    pub fn ca_parent_add(&self, handle: Handle, parent: ParentCaReq) -> KrillResult<()> {
+    pub fn ca_parent_add(
+        &self,
+        handle: Handle,
+        parent: ParentCaReq,
+        actor: &Actor,
+    ) -> KrillResult<()> {
         let (parent_handle, parent_contact) = parent.unpack();
 
-        let add = CmdDet::add_parent(&handle, parent_handle, parent_contact);
+        let add =
+            CmdDet::add_parent(&handle, parent_handle, parent_contact).with_actor(actor.name());
         self.send_command(add)
     }
 
@@ -492,20 +618,47 @@ impl<S: Signer> CaServer<S> {
         handle: Handle,
         parent: ParentHandle,
         contact: ParentCaContact,
+        actor: &Actor,
     ) -> KrillResult<()> {
-        let upd = CmdDet::update_parent(&handle, parent, contact);
+        let upd = CmdDet::update_parent(&handle, parent, contact).with_actor(actor.name());
         self.send_command(upd)
     }
 
     /// Removes a parent from a CA
-    pub fn ca_parent_remove(&self, handle: Handle, parent: ParentHandle) -> KrillResult<()> {
-        let upd = CmdDet::remove_parent(&handle, parent);
+    pub fn ca_parent_remove(
+        &self,
+        handle: Handle,
+        parent: ParentHandle,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let upd = CmdDet::remove_parent(&handle, parent).with_actor(actor.name());
         self.send_command(upd)
     }
 
+    /// Sets the signer which a CA prefers for new keys, and initiates a key roll
+    /// so that the CA's current keys are replaced by keys in that signer. The
+    /// roll is completed as usual, i.e. new keys are activated after staging.
+    pub fn ca_update_signer(
+        &self,
+        handle: Handle,
+        signer: Option<String>,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let update =
+            CmdDet::update_signer(&handle, signer, self.signer.clone()).with_actor(actor.name());
+        self.send_command(update)?;
+        self.ca_keyroll_init(handle, Duration::seconds(0), actor)
+    }
+
     /// Perform a key roll for all active keys in a CA older than the specified duration.
-    pub fn ca_keyroll_init(&self, handle: Handle, max_age: Duration) -> KrillResult<()> {
-        let init_key_roll = CmdDet::key_roll_init(&handle, max_age, self.signer.clone());
+    pub fn ca_keyroll_init(
+        &self,
+        handle: Handle,
+        max_age: Duration,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let init_key_roll =
+            CmdDet::key_roll_init(&handle, max_age, self.signer.clone()).with_actor(actor.name());
         self.send_command(init_key_roll)
     }
 
@@ -513,8 +666,15 @@ impl<S: Signer> CaServer<S> {
     /// have an age equal to or greater than the staging period are promoted. The RFC mandates
     /// a staging period of 24 hours, but we may use a shorter period for testing and/or emergency
     /// manual key rolls.
-    pub fn ca_keyroll_activate(&self, handle: Handle, staging: Duration) -> KrillResult<()> {
-        let activate_cmd = CmdDet::key_roll_activate(&handle, staging, self.signer.clone());
+    pub fn ca_keyroll_activate(
+        &self,
+        handle: Handle,
+        staging: Duration,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let activate_cmd =
+            CmdDet::key_roll_activate(&handle, staging, self.roa_aggregation, self.signer.clone())
+                .with_actor(actor.name());
         self.send_command(activate_cmd)
     }
 
@@ -751,6 +911,7 @@ impl<S: Signer> CaServer<S> {
                     handle,
                     class_name.clone(),
                     received,
+                    self.roa_aggregation,
                     self.signer.clone(),
                 );
 
@@ -1072,8 +1233,139 @@ impl<S: Signer> CaServer<S> {
         &self,
         handle: Handle,
         updates: RouteAuthorizationUpdates,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::route_authorizations_update(
+            &handle,
+            updates,
+            self.roa_aggregation,
+            self.signer.clone(),
+        )
+        .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Removes all route authorizations which are no longer valid, for all
+    /// CAs. This uses a normal update command, so that the removal shows up
+    /// in the history of each CA.
+    pub fn remove_expired_routes(&self) -> KrillResult<()> {
+        let now = Time::now();
+        for ca in self.ca_list().cas() {
+            let handle = ca.handle();
+            let expired = match self.get_ca(handle) {
+                Ok(ca) => ca.expired_authorizations(now),
+                Err(e) => {
+                    error!(
+                        "Could not check for expired ROA definitions for CA '{}', error: {}",
+                        handle, e
+                    );
+                    continue;
+                }
+            };
+            if !expired.is_empty() {
+                info!(
+                    "Removing {} expired ROA definition(s) for CA '{}'",
+                    expired.len(),
+                    handle
+                );
+                let updates = RouteAuthorizationUpdates::removals(expired);
+                if let Err(e) = self.ca_routes_update(handle.clone(), updates, &Actor::system()) {
+                    error!(
+                        "Could not remove expired ROA definitions for CA '{}', error: {}",
+                        handle, e
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// # Support ASPA functions
+///
+impl<S: Signer> CaServer<S> {
+    /// Add, replace and/or remove ASPA definitions for a CA
+    pub fn ca_aspas_definitions_update(
+        &self,
+        handle: Handle,
+        updates: AspaDefinitionUpdates,
+        actor: &Actor,
     ) -> KrillResult<()> {
-        let cmd = CmdDet::route_authorizations_update(&handle, updates, self.signer.clone());
+        let cmd = CmdDet::aspas_definitions_update(&handle, updates, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Update the providers of an existing ASPA definition for a CA
+    pub fn ca_aspas_update_aspa(
+        &self,
+        handle: Handle,
+        customer: AsNumber,
+        update: AspaProvidersUpdate,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::aspas_update_aspa(&handle, customer, update, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+}
+
+/// # Support BGPsec functions
+///
+impl<S: Signer> CaServer<S> {
+    /// Add and/or remove BGPsec router keys for a CA
+    pub fn ca_bgpsec_definitions_update(
+        &self,
+        handle: Handle,
+        updates: BgpSecDefinitionUpdates,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::bgpsec_update_definitions(&handle, updates, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+}
+
+/// # Support Resource Tagged Attestations
+///
+impl<S: Signer> CaServer<S> {
+    /// Sign an RTA using the keys of a CA, or prepare it for co-signing
+    /// by others if the request includes their keys.
+    pub fn ca_rta_sign(
+        &self,
+        handle: Handle,
+        name: RtaName,
+        request: RtaContentRequest,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd =
+            CmdDet::rta_sign(&handle, name, request, self.signer.clone()).with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Prepare keys in a CA for co-signing a multi-signer RTA.
+    pub fn ca_rta_multi_prep(
+        &self,
+        handle: Handle,
+        name: RtaName,
+        request: RtaPrepareRequest,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::rta_multi_prep(&handle, name, request, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Co-sign a multi-signer RTA with the keys prepared for it.
+    pub fn ca_rta_multi_cosign(
+        &self,
+        handle: Handle,
+        name: RtaName,
+        rta: ResourceTaggedAttestation,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::rta_multi_cosign(&handle, name, rta, self.signer.clone())
+            .with_actor(actor.name());
         self.send_command(cmd)
     }
 }
@@ -1087,7 +1379,10 @@ mod tests {
 
     use std::sync::{Arc, RwLock};
 
+    use std::str::FromStr;
+
     use crate::commons::api::RepoInfo;
+    use crate::commons::util::krillsigner::{KrillSigner, NamedSignerConfig, SignerConfig};
     use crate::commons::util::softsigner::OpenSslSigner;
     use crate::test;
 
@@ -1098,9 +1393,19 @@ mod tests {
             let signer = Arc::new(RwLock::new(signer));
 
             let event_queue = Arc::new(EventQueueListener::in_mem());
-
-            let server =
-                CaServer::<OpenSslSigner>::build(&d, None, None, event_queue, signer).unwrap();
+            let event_notifier = Arc::new(EventNotifier::new(&d, vec![]));
+
+            let server = CaServer::<OpenSslSigner>::build(
+                &d,
+                StorageType::Disk,
+                None,
+                None,
+                event_queue,
+                event_notifier,
+                RoaAggregation::default(),
+                signer,
+            )
+            .unwrap();
 
             let repo_info = {
                 let base_uri = test::rsync("rsync://localhost/repo/ta/");
@@ -1118,4 +1423,341 @@ mod tests {
             assert!(server.get_trust_anchor().is_ok());
         })
     }
+
+    fn repo_contact(name: &str) -> RepositoryContact {
+        let base_uri = test::rsync(&format!("rsync://localhost/{}/", name));
+        let rrdp_uri = test::https(&format!("https://localhost/{}/notification.xml", name));
+        RepositoryContact::embedded(RepoInfo::new(base_uri, rrdp_uri))
+    }
+
+    #[test]
+    fn repo_migration_awaiting_certificates() {
+        test::test_under_tmp(|d| {
+            let signer = OpenSslSigner::build(&d).unwrap();
+            let signer = Arc::new(RwLock::new(signer));
+
+            let event_queue = Arc::new(EventQueueListener::in_mem());
+            let event_notifier = Arc::new(EventNotifier::new(&d, vec![]));
+
+            let server = CaServer::<OpenSslSigner>::build(
+                &d,
+                StorageType::Disk,
+                None,
+                None,
+                event_queue,
+                event_notifier,
+                RoaAggregation::default(),
+                signer,
+            )
+            .unwrap();
+
+            let repo_info = repo_contact("repo/ta").repo_info().clone();
+            let ta_uri = test::https("https://localhost/ta/ta.cer");
+            let ta_aia = test::rsync("rsync://localhost/repo/ta.cer");
+            server.init_ta(repo_info, ta_aia, vec![ta_uri]).unwrap();
+
+            let handle = ta_handle();
+            let actor = Actor::admin();
+            let old = server
+                .get_ca(&handle)
+                .unwrap()
+                .get_repository_contact()
+                .unwrap()
+                .clone();
+            let new = repo_contact("new");
+
+            // there is nothing to abort or resume without a migration
+            match server.repo_migration_abort(&handle, &actor) {
+                Err(Error::CaRepoNoMigration(_)) => {}
+                _ => panic!("Expected error"),
+            }
+            match server.repo_migration_resume(&handle) {
+                Err(Error::CaRepoNoMigration(_)) => {}
+                _ => panic!("Expected error"),
+            }
+
+            server
+                .update_repo(handle.clone(), new.clone(), &actor)
+                .unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::PublishingNew),
+                ca.repo_migration_phase()
+            );
+            assert_eq!(Some(&old), ca.old_repository_contact());
+
+            match server.update_repo(handle.clone(), repo_contact("other"), &actor) {
+                Err(Error::CaRepoMigrationInProgress(_)) => {}
+                _ => panic!("Expected error"),
+            }
+
+            // the key of the TA needs a certificate for the new repository
+            server.repo_migration_advance(&handle).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::AwaitingCertificates),
+                ca.repo_migration_phase()
+            );
+
+            // and the phase stays the same until that certificate is received
+            server.repo_migration_advance(&handle).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::AwaitingCertificates),
+                ca.repo_migration_phase()
+            );
+
+            assert_eq!(
+                vec![
+                    QueueEvent::RequestsPending(handle.clone(), ca.version()),
+                    QueueEvent::RepoMigration(handle.clone(), ca.version())
+                ],
+                server.repo_migration_resume(&handle).unwrap()
+            );
+
+            // aborting migrates back to the old repository
+            server.repo_migration_abort(&handle, &actor).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(&old, ca.get_repository_contact().unwrap());
+            assert_eq!(Some(&new), ca.old_repository_contact());
+            assert_eq!(
+                Some(RepoMigrationPhase::PublishingNew),
+                ca.repo_migration_phase()
+            );
+
+            assert_eq!(
+                vec![QueueEvent::RepoMigration(handle.clone(), ca.version())],
+                server.repo_migration_resume(&handle).unwrap()
+            );
+        })
+    }
+
+    #[test]
+    fn repo_migration_without_requests() {
+        test::test_under_tmp(|d| {
+            let signer = OpenSslSigner::build(&d).unwrap();
+            let signer = Arc::new(RwLock::new(signer));
+
+            let event_queue = Arc::new(EventQueueListener::in_mem());
+            let event_notifier = Arc::new(EventNotifier::new(&d, vec![]));
+
+            let server = CaServer::<OpenSslSigner>::build(
+                &d,
+                StorageType::Disk,
+                None,
+                None,
+                event_queue,
+                event_notifier,
+                RoaAggregation::default(),
+                signer,
+            )
+            .unwrap();
+
+            let handle = Handle::from_str("ca").unwrap();
+            server.init_ca(&handle).unwrap();
+
+            let actor = Actor::admin();
+            let first = repo_contact("first");
+            let second = repo_contact("second");
+
+            // setting the first repository is not a migration
+            server
+                .update_repo(handle.clone(), first.clone(), &actor)
+                .unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(None, ca.repo_migration_phase());
+
+            // abort while publishing at the new repository
+            server
+                .update_repo(handle.clone(), second.clone(), &actor)
+                .unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::PublishingNew),
+                ca.repo_migration_phase()
+            );
+
+            server.repo_migration_abort(&handle, &actor).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(&first, ca.get_repository_contact().unwrap());
+            assert_eq!(Some(&second), ca.old_repository_contact());
+            assert_eq!(
+                Some(RepoMigrationPhase::PublishingNew),
+                ca.repo_migration_phase()
+            );
+
+            // without resource classes there are no requests to wait for
+            server.repo_migration_advance(&handle).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::WithdrawingOld),
+                ca.repo_migration_phase()
+            );
+
+            // advancing is a no-op once the old repository is withdrawn from
+            server.repo_migration_advance(&handle).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(
+                Some(RepoMigrationPhase::WithdrawingOld),
+                ca.repo_migration_phase()
+            );
+
+            // abort while withdrawing from the old repository
+            server.repo_migration_abort(&handle, &actor).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(&second, ca.get_repository_contact().unwrap());
+            assert_eq!(Some(&first), ca.old_repository_contact());
+            assert_eq!(
+                Some(RepoMigrationPhase::PublishingNew),
+                ca.repo_migration_phase()
+            );
+
+            server.repo_migration_advance(&handle).unwrap();
+            server.remove_old_repo(&handle).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(&second, ca.get_repository_contact().unwrap());
+            assert_eq!(None, ca.repo_migration_phase());
+
+            match server.repo_migration_resume(&handle) {
+                Err(Error::CaRepoNoMigration(_)) => {}
+                _ => panic!("Expected error"),
+            }
+        })
+    }
+
+    #[test]
+    fn update_signer() {
+        test::test_under_tmp(|d| {
+            let mut signer = KrillSigner::build(&SignerConfig::OpenSsl, &d).unwrap();
+            let other = NamedSignerConfig::new("other", SignerConfig::OpenSsl);
+            signer.add_signer(&other, &d).unwrap();
+            let signer = Arc::new(RwLock::new(signer));
+
+            let event_queue = Arc::new(EventQueueListener::in_mem());
+            let event_notifier = Arc::new(EventNotifier::new(&d, vec![]));
+
+            let server = CaServer::<KrillSigner>::build(
+                &d,
+                StorageType::Disk,
+                None,
+                None,
+                event_queue,
+                event_notifier,
+                RoaAggregation::default(),
+                signer,
+            )
+            .unwrap();
+
+            let handle = Handle::from_str("ca").unwrap();
+            server.init_ca(&handle).unwrap();
+
+            let actor = Actor::admin();
+
+            match server.ca_update_signer(handle.clone(), Some("unknown".to_string()), &actor) {
+                Err(Error::CaSignerUnknown(_, _)) => {}
+                _ => panic!("Expected unknown signer error"),
+            }
+
+            server
+                .ca_update_signer(handle.clone(), Some("other".to_string()), &actor)
+                .unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            assert_eq!(Some(&"other".to_string()), ca.preferred_signer());
+
+            // A new ID key is created in the preferred signer
+            server.ca_update_id(handle.clone(), &actor).unwrap();
+            let ca = server.get_ca(&handle).unwrap();
+            let mut key_path = d.clone();
+            key_path.push("signers/other/keys");
+            key_path.push(ca.id_key().to_string());
+            assert!(key_path.exists());
+
+            // The actor is recorded in the history
+            let history = server
+                .get_ca_history(&handle, CommandHistoryCriteria::default())
+                .unwrap();
+            assert!(!history.commands().is_empty());
+            for command in history.commands() {
+                assert_eq!(command.actor, "admin");
+            }
+        })
+    }
+
+    #[test]
+    fn get_ca_at_point() {
+        test::test_under_tmp(|d| {
+            let mut signer = KrillSigner::build(&SignerConfig::OpenSsl, &d).unwrap();
+            let other = NamedSignerConfig::new("other", SignerConfig::OpenSsl);
+            signer.add_signer(&other, &d).unwrap();
+            let signer = Arc::new(RwLock::new(signer));
+
+            let event_queue = Arc::new(EventQueueListener::in_mem());
+            let event_notifier = Arc::new(EventNotifier::new(&d, vec![]));
+
+            let server = CaServer::<KrillSigner>::build(
+                &d,
+                StorageType::Disk,
+                None,
+                None,
+                event_queue,
+                event_notifier,
+                RoaAggregation::default(),
+                signer,
+            )
+            .unwrap();
+
+            let handle = Handle::from_str("ca").unwrap();
+            let actor = Actor::admin();
+            let before_commands = Time::now() - Duration::seconds(5);
+            server.init_ca(&handle).unwrap();
+
+            server
+                .ca_update_signer(handle.clone(), Some("other".to_string()), &actor)
+                .unwrap();
+            let version_other = server.get_ca(&handle).unwrap().version();
+            let between_commands = Time::now();
+
+            // Commands are recorded by the second
+            std::thread::sleep(std::time::Duration::from_millis(1100));
+            server
+                .ca_update_signer(handle.clone(), None, &actor)
+                .unwrap();
+            let latest = server.get_ca(&handle).unwrap();
+            assert!(latest.version() > version_other);
+            assert_eq!(None, latest.preferred_signer());
+
+            let point = HistoryPoint::Version(version_other);
+            let ca = server.get_ca_at(&handle, point).unwrap();
+            assert_eq!(version_other, ca.version());
+            assert_eq!(Some(&"other".to_string()), ca.preferred_signer());
+
+            let point = HistoryPoint::Time(between_commands);
+            let ca = server.get_ca_at(&handle, point).unwrap();
+            assert_eq!(version_other, ca.version());
+            assert_eq!(Some(&"other".to_string()), ca.preferred_signer());
+
+            // Before the first command the CA is as it was created
+            let point = HistoryPoint::Time(before_commands);
+            let ca = server.get_ca_at(&handle, point).unwrap();
+            assert_eq!(1, ca.version());
+            assert_eq!(None, ca.preferred_signer());
+
+            for version in &[0, latest.version() + 1] {
+                let point = HistoryPoint::Version(*version);
+                match server.get_ca_at(&handle, point) {
+                    Err(Error::CaHistoryPointUnknown(ca, unknown)) => {
+                        assert_eq!(handle, ca);
+                        assert_eq!(point, unknown);
+                    }
+                    _ => panic!("Expected unknown history point"),
+                }
+            }
+
+            let unknown = Handle::from_str("unknown").unwrap();
+            assert!(matches!(
+                server.get_ca_at(&unknown, HistoryPoint::Version(1)),
+                Err(Error::CaUnknown(_))
+            ));
+        })
+    }
 }
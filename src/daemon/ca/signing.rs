@@ -6,26 +6,60 @@ use bytes::Bytes;
 
 use rpki::cert::{Cert, KeyUsage, Overclaim, TbsCert};
 use rpki::crl::Crl;
-use rpki::crypto::{self, DigestAlgorithm, KeyIdentifier, PublicKey};
+use rpki::crypto::{self, DigestAlgorithm, KeyIdentifier, PublicKey, PublicKeyFormat};
 use rpki::csr::Csr;
 use rpki::manifest::FileAndHash;
 use rpki::uri;
 use rpki::x509::{Name, Serial, Time, Validity};
 
-use crate::commons::api::{IssuedCert, ReplacedObject, RequestResourceLimit, ResourceSet};
+use crate::commons::api::{
+    AsNumber, IssuedCert, ObjectName, ReplacedObject, RequestResourceLimit, ResourceSet,
+};
 use crate::commons::error::Error;
+use crate::commons::util::pkcs11signer::Pkcs11Signer;
+use crate::commons::util::softsigner::OpenSslSigner;
 use crate::commons::KrillResult;
-use crate::daemon::ca::{self, CertifiedKey};
+use crate::constants::BGPSEC_CERTIFICATE_VALIDITY_YEARS;
+use crate::daemon::ca::{self, BgpSecCsr, BgpSecRouterCert, CertifiedKey, TbsRouterCert};
 
 //------------ Signer --------------------------------------------------------
 
 pub trait Signer:
     crypto::Signer<KeyId = KeyIdentifier> + Clone + Sized + Sync + Send + 'static
 {
+    /// Creates a new key for the given purpose. A CA may prefer that its
+    /// keys are kept by a specific (named) signer. Signers which only know
+    /// a single place to keep keys can simply ignore both arguments.
+    fn create_key_for(
+        &mut self,
+        purpose: KeyPurpose,
+        preferred: Option<&str>,
+    ) -> Result<KeyIdentifier, Self::Error> {
+        let _ = (purpose, preferred);
+        self.create_key(PublicKeyFormat::default())
+    }
+
+    /// Returns true if this signer can keep keys under the given name.
+    fn has_signer(&self, name: &str) -> bool {
+        let _ = name;
+        false
+    }
 }
-impl<T: crypto::Signer<KeyId = KeyIdentifier> + Clone + Sized + Sync + Send + 'static> Signer
-    for T
-{
+
+impl Signer for OpenSslSigner {}
+
+impl Signer for Pkcs11Signer {}
+
+//------------ KeyPurpose ----------------------------------------------------
+
+/// The purpose for which a new key is created.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyPurpose {
+    /// RFC 8183 identity keys, used for up-down and publication.
+    Id,
+
+    /// Keys used by resource classes to sign certificates and objects.
+    ResourceClass,
 }
 
 //------------ CsrInfo -------------------------------------------------------
@@ -198,6 +232,103 @@ impl SignSupport {
         Ok(IssuedCert::new(cert_uri, limit, resources, cert, replaces))
     }
 
+    /// Create a BGPsec router certificate for the router key in the CSR and
+    /// the given ASN. The ASN must be held by the signing key.
+    pub fn make_bgpsec_router_cert<S: Signer>(
+        csr: &BgpSecCsr,
+        asn: AsNumber,
+        signing_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<BgpSecRouterCert> {
+        let signing_cert = signing_key.incoming_cert();
+
+        if !signing_cert.resources().contains(&asn.into()) {
+            return Err(Error::MissingResources);
+        }
+
+        let crl_uri = match new_repo {
+            None => signing_cert.crl_uri(),
+            Some(base_uri) => base_uri.join(signing_cert.crl_name().as_bytes()),
+        };
+
+        let serial = Serial::random(signer).map_err(ca::Error::signer)?;
+        let issuer = signing_cert.cert().subject().clone();
+        let validity = Self::sign_validity_years(BGPSEC_CERTIFICATE_VALIDITY_YEARS);
+
+        let cert = TbsRouterCert::new(
+            serial,
+            issuer,
+            validity,
+            asn,
+            csr,
+            signing_cert.cert().subject_key_identifier(),
+            crl_uri,
+            signing_cert.uri().clone(),
+        );
+
+        cert.into_cert(signer, &signing_key.key_id())
+            .map_err(ca::Error::signer)
+    }
+
+    /// Create a one-off EE certificate for signing an RTA. The resources must
+    /// be held by the signing key. The validity is limited to the validity of
+    /// the signing certificate.
+    pub fn make_rta_ee_cert<S: Signer>(
+        resources: &ResourceSet,
+        signing_key: &CertifiedKey,
+        validity: Validity,
+        pub_key: PublicKey,
+        signer: &S,
+    ) -> KrillResult<Cert> {
+        let signing_cert = signing_key.incoming_cert();
+
+        if !signing_cert.resources().contains(resources) {
+            return Err(Error::MissingResources);
+        }
+
+        let serial = Serial::random(signer).map_err(ca::Error::signer)?;
+        let issuer = signing_cert.cert().subject().clone();
+        let validity = validity.trim(signing_cert.cert().validity());
+        let subject = Some(Name::from_pub_key(&pub_key));
+        let ki = pub_key.key_identifier();
+
+        let mut cert = TbsCert::new(
+            serial,
+            issuer,
+            validity,
+            subject,
+            pub_key,
+            KeyUsage::Ee,
+            Overclaim::Refuse,
+        );
+
+        cert.set_authority_key_identifier(Some(signing_cert.cert().subject_key_identifier()));
+        cert.set_ca_issuer(Some(signing_cert.uri().clone()));
+        cert.set_crl_uri(Some(signing_cert.crl_uri()));
+        cert.set_signed_object(Some(
+            signing_cert.uri_for_object(ObjectName::new(&ki, "rta")),
+        ));
+
+        let asns = resources.to_as_resources();
+        if !asns.as_blocks().unwrap().is_empty() {
+            cert.set_as_resources(Some(asns));
+        }
+
+        let ipv4 = resources.to_ip_resources_v4();
+        if !ipv4.as_blocks().unwrap().is_empty() {
+            cert.set_v4_resources(Some(ipv4));
+        }
+
+        let ipv6 = resources.to_ip_resources_v6();
+        if !ipv6.as_blocks().unwrap().is_empty() {
+            cert.set_v6_resources(Some(ipv6));
+        }
+
+        cert.into_cert(signer, &signing_key.key_id())
+            .map_err(ca::Error::signer)
+    }
+
     /// Returns a validity period from 5 minutes ago (in case of NTP mess-up), to
     /// X year from now.
     pub fn sign_validity_years(years: i32) -> Validity {
@@ -15,11 +15,20 @@ use syslog::Facility;
 use toml;
 
 use rpki::uri;
+use rpki::x509::Time;
 
 use crate::commons::api::Token;
+use crate::commons::bgp::AnnouncementSourceConfig;
+use crate::commons::eventsourcing::StorageType;
 use crate::commons::util::ext_serde;
+use crate::commons::util::krillsigner::{NamedSignerConfig, SignerConfig};
 use crate::constants::*;
+use crate::daemon::alerts::AlertSinkConfig;
+use crate::daemon::auth::openid_connect::OpenIdConnectConfig;
+use crate::daemon::auth::ApiToken;
+use crate::daemon::ca::RoaAggregation;
 use crate::daemon::http::tls_keys;
+use crate::daemon::webhooks::WebhookConfig;
 
 //------------ ConfigDefaults ------------------------------------------------
 
@@ -105,6 +114,22 @@ impl ConfigDefaults {
     fn bgp_risdumps_v6_uri() -> String {
         "http://www.ris.ripe.net/dumps/riswhoisdump.IPv6.gz".to_string()
     }
+
+    fn bgp_risdumps_refresh_minutes() -> u32 {
+        BGP_RIS_REFRESH_MINUTES as u32
+    }
+
+    fn bgp_risdumps_visibility_threshold() -> u32 {
+        BGP_RIS_VISIBILITY_THRESHOLD
+    }
+
+    fn roa_aggregate_threshold() -> usize {
+        ROA_AGGREGATE_THRESHOLD_DEFAULT
+    }
+
+    fn roa_deaggregate_threshold() -> usize {
+        ROA_DEAGGREGATE_THRESHOLD_DEFAULT
+    }
 }
 
 //------------ Config --------------------------------------------------------
@@ -137,6 +162,12 @@ pub struct Config {
     #[serde(default = "ConfigDefaults::data_dir")]
     pub data_dir: PathBuf,
 
+    #[serde(default)]
+    pub storage_type: StorageType,
+
+    #[serde(default)]
+    pub history_archive: HistoryArchiveConfig,
+
     pub pid_file: Option<PathBuf>,
 
     #[serde(default = "ConfigDefaults::rsync_base")]
@@ -165,6 +196,21 @@ pub struct Config {
     #[serde(default = "ConfigDefaults::auth_token")]
     pub auth_token: Token,
 
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+
+    pub openid_connect: Option<OpenIdConnectConfig>,
+
+    #[serde(default)]
+    pub signer: SignerConfig,
+
+    #[serde(default)]
+    pub signers: Vec<NamedSignerConfig>,
+
+    pub id_key_signer: Option<String>,
+
+    pub ca_key_signer: Option<String>,
+
     #[serde(default = "ConfigDefaults::ca_refresh")]
     pub ca_refresh: u32,
 
@@ -186,6 +232,36 @@ pub struct Config {
     pub bgp_risdumps_v4_uri: String,
     #[serde(default = "ConfigDefaults::bgp_risdumps_v6_uri")]
     pub bgp_risdumps_v6_uri: String,
+    #[serde(default = "ConfigDefaults::bgp_risdumps_refresh_minutes")]
+    pub bgp_risdumps_refresh_minutes: u32,
+    #[serde(default = "ConfigDefaults::bgp_risdumps_visibility_threshold")]
+    pub bgp_risdumps_visibility_threshold: u32,
+
+    // Other BGP announcement sources
+    #[serde(default)]
+    pub bgp_sources: Vec<AnnouncementSourceConfig>,
+
+    // Alerts for announcements made invalid by our ROAs
+    #[serde(default)]
+    pub alert_sinks: Vec<AlertSinkConfig>,
+
+    // Webhooks for CA and repository events
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    // RTA validation
+    #[serde(default)]
+    pub rta_tals: Vec<PathBuf>,
+
+    // ROA aggregation
+    #[serde(default = "ConfigDefaults::roa_aggregate_threshold")]
+    pub roa_aggregate_threshold: usize,
+    #[serde(default = "ConfigDefaults::roa_deaggregate_threshold")]
+    pub roa_deaggregate_threshold: usize,
+
+    // The file this config was read from, included in backups
+    #[serde(skip)]
+    config_file: Option<PathBuf>,
 }
 
 /// # Accessors
@@ -227,6 +303,25 @@ impl Config {
         uri::Https::from_string(format!("{}ta/ta.cer", &self.service_uri)).unwrap()
     }
 
+    pub fn roa_aggregation(&self) -> RoaAggregation {
+        RoaAggregation::new(self.roa_aggregate_threshold, self.roa_deaggregate_threshold)
+    }
+
+    /// All configured sources of BGP announcements, including the RIS dumps
+    /// if they are enabled.
+    pub fn bgp_sources(&self) -> Vec<AnnouncementSourceConfig> {
+        let mut res = vec![];
+        if self.bgp_risdumps_enabled {
+            res.push(AnnouncementSourceConfig::ris(
+                &self.bgp_risdumps_v4_uri,
+                &self.bgp_risdumps_v6_uri,
+                self.bgp_risdumps_refresh_minutes,
+            ));
+        }
+        res.extend(self.bgp_sources.iter().cloned());
+        res
+    }
+
     pub fn use_ta(&self) -> bool {
         self.use_ta
     }
@@ -241,6 +336,10 @@ impl Config {
             Some(file) => file.clone(),
         }
     }
+
+    pub fn config_file(&self) -> Option<&PathBuf> {
+        self.config_file.as_ref()
+    }
 }
 
 /// # Create
@@ -254,6 +353,8 @@ impl Config {
         let repo_enabled = true;
         let https_mode = HttpsMode::Generate;
         let data_dir = data_dir.clone();
+        let storage_type = StorageType::default();
+        let history_archive = HistoryArchiveConfig::default();
         let rsync_base = ConfigDefaults::rsync_base();
         let service_uri = ConfigDefaults::service_uri();
         let rrdp_service_uri = Some("https://localhost:3000/test-rrdp/".to_string());
@@ -263,6 +364,12 @@ impl Config {
         log_file.push("krill.log");
         let syslog_facility = ConfigDefaults::syslog_facility();
         let auth_token = Token::from("secret");
+        let api_tokens = vec![];
+        let openid_connect = None;
+        let signer = SignerConfig::default();
+        let signers = vec![];
+        let id_key_signer = None;
+        let ca_key_signer = None;
         let ca_refresh = 3600;
         let post_limit_api = ConfigDefaults::post_limit_api();
         let post_limit_rfc8181 = ConfigDefaults::post_limit_rfc8181();
@@ -281,6 +388,16 @@ impl Config {
         let bgp_risdumps_enabled = false;
         let bgp_risdumps_v4_uri = ConfigDefaults::bgp_risdumps_v4_uri();
         let bgp_risdumps_v6_uri = ConfigDefaults::bgp_risdumps_v6_uri();
+        let bgp_risdumps_refresh_minutes = ConfigDefaults::bgp_risdumps_refresh_minutes();
+        let bgp_risdumps_visibility_threshold = ConfigDefaults::bgp_risdumps_visibility_threshold();
+        let bgp_sources = vec![];
+        let alert_sinks = vec![];
+        let webhooks = vec![];
+
+        let rta_tals = vec![];
+
+        let roa_aggregate_threshold = ConfigDefaults::roa_aggregate_threshold();
+        let roa_deaggregate_threshold = ConfigDefaults::roa_deaggregate_threshold();
 
         Config {
             ip,
@@ -291,6 +408,8 @@ impl Config {
             repo_enabled,
             https_mode,
             data_dir,
+            storage_type,
+            history_archive,
             rsync_base,
             service_uri,
             rrdp_service_uri,
@@ -299,6 +418,12 @@ impl Config {
             log_file,
             syslog_facility,
             auth_token,
+            api_tokens,
+            openid_connect,
+            signer,
+            signers,
+            id_key_signer,
+            ca_key_signer,
             ca_refresh,
             post_limit_api,
             post_limit_rfc8181,
@@ -308,6 +433,15 @@ impl Config {
             bgp_risdumps_enabled,
             bgp_risdumps_v4_uri,
             bgp_risdumps_v6_uri,
+            bgp_risdumps_refresh_minutes,
+            bgp_risdumps_visibility_threshold,
+            bgp_sources,
+            alert_sinks,
+            webhooks,
+            rta_tals,
+            roa_aggregate_threshold,
+            roa_deaggregate_threshold,
+            config_file: None,
         }
     }
 
@@ -327,8 +461,8 @@ impl Config {
         config
     }
 
-    pub fn get_config_filename() -> String {
-        let matches = App::new(KRILL_SERVER_APP)
+    fn server_app<'a, 'b>() -> App<'a, 'b> {
+        App::new(KRILL_SERVER_APP)
             .version(KRILL_VERSION)
             .arg(
                 Arg::with_name("config")
@@ -338,7 +472,17 @@ impl Config {
                     .help("Override the path to the config file (default: './defaults/krill.conf')")
                     .required(false),
             )
-            .get_matches();
+            .arg(
+                Arg::with_name("restore")
+                    .long("restore")
+                    .value_name("FILE")
+                    .help("Restore a backup into the (empty) data directory, and exit")
+                    .required(false),
+            )
+    }
+
+    pub fn get_config_filename() -> String {
+        let matches = Self::server_app().get_matches();
 
         let config_file = matches
             .value_of("config")
@@ -347,6 +491,20 @@ impl Config {
         config_file.to_string()
     }
 
+    /// Returns the backup file to restore, and its passphrase if set in the
+    /// environment, if the server was started with --restore. The passphrase
+    /// is not taken as an argument, so that it does not show up in the
+    /// process list or shell history.
+    pub fn get_restore_args() -> Option<(PathBuf, Option<String>)> {
+        let matches = Self::server_app().get_matches();
+
+        let file = matches.value_of("restore")?;
+        let passphrase = env::var(KRILL_ENV_BACKUP_PASSPHRASE)
+            .ok()
+            .filter(|p| !p.is_empty());
+        Some((PathBuf::from(file), passphrase))
+    }
+
     /// Creates the config (at startup). Panics in case of issues.
     pub fn create() -> Result<Self, ConfigError> {
         let config_file = Self::get_config_filename();
@@ -364,12 +522,13 @@ impl Config {
                     )))
                 }
             }
-            Ok(config) => {
+            Ok(mut config) => {
                 config.init_logging()?;
                 info!(
                     "{} uses configuration file: {}",
                     KRILL_SERVER_APP, config_file
                 );
+                config.config_file = Some(PathBuf::from(&config_file));
                 Ok(config)
             }
         }?;
@@ -446,6 +605,30 @@ impl Config {
             ));
         }
 
+        if self.roa_deaggregate_threshold >= self.roa_aggregate_threshold {
+            return Err(ConfigError::other(
+                "roa_deaggregate_threshold must be lower than roa_aggregate_threshold",
+            ));
+        }
+
+        for (idx, api_token) in self.api_tokens.iter().enumerate() {
+            if api_token.token() == &self.auth_token {
+                return Err(ConfigError::Other(format!(
+                    "API token '{}' cannot use the same token as 'auth_token'",
+                    api_token.name()
+                )));
+            }
+            for other in self.api_tokens.iter().skip(idx + 1) {
+                if other.name() == api_token.name() || other.token() == api_token.token() {
+                    return Err(ConfigError::Other(format!(
+                        "API tokens '{}' and '{}' must have different names and tokens",
+                        api_token.name(),
+                        other.name()
+                    )));
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -459,121 +642,426 @@ impl Config {
     }
 
     pub fn init_logging(&self) -> Result<(), ConfigError> {
-        match self.log_type {
-            LogType::File => self.file_logger(&self.log_file),
-            LogType::Stderr => self.stderr_logger(),
-            LogType::Syslog => {
-                let facility = Facility::from_str(&self.syslog_facility)
-                    .map_err(|_| ConfigError::other("Invalid syslog_facility"))?;
-                self.syslog_logger(facility)
+        init_logging(
+            &self.log_type,
+            self.log_level,
+            &self.log_file,
+            &self.syslog_facility,
+        )
+    }
+}
+
+//------------ PubdConfig ----------------------------------------------------
+
+struct PubdConfigDefaults;
+
+impl PubdConfigDefaults {
+    fn port() -> u16 {
+        3001
+    }
+    fn service_uri() -> String {
+        "https://localhost:3001/".to_string()
+    }
+    fn log_file() -> PathBuf {
+        PathBuf::from("./krillpubd.log")
+    }
+    fn auth_token() -> Token {
+        match env::var(KRILL_ENV_PUBD_AUTH_TOKEN) {
+            Ok(token) => Token::from(token),
+            Err(_) => {
+                eprintln!("You MUST provide a value for the publication server API key, either by setting \"auth_token\" in the [pubd] section of the config file, or by setting the KRILL_PUBD_AUTH_TOKEN environment variable.");
+                ::std::process::exit(1);
             }
         }
     }
+}
 
-    /// Creates a stderr logger.
-    fn stderr_logger(&self) -> Result<(), ConfigError> {
-        self.fern_logger()
-            .chain(io::stderr())
-            .apply()
-            .map_err(|e| ConfigError::Other(format!("Failed to init stderr logging: {}", e)))
+/// The config file for the standalone publication server only needs to
+/// have a '[pubd]' section. Anything else is ignored, so the same file can
+/// also be used for a Krill server.
+#[derive(Debug, Deserialize)]
+struct PubdConfigFile {
+    pubd: PubdConfig,
+}
+
+/// Configuration for the standalone publication server ('krillpubd').
+///
+/// This will parse the '[pubd]' section of a default config file
+/// ('./defaults/krillpubd.conf') unless another file is explicitly
+/// specified. The data directory uses the same layout as the repository
+/// embedded in Krill, so an existing embedded repository can be served by
+/// krillpubd.
+#[derive(Debug, Deserialize)]
+pub struct PubdConfig {
+    #[serde(default = "ConfigDefaults::ip")]
+    ip: IpAddr,
+
+    #[serde(default = "PubdConfigDefaults::port")]
+    port: u16,
+
+    #[serde(default = "ConfigDefaults::test_mode")]
+    pub test_mode: bool,
+
+    #[serde(default = "ConfigDefaults::data_dir")]
+    pub data_dir: PathBuf,
+
+    #[serde(default)]
+    pub storage_type: StorageType,
+
+    #[serde(default)]
+    pub history_archive: HistoryArchiveConfig,
+
+    pub pid_file: Option<PathBuf>,
+
+    #[serde(default = "ConfigDefaults::rsync_base")]
+    pub rsync_base: uri::Rsync,
+
+    #[serde(default = "PubdConfigDefaults::service_uri")]
+    service_uri: String,
+
+    rrdp_service_uri: Option<String>,
+
+    #[serde(
+        default = "ConfigDefaults::log_level",
+        deserialize_with = "ext_serde::de_level_filter"
+    )]
+    log_level: LevelFilter,
+
+    #[serde(default = "ConfigDefaults::log_type")]
+    log_type: LogType,
+
+    #[serde(default = "PubdConfigDefaults::log_file")]
+    log_file: PathBuf,
+
+    #[serde(default = "ConfigDefaults::syslog_facility")]
+    syslog_facility: String,
+
+    #[serde(default = "PubdConfigDefaults::auth_token")]
+    pub auth_token: Token,
+
+    #[serde(default)]
+    pub signer: SignerConfig,
+
+    #[serde(default = "ConfigDefaults::post_limit_api")]
+    pub post_limit_api: u64,
+
+    #[serde(default = "ConfigDefaults::post_limit_rfc8181")]
+    pub post_limit_rfc8181: u64,
+    pub rfc8181_log_dir: Option<PathBuf>,
+}
+
+/// # Accessors
+impl PubdConfig {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
     }
 
-    /// Creates a file logger using the file provided by `path`.
-    fn file_logger(&self, path: &PathBuf) -> Result<(), ConfigError> {
-        let file = match fern::log_file(path) {
-            Ok(file) => file,
-            Err(err) => {
-                let error_string = format!("Failed to open log file '{}': {}", path.display(), err);
-                error!("{}", error_string.as_str());
-                return Err(ConfigError::Other(error_string));
-            }
-        };
-        self.fern_logger()
-            .chain(file)
-            .apply()
-            .map_err(|e| ConfigError::Other(format!("Failed to init file logging: {}", e)))
+    pub fn service_uri(&self) -> uri::Https {
+        uri::Https::from_str(&self.service_uri).unwrap()
     }
 
-    /// Creates a syslog logger and configures correctly.
-    #[cfg(unix)]
-    fn syslog_logger(&self, facility: syslog::Facility) -> Result<(), ConfigError> {
-        let process = env::current_exe()
-            .ok()
-            .and_then(|path| {
-                path.file_name()
-                    .and_then(std::ffi::OsStr::to_str)
-                    .map(ToString::to_string)
-            })
-            .unwrap_or_else(|| String::from("krill"));
-        let pid = unsafe { libc::getpid() };
-        let formatter = syslog::Formatter3164 {
-            facility,
-            hostname: None,
-            process,
-            pid,
-        };
-        let logger = syslog::unix(formatter.clone())
-            .or_else(|_| syslog::tcp(formatter.clone(), ("127.0.0.1", 601)))
-            .or_else(|_| syslog::udp(formatter, ("127.0.0.1", 0), ("127.0.0.1", 514)));
-        match logger {
-            Ok(logger) => self
-                .fern_logger()
-                .chain(logger)
-                .apply()
-                .map_err(|e| ConfigError::Other(format!("Failed to init syslog: {}", e))),
-            Err(err) => {
-                let msg = format!("Cannot connect to syslog: {}", err);
-                Err(ConfigError::Other(msg))
+    pub fn rrdp_service_uri(&self) -> uri::Https {
+        match &self.rrdp_service_uri {
+            None => uri::Https::from_string(format!("{}rrdp/", &self.service_uri)).unwrap(),
+            Some(uri) => uri::Https::from_str(uri).unwrap(),
+        }
+    }
+
+    pub fn pid_file(&self) -> PathBuf {
+        match &self.pid_file {
+            None => {
+                let mut path = self.data_dir.clone();
+                path.push("krillpubd.pid");
+                path
             }
+            Some(file) => file.clone(),
         }
     }
+}
 
-    /// Creates and returns a fern logger with log level tweaks
-    fn fern_logger(&self) -> fern::Dispatch {
-        let framework_level = match self.log_level {
-            LevelFilter::Off => LevelFilter::Off,
-            LevelFilter::Error => LevelFilter::Error,
-            _ => LevelFilter::Warn, // more becomes too noisy
+/// # Create
+impl PubdConfig {
+    pub fn test(data_dir: &PathBuf) -> Self {
+        let config = PubdConfig {
+            ip: ConfigDefaults::ip(),
+            port: PubdConfigDefaults::port(),
+            test_mode: true,
+            data_dir: data_dir.clone(),
+            storage_type: StorageType::default(),
+            history_archive: HistoryArchiveConfig::default(),
+            pid_file: None,
+            rsync_base: uri::Rsync::from_str("rsync://remotehost/repo/").unwrap(),
+            service_uri: PubdConfigDefaults::service_uri(),
+            rrdp_service_uri: None,
+            log_level: LevelFilter::Debug,
+            log_type: LogType::Stderr,
+            log_file: PubdConfigDefaults::log_file(),
+            syslog_facility: ConfigDefaults::syslog_facility(),
+            auth_token: Token::from("pubd-secret"),
+            signer: SignerConfig::default(),
+            post_limit_api: ConfigDefaults::post_limit_api(),
+            post_limit_rfc8181: ConfigDefaults::post_limit_rfc8181(),
+            rfc8181_log_dir: None,
         };
+        config.verify().unwrap();
+        config
+    }
 
-        let krill_framework_level = match self.log_level {
-            LevelFilter::Off => LevelFilter::Off,
-            LevelFilter::Error => LevelFilter::Error,
-            LevelFilter::Warn => LevelFilter::Warn,
-            _ => LevelFilter::Debug, // more becomes too noisy
-        };
+    /// Creates the config (at startup). Exits in case of issues.
+    pub fn create() -> Result<Self, ConfigError> {
+        let matches = App::new(KRILL_PUBD_APP)
+            .version(KRILL_VERSION)
+            .arg(
+                Arg::with_name("config")
+                    .short("c")
+                    .long("config")
+                    .value_name("FILE")
+                    .help("Override the path to the config file (default: './defaults/krillpubd.conf')")
+                    .required(false),
+            )
+            .get_matches();
 
-        let show_target =
-            self.log_level == LevelFilter::Trace || self.log_level == LevelFilter::Debug;
-        fern::Dispatch::new()
-            .format(move |out, message, record| {
-                if show_target {
-                    out.finish(format_args!(
-                        "{} [{}] [{}] {}",
-                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                        record.level(),
-                        record.target(),
-                        message
-                    ))
-                } else {
-                    out.finish(format_args!(
-                        "{} [{}] {}",
-                        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                        record.level(),
-                        message
-                    ))
-                }
-            })
-            .level(self.log_level)
-            .level_for("rustls", framework_level)
-            .level_for("hyper", framework_level)
-            .level_for("mio", framework_level)
-            .level_for("reqwest", framework_level)
-            .level_for("tokio_reactor", framework_level)
-            .level_for("want", framework_level)
-            .level_for("krill::commons::eventsourcing", krill_framework_level)
-            .level_for("krill::commons::util::file", krill_framework_level)
+        let config_file = matches
+            .value_of("config")
+            .unwrap_or(KRILL_PUBD_DEFAULT_CONFIG_FILE);
+
+        let config = Self::read_config(config_file).map_err(|e| {
+            ConfigError::Other(format!(
+                "Error parsing config file: {}, error: {}",
+                config_file, e
+            ))
+        })?;
+        config.init_logging()?;
+        info!(
+            "{} uses configuration file: {}",
+            KRILL_PUBD_APP, config_file
+        );
+
+        config.verify().map_err(|e| {
+            ConfigError::Other(format!(
+                "Error parsing config file: {}, error: {}",
+                config_file, e
+            ))
+        })?;
+        Ok(config)
+    }
+
+    pub fn verify(&self) -> Result<(), ConfigError> {
+        if self.port < 1024 {
+            return Err(ConfigError::other("Port number must be >1024"));
+        }
+
+        if self.test_mode {
+            env::set_var(KRILL_ENV_TEST, "1");
+        }
+
+        if !self.test_mode
+            && self
+                .rsync_base
+                .to_string()
+                .to_lowercase()
+                .starts_with("rsync://localhost")
+        {
+            return Err(ConfigError::other(
+                "Cannot use localhost in rsync base unless test mode is used (KRILL_TEST)",
+            ));
+        }
+
+        if !self.test_mode
+            && self
+                .rrdp_service_uri()
+                .to_string()
+                .to_lowercase()
+                .starts_with("https://localhost")
+        {
+            return Err(ConfigError::other(
+                "Cannot use localhost in RRDP service URI unless test mode is used (KRILL_TEST)",
+            ));
+        }
+
+        if !self.rsync_base.to_string().ends_with('/') {
+            return Err(ConfigError::other("rsync base URI must end with '/'"));
+        }
+
+        if !self.service_uri.ends_with('/') {
+            return Err(ConfigError::other("service URI must end with '/'"));
+        } else {
+            uri::Https::from_str(&self.service_uri).map_err(|_| {
+                ConfigError::Other(format!("Invalid service uri: {}", self.service_uri))
+            })?;
+        }
+
+        if !self.rrdp_service_uri().to_string().ends_with('/') {
+            return Err(ConfigError::other("service URI must end with '/'"));
+        }
+
+        Ok(())
     }
+
+    pub fn read_config(file: &str) -> Result<Self, ConfigError> {
+        let mut v = Vec::new();
+        let mut f = File::open(file)?;
+        f.read_to_end(&mut v)?;
+
+        let c: PubdConfigFile = toml::from_slice(v.as_slice())?;
+        Ok(c.pubd)
+    }
+
+    pub fn init_logging(&self) -> Result<(), ConfigError> {
+        init_logging(
+            &self.log_type,
+            self.log_level,
+            &self.log_file,
+            &self.syslog_facility,
+        )
+    }
+}
+
+//------------ HistoryArchiveConfig ------------------------------------------
+
+/// The number of days that commands and their events are kept in the live
+/// history, for each type of aggregate. Older history is moved to the
+/// archive of each CA or publisher daily. Nothing is archived for types
+/// which are not configured.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct HistoryArchiveConfig {
+    /// Days to keep for CAs.
+    pub cas: Option<u32>,
+
+    /// Days to keep for the publication server.
+    pub pubd: Option<u32>,
+}
+
+impl HistoryArchiveConfig {
+    /// Returns the time before which the history should be archived, if
+    /// archiving is configured for the number of days.
+    pub fn archive_before(days: Option<u32>) -> Option<Time> {
+        days.map(|days| Time::now() - chrono::Duration::days(i64::from(days)))
+    }
+}
+
+//------------ Logging -------------------------------------------------------
+
+/// Sets up logging, shared by the Krill and the standalone publication
+/// server.
+fn init_logging(
+    log_type: &LogType,
+    log_level: LevelFilter,
+    log_file: &PathBuf,
+    syslog_facility: &str,
+) -> Result<(), ConfigError> {
+    match log_type {
+        LogType::File => file_logger(log_level, log_file),
+        LogType::Stderr => stderr_logger(log_level),
+        LogType::Syslog => {
+            let facility = Facility::from_str(syslog_facility)
+                .map_err(|_| ConfigError::other("Invalid syslog_facility"))?;
+            syslog_logger(log_level, facility)
+        }
+    }
+}
+
+/// Creates a stderr logger.
+fn stderr_logger(log_level: LevelFilter) -> Result<(), ConfigError> {
+    fern_logger(log_level)
+        .chain(io::stderr())
+        .apply()
+        .map_err(|e| ConfigError::Other(format!("Failed to init stderr logging: {}", e)))
+}
+
+/// Creates a file logger using the file provided by `path`.
+fn file_logger(log_level: LevelFilter, path: &PathBuf) -> Result<(), ConfigError> {
+    let file = match fern::log_file(path) {
+        Ok(file) => file,
+        Err(err) => {
+            let error_string = format!("Failed to open log file '{}': {}", path.display(), err);
+            error!("{}", error_string.as_str());
+            return Err(ConfigError::Other(error_string));
+        }
+    };
+    fern_logger(log_level)
+        .chain(file)
+        .apply()
+        .map_err(|e| ConfigError::Other(format!("Failed to init file logging: {}", e)))
+}
+
+/// Creates a syslog logger and configures correctly.
+#[cfg(unix)]
+fn syslog_logger(log_level: LevelFilter, facility: syslog::Facility) -> Result<(), ConfigError> {
+    let process = env::current_exe()
+        .ok()
+        .and_then(|path| {
+            path.file_name()
+                .and_then(std::ffi::OsStr::to_str)
+                .map(ToString::to_string)
+        })
+        .unwrap_or_else(|| String::from("krill"));
+    let pid = unsafe { libc::getpid() };
+    let formatter = syslog::Formatter3164 {
+        facility,
+        hostname: None,
+        process,
+        pid,
+    };
+    let logger = syslog::unix(formatter.clone())
+        .or_else(|_| syslog::tcp(formatter.clone(), ("127.0.0.1", 601)))
+        .or_else(|_| syslog::udp(formatter, ("127.0.0.1", 0), ("127.0.0.1", 514)));
+    match logger {
+        Ok(logger) => fern_logger(log_level)
+            .chain(logger)
+            .apply()
+            .map_err(|e| ConfigError::Other(format!("Failed to init syslog: {}", e))),
+        Err(err) => {
+            let msg = format!("Cannot connect to syslog: {}", err);
+            Err(ConfigError::Other(msg))
+        }
+    }
+}
+
+/// Creates and returns a fern logger with log level tweaks
+fn fern_logger(log_level: LevelFilter) -> fern::Dispatch {
+    let framework_level = match log_level {
+        LevelFilter::Off => LevelFilter::Off,
+        LevelFilter::Error => LevelFilter::Error,
+        _ => LevelFilter::Warn, // more becomes too noisy
+    };
+
+    let krill_framework_level = match log_level {
+        LevelFilter::Off => LevelFilter::Off,
+        LevelFilter::Error => LevelFilter::Error,
+        LevelFilter::Warn => LevelFilter::Warn,
+        _ => LevelFilter::Debug, // more becomes too noisy
+    };
+
+    let show_target = log_level == LevelFilter::Trace || log_level == LevelFilter::Debug;
+    fern::Dispatch::new()
+        .format(move |out, message, record| {
+            if show_target {
+                out.finish(format_args!(
+                    "{} [{}] [{}] {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    record.level(),
+                    record.target(),
+                    message
+                ))
+            } else {
+                out.finish(format_args!(
+                    "{} [{}] {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    record.level(),
+                    message
+                ))
+            }
+        })
+        .level(log_level)
+        .level_for("rustls", framework_level)
+        .level_for("hyper", framework_level)
+        .level_for("mio", framework_level)
+        .level_for("reqwest", framework_level)
+        .level_for("tokio_reactor", framework_level)
+        .level_for("want", framework_level)
+        .level_for("krill::commons::eventsourcing", krill_framework_level)
+        .level_for("krill::commons::util::file", krill_framework_level)
 }
 
 #[derive(Debug, Display)]
@@ -674,6 +1162,10 @@ mod tests {
 
     use super::*;
 
+    use crate::commons::api::Handle;
+    use crate::commons::bgp::AnnouncementSourceKind;
+    use crate::daemon::auth::Role;
+
     #[test]
     fn should_parse_default_config_file() {
         // Config for auth token is required! If there is nothing in the conf
@@ -685,5 +1177,302 @@ mod tests {
         let c = Config::read_config("./defaults/krill.conf").unwrap();
         let expected_socket_addr: SocketAddr = ([127, 0, 0, 1], 3000).into();
         assert_eq!(c.socket_addr(), expected_socket_addr);
+        assert_eq!(c.signer, SignerConfig::OpenSsl);
+        assert!(c.signers.is_empty());
+        assert!(c.api_tokens.is_empty());
+        assert!(c.openid_connect.is_none());
+    }
+
+    #[test]
+    fn should_parse_pkcs11_signer() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [signer]
+            type = "pkcs11"
+            lib_path = "/usr/lib/softhsm/libsofthsm2.so"
+            slot = 1
+            user_pin = "1234"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            c.signer,
+            SignerConfig::Pkcs11 {
+                lib_path: PathBuf::from("/usr/lib/softhsm/libsofthsm2.so"),
+                slot: 1,
+                user_pin: Some("1234".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn should_parse_named_signers() {
+        let toml = r#"
+            auth_token = "secret"
+            id_key_signer = "default"
+            ca_key_signer = "hsm"
+
+            [[signers]]
+            name = "hsm"
+            type = "pkcs11"
+            lib_path = "/usr/lib/softhsm/libsofthsm2.so"
+            user_pin = "1234"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(c.signer, SignerConfig::OpenSsl);
+        assert_eq!(c.id_key_signer, Some("default".to_string()));
+        assert_eq!(c.ca_key_signer, Some("hsm".to_string()));
+        assert_eq!(
+            c.signers,
+            vec![NamedSignerConfig::new(
+                "hsm",
+                SignerConfig::Pkcs11 {
+                    lib_path: PathBuf::from("/usr/lib/softhsm/libsofthsm2.so"),
+                    slot: 0,
+                    user_pin: Some("1234".to_string()),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn should_parse_api_tokens() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [[api_tokens]]
+            name = "noc"
+            token = "noc-secret"
+            role = "read_only"
+
+            [[api_tokens]]
+            name = "alice"
+            token = "alice-secret"
+            role = "roa_editor"
+            cas = [ "ca1", "ca2" ]
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            c.api_tokens,
+            vec![
+                ApiToken::new("noc", Token::from("noc-secret"), Role::ReadOnly, None),
+                ApiToken::new(
+                    "alice",
+                    Token::from("alice-secret"),
+                    Role::RoaEditor,
+                    Some(vec![
+                        Handle::from_str("ca1").unwrap(),
+                        Handle::from_str("ca2").unwrap()
+                    ])
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_duplicate_api_tokens() {
+        let mut c = Config::test_config(&PathBuf::from("/tmp"));
+        c.api_tokens = vec![
+            ApiToken::new("noc", Token::from("token"), Role::ReadOnly, None),
+            ApiToken::new("noc", Token::from("other"), Role::CaAdmin, None),
+        ];
+        assert!(c.verify().is_err());
+
+        c.api_tokens = vec![ApiToken::new(
+            "noc",
+            Token::from("secret"),
+            Role::ReadOnly,
+            None,
+        )];
+        assert!(c.verify().is_err());
+    }
+
+    #[test]
+    fn should_parse_openid_connect() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [openid_connect]
+            issuer_url = "https://login.example.com/"
+            client_id = "krill"
+            client_secret = "client-secret"
+            role_claim = "groups"
+
+            [openid_connect.role_map]
+            "rpki-admins" = "admin"
+            "noc" = "read_only"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        let oidc = c.openid_connect.unwrap();
+        assert_eq!(oidc.issuer_url, "https://login.example.com/");
+        assert_eq!(oidc.id_claim, "email");
+        assert_eq!(oidc.role_claim, "groups");
+        assert_eq!(oidc.cas_claim, "krill_cas");
+        assert_eq!(oidc.role_map.get("noc"), Some(&Role::ReadOnly));
+        assert_eq!(oidc.session_ttl, 8 * 3600);
+    }
+
+    #[test]
+    fn should_parse_history_archive() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [history_archive]
+            cas = 90
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(c.history_archive.cas, Some(90));
+        assert_eq!(c.history_archive.pubd, None);
+    }
+
+    #[test]
+    fn should_parse_bgp_sources() {
+        let toml = r#"
+            auth_token = "secret"
+            bgp_risdumps_refresh_minutes = 120
+            bgp_risdumps_visibility_threshold = 2
+
+            [[bgp_sources]]
+            type = "mrt"
+            location = "/var/lib/krill/bgp/rib.mrt.gz"
+            refresh_minutes = 15
+
+            [[bgp_sources]]
+            type = "bmp"
+            listen = "127.0.0.1:11019"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(c.bgp_risdumps_visibility_threshold, 2);
+
+        let sources = c.bgp_sources();
+        assert_eq!(sources.len(), 3);
+        assert_eq!(
+            sources[0],
+            AnnouncementSourceConfig::ris(
+                &ConfigDefaults::bgp_risdumps_v4_uri(),
+                &ConfigDefaults::bgp_risdumps_v6_uri(),
+                120
+            )
+        );
+        assert_eq!(
+            sources[1],
+            AnnouncementSourceConfig::new(
+                AnnouncementSourceKind::Mrt {
+                    location: "/var/lib/krill/bgp/rib.mrt.gz".to_string()
+                },
+                15
+            )
+        );
+        assert_eq!(sources[2].refresh_minutes(), BGP_RIS_REFRESH_MINUTES as u32);
+    }
+
+    #[test]
+    fn should_parse_alert_sinks() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [[alert_sinks]]
+            type = "webhook"
+            uri = "https://noc.example.com/krill-alerts"
+
+            [[alert_sinks]]
+            type = "sendmail"
+            to = "noc@example.com"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            c.alert_sinks,
+            vec![
+                AlertSinkConfig::Webhook {
+                    uri: "https://noc.example.com/krill-alerts".to_string()
+                },
+                AlertSinkConfig::Sendmail {
+                    to: "noc@example.com".to_string(),
+                    from: None,
+                    command: "/usr/sbin/sendmail".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_webhooks() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [[webhooks]]
+            uri = "https://provisioning.example.com/krill"
+            secret = "shared"
+            events = [ "child_certificate_issued", "published" ]
+
+            [[webhooks]]
+            uri = "https://audit.example.com/krill"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            c.webhooks,
+            vec![
+                WebhookConfig::new(
+                    "https://provisioning.example.com/krill",
+                    Some("shared"),
+                    vec![
+                        "child_certificate_issued".to_string(),
+                        "published".to_string()
+                    ]
+                ),
+                WebhookConfig::new("https://audit.example.com/krill", None, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_default_pubd_config_file() {
+        env::set_var(KRILL_ENV_PUBD_AUTH_TOKEN, "pubd-secret");
+        env::set_var(KRILL_ENV_TEST, "1");
+
+        let c = PubdConfig::read_config("./defaults/krillpubd.conf").unwrap();
+        let expected_socket_addr: SocketAddr = ([127, 0, 0, 1], 3001).into();
+        assert_eq!(c.socket_addr(), expected_socket_addr);
+        assert_eq!(
+            c.rrdp_service_uri().to_string(),
+            "https://localhost:3001/rrdp/"
+        );
+        assert_eq!(c.signer, SignerConfig::OpenSsl);
+        c.verify().unwrap();
+    }
+
+    #[test]
+    fn should_parse_pubd_section_next_to_krill_config() {
+        let toml = r#"
+            auth_token = "secret"
+            port = 3000
+
+            [pubd]
+            auth_token = "pubd-secret"
+            data_dir = "/var/lib/krillpubd"
+            service_uri = "https://repo.example.com/"
+            rsync_base = "rsync://repo.example.com/repo/"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(c.auth_token, Token::from("secret"));
+
+        let c: PubdConfigFile = toml::from_str(toml).unwrap();
+        let c = c.pubd;
+        assert_eq!(c.auth_token, Token::from("pubd-secret"));
+        assert_eq!(c.port, 3001);
+        assert_eq!(c.data_dir, PathBuf::from("/var/lib/krillpubd"));
+        assert_eq!(
+            c.rrdp_service_uri().to_string(),
+            "https://repo.example.com/rrdp/"
+        );
     }
 }
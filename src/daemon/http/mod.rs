@@ -7,20 +7,26 @@ use bytes::{Buf, BufMut, Bytes};
 use serde::Serialize;
 
 use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, COOKIE, SET_COOKIE};
 use hyper::http::uri::PathAndQuery;
 use hyper::{Body, Method, StatusCode};
 
 use crate::commons::api::Token;
 use crate::commons::error::Error;
 use crate::commons::remote::{rfc6492, rfc8181};
-use crate::daemon::auth::Auth;
+use crate::daemon::auth::{Actor, Auth};
 use crate::daemon::http::server::State;
 
+pub mod pubd;
 pub mod server;
 pub mod statics;
 pub mod tls;
 pub mod tls_keys;
 
+/// The name of the cookie that holds the session token after an OpenID
+/// Connect login.
+pub const SESSION_COOKIE: &str = "krill_session";
+
 //------------ RoutingResult ---------------------------------------------
 
 pub type RoutingResult = Result<HttpResponse, Request>;
@@ -30,6 +36,7 @@ pub type RoutingResult = Result<HttpResponse, Request>;
 #[derive(Clone, Copy)]
 enum ContentType {
     Cert,
+    Gzip,
     Json,
     Rfc8181,
     Rfc6492,
@@ -48,6 +55,7 @@ impl AsRef<str> for ContentType {
     fn as_ref(&self) -> &str {
         match self {
             ContentType::Cert => "application/x-x509-ca-cert",
+            ContentType::Gzip => "application/gzip",
             ContentType::Json => "application/json",
             ContentType::Rfc8181 => rfc8181::CONTENT_TYPE,
             ContentType::Rfc6492 => rfc6492::CONTENT_TYPE,
@@ -170,6 +178,10 @@ impl HttpResponse {
         Self::ok_response(ContentType::Cert, body)
     }
 
+    pub fn gzip(body: Vec<u8>) -> Self {
+        Self::ok_response(ContentType::Gzip, body)
+    }
+
     pub fn html(content: &[u8]) -> Self {
         Self::ok_response(ContentType::Html, content.to_vec())
     }
@@ -223,6 +235,24 @@ impl HttpResponse {
     pub fn forbidden() -> Self {
         Response::new(StatusCode::FORBIDDEN).finalize()
     }
+
+    /// Redirects the client to the given location.
+    pub fn found(location: &str) -> Self {
+        let response = hyper::Response::builder()
+            .status(StatusCode::FOUND)
+            .header("Location", location)
+            .body(Body::empty())
+            .unwrap();
+        HttpResponse(response)
+    }
+
+    /// Adds a Set-Cookie header to this response.
+    pub fn with_cookie(mut self, cookie: &str) -> Self {
+        if let Ok(value) = HeaderValue::from_str(cookie) {
+            self.0.headers_mut().append(SET_COOKIE, value);
+        }
+        self
+    }
 }
 
 //------------ Request -------------------------------------------------------
@@ -294,102 +324,152 @@ impl Request {
         self.read_bytes(limit).await
     }
 
-    /// See hyper::body::to_bytes
-    ///
-    /// Here we want to limit the bytes consumed to a maximum. So, the
-    /// code below is adapted from the method in the hyper crate.
+    /// Reads the body, up to the given limit.
     pub async fn read_bytes(self, limit: u64) -> Result<Bytes, Error> {
-        let body = self.request.into_body();
-
-        futures_util::pin_mut!(body);
-
-        if body.size_hint().lower() > limit {
-            return Err(Error::PostTooBig);
-        }
+        read_body(self.request.into_body(), limit).await
+    }
+
+    /// Returns the value of a query parameter, if present.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let mut url = reqwest::Url::parse("https://localhost/").ok()?;
+        url.set_query(self.request.uri().query());
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// Returns the value of a cookie, if present.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.request
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(';'))
+            .filter_map(|pair| {
+                let mut parts = pair.trim().splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) if key == name => Some(value.to_string()),
+                    _ => None,
+                }
+            })
+            .next()
+    }
 
-        let mut size_processed = 0;
-
-        fn assert_body_size(
-            size_processed: u64,
-            body_lower_hint: u64,
-            post_limit: u64,
-        ) -> Result<(), Error> {
-            if size_processed + body_lower_hint > post_limit {
-                Err(Error::PostTooBig)
-            } else {
-                Ok(())
-            }
-        }
+    /// Returns the Bearer token in the Authorization header, if present.
+    pub fn bearer_token(&self) -> Option<Token> {
+        bearer_token(&self.request)
+    }
 
-        assert_body_size(size_processed, body.size_hint().lower(), limit)?;
+    /// Returns the session token in the session cookie, if present.
+    pub fn session_token(&self) -> Option<Token> {
+        self.cookie(SESSION_COOKIE).map(Token::from)
+    }
 
-        // If there's only 1 chunk, we can just return Buf::to_bytes()
-        let mut first = if let Some(buf) = body.data().await {
-            let buf = buf.map_err(|_| Error::PostCannotRead)?;
-            let size: u64 = buf
-                .bytes()
-                .len()
-                .try_into()
-                .map_err(|_| Error::PostTooBig)?;
-            size_processed += size;
-            buf
-        } else {
-            return Ok(Bytes::new());
+    /// Returns the actor for the Bearer token, or for the session cookie
+    /// if there is no Bearer token. Returns None if both are missing or
+    /// unknown.
+    pub async fn actor(&self) -> Option<Actor> {
+        let auth = match self.bearer_token() {
+            Some(token) => Auth::bearer(token),
+            None => Auth::session(self.session_token()?),
         };
+        self.state.read().await.actor(&auth)
+    }
+}
 
-        assert_body_size(size_processed, body.size_hint().lower(), limit)?;
-        let second = if let Some(buf) = body.data().await {
-            let buf = buf.map_err(|_| Error::PostCannotRead)?;
-            let size: u64 = buf
-                .bytes()
-                .len()
-                .try_into()
-                .map_err(|_| Error::PostTooBig)?;
-            size_processed += size;
-            buf
-        } else {
-            return Ok(first.to_bytes());
-        };
+//------------ bearer_token --------------------------------------------------
 
-        assert_body_size(size_processed, body.size_hint().lower(), limit)?;
-        // With more than 1 buf, we gotta flatten into a Vec first.
-        let cap = first.remaining() + second.remaining() + body.size_hint().lower() as usize;
-        let mut vec = Vec::with_capacity(cap);
-        vec.put(first);
-        vec.put(second);
-
-        while let Some(buf) = body.data().await {
-            let buf = buf.map_err(|_| Error::PostCannotRead)?;
-            let size: u64 = buf
-                .bytes()
-                .len()
-                .try_into()
-                .map_err(|_| Error::PostTooBig)?;
-            size_processed += size;
-            assert_body_size(size_processed, body.size_hint().lower(), limit)?;
-            vec.put(buf);
+/// Returns the Bearer token in the Authorization header, if present.
+fn bearer_token<B>(request: &hyper::Request<B>) -> Option<Token> {
+    let header = request.headers().get("Authorization")?;
+    let header = header.to_str().ok()?;
+    if header.len() > 6 {
+        let (bearer, token) = header.split_at(6);
+        if "Bearer" == bearer.trim() {
+            return Some(Token::from(token.trim()));
         }
+    }
+    None
+}
 
-        Ok(vec.into())
+//------------ read_body -----------------------------------------------------
+
+/// See hyper::body::to_bytes
+///
+/// Here we want to limit the bytes consumed to a maximum. So, the
+/// code below is adapted from the method in the hyper crate.
+async fn read_body(body: Body, limit: u64) -> Result<Bytes, Error> {
+    futures_util::pin_mut!(body);
+
+    if body.size_hint().lower() > limit {
+        return Err(Error::PostTooBig);
     }
 
-    /// Checks whether the Bearer token is set to what we expect
-    pub async fn is_authorized(&self) -> bool {
-        if let Some(header) = self.request.headers().get("Authorization") {
-            if let Ok(header) = header.to_str() {
-                if header.len() > 6 {
-                    let (bearer, token) = header.split_at(6);
-                    let bearer = bearer.trim();
-                    let token = Token::from(token.trim());
+    let mut size_processed = 0;
 
-                    if "Bearer" == bearer {
-                        return self.state.read().await.is_api_allowed(&Auth::bearer(token));
-                    }
-                }
-            }
+    fn assert_body_size(
+        size_processed: u64,
+        body_lower_hint: u64,
+        post_limit: u64,
+    ) -> Result<(), Error> {
+        if size_processed + body_lower_hint > post_limit {
+            Err(Error::PostTooBig)
+        } else {
+            Ok(())
         }
-        false
     }
+
+    assert_body_size(size_processed, body.size_hint().lower(), limit)?;
+
+    // If there's only 1 chunk, we can just return Buf::to_bytes()
+    let mut first = if let Some(buf) = body.data().await {
+        let buf = buf.map_err(|_| Error::PostCannotRead)?;
+        let size: u64 = buf
+            .bytes()
+            .len()
+            .try_into()
+            .map_err(|_| Error::PostTooBig)?;
+        size_processed += size;
+        buf
+    } else {
+        return Ok(Bytes::new());
+    };
+
+    assert_body_size(size_processed, body.size_hint().lower(), limit)?;
+    let second = if let Some(buf) = body.data().await {
+        let buf = buf.map_err(|_| Error::PostCannotRead)?;
+        let size: u64 = buf
+            .bytes()
+            .len()
+            .try_into()
+            .map_err(|_| Error::PostTooBig)?;
+        size_processed += size;
+        buf
+    } else {
+        return Ok(first.to_bytes());
+    };
+
+    assert_body_size(size_processed, body.size_hint().lower(), limit)?;
+    // With more than 1 buf, we gotta flatten into a Vec first.
+    let cap = first.remaining() + second.remaining() + body.size_hint().lower() as usize;
+    let mut vec = Vec::with_capacity(cap);
+    vec.put(first);
+    vec.put(second);
+
+    while let Some(buf) = body.data().await {
+        let buf = buf.map_err(|_| Error::PostCannotRead)?;
+        let size: u64 = buf
+            .bytes()
+            .len()
+            .try_into()
+            .map_err(|_| Error::PostTooBig)?;
+        size_processed += size;
+        assert_body_size(size_processed, body.size_hint().lower(), limit)?;
+        vec.put(buf);
+    }
+
+    Ok(vec.into())
 }
 
 //------------ RequestPath ---------------------------------------------------
@@ -0,0 +1,269 @@
+//! Hyper based HTTP server for the standalone publication server.
+//!
+//! This serves RFC 8181 for publishers, the RRDP files, and the publisher
+//! management API under the same paths as Krill does, so that existing
+//! clients (like krillc) can be pointed at it.
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryFutureExt;
+
+use hyper;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method};
+
+use tokio::time::delay_for;
+
+use crate::commons::api::{Handle, PublisherList};
+use crate::commons::error::Error;
+use crate::commons::remote::rfc8183;
+use crate::daemon::config::{HistoryArchiveConfig, PubdConfig};
+use crate::daemon::http::server::repo_metrics;
+use crate::daemon::http::{bearer_token, read_body, tls, tls_keys, HttpResponse, RequestPath};
+use crate::daemon::pubdserver::PubdServer;
+use crate::upgrades::pre_start_upgrade;
+
+//------------ State ---------------------------------------------------------
+
+pub type PubdState = Arc<PubdServer>;
+
+pub async fn start(config: PubdConfig) -> Result<(), Error> {
+    // Call upgrade, this will only do actual work if needed.
+    pre_start_upgrade(&config.data_dir)
+        .map_err(|e| Error::Custom(format!("Could not upgrade repository: {}", e)))?;
+
+    let state = Arc::new(PubdServer::build(&config)?);
+
+    // Move old history to the archives of publishers daily, if configured.
+    let days = config.history_archive.pubd;
+    if days.is_some() {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                delay_for(Duration::from_secs(24 * 3600)).await;
+                if let Some(before) = HistoryArchiveConfig::archive_before(days) {
+                    if let Err(e) = state.archive_history(before) {
+                        error!("Failed to archive repository history: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    let service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                let state = state.clone();
+                map_requests(req, state)
+            }))
+        }
+    });
+
+    tls_keys::create_key_cert_if_needed(&config.data_dir)
+        .map_err(|e| Error::HttpsSetup(format!("{}", e)))?;
+
+    let server_config_builder = tls::TlsConfigBuilder::new()
+        .cert_path(tls_keys::cert_file_path(&config.data_dir))
+        .key_path(tls_keys::key_file_path(&config.data_dir));
+    let server_config = server_config_builder.build().unwrap();
+
+    let acceptor = tls::TlsAcceptor::new(
+        server_config,
+        AddrIncoming::bind(&config.socket_addr()).unwrap(),
+    );
+
+    let server = hyper::Server::builder(acceptor)
+        .serve(service)
+        .map_err(|e| eprintln!("Server error: {}", e));
+
+    if server.await.is_err() {
+        eprintln!("Publication server failed to start");
+        ::std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn map_requests(
+    req: hyper::Request<Body>,
+    state: PubdState,
+) -> Result<hyper::Response<Body>, Error> {
+    let log_req = format!("{} {}", req.method(), req.uri().path());
+
+    let response = route(req, &state).await.response();
+    info!("{} {}", log_req, response.status());
+    trace!("Response body: {:?}", response.body());
+    Ok(response)
+}
+
+async fn route(req: hyper::Request<Body>, state: &PubdServer) -> HttpResponse {
+    let path = RequestPath::from_request(&req);
+    let method = req.method().clone();
+    let segment = path.segment().to_string();
+
+    match (method, segment.as_str()) {
+        (Method::GET, "health") => HttpResponse::ok(),
+        (Method::GET, "metrics") => metrics(state),
+        (Method::GET, "stats") => match path.full() {
+            "/stats/info" => HttpResponse::json(&state.server_info()),
+            "/stats/repo" => json_res(state.repo_stats()),
+            _ => HttpResponse::not_found(),
+        },
+        (_, "rfc8181") => rfc8181(req, path, state).await,
+        (Method::GET, "rrdp") => rrdp(&path, state),
+        (_, "api") => api(req, path, state).await,
+        _ => HttpResponse::not_found(),
+    }
+}
+
+fn json_res<O: serde::Serialize>(res: Result<O, Error>) -> HttpResponse {
+    match res {
+        Ok(o) => HttpResponse::json(&o),
+        Err(e) => HttpResponse::error(e),
+    }
+}
+
+/// Produce prometheus style metrics
+fn metrics(state: &PubdServer) -> HttpResponse {
+    let mut res = String::new();
+
+    let info = state.server_info();
+    res.push_str("# HELP krill_server_start timestamp of last krill server start\n");
+    res.push_str("# TYPE krill_server_start gauge\n");
+    res.push_str(&format!("krill_server_start {}\n", info.started()));
+    res.push_str("\n");
+
+    if let Ok(stats) = state.repo_stats() {
+        repo_metrics(&mut res, &stats);
+    }
+
+    HttpResponse::text(res.into_bytes())
+}
+
+/// Handle RFC8181 queries and return the appropriate response.
+async fn rfc8181(
+    req: hyper::Request<Body>,
+    mut path: RequestPath,
+    state: &PubdServer,
+) -> HttpResponse {
+    let publisher = match path.path_arg() {
+        Some(publisher) => publisher,
+        None => return HttpResponse::error(Error::ApiInvalidHandle),
+    };
+
+    let bytes = match read_body(req.into_body(), state.limit_rfc8181()).await {
+        Ok(bytes) => bytes,
+        Err(e) => return HttpResponse::error(e),
+    };
+
+    match state.rfc8181(publisher, bytes) {
+        Ok(bytes) => HttpResponse::rfc8181(bytes.to_vec()),
+        Err(e) => HttpResponse::error(e),
+    }
+}
+
+fn rrdp(path: &RequestPath, state: &PubdServer) -> HttpResponse {
+    let mut full_path = state.rrdp_base_path();
+    let (_, path) = path.remaining().split_at(1);
+    let cache_seconds = if path.ends_with("notification.xml") {
+        60
+    } else {
+        86400
+    };
+    full_path.push(path);
+
+    match File::open(full_path) {
+        Ok(mut file) => {
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer).unwrap();
+            HttpResponse::xml_with_cache(buffer, cache_seconds)
+        }
+        _ => HttpResponse::not_found(),
+    }
+}
+
+/// Maps the publisher management API, which uses the same paths as Krill.
+/// Access requires the token configured for the publication server.
+async fn api(req: hyper::Request<Body>, mut path: RequestPath, state: &PubdServer) -> HttpResponse {
+    if path.next() != Some("v1") {
+        return HttpResponse::not_found();
+    }
+
+    if !state.is_api_allowed(bearer_token(&req).as_ref()) {
+        return HttpResponse::forbidden();
+    }
+
+    let method = req.method().clone();
+    match (method, path.next()) {
+        (Method::GET, Some("authorized")) => HttpResponse::ok(),
+        (method, Some("integrity")) => match (method, path.next()) {
+            (Method::GET, None) => json_res(state.verify_integrity(false)),
+            (Method::POST, Some("repair")) => json_res(state.verify_integrity(true)),
+            _ => HttpResponse::error(Error::ApiUnknownMethod),
+        },
+        (method, Some("publishers")) => api_publishers(req, method, path, state).await,
+        _ => HttpResponse::error(Error::ApiUnknownMethod),
+    }
+}
+
+async fn api_publishers(
+    req: hyper::Request<Body>,
+    method: Method,
+    mut path: RequestPath,
+    state: &PubdServer,
+) -> HttpResponse {
+    match method {
+        Method::GET => match path.next() {
+            None => json_res(
+                state
+                    .publishers()
+                    .map(|publishers| PublisherList::build(&publishers, "/api/v1/publishers")),
+            ),
+            Some("stale") => match path.next().map(i64::from_str) {
+                Some(Ok(seconds)) => json_res(state.repo_stats().map(|stats| {
+                    PublisherList::build(&stats.stale_publishers(seconds), "/api/v1/publishers")
+                })),
+                _ => HttpResponse::error(Error::ApiInvalidSeconds),
+            },
+            Some(publisher) => match Handle::from_str(publisher) {
+                Ok(publisher) => match path.next() {
+                    None => json_res(state.get_publisher(&publisher)),
+                    Some("response.xml") => match state.repository_response(&publisher) {
+                        Ok(res) => HttpResponse::xml(res.encode_vec()),
+                        Err(e) => HttpResponse::error(e),
+                    },
+                    Some("response.json") => json_res(state.repository_response(&publisher)),
+                    _ => HttpResponse::error(Error::ApiUnknownMethod),
+                },
+                Err(_) => HttpResponse::error(Error::ApiInvalidHandle),
+            },
+        },
+        Method::POST => match path.next() {
+            None => {
+                let bytes = match read_body(req.into_body(), state.limit_api()).await {
+                    Ok(bytes) => bytes,
+                    Err(e) => return HttpResponse::error(e),
+                };
+                match serde_json::from_slice::<rfc8183::PublisherRequest>(&bytes) {
+                    Ok(pbl) => json_res(state.add_publisher(pbl)),
+                    Err(e) => HttpResponse::error(Error::JsonError(e)),
+                }
+            }
+            _ => HttpResponse::error(Error::ApiUnknownMethod),
+        },
+        Method::DELETE => match path.path_arg() {
+            Some(publisher) => match state.remove_publisher(publisher) {
+                Ok(()) => HttpResponse::ok(),
+                Err(e) => HttpResponse::error(e),
+            },
+            None => HttpResponse::error(Error::ApiInvalidHandle),
+        },
+        _ => HttpResponse::error(Error::ApiUnknownMethod),
+    }
+}
@@ -21,16 +21,22 @@ use hyper::service::{make_service_fn, service_fn};
 use hyper::Method;
 
 use crate::commons::api::{
-    BgpStats, ChildHandle, CommandHistoryCriteria, Handle, ParentCaContact, ParentCaReq,
-    ParentHandle, PublisherList, RepositoryUpdate,
+    AsNumber, BackupRequest, BgpStats, CertAuthList, ChildHandle, CommandHistoryCriteria, Handle,
+    HistoryPoint, ParentCaContact, ParentCaReq, ParentHandle, PublisherList, RepositoryUpdate,
+    RtaName,
 };
 use crate::commons::error::Error;
 use crate::commons::remote::rfc8183;
-use crate::constants::KRILL_ENV_UPGRADE_ONLY;
+use crate::constants::{EVENTS_LONG_POLL_SECS, KRILL_ENV_UPGRADE_ONLY};
+use crate::daemon::auth::{Actor, Permission};
 use crate::daemon::config::Config;
 use crate::daemon::http::statics::statics;
-use crate::daemon::http::{tls, tls_keys, HttpResponse, Request, RequestPath, RoutingResult};
+use crate::daemon::http::{
+    tls, tls_keys, HttpResponse, Request, RequestPath, RoutingResult, SESSION_COOKIE,
+};
 use crate::daemon::krillserver::KrillServer;
+use crate::daemon::webhooks::{EventNotification, EventSource};
+use crate::pubd::RepoStats;
 use crate::upgrades::{post_start_upgrade, pre_start_upgrade};
 
 //------------ State -----------------------------------------------------
@@ -46,6 +52,7 @@ pub async fn start(config: Config) -> Result<(), Error> {
     let krill = KrillServer::build(&config)?;
 
     post_start_upgrade(&config.data_dir, &krill)
+        .await
         .map_err(|e| Error::Custom(format!("Could not upgrade Krill: {}", e)))?;
 
     if env::var(KRILL_ENV_UPGRADE_ONLY).is_ok() {
@@ -99,6 +106,7 @@ async fn map_requests(
     let log_req = format!("{} {}", req.method(), req.path.full());
 
     let res = api(req)
+        .or_else(auth)
         .or_else(health)
         .or_else(metrics)
         .or_else(stats)
@@ -163,6 +171,11 @@ fn render_unknown_method() -> RoutingResult {
     Ok(HttpResponse::error(Error::ApiUnknownMethod))
 }
 
+/// A clean 403 result for actors lacking the permission for a resource
+fn render_forbidden() -> RoutingResult {
+    Ok(HttpResponse::forbidden())
+}
+
 /// A clean 404 response
 pub async fn render_not_found(_req: Request) -> RoutingResult {
     Ok(HttpResponse::not_found())
@@ -188,6 +201,7 @@ pub async fn metrics(req: Request) -> RoutingResult {
             announcements_invalid_asn: HashMap<Handle, usize>,
             announcements_invalid_length: HashMap<Handle, usize>,
             announcements_not_found: HashMap<Handle, usize>,
+            announcements_low_visibility: HashMap<Handle, usize>,
             roas_stale: HashMap<Handle, usize>,
         }
 
@@ -201,6 +215,8 @@ pub async fn metrics(req: Request) -> RoutingResult {
                     .insert(ca.clone(), stats.announcements_invalid_length);
                 self.announcements_not_found
                     .insert(ca.clone(), stats.announcements_not_found);
+                self.announcements_low_visibility
+                    .insert(ca.clone(), stats.announcements_low_visibility);
                 self.roas_stale.insert(ca.clone(), stats.roas_stale);
             }
         }
@@ -213,68 +229,39 @@ pub async fn metrics(req: Request) -> RoutingResult {
         res.push_str(&format!("krill_server_start {}\n", info.started()));
         res.push_str("\n");
 
-        if let Ok(stats) = server.repo_stats() {
-            let publishers = stats.get_publishers();
-
-            res.push_str("# HELP krill_repo_publisher number of publishers in repository\n");
-            res.push_str("# TYPE krill_repo_publisher gauge\n");
-            res.push_str(&format!("krill_repo_publisher {}\n", publishers.len()));
-
-            if let Some(last_update) = stats.last_update() {
-                res.push_str("\n");
-                res.push_str(
-                    "# HELP krill_repo_rrdp_last_update timestamp of last update by any publisher\n",
-                );
-                res.push_str("# TYPE krill_repo_rrdp_last_update gauge\n");
-                res.push_str(&format!(
-                    "krill_repo_rrdp_last_update {}\n",
-                    last_update.timestamp()
-                ));
-            }
-
-            res.push_str("\n");
-            res.push_str("# HELP krill_repo_rrdp_serial RRDP serial\n");
-            res.push_str("# TYPE krill_repo_rrdp_serial counter\n");
-            res.push_str(&format!("krill_repo_rrdp_serial {}\n", stats.serial()));
+        let queue_stats = server.event_queue_stats();
+        res.push_str(
+            "# HELP krill_event_queue_pending number of triggered tasks waiting to be processed\n",
+        );
+        res.push_str("# TYPE krill_event_queue_pending gauge\n");
+        res.push_str(&format!(
+            "krill_event_queue_pending {}\n",
+            queue_stats.pending
+        ));
 
-            res.push_str("\n");
-            res.push_str(
-                "# HELP krill_repo_objects number of objects in repository for publisher\n",
-            );
-            res.push_str("# TYPE krill_repo_objects gauge\n");
-            for (publisher, stats) in publishers {
-                res.push_str(&format!(
-                    "krill_repo_objects{{publisher=\"{}\"}} {}\n",
-                    publisher,
-                    stats.objects()
-                ));
-            }
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_event_queue_retrying number of triggered tasks waiting for a retry\n",
+        );
+        res.push_str("# TYPE krill_event_queue_retrying gauge\n");
+        res.push_str(&format!(
+            "krill_event_queue_retrying {}\n",
+            queue_stats.retrying
+        ));
 
-            res.push_str("\n");
-            res.push_str(
-                "# HELP krill_repo_size size of objects in bytes in repository for publisher\n",
-            );
-            res.push_str("# TYPE krill_repo_size gauge\n");
-            for (publisher, stats) in publishers {
-                res.push_str(&format!(
-                    "krill_repo_size{{publisher=\"{}\"}} {}\n",
-                    publisher,
-                    stats.size()
-                ));
-            }
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_event_queue_failures number of failed triggered tasks since start\n",
+        );
+        res.push_str("# TYPE krill_event_queue_failures counter\n");
+        res.push_str(&format!(
+            "krill_event_queue_failures {}\n",
+            queue_stats.failures
+        ));
+        res.push_str("\n");
 
-            res.push_str("\n");
-            res.push_str("# HELP krill_repo_last_update timestamp of last update for publisher\n");
-            res.push_str("# TYPE krill_repo_last_update gauge\n");
-            for (publisher, stats) in publishers {
-                if let Some(last_update) = stats.last_update() {
-                    res.push_str(&format!(
-                        "krill_repo_last_update{{publisher=\"{}\"}} {}\n",
-                        publisher,
-                        last_update.timestamp()
-                    ));
-                }
-            }
+        if let Ok(stats) = server.repo_stats() {
+            repo_metrics(&mut res, &stats);
         }
 
         let cas_status = server.cas_stats();
@@ -313,6 +300,7 @@ pub async fn metrics(req: Request) -> RoutingResult {
             announcements_invalid_asn: HashMap::new(),
             announcements_invalid_length: HashMap::new(),
             announcements_not_found: HashMap::new(),
+            announcements_low_visibility: HashMap::new(),
             roas_stale: HashMap::new(),
         };
         for (ca, status) in cas_status.iter() {
@@ -365,6 +353,18 @@ pub async fn metrics(req: Request) -> RoutingResult {
             ));
         }
 
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_cas_bgp_announcements_low_visibility number of announcements seen for CA resources by too few peers to be considered reliable\n",
+        );
+        res.push_str("# TYPE krill_cas_bgp_announcements_low_visibility gauge\n");
+        for (ca, nr) in all_bgp_stats.announcements_low_visibility.iter() {
+            res.push_str(&format!(
+                "krill_cas_bgp_announcements_low_visibility{{ca=\"{}\"}} {}\n",
+                ca, nr
+            ));
+        }
+
         res.push_str("\n");
         res.push_str(
             "# HELP krill_cas_bgp_roas_stale number of ROAs for this CA for which no announcements are seen (0 may also indicate that no BGP info is available)\n",
@@ -383,6 +383,68 @@ pub async fn metrics(req: Request) -> RoutingResult {
     }
 }
 
+/// Adds the prometheus style metrics for the repository, used by Krill with
+/// an embedded repository and by the standalone publication server.
+pub(super) fn repo_metrics(res: &mut String, stats: &RepoStats) {
+    let publishers = stats.get_publishers();
+
+    res.push_str("# HELP krill_repo_publisher number of publishers in repository\n");
+    res.push_str("# TYPE krill_repo_publisher gauge\n");
+    res.push_str(&format!("krill_repo_publisher {}\n", publishers.len()));
+
+    if let Some(last_update) = stats.last_update() {
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_repo_rrdp_last_update timestamp of last update by any publisher\n",
+        );
+        res.push_str("# TYPE krill_repo_rrdp_last_update gauge\n");
+        res.push_str(&format!(
+            "krill_repo_rrdp_last_update {}\n",
+            last_update.timestamp()
+        ));
+    }
+
+    res.push_str("\n");
+    res.push_str("# HELP krill_repo_rrdp_serial RRDP serial\n");
+    res.push_str("# TYPE krill_repo_rrdp_serial counter\n");
+    res.push_str(&format!("krill_repo_rrdp_serial {}\n", stats.serial()));
+
+    res.push_str("\n");
+    res.push_str("# HELP krill_repo_objects number of objects in repository for publisher\n");
+    res.push_str("# TYPE krill_repo_objects gauge\n");
+    for (publisher, stats) in publishers {
+        res.push_str(&format!(
+            "krill_repo_objects{{publisher=\"{}\"}} {}\n",
+            publisher,
+            stats.objects()
+        ));
+    }
+
+    res.push_str("\n");
+    res.push_str("# HELP krill_repo_size size of objects in bytes in repository for publisher\n");
+    res.push_str("# TYPE krill_repo_size gauge\n");
+    for (publisher, stats) in publishers {
+        res.push_str(&format!(
+            "krill_repo_size{{publisher=\"{}\"}} {}\n",
+            publisher,
+            stats.size()
+        ));
+    }
+
+    res.push_str("\n");
+    res.push_str("# HELP krill_repo_last_update timestamp of last update for publisher\n");
+    res.push_str("# TYPE krill_repo_last_update gauge\n");
+    for (publisher, stats) in publishers {
+        if let Some(last_update) = stats.last_update() {
+            res.push_str(&format!(
+                "krill_repo_last_update{{publisher=\"{}\"}} {}\n",
+                publisher,
+                last_update.timestamp()
+            ));
+        }
+    }
+}
+
 //------------ Publication ---------------------------------------------------
 
 /// Handle RFC8181 queroes and return the appropriate response.
@@ -478,15 +540,65 @@ async fn stats(req: Request) -> RoutingResult {
     }
 }
 
+/// Maps the OpenID Connect login, callback and logout requests
+async fn auth(req: Request) -> RoutingResult {
+    match req.path().full() {
+        "/auth/login" if req.is_get() => match req.state().read().await.login_uri().await {
+            Ok(uri) => Ok(HttpResponse::found(&uri)),
+            Err(e) => render_error(e),
+        },
+        "/auth/callback" if req.is_get() => {
+            let (code, state) = match (req.query_param("code"), req.query_param("state")) {
+                (Some(code), Some(state)) => (code, state),
+                _ => {
+                    return render_error(Error::ApiLoginFailed("missing code or state".to_string()))
+                }
+            };
+
+            match req.state().read().await.login_callback(&code, &state).await {
+                Ok((token, ttl)) => {
+                    // Lagosta keeps the token in local storage and uses it as
+                    // a Bearer token, the cookie is for other browser clients.
+                    let page = format!(
+                        "<!DOCTYPE html><html><head><script>\
+                         localStorage.setItem(\"krill_user\", JSON.stringify({{authdata: btoa(\"{}\")}}));\
+                         window.location.replace(\"/\");\
+                         </script></head><body></body></html>",
+                        token
+                    );
+                    let cookie = format!(
+                        "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                        SESSION_COOKIE, token, ttl
+                    );
+                    Ok(HttpResponse::html(page.as_bytes()).with_cookie(&cookie))
+                }
+                Err(e) => render_error(e),
+            }
+        }
+        "/auth/logout" if req.is_post() => {
+            if let Some(token) = req.session_token().or_else(|| req.bearer_token()) {
+                req.state().read().await.logout(&token);
+            }
+            let cookie = format!(
+                "{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax",
+                SESSION_COOKIE
+            );
+            Ok(HttpResponse::ok().with_cookie(&cookie))
+        }
+        _ => Err(req),
+    }
+}
+
 /// Maps the API methods
 async fn api(req: Request) -> RoutingResult {
     if !req.path().full().starts_with("/api/v1") {
         Err(req) // Not for us
     } else {
         // Make sure access is allowed
-        if !req.is_authorized().await {
-            return Ok(HttpResponse::forbidden());
-        }
+        let actor = match req.actor().await {
+            Some(actor) => actor,
+            None => return render_forbidden(),
+        };
 
         // Eat the first two segments of the path "api/v1"
         let mut path = req.path().clone();
@@ -494,9 +606,14 @@ async fn api(req: Request) -> RoutingResult {
 
         match path.next() {
             Some("authorized") => api_authorized(req),
-            Some("bulk") => api_bulk(req, &mut path).await,
-            Some("cas") => api_cas(req, &mut path).await,
-            Some("publishers") => api_publishers(req, &mut path).await,
+            Some("backup") => api_backup(req, &actor).await,
+            Some("bulk") => api_bulk(req, &mut path, &actor).await,
+            Some("cas") => api_cas(req, &mut path, &actor).await,
+            Some("events") => api_events(req, &actor).await,
+            Some("integrity") => api_integrity(req, &mut path, &actor).await,
+            Some("publishers") => api_publishers(req, &mut path, &actor).await,
+            Some("rta") => api_rta(req, &mut path, &actor).await,
+            Some("tasks") => api_tasks(req, &actor).await,
             _ => render_unknown_method(),
         }
     }
@@ -509,7 +626,109 @@ fn api_authorized(req: Request) -> RoutingResult {
     }
 }
 
-async fn api_bulk(req: Request, path: &mut RequestPath) -> RoutingResult {
+/// Returns recent CA and repository events, which the actor may see. If
+/// 'since' is given, only later events are returned, and if there are none
+/// yet this waits for a while for new events.
+async fn api_events(req: Request, actor: &Actor) -> RoutingResult {
+    if *req.method() != Method::GET {
+        return render_unknown_method();
+    }
+
+    let since = match req.query_param("since") {
+        Some(since) => match u64::from_str(&since) {
+            Ok(since) => Some(since),
+            Err(_) => return render_error(Error::ApiInvalidEventId),
+        },
+        None => None,
+    };
+
+    let visible = |notification: &EventNotification| match notification.source() {
+        EventSource::Ca => actor.is_allowed(Permission::CaRead, Some(notification.handle())),
+        EventSource::Repository => actor.is_allowed(Permission::PubRead, None),
+    };
+
+    // Do not keep the server locked while waiting for events.
+    let notifier = req.state().read().await.event_notifier();
+
+    let mut events = notifier.events_since(since, visible);
+    if since.is_some() {
+        let mut waited = 0;
+        while events.is_empty() && waited < EVENTS_LONG_POLL_SECS {
+            tokio::time::delay_for(std::time::Duration::from_secs(1)).await;
+            waited += 1;
+            events = notifier.events_since(since, visible);
+        }
+    }
+
+    render_json(events)
+}
+
+/// Returns the status of the background tasks.
+async fn api_tasks(req: Request, actor: &Actor) -> RoutingResult {
+    if !actor.is_allowed(Permission::CaRead, None) {
+        return render_forbidden();
+    }
+
+    match *req.method() {
+        Method::GET => render_json(req.state().read().await.tasks()),
+        _ => render_unknown_method(),
+    }
+}
+
+/// Verifies the stored history of CAs and the repository, and repairs it on
+/// request. This needs full read, or admin, access to both.
+async fn api_integrity(req: Request, path: &mut RequestPath, actor: &Actor) -> RoutingResult {
+    let (permissions, repair) = match (req.method(), path.next()) {
+        (&Method::GET, None) => ([Permission::CaRead, Permission::PubRead], false),
+        (&Method::POST, Some("repair")) => ([Permission::CaAdmin, Permission::PubAdmin], true),
+        _ => return render_unknown_method(),
+    };
+    if !permissions.iter().all(|p| actor.is_allowed(*p, None)) {
+        return render_forbidden();
+    }
+
+    if repair {
+        render_json_res(req.state().write().await.verify_integrity(true))
+    } else {
+        render_json_res(req.state().read().await.verify_integrity(false))
+    }
+}
+
+/// Returns a backup of the complete server state. This needs admin access
+/// to both CAs and the repository.
+async fn api_backup(req: Request, actor: &Actor) -> RoutingResult {
+    if *req.method() != Method::POST {
+        return render_unknown_method();
+    }
+    let permissions = [Permission::CaAdmin, Permission::PubAdmin];
+    if !permissions.iter().all(|p| actor.is_allowed(*p, None)) {
+        return render_forbidden();
+    }
+
+    let state = req.state().clone();
+    match req.json::<BackupRequest>().await {
+        Err(e) => render_error(e),
+        Ok(backup) => match state
+            .read()
+            .await
+            .backup(backup.passphrase().map(str::to_string))
+            .await
+        {
+            Ok(bytes) => Ok(HttpResponse::gzip(bytes.to_vec())),
+            Err(e) => render_error(e),
+        },
+    }
+}
+
+async fn api_bulk(req: Request, path: &mut RequestPath, actor: &Actor) -> RoutingResult {
+    let permission = match *req.method() {
+        Method::GET => Permission::CaRead,
+        _ => Permission::CaAdmin,
+    };
+    if !actor.is_allowed(permission, None) {
+        return render_forbidden();
+    }
+
     match path.full() {
         "/api/v1/bulk/cas/issues" => all_ca_issues(req).await,
         "/api/v1/bulk/cas/sync/parent" => refresh_all(req).await,
@@ -519,89 +738,214 @@ async fn api_bulk(req: Request, path: &mut RequestPath) -> RoutingResult {
     }
 }
 
-async fn api_cas(req: Request, path: &mut RequestPath) -> RoutingResult {
+async fn api_cas(req: Request, path: &mut RequestPath, actor: &Actor) -> RoutingResult {
     match path.path_arg() {
-        Some(ca) => match path.next() {
-            None => ca_info(req, ca).await,
-            Some("child_request.xml") => ca_child_req_xml(req, ca).await,
-            Some("child_request.json") => ca_child_req_json(req, ca).await,
-            Some("children") => ca_children(req, path, ca).await,
-            Some("history") => ca_history(req, path, ca).await,
-            Some("command") => ca_command_details(req, path, ca).await,
-            Some("id") => ca_regenerate_id(req, ca).await,
-            Some("issues") => ca_issues(req, ca).await,
-            Some("keys") => ca_keys(req, path, ca).await,
-            Some("parents") => api_ca_parents(req, path, ca).await,
-            Some("parents-xml") => ca_add_parent_xml(req, path, ca).await,
-            Some("repo") => api_ca_repo(req, path, ca).await,
-            Some("routes") => api_ca_routes(req, path, ca).await,
-            _ => render_unknown_method(),
-        },
+        Some(ca) => {
+            let segment = path.next();
+
+            // Reading is allowed for all CA resources, updating ROAs and ASPAs
+            // requires less privileges than other updates.
+            let permission = match (req.method(), segment) {
+                (&Method::GET, _) => Permission::CaRead,
+                (_, Some("routes")) | (_, Some("aspas")) => Permission::CaRoutes,
+                _ => Permission::CaAdmin,
+            };
+            if !actor.is_allowed(permission, Some(&ca)) {
+                return render_forbidden();
+            }
+
+            match segment {
+                None => ca_info(req, ca).await,
+                Some("aspas") => api_ca_aspas(req, path, ca, actor).await,
+                Some("at") => ca_info_at(req, path, ca).await,
+                Some("bgpsec") => api_ca_bgpsec(req, path, ca, actor).await,
+                Some("child_request.xml") => ca_child_req_xml(req, ca).await,
+                Some("child_request.json") => ca_child_req_json(req, ca).await,
+                Some("children") => ca_children(req, path, ca, actor).await,
+                Some("history") => ca_history(req, path, ca).await,
+                Some("command") => ca_command_details(req, path, ca).await,
+                Some("id") => ca_regenerate_id(req, ca, actor).await,
+                Some("issues") => ca_issues(req, ca).await,
+                Some("keys") => ca_keys(req, path, ca, actor).await,
+                Some("parents") => api_ca_parents(req, path, ca, actor).await,
+                Some("parents-xml") => ca_add_parent_xml(req, path, ca, actor).await,
+                Some("repo") => api_ca_repo(req, path, ca, actor).await,
+                Some("routes") => api_ca_routes(req, path, ca, actor).await,
+                Some("rta") => api_ca_rta(req, path, ca, actor).await,
+                Some("signer") => ca_update_signer(req, ca, actor).await,
+                _ => render_unknown_method(),
+            }
+        }
         None => match *req.method() {
-            Method::GET => cas(req).await,
-            Method::POST => ca_init(req).await,
+            Method::GET => cas(req, actor).await,
+            Method::POST => {
+                if actor.is_allowed(Permission::CaAdmin, None) {
+                    ca_init(req).await
+                } else {
+                    render_forbidden()
+                }
+            }
             _ => render_unknown_method(),
         },
     }
 }
 
-async fn ca_keys(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn ca_keys(req: Request, path: &mut RequestPath, ca: Handle, actor: &Actor) -> RoutingResult {
     match *req.method() {
         Method::POST => match path.next() {
-            Some("roll_init") => ca_kr_init(req, ca).await,
-            Some("roll_activate") => ca_kr_activate(req, ca).await,
+            Some("roll_init") => ca_kr_init(req, ca, actor).await,
+            Some("roll_activate") => ca_kr_activate(req, ca, actor).await,
             _ => render_unknown_method(),
         },
         _ => render_unknown_method(),
     }
 }
 
-async fn api_ca_parents(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn api_ca_parents(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
     if let Some(parent) = path.path_arg() {
         match *req.method() {
             Method::GET => ca_my_parent_contact(req, ca, parent).await,
-            Method::POST => ca_update_parent(req, ca, parent).await,
-            Method::DELETE => ca_remove_parent(req, ca, parent).await,
+            Method::POST => ca_update_parent(req, ca, parent, actor).await,
+            Method::DELETE => ca_remove_parent(req, ca, parent, actor).await,
             _ => render_unknown_method(),
         }
     } else {
         match *req.method() {
-            Method::POST => ca_add_parent(req, ca).await,
+            Method::POST => ca_add_parent(req, ca, actor).await,
             _ => render_unknown_method(),
         }
     }
 }
 
-async fn api_ca_repo(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn api_ca_repo(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
     match path.next() {
         None => match *req.method() {
             Method::GET => ca_repo_details(req, ca).await,
-            Method::POST => ca_repo_update(req, ca).await,
+            Method::POST => ca_repo_update(req, ca, actor).await,
             _ => render_unknown_method(),
         },
         Some("request.json") => ca_publisher_req_json(req, ca).await,
         Some("request.xml") => ca_publisher_req_xml(req, ca).await,
         Some("state") => ca_repo_state(req, ca).await,
+        Some("migration") => ca_repo_migration(req, path, ca, actor).await,
         _ => render_unknown_method(),
     }
 }
 
-async fn api_ca_routes(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn api_ca_routes(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
     match path.next() {
         None => match *req.method() {
             Method::GET => ca_routes_show(req, ca).await,
-            Method::POST => ca_routes_update(req, ca).await,
+            Method::POST => ca_routes_update(req, ca, actor).await,
             _ => render_unknown_method(),
         },
         Some("analysis") => match *req.method() {
             Method::GET => ca_routes_analysis(req, path, ca).await,
+            Method::POST => ca_routes_analysis_dry_run(req, path, ca).await,
+            _ => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_ca_aspas(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    match path.next() {
+        None => match *req.method() {
+            Method::GET => ca_aspas_definitions_show(req, ca).await,
+            Method::POST => ca_aspas_definitions_update(req, ca, actor).await,
+            _ => render_unknown_method(),
+        },
+        Some("as") => match path.path_arg() {
+            Some(customer) => match *req.method() {
+                Method::POST => ca_aspas_update_aspa(req, ca, customer, actor).await,
+                _ => render_unknown_method(),
+            },
+            None => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_ca_bgpsec(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    match path.next() {
+        None => match *req.method() {
+            Method::GET => ca_bgpsec_definitions_show(req, ca).await,
+            Method::POST => ca_bgpsec_definitions_update(req, ca, actor).await,
+            _ => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_ca_rta(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    match path.path_arg::<RtaName>() {
+        None => match *req.method() {
+            Method::GET => ca_rta_list(req, ca).await,
             _ => render_unknown_method(),
         },
+        Some(name) => match (req.method().clone(), path.next()) {
+            (Method::GET, None) => ca_rta_show(req, ca, name).await,
+            (Method::POST, Some("sign")) => ca_rta_sign(req, ca, name, actor).await,
+            (Method::POST, Some("multi")) => match path.next() {
+                Some("prep") => ca_rta_multi_prep(req, ca, name, actor).await,
+                Some("cosign") => ca_rta_multi_cosign(req, ca, name, actor).await,
+                _ => render_unknown_method(),
+            },
+            _ => render_unknown_method(),
+        },
+    }
+}
+
+async fn api_rta(req: Request, path: &mut RequestPath, actor: &Actor) -> RoutingResult {
+    if !actor.is_allowed(Permission::CaRead, None) {
+        return render_forbidden();
+    }
+
+    match (req.method().clone(), path.next()) {
+        (Method::POST, Some("validate")) => rta_validate(req).await,
         _ => render_unknown_method(),
     }
 }
 
-async fn api_publishers(req: Request, path: &mut RequestPath) -> RoutingResult {
+async fn api_publishers(req: Request, path: &mut RequestPath, actor: &Actor) -> RoutingResult {
+    let permission = match *req.method() {
+        Method::GET => Permission::PubRead,
+        _ => Permission::PubAdmin,
+    };
+    if !actor.is_allowed(permission, None) {
+        return render_forbidden();
+    }
+
     match *req.method() {
         Method::GET => match path.path_arg() {
             Some(publisher) => match path.next() {
@@ -695,26 +1039,41 @@ async fn repository_response(
     req.state().read().await.repository_response(publisher)
 }
 
-async fn ca_add_child(req: Request, parent: ParentHandle) -> RoutingResult {
+async fn ca_add_child(req: Request, parent: ParentHandle, actor: &Actor) -> RoutingResult {
     let server = req.state().clone();
     match req.json().await {
-        Ok(child_req) => render_json_res(server.read().await.ca_add_child(&parent, child_req)),
+        Ok(child_req) => {
+            render_json_res(server.read().await.ca_add_child(&parent, child_req, actor))
+        }
         Err(e) => render_error(e),
     }
 }
 
-async fn ca_child_update(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
+async fn ca_child_update(
+    req: Request,
+    ca: Handle,
+    child: ChildHandle,
+    actor: &Actor,
+) -> RoutingResult {
     let server = req.state().clone();
     match req.json().await {
-        Ok(child_req) => {
-            render_empty_res(server.read().await.ca_child_update(&ca, child, child_req))
-        }
+        Ok(child_req) => render_empty_res(
+            server
+                .read()
+                .await
+                .ca_child_update(&ca, child, child_req, actor),
+        ),
         Err(e) => render_error(e),
     }
 }
 
-async fn ca_child_remove(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_child_remove(&ca, child))
+async fn ca_child_remove(
+    req: Request,
+    ca: Handle,
+    child: ChildHandle,
+    actor: &Actor,
+) -> RoutingResult {
+    render_empty_res(req.state().read().await.ca_child_remove(&ca, child, actor))
 }
 
 async fn ca_child_show(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
@@ -768,8 +1127,16 @@ async fn ca_issues(req: Request, ca: Handle) -> RoutingResult {
     }
 }
 
-async fn cas(req: Request) -> RoutingResult {
-    render_json(req.state().read().await.cas())
+/// Returns the CAs which the actor can see.
+async fn cas(req: Request, actor: &Actor) -> RoutingResult {
+    let cas = req.state().read().await.cas();
+    let visible = cas
+        .cas()
+        .iter()
+        .filter(|ca| actor.can_see_ca(ca.handle()))
+        .cloned()
+        .collect();
+    render_json(CertAuthList::new(visible))
 }
 
 pub async fn ca_init(req: Request) -> RoutingResult {
@@ -781,9 +1148,25 @@ pub async fn ca_init(req: Request) -> RoutingResult {
     }
 }
 
-async fn ca_regenerate_id(req: Request, handle: Handle) -> RoutingResult {
+async fn ca_regenerate_id(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
     match *req.method() {
-        Method::POST => render_empty_res(req.state().read().await.ca_update_id(handle)),
+        Method::POST => render_empty_res(req.state().read().await.ca_update_id(handle, actor)),
+        _ => render_unknown_method(),
+    }
+}
+
+/// Sets the signer preferred by the CA, and rolls its keys to that signer.
+async fn ca_update_signer(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
+    match *req.method() {
+        Method::POST => {
+            let state = req.state().clone();
+            match req.json().await {
+                Ok(update) => {
+                    render_empty_res(state.read().await.ca_update_signer(handle, update, actor))
+                }
+                Err(e) => render_error(e),
+            }
+        }
         _ => render_unknown_method(),
     }
 }
@@ -795,17 +1178,33 @@ async fn ca_info(req: Request, handle: Handle) -> RoutingResult {
     }
 }
 
+async fn ca_info_at(req: Request, path: &mut RequestPath, handle: Handle) -> RoutingResult {
+    // /api/v1/cas/{ca}/at/<version|RFC 3339 date/time>
+    match path.path_arg::<HistoryPoint>() {
+        Some(point) => match *req.method() {
+            Method::GET => render_json_res(req.state().read().await.ca_info_at(&handle, point)),
+            _ => render_unknown_method(),
+        },
+        None => render_unknown_resource(),
+    }
+}
+
 async fn ca_my_parent_contact(req: Request, ca: Handle, parent: ParentHandle) -> RoutingResult {
     render_json_res(req.state().read().await.ca_my_parent_contact(&ca, &parent))
 }
 
-async fn ca_children(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn ca_children(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
     match path.path_arg() {
         Some(child) => match path.next() {
             None => match *req.method() {
                 Method::GET => ca_child_show(req, ca, child).await,
-                Method::POST => ca_child_update(req, ca, child).await,
-                Method::DELETE => ca_child_remove(req, ca, child).await,
+                Method::POST => ca_child_update(req, ca, child, actor).await,
+                Method::DELETE => ca_child_remove(req, ca, child, actor).await,
                 _ => render_unknown_method(),
             },
             Some("contact") => ca_parent_contact(req, ca, child).await,
@@ -814,7 +1213,7 @@ async fn ca_children(req: Request, path: &mut RequestPath, ca: Handle) -> Routin
             _ => render_unknown_method(),
         },
         None => match *req.method() {
-            Method::POST => ca_add_child(req, ca).await,
+            Method::POST => ca_add_child(req, ca, actor).await,
             _ => render_unknown_method(),
         },
     }
@@ -940,6 +1339,26 @@ async fn ca_repo_state(req: Request, handle: Handle) -> RoutingResult {
     }
 }
 
+async fn ca_repo_migration(
+    req: Request,
+    path: &mut RequestPath,
+    handle: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    match (req.method().clone(), path.next()) {
+        (Method::POST, Some("resume")) => {
+            render_empty_res(req.state().read().await.ca_repo_migration_resume(&handle))
+        }
+        (Method::POST, Some("abort")) => render_empty_res(
+            req.state()
+                .read()
+                .await
+                .ca_repo_migration_abort(&handle, actor),
+        ),
+        _ => render_unknown_method(),
+    }
+}
+
 fn extract_repository_update(handle: &Handle, bytes: Bytes) -> Result<RepositoryUpdate, Error> {
     let string = String::from_utf8(bytes.to_vec()).map_err(Error::custom)?;
 
@@ -957,7 +1376,7 @@ fn extract_repository_update(handle: &Handle, bytes: Bytes) -> Result<Repository
     }
 }
 
-pub async fn ca_repo_update(req: Request, handle: Handle) -> RoutingResult {
+pub async fn ca_repo_update(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
     let server = req.state().clone();
 
     match req
@@ -965,14 +1384,18 @@ pub async fn ca_repo_update(req: Request, handle: Handle) -> RoutingResult {
         .await
         .map(|bytes| extract_repository_update(&handle, bytes))
     {
-        Ok(Ok(update)) => {
-            render_empty_res(server.read().await.ca_update_repo(handle, update).await)
-        }
+        Ok(Ok(update)) => render_empty_res(
+            server
+                .read()
+                .await
+                .ca_update_repo(handle, update, actor)
+                .await,
+        ),
         Ok(Err(e)) | Err(e) => render_error(e),
     }
 }
 
-async fn ca_add_parent(req: Request, ca: Handle) -> RoutingResult {
+async fn ca_add_parent(req: Request, ca: Handle, actor: &Actor) -> RoutingResult {
     let server = req.state().clone();
 
     let parent_req = match req.json().await {
@@ -980,13 +1403,18 @@ async fn ca_add_parent(req: Request, ca: Handle) -> RoutingResult {
         Err(e) => return render_error(e),
     };
 
-    match ca_parent_add(server, ca, parent_req).await {
+    match ca_parent_add(server, ca, parent_req, actor).await {
         Ok(()) => render_ok(),
         Err(e) => render_error(e),
     }
 }
 
-async fn ca_add_parent_xml(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+async fn ca_add_parent_xml(
+    req: Request,
+    path: &mut RequestPath,
+    ca: Handle,
+    actor: &Actor,
+) -> RoutingResult {
     let server = req.state().clone();
 
     let parent = match path.path_arg() {
@@ -1019,15 +1447,24 @@ async fn ca_add_parent_xml(req: Request, path: &mut RequestPath, ca: Handle) ->
     };
 
     {
-        match ca_parent_add(server, ca, parent_req).await {
+        match ca_parent_add(server, ca, parent_req, actor).await {
             Ok(()) => render_ok(),
             Err(e) => render_error(e),
         }
     }
 }
 
-async fn ca_parent_add(server: State, ca: Handle, parent_req: ParentCaReq) -> Result<(), Error> {
-    server.read().await.ca_parent_add(ca, parent_req).await
+async fn ca_parent_add(
+    server: State,
+    ca: Handle,
+    parent_req: ParentCaReq,
+    actor: &Actor,
+) -> Result<(), Error> {
+    server
+        .read()
+        .await
+        .ca_parent_add(ca, parent_req, actor)
+        .await
 }
 
 fn extract_parent_ca_contact(ca: &Handle, bytes: Bytes) -> Result<ParentCaContact, Error> {
@@ -1047,7 +1484,12 @@ fn extract_parent_ca_contact(ca: &Handle, bytes: Bytes) -> Result<ParentCaContac
     }
 }
 
-async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> RoutingResult {
+async fn ca_update_parent(
+    req: Request,
+    ca: Handle,
+    parent: ParentHandle,
+    actor: &Actor,
+) -> RoutingResult {
     let server = req.state().clone();
 
     let bytes = match req.api_bytes().await {
@@ -1060,7 +1502,7 @@ async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> Rou
             let res = server
                 .read()
                 .await
-                .ca_parent_update(ca, parent, contact)
+                .ca_parent_update(ca, parent, contact, actor)
                 .await;
             render_empty_res(res)
         }
@@ -1068,27 +1510,38 @@ async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> Rou
     }
 }
 
-async fn ca_remove_parent(req: Request, ca: Handle, parent: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_parent_remove(ca, parent))
+async fn ca_remove_parent(
+    req: Request,
+    ca: Handle,
+    parent: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    render_empty_res(req.state().read().await.ca_parent_remove(ca, parent, actor))
 }
 
 /// Force a key roll for a CA, i.e. use a max key age of 0 seconds.
-async fn ca_kr_init(req: Request, handle: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_keyroll_init(handle))
+async fn ca_kr_init(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
+    render_empty_res(req.state().read().await.ca_keyroll_init(handle, actor))
 }
 
 /// Force key activation for all new keys, i.e. use a staging period of 0 seconds.
-async fn ca_kr_activate(req: Request, handle: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_keyroll_activate(handle))
+async fn ca_kr_activate(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
+    render_empty_res(req.state().read().await.ca_keyroll_activate(handle, actor))
 }
 
 /// Update the route authorizations for this CA
-async fn ca_routes_update(req: Request, handle: Handle) -> RoutingResult {
+async fn ca_routes_update(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
     let state = req.state().clone();
 
     match req.json().await {
         Err(e) => render_error(e),
-        Ok(updates) => render_empty_res(state.read().await.ca_routes_update(handle, updates)),
+        Ok(updates) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_routes_update(handle, updates, actor)
+                .await,
+        ),
     }
 }
 
@@ -1104,10 +1557,187 @@ async fn ca_routes_show(req: Request, handle: Handle) -> RoutingResult {
 async fn ca_routes_analysis(req: Request, path: &mut RequestPath, handle: Handle) -> RoutingResult {
     match path.next() {
         Some("full") => render_json_res(req.state().read().await.ca_routes_bgp_analysis(&handle)),
+        Some("suggest") => render_json_res(req.state().read().await.ca_routes_bgp_suggest(&handle)),
+        Some("timeline") => {
+            render_json_res(req.state().read().await.ca_routes_bgp_timeline(&handle))
+        }
         _ => render_unknown_method(),
     }
 }
 
+/// Show the ROAs and the state of ROAs vs BGP, if updates were applied
+async fn ca_routes_analysis_dry_run(
+    req: Request,
+    path: &mut RequestPath,
+    handle: Handle,
+) -> RoutingResult {
+    match path.next() {
+        Some("dryrun") => {
+            let state = req.state().clone();
+            match req.json().await {
+                Err(e) => render_error(e),
+                Ok(updates) => {
+                    render_json_res(state.read().await.ca_routes_bgp_dry_run(&handle, updates))
+                }
+            }
+        }
+        _ => render_unknown_method(),
+    }
+}
+
+/// Show the ASPA definitions for this CA
+async fn ca_aspas_definitions_show(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_aspas_definitions_show(&handle) {
+        Ok(definitions) => render_json(definitions),
+        Err(_) => render_unknown_resource(),
+    }
+}
+
+/// Add, replace and/or remove ASPA definitions for this CA
+async fn ca_aspas_definitions_update(req: Request, handle: Handle, actor: &Actor) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(updates) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_aspas_definitions_update(handle, updates, actor),
+        ),
+    }
+}
+
+/// Update the providers of an existing ASPA definition for this CA
+async fn ca_aspas_update_aspa(
+    req: Request,
+    handle: Handle,
+    customer: AsNumber,
+    actor: &Actor,
+) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(update) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_aspas_update_aspa(handle, customer, update, actor),
+        ),
+    }
+}
+
+/// Show the BGPsec router keys for this CA
+async fn ca_bgpsec_definitions_show(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_bgpsec_definitions_show(&handle) {
+        Ok(definitions) => render_json(definitions),
+        Err(_) => render_unknown_resource(),
+    }
+}
+
+/// Add and/or remove BGPsec router keys for this CA
+async fn ca_bgpsec_definitions_update(
+    req: Request,
+    handle: Handle,
+    actor: &Actor,
+) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(updates) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_bgpsec_definitions_update(handle, updates, actor),
+        ),
+    }
+}
+
+/// List the RTAs of this CA
+async fn ca_rta_list(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_rta_list(&handle) {
+        Ok(list) => render_json(list),
+        Err(e) => render_error(e),
+    }
+}
+
+/// Show a signed RTA
+async fn ca_rta_show(req: Request, handle: Handle, name: RtaName) -> RoutingResult {
+    match req.state().read().await.ca_rta_show(&handle, &name) {
+        Ok(rta) => render_json(rta),
+        Err(e) => render_error(e),
+    }
+}
+
+/// Sign an RTA
+async fn ca_rta_sign(req: Request, handle: Handle, name: RtaName, actor: &Actor) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(request) => {
+            render_empty_res(state.read().await.ca_rta_sign(handle, name, request, actor))
+        }
+    }
+}
+
+/// Prepare keys for co-signing a multi-signer RTA
+async fn ca_rta_multi_prep(
+    req: Request,
+    handle: Handle,
+    name: RtaName,
+    actor: &Actor,
+) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(request) => match state
+            .read()
+            .await
+            .ca_rta_multi_prep(&handle, name, request, actor)
+        {
+            Ok(response) => render_json(response),
+            Err(e) => render_error(e),
+        },
+    }
+}
+
+/// Co-sign a multi-signer RTA
+async fn ca_rta_multi_cosign(
+    req: Request,
+    handle: Handle,
+    name: RtaName,
+    actor: &Actor,
+) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(rta) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_rta_multi_cosign(handle, name, rta, actor),
+        ),
+    }
+}
+
+/// Validate an RTA
+async fn rta_validate(req: Request) -> RoutingResult {
+    let state = req.state().clone();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(request) => match state.read().await.rta_validate(request).await {
+            Ok(report) => render_json(report),
+            Err(e) => render_error(e),
+        },
+    }
+}
+
 //------------ Admin: Force republish ----------------------------------------
 
 async fn republish_all(req: Request) -> RoutingResult {
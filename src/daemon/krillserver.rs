@@ -7,29 +7,40 @@ use bytes::Bytes;
 use chrono::Duration;
 
 use rpki::cert::Cert;
+use rpki::crypto::KeyIdentifier;
+use rpki::tal::{Tal, TalUri};
 use rpki::uri;
 use rpki::x509::Time;
 
 use crate::commons::api::{
-    AddChildRequest, AllCertAuthIssues, CaCommandDetails, CaRepoDetails, CertAuthInfo,
-    CertAuthInit, CertAuthIssues, CertAuthList, CertAuthStats, ChildCaInfo, ChildHandle,
-    CommandHistory, CommandHistoryCriteria, CurrentRepoState, Handle, ListReply, ParentCaContact,
-    ParentCaReq, ParentHandle, PublishDelta, PublisherDetails, PublisherHandle, RepoInfo,
-    RepositoryContact, RepositoryUpdate, RoaDefinition, RoaDefinitionUpdates, ServerInfo,
-    TaCertDetails, UpdateChildRequest,
+    AddChildRequest, AllCertAuthIssues, AsNumber, AspaDefinitionList, AspaDefinitionUpdates,
+    AspaProvidersUpdate, BgpSecCsrInfoList, BgpSecDefinitionUpdates, CaCommandDetails,
+    CaRepoDetails, CaRepoState, CertAuthInfo, CertAuthInfoAt, CertAuthInit, CertAuthIssues,
+    CertAuthList, CertAuthSignerUpdate, CertAuthStats, ChildCaInfo, ChildHandle, CommandHistory,
+    CommandHistoryCriteria, CurrentRepoState, Handle, HistoryPoint, IntegrityReport, ListReply,
+    ParentCaContact, ParentCaReq, ParentHandle, PublishDelta, PublisherDetails, PublisherHandle,
+    RepoInfo, RepositoryContact, RepositoryUpdate, ResourceTaggedAttestation, RoaConfiguration,
+    RoaDefinitionUpdates, RtaContentRequest, RtaList, RtaName, RtaPrepResponse, RtaPrepareRequest,
+    RtaValidationReport, RtaValidationRequest, ServerInfo, TaCertDetails, TaskList, Token,
+    UpdateChildRequest,
 };
-use crate::commons::bgp::{BgpAnalyser, BgpAnalysisReport};
+use crate::commons::bgp::{BgpAnalyser, BgpAnalysisReport, BgpStatsTimeline, RoaDryRunReport};
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::CommandKey;
+use crate::commons::eventsourcing::{Aggregate, CommandKey};
 use crate::commons::remote::rfc8183;
-use crate::commons::util::softsigner::OpenSslSigner;
+use crate::commons::util::krillsigner::KrillSigner;
+use crate::commons::util::{file, httpclient};
 use crate::commons::{KrillEmptyResult, KrillResult};
 use crate::constants::*;
-use crate::daemon::auth::{Auth, Authorizer};
+use crate::daemon::alerts::Alerter;
+use crate::daemon::auth::openid_connect::OpenIdConnect;
+use crate::daemon::auth::{Actor, Auth, Authorizer};
+use crate::daemon::backup::{self, BackupFile, KrillBackup};
 use crate::daemon::ca::{self, ta_handle};
 use crate::daemon::config::Config;
-use crate::daemon::mq::EventQueueListener;
+use crate::daemon::mq::{EventQueueListener, EventQueueStats};
 use crate::daemon::scheduler::Scheduler;
+use crate::daemon::webhooks::EventNotifier;
 use crate::pubd::{PubServer, RepoStats};
 use crate::publish::CaPublisher;
 
@@ -51,13 +62,21 @@ pub struct KrillServer {
     pubserver: Option<Arc<PubServer>>,
 
     // Handles the internal TA and/or CAs
-    caserver: Arc<ca::CaServer<OpenSslSigner>>,
+    caserver: Arc<ca::CaServer<KrillSigner>>,
 
     // Handles the internal TA and/or CAs
     bgp_analyser: Arc<BgpAnalyser>,
 
+    // Sends alerts for announcements made invalid by ROAs
+    alerter: Arc<Alerter>,
+
+    // Notifies external systems about CA and repository events
+    event_notifier: Arc<EventNotifier>,
+
+    // Queue of tasks triggered by CA events
+    event_queue: Arc<EventQueueListener>,
+
     // Responsible for background tasks, e.g. re-publishing
-    #[allow(dead_code)] // just need to keep this in scope
     scheduler: Scheduler,
 
     // Time this server was started
@@ -65,6 +84,12 @@ pub struct KrillServer {
 
     // Global size constraints on things which can be posted
     post_limits: PostLimits,
+
+    // TALs used for validating RTAs
+    rta_tals: Vec<PathBuf>,
+
+    // The file the config was read from, if any, included in backups
+    config_file: Option<PathBuf>,
 }
 
 pub struct PostLimits {
@@ -111,10 +136,24 @@ impl KrillServer {
         let mut repo_dir = work_dir.clone();
         repo_dir.push("repo");
 
-        let signer = OpenSslSigner::build(work_dir)?;
+        let mut signer = KrillSigner::build(&config.signer, work_dir)?;
+        for named in config.signers.iter() {
+            signer.add_signer(named, work_dir)?;
+        }
+        signer.set_key_placement(
+            config.id_key_signer.as_deref(),
+            config.ca_key_signer.as_deref(),
+        )?;
         let signer = Arc::new(RwLock::new(signer));
 
-        let authorizer = Authorizer::new(token);
+        let mut authorizer = Authorizer::new(token, &config.api_tokens);
+        if let Some(oidc_config) = &config.openid_connect {
+            let redirect_uri = format!("{}auth/callback", service_uri);
+            let oidc = OpenIdConnect::new(oidc_config.clone(), redirect_uri);
+            authorizer = authorizer.with_openid_connect(oidc);
+        }
+
+        let event_notifier = Arc::new(EventNotifier::new(work_dir, config.webhooks.clone()));
 
         let pubserver = {
             if config.repo_enabled {
@@ -122,7 +161,9 @@ impl KrillServer {
                     &base_uri,
                     rrdp_base_uri.clone(),
                     work_dir,
+                    config.storage_type,
                     config.rfc8181_log_dir.as_ref(),
+                    event_notifier.clone(),
                     signer.clone(),
                 )?)
             } else {
@@ -130,19 +171,24 @@ impl KrillServer {
                     &base_uri,
                     rrdp_base_uri.clone(),
                     work_dir,
+                    config.storage_type,
                     config.rfc8181_log_dir.as_ref(),
+                    event_notifier.clone(),
                     signer.clone(),
                 )?
             }
         };
         let pubserver: Option<Arc<PubServer>> = pubserver.map(Arc::new);
 
-        let event_queue = Arc::new(EventQueueListener::in_mem());
+        let event_queue = Arc::new(EventQueueListener::disk(work_dir)?);
         let caserver = Arc::new(ca::CaServer::build(
             work_dir,
+            config.storage_type,
             config.rfc8181_log_dir.as_ref(),
             config.rfc6492_log_dir.as_ref(),
             event_queue.clone(),
+            event_notifier.clone(),
+            config.roa_aggregation(),
             signer,
         )?);
 
@@ -177,18 +223,25 @@ impl KrillServer {
             }
         }
 
-        let bgp_analyser = Arc::new(BgpAnalyser::new(
-            config.bgp_risdumps_enabled,
-            &config.bgp_risdumps_v4_uri,
-            &config.bgp_risdumps_v6_uri,
-        ));
+        let bgp_analyser = BgpAnalyser::new(
+            work_dir,
+            &config.bgp_sources(),
+            config.bgp_risdumps_visibility_threshold,
+        )
+        .map_err(Error::custom)?;
+        let bgp_analyser = Arc::new(bgp_analyser);
+
+        let alerter = Arc::new(Alerter::new(work_dir, config.alert_sinks.clone()));
 
         let scheduler = Scheduler::build(
-            event_queue,
+            event_queue.clone(),
             caserver.clone(),
             pubserver.clone(),
             bgp_analyser.clone(),
+            alerter.clone(),
+            event_notifier.clone(),
             ca_refresh_rate,
+            config.history_archive.clone(),
         );
 
         let post_limits = PostLimits::new(
@@ -204,9 +257,14 @@ impl KrillServer {
             pubserver,
             caserver,
             bgp_analyser,
+            alerter,
+            event_notifier,
+            event_queue,
             scheduler,
             started: Time::now(),
             post_limits,
+            rta_tals: config.rta_tals.clone(),
+            config_file: config.config_file().cloned(),
         })
     }
 
@@ -217,12 +275,104 @@ impl KrillServer {
     pub fn server_info(&self) -> ServerInfo {
         ServerInfo::new(KRILL_VERSION, self.started)
     }
+
+    pub fn event_notifier(&self) -> Arc<EventNotifier> {
+        self.event_notifier.clone()
+    }
+
+    pub fn event_queue_stats(&self) -> EventQueueStats {
+        self.event_queue.stats()
+    }
+
+    pub fn tasks(&self) -> TaskList {
+        self.scheduler.tasks()
+    }
+
+    /// Verifies the stored history of all CAs, and of the embedded
+    /// repository if it is enabled. If 'repair' is true, snapshots and
+    /// info are rebuilt from the events where this is safe.
+    pub fn verify_integrity(&self, repair: bool) -> KrillResult<IntegrityReport> {
+        let mut stores = vec![self.caserver.verify_integrity(repair)?];
+        if let Some(pubserver) = &self.pubserver {
+            stores.push(pubserver.verify_integrity(repair)?);
+        }
+        Ok(IntegrityReport::new(stores))
+    }
+
+    /// Returns a gzipped backup of all CAs, the repository, the openssl
+    /// signer keys, the repository files and the config. Everything is
+    /// copied while no CA and repository commands are processed, so that
+    /// the keys match the CAs and the repository files match the repository,
+    /// and the keys are encrypted if a passphrase is given.
+    ///
+    /// The backup is built on the blocking thread pool, as it reads and
+    /// compresses all stored files.
+    pub async fn backup(&self, passphrase: Option<String>) -> KrillResult<Bytes> {
+        let work_dir = self.work_dir.clone();
+        let caserver = self.caserver.clone();
+        let pubserver = self.pubserver.clone();
+        let config_file = self.config_file.clone();
+
+        tokio::task::spawn_blocking(move || {
+            // Take the CA lock first, then the repository lock, and hold
+            // both until all files are copied.
+            let (cas, (keys, pubd)) = caserver.backup(|| {
+                let keys = BackupFile::read_dirs(&work_dir, backup::KEY_DIRS);
+                let pubd = pubserver.as_ref().map(|pubserver| {
+                    pubserver.backup(|| BackupFile::read_dirs(&work_dir, backup::FILE_DIRS))
+                });
+                (keys, pubd)
+            })?;
+            let mut stores = vec![cas];
+
+            let mut files = vec![];
+            if let Some(pubd) = pubd {
+                let (pubd, repo_files) = pubd?;
+                stores.push(pubd);
+                files = repo_files?;
+            }
+
+            let config = match &config_file {
+                Some(path) => {
+                    Some(file::read(path).map(|c| String::from_utf8_lossy(&c).to_string())?)
+                }
+                None => None,
+            };
+
+            let backup = KrillBackup::new(
+                &work_dir,
+                stores,
+                keys?,
+                files,
+                config,
+                passphrase.as_deref(),
+            )?;
+            Ok(Bytes::from(backup.encode()?))
+        })
+        .await
+        .map_err(Error::custom)?
+    }
 }
 
 /// # Authentication and Access
 impl KrillServer {
-    pub fn is_api_allowed(&self, auth: &Auth) -> bool {
-        self.authorizer.is_api_allowed(auth)
+    pub fn actor(&self, auth: &Auth) -> Option<Actor> {
+        self.authorizer.actor(auth)
+    }
+
+    /// Returns the URI where users can log in through OpenID Connect.
+    pub async fn login_uri(&self) -> KrillResult<String> {
+        self.authorizer.login_uri().await
+    }
+
+    /// Completes an OpenID Connect login, returns a session token and the
+    /// number of seconds that it is valid.
+    pub async fn login_callback(&self, code: &str, state: &str) -> KrillResult<(Token, i64)> {
+        self.authorizer.login_callback(code, state).await
+    }
+
+    pub fn logout(&self, token: &Token) {
+        self.authorizer.logout(token)
     }
 
     pub fn limit_api(&self) -> u64 {
@@ -326,8 +476,11 @@ impl KrillServer {
         &self,
         parent: &ParentHandle,
         req: AddChildRequest,
+        actor: &Actor,
     ) -> KrillResult<ParentCaContact> {
-        let contact = self.caserver.ca_add_child(parent, req, &self.service_uri)?;
+        let contact = self
+            .caserver
+            .ca_add_child(parent, req, &self.service_uri, actor)?;
         Ok(contact)
     }
 
@@ -361,14 +514,20 @@ impl KrillServer {
         parent: &ParentHandle,
         child: ChildHandle,
         req: UpdateChildRequest,
+        actor: &Actor,
     ) -> KrillEmptyResult {
-        self.caserver.ca_child_update(parent, child, req)?;
+        self.caserver.ca_child_update(parent, child, req, actor)?;
         Ok(())
     }
 
     /// Update IdCert or resources of a child.
-    pub fn ca_child_remove(&self, handle: &Handle, child: ChildHandle) -> KrillEmptyResult {
-        self.caserver.ca_child_remove(handle, child)?;
+    pub fn ca_child_remove(
+        &self,
+        handle: &Handle,
+        child: ChildHandle,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        self.caserver.ca_child_remove(handle, child, actor)?;
         Ok(())
     }
 
@@ -392,13 +551,18 @@ impl KrillServer {
     }
 
     /// Adds a parent to a CA, will check first if the parent can be reached.
-    pub async fn ca_parent_add(&self, handle: Handle, parent: ParentCaReq) -> KrillEmptyResult {
+    pub async fn ca_parent_add(
+        &self,
+        handle: Handle,
+        parent: ParentCaReq,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
         self.ca_parent_reachable(&handle, parent.handle(), parent.contact())
             .await
             .map_err(|_| {
                 Error::CaParentAddNotResponsive(handle.clone(), parent.handle().clone())
             })?;
-        Ok(self.caserver.ca_parent_add(handle, parent)?)
+        Ok(self.caserver.ca_parent_add(handle, parent, actor)?)
     }
 
     /// Updates a parent contact for a CA
@@ -407,9 +571,12 @@ impl KrillServer {
         handle: Handle,
         parent: ParentHandle,
         contact: ParentCaContact,
+        actor: &Actor,
     ) -> KrillEmptyResult {
         self.ca_parent_reachable(&handle, &parent, &contact).await?;
-        Ok(self.caserver.ca_parent_update(handle, parent, contact)?)
+        Ok(self
+            .caserver
+            .ca_parent_update(handle, parent, contact, actor)?)
     }
 
     async fn ca_parent_reachable(
@@ -424,8 +591,13 @@ impl KrillServer {
         Ok(())
     }
 
-    pub fn ca_parent_remove(&self, handle: Handle, parent: ParentHandle) -> KrillEmptyResult {
-        Ok(self.caserver.ca_parent_remove(handle, parent)?)
+    pub fn ca_parent_remove(
+        &self,
+        handle: Handle,
+        parent: ParentHandle,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_parent_remove(handle, parent, actor)?)
     }
 }
 
@@ -470,8 +642,8 @@ impl KrillServer {
     pub async fn ca_issues(&self, ca_handle: &Handle) -> KrillResult<CertAuthIssues> {
         let mut issues = CertAuthIssues::default();
 
-        if let CurrentRepoState::Error(msg) = self.ca_repo_state(ca_handle).await? {
-            issues.add_repo_issue(msg);
+        if let CurrentRepoState::Error(msg) = self.ca_repo_state(ca_handle).await?.current() {
+            issues.add_repo_issue(msg.clone());
         }
 
         let ca = self.caserver.get_ca(ca_handle)?;
@@ -534,6 +706,18 @@ impl KrillServer {
         self.caserver.get_ca(handle).map(|ca| ca.as_ca_info())
     }
 
+    /// Returns the CA info, ROAs and issued child certificates for a CA as
+    /// they were at the given point in its history.
+    pub fn ca_info_at(&self, handle: &Handle, point: HistoryPoint) -> KrillResult<CertAuthInfoAt> {
+        let ca = self.caserver.get_ca_at(handle, point)?;
+        Ok(CertAuthInfoAt::new(
+            ca.version(),
+            ca.as_ca_info(),
+            ca.roa_configurations(),
+            ca.child_issued_certs(),
+        ))
+    }
+
     /// Returns the parent contact for a CA and parent, or NONE if either the CA or the parent cannot be found.
     pub fn ca_my_parent_contact(
         &self,
@@ -586,11 +770,27 @@ impl KrillServer {
         Ok(CaRepoDetails::new(contact.clone()))
     }
 
-    /// Returns the state of the current configured repo for a ca
-    pub async fn ca_repo_state(&self, handle: &Handle) -> KrillResult<CurrentRepoState> {
+    /// Returns the state of the current configured repo for a ca, and of the
+    /// migration to it if that is still in progress.
+    pub async fn ca_repo_state(&self, handle: &Handle) -> KrillResult<CaRepoState> {
         let ca = self.caserver.get_ca(handle)?;
         let contact = ca.get_repository_contact()?;
-        Ok(self.repo_state(handle, contact.as_reponse_opt()).await)
+        let current = self.repo_state(handle, contact.as_reponse_opt()).await;
+        Ok(CaRepoState::new(current, ca.repo_migration_status()))
+    }
+
+    /// Resume a repository migration which got stuck, e.g. because the new
+    /// repository or a parent was unavailable for too long.
+    pub fn ca_repo_migration_resume(&self, handle: &Handle) -> KrillEmptyResult {
+        for event in self.caserver.repo_migration_resume(handle)? {
+            self.event_queue.push_back(event);
+        }
+        Ok(())
+    }
+
+    /// Abort a repository migration, by migrating back to the old repository.
+    pub fn ca_repo_migration_abort(&self, handle: &Handle, actor: &Actor) -> KrillEmptyResult {
+        Ok(self.caserver.repo_migration_abort(handle, actor)?)
     }
 
     /// Update the repository for a CA, or return an error. (see `CertAuth::repo_update`)
@@ -598,7 +798,14 @@ impl KrillServer {
         &self,
         handle: Handle,
         update: RepositoryUpdate,
+        actor: &Actor,
     ) -> KrillEmptyResult {
+        // Check this before adding the CA as a publisher to the embedded
+        // repository, the CA would refuse the update anyway.
+        if self.caserver.get_ca(&handle)?.has_old_repo() {
+            return Err(Error::CaRepoMigrationInProgress(handle));
+        }
+
         let contact = match update {
             RepositoryUpdate::Embedded => {
                 // Add to embedded publication server if not present
@@ -625,7 +832,7 @@ impl KrillServer {
             }
         };
 
-        Ok(self.caserver.update_repo(handle, contact)?)
+        Ok(self.caserver.update_repo(handle, contact, actor)?)
     }
 
     async fn repo_state(
@@ -648,20 +855,31 @@ impl KrillServer {
         }
     }
 
-    pub fn ca_update_id(&self, handle: Handle) -> KrillEmptyResult {
-        Ok(self.caserver.ca_update_id(handle)?)
+    pub fn ca_update_id(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
+        Ok(self.caserver.ca_update_id(handle, actor)?)
+    }
+
+    pub fn ca_update_signer(
+        &self,
+        handle: Handle,
+        update: CertAuthSignerUpdate,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self
+            .caserver
+            .ca_update_signer(handle, update.unpack(), actor)?)
     }
 
-    pub fn ca_keyroll_init(&self, handle: Handle) -> KrillEmptyResult {
+    pub fn ca_keyroll_init(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
         Ok(self
             .caserver
-            .ca_keyroll_init(handle, Duration::seconds(0))?)
+            .ca_keyroll_init(handle, Duration::seconds(0), actor)?)
     }
 
-    pub fn ca_keyroll_activate(&self, handle: Handle) -> KrillEmptyResult {
+    pub fn ca_keyroll_activate(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
         Ok(self
             .caserver
-            .ca_keyroll_activate(handle, Duration::seconds(0))?)
+            .ca_keyroll_activate(handle, Duration::seconds(0), actor)?)
     }
 
     pub fn rfc6492(&self, handle: Handle, msg_bytes: Bytes) -> KrillResult<Bytes> {
@@ -672,17 +890,34 @@ impl KrillServer {
 /// # Handle route authorization requests
 ///
 impl KrillServer {
-    pub fn ca_routes_update(
+    pub async fn ca_routes_update(
         &self,
         handle: Handle,
         updates: RoaDefinitionUpdates,
+        actor: &Actor,
     ) -> KrillEmptyResult {
-        Ok(self.caserver.ca_routes_update(handle, updates.into())?)
+        self.caserver
+            .ca_routes_update(handle.clone(), updates.into(), actor)?;
+
+        let ca = self.caserver.get_ca(&handle)?;
+        let roas = ca.roa_definitions();
+        let resources = ca.all_resources();
+        if let Err(e) = self
+            .bgp_analyser
+            .record_stats(&handle, roas.as_slice(), &resources)
+        {
+            warn!("Could not record BGP stats for CA '{}': {}", handle, e);
+        }
+
+        let report = self.bgp_analyser.analyse(roas.as_slice(), &resources);
+        self.alerter.check(&handle, &report).await;
+
+        Ok(())
     }
 
-    pub fn ca_routes_show(&self, handle: &Handle) -> KrillResult<Vec<RoaDefinition>> {
+    pub fn ca_routes_show(&self, handle: &Handle) -> KrillResult<Vec<RoaConfiguration>> {
         let ca = self.caserver.get_ca(handle)?;
-        Ok(ca.roa_definitions())
+        Ok(ca.roa_configurations())
     }
 
     pub fn ca_routes_bgp_analysis(&self, handle: &Handle) -> KrillResult<BgpAnalysisReport> {
@@ -693,6 +928,206 @@ impl KrillServer {
             .bgp_analyser
             .analyse(definitions.as_slice(), &resources))
     }
+
+    /// Shows how the BGP analysis stats for the CA changed over time.
+    pub fn ca_routes_bgp_timeline(&self, handle: &Handle) -> KrillResult<BgpStatsTimeline> {
+        self.caserver.get_ca(handle)?;
+        self.bgp_analyser.timeline(handle).map_err(Error::custom)
+    }
+
+    /// Suggests updates to the ROAs of the CA, based on the announcements
+    /// seen for its resources. The result can be posted as a ROA update.
+    pub fn ca_routes_bgp_suggest(&self, handle: &Handle) -> KrillResult<RoaDefinitionUpdates> {
+        let ca = self.caserver.get_ca(handle)?;
+        let configurations = ca.roa_configurations();
+        let resources = ca.all_resources();
+        Ok(self
+            .bgp_analyser
+            .suggest(configurations.as_slice(), &resources))
+    }
+
+    /// Shows the ROAs which would result from the updates, and the impact
+    /// they would have on known BGP announcements, without applying them.
+    pub fn ca_routes_bgp_dry_run(
+        &self,
+        handle: &Handle,
+        updates: RoaDefinitionUpdates,
+    ) -> KrillResult<RoaDryRunReport> {
+        let ca = self.caserver.get_ca(handle)?;
+        let current = ca.roa_definitions();
+        let proposed = ca.route_authorizations_dry_run(updates.into())?;
+        let resources = ca.all_resources();
+        Ok(self
+            .bgp_analyser
+            .dry_run(current.as_slice(), proposed.as_slice(), &resources))
+    }
+}
+
+/// # Handle ASPA requests
+///
+impl KrillServer {
+    pub fn ca_aspas_definitions_show(&self, handle: &Handle) -> KrillResult<AspaDefinitionList> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.aspa_definitions())
+    }
+
+    pub fn ca_aspas_definitions_update(
+        &self,
+        handle: Handle,
+        updates: AspaDefinitionUpdates,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self
+            .caserver
+            .ca_aspas_definitions_update(handle, updates, actor)?)
+    }
+
+    pub fn ca_aspas_update_aspa(
+        &self,
+        handle: Handle,
+        customer: AsNumber,
+        update: AspaProvidersUpdate,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self
+            .caserver
+            .ca_aspas_update_aspa(handle, customer, update, actor)?)
+    }
+}
+
+/// # Handle BGPsec requests
+///
+impl KrillServer {
+    pub fn ca_bgpsec_definitions_show(&self, handle: &Handle) -> KrillResult<BgpSecCsrInfoList> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.bgpsec_definitions())
+    }
+
+    pub fn ca_bgpsec_definitions_update(
+        &self,
+        handle: Handle,
+        updates: BgpSecDefinitionUpdates,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self
+            .caserver
+            .ca_bgpsec_definitions_update(handle, updates, actor)?)
+    }
+}
+
+/// # Handle RTA requests
+///
+impl KrillServer {
+    pub fn ca_rta_list(&self, handle: &Handle) -> KrillResult<RtaList> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.rta_list())
+    }
+
+    pub fn ca_rta_show(
+        &self,
+        handle: &Handle,
+        name: &str,
+    ) -> KrillResult<ResourceTaggedAttestation> {
+        let ca = self.caserver.get_ca(handle)?;
+        ca.rta_show(name)
+    }
+
+    pub fn ca_rta_sign(
+        &self,
+        handle: Handle,
+        name: RtaName,
+        request: RtaContentRequest,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        self.caserver.ca_rta_sign(handle, name, request, actor)
+    }
+
+    /// Prepares keys for co-signing a multi-signer RTA, and returns them so
+    /// that they can be included by the party who signs the RTA first.
+    pub fn ca_rta_multi_prep(
+        &self,
+        handle: &Handle,
+        name: RtaName,
+        request: RtaPrepareRequest,
+        actor: &Actor,
+    ) -> KrillResult<RtaPrepResponse> {
+        self.caserver
+            .ca_rta_multi_prep(handle.clone(), name.clone(), request, actor)?;
+        let ca = self.caserver.get_ca(handle)?;
+        ca.rta_prep_response(&name)
+    }
+
+    pub fn ca_rta_multi_cosign(
+        &self,
+        handle: Handle,
+        name: RtaName,
+        rta: ResourceTaggedAttestation,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        self.caserver.ca_rta_multi_cosign(handle, name, rta, actor)
+    }
+
+    /// Validates an RTA for the content in the request, under the configured
+    /// TALs. Trust anchor certificates are retrieved when they are needed.
+    pub async fn rta_validate(
+        &self,
+        request: RtaValidationRequest,
+    ) -> KrillResult<RtaValidationReport> {
+        let (rta, content) = request.unpack();
+        let rta = ca::Rta::decode(rta.as_bytes().clone())
+            .map_err(|e| Error::RtaInvalid(e.to_string()))?;
+
+        let anchors = self.rta_trust_anchors(&rta.anchor_keys()).await;
+
+        rta.validate(content.as_ref(), &anchors)
+            .map_err(|e| Error::RtaInvalid(e.to_string()))
+    }
+
+    /// Returns the trust anchors for the configured TALs which have one of
+    /// the given keys. TALs or certificates which cannot be read are skipped,
+    /// which will make validation fail if they were needed.
+    async fn rta_trust_anchors(&self, keys: &[KeyIdentifier]) -> Vec<ca::RtaTrustAnchor> {
+        let mut anchors = vec![];
+
+        for path in self.rta_tals.iter() {
+            let tal = match file::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| Tal::read(path, &mut bytes.as_ref()).map_err(|e| e.to_string()))
+            {
+                Ok(tal) => tal,
+                Err(e) => {
+                    warn!("Cannot read TAL at '{}': {}", path.to_string_lossy(), e);
+                    continue;
+                }
+            };
+
+            if !keys.contains(&tal.key_info().key_identifier()) {
+                continue;
+            }
+
+            for tal_uri in tal.uris() {
+                if let TalUri::Https(uri) = tal_uri {
+                    let uri = uri.to_string();
+                    match httpclient::get_binary(&uri).await {
+                        Ok(bytes) => match Cert::decode(bytes) {
+                            Ok(cert) => {
+                                if let Some(anchor) = ca::RtaTrustAnchor::new(&tal, cert) {
+                                    anchors.push(anchor);
+                                    break;
+                                } else {
+                                    warn!("Certificate at '{}' does not match TAL", uri);
+                                }
+                            }
+                            Err(e) => warn!("Cannot decode certificate at '{}': {}", uri, e),
+                        },
+                        Err(e) => warn!("Cannot retrieve certificate at '{}': {}", uri, e),
+                    }
+                }
+            }
+        }
+
+        anchors
+    }
 }
 
 /// # Handle publication requests
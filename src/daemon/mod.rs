@@ -1,7 +1,11 @@
+pub mod alerts;
 pub mod auth;
+pub mod backup;
 pub mod ca;
 pub mod config;
 pub mod http;
 pub mod krillserver;
 pub mod mq;
+pub mod pubdserver;
 pub mod scheduler;
+pub mod webhooks;
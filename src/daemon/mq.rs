@@ -2,22 +2,41 @@
 //! making them available for triggered processing, such as publishing
 //! signed material, or asking a newly added parent for resource
 //! entitlements.
+//!
+//! The queue can be kept on disk, so that triggered processing is not lost
+//! if Krill stops before it is done. Tasks which fail are retried with
+//! backoff.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::RwLock;
 
-use crate::commons::api::{Handle, ParentHandle, ResourceClassName, RevocationRequest};
+use chrono::Duration;
+
+use rpki::x509::Time;
+
+use crate::commons::api::{
+    Handle, ParentHandle, RepoMigrationPhase, ResourceClassName, RevocationRequest,
+};
 use crate::commons::eventsourcing::{self, Event};
+use crate::commons::util::file;
+use crate::constants::{MQ_MAX_ATTEMPTS, MQ_RETRY_MAX_SECS, MQ_RETRY_MIN_SECS};
 use crate::daemon::ca::{CertAuth, Evt, EvtDet, Signer};
-use rpki::x509::Time;
+
+const MQ_DIR: &str = "mq";
+const QUEUE_FILE: &str = "queue.json";
 
 //------------ QueueEvent ----------------------------------------------------
 
 /// This type contains all the events of interest for a KrillServer, with
 /// the details needed for triggered processing.
-#[derive(Clone, Debug, Display, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
 #[allow(clippy::large_enum_variant)]
+#[serde(rename_all = "snake_case")]
 pub enum QueueEvent {
     #[display(fmt = "delta for '{}' version '{}'", _0, _1)]
     Delta(Handle, u64),
@@ -47,34 +66,198 @@ pub enum QueueEvent {
     )]
     UnexpectedKey(Handle, u64, ResourceClassName, RevocationRequest),
 
+    // No longer queued, but kept so that tasks persisted by earlier versions
+    // can still be processed.
     #[display(fmt = "clean up old repo *if it exists* for '{}' version '{}'", _0, _1)]
     CleanOldRepo(Handle, u64),
 
+    #[display(fmt = "repository migration for '{}' version '{}'", _0, _1)]
+    RepoMigration(Handle, u64),
+
     #[display(fmt = "reschedule failed publication for '{}'", _0)]
     ReschedulePublish(Handle, Time),
 }
 
+impl QueueEvent {
+    /// The CA for which the event should be processed.
+    pub fn handle(&self) -> &Handle {
+        match self {
+            QueueEvent::Delta(handle, _)
+            | QueueEvent::ParentAdded(handle, _, _)
+            | QueueEvent::RepositoryConfigured(handle, _)
+            | QueueEvent::RequestsPending(handle, _)
+            | QueueEvent::ResourceClassRemoved(handle, _, _, _)
+            | QueueEvent::UnexpectedKey(handle, _, _, _)
+            | QueueEvent::CleanOldRepo(handle, _)
+            | QueueEvent::RepoMigration(handle, _)
+            | QueueEvent::ReschedulePublish(handle, _) => handle,
+        }
+    }
+}
+
+//------------ QueueTask -----------------------------------------------------
+
+/// An event in the queue, with the state needed to retry its processing.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct QueueTask {
+    id: u64,
+    event: QueueEvent,
+    attempts: u32,
+    not_before: Option<Time>,
+    #[serde(default)]
+    last_try: Option<Time>,
+    #[serde(default)]
+    last_error: Option<String>,
+}
+
+impl QueueTask {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn event(&self) -> &QueueEvent {
+        &self.event
+    }
+
+    /// The number of times processing this task failed before.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The time before which the task should not be retried.
+    pub fn not_before(&self) -> Option<Time> {
+        self.not_before
+    }
+
+    pub fn last_try(&self) -> Option<Time> {
+        self.last_try
+    }
+
+    pub fn last_error(&self) -> Option<&String> {
+        self.last_error.as_ref()
+    }
+
+    fn is_due(&self, now: Time) -> bool {
+        self.not_before.map(|t| t <= now).unwrap_or(true)
+    }
+
+    /// Publication is always retried, other tasks only up to a maximum
+    /// number of attempts.
+    fn may_retry(&self) -> bool {
+        match self.event {
+            QueueEvent::Delta(_, _) | QueueEvent::ReschedulePublish(_, _) => true,
+            _ => self.attempts < MQ_MAX_ATTEMPTS,
+        }
+    }
+
+    fn backoff_secs(attempts: u32) -> i64 {
+        let backoff = MQ_RETRY_MIN_SECS << (attempts.max(1) - 1).min(16);
+        backoff.min(MQ_RETRY_MAX_SECS)
+    }
+}
+
+//------------ EventQueueStats -----------------------------------------------
+
+/// The number of tasks in the queue, and of the tasks which failed since
+/// Krill was started, for use in metrics.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EventQueueStats {
+    pub pending: usize,
+    pub retrying: usize,
+    pub failures: u64,
+}
+
+//------------ EventQueueListener --------------------------------------------
+
 #[derive(Debug)]
 pub struct EventQueueListener {
     q: RwLock<Box<dyn EventQueueStore>>,
+    failures: AtomicU64,
 }
 
 impl EventQueueListener {
     pub fn in_mem() -> Self {
+        Self::with_store(Box::new(MemoryEventQueue::new()))
+    }
+
+    /// Keeps the queue in the 'mq' directory under the work directory, and
+    /// picks up any tasks which were left there.
+    pub fn disk(work_dir: &PathBuf) -> Result<Self, io::Error> {
+        let store = DiskEventQueue::new(work_dir)?;
+        Ok(Self::with_store(Box::new(store)))
+    }
+
+    fn with_store(store: Box<dyn EventQueueStore>) -> Self {
         EventQueueListener {
-            q: RwLock::new(Box::new(MemoryEventQueue::new())),
+            q: RwLock::new(store),
+            failures: AtomicU64::new(0),
         }
     }
 }
 
 impl EventQueueListener {
-    pub fn pop(&self) -> Option<QueueEvent> {
-        self.q.write().unwrap().pop()
+    /// Returns the next task which is due, skipping tasks for the given
+    /// (busy) CAs. The task stays in the queue until it is marked as done,
+    /// or for retry.
+    pub fn pop(&self, busy: &HashSet<Handle>) -> Option<QueueTask> {
+        self.q.write().unwrap().pop(busy)
     }
 
     pub fn push_back(&self, evt: QueueEvent) {
         self.q.write().unwrap().push_back(evt)
     }
+
+    pub fn done(&self, task: &QueueTask) {
+        self.q.write().unwrap().remove(task.id)
+    }
+
+    /// Marks the task as failed, and retries it as the given event after a
+    /// backoff, unless it was tried too often already.
+    pub fn retry_as(&self, task: &QueueTask, event: QueueEvent, error: &str) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        if task.may_retry() {
+            let attempts = task.attempts + 1;
+            let backoff = QueueTask::backoff_secs(attempts);
+            debug!("Will retry '{}' in {} seconds", event, backoff);
+            let now = Time::now();
+            let retry = QueueTask {
+                id: task.id,
+                event,
+                attempts,
+                not_before: Some(now + Duration::seconds(backoff)),
+                last_try: Some(now),
+                last_error: Some(error.to_string()),
+            };
+            self.q.write().unwrap().retry(retry)
+        } else {
+            error!(
+                "Giving up on '{}' after {} attempts",
+                task.event,
+                task.attempts + 1
+            );
+            self.done(task)
+        }
+    }
+
+    /// Marks the task as failed, and retries it after a backoff, unless it
+    /// was tried too often already.
+    pub fn retry(&self, task: &QueueTask, error: &str) {
+        self.retry_as(task, task.event.clone(), error)
+    }
+
+    /// Returns all tasks in the queue, and whether they are being processed.
+    pub fn tasks(&self) -> Vec<(QueueTask, bool)> {
+        self.q.read().unwrap().tasks()
+    }
+
+    pub fn stats(&self) -> EventQueueStats {
+        let q = self.q.read().unwrap();
+        EventQueueStats {
+            pending: q.len(),
+            retrying: q.retrying(),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
 }
 
 // TODO: Is this unsafe here? I would think the RwLock is safe, but..
@@ -83,7 +266,7 @@ unsafe impl Sync for EventQueueListener {}
 
 /// Implement listening for CertAuth Published events.
 impl<S: Signer> eventsourcing::EventListener<CertAuth<S>> for EventQueueListener {
-    fn listen(&self, _ca: &CertAuth<S>, event: &Evt) {
+    fn listen(&self, ca: &CertAuth<S>, event: &Evt) {
         trace!("Seen CertAuth event '{}'", event);
 
         let handle = event.handle();
@@ -136,25 +319,112 @@ impl<S: Signer> eventsourcing::EventListener<CertAuth<S>> for EventQueueListener
                 let evt = QueueEvent::RequestsPending(handle.clone(), version);
                 self.push_back(evt);
             }
-            EvtDet::CertificateReceived(_, _, _) => {
-                let evt = QueueEvent::CleanOldRepo(handle.clone(), version);
+            EvtDet::RepoMigrationUpdated(_) => {
+                let evt = QueueEvent::RepoMigration(handle.clone(), version);
                 self.push_back(evt);
             }
             _ => {}
         }
+
+        // A received certificate may be the last one a repository migration
+        // is waiting for.
+        let cert_received = matches!(
+            event.details(),
+            EvtDet::CertificateReceived(_, _, _)
+                | EvtDet::KeyPendingToNew(_, _, _)
+                | EvtDet::KeyPendingToActive(_, _, _)
+        );
+        if cert_received
+            && ca.repo_migration_phase() == Some(RepoMigrationPhase::AwaitingCertificates)
+        {
+            self.push_back(QueueEvent::RepoMigration(handle.clone(), version));
+        }
     }
 }
 
 //------------ EventQueue ----------------------------------------------------
 
 /// This trait provides the public contract for an EventQueue used by the
-/// KrillServer. First implementation can be a simple in memory thing, but
-/// we will need someting more robust, and possibly multi-master later.
+/// KrillServer. Tasks are kept until they are removed, so that they can be
+/// retried if processing them fails.
 ///
 /// The EventQueue should implement Eventlistener
 trait EventQueueStore: fmt::Debug {
-    fn pop(&self) -> Option<QueueEvent>;
+    fn pop(&self, busy: &HashSet<Handle>) -> Option<QueueTask>;
     fn push_back(&self, evt: QueueEvent);
+    fn remove(&self, id: u64);
+    fn retry(&self, task: QueueTask);
+    fn len(&self) -> usize;
+    fn retrying(&self) -> usize;
+    fn tasks(&self) -> Vec<(QueueTask, bool)>;
+}
+
+//------------ QueueState ----------------------------------------------------
+
+/// The tasks in a queue. Tasks which are being processed are skipped by pop,
+/// but this is not persisted so that they are picked up again after a
+/// restart.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct QueueState {
+    next_id: u64,
+    tasks: VecDeque<QueueTask>,
+    #[serde(skip)]
+    in_progress: HashSet<u64>,
+}
+
+impl QueueState {
+    fn pop(&mut self, busy: &HashSet<Handle>) -> Option<QueueTask> {
+        let now = Time::now();
+        let in_progress = &self.in_progress;
+        let task = self
+            .tasks
+            .iter()
+            .find(|t| {
+                !in_progress.contains(&t.id) && !busy.contains(t.event.handle()) && t.is_due(now)
+            })
+            .cloned()?;
+
+        trace!("Popping evt from schedule queue: {}", task.event);
+        self.in_progress.insert(task.id);
+        Some(task)
+    }
+
+    fn push_back(&mut self, event: QueueEvent) {
+        trace!("Pushing event to schedule queue: {}", event);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.push_back(QueueTask {
+            id,
+            event,
+            attempts: 0,
+            not_before: None,
+            last_try: None,
+            last_error: None,
+        });
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.in_progress.remove(&id);
+        self.tasks.retain(|t| t.id != id);
+    }
+
+    fn retry(&mut self, task: QueueTask) {
+        self.in_progress.remove(&task.id);
+        if let Some(existing) = self.tasks.iter_mut().find(|t| t.id == task.id) {
+            *existing = task;
+        }
+    }
+
+    fn retrying(&self) -> usize {
+        self.tasks.iter().filter(|t| t.attempts > 0).count()
+    }
+
+    fn tasks(&self) -> Vec<(QueueTask, bool)> {
+        self.tasks
+            .iter()
+            .map(|t| (t.clone(), self.in_progress.contains(&t.id)))
+            .collect()
+    }
 }
 
 //------------ MemoryEventQueue ----------------------------------------------
@@ -162,30 +432,228 @@ trait EventQueueStore: fmt::Debug {
 /// In memory event queue implementation.
 #[derive(Debug)]
 struct MemoryEventQueue {
-    q: RwLock<VecDeque<QueueEvent>>,
+    q: RwLock<QueueState>,
 }
 
 impl MemoryEventQueue {
     pub fn new() -> Self {
         MemoryEventQueue {
-            q: RwLock::new(VecDeque::new()),
+            q: RwLock::new(QueueState::default()),
         }
     }
 }
 
 impl EventQueueStore for MemoryEventQueue {
-    fn pop(&self) -> Option<QueueEvent> {
-        let res = self.q.write().unwrap().pop_front();
+    fn pop(&self, busy: &HashSet<Handle>) -> Option<QueueTask> {
+        self.q.write().unwrap().pop(busy)
+    }
+
+    fn push_back(&self, evt: QueueEvent) {
+        self.q.write().unwrap().push_back(evt)
+    }
+
+    fn remove(&self, id: u64) {
+        self.q.write().unwrap().remove(id)
+    }
+
+    fn retry(&self, task: QueueTask) {
+        self.q.write().unwrap().retry(task)
+    }
+
+    fn len(&self) -> usize {
+        self.q.read().unwrap().tasks.len()
+    }
+
+    fn retrying(&self) -> usize {
+        self.q.read().unwrap().retrying()
+    }
+
+    fn tasks(&self) -> Vec<(QueueTask, bool)> {
+        self.q.read().unwrap().tasks()
+    }
+}
+
+//------------ DiskEventQueue ------------------------------------------------
+
+/// Event queue implementation which saves all changes to disk. The queue is
+/// written to a temporary file first, and then renamed, so that a crash
+/// while saving leaves the previous version intact.
+#[derive(Debug)]
+struct DiskEventQueue {
+    path: PathBuf,
+    q: RwLock<QueueState>,
+}
+
+impl DiskEventQueue {
+    fn new(work_dir: &PathBuf) -> Result<Self, io::Error> {
+        let dir = file::sub_dir(work_dir, MQ_DIR)?;
+        let path = file::file_path(&dir, QUEUE_FILE);
 
-        if let Some(evt) = res.as_ref() {
-            trace!("Popping evt from schedule queue: {}", evt)
+        let state: QueueState = if path.exists() {
+            file::load_json(&path)?
+        } else {
+            QueueState::default()
+        };
+
+        if !state.tasks.is_empty() {
+            info!(
+                "Resuming {} triggered task(s) from before restart",
+                state.tasks.len()
+            );
+        }
+
+        Ok(DiskEventQueue {
+            path,
+            q: RwLock::new(state),
+        })
+    }
+
+    fn save(&self, state: &QueueState) {
+        let tmp = self.path.with_extension("json.tmp");
+        let res = file::save_json(state, &tmp).and_then(|_| fs::rename(&tmp, &self.path));
+        if let Err(e) = res {
+            error!("Could not save event queue: {}", e);
         }
+    }
+}
 
-        res
+impl EventQueueStore for DiskEventQueue {
+    fn pop(&self, busy: &HashSet<Handle>) -> Option<QueueTask> {
+        self.q.write().unwrap().pop(busy)
     }
 
     fn push_back(&self, evt: QueueEvent) {
-        trace!("Pushing event to schedule queue: {}", evt);
-        self.q.write().unwrap().push_back(evt);
+        let mut q = self.q.write().unwrap();
+        q.push_back(evt);
+        self.save(&q);
+    }
+
+    fn remove(&self, id: u64) {
+        let mut q = self.q.write().unwrap();
+        q.remove(id);
+        self.save(&q);
+    }
+
+    fn retry(&self, task: QueueTask) {
+        let mut q = self.q.write().unwrap();
+        q.retry(task);
+        self.save(&q);
+    }
+
+    fn len(&self) -> usize {
+        self.q.read().unwrap().tasks.len()
+    }
+
+    fn retrying(&self) -> usize {
+        self.q.read().unwrap().retrying()
+    }
+
+    fn tasks(&self) -> Vec<(QueueTask, bool)> {
+        self.q.read().unwrap().tasks()
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::test::*;
+
+    use super::*;
+
+    #[test]
+    fn keep_tasks_on_disk_until_done() {
+        test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let parent = Handle::from_str("parent").unwrap();
+
+            let queue = EventQueueListener::disk(&d).unwrap();
+            queue.push_back(QueueEvent::Delta(ca.clone(), 1));
+            queue.push_back(QueueEvent::ParentAdded(ca.clone(), 2, parent));
+
+            let none = HashSet::new();
+            let delta = queue.pop(&none).unwrap();
+            assert_eq!(delta.event(), &QueueEvent::Delta(ca.clone(), 1));
+            queue.done(&delta);
+
+            // Crash while processing the second task
+            let parent_added = queue.pop(&none).unwrap();
+            assert!(queue.pop(&none).is_none());
+            assert_eq!(queue.tasks(), vec![(parent_added.clone(), true)]);
+
+            let queue = EventQueueListener::disk(&d).unwrap();
+            assert_eq!(queue.stats().pending, 1);
+            assert_eq!(queue.pop(&none).unwrap(), parent_added);
+        })
+    }
+
+    #[test]
+    fn retry_with_backoff() {
+        let ca = Handle::from_str("ca").unwrap();
+        let none = HashSet::new();
+        let queue = EventQueueListener::in_mem();
+        queue.push_back(QueueEvent::Delta(ca.clone(), 1));
+
+        let task = queue.pop(&none).unwrap();
+        queue.retry_as(
+            &task,
+            QueueEvent::ReschedulePublish(ca, Time::now()),
+            "repository unavailable",
+        );
+
+        // not due yet
+        assert!(queue.pop(&none).is_none());
+        let (task, running) = queue.tasks().pop().unwrap();
+        assert!(!running);
+        assert_eq!(task.attempts(), 1);
+        assert_eq!(task.last_error().unwrap(), "repository unavailable");
+        assert_eq!(
+            queue.stats(),
+            EventQueueStats {
+                pending: 1,
+                retrying: 1,
+                failures: 1
+            }
+        );
+
+        assert_eq!(QueueTask::backoff_secs(1), MQ_RETRY_MIN_SECS);
+        assert_eq!(QueueTask::backoff_secs(2), 2 * MQ_RETRY_MIN_SECS);
+        assert_eq!(QueueTask::backoff_secs(40), MQ_RETRY_MAX_SECS);
+    }
+
+    #[test]
+    fn skip_tasks_for_busy_cas() {
+        let ca = Handle::from_str("ca").unwrap();
+        let other = Handle::from_str("other").unwrap();
+        let queue = EventQueueListener::in_mem();
+        queue.push_back(QueueEvent::Delta(ca.clone(), 1));
+        queue.push_back(QueueEvent::Delta(other.clone(), 1));
+
+        let mut busy = HashSet::new();
+        busy.insert(ca);
+        assert_eq!(
+            queue.pop(&busy).unwrap().event(),
+            &QueueEvent::Delta(other, 1)
+        );
+        assert!(queue.pop(&busy).is_none());
+    }
+
+    #[test]
+    fn give_up_after_max_attempts() {
+        let ca = Handle::from_str("ca").unwrap();
+        let queue = EventQueueListener::in_mem();
+        queue.push_back(QueueEvent::RequestsPending(ca, 1));
+
+        let mut task = queue.pop(&HashSet::new()).unwrap();
+        for _ in 0..MQ_MAX_ATTEMPTS {
+            assert!(task.may_retry());
+            task.attempts += 1;
+        }
+        assert!(!task.may_retry());
+
+        queue.retry(&task, "parent unavailable");
+        assert_eq!(queue.stats().pending, 0);
     }
 }
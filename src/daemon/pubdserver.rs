@@ -0,0 +1,216 @@
+//! A standalone RPKI publication server, without any CA functionality.
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+
+use rpki::uri;
+use rpki::x509::Time;
+
+use crate::commons::api::{IntegrityReport, PublisherDetails, PublisherHandle, ServerInfo, Token};
+use crate::commons::remote::rfc8183;
+use crate::commons::util::file;
+use crate::commons::util::krillsigner::KrillSigner;
+use crate::commons::{KrillEmptyResult, KrillResult};
+use crate::constants::*;
+use crate::daemon::config::PubdConfig;
+use crate::daemon::webhooks::EventNotifier;
+use crate::pubd::{PubServer, RepoStats};
+
+//------------ PubdServer ----------------------------------------------------
+
+/// This runs only the publication server part of Krill: RFC 8181 for
+/// publishers, the RRDP and rsync output, and the management of publishers.
+///
+/// It uses the same data directory layout as the repository embedded in
+/// Krill, so that an existing embedded repository can be served by it.
+pub struct PubdServer {
+    // The base URI for this service
+    service_uri: uri::Https,
+
+    // The base working directory, used for various storage
+    work_dir: PathBuf,
+
+    // The token for the publisher management API
+    auth_token: Token,
+
+    // Publication server, with configured publishers
+    pubserver: PubServer,
+
+    // Time this server was started
+    started: Time,
+
+    post_limit_api: u64,
+    post_limit_rfc8181: u64,
+}
+
+/// # Set up and initialisation
+impl PubdServer {
+    pub fn build(config: &PubdConfig) -> KrillResult<Self> {
+        let work_dir = &config.data_dir;
+        let service_uri = config.service_uri();
+
+        info!("Starting {} v{}", KRILL_PUBD_APP, KRILL_VERSION);
+        info!("{} uses service uri: {}", KRILL_PUBD_APP, service_uri);
+
+        file::create_dir(work_dir)?;
+
+        let signer = KrillSigner::build(&config.signer, work_dir)?;
+        let signer = Arc::new(RwLock::new(signer));
+
+        let event_notifier = Arc::new(EventNotifier::new(work_dir, vec![]));
+
+        let pubserver = PubServer::build(
+            &config.rsync_base,
+            config.rrdp_service_uri(),
+            work_dir,
+            config.storage_type,
+            config.rfc8181_log_dir.as_ref(),
+            event_notifier,
+            signer,
+        )?;
+
+        Ok(PubdServer {
+            service_uri,
+            work_dir: work_dir.clone(),
+            auth_token: config.auth_token.clone(),
+            pubserver,
+            started: Time::now(),
+            post_limit_api: config.post_limit_api,
+            post_limit_rfc8181: config.post_limit_rfc8181,
+        })
+    }
+
+    pub fn server_info(&self) -> ServerInfo {
+        ServerInfo::new(KRILL_VERSION, self.started)
+    }
+
+    /// Returns whether the token may be used for the publisher management
+    /// API. Unlike Krill there are no roles: the token gives full access.
+    pub fn is_api_allowed(&self, token: Option<&Token>) -> bool {
+        token == Some(&self.auth_token)
+    }
+
+    pub fn limit_api(&self) -> u64 {
+        self.post_limit_api
+    }
+
+    pub fn limit_rfc8181(&self) -> u64 {
+        self.post_limit_rfc8181
+    }
+
+    pub fn rrdp_base_path(&self) -> PathBuf {
+        let mut path = self.work_dir.clone();
+        path.push(REPOSITORY_DIR);
+        path.push(REPOSITORY_RRDP_DIR);
+        path
+    }
+}
+
+/// # Configure publishers
+impl PubdServer {
+    /// Returns the repository server stats
+    pub fn repo_stats(&self) -> KrillResult<RepoStats> {
+        self.pubserver.repo_stats()
+    }
+
+    /// Returns all currently configured publishers.
+    pub fn publishers(&self) -> KrillResult<Vec<PublisherHandle>> {
+        self.pubserver.publishers()
+    }
+
+    /// Adds the publisher, blows up if it already existed.
+    pub fn add_publisher(
+        &self,
+        req: rfc8183::PublisherRequest,
+    ) -> KrillResult<rfc8183::RepositoryResponse> {
+        let publisher_handle = req.publisher_handle().clone();
+        self.pubserver.create_publisher(req)?;
+        self.repository_response(&publisher_handle)
+    }
+
+    /// Removes a publisher, blows up if it didn't exist.
+    pub fn remove_publisher(&self, publisher: PublisherHandle) -> KrillEmptyResult {
+        self.pubserver.remove_publisher(publisher)
+    }
+
+    /// Returns a publisher.
+    pub fn get_publisher(&self, publisher: &PublisherHandle) -> KrillResult<PublisherDetails> {
+        self.pubserver.get_publisher_details(publisher)
+    }
+
+    /// Moves the history of the repository before the given time to its
+    /// archive.
+    pub fn archive_history(&self, before: Time) -> KrillResult<usize> {
+        self.pubserver.archive_history(before)
+    }
+
+    /// Verifies, and optionally repairs, the stored history of the repository.
+    pub fn verify_integrity(&self, repair: bool) -> KrillResult<IntegrityReport> {
+        let store = self.pubserver.verify_integrity(repair)?;
+        Ok(IntegrityReport::new(vec![store]))
+    }
+}
+
+/// # Manage RFC8181 clients
+impl PubdServer {
+    pub fn repository_response(
+        &self,
+        publisher: &PublisherHandle,
+    ) -> KrillResult<rfc8183::RepositoryResponse> {
+        let rfc8181_uri =
+            uri::Https::from_string(format!("{}rfc8181/{}", self.service_uri, publisher)).unwrap();
+
+        self.pubserver.repository_response(rfc8181_uri, publisher)
+    }
+
+    pub fn rfc8181(&self, publisher: PublisherHandle, msg_bytes: Bytes) -> KrillResult<Bytes> {
+        self.pubserver.rfc8181(publisher, msg_bytes)
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rpki::crypto::{PublicKeyFormat, Signer};
+
+    use crate::commons::api::Handle;
+    use crate::commons::remote::builder::IdCertBuilder;
+    use crate::commons::util::softsigner::OpenSslSigner;
+    use crate::test;
+
+    use super::*;
+
+    #[test]
+    fn add_and_remove_publisher() {
+        test::test_under_tmp(|d| {
+            let config = PubdConfig::test(&d);
+            let server = PubdServer::build(&config).unwrap();
+
+            assert!(server.is_api_allowed(Some(&Token::from("pubd-secret"))));
+            assert!(!server.is_api_allowed(Some(&Token::from("secret"))));
+            assert!(!server.is_api_allowed(None));
+
+            let publisher = Handle::from_str("alice").unwrap();
+            let id_cert = {
+                let mut signer = OpenSslSigner::build(&d).unwrap();
+                let key = signer.create_key(PublicKeyFormat::default()).unwrap();
+                IdCertBuilder::new_ta_id_cert(&key, &signer).unwrap()
+            };
+            let req = rfc8183::PublisherRequest::new(None, publisher.clone(), id_cert);
+
+            let response = server.add_publisher(req).unwrap();
+            assert_eq!(
+                response.service_uri().to_string(),
+                "https://localhost:3001/rfc8181/alice"
+            );
+            assert_eq!(server.publishers().unwrap(), vec![publisher.clone()]);
+
+            server.remove_publisher(publisher).unwrap();
+            assert!(server.publishers().unwrap().is_empty());
+        })
+    }
+}
@@ -1,230 +1,596 @@
 //! Deal with asynchronous scheduled processes, either triggered by an
 //! event that occurred, or planned (e.g. re-publishing).
+//!
+//! All processes run as async tasks on the runtime of the server. The status
+//! of the periodic tasks, and of the triggered tasks in the event queue, is
+//! kept so that it can be shown through the API.
 
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
-use clokwerk::{self, ScheduleHandle, TimeUnits};
-use tokio::runtime::Runtime;
+use futures::future::join_all;
+use tokio::sync::Semaphore;
+use tokio::time::delay_for;
 
 use rpki::x509::Time;
 
-use crate::commons::api::Handle;
+use crate::commons::api::{Handle, RepoMigrationPhase, TaskKind, TaskList, TaskState, TaskStatus};
 use crate::commons::bgp::BgpAnalyser;
-use crate::commons::util::softsigner::OpenSslSigner;
+use crate::commons::util::krillsigner::KrillSigner;
+use crate::constants::{
+    SCHEDULER_MAX_PARENT_SYNCS, SCHEDULER_MAX_REPO_SYNCS, WEBHOOK_DELIVERY_SECONDS,
+};
+use crate::daemon::alerts::Alerter;
 use crate::daemon::ca::CaServer;
-use crate::daemon::mq::{EventQueueListener, QueueEvent};
+use crate::daemon::config::HistoryArchiveConfig;
+use crate::daemon::mq::{EventQueueListener, QueueEvent, QueueTask};
+use crate::daemon::webhooks::EventNotifier;
 use crate::pubd::PubServer;
 use crate::publish::CaPublisher;
 
-pub struct Scheduler {
-    /// Responsible for listening to events and executing triggered processes, such
-    /// as publication of newly generated RPKI objects.
-    #[allow(dead_code)] // just need to keep this in scope
-    event_sh: ScheduleHandle,
-
-    /// Responsible for periodically republishing so that MFTs and CRLs do not go stale.
-    #[allow(dead_code)] // just need to keep this in scope
-    republish_sh: ScheduleHandle,
+//------------ Scheduler -----------------------------------------------------
 
-    /// Responsible for letting CA check with their parents whether their resource
-    /// entitlements have changed *and* for the shrinking of issued certificates, if
-    /// they are not renewed within the configured grace period.
-    #[allow(dead_code)] // just need to keep this in scope
-    ca_refresh_sh: ScheduleHandle,
+pub struct Scheduler {
+    /// Triggered tasks, such as publication of newly generated RPKI objects.
+    event_queue: Arc<EventQueueListener>,
 
-    /// Responsible for refreshing announcement information
-    #[allow(dead_code)] // just need to keep this in scope
-    announcements_refresh_sh: ScheduleHandle,
+    /// The status of the periodic tasks.
+    registry: Arc<TaskRegistry>,
 }
 
 impl Scheduler {
+    /// Spawns all tasks on the current runtime. Parent and repository syncs
+    /// share a limited number of permits, so that only a bounded number of
+    /// them run concurrently.
+    #[allow(clippy::too_many_arguments)]
     pub fn build(
         event_queue: Arc<EventQueueListener>,
-        caserver: Arc<CaServer<OpenSslSigner>>,
+        caserver: Arc<CaServer<KrillSigner>>,
         pubserver: Option<Arc<PubServer>>,
         bgp_analyser: Arc<BgpAnalyser>,
+        alerter: Arc<Alerter>,
+        event_notifier: Arc<EventNotifier>,
         ca_refresh_rate: u32,
+        history_archive: HistoryArchiveConfig,
     ) -> Self {
-        let event_sh = make_event_sh(event_queue, caserver.clone(), pubserver);
-        let republish_sh = make_republish_sh(caserver.clone());
-        let ca_refresh_sh = make_ca_refresh_sh(caserver, ca_refresh_rate);
-        let announcements_refresh_sh = make_announcements_refresh_sh(bgp_analyser);
+        let registry = Arc::new(TaskRegistry::default());
+        let syncs = Arc::new(SyncLimits {
+            parents: Semaphore::new(SCHEDULER_MAX_PARENT_SYNCS),
+            repos: Semaphore::new(SCHEDULER_MAX_REPO_SYNCS),
+        });
 
-        Scheduler {
-            event_sh,
-            republish_sh,
-            ca_refresh_sh,
-            announcements_refresh_sh,
+        spawn_event_processing(
+            event_queue.clone(),
+            caserver.clone(),
+            pubserver.clone(),
+            syncs.clone(),
+        );
+
+        // Move old history to the archives of CAs and publishers, if configured.
+        if history_archive.cas.is_some() || history_archive.pubd.is_some() {
+            let ca = caserver.clone();
+            spawn_periodic(&registry, "history_archive", 24 * 3600, move || {
+                let caserver = ca.clone();
+                let pubserver = pubserver.clone();
+                let config = history_archive.clone();
+                blocking(move || archive_history(&caserver, pubserver.as_deref(), &config))
+            });
         }
-    }
-}
 
-fn make_event_sh(
-    event_queue: Arc<EventQueueListener>,
-    caserver: Arc<CaServer<OpenSslSigner>>,
-    pubserver: Option<Arc<PubServer>>,
-) -> ScheduleHandle {
-    let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(1.seconds()).run(move || {
-        while let Some(evt) = event_queue.pop() {
-            let mut rt = Runtime::new().unwrap();
-            match evt {
-                QueueEvent::Delta(handle, _version) => {
-                    rt.block_on(
-                        try_publish(&event_queue, caserver.clone(), pubserver.clone(), handle)
-                    )
-                }
-                QueueEvent::ReschedulePublish(handle, last_try) => {
-                    if Time::five_minutes_ago().timestamp() > last_try.timestamp() {
-                        rt.block_on(
-                            try_publish(&event_queue, caserver.clone(), pubserver.clone(), handle)
-                        )
-                    } else {
-                        event_queue.push_back(QueueEvent::ReschedulePublish(handle, last_try));
+        // Republish periodically, so that MFTs and CRLs do not go stale.
+        let ca = caserver.clone();
+        spawn_periodic(&registry, "republish", 3600, move || {
+            let caserver = ca.clone();
+            blocking(move || {
+                info!("Triggering background republication for all CAs");
+                caserver.republish_all().map_err(|e| e.to_string())
+            })
+        });
+
+        // Let CAs check with their parents whether their resource entitlements
+        // have changed *and* shrink issued certificates, if they are not
+        // renewed within the configured grace period.
+        let ca = caserver.clone();
+        spawn_periodic(
+            &registry,
+            "ca_refresh",
+            u64::from(ca_refresh_rate),
+            move || refresh_all_cas(ca.clone(), syncs.clone()),
+        );
+
+        // Remove route authorizations which are no longer valid.
+        let ca = caserver.clone();
+        spawn_periodic(&registry, "route_expiry", 60, move || {
+            let caserver = ca.clone();
+            blocking(move || {
+                caserver
+                    .remove_expired_routes()
+                    .map_err(|e| format!("Failed to remove expired ROA definitions: {}", e))
+            })
+        });
+
+        // Refresh announcement information.
+        spawn_periodic(&registry, "announcements_refresh", 1, move || {
+            let caserver = caserver.clone();
+            let bgp_analyser = bgp_analyser.clone();
+            let alerter = alerter.clone();
+            async move {
+                match bgp_analyser.update().await {
+                    Ok(true) => {
+                        analyse_all_cas(&caserver, &bgp_analyser, &alerter).await;
+                        Ok(())
                     }
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(format!("Failed to update BGP announcements: {}", e)),
                 }
-                QueueEvent::ResourceClassRemoved(handle, _, parent, revocations) => {
-                    rt.block_on(async {
-                        info!(
-                            "Trigger send revoke requests for removed RC for '{}' under '{}'",
-                            handle,
-                            parent
-                        );
-                        if caserver
-                            .send_revoke_requests(&handle, &parent, revocations).await
-                            .is_err()
-                        {
-                            warn!("Could not revoke key for removed resource class. This is not \
-                            an issue, because typically the parent will revoke our keys pro-actively, \
-                            just before removing the resource class entitlements.");
-                        }
-                    })
-                }
-                QueueEvent::UnexpectedKey(handle, _, rcn, revocation) => {
-                    rt.block_on(async {
-                        info!(
-                            "Trigger sending revocation requests for unexpected key with id '{}' in RC '{}'",
-                            revocation.key(),
-                            rcn
-                        );
-                        if let Err(e) = caserver
-                            .send_revoke_unexpected_key(&handle, rcn, revocation).await {
-                            error!("Could not revoke unexpected surplus key at parent: {}", e);
-                        }
-                    })
-                }
-                QueueEvent::ParentAdded(handle, _, parent) => {
-                    rt.block_on(async {
-                        info!(
-                            "Get updates for '{}' from added parent '{}'.",
-                            handle,
-                            parent
-                        );
-                        if let Err(e) = caserver.get_updates_from_parent(&handle, &parent).await {
-                            error!(
-                                "Error getting updates for '{}', from parent '{}',  error: '{}'",
-                                &handle, &parent, e
-                            )
-                        }
-                    })
-                }
-                QueueEvent::RepositoryConfigured(ca, _) => {
-                    rt.block_on(async {
-                        info!("Repository configured for '{}'", ca);
-                        if let Err(e) = caserver.get_delayed_updates(&ca).await {
-                            error!(
-                                "Error getting updates after configuring repository for '{}',  error: '{}'",
-                                &ca, e
-                            )
-                        }
-                    })
-                }
+            }
+        });
 
-                QueueEvent::RequestsPending(handle, _) => {
-                    rt.block_on(async {
-                        info!("Get updates for pending requests for '{}'.", handle);
-                        if let Err(e) = caserver.send_all_requests(&handle).await {
-                            error!(
-                                "Failed to send pending requests for '{}', error '{}'",
-                                &handle, e
-                            );
-                        }
-                    })
-                }
-                QueueEvent::CleanOldRepo(handle, _) => {
-                    rt.block_on(async {
-                        let publisher = CaPublisher::new(caserver.clone(), pubserver.clone());
-                        if let Err(e) = publisher.clean_up(&handle).await {
-                            info!(
-                                "Could not clean up old repo for '{}', it may be that it's no longer available. Got error '{}'",
-                                &handle, e
-                            );
-                        }
-                        if let Err(e) = caserver.remove_old_repo(&handle) {
-                            error!(
-                                "Failed to remove old repo from ca '{}', error '{}'",
-                                &handle, e
-                            );
-                        }
-                    })
+        // Deliver and retry webhook notifications.
+        spawn_periodic(
+            &registry,
+            "webhooks",
+            u64::from(WEBHOOK_DELIVERY_SECONDS),
+            move || {
+                let event_notifier = event_notifier.clone();
+                async move {
+                    event_notifier.deliver_pending().await;
+                    Ok(())
                 }
+            },
+        );
+
+        Scheduler {
+            event_queue,
+            registry,
+        }
+    }
+
+    /// Returns the status of all periodic tasks, followed by all triggered
+    /// tasks which are waiting or running.
+    pub fn tasks(&self) -> TaskList {
+        let mut tasks = self.registry.list();
+
+        for (task, running) in self.event_queue.tasks() {
+            let state = if running {
+                TaskState::Running
+            } else {
+                TaskState::Scheduled
+            };
+            let status = TaskStatus::new(
+                &task.event().to_string(),
+                TaskKind::Triggered,
+                task.not_before(),
+            )
+            .with_last_run(task.last_try(), task.last_error().cloned())
+            .with_state(state);
+            tasks.push(status);
+        }
+
+        TaskList::new(tasks)
+    }
+}
+
+//------------ TaskRegistry --------------------------------------------------
+
+/// Keeps the status of the periodic tasks by name.
+#[derive(Default)]
+struct TaskRegistry {
+    tasks: RwLock<BTreeMap<String, TaskStatus>>,
+}
+
+impl TaskRegistry {
+    fn add(&self, name: &str, next_run: Time) {
+        let status = TaskStatus::new(name, TaskKind::Periodic, Some(next_run));
+        self.tasks.write().unwrap().insert(name.to_string(), status);
+    }
+
+    fn start(&self, name: &str) {
+        if let Some(status) = self.tasks.write().unwrap().get_mut(name) {
+            status.start();
+        }
+    }
+
+    fn finish(&self, name: &str, error: Option<String>, next_run: Time) {
+        if let Some(status) = self.tasks.write().unwrap().get_mut(name) {
+            status.finish(error, next_run);
+        }
+    }
+
+    fn list(&self) -> Vec<TaskStatus> {
+        self.tasks.read().unwrap().values().cloned().collect()
+    }
+}
+
+/// Limits the number of concurrent syncs with parents and repositories.
+struct SyncLimits {
+    parents: Semaphore,
+    repos: Semaphore,
+}
+
+//------------ Periodic tasks ------------------------------------------------
+
+/// Spawns a task which runs every 'interval' seconds, starting 'interval'
+/// seconds from now, and keeps its status in the registry.
+fn spawn_periodic<F, Fut>(registry: &Arc<TaskRegistry>, name: &'static str, interval: u64, run: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+{
+    let interval = Duration::from_secs(interval);
+    let next_run = move || Time::now() + chrono::Duration::from_std(interval).unwrap();
+
+    registry.add(name, next_run());
+    let registry = registry.clone();
+
+    tokio::spawn(async move {
+        loop {
+            delay_for(interval).await;
+
+            registry.start(name);
+            let error = run().await.err();
+            if let Some(e) = error.as_ref() {
+                error!("Background task '{}' failed: {}", name, e);
             }
+            registry.finish(name, error, next_run());
         }
     });
-    scheduler.watch_thread(Duration::from_millis(100))
 }
 
-async fn try_publish(
-    event_queue: &Arc<EventQueueListener>,
-    caserver: Arc<CaServer<OpenSslSigner>>,
-    pubserver: Option<Arc<PubServer>>,
-    ca: Handle,
+/// Runs synchronous work, which may take a while and hold locks, on the
+/// blocking thread pool rather than on a runtime worker.
+async fn blocking<F>(op: F) -> Result<(), String>
+where
+    F: FnOnce() -> Result<(), String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(op)
+        .await
+        .map_err(|e| format!("task failed: {}", e))?
+}
+
+/// Gets updates from all parents of all CAs.
+async fn refresh_all_cas(
+    caserver: Arc<CaServer<KrillSigner>>,
+    syncs: Arc<SyncLimits>,
+) -> Result<(), String> {
+    info!("Triggering background refresh for all CAs");
+
+    let mut refreshes = vec![];
+    for ca in caserver.ca_list().cas() {
+        if let Ok(ca) = caserver.get_ca(ca.handle()) {
+            for parent in ca.parents() {
+                let caserver = caserver.clone();
+                let syncs = syncs.clone();
+                let handle = ca.handle().clone();
+                let parent = parent.clone();
+
+                refreshes.push(tokio::spawn(async move {
+                    let _permit = syncs.parents.acquire().await;
+                    caserver
+                        .get_updates_from_parent(&handle, &parent)
+                        .await
+                        .map_err(|e| format!("'{}' from parent '{}': {}", handle, parent, e))
+                }));
+            }
+        }
+    }
+
+    let errors: Vec<String> = join_all(refreshes)
+        .await
+        .into_iter()
+        .filter_map(|res| match res {
+            Ok(res) => res.err(),
+            Err(e) => Some(e.to_string()),
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to refresh CA certificates for {}",
+            errors.join(", ")
+        ))
+    }
+}
+
+/// Adds the BGP analysis stats of all CAs to their timelines, and alerts
+/// about any new invalid announcements.
+async fn analyse_all_cas(
+    caserver: &CaServer<KrillSigner>,
+    bgp_analyser: &BgpAnalyser,
+    alerter: &Alerter,
 ) {
-    info!("Try to publish for '{}'", ca);
-    let publisher = CaPublisher::new(caserver, pubserver);
+    for ca in caserver.ca_list().cas() {
+        if let Ok(ca) = caserver.get_ca(ca.handle()) {
+            let roas = ca.roa_definitions();
+            let resources = ca.all_resources();
+            if let Err(e) = bgp_analyser.record_stats(ca.handle(), &roas, &resources) {
+                error!("Failed to record BGP stats for CA '{}': {}", ca.handle(), e);
+            }
 
-    if let Err(e) = publisher.publish(&ca).await {
-        error!(
-            "Failed to publish for '{}' will reschedule, error: {}",
-            ca, e
-        );
-        event_queue.push_back(QueueEvent::ReschedulePublish(ca, Time::now()))
+            let report = bgp_analyser.analyse(&roas, &resources);
+            alerter.check(ca.handle(), &report).await;
+        }
     }
 }
 
-fn make_republish_sh(caserver: Arc<CaServer<OpenSslSigner>>) -> ScheduleHandle {
-    let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(1.hours()).run(move || {
-        info!("Triggering background republication for all CAs");
-        if let Err(e) = caserver.republish_all() {
-            error!("Background republishing failed: {}", e);
+/// Archives the history before the configured number of days for CAs and
+/// the embedded repository.
+fn archive_history(
+    caserver: &CaServer<KrillSigner>,
+    pubserver: Option<&PubServer>,
+    config: &HistoryArchiveConfig,
+) -> Result<(), String> {
+    if let Some(before) = HistoryArchiveConfig::archive_before(config.cas) {
+        caserver
+            .archive_history(before)
+            .map_err(|e| format!("Failed to archive CA history: {}", e))?;
+    }
+    if let (Some(pubserver), Some(before)) =
+        (pubserver, HistoryArchiveConfig::archive_before(config.pubd))
+    {
+        pubserver
+            .archive_history(before)
+            .map_err(|e| format!("Failed to archive repository history: {}", e))?;
+    }
+    Ok(())
+}
+
+//------------ Triggered tasks -----------------------------------------------
+
+/// Spawns a task which picks up due tasks from the event queue every second,
+/// and processes each in its own task. Tasks for the same CA are processed
+/// one at a time, in order. Tasks which panic are retried like failed tasks.
+fn spawn_event_processing(
+    event_queue: Arc<EventQueueListener>,
+    caserver: Arc<CaServer<KrillSigner>>,
+    pubserver: Option<Arc<PubServer>>,
+    syncs: Arc<SyncLimits>,
+) {
+    tokio::spawn(async move {
+        let busy = Arc::new(Mutex::new(HashSet::new()));
+
+        loop {
+            loop {
+                let task = event_queue.pop(&busy.lock().unwrap());
+                let task = match task {
+                    Some(task) => task,
+                    None => break,
+                };
+
+                let busy = Busy::start(&busy, task.event().handle());
+
+                let event_queue = event_queue.clone();
+                let caserver = caserver.clone();
+                let pubserver = pubserver.clone();
+                let syncs = syncs.clone();
+
+                tokio::spawn(async move {
+                    let _busy = busy;
+                    let processing = {
+                        let event_queue = event_queue.clone();
+                        let task = task.clone();
+                        tokio::spawn(async move {
+                            process_task(&event_queue, &task, caserver, pubserver, &syncs).await;
+                        })
+                    };
+
+                    // If processing panics the task is neither done nor
+                    // retried, so it must be retried here to get it out
+                    // of progress.
+                    if let Err(e) = processing.await {
+                        let e = format!("Processing '{}' failed: {}", task.event(), e);
+                        error!("{}", e);
+                        event_queue.retry(&task, &e);
+                    }
+                });
+            }
+
+            delay_for(Duration::from_secs(1)).await;
         }
     });
-    scheduler.watch_thread(Duration::from_millis(100))
 }
 
-fn make_ca_refresh_sh(caserver: Arc<CaServer<OpenSslSigner>>, refresh_rate: u32) -> ScheduleHandle {
-    let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(refresh_rate.seconds()).run(move || {
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            info!("Triggering background refresh for all CAs");
-            caserver.refresh_all().await
-        })
-    });
-    scheduler.watch_thread(Duration::from_millis(100))
+/// Marks a CA as busy with a task, until this is dropped. This also happens
+/// if the task panics, so that later tasks for the CA are not blocked.
+struct Busy {
+    busy: Arc<Mutex<HashSet<Handle>>>,
+    handle: Handle,
+}
+
+impl Busy {
+    fn start(busy: &Arc<Mutex<HashSet<Handle>>>, handle: &Handle) -> Self {
+        busy.lock().unwrap().insert(handle.clone());
+        Busy {
+            busy: busy.clone(),
+            handle: handle.clone(),
+        }
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        if let Ok(mut busy) = self.busy.lock() {
+            busy.remove(&self.handle);
+        }
+    }
 }
 
-fn make_announcements_refresh_sh(bgp_analyser: Arc<BgpAnalyser>) -> ScheduleHandle {
-    let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(1.seconds()).run(move || {
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            if let Err(e) = bgp_analyser.update().await {
-                error!("Failed to update BGP announcements: {}", e)
+/// Takes the next step in the repository migration of a CA, if it has one
+/// in progress. Objects are withdrawn from the old repository only after
+/// they were published at the new repository, and all parents issued
+/// certificates for the new repository.
+async fn migrate_repo(
+    handle: &Handle,
+    caserver: Arc<CaServer<KrillSigner>>,
+    pubserver: Option<Arc<PubServer>>,
+) -> Result<(), String> {
+    let phase = caserver
+        .get_ca(handle)
+        .map_err(|e| e.to_string())?
+        .repo_migration_phase();
+
+    match phase {
+        None => Ok(()),
+        Some(RepoMigrationPhase::PublishingNew) => {
+            info!("Publish '{}' at new repository", handle);
+            let publisher = CaPublisher::new(caserver.clone(), pubserver);
+            publisher.publish(handle).await.map_err(|e| {
+                format!(
+                    "Failed to publish '{}' at new repository, error '{}'",
+                    handle, e
+                )
+            })?;
+            repo_migration_advance(handle, &caserver)
+        }
+        Some(RepoMigrationPhase::AwaitingCertificates) => repo_migration_advance(handle, &caserver),
+        Some(RepoMigrationPhase::WithdrawingOld) => {
+            clean_old_repo(handle, caserver, pubserver).await
+        }
+    }
+}
+
+fn repo_migration_advance(handle: &Handle, caserver: &CaServer<KrillSigner>) -> Result<(), String> {
+    caserver.repo_migration_advance(handle).map_err(|e| {
+        format!(
+            "Failed to advance repository migration for '{}', error '{}'",
+            handle, e
+        )
+    })
+}
+
+async fn clean_old_repo(
+    handle: &Handle,
+    caserver: Arc<CaServer<KrillSigner>>,
+    pubserver: Option<Arc<PubServer>>,
+) -> Result<(), String> {
+    let publisher = CaPublisher::new(caserver.clone(), pubserver);
+    if let Err(e) = publisher.clean_up(handle).await {
+        info!(
+            "Could not clean up old repo for '{}', it may be that it's no longer available. Got error '{}'",
+            handle, e
+        );
+    }
+    caserver.remove_old_repo(handle).map_err(|e| {
+        format!(
+            "Failed to remove old repo from ca '{}', error '{}'",
+            handle, e
+        )
+    })
+}
+
+/// Processes a triggered task, and marks it as done, or for retry if it
+/// failed.
+async fn process_task(
+    event_queue: &EventQueueListener,
+    task: &QueueTask,
+    caserver: Arc<CaServer<KrillSigner>>,
+    pubserver: Option<Arc<PubServer>>,
+    syncs: &SyncLimits,
+) {
+    let result = match task.event().clone() {
+        QueueEvent::Delta(ca, _) | QueueEvent::ReschedulePublish(ca, _) => {
+            let _permit = syncs.repos.acquire().await;
+            info!("Try to publish for '{}'", ca);
+            let publisher = CaPublisher::new(caserver, pubserver);
+
+            if let Err(e) = publisher.publish(&ca).await {
+                error!(
+                    "Failed to publish for '{}' will reschedule, error: {}",
+                    ca, e
+                );
+                let reschedule = QueueEvent::ReschedulePublish(ca, Time::now());
+                event_queue.retry_as(task, reschedule, &e.to_string());
+                return;
             }
-        })
-    });
-    scheduler.watch_thread(Duration::from_millis(100))
+            Ok(())
+        }
+        QueueEvent::ResourceClassRemoved(handle, _, parent, revocations) => {
+            let _permit = syncs.parents.acquire().await;
+            info!(
+                "Trigger send revoke requests for removed RC for '{}' under '{}'",
+                handle, parent
+            );
+            if caserver
+                .send_revoke_requests(&handle, &parent, revocations)
+                .await
+                .is_err()
+            {
+                warn!(
+                    "Could not revoke key for removed resource class. This is not \
+                    an issue, because typically the parent will revoke our keys pro-actively, \
+                    just before removing the resource class entitlements."
+                );
+            }
+            Ok(())
+        }
+        QueueEvent::UnexpectedKey(handle, _, rcn, revocation) => {
+            let _permit = syncs.parents.acquire().await;
+            info!(
+                "Trigger sending revocation requests for unexpected key with id '{}' in RC '{}'",
+                revocation.key(),
+                rcn
+            );
+            caserver
+                .send_revoke_unexpected_key(&handle, rcn, revocation)
+                .await
+                .map(|_| ())
+                .map_err(|e| format!("Could not revoke unexpected surplus key at parent: {}", e))
+        }
+        QueueEvent::ParentAdded(handle, _, parent) => {
+            let _permit = syncs.parents.acquire().await;
+            info!(
+                "Get updates for '{}' from added parent '{}'.",
+                handle, parent
+            );
+            caserver
+                .get_updates_from_parent(&handle, &parent)
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Error getting updates for '{}', from parent '{}',  error: '{}'",
+                        &handle, &parent, e
+                    )
+                })
+        }
+        QueueEvent::RepositoryConfigured(ca, _) => {
+            let _permit = syncs.parents.acquire().await;
+            info!("Repository configured for '{}'", ca);
+            caserver.get_delayed_updates(&ca).await.map_err(|e| {
+                format!(
+                    "Error getting updates after configuring repository for '{}',  error: '{}'",
+                    &ca, e
+                )
+            })
+        }
+        QueueEvent::RequestsPending(handle, _) => {
+            let _permit = syncs.parents.acquire().await;
+            info!("Get updates for pending requests for '{}'.", handle);
+            caserver.send_all_requests(&handle).await.map_err(|e| {
+                format!(
+                    "Failed to send pending requests for '{}', error '{}'",
+                    &handle, e
+                )
+            })
+        }
+        QueueEvent::CleanOldRepo(handle, _) => {
+            let _permit = syncs.repos.acquire().await;
+            clean_old_repo(&handle, caserver, pubserver).await
+        }
+        QueueEvent::RepoMigration(handle, _) => {
+            let _permit = syncs.repos.acquire().await;
+            migrate_repo(&handle, caserver, pubserver).await
+        }
+    };
+
+    match result {
+        Ok(()) => event_queue.done(task),
+        Err(e) => {
+            error!("{}", e);
+            event_queue.retry(task, &e);
+        }
+    }
 }
@@ -0,0 +1,533 @@
+//! Delivery of CA and repository events to external systems.
+//!
+//! All stored events are turned into small notifications, which identify
+//! the event and summarise it. The details can be looked up through the
+//! API, e.g. in the history of a CA. Notifications are:
+//!  - kept in memory for a while, so that clients can long-poll for them;
+//!  - POSTed to the configured webhooks, if they selected the event.
+//!
+//! Webhook deliveries are kept on disk until they succeed, so that they
+//! are retried with backoff, also after a restart.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use chrono::Duration;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer as HmacSigner;
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+
+use rpki::x509::Time;
+
+use crate::commons::api::Handle;
+use crate::commons::eventsourcing::{self, Event};
+use crate::commons::util::{file, httpclient};
+use crate::constants::{
+    EVENTS_ID_BLOCK, EVENTS_RECENT_MAX, WEBHOOK_MAX_ATTEMPTS, WEBHOOK_MAX_BACKOFF_SECS,
+    WEBHOOK_SIGNATURE_HEADER,
+};
+use crate::daemon::ca::{self, CertAuth, Signer};
+use crate::pubd::{self, Repository};
+
+const WEBHOOKS_DIR: &str = "webhooks";
+const STATE_FILE: &str = "state.json";
+
+//------------ WebhookConfig -------------------------------------------------
+
+/// A webhook to which notifications are POSTed as JSON. If a secret is set,
+/// then the body is signed using HMAC-SHA256, and the hex encoded signature
+/// is included in the 'X-Krill-Signature' header as "sha256=<hex>". If events
+/// are listed, then only those events are delivered, e.g.
+/// "child_certificate_issued".
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct WebhookConfig {
+    uri: String,
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub fn new(uri: &str, secret: Option<&str>, events: Vec<String>) -> Self {
+        WebhookConfig {
+            uri: uri.to_string(),
+            secret: secret.map(|s| s.to_string()),
+            events,
+        }
+    }
+
+    fn selects(&self, notification: &EventNotification) -> bool {
+        self.events.is_empty() || self.events.contains(&notification.event)
+    }
+
+    /// Returns the signature header value for the body, if there is a secret.
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let key = PKey::hmac(secret.as_bytes()).ok()?;
+        let mut signer = HmacSigner::new(MessageDigest::sha256(), &key).ok()?;
+        signer.update(body).ok()?;
+        let signature = signer.sign_to_vec().ok()?;
+        Some(format!("sha256={}", hex::encode(signature)))
+    }
+}
+
+//------------ EventNotification ---------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EventNotification {
+    id: u64,
+    time: Time,
+    source: EventSource,
+    handle: Handle,
+    version: u64,
+    event: String,
+    summary: String,
+}
+
+impl EventNotification {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn source(&self) -> EventSource {
+        self.source
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    Ca,
+    Repository,
+}
+
+//------------ NotifierState -------------------------------------------------
+
+/// The persisted state: the first notification id which is not reserved
+/// yet, so that ids keep increasing after a restart, and the pending webhook
+/// deliveries.
+///
+/// Ids are reserved in blocks, so that the state only needs to be saved when
+/// a block is used up, rather than for every notification.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct NotifierState {
+    next_id: u64,
+    pending: Vec<PendingDelivery>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct PendingDelivery {
+    uri: String,
+    notification: EventNotification,
+    attempts: u32,
+    next_try: Time,
+}
+
+impl PendingDelivery {
+    fn is(&self, other: &PendingDelivery) -> bool {
+        self.uri == other.uri && self.notification.id == other.notification.id
+    }
+}
+
+//------------ EventNotifier -------------------------------------------------
+
+/// Listens for CA and repository events, and makes them available to
+/// external systems.
+pub struct EventNotifier {
+    webhooks: Vec<WebhookConfig>,
+    state_path: PathBuf,
+    state: Mutex<NotifierState>,
+    next_id: AtomicU64,
+    recent: RwLock<VecDeque<EventNotification>>,
+}
+
+impl EventNotifier {
+    /// Creates a notifier which keeps its state under the work directory.
+    pub fn new(work_dir: &PathBuf, webhooks: Vec<WebhookConfig>) -> Self {
+        let dir = file::file_path(work_dir, WEBHOOKS_DIR);
+        let state_path = file::file_path(&dir, STATE_FILE);
+
+        let state = if state_path.exists() {
+            file::load_json(&state_path).unwrap_or_else(|e| {
+                warn!("Could not load webhook state, will start without: {}", e);
+                NotifierState::default()
+            })
+        } else {
+            NotifierState::default()
+        };
+
+        EventNotifier {
+            webhooks,
+            state_path,
+            next_id: AtomicU64::new(state.next_id),
+            state: Mutex::new(state),
+            recent: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the recent notifications after the given id, or all recent
+    /// notifications if no id is given, for which `visible` holds.
+    pub fn events_since<F>(&self, since: Option<u64>, visible: F) -> Vec<EventNotification>
+    where
+        F: Fn(&EventNotification) -> bool,
+    {
+        self.recent
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|n| since.map(|id| n.id > id).unwrap_or(true))
+            .filter(|n| visible(n))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of webhook deliveries which are still pending.
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Tries all webhook deliveries which are due. Failed deliveries are
+    /// retried with exponential backoff, and dropped after too many
+    /// attempts.
+    pub async fn deliver_pending(&self) {
+        let now = Time::now();
+        let due: Vec<PendingDelivery> = self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .filter(|d| d.next_try <= now)
+            .cloned()
+            .collect();
+
+        for delivery in due {
+            let result = match self.webhooks.iter().find(|w| w.uri == delivery.uri) {
+                Some(webhook) => Self::deliver(webhook, &delivery.notification).await,
+                None => Ok(()), // no longer configured, nothing to do
+            };
+
+            let mut state = self.state.lock().unwrap();
+            let pos = match state.pending.iter().position(|d| d.is(&delivery)) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            match result {
+                Ok(()) => {
+                    state.pending.remove(pos);
+                }
+                Err(e) => {
+                    let pending = &mut state.pending[pos];
+                    pending.attempts += 1;
+                    if pending.attempts >= WEBHOOK_MAX_ATTEMPTS {
+                        error!(
+                            "Giving up delivering event {} to webhook '{}' after {} attempts: {}",
+                            delivery.notification.id, delivery.uri, pending.attempts, e
+                        );
+                        state.pending.remove(pos);
+                    } else {
+                        let backoff = Self::backoff_secs(pending.attempts);
+                        warn!(
+                            "Could not deliver event {} to webhook '{}', will retry in {}s: {}",
+                            delivery.notification.id, delivery.uri, backoff, e
+                        );
+                        pending.next_try = Time::now() + Duration::seconds(backoff);
+                    }
+                }
+            }
+            self.save(&state);
+        }
+    }
+
+    async fn deliver(
+        webhook: &WebhookConfig,
+        notification: &EventNotification,
+    ) -> Result<(), httpclient::Error> {
+        let body = serde_json::to_string(notification)?;
+        let mut req = httpclient::client(&webhook.uri)
+            .await?
+            .post(&webhook.uri)
+            .header(USER_AGENT, "krill")
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(signature) = webhook.signature(body.as_bytes()) {
+            req = req.header(WEBHOOK_SIGNATURE_HEADER, signature);
+        }
+
+        req.body(body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(httpclient::Error::RequestError)?;
+        Ok(())
+    }
+
+    fn backoff_secs(attempts: u32) -> i64 {
+        let backoff = 10i64 << attempts.min(16);
+        backoff.min(WEBHOOK_MAX_BACKOFF_SECS)
+    }
+
+    fn notify(
+        &self,
+        source: EventSource,
+        handle: &Handle,
+        version: u64,
+        event: &str,
+        summary: String,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut save = id >= state.next_id;
+        if save {
+            state.next_id = id + EVENTS_ID_BLOCK;
+        }
+
+        let notification = EventNotification {
+            id,
+            time: Time::now(),
+            source,
+            handle: handle.clone(),
+            version,
+            event: event.to_string(),
+            summary,
+        };
+
+        for webhook in self.webhooks.iter().filter(|w| w.selects(&notification)) {
+            state.pending.push(PendingDelivery {
+                uri: webhook.uri.clone(),
+                notification: notification.clone(),
+                attempts: 0,
+                next_try: notification.time,
+            });
+            save = true;
+        }
+        if save {
+            self.save(&state);
+        }
+
+        let mut recent = self.recent.write().unwrap();
+        recent.push_back(notification);
+        if recent.len() > EVENTS_RECENT_MAX {
+            recent.pop_front();
+        }
+    }
+
+    fn save(&self, state: &NotifierState) {
+        let tmp = self.state_path.with_extension("json.tmp");
+        let res = file::save_json(state, &tmp).and_then(|_| fs::rename(&tmp, &self.state_path));
+        if let Err(e) = res {
+            error!("Could not save webhook state: {}", e);
+        }
+    }
+}
+
+impl<S: Signer> eventsourcing::EventListener<CertAuth<S>> for EventNotifier {
+    fn listen(&self, _ca: &CertAuth<S>, event: &ca::Evt) {
+        self.notify(
+            EventSource::Ca,
+            event.handle(),
+            event.version(),
+            ca_event_name(event.details()),
+            event.details().to_string(),
+        )
+    }
+}
+
+impl eventsourcing::EventListener<Repository> for EventNotifier {
+    fn listen(&self, _repository: &Repository, event: &pubd::Evt) {
+        let name = match event.details() {
+            pubd::EvtDet::PublisherAdded(_, _) => "publisher_added",
+            pubd::EvtDet::PublisherRemoved(_, _) => "publisher_removed",
+            pubd::EvtDet::Published(_, _) => "published",
+        };
+        self.notify(
+            EventSource::Repository,
+            event.handle(),
+            event.version(),
+            name,
+            event.details().to_string(),
+        )
+    }
+}
+
+/// The name of the event, as used in its JSON serialization.
+fn ca_event_name(details: &ca::EvtDet) -> &'static str {
+    use crate::daemon::ca::EvtDet::*;
+
+    match details {
+        TrustAnchorMade(_) => "trust_anchor_made",
+        ChildAdded(_, _) => "child_added",
+        ChildCertificateIssued(_, _, _) => "child_certificate_issued",
+        ChildKeyRevoked(_, _, _) => "child_key_revoked",
+        ChildCertificatesUpdated(_, _) => "child_certificates_updated",
+        ChildUpdatedIdCert(_, _) => "child_updated_id_cert",
+        ChildUpdatedResources(_, _) => "child_updated_resources",
+        ChildRemoved(_) => "child_removed",
+        IdUpdated(_) => "id_updated",
+        SignerUpdated(_) => "signer_updated",
+        ParentAdded(_, _) => "parent_added",
+        ParentUpdated(_, _) => "parent_updated",
+        ParentRemoved(_, _) => "parent_removed",
+        ResourceClassAdded(_, _) => "resource_class_added",
+        ResourceClassRemoved(_, _, _, _) => "resource_class_removed",
+        CertificateRequested(_, _, _) => "certificate_requested",
+        CertificateReceived(_, _, _) => "certificate_received",
+        KeyRollPendingKeyAdded(_, _) => "key_roll_pending_key_added",
+        KeyPendingToNew(_, _, _) => "key_pending_to_new",
+        KeyPendingToActive(_, _, _) => "key_pending_to_active",
+        KeyRollActivated(_, _) => "key_roll_activated",
+        KeyRollFinished(_, _) => "key_roll_finished",
+        UnexpectedKeyFound(_, _) => "unexpected_key_found",
+        RouteAuthorizationAdded(_) => "route_authorization_added",
+        RouteAuthorizationAddedWithMeta(_, _) => "route_authorization_added_with_meta",
+        RouteAuthorizationRemoved(_) => "route_authorization_removed",
+        RoasUpdated(_, _) => "roas_updated",
+        AspaConfigAdded(_) => "aspa_config_added",
+        AspaConfigUpdated(_, _) => "aspa_config_updated",
+        AspaConfigRemoved(_) => "aspa_config_removed",
+        AspaObjectsUpdated(_, _) => "aspa_objects_updated",
+        BgpSecDefinitionAdded(_, _) => "bgp_sec_definition_added",
+        BgpSecDefinitionRemoved(_) => "bgp_sec_definition_removed",
+        BgpSecCertificatesUpdated(_, _) => "bgp_sec_certificates_updated",
+        RtaPrepared(_, _) => "rta_prepared",
+        RtaSigned(_, _) => "rta_signed",
+        ObjectSetUpdated(_, _) => "object_set_updated",
+        RepoUpdated(_) => "repo_updated",
+        RepoMigrationUpdated(_) => "repo_migration_updated",
+        RepoCleaned(_) => "repo_cleaned",
+    }
+}
+
+//------------ Tests --------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::test::*;
+
+    use super::*;
+
+    #[test]
+    fn keep_recent_events_and_pending_deliveries() {
+        test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let webhooks = vec![
+                WebhookConfig::new("https://localhost:1/all", None, vec![]),
+                WebhookConfig::new(
+                    "https://localhost:1/children",
+                    Some("secret"),
+                    vec!["child_certificate_issued".to_string()],
+                ),
+            ];
+
+            let notifier = EventNotifier::new(&d, webhooks.clone());
+            notifier.notify(EventSource::Ca, &ca, 1, "child_added", "added".to_string());
+            notifier.notify(
+                EventSource::Ca,
+                &ca,
+                2,
+                "child_certificate_issued",
+                "issued".to_string(),
+            );
+
+            let all = notifier.events_since(None, |_| true);
+            assert_eq!(all.len(), 2);
+            let since_first = notifier.events_since(Some(all[0].id()), |_| true);
+            assert_eq!(since_first, vec![all[1].clone()]);
+            assert!(notifier
+                .events_since(None, |n| n.source() == EventSource::Repository)
+                .is_empty());
+
+            assert_eq!(notifier.pending(), 3);
+
+            // pending deliveries survive a restart, and ids keep increasing
+            let restarted = EventNotifier::new(&d, webhooks);
+            assert_eq!(restarted.pending(), 3);
+            restarted.notify(EventSource::Ca, &ca, 3, "child_removed", "gone".to_string());
+            assert!(restarted.events_since(None, |_| true)[0].id() > all[1].id());
+        })
+    }
+
+    #[test]
+    fn save_state_only_when_needed() {
+        test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let notifier = EventNotifier::new(&d, vec![]);
+
+            // the first notification reserves a block of ids
+            notifier.notify(EventSource::Ca, &ca, 1, "child_added", "added".to_string());
+            let state: NotifierState = file::load_json(&notifier.state_path).unwrap();
+            assert_eq!(state.next_id, EVENTS_ID_BLOCK);
+
+            // further ids in the block need no save without webhooks
+            fs::remove_file(&notifier.state_path).unwrap();
+            notifier.notify(EventSource::Ca, &ca, 2, "child_removed", "gone".to_string());
+            assert!(!notifier.state_path.exists());
+
+            // the next block is reserved once this one is used up
+            for version in 3..=EVENTS_ID_BLOCK {
+                notifier.notify(EventSource::Ca, &ca, version, "child_added", "".to_string());
+            }
+            assert!(!notifier.state_path.exists());
+            notifier.notify(
+                EventSource::Ca,
+                &ca,
+                EVENTS_ID_BLOCK + 1,
+                "child_added",
+                "".to_string(),
+            );
+            let state: NotifierState = file::load_json(&notifier.state_path).unwrap();
+            assert_eq!(state.next_id, 2 * EVENTS_ID_BLOCK);
+
+            // so that ids keep increasing after a restart
+            let restarted = EventNotifier::new(&d, vec![]);
+            restarted.notify(
+                EventSource::Ca,
+                &ca,
+                EVENTS_ID_BLOCK + 2,
+                "child_added",
+                "".to_string(),
+            );
+            let last = notifier.events_since(None, |_| true).pop().unwrap();
+            assert!(restarted.events_since(None, |_| true)[0].id() > last.id());
+        })
+    }
+
+    #[test]
+    fn sign_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        let webhook = WebhookConfig::new("https://localhost/", Some("Jefe"), vec![]);
+        assert_eq!(
+            webhook.signature(b"what do ya want for nothing?"),
+            Some(
+                "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+                    .to_string()
+            )
+        );
+
+        let unsigned = WebhookConfig::new("https://localhost/", None, vec![]);
+        assert_eq!(unsigned.signature(b"body"), None);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(EventNotifier::backoff_secs(1), 20);
+        assert_eq!(EventNotifier::backoff_secs(2), 40);
+        assert_eq!(EventNotifier::backoff_secs(30), WEBHOOK_MAX_BACKOFF_SECS);
+    }
+}
@@ -4,7 +4,6 @@ extern crate bcder;
 extern crate bytes;
 extern crate chrono;
 extern crate clap;
-extern crate clokwerk;
 #[macro_use]
 extern crate derive_more;
 extern crate futures;
@@ -16,6 +15,7 @@ extern crate libflate;
 #[macro_use]
 extern crate log;
 extern crate openssl;
+extern crate pkcs11;
 extern crate rand;
 extern crate reqwest;
 extern crate rpki;
@@ -5,20 +5,25 @@ use std::sync::{Arc, RwLock};
 
 use bytes::Bytes;
 use rpki::uri;
+use rpki::x509::Time;
 
 use crate::commons::api::{
     Handle, ListReply, PublishDelta, PublisherDetails, PublisherHandle, RepoInfo, RepositoryHandle,
+    StoreIntegrityReport,
 };
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::{AggregateStore, AggregateStoreError, DiskAggregateStore};
+use crate::commons::eventsourcing::{
+    AggregateStore, AggregateStoreError, DiskAggregateStore, StorageType, StoreBackup,
+};
 use crate::commons::remote::builder::SignedMessageBuilder;
 use crate::commons::remote::cmslogger::CmsLogger;
 use crate::commons::remote::rfc8181;
 use crate::commons::remote::rfc8183;
 use crate::commons::remote::sigmsg::SignedMessage;
-use crate::commons::util::softsigner::OpenSslSigner;
+use crate::commons::util::krillsigner::KrillSigner;
 use crate::commons::KrillResult;
 use crate::constants::*;
+use crate::daemon::webhooks::EventNotifier;
 use crate::pubd::{self, CmdDet, RepoStats, Repository};
 
 //------------ PubServer -----------------------------------------------------
@@ -36,7 +41,7 @@ use crate::pubd::{self, CmdDet, RepoStats, Repository};
 ///
 pub struct PubServer {
     store: Arc<DiskAggregateStore<Repository>>,
-    signer: Arc<RwLock<OpenSslSigner>>,
+    signer: Arc<RwLock<KrillSigner>>,
     rfc8181_log_dir: Option<PathBuf>,
 }
 
@@ -47,14 +52,23 @@ impl PubServer {
         rsync_base: &uri::Rsync,
         rrdp_base_uri: uri::Https,         // for the RRDP files
         work_dir: &PathBuf,                // for the aggregate stores
+        storage_type: StorageType,         // for the aggregate stores
         rfc8181_log_dir: Option<&PathBuf>, // for optional CMS exchange logging
-        signer: Arc<RwLock<OpenSslSigner>>,
+        event_notifier: Arc<EventNotifier>,
+        signer: Arc<RwLock<KrillSigner>>,
     ) -> Result<Option<Self>, Error> {
         let mut pub_server_dir = work_dir.clone();
         pub_server_dir.push(PUBSERVER_DIR);
         if pub_server_dir.exists() {
-            let server =
-                PubServer::build(rsync_base, rrdp_base_uri, work_dir, rfc8181_log_dir, signer)?;
+            let server = PubServer::build(
+                rsync_base,
+                rrdp_base_uri,
+                work_dir,
+                storage_type,
+                rfc8181_log_dir,
+                event_notifier,
+                signer,
+            )?;
             if server.publishers()?.is_empty() {
                 let _result = fs::remove_dir_all(pub_server_dir);
                 Ok(None)
@@ -70,15 +84,17 @@ impl PubServer {
         rsync_base: &uri::Rsync,
         rrdp_base_uri: uri::Https,         // for the RRDP files
         work_dir: &PathBuf,                // for the aggregate stores
+        storage_type: StorageType,         // for the aggregate stores
         rfc8181_log_dir: Option<&PathBuf>, // for optional CMS exchange logging
-        signer: Arc<RwLock<OpenSslSigner>>,
+        event_notifier: Arc<EventNotifier>,
+        signer: Arc<RwLock<KrillSigner>>,
     ) -> Result<Self, Error> {
         let default = Self::repository_handle();
 
-        let store = Arc::new(DiskAggregateStore::<Repository>::new(
-            work_dir,
-            PUBSERVER_DIR,
-        )?);
+        let mut store =
+            DiskAggregateStore::<Repository>::new(work_dir, PUBSERVER_DIR, storage_type)?;
+        store.add_listener(event_notifier);
+        let store = Arc::new(store);
 
         if !store.has(&default) {
             info!("Creating default repository");
@@ -186,6 +202,26 @@ impl PubServer {
         self.write_repository()
     }
 
+    /// Verifies the stored history of the repository, and repairs it if
+    /// asked and possible.
+    pub fn verify_integrity(&self, repair: bool) -> KrillResult<StoreIntegrityReport> {
+        let aggregates = self.store.verify_integrity(repair)?;
+        Ok(StoreIntegrityReport::new(PUBSERVER_DIR, aggregates))
+    }
+
+    /// Moves the history of the repository before the given time to its
+    /// archive.
+    pub fn archive_history(&self, before: Time) -> KrillResult<usize> {
+        Ok(self.store.archive_history(before)?)
+    }
+
+    /// Returns a copy of the stored state of the repository. The function
+    /// 'with' is called while no commands are processed, so that the RRDP
+    /// files can be copied along with it.
+    pub fn backup<T, F: FnOnce() -> T>(&self, with: F) -> KrillResult<(StoreBackup, T)> {
+        Ok(self.store.backup(PUBSERVER_DIR, with)?)
+    }
+
     pub fn repo_stats(&self) -> KrillResult<RepoStats> {
         let repo = self.repository()?;
         Ok(repo.stats().clone())
@@ -280,6 +316,8 @@ mod tests {
     use crate::commons::remote::builder::IdCertBuilder;
     use crate::commons::remote::id::IdCert;
     use crate::commons::util::file::CurrentFile;
+    use crate::commons::util::krillsigner::SignerConfig;
+    use crate::commons::util::softsigner::OpenSslSigner;
     use crate::pubd::Publisher;
     use crate::test;
 
@@ -310,14 +348,16 @@ mod tests {
     }
 
     fn make_server(work_dir: &PathBuf) -> PubServer {
-        let signer = OpenSslSigner::build(work_dir).unwrap();
+        let signer = KrillSigner::build(&SignerConfig::OpenSsl, work_dir).unwrap();
         let signer = Arc::new(RwLock::new(signer));
 
         PubServer::build(
             &server_base_uri(),
             server_base_http_uri(),
             work_dir,
+            StorageType::Disk,
             None,
+            Arc::new(EventNotifier::new(work_dir, vec![])),
             signer,
         )
         .unwrap()
@@ -20,10 +20,10 @@ use crate::cli::options::{BulkCaCommand, CaCommand, Command, Options, Publishers
 use crate::cli::report::{ApiResponse, ReportFormat};
 use crate::cli::{Error, KrillClient};
 use crate::commons::api::{
-    AddChildRequest, CertAuthInfo, CertAuthInit, CertifiedKeyInfo, ChildAuthRequest, ChildHandle,
-    Handle, ParentCaContact, ParentCaReq, ParentHandle, Publish, PublisherDetails, PublisherHandle,
-    RepositoryUpdate, ResourceClassKeysInfo, ResourceClassName, ResourceSet, RoaDefinition,
-    RoaDefinitionUpdates, UpdateChildRequest,
+    AddChildRequest, CertAuthInfo, CertAuthInit, CertAuthSignerUpdate, CertifiedKeyInfo,
+    ChildAuthRequest, ChildHandle, Handle, ParentCaContact, ParentCaReq, ParentHandle, Publish,
+    PublisherDetails, PublisherHandle, RepositoryUpdate, ResourceClassKeysInfo, ResourceClassName,
+    ResourceSet, RoaConfiguration, RoaDefinition, RoaDefinitionUpdates, UpdateChildRequest,
 };
 use crate::commons::bgp::Announcement;
 use crate::commons::remote::rfc8183;
@@ -69,12 +69,20 @@ pub async fn server_ready(uri: &str) -> bool {
 /// Creates a random base directory in the 'work' folder, and returns
 /// it. Be sure to clean it up when the test is done.
 pub async fn start_krill() -> PathBuf {
+    start_krill_with(|_| {}).await
+}
+
+/// Starts krill with the test config, after applying the given changes to
+/// the config.
+pub async fn start_krill_with<F: FnOnce(&mut Config)>(op: F) -> PathBuf {
     let dir = tmp_dir();
 
     let server_conf = {
         // Use a data dir for the storage
         let data_dir = sub_dir(&dir);
-        Config::test(&data_dir)
+        let mut config = Config::test(&data_dir);
+        op(&mut config);
+        config
     };
 
     tokio::spawn(server::start(server_conf));
@@ -283,6 +291,22 @@ pub async fn ca_roll_activate(handle: &Handle) {
     .await;
 }
 
+pub async fn ca_roll_to_signer(handle: &Handle, signer: &str) {
+    krill_admin(Command::CertAuth(CaCommand::KeyRollSigner(
+        handle.clone(),
+        CertAuthSignerUpdate::new(Some(signer.to_string())),
+    )))
+    .await;
+}
+
+pub async fn ca_roll_to_signer_expect_error(handle: &Handle, signer: &str) {
+    krill_admin_expect_error(Command::CertAuth(CaCommand::KeyRollSigner(
+        handle.clone(),
+        CertAuthSignerUpdate::new(Some(signer.to_string())),
+    )))
+    .await;
+}
+
 pub async fn ca_route_authorizations_update(handle: &Handle, updates: RoaDefinitionUpdates) {
     krill_admin(Command::CertAuth(CaCommand::RouteAuthorizationsUpdate(
         handle.clone(),
@@ -527,3 +551,7 @@ pub fn announcement(s: &str) -> Announcement {
 pub fn definition(s: &str) -> RoaDefinition {
     RoaDefinition::from_str(s).unwrap()
 }
+
+pub fn configuration(s: &str) -> RoaConfiguration {
+    RoaConfiguration::from_str(s).unwrap()
+}
@@ -6,7 +6,9 @@ use std::path::PathBuf;
 use std::{fmt, fs, io};
 
 use crate::commons::api::Handle;
-use crate::commons::eventsourcing::{DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion};
+use crate::commons::eventsourcing::{
+    DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion, SqlKeyStore,
+};
 use crate::commons::util::file;
 use crate::daemon::krillserver::KrillServer;
 use crate::upgrades::roa_cleanup_0_7_0::RoaCleanupError;
@@ -86,17 +88,34 @@ pub fn pre_start_upgrade(work_dir: &PathBuf) -> Result<(), UpgradeError> {
 }
 
 /// Should be called right after the KrillServer is initiated
-pub fn post_start_upgrade(work_dir: &PathBuf, server: &KrillServer) -> Result<(), UpgradeError> {
-    let version_0_7 = KeyStoreVersion::V0_7;
+pub async fn post_start_upgrade(
+    work_dir: &PathBuf,
+    server: &KrillServer,
+) -> Result<(), UpgradeError> {
+    // Stores kept in SQLite were created by this version of Krill, so
+    // there is nothing to upgrade.
+    if SqlKeyStore::exists(work_dir, "cas") {
+        return Ok(());
+    }
+
+    let current = KeyStoreVersion::current();
     let ca_store = DiskKeyStore::new(work_dir, "cas");
     let pubd_store = DiskKeyStore::new(work_dir, "pubd");
-    if ca_store.get_version()? != version_0_7 {
-        info!("Will clean up redundant ROAs for all CAs and update version of storage dirs");
-        roa_cleanup_0_7_0::roa_cleanup(server)?;
-        ca_store.set_version(&version_0_7)?;
-        pubd_store.set_version(&version_0_7)?;
+    let version = ca_store.get_version()?;
+    if version == current {
+        return Ok(());
     }
 
+    if version != KeyStoreVersion::V0_7 {
+        info!("Will clean up redundant ROAs for all CAs");
+        roa_cleanup_0_7_0::roa_cleanup(server).await?;
+    }
+
+    // Stores from 0.7 can be used as they are, only the version is updated.
+    info!("Will update version of storage dirs to {:?}", current);
+    ca_store.set_version(&current)?;
+    pubd_store.set_version(&current)?;
+
     Ok(())
 }
 
@@ -109,7 +128,7 @@ fn upgrade_pre_0_6_0_cas_commands(work_dir: &PathBuf) -> Result<(), UpgradeError
     let ca_store = DiskKeyStore::new(work_dir, "cas");
 
     // bail out if there is nothing to do
-    if !pre_0_6_0_ca_commands.needs_migrate(&ca_store)? {
+    if SqlKeyStore::exists(work_dir, "cas") || !pre_0_6_0_ca_commands.needs_migrate(&ca_store)? {
         return Ok(());
     }
 
@@ -145,7 +164,9 @@ fn upgrade_pre_0_6_0_pubd_commands(work_dir: &PathBuf) -> Result<(), UpgradeErro
     let pubd_store = DiskKeyStore::new(work_dir, "pubd");
 
     // bail out if there is nothing to do
-    if !pre_0_6_0_pubd_commands.needs_migrate(&pubd_store)? {
+    if SqlKeyStore::exists(work_dir, "pubd")
+        || !pre_0_6_0_pubd_commands.needs_migrate(&pubd_store)?
+    {
         return Ok(());
     }
 
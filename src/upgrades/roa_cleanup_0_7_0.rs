@@ -2,13 +2,18 @@ use std::collections::HashSet;
 
 use crate::commons::api::{RoaDefinition, RoaDefinitionUpdates};
 use crate::commons::bgp::make_roa_tree;
+use crate::daemon::auth::Actor;
 use crate::daemon::krillserver::KrillServer;
 
-pub fn roa_cleanup(server: &KrillServer) -> Result<(), RoaCleanupError> {
+pub async fn roa_cleanup(server: &KrillServer) -> Result<(), RoaCleanupError> {
     for ca in server.cas().cas() {
         info!("Will check ROAs for CA: {}", ca.handle());
 
-        let roas = server.ca_routes_show(ca.handle())?;
+        let roas: Vec<RoaDefinition> = server
+            .ca_routes_show(ca.handle())?
+            .into_iter()
+            .map(|config| config.definition())
+            .collect();
 
         if roas.is_empty() {
             info!("No ROAs found for CA: {}", ca.handle());
@@ -21,7 +26,9 @@ pub fn roa_cleanup(server: &KrillServer) -> Result<(), RoaCleanupError> {
 
         if let Some(updates) = clean(roas) {
             info!("Will clean up ROAs as follows:\n{}", updates);
-            server.ca_routes_update(ca.handle().clone(), updates)?;
+            server
+                .ca_routes_update(ca.handle().clone(), updates, &Actor::system())
+                .await?;
         } else {
             info!("No clean up needed");
         }
@@ -0,0 +1,80 @@
+extern crate krill;
+
+use std::fs;
+use std::path::Path;
+
+use krill::commons::api::{Handle, ParentCaReq, ResourceSet};
+use krill::commons::util::krillsigner::{NamedSignerConfig, SignerConfig};
+use krill::daemon::ca::ta_handle;
+use krill::test::*;
+
+/// Counts the key files kept by the openssl signer with the given name,
+/// anywhere under the directory.
+fn count_signer_keys(dir: &Path, signer: &str) -> usize {
+    let mut count = 0;
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.ends_with(Path::new("signers").join(signer).join("keys")) {
+                count += fs::read_dir(&path).map(|d| d.count()).unwrap_or(0);
+            } else if path.is_dir() {
+                count += count_signer_keys(&path, signer);
+            }
+        }
+    }
+    count
+}
+
+#[tokio::test]
+async fn ca_keyroll_signer() {
+    let dir = start_krill_with(|config| {
+        config
+            .signers
+            .push(NamedSignerConfig::new("second", SignerConfig::OpenSsl));
+    })
+    .await;
+
+    let ta_handle = ta_handle();
+
+    let child = unsafe { Handle::from_str_unsafe("signer") };
+    let child_resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+
+    init_child_with_embedded_repo(&child).await;
+    let req = child_request(&child).await;
+
+    let parent = {
+        let contact = add_child_to_ta_rfc6492(&child, req, child_resources.clone()).await;
+        ParentCaReq::new(ta_handle, contact)
+    };
+
+    add_parent_to_ca(&child, parent).await;
+    assert!(ca_gets_resources(&child, &child_resources).await);
+    assert!(ta_will_have_issued_n_certs(1).await);
+    assert_eq!(0, count_signer_keys(&dir, "second"));
+
+    // A signer which is not configured cannot be used
+    ca_roll_to_signer_expect_error(&child, "unknown").await;
+
+    // Moving to another configured signer is a normal key roll, which
+    // creates the new key in that signer
+    ca_roll_to_signer(&child, "second").await;
+    assert!(rc_state_becomes_new_key(&child).await);
+    assert!(ta_will_have_issued_n_certs(2).await);
+    assert_eq!(1, count_signer_keys(&dir, "second"));
+
+    ca_roll_activate(&child).await;
+    assert!(rc_state_becomes_active(&child).await);
+    assert!(ta_will_have_issued_n_certs(1).await);
+
+    // The CA keeps using the key in the other signer, and can move back
+    ca_roll_to_signer(&child, "default").await;
+    assert!(rc_state_becomes_new_key(&child).await);
+    assert!(ta_will_have_issued_n_certs(2).await);
+
+    ca_roll_activate(&child).await;
+    assert!(rc_state_becomes_active(&child).await);
+    assert!(ta_will_have_issued_n_certs(1).await);
+    assert_eq!(1, count_signer_keys(&dir, "second"));
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -4,10 +4,12 @@ use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use krill::commons::api::{CaCommandDetails, CommandHistoryCriteria, Handle};
+use krill::commons::eventsourcing::StorageType;
 use krill::commons::util::file;
 use krill::commons::util::softsigner::OpenSslSigner;
-use krill::daemon::ca::CaServer;
+use krill::daemon::ca::{CaServer, RoaAggregation};
 use krill::daemon::mq::EventQueueListener;
+use krill::daemon::webhooks::EventNotifier;
 use krill::test::*;
 use std::env;
 
@@ -58,8 +60,19 @@ fn make_server(work_dir: &PathBuf, scenario: &str) -> CaServer<OpenSslSigner> {
         let signer = Arc::new(RwLock::new(signer));
 
         let event_queue = Arc::new(EventQueueListener::in_mem());
-
-        CaServer::<OpenSslSigner>::build(&server_dir, None, None, event_queue, signer).unwrap()
+        let event_notifier = Arc::new(EventNotifier::new(&server_dir, vec![]));
+
+        CaServer::<OpenSslSigner>::build(
+            &server_dir,
+            StorageType::Disk,
+            None,
+            None,
+            event_queue,
+            event_notifier,
+            RoaAggregation::default(),
+            signer,
+        )
+        .unwrap()
     };
 
     server
@@ -14,7 +14,7 @@ use rpki::roa::Roa;
 use krill::cli::options::{CaCommand, Command, PublishersCommand};
 use krill::cli::report::ApiResponse;
 use krill::commons::api::{
-    CaRepoDetails, CurrentRepoState, Handle, ParentCaReq, PublisherDetails, PublisherHandle,
+    CaRepoDetails, CaRepoState, Handle, ParentCaReq, PublisherDetails, PublisherHandle,
     RepositoryUpdate, ResourceSet, RoaDefinition, RoaDefinitionUpdates,
 };
 use krill::commons::remote::rfc8183;
@@ -60,7 +60,7 @@ async fn repo_details(ca: &Handle) -> CaRepoDetails {
     }
 }
 
-async fn repo_state(ca: &Handle) -> CurrentRepoState {
+async fn repo_state(ca: &Handle) -> CaRepoState {
     let command = Command::CertAuth(CaCommand::RepoState(ca.clone()));
     match krill_admin(command).await {
         ApiResponse::RepoState(state) => state,
@@ -92,6 +92,16 @@ async fn will_publish(ca: &Handle, number: usize) -> bool {
     false
 }
 
+async fn will_finish_migration(ca: &Handle) -> bool {
+    for _ in 0..300 {
+        if repo_state(ca).await.migration().is_none() {
+            return true;
+        }
+        delay_for(Duration::from_millis(100)).await
+    }
+    false
+}
+
 async fn will_clean(publisher: &Handle, context: PubdTestContext) -> bool {
     for _ in 0..300 {
         let details = details_publisher(publisher, context).await;
@@ -188,8 +198,9 @@ async fn remote_publication() {
         assert!(crl_uri.to_string().starts_with("rsync://remotehost/repo/"));
     }
 
-    // Child should now clean up the old repo
+    // Child should now clean up the old repo, and finish the migration
     assert!(will_clean(&child, PubdTestContext::Main).await);
+    assert!(will_finish_migration(&child).await);
 
     // Now let's migrate back, so that we see that works too.
 
@@ -207,6 +218,7 @@ async fn remote_publication() {
 
     // Child should now clean up the secondary repo
     assert!(will_clean(&child, PubdTestContext::Secondary).await);
+    assert!(will_finish_migration(&child).await);
 
     let _ = fs::remove_dir_all(&dir);
 }
@@ -3,7 +3,7 @@ extern crate krill;
 use std::path::PathBuf;
 
 use krill::commons::api::Handle;
-use krill::commons::eventsourcing::{AggregateStore, DiskAggregateStore};
+use krill::commons::eventsourcing::{AggregateStore, DiskAggregateStore, StorageType};
 use krill::commons::util::softsigner::OpenSslSigner;
 use krill::constants::PUBSERVER_DFLT;
 use krill::daemon::ca::CertAuth;
@@ -66,7 +66,9 @@ fn publication_since_0_4_0() {
 }
 
 fn test_cas(work_dir: &PathBuf, cas: &[&str]) {
-    let ca_store = DiskAggregateStore::<CertAuth<OpenSslSigner>>::new(&work_dir, "cas").unwrap();
+    let ca_store =
+        DiskAggregateStore::<CertAuth<OpenSslSigner>>::new(&work_dir, "cas", StorageType::Disk)
+            .unwrap();
 
     for ca in cas {
         assert_no_snapshot(work_dir, &format!("cas/{}", ca));
@@ -78,7 +80,8 @@ fn test_cas(work_dir: &PathBuf, cas: &[&str]) {
 }
 
 fn test_repo(work_dir: &PathBuf, repo: &str) {
-    let repo_store = DiskAggregateStore::<Repository>::new(&work_dir, repo).unwrap();
+    let repo_store =
+        DiskAggregateStore::<Repository>::new(&work_dir, repo, StorageType::Disk).unwrap();
     assert_no_snapshot(work_dir, &format!("{}/{}", repo, PUBSERVER_DFLT));
     let handle = unsafe { Handle::from_str_unsafe(PUBSERVER_DFLT) };
     if let Err(e) = repo_store.get_latest(&handle) {